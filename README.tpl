@@ -19,3 +19,7 @@ To do so, go into the `generate` folder and run the program:
 cd "generate"
 cargo run
 ```
+
+Extra derives (such as the optional `serde` implementations) are configured in `generate/src/lib.rs`.
+They are emitted behind `cfg_attr` attributes, so the checked-in code can be customized with cargo features.
+The `generate` crate can also be used from a `build.rs` script if you need to generate the messages from a modified `egm.proto`.
@@ -0,0 +1,115 @@
+//! Browser demo that talks to a robot controller through a WebSocket relay.
+//!
+//! The relay forwards every UDP datagram from the robot controller as a binary WebSocket message, and the other way around.
+//! This crate only implements the browser side: all encoding, decoding and validation is done by `abbegm`.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use abbegm::msg::{EgmClock, EgmRobot, EgmSensor};
+use abbegm::transport::{Channel, ChannelError, DatagramTransport};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{BinaryType, MessageEvent, WebSocket};
+
+/// Datagram transport over a WebSocket.
+struct WebSocketTransport {
+	socket: WebSocket,
+	received: Rc<RefCell<VecDeque<Vec<u8>>>>,
+	_on_message: Closure<dyn FnMut(MessageEvent)>,
+}
+
+impl WebSocketTransport {
+	fn connect(url: &str) -> Result<Self, JsValue> {
+		let socket = WebSocket::new(url)?;
+		socket.set_binary_type(BinaryType::Arraybuffer);
+
+		let received = Rc::new(RefCell::new(VecDeque::new()));
+		let on_message = Closure::wrap(Box::new({
+			let received = received.clone();
+			move |event: MessageEvent| {
+				if let Ok(buffer) = event.data().dyn_into::<js_sys::ArrayBuffer>() {
+					received.borrow_mut().push_back(js_sys::Uint8Array::new(&buffer).to_vec());
+				}
+			}
+		}) as Box<dyn FnMut(MessageEvent)>);
+		socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+		Ok(Self {
+			socket,
+			received,
+			_on_message: on_message,
+		})
+	}
+}
+
+impl DatagramTransport for WebSocketTransport {
+	type Error = JsValue;
+
+	fn send_datagram(&mut self, datagram: &[u8]) -> Result<(), Self::Error> {
+		self.socket.send_with_u8_array(datagram)
+	}
+
+	fn recv_datagram(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+		let datagram = match self.received.borrow_mut().pop_front() {
+			Some(datagram) => datagram,
+			None => return Ok(None),
+		};
+		if datagram.len() > buffer.len() {
+			return Err(JsValue::from_str("received datagram is too large"));
+		}
+		buffer[..datagram.len()].copy_from_slice(&datagram);
+		Ok(Some(datagram.len()))
+	}
+}
+
+/// Convert a channel error to a JavaScript value.
+fn to_js_error(error: ChannelError<JsValue>) -> JsValue {
+	match error {
+		ChannelError::Transport(e) => e,
+		ChannelError::InvalidMessage(e) => JsValue::from_str(&e.to_string()),
+		ChannelError::Encode(e) => JsValue::from_str(&e.to_string()),
+		ChannelError::Decode(e) => JsValue::from_str(&e.to_string()),
+	}
+}
+
+/// Connection to a robot controller, exported to JavaScript.
+#[wasm_bindgen]
+pub struct Robot {
+	channel: Channel<WebSocketTransport>,
+	state: Option<EgmRobot>,
+	sequence_number: u32,
+}
+
+#[wasm_bindgen]
+impl Robot {
+	/// Connect to the WebSocket relay at the given URL.
+	#[wasm_bindgen(constructor)]
+	pub fn new(url: &str) -> Result<Robot, JsValue> {
+		Ok(Self {
+			channel: Channel::new(WebSocketTransport::connect(url)?),
+			state: None,
+			sequence_number: 0,
+		})
+	}
+
+	/// Process all received messages and return the latest joint positions in degrees.
+	pub fn poll(&mut self) -> Result<Option<Vec<f64>>, JsValue> {
+		while let Some(state) = self.channel.recv().map_err(to_js_error)? {
+			self.state = Some(state);
+		}
+		Ok(self.state.as_ref().and_then(|state| state.feedback_joints().cloned()))
+	}
+
+	/// Send a joint target in degrees to the robot controller.
+	///
+	/// The target is validated before it is sent, exactly like the native peers do.
+	pub fn send_joints(&mut self, joints: Vec<f64>) -> Result<(), JsValue> {
+		let time = self.state.as_ref().and_then(|state| state.feedback_time()).unwrap_or(EgmClock::new(0, 0));
+		let target = EgmSensor::joint_target(self.sequence_number, joints, time);
+		self.channel.send(&target).map_err(to_js_error)?;
+		self.sequence_number = self.sequence_number.wrapping_add(1);
+		Ok(())
+	}
+}
@@ -0,0 +1,126 @@
+use abbegm::servo::TargetSource;
+use abbegm::tokio_peer::EgmPeer;
+use rand::Rng;
+use std::convert::TryInto;
+use std::time::Duration;
+use structopt::clap::AppSettings;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::ColoredHelp)]
+#[structopt(setting = AppSettings::DeriveDisplayOrder)]
+#[structopt(setting = AppSettings::UnifiedHelpMessage)]
+struct Options {
+	/// Local address to bind to.
+	#[structopt(long)]
+	#[structopt(value_name = "HOST:PORT")]
+	#[structopt(default_value = "[::]:6510")]
+	bind: String,
+
+	/// Smoothing factor for new measurements, between 0 and 1.
+	#[structopt(long)]
+	#[structopt(value_name = "FACTOR")]
+	#[structopt(default_value = "0.1")]
+	smoothing: f64,
+
+	/// Maximum distance the target may move per robot cycle, in millimeters.
+	#[structopt(long)]
+	#[structopt(value_name = "MM")]
+	#[structopt(default_value = "0.5")]
+	max_step: f64,
+
+	/// Maximum distance of the target from the start pose, in millimeters.
+	#[structopt(long)]
+	#[structopt(value_name = "MM")]
+	#[structopt(default_value = "50")]
+	max_offset: f64,
+
+	/// Confirm that the robot should perform motion.
+	#[structopt(long)]
+	confirm_motion: bool,
+}
+
+/// Simulate a camera that measures the desired offset from the start pose at 30 Hz.
+///
+/// In a real application, this would be a task processing images from a camera.
+async fn simulate_camera(sender: tokio::sync::mpsc::Sender<nalgebra::Vector3<f64>>) {
+	let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / 30.0));
+	let start = std::time::Instant::now();
+	loop {
+		interval.tick().await;
+		let t = start.elapsed().as_secs_f64();
+		let noise = rand::thread_rng().gen_range(-1.0..1.0);
+		let offset = nalgebra::Vector3::new(30.0 * (0.5 * t).sin() + noise, 0.0, 0.0);
+		if sender.send(offset).await.is_err() {
+			return;
+		}
+	}
+}
+
+/// Limit the length of a vector.
+fn clamp_norm(vector: nalgebra::Vector3<f64>, max: f64) -> nalgebra::Vector3<f64> {
+	let norm = vector.norm();
+	if norm > max {
+		vector * (max / norm)
+	} else {
+		vector
+	}
+}
+
+async fn do_main(options: Options) -> Result<(), String> {
+	if !options.confirm_motion {
+		return Err(String::from("refusing to send motion commands to the robot without --confirm-motion flag"))
+	}
+
+	let mut peer = EgmPeer::bind(&options.bind).await
+		.map_err(|e| format!("failed to bind to local enpoint {}: {}", options.bind, e))?;
+
+	let local_address = peer.socket().local_addr()
+		.map_err(|e| format!("failed to get local socket address: {}", e))?;
+
+	eprintln!("Listening for messages on {}", local_address);
+
+	let (state, _address) = peer.recv_from().await
+		.map_err(|e| format!("failed to receive robot state: {}", e))?;
+
+	eprintln!("Received initial robot state.");
+
+	let start_pose : nalgebra::Isometry3<f64> = state.feedback_pose().ok_or("state did not contain a pose")?
+		.try_into().map_err(|e| format!("failed to convert pose to isometry: {}", e))?;
+
+	let (sender, mut measurements) = tokio::sync::mpsc::channel(16);
+	tokio::spawn(simulate_camera(sender));
+
+	let mut sequence_number = 0u32;
+	let mut measured_offset = nalgebra::Vector3::zeros();
+	let mut commanded_offset = nalgebra::Vector3::zeros();
+
+	// The robot controller sends a message every cycle, so this loop runs at the EGM sample rate.
+	loop {
+		let (state, address) = peer.recv_from().await
+			.map_err(|e| format!("failed to receive robot state: {}", e))?;
+		let time = state.feedback_time().ok_or("missing feedback.clock in robot message")?;
+
+		// Filter new measurements with an exponential moving average.
+		if let Some(measurement) = measurements.poll_target() {
+			measured_offset += (measurement - measured_offset) * options.smoothing;
+		}
+
+		// Limit the step size per cycle and the total distance from the start pose.
+		let step = clamp_norm(measured_offset - commanded_offset, options.max_step);
+		commanded_offset = clamp_norm(commanded_offset + step, options.max_offset);
+
+		let target = nalgebra::Translation3::from(commanded_offset) * start_pose;
+		peer.send_to(&abbegm::msg::EgmSensor::pose_target(sequence_number, target, time), &address).await
+			.map_err(|e| format!("failed to send message to robot: {}", e))?;
+		sequence_number = sequence_number.wrapping_add(1);
+	}
+}
+
+#[tokio::main]
+async fn main() {
+	if let Err(e) = do_main(Options::from_args()).await {
+		eprintln!("Error: {}", e);
+		std::process::exit(1);
+	}
+}
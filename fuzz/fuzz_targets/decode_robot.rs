@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use prost::Message;
+
+fuzz_target!(|data: &[u8]| {
+	// Decoding must never panic, and accepted messages must survive a round trip.
+	if let Ok(msg) = abbegm::codec::decode_robot(data) {
+		let encoded = msg.encode_to_vec();
+		assert!(abbegm::codec::decode_robot(&encoded).unwrap() == msg);
+	}
+});
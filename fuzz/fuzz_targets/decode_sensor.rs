@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+	// Decoding must never panic, and accepted messages must survive a round trip.
+	if let Ok(msg) = abbegm::codec::decode_sensor(data) {
+		let mut encoded = Vec::new();
+		abbegm::codec::encode_sensor(&msg, &mut encoded).unwrap();
+		assert!(abbegm::codec::decode_sensor(&encoded).unwrap() == msg);
+	}
+});
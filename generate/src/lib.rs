@@ -0,0 +1,43 @@
+//! Code generation for the EGM protobuf messages.
+//!
+//! The generated code is checked in to the `abbegm` repository,
+//! but the functions in this crate can also be used from a `build.rs` script
+//! if you need to generate the messages from a modified `egm.proto`.
+//!
+//! Extra derives are emitted behind `cfg_attr` attributes,
+//! so they are enabled by the cargo features of the crate that includes the generated code.
+
+use std::path::Path;
+
+/// Attribute to add an optional `serde` implementation to a generated type.
+const SERDE_ATTRIBUTE: &str = r#"#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]"#;
+
+/// Messages with only fixed-size fields, which can safely be `Copy`.
+const COPY_MESSAGES: &[&str] = &[
+	".abb.egm.EgmCartesian",
+	".abb.egm.EgmQuaternion",
+	".abb.egm.EgmEuler",
+	".abb.egm.EgmClock",
+	".abb.egm.EgmPathCorr",
+];
+
+/// Create a [`prost_build::Config`] configured with all extra attributes for the EGM messages.
+pub fn config() -> prost_build::Config {
+	let mut config = prost_build::Config::new();
+	config.type_attribute(".", SERDE_ATTRIBUTE);
+	for message in COPY_MESSAGES {
+		config.type_attribute(message, "#[derive(Copy)]");
+	}
+	config
+}
+
+/// Generate the Rust code for the EGM messages.
+///
+/// The `proto_dir` must contain the `egm.proto` file.
+/// The generated code is written to `out_dir`.
+pub fn generate(proto_dir: impl AsRef<Path>, out_dir: impl AsRef<Path>) -> std::io::Result<()> {
+	let proto_dir = proto_dir.as_ref();
+	let mut config = config();
+	config.out_dir(out_dir.as_ref());
+	config.compile_protos(&[proto_dir.join("egm.proto")], &[proto_dir])
+}
@@ -1,4 +1,4 @@
-//! Run this from the repository root to generate Rust code from the protobuf files.
+//! Run this from the `generate` folder to generate Rust code from the protobuf files.
 
 use std::path::Path;
 
@@ -18,6 +18,6 @@ fn main() {
 		std::process::exit(1);
 	}
 
-	std::env::set_var("OUT_DIR", "../src/generated");
-	prost_build::compile_protos(&["../proto/egm.proto"], &["../proto"]).unwrap()
+	generate::generate("../proto", "../src/generated").unwrap();
+	generate::generate("../proto/robotware-6.10", "../src/generated/robotware_6_10").unwrap();
 }
@@ -0,0 +1,128 @@
+//! Normalization and unwrapping of revolute joint angles.
+//!
+//! Targets for revolute joints are often computed in the range [-180, 180) degrees,
+//! while the robot controller interprets them as absolute angles, so a target of -170 degrees for a joint at 170 degrees
+//! makes the joint turn 340 degrees instead of 20 degrees.
+//! The functions in this module choose the equivalent angle that is nearest to the current position instead.
+
+use crate::float;
+
+/// Error that may occur when unwrapping joint targets.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UnwrapError {
+	/// The number of feedback joints does not match the number of targets.
+	WrongNumberOfJoints {
+		/// The number of targets.
+		expected: usize,
+
+		/// The number of feedback joints.
+		actual: usize,
+	},
+
+	/// No equivalent angle of a target lies within the joint limits.
+	OutOfLimits {
+		/// The index of the joint.
+		joint: usize,
+
+		/// The target in degrees.
+		target: f64,
+	},
+}
+
+/// Normalize an angle in degrees to the range [-180, 180).
+pub fn normalize(angle: f64) -> f64 {
+	angle - 360.0 * float::floor((angle + 180.0) / 360.0)
+}
+
+/// Get the angle equivalent to `angle` that is nearest to `reference`, in degrees.
+pub fn nearest_equivalent(angle: f64, reference: f64) -> f64 {
+	angle + 360.0 * float::round((reference - angle) / 360.0)
+}
+
+/// Get the angle equivalent to `angle` within `[min, max]` that is nearest to `reference`, in degrees.
+///
+/// Returns `None` if no equivalent angle lies within the limits.
+pub fn nearest_equivalent_within(angle: f64, reference: f64, [min, max]: [f64; 2]) -> Option<f64> {
+	let mut angle = nearest_equivalent(angle, reference);
+	if angle < min {
+		angle += 360.0 * float::ceil((min - angle) / 360.0);
+	} else if angle > max {
+		angle -= 360.0 * float::ceil((angle - max) / 360.0);
+	}
+	if angle >= min && angle <= max {
+		Some(angle)
+	} else {
+		None
+	}
+}
+
+/// Replace each joint target with the equivalent angle that is nearest to the feedback position of the joint.
+///
+/// If `limits` has an entry for a joint, the target is chosen within those limits.
+/// Joints without an entry in `limits` are unwrapped without limits.
+///
+/// Only pass revolute joints: the targets of linear joints are distances and must not be unwrapped.
+/// The targets are left unmodified if an error is returned.
+pub fn unwrap_joints(targets: &mut [f64], feedback: &[f64], limits: &[[f64; 2]]) -> Result<(), UnwrapError> {
+	if feedback.len() != targets.len() {
+		return Err(UnwrapError::WrongNumberOfJoints {
+			expected: targets.len(),
+			actual: feedback.len(),
+		});
+	}
+
+	for (joint, (&target, &reference)) in targets.iter().zip(feedback).enumerate() {
+		if let Some(&limits) = limits.get(joint) {
+			if nearest_equivalent_within(target, reference, limits).is_none() {
+				return Err(UnwrapError::OutOfLimits { joint, target });
+			}
+		}
+	}
+
+	for (joint, (target, &reference)) in targets.iter_mut().zip(feedback).enumerate() {
+		*target = match limits.get(joint) {
+			Some(&limits) => nearest_equivalent_within(*target, reference, limits).unwrap_or(*target),
+			None => nearest_equivalent(*target, reference),
+		};
+	}
+	Ok(())
+}
+
+impl core::fmt::Display for UnwrapError {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		match self {
+			Self::WrongNumberOfJoints { expected, actual } => write!(f, "wrong number of feedback joints, expected {}, got {}", expected, actual),
+			Self::OutOfLimits { joint, target } => write!(f, "no equivalent angle of target {} for joint {} lies within the joint limits", target, joint + 1),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for UnwrapError {}
+
+#[cfg(test)]
+#[test]
+fn test_unwrap_joints() {
+	use assert2::assert;
+
+	assert!(normalize(190.0) == -170.0);
+	assert!(normalize(-180.0) == -180.0);
+	assert!(normalize(180.0) == -180.0);
+	assert!(normalize(725.0) == 5.0);
+
+	assert!(nearest_equivalent(-170.0, 170.0) == 190.0);
+	assert!(nearest_equivalent(10.0, 700.0) == 730.0);
+	assert!(nearest_equivalent_within(-170.0, 170.0, [-200.0, 185.0]) == Some(-170.0));
+	assert!(nearest_equivalent_within(170.0, -170.0, [-180.0, 180.0]) == Some(170.0));
+	assert!(nearest_equivalent_within(170.0, -170.0, [-400.0, -100.0]) == Some(-190.0));
+	assert!(nearest_equivalent_within(90.0, 0.0, [-45.0, 45.0]) == None);
+
+	let mut targets = [-170.0, 170.0, 10.0];
+	assert!(let Ok(()) = unwrap_joints(&mut targets, &[170.0, -170.0, 350.0], &[[-360.0, 360.0], [-180.0, 180.0]]));
+	assert!(targets == [190.0, 170.0, 370.0]);
+
+	let mut targets = [-170.0, 90.0];
+	assert!(unwrap_joints(&mut targets, &[170.0, 0.0], &[[-360.0, 360.0], [-45.0, 45.0]]) == Err(UnwrapError::OutOfLimits { joint: 1, target: 90.0 }));
+	assert!(targets == [-170.0, 90.0]);
+	assert!(let Err(UnwrapError::WrongNumberOfJoints { expected: 2, actual: 1 }) = unwrap_joints(&mut targets, &[0.0], &[]));
+}
@@ -0,0 +1,67 @@
+use crate::msg;
+use approx::AbsDiffEq;
+
+impl AbsDiffEq for msg::EgmCartesian {
+	/// The maximum distance in millimeters.
+	type Epsilon = f64;
+
+	fn default_epsilon() -> f64 {
+		1e-6
+	}
+
+	fn abs_diff_eq(&self, other: &Self, epsilon: f64) -> bool {
+		self.approx_eq(other, epsilon)
+	}
+}
+
+impl AbsDiffEq for msg::EgmQuaternion {
+	/// The maximum angle in degrees.
+	type Epsilon = f64;
+
+	fn default_epsilon() -> f64 {
+		1e-6
+	}
+
+	fn abs_diff_eq(&self, other: &Self, epsilon: f64) -> bool {
+		self.approx_eq(other, epsilon)
+	}
+}
+
+impl AbsDiffEq for msg::EgmPose {
+	/// The maximum distance in millimeters and the maximum angle in degrees.
+	type Epsilon = f64;
+
+	fn default_epsilon() -> f64 {
+		1e-6
+	}
+
+	fn abs_diff_eq(&self, other: &Self, epsilon: f64) -> bool {
+		self.approx_eq(other, epsilon, epsilon)
+	}
+}
+
+impl AbsDiffEq for msg::EgmJoints {
+	/// The maximum difference per joint in degrees or millimeters.
+	type Epsilon = f64;
+
+	fn default_epsilon() -> f64 {
+		1e-6
+	}
+
+	fn abs_diff_eq(&self, other: &Self, epsilon: f64) -> bool {
+		self.approx_eq(other, epsilon)
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_abs_diff_eq() {
+	use approx::{assert_abs_diff_eq, assert_abs_diff_ne};
+
+	let a = msg::EgmPose::new([1.0, 2.0, 3.0], msg::EgmQuaternion::IDENTITY);
+	let b = msg::EgmPose::new([1.0, 2.0, 3.0 + 1e-9], msg::EgmQuaternion::IDENTITY);
+	assert_abs_diff_eq!(a, b);
+	assert_abs_diff_ne!(a, msg::EgmPose::new([1.0, 2.0, 3.5], msg::EgmQuaternion::IDENTITY));
+	assert_abs_diff_eq!(a, msg::EgmPose::new([1.0, 2.0, 3.5], msg::EgmQuaternion::IDENTITY), epsilon = 0.5);
+	assert_abs_diff_eq!(msg::EgmJoints::from(vec![1.0, 2.0]), msg::EgmJoints::from(vec![1.1, 2.0]), epsilon = 0.2);
+}
@@ -0,0 +1,258 @@
+use std::io::{Read, Seek};
+use std::time::Duration;
+
+use prost::Message;
+
+use crate::msg::EgmRobot;
+use crate::recording::{Direction, Record, RecordingError, RecordingReader};
+
+type FieldSize = fn(&EgmRobot) -> Option<usize>;
+
+/// The fields of a message from the robot controller that are tracked by [`BandwidthStats`].
+static FIELDS: [(&str, FieldSize); 17] = [
+	("header", |x| x.header.as_ref().map(Message::encoded_len)),
+	("feed_back.joints", |x| x.feed_back.as_ref()?.joints.as_ref().map(Message::encoded_len)),
+	("feed_back.cartesian", |x| x.feed_back.as_ref()?.cartesian.as_ref().map(Message::encoded_len)),
+	("feed_back.external_joints", |x| x.feed_back.as_ref()?.external_joints.as_ref().map(Message::encoded_len)),
+	("feed_back.time", |x| x.feed_back.as_ref()?.time.as_ref().map(Message::encoded_len)),
+	("planned.joints", |x| x.planned.as_ref()?.joints.as_ref().map(Message::encoded_len)),
+	("planned.cartesian", |x| x.planned.as_ref()?.cartesian.as_ref().map(Message::encoded_len)),
+	("planned.external_joints", |x| x.planned.as_ref()?.external_joints.as_ref().map(Message::encoded_len)),
+	("planned.time", |x| x.planned.as_ref()?.time.as_ref().map(Message::encoded_len)),
+	("motor_state", |x| x.motor_state.as_ref().map(Message::encoded_len)),
+	("mci_state", |x| x.mci_state.as_ref().map(Message::encoded_len)),
+	("mci_convergence_met", |x| x.mci_convergence_met.map(|_| 1)),
+	("test_signals", |x| x.test_signals.as_ref().map(Message::encoded_len)),
+	("rapid_exec_state", |x| x.rapid_exec_state.as_ref().map(Message::encoded_len)),
+	("measured_force", |x| x.measured_force.as_ref().map(Message::encoded_len)),
+	("utilization_rate", |x| x.utilization_rate.map(|_| 8)),
+	("other", |x| Some(x.encoded_len().saturating_sub(FIELDS[..16].iter().filter_map(|(_, size)| size(x)).sum()))),
+];
+
+/// Statistics of a single field of the messages from the robot controller.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldStats {
+	/// The path of the field, such as `feed_back.joints`.
+	pub name: &'static str,
+
+	/// The number of messages in which the field was populated.
+	pub count: u64,
+
+	/// The total encoded size of the field in bytes, excluding the protobuf tag and length.
+	pub bytes: u64,
+}
+
+/// Message size, field population and bandwidth statistics of a stream of messages from the robot controller.
+///
+/// Feed it with messages from a live stream with [`BandwidthStats::add`],
+/// or analyze a recording with [`BandwidthStats::from_recording`].
+/// The statistics help to decide which optional fields to disable on the controller,
+/// for example on constrained wireless links.
+///
+/// The [`std::fmt::Display`] implementation prints a report with the populated fields.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BandwidthStats {
+	messages: u64,
+	bytes: u64,
+	min_size: usize,
+	max_size: usize,
+	first_time: Option<Duration>,
+	last_time: Option<Duration>,
+	fields: Vec<FieldStats>,
+}
+
+impl BandwidthStats {
+	/// Create empty statistics.
+	pub fn new() -> Self {
+		Self {
+			messages: 0,
+			bytes: 0,
+			min_size: 0,
+			max_size: 0,
+			first_time: None,
+			last_time: None,
+			fields: FIELDS.iter().map(|(name, _)| FieldStats { name, count: 0, bytes: 0 }).collect(),
+		}
+	}
+
+	/// Add a message received at a time relative to an arbitrary reference.
+	///
+	/// The size of the message is the length of its encoding.
+	pub fn add(&mut self, time: Duration, msg: &EgmRobot) {
+		self.add_datagram(time, msg.encoded_len(), msg)
+	}
+
+	/// Add a message with the length of the datagram it was received in.
+	pub fn add_datagram(&mut self, time: Duration, len: usize, msg: &EgmRobot) {
+		self.min_size = if self.messages == 0 { len } else { self.min_size.min(len) };
+		self.max_size = self.max_size.max(len);
+		self.messages += 1;
+		self.bytes += len as u64;
+		self.first_time.get_or_insert(time);
+		self.last_time = Some(time);
+		for (stats, (_, size)) in self.fields.iter_mut().zip(&FIELDS) {
+			if let Some(size) = size(msg) {
+				stats.count += 1;
+				stats.bytes += size as u64;
+			}
+		}
+	}
+
+	/// Add a recorded message.
+	///
+	/// Messages sent to the robot controller and messages that fail to decode are ignored.
+	/// Returns true if the message was added.
+	pub fn add_record(&mut self, record: &Record) -> bool {
+		if record.direction != Direction::FromRobot {
+			return false;
+		}
+		match record.decode_robot() {
+			Ok(msg) => {
+				self.add_datagram(record.time, record.payload.len(), &msg);
+				true
+			},
+			Err(_) => false,
+		}
+	}
+
+	/// Compute the statistics of all messages from the robot controller in a recording.
+	pub fn from_recording<R: Read + Seek>(reader: &mut RecordingReader<R>) -> Result<Self, RecordingError> {
+		let mut stats = Self::new();
+		for index in 0..reader.len() {
+			if let Some(record) = reader.read(index)? {
+				stats.add_record(&record);
+			}
+		}
+		Ok(stats)
+	}
+
+	/// Get the number of messages.
+	pub fn messages(&self) -> u64 {
+		self.messages
+	}
+
+	/// Get the total size of all messages in bytes.
+	pub fn total_bytes(&self) -> u64 {
+		self.bytes
+	}
+
+	/// Get the size of the smallest message in bytes.
+	pub fn min_size(&self) -> usize {
+		self.min_size
+	}
+
+	/// Get the size of the largest message in bytes.
+	pub fn max_size(&self) -> usize {
+		self.max_size
+	}
+
+	/// Get the mean size of the messages in bytes.
+	pub fn mean_size(&self) -> f64 {
+		if self.messages == 0 {
+			return 0.0;
+		}
+		self.bytes as f64 / self.messages as f64
+	}
+
+	/// Get the time between the first and the last message.
+	pub fn duration(&self) -> Duration {
+		match (self.first_time, self.last_time) {
+			(Some(first), Some(last)) => last.checked_sub(first).unwrap_or_default(),
+			_ => Duration::ZERO,
+		}
+	}
+
+	/// Get the bandwidth used by the messages in bytes per second.
+	///
+	/// The bandwidth is estimated from the time between messages, so the last message is not counted.
+	/// Returns `None` if there are less than two messages or no time passed between them.
+	pub fn bytes_per_second(&self) -> Option<f64> {
+		let duration = self.duration().as_secs_f64();
+		if self.messages < 2 || duration <= 0.0 {
+			return None;
+		}
+		Some((self.bytes as f64 - self.bytes as f64 / self.messages as f64) / duration)
+	}
+
+	/// Get the statistics of all tracked fields.
+	///
+	/// The field `other` holds fields that are not tracked individually, such as fields of newer RobotWare versions,
+	/// and the protobuf tags and lengths of all fields.
+	pub fn fields(&self) -> &[FieldStats] {
+		&self.fields
+	}
+
+	/// Get the fraction of messages in which a field was populated.
+	///
+	/// Returns `None` if the field is not tracked or there are no messages.
+	pub fn field_frequency(&self, name: &str) -> Option<f64> {
+		if self.messages == 0 {
+			return None;
+		}
+		let field = self.fields.iter().find(|x| x.name == name)?;
+		Some(field.count as f64 / self.messages as f64)
+	}
+}
+
+impl Default for BandwidthStats {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl std::fmt::Display for BandwidthStats {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		writeln!(f, "messages: {}", self.messages)?;
+		writeln!(f, "size: {:.1} bytes mean, {} min, {} max", self.mean_size(), self.min_size, self.max_size)?;
+		match self.bytes_per_second() {
+			Some(bandwidth) => writeln!(f, "bandwidth: {:.0} bytes/s", bandwidth)?,
+			None => writeln!(f, "bandwidth: unknown")?,
+		}
+		for field in self.fields.iter().filter(|x| x.count > 0) {
+			let frequency = field.count as f64 / self.messages as f64;
+			let share = field.bytes as f64 / self.bytes as f64;
+			writeln!(f, "  {:<26} {:>6.1}% of messages {:>6.1}% of bytes", field.name, frequency * 100.0, share * 100.0)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_bandwidth_stats() {
+	use crate::msg::{EgmClock, EgmFeedBack, EgmJoints};
+	use crate::recording::Recorder;
+	use assert2::{assert, let_assert};
+
+	let message = |msec: u64, force: bool| EgmRobot {
+		feed_back: Some(EgmFeedBack {
+			joints: Some(EgmJoints::from_degrees(vec![1.0; 6])),
+			time: Some(EgmClock::new(0, msec * 1000)),
+			..Default::default()
+		}),
+		utilization_rate: if force { Some(50.0) } else { None },
+		..Default::default()
+	};
+
+	let mut recorder = Recorder::new(std::io::Cursor::new(Vec::new())).unwrap();
+	for i in 0..4 {
+		recorder.record_robot(Duration::from_millis(4 * i), &message(4 * i, i % 2 == 0)).unwrap();
+	}
+	recorder.record(Direction::ToRobot, Duration::from_millis(16), &[1, 2, 3]).unwrap();
+	let mut reader = RecordingReader::new(recorder.finish().unwrap()).unwrap();
+	let_assert!(Ok(stats) = BandwidthStats::from_recording(&mut reader));
+
+	assert!(stats.messages() == 4);
+	assert!(stats.duration() == Duration::from_millis(12));
+	assert!(stats.field_frequency("feed_back.joints") == Some(1.0));
+	assert!(stats.field_frequency("utilization_rate") == Some(0.5));
+	assert!(stats.field_frequency("planned.joints") == Some(0.0));
+	assert!(stats.field_frequency("other") == Some(1.0));
+	assert!(stats.max_size() - stats.min_size() == 9);
+	let_assert!(Some(bandwidth) = stats.bytes_per_second());
+	assert!((bandwidth - stats.mean_size() * 250.0).abs() < 1e-6);
+
+	let report = stats.to_string();
+	assert!(report.contains("feed_back.joints"));
+	assert!(!report.contains("planned.joints"));
+}
@@ -0,0 +1,224 @@
+//! Receiving and sending several datagrams at once.
+//!
+//! With the `mmsg` feature on Linux, this uses the `recvmmsg` and `sendmmsg` system calls.
+//! Otherwise, it falls back to one system call per datagram.
+
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+/// Receive up to `buffers.len()` datagrams.
+///
+/// Waits for the first datagram according to the blocking mode of the socket,
+/// and then only returns datagrams that are already queued.
+/// Returns the length and the sender of each received datagram, in order.
+///
+/// The socket must be in non-blocking mode if `nonblocking` is true.
+#[cfg(all(feature = "mmsg", target_os = "linux"))]
+pub(crate) fn recv_batch(socket: &UdpSocket, buffers: &mut [Vec<u8>], nonblocking: bool) -> io::Result<Vec<(usize, SocketAddr)>> {
+	use std::os::unix::io::AsRawFd;
+	let _ = nonblocking;
+
+	let mut addresses: Vec<libc::sockaddr_storage> = vec![unsafe { std::mem::zeroed() }; buffers.len()];
+	let mut iovecs: Vec<libc::iovec> = buffers
+		.iter_mut()
+		.map(|buffer| libc::iovec {
+			iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+			iov_len: buffer.len(),
+		})
+		.collect();
+	let mut headers: Vec<libc::mmsghdr> = iovecs
+		.iter_mut()
+		.zip(&mut addresses)
+		.map(|(iovec, address)| {
+			let mut header: libc::mmsghdr = unsafe { std::mem::zeroed() };
+			header.msg_hdr.msg_name = address as *mut libc::sockaddr_storage as *mut libc::c_void;
+			header.msg_hdr.msg_namelen = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+			header.msg_hdr.msg_iov = iovec;
+			header.msg_hdr.msg_iovlen = 1;
+			header
+		})
+		.collect();
+
+	let count = unsafe {
+		libc::recvmmsg(
+			socket.as_raw_fd(),
+			headers.as_mut_ptr(),
+			headers.len() as libc::c_uint,
+			libc::MSG_WAITFORONE as _,
+			std::ptr::null_mut(),
+		)
+	};
+	if count < 0 {
+		return Err(io::Error::last_os_error());
+	}
+
+	headers[..count as usize]
+		.iter()
+		.zip(&addresses)
+		.map(|(header, address)| {
+			let address = from_sockaddr(address).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unsupported address family"))?;
+			Ok((header.msg_len as usize, address))
+		})
+		.collect()
+}
+
+/// Receive up to `buffers.len()` datagrams.
+///
+/// Waits for the first datagram according to the blocking mode of the socket,
+/// and then only returns datagrams that are already queued.
+/// Returns the length and the sender of each received datagram, in order.
+///
+/// The socket must be in non-blocking mode if `nonblocking` is true.
+#[cfg(not(all(feature = "mmsg", target_os = "linux")))]
+pub(crate) fn recv_batch(socket: &UdpSocket, buffers: &mut [Vec<u8>], nonblocking: bool) -> io::Result<Vec<(usize, SocketAddr)>> {
+	let mut received = Vec::with_capacity(buffers.len());
+	let (first, rest) = match buffers.split_first_mut() {
+		Some(x) => x,
+		None => return Ok(received),
+	};
+	received.push(socket.recv_from(first)?);
+
+	socket.set_nonblocking(true)?;
+	let mut result = Ok(());
+	for buffer in rest {
+		match socket.recv_from(buffer) {
+			Ok(x) => received.push(x),
+			Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+			Err(e) => {
+				result = Err(e);
+				break;
+			},
+		}
+	}
+	socket.set_nonblocking(nonblocking)?;
+	result?;
+	Ok(received)
+}
+
+/// Send datagrams to the given addresses.
+///
+/// Returns the number of bytes sent for each datagram, for the datagrams that were sent before an error occurred.
+/// An error is only returned if the first datagram could not be sent.
+#[cfg(all(feature = "mmsg", target_os = "linux"))]
+pub(crate) fn send_batch(socket: &UdpSocket, datagrams: &[(&[u8], SocketAddr)]) -> io::Result<Vec<usize>> {
+	use std::os::unix::io::AsRawFd;
+
+	let mut addresses: Vec<(libc::sockaddr_storage, libc::socklen_t)> = datagrams.iter().map(|(_, address)| to_sockaddr(address)).collect();
+	let mut iovecs: Vec<libc::iovec> = datagrams
+		.iter()
+		.map(|(datagram, _)| libc::iovec {
+			iov_base: datagram.as_ptr() as *mut libc::c_void,
+			iov_len: datagram.len(),
+		})
+		.collect();
+	let mut headers: Vec<libc::mmsghdr> = iovecs
+		.iter_mut()
+		.zip(&mut addresses)
+		.map(|(iovec, (address, length))| {
+			let mut header: libc::mmsghdr = unsafe { std::mem::zeroed() };
+			header.msg_hdr.msg_name = address as *mut libc::sockaddr_storage as *mut libc::c_void;
+			header.msg_hdr.msg_namelen = *length;
+			header.msg_hdr.msg_iov = iovec;
+			header.msg_hdr.msg_iovlen = 1;
+			header
+		})
+		.collect();
+
+	let mut sent = Vec::with_capacity(headers.len());
+	while sent.len() < headers.len() {
+		let remaining = &mut headers[sent.len()..];
+		let count = unsafe { libc::sendmmsg(socket.as_raw_fd(), remaining.as_mut_ptr(), remaining.len() as libc::c_uint, 0) };
+		if count < 0 {
+			let error = io::Error::last_os_error();
+			if sent.is_empty() {
+				return Err(error);
+			}
+			break;
+		}
+		sent.extend(remaining[..count as usize].iter().map(|header| header.msg_len as usize));
+	}
+	Ok(sent)
+}
+
+/// Send datagrams to the given addresses.
+///
+/// Returns the number of bytes sent for each datagram, for the datagrams that were sent before an error occurred.
+/// An error is only returned if the first datagram could not be sent.
+#[cfg(not(all(feature = "mmsg", target_os = "linux")))]
+pub(crate) fn send_batch(socket: &UdpSocket, datagrams: &[(&[u8], SocketAddr)]) -> io::Result<Vec<usize>> {
+	let mut sent = Vec::with_capacity(datagrams.len());
+	for (datagram, address) in datagrams {
+		match socket.send_to(datagram, address) {
+			Ok(x) => sent.push(x),
+			Err(e) if sent.is_empty() => return Err(e),
+			Err(_) => break,
+		}
+	}
+	Ok(sent)
+}
+
+#[cfg(all(feature = "mmsg", target_os = "linux"))]
+fn from_sockaddr(address: &libc::sockaddr_storage) -> Option<SocketAddr> {
+	use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+
+	match address.ss_family as libc::c_int {
+		libc::AF_INET => {
+			let address = unsafe { &*(address as *const libc::sockaddr_storage as *const libc::sockaddr_in) };
+			let ip = Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr));
+			Some(SocketAddrV4::new(ip, u16::from_be(address.sin_port)).into())
+		},
+		libc::AF_INET6 => {
+			let address = unsafe { &*(address as *const libc::sockaddr_storage as *const libc::sockaddr_in6) };
+			let ip = Ipv6Addr::from(address.sin6_addr.s6_addr);
+			Some(SocketAddrV6::new(ip, u16::from_be(address.sin6_port), address.sin6_flowinfo, address.sin6_scope_id).into())
+		},
+		_ => None,
+	}
+}
+
+#[cfg(all(feature = "mmsg", target_os = "linux"))]
+fn to_sockaddr(address: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+	let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+	let length = match address {
+		SocketAddr::V4(address) => {
+			let raw = unsafe { &mut *(&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in) };
+			raw.sin_family = libc::AF_INET as libc::sa_family_t;
+			raw.sin_port = address.port().to_be();
+			raw.sin_addr.s_addr = u32::from(*address.ip()).to_be();
+			std::mem::size_of::<libc::sockaddr_in>()
+		},
+		SocketAddr::V6(address) => {
+			let raw = unsafe { &mut *(&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in6) };
+			raw.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+			raw.sin6_port = address.port().to_be();
+			raw.sin6_addr.s6_addr = address.ip().octets();
+			raw.sin6_flowinfo = address.flowinfo();
+			raw.sin6_scope_id = address.scope_id();
+			std::mem::size_of::<libc::sockaddr_in6>()
+		},
+	};
+	(storage, length as libc::socklen_t)
+}
+
+#[cfg(test)]
+#[test]
+fn test_batch() {
+	use assert2::{assert, let_assert};
+
+	let a = UdpSocket::bind("127.0.0.1:0").unwrap();
+	let b = UdpSocket::bind("127.0.0.1:0").unwrap();
+	let (address_a, address_b) = (a.local_addr().unwrap(), b.local_addr().unwrap());
+
+	let_assert!(Ok(sent) = send_batch(&a, &[(&[1, 2, 3], address_b), (&[4, 5], address_b), (&[6], address_b)]));
+	assert!(sent == [3, 2, 1]);
+
+	let mut buffers = vec![vec![0; 16]; 2];
+	let_assert!(Ok(received) = recv_batch(&b, &mut buffers, false));
+	assert!(received == [(3, address_a), (2, address_a)]);
+	assert!(buffers[0][..3] == [1, 2, 3]);
+	assert!(buffers[1][..2] == [4, 5]);
+
+	let_assert!(Ok(received) = recv_batch(&b, &mut buffers, false));
+	assert!(received == [(1, address_a)]);
+	assert!(buffers[0][0] == 6);
+}
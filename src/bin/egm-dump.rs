@@ -0,0 +1,348 @@
+use abbegm::msg::EgmRobot;
+use abbegm::recording::Recorder;
+use abbegm::sync_peer::EgmPeer;
+use abbegm::ReceiveError;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use structopt::clap::AppSettings;
+use structopt::StructOpt;
+
+const FIELDS: &[&str] = &[
+	"seqno",
+	"tm",
+	"time",
+	"joints",
+	"external_joints",
+	"pos",
+	"orient",
+	"planned_joints",
+	"planned_pos",
+	"planned_orient",
+	"motors",
+	"mci_state",
+	"convergence",
+	"rapid_running",
+	"utilization",
+	"force",
+];
+
+#[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::ColoredHelp)]
+#[structopt(setting = AppSettings::DeriveDisplayOrder)]
+#[structopt(setting = AppSettings::UnifiedHelpMessage)]
+/// Capture and decode EGM messages sent by a robot controller.
+struct Options {
+	/// Local address to bind to.
+	///
+	/// Defaults to port 6510 on all IPv4 and IPv6 interfaces.
+	#[structopt(long)]
+	#[structopt(value_name = "HOST:PORT")]
+	bind: Option<String>,
+
+	/// The output format.
+	#[structopt(long)]
+	#[structopt(value_name = "FORMAT")]
+	#[structopt(possible_values = &["pretty", "csv", "json"])]
+	#[structopt(default_value = "pretty")]
+	format: String,
+
+	/// Comma separated list of fields to print.
+	///
+	/// Without this option, the pretty format prints the whole message and the other formats print all fields.
+	#[structopt(long)]
+	#[structopt(value_name = "FIELDS")]
+	#[structopt(use_delimiter = true)]
+	#[structopt(possible_values = FIELDS)]
+	fields: Vec<String>,
+
+	/// Print at most this many messages per second.
+	#[structopt(long)]
+	#[structopt(value_name = "HZ")]
+	#[structopt(parse(try_from_str = parse_rate))]
+	max_rate: Option<f64>,
+
+	/// Exit after receiving this many messages.
+	#[structopt(long)]
+	#[structopt(value_name = "N")]
+	count: Option<u64>,
+
+	/// Also write all received messages to a recording file.
+	#[structopt(long)]
+	#[structopt(value_name = "PATH")]
+	record: Option<std::path::PathBuf>,
+}
+
+fn parse_rate(input: &str) -> Result<f64, String> {
+	let rate: f64 = input.parse().map_err(|e| format!("{}", e))?;
+	if rate.is_finite() && rate > 0.0 {
+		Ok(rate)
+	} else {
+		Err(String::from("rate must be a positive number"))
+	}
+}
+
+/// Check if a receive was interrupted by the read timeout or a signal.
+fn is_timeout(error: &std::io::Error) -> bool {
+	use std::io::ErrorKind;
+	matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted)
+}
+
+/// Set when the user presses Ctrl-C, so the recording can be finished before exiting.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Set [`INTERRUPTED`] on Ctrl-C instead of terminating the process.
+#[cfg(unix)]
+fn catch_interrupt() {
+	use std::os::raw::c_int;
+	const SIGINT: c_int = 2;
+
+	extern "C" fn handler(_signal: c_int) {
+		INTERRUPTED.store(true, Ordering::Relaxed);
+	}
+
+	extern "C" {
+		fn signal(signal: c_int, handler: extern "C" fn(c_int)) -> usize;
+	}
+
+	unsafe {
+		signal(SIGINT, handler);
+	}
+}
+
+/// Set [`INTERRUPTED`] on Ctrl-C instead of terminating the process.
+#[cfg(windows)]
+fn catch_interrupt() {
+	unsafe extern "system" fn handler(_event: u32) -> i32 {
+		INTERRUPTED.store(true, Ordering::Relaxed);
+		1
+	}
+
+	#[link(name = "kernel32")]
+	extern "system" {
+		fn SetConsoleCtrlHandler(handler: Option<unsafe extern "system" fn(u32) -> i32>, add: i32) -> i32;
+	}
+
+	unsafe {
+		SetConsoleCtrlHandler(Some(handler), 1);
+	}
+}
+
+#[cfg(not(any(unix, windows)))]
+fn catch_interrupt() {}
+
+/// The value of a field in a message.
+enum Value {
+	Number(String),
+	Bool(bool),
+	Text(&'static str),
+	Missing,
+}
+
+impl Value {
+	fn number(value: impl ToString) -> Self {
+		Self::Number(value.to_string())
+	}
+
+	fn csv(&self) -> String {
+		match self {
+			Self::Number(x) => x.clone(),
+			Self::Bool(x) => x.to_string(),
+			Self::Text(x) => x.to_string(),
+			Self::Missing => String::new(),
+		}
+	}
+
+	fn json(&self) -> String {
+		match self {
+			Self::Number(x) => x.clone(),
+			Self::Bool(x) => x.to_string(),
+			Self::Text(x) => format!("\"{}\"", x),
+			Self::Missing => String::from("null"),
+		}
+	}
+}
+
+/// Add a column for every value in a list, or a single missing column if the list is absent.
+fn values(columns: &mut Vec<(String, Value)>, name: &str, values: Option<&[f64]>) {
+	match values {
+		Some(values) => {
+			for (i, value) in values.iter().enumerate() {
+				columns.push((format!("{}[{}]", name, i), Value::number(value)));
+			}
+		}
+		None => columns.push((name.to_string(), Value::Missing)),
+	}
+}
+
+/// Extract the columns for a field from a message.
+fn extract(columns: &mut Vec<(String, Value)>, state: &EgmRobot, field: &str) {
+	let feedback_pose = state.feedback_pose();
+	let planned_pose = state.planned_pose();
+	match field {
+		"seqno" => columns.push((field.into(), state.sequence_number().map_or(Value::Missing, Value::number))),
+		"tm" => columns.push((field.into(), state.timestamp_ms().map_or(Value::Missing, Value::number))),
+		"time" => {
+			let time = state.feedback_time().map(|x| x.elapsed_since_epoch().as_secs_f64());
+			columns.push((field.into(), time.map_or(Value::Missing, Value::number)))
+		}
+		"joints" => values(columns, field, state.feedback_joints().map(|x| x.as_slice())),
+		"external_joints" => values(columns, field, state.feedback_extenal_joints().map(|x| x.as_slice())),
+		"pos" => values(columns, field, feedback_pose.and_then(|x| x.pos).map(|x| x.as_mm()).as_ref().map(|x| &x[..])),
+		"orient" => values(columns, field, feedback_pose.and_then(|x| x.orient).map(|x| [x.u0, x.u1, x.u2, x.u3]).as_ref().map(|x| &x[..])),
+		"planned_joints" => values(columns, field, state.planned_joints().map(|x| x.as_slice())),
+		"planned_pos" => values(columns, field, planned_pose.and_then(|x| x.pos).map(|x| x.as_mm()).as_ref().map(|x| &x[..])),
+		"planned_orient" => values(columns, field, planned_pose.and_then(|x| x.orient).map(|x| [x.u0, x.u1, x.u2, x.u3]).as_ref().map(|x| &x[..])),
+		"motors" => columns.push((field.into(), state.motors_enabled().map_or(Value::Missing, Value::Bool))),
+		"mci_state" => {
+			use abbegm::msg::egm_mci_state::MciStateType;
+			let value = match state.mci_state.as_ref().map(|x| x.state()) {
+				Some(MciStateType::MciUndefined) => Value::Text("undefined"),
+				Some(MciStateType::MciError) => Value::Text("error"),
+				Some(MciStateType::MciStopped) => Value::Text("stopped"),
+				Some(MciStateType::MciRunning) => Value::Text("running"),
+				None => Value::Missing,
+			};
+			columns.push((field.into(), value))
+		}
+		"convergence" => columns.push((field.into(), state.mci_convergence_met.map_or(Value::Missing, Value::Bool))),
+		"rapid_running" => columns.push((field.into(), state.rapid_running().map_or(Value::Missing, Value::Bool))),
+		"utilization" => columns.push((field.into(), state.utilization_rate.map_or(Value::Missing, Value::number))),
+		"force" => values(columns, field, state.measured_force().map(|x| x.as_slice())),
+		_ => unreachable!("unknown field: {}", field),
+	}
+}
+
+struct Printer {
+	format: String,
+	fields: Vec<String>,
+	header_printed: bool,
+}
+
+impl Printer {
+	fn print(&mut self, output: &mut impl Write, state: &EgmRobot) -> std::io::Result<()> {
+		if self.format == "pretty" && self.fields.is_empty() {
+			return writeln!(output, "{:#?}", state);
+		}
+
+		let mut columns = Vec::new();
+		for field in &self.fields {
+			extract(&mut columns, state, field);
+		}
+		match self.format.as_str() {
+			"csv" => {
+				if !self.header_printed {
+					let header: Vec<_> = columns.iter().map(|(name, _)| name.as_str()).collect();
+					writeln!(output, "{}", header.join(","))?;
+					self.header_printed = true;
+				}
+				let row: Vec<_> = columns.iter().map(|(_, value)| value.csv()).collect();
+				writeln!(output, "{}", row.join(","))
+			}
+			"json" => {
+				let entries: Vec<_> = columns.iter().map(|(name, value)| format!("\"{}\":{}", name, value.json())).collect();
+				writeln!(output, "{{{}}}", entries.join(","))
+			}
+			_ => {
+				for (name, value) in &columns {
+					if !matches!(value, Value::Missing) {
+						writeln!(output, "{}: {}", name, value.csv())?;
+					}
+				}
+				writeln!(output)
+			}
+		}
+	}
+}
+
+fn do_main(options: Options) -> Result<(), String> {
+	let mut peer = match &options.bind {
+		Some(bind) => EgmPeer::bind(bind).map_err(|e| format!("failed to bind to local endpoint {}: {}", bind, e))?,
+		None => {
+			let socket = abbegm::net::bind_dual_stack(6510).map_err(|e| format!("failed to bind to local endpoint [::]:6510: {}", e))?;
+			EgmPeer::new(socket)
+		}
+	};
+
+	let local_address = peer.socket().local_addr().map_err(|e| format!("failed to get local socket address: {}", e))?;
+	eprintln!("Listening for messages on {}", local_address);
+
+	let mut recorder = match &options.record {
+		None => None,
+		Some(path) => {
+			let file = std::fs::File::create(path).map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+			let recorder = Recorder::new(std::io::BufWriter::new(file)).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+			Some(recorder)
+		}
+	};
+
+	// Wake up regularly while recording, so the recording is finished properly when the user presses Ctrl-C.
+	if recorder.is_some() {
+		catch_interrupt();
+		let timeout = Some(Duration::from_millis(100));
+		peer.socket().set_read_timeout(timeout).map_err(|e| format!("failed to set read timeout: {}", e))?;
+	}
+
+	let fields = if options.fields.is_empty() && options.format != "pretty" {
+		FIELDS.iter().map(|x| x.to_string()).collect()
+	} else {
+		options.fields.clone()
+	};
+	let mut printer = Printer {
+		format: options.format.clone(),
+		fields,
+		header_printed: false,
+	};
+	let min_interval = options.max_rate.map(|rate| Duration::from_secs_f64(1.0 / rate));
+
+	let stdout = std::io::stdout();
+	let mut stdout = stdout.lock();
+	let start = Instant::now();
+	let mut last_print: Option<Instant> = None;
+	let mut received = 0;
+	loop {
+		if let Some(count) = options.count {
+			if received >= count {
+				break;
+			}
+		}
+		if INTERRUPTED.load(Ordering::Relaxed) {
+			break;
+		}
+		let state = match peer.recv_from() {
+			Ok((state, _address)) => state,
+			Err(ReceiveError::Io(e)) if is_timeout(&e) => continue,
+			Err(ReceiveError::Io(e)) => return Err(format!("failed to receive robot state: {}", e)),
+			Err(e) => {
+				eprintln!("Warning: ignoring invalid message: {}", e);
+				continue;
+			}
+		};
+		received += 1;
+
+		if let Some(recorder) = &mut recorder {
+			recorder.record_robot(start.elapsed(), &state).map_err(|e| format!("failed to record message: {}", e))?;
+		}
+
+		let now = Instant::now();
+		if let (Some(last_print), Some(min_interval)) = (last_print, min_interval) {
+			if now - last_print < min_interval {
+				continue;
+			}
+		}
+		last_print = Some(now);
+		printer.print(&mut stdout, &state).map_err(|e| format!("failed to write output: {}", e))?;
+	}
+
+	if let Some(recorder) = recorder {
+		recorder.finish().map_err(|e| format!("failed to finish recording: {}", e))?;
+	}
+	Ok(())
+}
+
+fn main() {
+	if let Err(e) = do_main(Options::from_args()) {
+		eprintln!("Error: {}", e);
+		std::process::exit(1);
+	}
+}
@@ -0,0 +1,109 @@
+use abbegm::recording::{Direction, RecordingReader};
+use abbegm::sync_peer::EgmPeer;
+use std::net::ToSocketAddrs;
+use std::time::{Duration, Instant};
+use structopt::clap::AppSettings;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::ColoredHelp)]
+#[structopt(setting = AppSettings::DeriveDisplayOrder)]
+#[structopt(setting = AppSettings::UnifiedHelpMessage)]
+/// Send the messages to the robot controller from a recording.
+struct Options {
+	/// The recording to play back.
+	#[structopt(value_name = "RECORDING")]
+	recording: std::path::PathBuf,
+
+	/// Address of the robot controller to send the messages to.
+	#[structopt(long)]
+	#[structopt(value_name = "HOST:PORT")]
+	target: String,
+
+	/// Local address to bind to.
+	///
+	/// Defaults to a random port on all interfaces of the same address family as the target.
+	#[structopt(long)]
+	#[structopt(value_name = "HOST:PORT")]
+	bind: Option<String>,
+
+	/// Playback speed relative to the original timing.
+	#[structopt(long)]
+	#[structopt(value_name = "FACTOR")]
+	#[structopt(default_value = "1")]
+	speed: f64,
+
+	/// Start playback at this time in the recording, in seconds.
+	#[structopt(long)]
+	#[structopt(value_name = "SECONDS")]
+	#[structopt(default_value = "0")]
+	start: f64,
+
+	/// Confirm that the robot should perform motion.
+	#[structopt(long)]
+	confirm_motion: bool,
+}
+
+fn do_main(options: Options) -> Result<(), String> {
+	if !options.confirm_motion {
+		return Err("refusing to send motion commands without --confirm-motion".into());
+	}
+	if !(options.speed > 0.0 && options.speed.is_finite()) {
+		return Err(format!("invalid playback speed: {}", options.speed));
+	}
+	if !(options.start >= 0.0 && options.start.is_finite()) {
+		return Err(format!("invalid start time: {}", options.start));
+	}
+
+	let target = options
+		.target
+		.to_socket_addrs()
+		.map_err(|e| format!("failed to resolve target address {}: {}", options.target, e))?
+		.next()
+		.ok_or_else(|| format!("target address {} did not resolve to any address", options.target))?;
+
+	let path = &options.recording;
+	let file = std::fs::File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+	let mut reader = RecordingReader::new(std::io::BufReader::new(file)).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+	let bind = match &options.bind {
+		Some(bind) => bind.as_str(),
+		None if target.is_ipv4() => "0.0.0.0:0",
+		None => "[::]:0",
+	};
+	let mut peer = EgmPeer::bind(bind).map_err(|e| format!("failed to bind to local endpoint {}: {}", bind, e))?;
+
+	let first = reader.seek_time(Duration::from_secs_f64(options.start));
+	let start = Instant::now();
+	let mut start_time = None;
+	let mut sent = 0;
+	for index in first..reader.len() {
+		let record = match reader.read(index).map_err(|e| format!("failed to read record {}: {}", index, e))? {
+			Some(record) => record,
+			None => break,
+		};
+		if record.direction != Direction::ToRobot {
+			continue;
+		}
+		let msg = record.decode_sensor().map_err(|e| format!("failed to decode record {}: {}", index, e))?;
+
+		let start_time = *start_time.get_or_insert(record.time);
+		let due = start + (record.time - start_time).div_f64(options.speed);
+		let now = Instant::now();
+		if due > now {
+			std::thread::sleep(due - now);
+		}
+		peer.send_to(&msg, &target).map_err(|e| format!("failed to send message: {}", e))?;
+		sent += 1;
+	}
+
+	eprintln!("Sent {} messages to {} in {:.3} seconds", sent, target, start.elapsed().as_secs_f64());
+	Ok(())
+}
+
+fn main() {
+	if let Err(e) = do_main(Options::from_args()) {
+		eprintln!("Error: {}", e);
+		std::process::exit(1);
+	}
+}
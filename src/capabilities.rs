@@ -0,0 +1,178 @@
+use crate::msg::EgmRobot;
+
+/// The optional fields that a robot controller populates in its messages.
+///
+/// Which fields are sent depends on the controller configuration and the RobotWare version.
+/// Use a [`CapabilityDetector`] to determine the capabilities from the first messages of a session.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ProtocolCapabilities {
+	/// The controller sends feedback joint positions.
+	pub feedback_joints: bool,
+
+	/// The controller sends a feedback cartesian pose.
+	pub feedback_pose: bool,
+
+	/// The controller sends feedback positions for external joints.
+	pub external_joints: bool,
+
+	/// The controller sends planned joint positions.
+	pub planned_joints: bool,
+
+	/// The controller sends a planned cartesian pose.
+	pub planned_pose: bool,
+
+	/// The controller sends the state of the motors.
+	pub motor_state: bool,
+
+	/// The controller sends the state of the EGM motion control interface.
+	pub mci_state: bool,
+
+	/// The controller sends the RAPID execution state.
+	pub rapid_exec_state: bool,
+
+	/// The controller sends measured force values.
+	pub measured_force: bool,
+
+	/// The controller sends the utilization rate.
+	pub utilization_rate: bool,
+
+	/// The controller sends test signals.
+	pub test_signals: bool,
+}
+
+impl ProtocolCapabilities {
+	/// Get the capabilities that are present in a single message.
+	pub fn from_message(message: &EgmRobot) -> Self {
+		let feed_back = message.feed_back.as_ref();
+		let planned = message.planned.as_ref();
+		Self {
+			feedback_joints: feed_back.and_then(|x| x.joints.as_ref()).map(|x| !x.joints.is_empty()).unwrap_or(false),
+			feedback_pose: feed_back.map(|x| x.cartesian.is_some()).unwrap_or(false),
+			external_joints: feed_back.and_then(|x| x.external_joints.as_ref()).map(|x| !x.joints.is_empty()).unwrap_or(false),
+			planned_joints: planned.and_then(|x| x.joints.as_ref()).map(|x| !x.joints.is_empty()).unwrap_or(false),
+			planned_pose: planned.map(|x| x.cartesian.is_some()).unwrap_or(false),
+			motor_state: message.motor_state.is_some(),
+			mci_state: message.mci_state.is_some(),
+			rapid_exec_state: message.rapid_exec_state.is_some(),
+			measured_force: message.measured_force.as_ref().map(|x| !x.force.is_empty()).unwrap_or(false),
+			utilization_rate: message.utilization_rate.is_some(),
+			test_signals: message.test_signals.as_ref().map(|x| !x.signals.is_empty()).unwrap_or(false),
+		}
+	}
+
+	/// Add the capabilities of `other` to `self`.
+	pub fn merge(&mut self, other: &Self) {
+		self.feedback_joints |= other.feedback_joints;
+		self.feedback_pose |= other.feedback_pose;
+		self.external_joints |= other.external_joints;
+		self.planned_joints |= other.planned_joints;
+		self.planned_pose |= other.planned_pose;
+		self.motor_state |= other.motor_state;
+		self.mci_state |= other.mci_state;
+		self.rapid_exec_state |= other.rapid_exec_state;
+		self.measured_force |= other.measured_force;
+		self.utilization_rate |= other.utilization_rate;
+		self.test_signals |= other.test_signals;
+	}
+}
+
+/// Detect the [`ProtocolCapabilities`] of a robot controller from its first messages.
+///
+/// A field is considered supported if it is populated in any of the observed messages.
+#[derive(Clone, Debug)]
+pub struct CapabilityDetector {
+	capabilities: ProtocolCapabilities,
+	observed: usize,
+	required: usize,
+}
+
+impl CapabilityDetector {
+	/// The default number of messages to observe before the detection is complete.
+	pub const DEFAULT_MESSAGE_COUNT: usize = 10;
+
+	/// Create a detector that observes `message_count` messages.
+	pub fn new(message_count: usize) -> Self {
+		Self {
+			capabilities: ProtocolCapabilities::default(),
+			observed: 0,
+			required: message_count,
+		}
+	}
+
+	/// Observe a message from the robot controller.
+	///
+	/// Returns the detected capabilities once enough messages have been observed.
+	/// Messages observed after the detection is complete are ignored.
+	pub fn observe(&mut self, message: &EgmRobot) -> Option<&ProtocolCapabilities> {
+		if !self.is_complete() {
+			self.capabilities.merge(&ProtocolCapabilities::from_message(message));
+			self.observed += 1;
+		}
+		if self.is_complete() {
+			Some(&self.capabilities)
+		} else {
+			None
+		}
+	}
+
+	/// Check if enough messages have been observed to complete the detection.
+	pub fn is_complete(&self) -> bool {
+		self.observed >= self.required
+	}
+
+	/// Get the number of messages observed so far.
+	pub fn observed(&self) -> usize {
+		self.observed
+	}
+
+	/// Get the capabilities detected so far, even if the detection is not complete yet.
+	pub fn capabilities(&self) -> &ProtocolCapabilities {
+		&self.capabilities
+	}
+}
+
+impl Default for CapabilityDetector {
+	fn default() -> Self {
+		Self::new(Self::DEFAULT_MESSAGE_COUNT)
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_detect_capabilities() {
+	use crate::msg;
+	use assert2::assert;
+
+	let with_joints = EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(vec![0.0; 6])),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let with_force = EgmRobot {
+		measured_force: Some(msg::EgmMeasuredForce::from_values(vec![0.0; 6])),
+		utilization_rate: Some(10.0),
+		..Default::default()
+	};
+
+	let mut detector = CapabilityDetector::new(2);
+	assert!(detector.observe(&with_joints).is_none());
+	let capabilities = *detector.observe(&with_force).unwrap();
+	assert!(capabilities.feedback_joints);
+	assert!(capabilities.measured_force);
+	assert!(capabilities.utilization_rate);
+	assert!(!capabilities.external_joints);
+	assert!(!capabilities.feedback_pose);
+
+	// Messages after completion are ignored.
+	let with_external = EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			external_joints: Some(msg::EgmJoints::from_degrees(vec![0.0])),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	assert!(!detector.observe(&with_external).unwrap().external_joints);
+	assert!(detector.observed() == 2);
+}
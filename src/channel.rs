@@ -0,0 +1,275 @@
+//! Bounded channels between planners and control loops, with explicit overflow policies.
+//!
+//! A planner that computes targets ahead of time and a control loop that consumes one target per cycle rarely run at exactly the same pace.
+//! The [`OverflowPolicy`] of the channel determines what happens when the planner gets too far ahead:
+//! the oldest targets are dropped, the planner waits, or the planner gets an error.
+//!
+//! The control loop should use [`TargetReceiver::try_recv`], which never blocks.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use tokio::sync::Notify;
+
+/// What happens when a target is sent to a full channel.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+	/// Drop the oldest target in the channel to make room for the new one.
+	///
+	/// Sending never waits, and the number of dropped targets is counted.
+	/// Use this when only recent targets matter, such as with targets computed from live sensor data.
+	DropOldest,
+
+	/// Wait until the control loop has consumed a target.
+	///
+	/// Use this when every target matters, such as with a precomputed trajectory.
+	Block,
+
+	/// Fail with [`TargetSendError::Full`].
+	Error,
+}
+
+/// Error that may occur when sending a target.
+///
+/// The error holds the target that could not be sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TargetSendError<T> {
+	/// The channel is full and the overflow policy is [`OverflowPolicy::Error`].
+	///
+	/// Also returned by [`TargetSender::try_send`] for [`OverflowPolicy::Block`].
+	Full(T),
+
+	/// The receiver has been dropped.
+	Closed(T),
+}
+
+/// Sending half of a target channel, for the planner.
+///
+/// The sender can be cloned to send targets from multiple tasks.
+#[derive(Debug)]
+pub struct TargetSender<T> {
+	shared: Arc<Shared<T>>,
+}
+
+/// Receiving half of a target channel, for the control loop.
+#[derive(Debug)]
+pub struct TargetReceiver<T> {
+	shared: Arc<Shared<T>>,
+}
+
+#[derive(Debug)]
+struct Shared<T> {
+	state: Mutex<State<T>>,
+	capacity: usize,
+	policy: OverflowPolicy,
+	not_empty: Notify,
+	not_full: Notify,
+}
+
+#[derive(Debug)]
+struct State<T> {
+	queue: VecDeque<T>,
+	senders: usize,
+	receiver_alive: bool,
+	dropped: u64,
+}
+
+/// Create a bounded target channel with a capacity and an overflow policy.
+///
+/// The capacity is at least one.
+pub fn target_channel<T>(capacity: usize, policy: OverflowPolicy) -> (TargetSender<T>, TargetReceiver<T>) {
+	let capacity = capacity.max(1);
+	let shared = Arc::new(Shared {
+		state: Mutex::new(State {
+			queue: VecDeque::with_capacity(capacity),
+			senders: 1,
+			receiver_alive: true,
+			dropped: 0,
+		}),
+		capacity,
+		policy,
+		not_empty: Notify::new(),
+		not_full: Notify::new(),
+	});
+	(TargetSender { shared: shared.clone() }, TargetReceiver { shared })
+}
+
+impl<T> Shared<T> {
+	fn lock(&self) -> MutexGuard<'_, State<T>> {
+		self.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+impl<T> TargetSender<T> {
+	/// Send a target, applying the overflow policy if the channel is full.
+	///
+	/// With [`OverflowPolicy::Block`], this waits until there is room in the channel.
+	pub async fn send(&self, target: T) -> Result<(), TargetSendError<T>> {
+		let mut target = target;
+		loop {
+			let notified = self.shared.not_full.notified();
+			match self.try_send(target) {
+				Err(TargetSendError::Full(x)) if self.shared.policy == OverflowPolicy::Block => target = x,
+				result => return result,
+			}
+			notified.await;
+		}
+	}
+
+	/// Send a target without waiting.
+	///
+	/// With [`OverflowPolicy::Block`], this fails with [`TargetSendError::Full`] if the channel is full.
+	pub fn try_send(&self, target: T) -> Result<(), TargetSendError<T>> {
+		let mut state = self.shared.lock();
+		if !state.receiver_alive {
+			return Err(TargetSendError::Closed(target));
+		}
+		if state.queue.len() >= self.shared.capacity {
+			match self.shared.policy {
+				OverflowPolicy::DropOldest => {
+					state.queue.pop_front();
+					state.dropped += 1;
+				},
+				OverflowPolicy::Block | OverflowPolicy::Error => return Err(TargetSendError::Full(target)),
+			}
+		}
+		state.queue.push_back(target);
+		drop(state);
+		self.shared.not_empty.notify_one();
+		Ok(())
+	}
+
+	/// Get the overflow policy of the channel.
+	pub fn policy(&self) -> OverflowPolicy {
+		self.shared.policy
+	}
+
+	/// Check if the receiver has been dropped.
+	pub fn is_closed(&self) -> bool {
+		!self.shared.lock().receiver_alive
+	}
+}
+
+impl<T> TargetReceiver<T> {
+	/// Receive the next target, waiting until one is available.
+	///
+	/// Returns `None` if the channel is empty and all senders have been dropped.
+	pub async fn recv(&mut self) -> Option<T> {
+		loop {
+			let notified = self.shared.not_empty.notified();
+			{
+				let mut state = self.shared.lock();
+				if let Some(target) = state.queue.pop_front() {
+					drop(state);
+					self.shared.not_full.notify_one();
+					return Some(target);
+				}
+				if state.senders == 0 {
+					return None;
+				}
+			}
+			notified.await;
+		}
+	}
+
+	/// Receive the next target if one is available, without waiting.
+	pub fn try_recv(&mut self) -> Option<T> {
+		let target = self.shared.lock().queue.pop_front()?;
+		self.shared.not_full.notify_one();
+		Some(target)
+	}
+
+	/// Get the number of targets in the channel.
+	pub fn len(&self) -> usize {
+		self.shared.lock().queue.len()
+	}
+
+	/// Check if the channel is empty.
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Get the number of targets dropped by [`OverflowPolicy::DropOldest`].
+	pub fn dropped(&self) -> u64 {
+		self.shared.lock().dropped
+	}
+}
+
+impl<T> Clone for TargetSender<T> {
+	fn clone(&self) -> Self {
+		self.shared.lock().senders += 1;
+		Self { shared: self.shared.clone() }
+	}
+}
+
+impl<T> Drop for TargetSender<T> {
+	fn drop(&mut self) {
+		let mut state = self.shared.lock();
+		state.senders -= 1;
+		if state.senders == 0 {
+			drop(state);
+			self.shared.not_empty.notify_waiters();
+		}
+	}
+}
+
+impl<T> Drop for TargetReceiver<T> {
+	fn drop(&mut self) {
+		self.shared.lock().receiver_alive = false;
+		self.shared.not_full.notify_waiters();
+	}
+}
+
+impl<T> std::fmt::Display for TargetSendError<T> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Full(_) => write!(f, "target channel is full"),
+			Self::Closed(_) => write!(f, "target channel is closed"),
+		}
+	}
+}
+
+impl<T: std::fmt::Debug> std::error::Error for TargetSendError<T> {}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_target_channel() {
+	use assert2::assert;
+	use std::time::Duration;
+
+	let (sender, mut receiver) = target_channel(2, OverflowPolicy::DropOldest);
+	for i in 0..5 {
+		assert!(let Ok(()) = sender.send(i).await);
+	}
+	assert!(receiver.dropped() == 3);
+	assert!(receiver.try_recv() == Some(3));
+	assert!(receiver.recv().await == Some(4));
+	assert!(receiver.try_recv() == None);
+
+	let (sender, mut receiver) = target_channel(1, OverflowPolicy::Error);
+	assert!(let Ok(()) = sender.send(1).await);
+	assert!(sender.send(2).await == Err(TargetSendError::Full(2)));
+
+	let (sender, mut receiver_blocking) = target_channel(1, OverflowPolicy::Block);
+	assert!(let Ok(()) = sender.send(1).await);
+	assert!(sender.try_send(2) == Err(TargetSendError::Full(2)));
+	let planner = tokio::spawn(async move {
+		sender.send(2).await.unwrap();
+		sender.send(3).await.unwrap();
+	});
+	tokio::time::sleep(Duration::from_millis(10)).await;
+	assert!(receiver_blocking.len() == 1);
+	assert!(receiver_blocking.recv().await == Some(1));
+	assert!(receiver_blocking.recv().await == Some(2));
+	assert!(receiver_blocking.recv().await == Some(3));
+	planner.await.unwrap();
+	assert!(receiver_blocking.recv().await == None);
+
+	let sender = {
+		let (sender, _) = target_channel::<i32>(1, OverflowPolicy::Block);
+		sender
+	};
+	assert!(sender.is_closed());
+	assert!(sender.send(1).await == Err(TargetSendError::Closed(1)));
+	assert!(receiver.try_recv() == Some(1));
+}
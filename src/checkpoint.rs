@@ -0,0 +1,364 @@
+//! Periodic checkpoints of the last commanded target, for recovery after a crash.
+//!
+//! An application that crashes in the middle of a session loses track of where it left the robot.
+//! By saving a [`Checkpoint`] with the last commanded target while streaming,
+//! the application can load it on startup and re-home to the last commanded pose or joints
+//! before resuming, instead of jumping to the first target of a fresh run.
+//!
+//! Checkpoints are written atomically: the data is written to a temporary file which then replaces the checkpoint,
+//! so a crash while saving leaves the previous checkpoint intact.
+
+use std::convert::TryFrom;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use prost::Message;
+
+use crate::codec::DecodeError;
+use crate::msg::{EgmJoints, EgmPose, EgmSensor};
+
+/// The magic bytes at the start of a checkpoint.
+pub const MAGIC: [u8; 8] = *b"ABBEGMCP";
+
+/// The version of the checkpoint format written by [`Checkpoint::encode`].
+pub const VERSION: u16 = 1;
+
+/// The last commanded target and metadata of a session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+	/// The last target sent to the robot controller.
+	pub target: EgmSensor,
+
+	/// The time at which the checkpoint was taken.
+	pub saved_at: SystemTime,
+
+	/// The address of the robot controller, if known.
+	pub controller: Option<SocketAddr>,
+
+	/// Free-form application data, such as the name of the running program and the current step.
+	pub label: String,
+}
+
+/// Error that may occur when saving or loading a checkpoint.
+#[derive(Debug)]
+pub enum CheckpointError {
+	/// An I/O error occurred.
+	Io(std::io::Error),
+
+	/// The data does not start with the magic bytes of a checkpoint.
+	InvalidMagic,
+
+	/// The checkpoint was written with an unsupported version of the format.
+	UnsupportedVersion(u16),
+
+	/// The checkpoint is corrupt.
+	Corrupt(&'static str),
+
+	/// The target in the checkpoint could not be decoded.
+	Decode(DecodeError),
+}
+
+impl Checkpoint {
+	/// Create a checkpoint of a target, taken now.
+	pub fn new(target: EgmSensor) -> Self {
+		Self {
+			target,
+			saved_at: SystemTime::now(),
+			controller: None,
+			label: String::new(),
+		}
+	}
+
+	/// Set the address of the robot controller.
+	pub fn with_controller(mut self, controller: SocketAddr) -> Self {
+		self.controller = Some(controller);
+		self
+	}
+
+	/// Set the free-form application data.
+	pub fn with_label(mut self, label: impl Into<String>) -> Self {
+		self.label = label.into();
+		self
+	}
+
+	/// Get the sequence number of the target.
+	pub fn sequence_number(&self) -> Option<u32> {
+		self.target.header.as_ref()?.seqno
+	}
+
+	/// Get the commanded pose of the target, if it has one.
+	pub fn target_pose(&self) -> Option<&EgmPose> {
+		self.target.planned.as_ref()?.cartesian.as_ref()
+	}
+
+	/// Get the commanded joints of the target, if it has them.
+	pub fn target_joints(&self) -> Option<&EgmJoints> {
+		self.target.planned.as_ref()?.joints.as_ref()
+	}
+
+	/// Get the time elapsed since the checkpoint was taken.
+	///
+	/// Returns zero if the checkpoint was taken in the future according to the system clock.
+	pub fn age(&self) -> Duration {
+		SystemTime::now().duration_since(self.saved_at).unwrap_or_default()
+	}
+
+	/// Encode the checkpoint.
+	///
+	/// All integers are stored in little-endian byte order, so checkpoints can be read on any platform.
+	pub fn encode(&self) -> Vec<u8> {
+		let saved_at = self.saved_at.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+		let controller = self.controller.map(|x| x.to_string()).unwrap_or_default();
+		let target = self.target.encode_to_vec();
+
+		let mut data = Vec::new();
+		data.extend_from_slice(&MAGIC);
+		data.extend_from_slice(&VERSION.to_le_bytes());
+		data.extend_from_slice(&0u16.to_le_bytes());
+		data.extend_from_slice(&saved_at.as_secs().to_le_bytes());
+		data.extend_from_slice(&saved_at.subsec_nanos().to_le_bytes());
+		for field in [controller.as_bytes(), self.label.as_bytes(), &target[..]] {
+			data.extend_from_slice(&(field.len() as u32).to_le_bytes());
+			data.extend_from_slice(field);
+		}
+		data
+	}
+
+	/// Decode a checkpoint written by [`Checkpoint::encode`].
+	pub fn decode(data: &[u8]) -> Result<Self, CheckpointError> {
+		let mut reader = Reader { data };
+		if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+			return Err(CheckpointError::InvalidMagic);
+		}
+		let version = u16::from_le_bytes(reader.array()?);
+		if version != VERSION {
+			return Err(CheckpointError::UnsupportedVersion(version));
+		}
+		reader.take(2)?;
+		let secs = u64::from_le_bytes(reader.array()?);
+		let nanos = u32::from_le_bytes(reader.array()?);
+		if nanos >= 1_000_000_000 {
+			return Err(CheckpointError::Corrupt("invalid timestamp"));
+		}
+		let saved_at = SystemTime::UNIX_EPOCH + Duration::new(secs, nanos);
+
+		let controller = std::str::from_utf8(reader.field()?).map_err(|_| CheckpointError::Corrupt("invalid controller address"))?;
+		let controller = match controller {
+			"" => None,
+			x => Some(x.parse().map_err(|_| CheckpointError::Corrupt("invalid controller address"))?),
+		};
+		let label = std::str::from_utf8(reader.field()?).map_err(|_| CheckpointError::Corrupt("invalid label"))?.to_string();
+		let target = crate::codec::decode_sensor(reader.field()?).map_err(CheckpointError::Decode)?;
+		if !reader.data.is_empty() {
+			return Err(CheckpointError::Corrupt("trailing data"));
+		}
+
+		Ok(Self {
+			target,
+			saved_at,
+			controller,
+			label,
+		})
+	}
+
+	/// Atomically save the checkpoint to a file.
+	///
+	/// The checkpoint is written to a temporary file in the same directory, which then replaces the file at `path`.
+	pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CheckpointError> {
+		let path = path.as_ref();
+		let temporary = temporary_path(path);
+		let result = (|| {
+			let mut file = std::fs::File::create(&temporary)?;
+			file.write_all(&self.encode())?;
+			file.sync_all()?;
+			std::fs::rename(&temporary, path)
+		})();
+		if result.is_err() {
+			let _ = std::fs::remove_file(&temporary);
+		}
+		Ok(result?)
+	}
+
+	/// Load a checkpoint from a file.
+	///
+	/// Returns `None` if the file does not exist, which means there is nothing to recover.
+	pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>, CheckpointError> {
+		match std::fs::read(path) {
+			Ok(data) => Ok(Some(Self::decode(&data)?)),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	/// Remove a checkpoint file, for example after a session finished cleanly.
+	///
+	/// It is not an error if the file does not exist.
+	pub fn remove(path: impl AsRef<Path>) -> Result<(), CheckpointError> {
+		match std::fs::remove_file(path) {
+			Ok(()) => Ok(()),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+			Err(e) => Err(e.into()),
+		}
+	}
+}
+
+/// Saves checkpoints of the commanded targets to a file at a fixed interval.
+///
+/// Call [`Checkpointer::update`] with every target sent to the robot controller.
+/// Saving every target would put a file system write in the control loop at 250 Hz,
+/// so the checkpoint is only saved when the interval has elapsed since the last save.
+#[derive(Debug)]
+pub struct Checkpointer {
+	path: PathBuf,
+	interval: Duration,
+	controller: Option<SocketAddr>,
+	label: String,
+	last_save: Option<Instant>,
+}
+
+impl Checkpointer {
+	/// Create a checkpointer that saves to a file at most once per interval.
+	pub fn new(path: impl Into<PathBuf>, interval: Duration) -> Self {
+		Self {
+			path: path.into(),
+			interval,
+			controller: None,
+			label: String::new(),
+			last_save: None,
+		}
+	}
+
+	/// Set the address of the robot controller saved in the checkpoints.
+	pub fn with_controller(mut self, controller: SocketAddr) -> Self {
+		self.controller = Some(controller);
+		self
+	}
+
+	/// Get the path of the checkpoint file.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Set the free-form application data saved in the next checkpoints.
+	pub fn set_label(&mut self, label: impl Into<String>) {
+		self.label = label.into();
+	}
+
+	/// Save a checkpoint of a target if the interval has elapsed since the last save.
+	///
+	/// Returns true if the checkpoint was saved.
+	pub fn update(&mut self, target: &EgmSensor) -> Result<bool, CheckpointError> {
+		let now = Instant::now();
+		if self.last_save.map(|x| now.duration_since(x) < self.interval) == Some(true) {
+			return Ok(false);
+		}
+		self.save(target)?;
+		self.last_save = Some(now);
+		Ok(true)
+	}
+
+	/// Save a checkpoint of a target now, regardless of the interval.
+	pub fn save(&mut self, target: &EgmSensor) -> Result<(), CheckpointError> {
+		let mut checkpoint = Checkpoint::new(target.clone()).with_label(self.label.clone());
+		checkpoint.controller = self.controller;
+		checkpoint.save(&self.path)?;
+		self.last_save = Some(Instant::now());
+		Ok(())
+	}
+
+	/// Load the checkpoint from the file, if it exists.
+	pub fn load(&self) -> Result<Option<Checkpoint>, CheckpointError> {
+		Checkpoint::load(&self.path)
+	}
+
+	/// Remove the checkpoint file after a session finished cleanly.
+	pub fn clear(&mut self) -> Result<(), CheckpointError> {
+		self.last_save = None;
+		Checkpoint::remove(&self.path)
+	}
+}
+
+/// Get the path of the temporary file used to save a checkpoint atomically.
+fn temporary_path(path: &Path) -> PathBuf {
+	let mut name = path.file_name().map(|x| x.to_os_string()).unwrap_or_default();
+	name.push(".tmp");
+	path.with_file_name(name)
+}
+
+/// Reader for the fields of an encoded checkpoint.
+struct Reader<'a> {
+	data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Result<&'a [u8], CheckpointError> {
+		if self.data.len() < len {
+			return Err(CheckpointError::Corrupt("unexpected end of data"));
+		}
+		let (head, tail) = self.data.split_at(len);
+		self.data = tail;
+		Ok(head)
+	}
+
+	fn array<const N: usize>(&mut self) -> Result<[u8; N], CheckpointError> {
+		Ok(<[u8; N]>::try_from(self.take(N)?).unwrap())
+	}
+
+	fn field(&mut self) -> Result<&'a [u8], CheckpointError> {
+		let len = u32::from_le_bytes(self.array()?);
+		self.take(len as usize)
+	}
+}
+
+impl From<std::io::Error> for CheckpointError {
+	fn from(other: std::io::Error) -> Self {
+		Self::Io(other)
+	}
+}
+
+impl std::fmt::Display for CheckpointError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Io(e) => e.fmt(f),
+			Self::InvalidMagic => write!(f, "data is not an EGM checkpoint"),
+			Self::UnsupportedVersion(version) => write!(f, "unsupported checkpoint format version: {}", version),
+			Self::Corrupt(reason) => write!(f, "corrupt checkpoint: {}", reason),
+			Self::Decode(e) => write!(f, "failed to decode target of checkpoint: {}", e),
+		}
+	}
+}
+
+impl std::error::Error for CheckpointError {}
+
+#[cfg(test)]
+#[test]
+fn test_checkpoint() {
+	use crate::msg::{EgmClock, EgmQuaternion};
+	use assert2::{assert, let_assert};
+
+	let target = EgmSensor::pose_target(42, EgmPose::new([1.0, 2.0, 3.0], EgmQuaternion::IDENTITY), EgmClock::new(1, 0));
+	let checkpoint = Checkpoint::new(target.clone())
+		.with_controller("192.168.125.1:6510".parse().unwrap())
+		.with_label("program.toml step 3");
+	let data = checkpoint.encode();
+	let_assert!(Ok(decoded) = Checkpoint::decode(&data));
+	assert!(decoded == checkpoint);
+	assert!(decoded.sequence_number() == Some(42));
+	assert!(decoded.target_pose().is_some());
+	assert!(decoded.target_joints().is_none());
+	assert!(let Err(CheckpointError::Corrupt(_)) = Checkpoint::decode(&data[..data.len() - 1]));
+	assert!(let Err(CheckpointError::InvalidMagic) = Checkpoint::decode(b"ABBEGMRC"));
+
+	let path = std::env::temp_dir().join(format!("abbegm-checkpoint-{}", std::process::id()));
+	let mut checkpointer = Checkpointer::new(&path, Duration::from_secs(60));
+	assert!(let Ok(None) = checkpointer.load());
+	assert!(let Ok(true) = checkpointer.update(&target));
+	assert!(let Ok(false) = checkpointer.update(&EgmSensor::default()));
+	let_assert!(Ok(Some(loaded)) = checkpointer.load());
+	assert!(loaded.target == target);
+	assert!(!temporary_path(&path).exists());
+	assert!(let Ok(()) = checkpointer.clear());
+	assert!(!path.exists());
+}
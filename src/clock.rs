@@ -0,0 +1,109 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Source of the current time for timing logic, such as [`RateTimer`](crate::rate::RateTimer), [`LoopTimer`](crate::rate::LoopTimer)
+/// and [`PathCorrectionSession`](crate::path_correction::PathCorrectionSession).
+///
+/// Use [`SystemClock`] in applications, and [`MockClock`] in tests to advance time deterministically without sleeping.
+pub trait Clock {
+	/// Get the current time.
+	fn now(&self) -> Instant;
+
+	/// Block the current thread for a duration.
+	fn sleep(&self, duration: Duration);
+}
+
+/// The real monotonic clock of the system.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	fn now(&self) -> Instant {
+		Instant::now()
+	}
+
+	fn sleep(&self, duration: Duration) {
+		std::thread::sleep(duration)
+	}
+}
+
+/// A manually advanced clock for tests.
+///
+/// Time only moves when [`MockClock::advance`] or [`Clock::sleep`] is called, and sleeping returns immediately.
+/// Clones share the same time, so a test can keep a clone to advance the time seen by the code under test.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+	now: Arc<Mutex<Instant>>,
+}
+
+impl MockClock {
+	/// Create a mock clock starting at the current time of the system.
+	pub fn new() -> Self {
+		Self::starting_at(Instant::now())
+	}
+
+	/// Create a mock clock starting at a specific time.
+	pub fn starting_at(start: Instant) -> Self {
+		Self {
+			now: Arc::new(Mutex::new(start)),
+		}
+	}
+
+	/// Advance the time of the clock and all its clones.
+	pub fn advance(&self, duration: Duration) {
+		*self.now.lock().unwrap() += duration;
+	}
+}
+
+impl Default for MockClock {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Clock for MockClock {
+	fn now(&self) -> Instant {
+		*self.now.lock().unwrap()
+	}
+
+	fn sleep(&self, duration: Duration) {
+		self.advance(duration)
+	}
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+	fn now(&self) -> Instant {
+		(**self).now()
+	}
+
+	fn sleep(&self, duration: Duration) {
+		(**self).sleep(duration)
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_mock_clock() {
+	use crate::rate::{LoopTimer, RateTimer};
+	use assert2::assert;
+
+	let ms = Duration::from_millis;
+	let clock = MockClock::new();
+	let start = clock.now();
+
+	// Waiting sleeps until the next tick, and missed ticks are counted.
+	let mut timer = RateTimer::with_clock(ms(4), clock.clone());
+	assert!(timer.wait() == 0);
+	assert!(clock.now() == start + ms(4));
+	clock.advance(ms(9));
+	assert!(timer.wait() == 1);
+	assert!(clock.now() == start + ms(13));
+	assert!(timer.wait() == 0);
+	assert!(clock.now() == start + ms(16));
+
+	let mut timer = LoopTimer::new(ms(4)).with_warnings(false).with_clock(clock.clone());
+	timer.received();
+	clock.advance(ms(5));
+	assert!(timer.sent() == Some(ms(5)));
+	assert!(timer.stats().overruns == 1);
+}
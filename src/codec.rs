@@ -0,0 +1,298 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use prost::Message;
+
+use crate::msg::{EgmRobot, EgmSensor};
+use crate::InvalidMessageError;
+
+/// Error that may occur when encoding a message.
+#[derive(Debug)]
+pub enum EncodeError {
+	InvalidMessage(InvalidMessageError),
+	Encode(prost::EncodeError),
+}
+
+/// Limits applied when decoding untrusted datagrams.
+///
+/// The limits protect the receive path against malformed or malicious datagrams.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodeLimits {
+	/// The maximum length of a datagram in bytes.
+	pub max_len: usize,
+
+	/// The maximum number of values in a repeated field, such as the joints of a robot.
+	pub max_values: usize,
+
+	/// Reject messages that contain NaN values.
+	pub reject_nan: bool,
+}
+
+impl DecodeLimits {
+	/// The default limits, which accept all messages sent by a robot controller.
+	pub const DEFAULT: Self = Self {
+		max_len: 1024,
+		max_values: 32,
+		reject_nan: true,
+	};
+}
+
+impl Default for DecodeLimits {
+	fn default() -> Self {
+		Self::DEFAULT
+	}
+}
+
+/// Error that may occur when decoding a message.
+#[derive(Debug)]
+pub enum DecodeError {
+	/// The datagram is longer than allowed.
+	TooLong { len: usize, max: usize },
+
+	/// The datagram is not a valid protobuf message.
+	Protobuf(prost::DecodeError),
+
+	/// A repeated field holds more values than allowed.
+	TooManyValues { field: &'static str, count: usize, max: usize },
+
+	/// The message contains invalid values.
+	InvalidMessage(InvalidMessageError),
+}
+
+/// Decode a message from the robot controller with the default [`DecodeLimits`].
+///
+/// This function never panics, whatever the input, so it can be used directly as fuzz target.
+pub fn decode_robot(data: &[u8]) -> Result<EgmRobot, DecodeError> {
+	decode_robot_with(data, &DecodeLimits::DEFAULT)
+}
+
+/// Decode a message from the robot controller with custom [`DecodeLimits`].
+pub fn decode_robot_with(data: &[u8], limits: &DecodeLimits) -> Result<EgmRobot, DecodeError> {
+	check_len(data, limits)?;
+	let msg = EgmRobot::decode(data)?;
+	check_values(&msg.repeated_fields(), limits)?;
+	if limits.reject_nan {
+		msg.validate()?;
+	}
+	Ok(msg)
+}
+
+/// Decode a message for the robot controller with the default [`DecodeLimits`].
+///
+/// This function never panics, whatever the input, so it can be used directly as fuzz target.
+pub fn decode_sensor(data: &[u8]) -> Result<EgmSensor, DecodeError> {
+	decode_sensor_with(data, &DecodeLimits::DEFAULT)
+}
+
+/// Decode a message for the robot controller with custom [`DecodeLimits`].
+///
+/// If [`DecodeLimits::reject_nan`] is set, the message is validated the same way as messages sent by the peers.
+pub fn decode_sensor_with(data: &[u8], limits: &DecodeLimits) -> Result<EgmSensor, DecodeError> {
+	check_len(data, limits)?;
+	let msg = EgmSensor::decode(data)?;
+	check_values(&msg.repeated_fields(), limits)?;
+	if limits.reject_nan {
+		msg.validate()?;
+	}
+	Ok(msg)
+}
+
+/// Validate and encode a message for the robot controller.
+///
+/// The buffer is cleared before the message is encoded into it.
+/// Messages are validated the same way as by the peers, so invalid messages are never encoded.
+pub fn encode_sensor(msg: &EgmSensor, buffer: &mut Vec<u8>) -> Result<(), EncodeError> {
+	InvalidMessageError::check_sensor_msg(msg)?;
+	buffer.clear();
+	msg.encode(buffer)?;
+	Ok(())
+}
+
+fn check_len(data: &[u8], limits: &DecodeLimits) -> Result<(), DecodeError> {
+	if data.len() > limits.max_len {
+		return Err(DecodeError::TooLong {
+			len: data.len(),
+			max: limits.max_len,
+		});
+	}
+	Ok(())
+}
+
+fn check_values(fields: &[(&'static str, usize)], limits: &DecodeLimits) -> Result<(), DecodeError> {
+	match fields.iter().find(|(_, count)| *count > limits.max_values) {
+		Some(&(field, count)) => Err(DecodeError::TooManyValues {
+			field,
+			count,
+			max: limits.max_values,
+		}),
+		None => Ok(()),
+	}
+}
+
+fn len<T>(values: Option<&[T]>) -> usize {
+	values.map_or(0, |x| x.len())
+}
+
+impl EgmRobot {
+	/// Get the names and lengths of all repeated fields.
+	fn repeated_fields(&self) -> Vec<(&'static str, usize)> {
+		let feedback = self.feed_back.as_ref();
+		let planned = self.planned.as_ref();
+		vec![
+			("feed_back.joints", len(feedback.and_then(|x| x.joints.as_ref()).map(|x| x.joints.as_slice()))),
+			("feed_back.external_joints", len(feedback.and_then(|x| x.external_joints.as_ref()).map(|x| x.joints.as_slice()))),
+			("planned.joints", len(planned.and_then(|x| x.joints.as_ref()).map(|x| x.joints.as_slice()))),
+			("planned.external_joints", len(planned.and_then(|x| x.external_joints.as_ref()).map(|x| x.joints.as_slice()))),
+			("test_signals.signals", len(self.test_signals.as_ref().map(|x| x.signals.as_slice()))),
+			("measured_force.force", len(self.measured_force.as_ref().map(|x| x.force.as_slice()))),
+			#[cfg(feature = "robotware-6-10")]
+			("collision_info.coll_det_quote", len(self.collision_info.as_ref().map(|x| x.coll_det_quote.as_slice()))),
+			#[cfg(feature = "robotware-6-10")]
+			("rapi_dfrom_robot.dnum", len(self.rapi_dfrom_robot.as_ref().map(|x| x.dnum.as_slice()))),
+		]
+	}
+}
+
+impl EgmSensor {
+	/// Get the names and lengths of all repeated fields.
+	fn repeated_fields(&self) -> Vec<(&'static str, usize)> {
+		let planned = self.planned.as_ref();
+		let speed_ref = self.speed_ref.as_ref();
+		vec![
+			("planned.joints", len(planned.and_then(|x| x.joints.as_ref()).map(|x| x.joints.as_slice()))),
+			("planned.external_joints", len(planned.and_then(|x| x.external_joints.as_ref()).map(|x| x.joints.as_slice()))),
+			("speed_ref.joints", len(speed_ref.and_then(|x| x.joints.as_ref()).map(|x| x.joints.as_slice()))),
+			("speed_ref.cartesians", len(speed_ref.and_then(|x| x.cartesians.as_ref()).map(|x| x.value.as_slice()))),
+			("speed_ref.external_joints", len(speed_ref.and_then(|x| x.external_joints.as_ref()).map(|x| x.joints.as_slice()))),
+			#[cfg(feature = "robotware-6-10")]
+			("rapi_dto_robot.dnum", len(self.rapi_dto_robot.as_ref().map(|x| x.dnum.as_slice()))),
+		]
+	}
+}
+
+impl TryFrom<&[u8]> for EgmRobot {
+	type Error = DecodeError;
+
+	/// Decode a message with the default [`DecodeLimits`], see [`decode_robot`].
+	fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+		decode_robot(data)
+	}
+}
+
+impl TryFrom<&[u8]> for EgmSensor {
+	type Error = DecodeError;
+
+	/// Decode a message with the default [`DecodeLimits`], see [`decode_sensor`].
+	fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+		decode_sensor(data)
+	}
+}
+
+impl From<prost::DecodeError> for DecodeError {
+	fn from(other: prost::DecodeError) -> Self {
+		Self::Protobuf(other)
+	}
+}
+
+impl From<InvalidMessageError> for DecodeError {
+	fn from(other: InvalidMessageError) -> Self {
+		Self::InvalidMessage(other)
+	}
+}
+
+impl core::fmt::Display for DecodeError {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		match self {
+			Self::TooLong { len, max } => write!(f, "datagram too long: {} bytes, maximum is {} bytes", len, max),
+			Self::Protobuf(e) => e.fmt(f),
+			Self::TooManyValues { field, count, max } => write!(f, "too many values in {}: {}, maximum is {}", field, count, max),
+			Self::InvalidMessage(e) => e.fmt(f),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+impl From<InvalidMessageError> for EncodeError {
+	fn from(other: InvalidMessageError) -> Self {
+		Self::InvalidMessage(other)
+	}
+}
+
+impl From<prost::EncodeError> for EncodeError {
+	fn from(other: prost::EncodeError) -> Self {
+		Self::Encode(other)
+	}
+}
+
+impl core::fmt::Display for EncodeError {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		match self {
+			Self::InvalidMessage(e) => e.fmt(f),
+			Self::Encode(e) => e.fmt(f),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EncodeError {}
+
+#[cfg(test)]
+#[test]
+fn test_codec() {
+	use crate::msg::EgmClock;
+	use assert2::assert;
+
+	let mut buffer = Vec::new();
+	let msg = EgmSensor::joint_target(1, [1.0; 6], EgmClock::new(0, 0));
+	encode_sensor(&msg, &mut buffer).unwrap();
+	assert!(EgmSensor::decode(buffer.as_slice()).unwrap() == msg);
+
+	let msg = EgmSensor::joint_target(1, [f64::NAN; 6], EgmClock::new(0, 0));
+	assert!(let Err(EncodeError::InvalidMessage(_)) = encode_sensor(&msg, &mut buffer));
+
+	let robot = EgmRobot::default();
+	assert!(decode_robot(&robot.encode_to_vec()).unwrap() == robot);
+	assert!(let Err(DecodeError::Protobuf(_)) = decode_robot(&[0xFF]));
+	assert!(let Err(DecodeError::TooLong { len: 2000, max: 1024 }) = decode_robot(&[0; 2000]));
+}
+
+#[cfg(test)]
+#[test]
+fn test_decode_limits() {
+	use crate::msg::{EgmClock, EgmFeedBack, EgmJoints};
+	use assert2::assert;
+
+	let robot = |joints: Vec<f64>| EgmRobot {
+		feed_back: Some(EgmFeedBack {
+			joints: Some(EgmJoints::from_degrees(joints)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+
+	let msg = robot(vec![1.0; 6]);
+	assert!(EgmRobot::try_from(msg.encode_to_vec().as_slice()).unwrap() == msg);
+	assert!(let Err(DecodeError::TooManyValues { field: "feed_back.joints", count: 40, max: 32 }) = decode_robot(&robot(vec![1.0; 40]).encode_to_vec()));
+	assert!(let Err(DecodeError::InvalidMessage(_)) = decode_robot(&robot(vec![f64::NAN; 6]).encode_to_vec()));
+
+	let limits = DecodeLimits {
+		max_values: 64,
+		reject_nan: false,
+		..DecodeLimits::DEFAULT
+	};
+	assert!(let Ok(_) = decode_robot_with(&robot(vec![1.0; 40]).encode_to_vec(), &limits));
+	assert!(let Ok(_) = decode_robot_with(&robot(vec![f64::NAN; 6]).encode_to_vec(), &limits));
+
+	let sensor = EgmSensor::joint_target(1, [0.0; 6], EgmClock::new(0, 0));
+	assert!(EgmSensor::try_from(sensor.encode_to_vec().as_slice()).unwrap() == sensor);
+	let sensor = EgmSensor::joint_target(1, vec![0.0; 33], EgmClock::new(0, 0));
+	assert!(let Err(DecodeError::TooManyValues { field: "planned.joints", .. }) = decode_sensor(&sensor.encode_to_vec()));
+
+	// Truncated datagrams must produce errors, not panics.
+	let data = msg.encode_to_vec();
+	for end in 0..data.len() {
+		let _ = decode_robot(&data[..end]);
+	}
+}
@@ -0,0 +1,557 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::middleware::{Middleware, MiddlewareStack};
+use crate::msg::{EgmRobot, EgmSensor};
+use crate::rate::SampleTimeEstimator;
+use crate::streamer::StreamerConfig;
+use crate::{MessageRejectedError, ValidationOptions};
+
+/// Configuration of a complete EGM setup, loaded from a file.
+///
+/// The configuration describes the network setup, the limits of the motions and the targets,
+/// and what to do with targets that violate the limits.
+/// It can construct a streamer with everything wired up, so deployments can be configured by file rather than code.
+/// All fields have defaults, so a configuration file only needs to list the values that differ.
+/// In TOML, a configuration looks like this:
+///
+/// ```toml
+/// bind_address = "0.0.0.0:6510"
+/// robot_address = "192.168.125.1:6510"
+/// sample_time = 4
+///
+/// [limits]
+/// joint_acceleration = 45.0
+/// joint_limits = [[-170.0, 170.0], [-65.0, 85.0], [-180.0, 70.0]]
+/// workspace = [[200.0, -500.0, 100.0], [900.0, 500.0, 1200.0]]
+///
+/// [filters]
+/// max_joint_step = 0.5
+///
+/// [validation]
+/// policy = "clamp"
+/// ```
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EgmConfig {
+	/// The local address to bind the UDP socket to.
+	pub bind_address: SocketAddr,
+
+	/// The address of the robot controller.
+	///
+	/// If set, the socket is connected to this address, so messages from other addresses are ignored.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub robot_address: Option<SocketAddr>,
+
+	/// The sample time of the EGM session on the robot controller in milliseconds.
+	///
+	/// It is used for the receive timeout, and for the speed filters until the sample time has been measured.
+	pub sample_time: u32,
+
+	/// The number of consecutive messages that may be missed before receiving fails with a timeout.
+	///
+	/// Set to `None` to wait forever.
+	/// The timeout is only applied to blocking peers.
+	pub max_missed_messages: Option<u32>,
+
+	/// The limits of the motions and targets.
+	pub limits: LimitsConfig,
+
+	/// The filters applied to outgoing targets.
+	pub filters: FilterConfig,
+
+	/// The validation of outgoing targets.
+	pub validation: ValidationConfig,
+}
+
+/// Limits of the motions of a streamer and of the targets sent to the robot.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+	/// The linear acceleration in mm/s² used for linear motions.
+	pub linear_acceleration: f64,
+
+	/// The maximum angular velocity of the tool in degrees/s used for linear motions.
+	pub angular_velocity: f64,
+
+	/// The angular acceleration of the tool in degrees/s² used for linear motions.
+	pub angular_acceleration: f64,
+
+	/// The joint acceleration in degrees/s² used for joint motions.
+	pub joint_acceleration: f64,
+
+	/// The minimum and maximum position in degrees of each joint.
+	///
+	/// Joints without limits are not checked.
+	pub joint_limits: Vec<[f64; 2]>,
+
+	/// The minimum and maximum corner in millimeters of the box that target positions must stay inside.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub workspace: Option<[[f64; 3]; 2]>,
+}
+
+/// Filters that guard against jumps between consecutive targets.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+	/// The maximum change of a joint in degrees between consecutive targets.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub max_joint_step: Option<f64>,
+
+	/// The maximum distance in millimeters between the positions of consecutive targets.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub max_position_step: Option<f64>,
+
+	/// The maximum speed of a joint in degrees/s between consecutive targets.
+	///
+	/// The speed is converted to a maximum step with the sample time measured from the messages of the robot controller.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub max_joint_speed: Option<f64>,
+
+	/// The maximum linear speed in mm/s between the positions of consecutive targets.
+	///
+	/// The speed is converted to a maximum step with the sample time measured from the messages of the robot controller.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub max_linear_speed: Option<f64>,
+}
+
+/// Validation of outgoing targets.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ValidationConfig {
+	/// What to do with targets that violate the limits or filters.
+	pub policy: ValidationPolicy,
+
+	/// The maximum angle in degrees between the quaternion and the Euler angles of a pose.
+	///
+	/// This is checked in addition to the default [`ValidationOptions`] of the peer,
+	/// so it can only make the check stricter.
+	pub orientation_tolerance: f64,
+}
+
+/// What to do with targets that violate the limits or filters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationPolicy {
+	/// Reject the target, so sending it fails with a `Rejected` error.
+	#[default]
+	Reject,
+
+	/// Clamp the target to the limits and send it anyway.
+	Clamp,
+}
+
+/// Error that may occur while loading a configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+	/// Reading the configuration file failed.
+	Io(std::io::Error),
+
+	/// The configuration is not valid TOML for an EGM configuration.
+	Toml(toml::de::Error),
+
+	/// The configuration is not valid JSON for an EGM configuration.
+	Json(serde_json::Error),
+
+	/// The file extension does not indicate a supported format.
+	UnknownFormat(PathBuf),
+
+	/// A value of the configuration is invalid.
+	Invalid(&'static str),
+}
+
+impl Default for EgmConfig {
+	fn default() -> Self {
+		Self {
+			bind_address: SocketAddr::from(([0, 0, 0, 0], 6510)),
+			robot_address: None,
+			sample_time: 4,
+			max_missed_messages: Some(25),
+			limits: LimitsConfig::default(),
+			filters: FilterConfig::default(),
+			validation: ValidationConfig::default(),
+		}
+	}
+}
+
+impl Default for LimitsConfig {
+	fn default() -> Self {
+		let streamer = StreamerConfig::default();
+		Self {
+			linear_acceleration: streamer.linear_acceleration,
+			angular_velocity: streamer.angular_velocity,
+			angular_acceleration: streamer.angular_acceleration,
+			joint_acceleration: streamer.joint_acceleration,
+			joint_limits: Vec::new(),
+			workspace: None,
+		}
+	}
+}
+
+impl Default for ValidationConfig {
+	fn default() -> Self {
+		Self {
+			policy: ValidationPolicy::Reject,
+			orientation_tolerance: ValidationOptions::default().orientation_tolerance,
+		}
+	}
+}
+
+impl EgmConfig {
+	/// Parse a configuration from TOML.
+	pub fn from_toml_str(data: &str) -> Result<Self, ConfigError> {
+		let config: Self = toml::from_str(data).map_err(ConfigError::Toml)?;
+		config.validate()?;
+		Ok(config)
+	}
+
+	/// Parse a configuration from JSON.
+	pub fn from_json_str(data: &str) -> Result<Self, ConfigError> {
+		let config: Self = serde_json::from_str(data).map_err(ConfigError::Json)?;
+		config.validate()?;
+		Ok(config)
+	}
+
+	/// Load a configuration from a file.
+	///
+	/// The format is determined by the file extension: `.toml` or `.json`.
+	pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+		let path = path.as_ref();
+		let parse = match path.extension().and_then(|x| x.to_str()) {
+			Some("toml") => Self::from_toml_str,
+			Some("json") => Self::from_json_str,
+			_ => return Err(ConfigError::UnknownFormat(path.to_owned())),
+		};
+		parse(&std::fs::read_to_string(path).map_err(ConfigError::Io)?)
+	}
+
+	/// Check that all values of the configuration are usable.
+	pub fn validate(&self) -> Result<(), ConfigError> {
+		let positive = |x: f64| x.is_finite() && x > 0.0;
+		let limits = &self.limits;
+		if self.sample_time == 0 {
+			return Err(ConfigError::Invalid("sample time must be positive"));
+		}
+		if ![limits.linear_acceleration, limits.angular_velocity, limits.angular_acceleration, limits.joint_acceleration]
+			.iter()
+			.all(|x| positive(*x))
+		{
+			return Err(ConfigError::Invalid("accelerations and velocities must be positive numbers"));
+		}
+		if !limits.joint_limits.iter().all(|[min, max]| min <= max) {
+			return Err(ConfigError::Invalid("joint limits must be ordered as [min, max]"));
+		}
+		if let Some([min, max]) = &limits.workspace {
+			if !min.iter().zip(max).all(|(min, max)| min <= max) {
+				return Err(ConfigError::Invalid("workspace must be ordered as [min, max]"));
+			}
+		}
+		let filters = &self.filters;
+		if ![filters.max_joint_step, filters.max_position_step, filters.max_joint_speed, filters.max_linear_speed]
+			.iter()
+			.flatten()
+			.all(|x| positive(*x))
+		{
+			return Err(ConfigError::Invalid("maximum steps and speeds must be positive numbers"));
+		}
+		if self.validation.orientation_tolerance.is_nan() || self.validation.orientation_tolerance < 0.0 {
+			return Err(ConfigError::Invalid("orientation tolerance must be a non-negative number"));
+		}
+		Ok(())
+	}
+
+	/// Get the streamer configuration with the limits of this configuration.
+	pub fn streamer_config(&self) -> StreamerConfig {
+		StreamerConfig {
+			linear_acceleration: self.limits.linear_acceleration,
+			angular_velocity: self.limits.angular_velocity,
+			angular_acceleration: self.limits.angular_acceleration,
+			joint_acceleration: self.limits.joint_acceleration,
+			..StreamerConfig::default()
+		}
+	}
+
+	/// Get the time after which receiving a message fails, if any.
+	pub fn receive_timeout(&self) -> Option<Duration> {
+		let missed = self.max_missed_messages?;
+		Some(Duration::from_millis(u64::from(self.sample_time) * u64::from(missed.max(1))))
+	}
+
+	/// Add a middleware to a stack that enforces the limits, filters and validation of this configuration.
+	pub fn install_middleware(&self, stack: &mut MiddlewareStack) {
+		stack.push(TargetGuard::new(self.clone()));
+	}
+
+	/// Create a blocking peer with the network settings and middleware of this configuration.
+	pub fn bind(&self) -> std::io::Result<crate::sync_peer::EgmPeer> {
+		let socket = std::net::UdpSocket::bind(self.bind_address)?;
+		if let Some(address) = self.robot_address {
+			socket.connect(address)?;
+		}
+		socket.set_read_timeout(self.receive_timeout())?;
+		let mut peer = crate::sync_peer::EgmPeer::new(socket);
+		self.install_middleware(peer.middleware_mut());
+		Ok(peer)
+	}
+
+	/// Create an asynchronous peer with the network settings and middleware of this configuration.
+	///
+	/// The receive timeout is not applied: use [`tokio::time::timeout`] instead.
+	#[cfg(feature = "tokio")]
+	pub async fn bind_async(&self) -> std::io::Result<crate::tokio_peer::EgmPeer> {
+		let socket = tokio::net::UdpSocket::bind(self.bind_address).await?;
+		if let Some(address) = self.robot_address {
+			socket.connect(address).await?;
+		}
+		let mut peer = crate::tokio_peer::EgmPeer::new(socket);
+		self.install_middleware(peer.middleware_mut());
+		Ok(peer)
+	}
+
+	/// Create a blocking streamer with all settings of this configuration.
+	pub fn bind_streamer(&self) -> std::io::Result<crate::streamer::Streamer> {
+		Ok(crate::streamer::Streamer::new(self.bind()?, self.streamer_config()))
+	}
+
+	/// Create an asynchronous streamer with all settings of this configuration.
+	///
+	/// The receive timeout is not applied: use [`tokio::time::timeout`] instead.
+	#[cfg(feature = "tokio")]
+	pub async fn bind_async_streamer(&self) -> std::io::Result<crate::streamer::AsyncStreamer> {
+		Ok(crate::streamer::AsyncStreamer::new(self.bind_async().await?, self.streamer_config()))
+	}
+}
+
+/// Middleware enforcing the limits, filters and validation of an [`EgmConfig`].
+struct TargetGuard {
+	config: EgmConfig,
+	last: Mutex<LastTarget>,
+	sample_time: Mutex<SampleTimeEstimator>,
+}
+
+/// The last target that passed a [`TargetGuard`].
+#[derive(Default)]
+struct LastTarget {
+	joints: Option<Vec<f64>>,
+	position: Option<[f64; 3]>,
+}
+
+impl TargetGuard {
+	fn new(config: EgmConfig) -> Self {
+		Self {
+			config,
+			last: Mutex::new(LastTarget::default()),
+			sample_time: Mutex::new(SampleTimeEstimator::default()),
+		}
+	}
+
+	/// Get the measured sample time, or the configured sample time if it has not been measured yet.
+	fn sample_time(&self) -> Duration {
+		let measured = self.sample_time.lock().unwrap_or_else(|e| e.into_inner()).sample_time();
+		measured.unwrap_or_else(|| Duration::from_millis(self.config.sample_time.into()))
+	}
+
+	/// Get the maximum step between consecutive targets from a step and a speed filter, if either is set.
+	fn max_step(&self, step: Option<f64>, speed: Option<f64>) -> Option<f64> {
+		let speed_step = speed.map(|speed| speed * self.sample_time().as_secs_f64());
+		match (step, speed_step) {
+			(Some(a), Some(b)) => Some(a.min(b)),
+			(a, b) => a.or(b),
+		}
+	}
+
+	/// Apply the validation policy to a value that should be limited.
+	fn limit(&self, value: &mut f64, limited: f64, reason: impl FnOnce() -> String) -> Result<(), MessageRejectedError> {
+		if limited == *value {
+			return Ok(());
+		}
+		match self.config.validation.policy {
+			ValidationPolicy::Reject => Err(MessageRejectedError::new(reason())),
+			ValidationPolicy::Clamp => {
+				log::debug!("clamping target: {}", reason());
+				*value = limited;
+				Ok(())
+			},
+		}
+	}
+}
+
+impl Middleware for TargetGuard {
+	fn on_recv(&self, _msg: &mut EgmRobot) -> Result<(), MessageRejectedError> {
+		self.sample_time.lock().unwrap_or_else(|e| e.into_inner()).record(Instant::now());
+		Ok(())
+	}
+
+	fn on_send(&self, msg: &mut EgmSensor) -> Result<(), MessageRejectedError> {
+		let options = ValidationOptions {
+			orientation_tolerance: self.config.validation.orientation_tolerance,
+			..ValidationOptions::default()
+		};
+		msg.validate_with(&options).map_err(|e| MessageRejectedError::new(e.to_string()))?;
+
+		let planned = match &mut msg.planned {
+			Some(planned) => planned,
+			None => return Ok(()),
+		};
+		let max_joint_step = self.max_step(self.config.filters.max_joint_step, self.config.filters.max_joint_speed);
+		let max_position_step = self.max_step(self.config.filters.max_position_step, self.config.filters.max_linear_speed);
+		let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+		let (last_joints, last_position) = (last.joints.clone(), last.position);
+
+		if let Some(joints) = &mut planned.joints {
+			for (i, value) in joints.joints.iter_mut().enumerate() {
+				if let Some([min, max]) = self.config.limits.joint_limits.get(i) {
+					let target = *value;
+					self.limit(value, target.clamp(*min, *max), || format!("joint {} target {} is outside of the limits [{}, {}]", i + 1, target, min, max))?;
+				}
+				if let (Some(step), Some(previous)) = (max_joint_step, last_joints.as_ref().and_then(|x| x.get(i))) {
+					let target = *value;
+					let limited = target.clamp(previous - step, previous + step);
+					self.limit(value, limited, || format!("joint {} target {} is more than {} degrees from the previous target", i + 1, target, step))?;
+				}
+			}
+		}
+
+		if let Some(pos) = planned.cartesian.as_mut().and_then(|x| x.pos.as_mut()) {
+			let mut position = pos.as_mm();
+			if let Some([min, max]) = &self.config.limits.workspace {
+				let target = position;
+				for ((value, min), max) in position.iter_mut().zip(min).zip(max) {
+					self.limit(value, value.clamp(*min, *max), || format!("target position {:?} is outside of the workspace", target))?;
+				}
+			}
+			if let (Some(step), Some(previous)) = (max_position_step, last_position) {
+				let delta = [position[0] - previous[0], position[1] - previous[1], position[2] - previous[2]];
+				let distance = (delta[0] * delta[0] + delta[1] * delta[1] + delta[2] * delta[2]).sqrt();
+				let mut scaled = distance;
+				self.limit(&mut scaled, distance.min(step), || format!("target position is {} mm from the previous target", distance))?;
+				if scaled < distance {
+					let scale = scaled / distance;
+					position = [previous[0] + delta[0] * scale, previous[1] + delta[1] * scale, previous[2] + delta[2] * scale];
+				}
+			}
+			*pos = position.into();
+		}
+
+		*last = LastTarget {
+			joints: planned.joints.as_ref().map(|x| x.joints.clone()),
+			position: planned.cartesian.as_ref().and_then(|x| x.pos).map(|x| x.as_mm()),
+		};
+		Ok(())
+	}
+}
+
+impl std::fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "failed to read EGM configuration: {}", e),
+			Self::Toml(e) => write!(f, "failed to parse EGM configuration: {}", e),
+			Self::Json(e) => write!(f, "failed to parse EGM configuration: {}", e),
+			Self::UnknownFormat(path) => write!(f, "unknown EGM configuration format, expected .toml or .json: {}", path.display()),
+			Self::Invalid(reason) => write!(f, "invalid EGM configuration: {}", reason),
+		}
+	}
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+#[test]
+fn test_egm_config() {
+	use crate::msg::{EgmClock, EgmPose, EgmQuaternion};
+	use assert2::{assert, let_assert};
+
+	let_assert!(
+		Ok(config) = EgmConfig::from_toml_str(
+			r#"
+			bind_address = "127.0.0.1:0"
+			sample_time = 4
+			max_missed_messages = 10
+
+			[limits]
+			joint_acceleration = 45.0
+			joint_limits = [[-170.0, 170.0], [-65.0, 85.0]]
+			workspace = [[200.0, -500.0, 100.0], [900.0, 500.0, 1200.0]]
+
+			[filters]
+			max_position_step = 2.0
+			"#
+		)
+	);
+	assert!(config.receive_timeout() == Some(Duration::from_millis(40)));
+	assert!(config.streamer_config().joint_acceleration == 45.0);
+	assert!(config.validation.policy == ValidationPolicy::Reject);
+
+	let json = serde_json::to_string(&config).unwrap();
+	let_assert!(Ok(parsed) = EgmConfig::from_json_str(&json));
+	assert!(parsed == config);
+
+	let_assert!(Ok(streamer) = config.bind_streamer());
+	let middleware = streamer.peer().middleware();
+	assert!(middleware.len() == 1);
+
+	let target = EgmSensor::joint_target(0, vec![0.0, 90.0], EgmClock::new(0, 0));
+	let_assert!(Err(MessageRejectedError { reason }) = middleware.process_outgoing(&target));
+	assert!(reason.contains("joint 2"));
+
+	let pose = |x: f64| EgmSensor::pose_target(0, EgmPose::new([x, 0.0, 500.0], EgmQuaternion::IDENTITY), EgmClock::new(0, 0));
+	assert!(let Ok(_) = middleware.process_outgoing(&pose(500.0)));
+	assert!(let Ok(_) = middleware.process_outgoing(&pose(501.0)));
+	assert!(let Err(_) = middleware.process_outgoing(&pose(510.0)));
+
+	let mut stack = MiddlewareStack::new();
+	let config = EgmConfig {
+		validation: ValidationConfig {
+			policy: ValidationPolicy::Clamp,
+			..Default::default()
+		},
+		..config
+	};
+	config.install_middleware(&mut stack);
+	let_assert!(Ok(clamped) = stack.process_outgoing(&pose(1000.0)).map(|x| x.into_owned()));
+	let_assert!(Some(pos) = clamped.planned.as_ref().and_then(|x| x.cartesian.as_ref()).and_then(|x| x.pos));
+	assert!(pos.as_mm() == [900.0, 0.0, 500.0]);
+	let_assert!(Ok(clamped) = stack.process_outgoing(&pose(0.0)).map(|x| x.into_owned()));
+	let_assert!(Some(pos) = clamped.planned.as_ref().and_then(|x| x.cartesian.as_ref()).and_then(|x| x.pos));
+	assert!(pos.as_mm() == [898.0, 0.0, 500.0]);
+
+	let_assert!(Err(ConfigError::Invalid(_)) = EgmConfig::from_toml_str("[limits]\njoint_limits = [[10.0, -10.0]]"));
+	let_assert!(Err(ConfigError::Toml(_)) = EgmConfig::from_toml_str("sample_rate = 4"));
+	let_assert!(Err(ConfigError::UnknownFormat(_)) = EgmConfig::from_file("egm.yaml"));
+}
+
+#[cfg(test)]
+#[test]
+fn test_speed_filters() {
+	use crate::msg::EgmClock;
+	use assert2::{assert, let_assert};
+
+	let config = EgmConfig {
+		filters: FilterConfig {
+			max_joint_speed: Some(250.0),
+			..Default::default()
+		},
+		..Default::default()
+	};
+	let guard = TargetGuard::new(config);
+	let target = |joint: f64| EgmSensor::joint_target(0, vec![joint], EgmClock::new(0, 0));
+
+	// Until the sample time is measured, the configured sample time of 4 ms limits the step to 1 degree.
+	assert!(let Ok(()) = guard.on_send(&mut target(0.0)));
+	assert!(let Ok(()) = guard.on_send(&mut target(1.0)));
+	assert!(let Err(_) = guard.on_send(&mut target(2.5)));
+
+	// With a measured sample time of 8 ms, the step is 2 degrees.
+	let start = Instant::now();
+	let mut estimator = SampleTimeEstimator::new(2);
+	for arrival in [0, 8, 16] {
+		estimator.record(start + Duration::from_millis(arrival));
+	}
+	*guard.sample_time.lock().unwrap() = estimator;
+	assert!(let Ok(()) = guard.on_send(&mut target(2.5)));
+	assert!(let Err(_) = guard.on_send(&mut target(5.0)));
+
+	let_assert!(Err(ConfigError::Invalid(_)) = EgmConfig::from_toml_str("[filters]\nmax_linear_speed = -1.0"));
+}
@@ -0,0 +1,353 @@
+use std::time::{Duration, Instant};
+
+use crate::capabilities::ProtocolCapabilities;
+use crate::error::ReceiveError;
+use crate::keepalive::KeepaliveMode;
+use crate::msg::{EgmHeader, EgmRobot, EgmSensor};
+use crate::sync_peer::ConnectedEgmPeer;
+use crate::transport::EgmTransport;
+
+/// Parameters of a conformance run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConformanceConfig {
+	/// The number of messages to observe for the rate, field and convergence checks.
+	pub messages: usize,
+
+	/// The number of messages that must follow the invalid targets for the controller to pass that check.
+	pub messages_after_invalid: usize,
+
+	/// The expected interval between messages, or `None` to compare the intervals with their mean.
+	pub expected_sample_time: Option<Duration>,
+
+	/// The maximum deviation of a message interval from the expected interval, as fraction of the expected interval.
+	pub rate_tolerance: f64,
+
+	/// The time without messages after which the controller is considered to have stopped sending.
+	pub receive_timeout: Duration,
+
+	/// The kind of targets used to hold the robot in place, matching the RAPID instruction that started the session.
+	pub mode: KeepaliveMode,
+}
+
+impl Default for ConformanceConfig {
+	fn default() -> Self {
+		Self {
+			messages: 250,
+			messages_after_invalid: 25,
+			expected_sample_time: None,
+			rate_tolerance: 0.25,
+			receive_timeout: Duration::from_secs(1),
+			mode: KeepaliveMode::Joint,
+		}
+	}
+}
+
+/// The outcome of a single conformance check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckOutcome {
+	/// The controller behaved as expected.
+	Pass,
+
+	/// The controller did not behave as expected.
+	Fail,
+
+	/// The check could not be performed, for example because the controller does not send the required field.
+	Skipped,
+}
+
+/// The result of a single conformance check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckResult {
+	/// The name of the check, such as `message_rate`.
+	pub name: &'static str,
+
+	/// The outcome of the check.
+	pub outcome: CheckOutcome,
+
+	/// A human readable explanation of the outcome.
+	pub details: String,
+}
+
+/// Structured report of a conformance run.
+///
+/// The [`std::fmt::Display`] implementation prints one line per check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConformanceReport {
+	/// The results of all checks, in the order they were run.
+	pub checks: Vec<CheckResult>,
+
+	/// The fields that the controller populated in the observed messages.
+	pub capabilities: ProtocolCapabilities,
+}
+
+impl ConformanceReport {
+	/// Check if no check failed.
+	pub fn passed(&self) -> bool {
+		self.checks.iter().all(|x| x.outcome != CheckOutcome::Fail)
+	}
+
+	/// Get the result of a check by name.
+	pub fn check(&self, name: &str) -> Option<&CheckResult> {
+		self.checks.iter().find(|x| x.name == name)
+	}
+}
+
+/// Run a battery of checks against a live controller, to qualify new controllers or RobotWare versions.
+///
+/// The controller must be running an EGM session started with `EGMRunJoint` or `EGMRunPose`, matching [`ConformanceConfig::mode`].
+/// During the run, every received message is answered with a target that echoes the planned position,
+/// so the robot holds its position.
+///
+/// The following checks are performed:
+///   * `message_rate`: the intervals between the controller timestamps are stable.
+///   * `sequence_numbers`: the sequence numbers increase by one without gaps.
+///   * `field_availability`: the header, feedback and planned positions and timestamps are present.
+///   * `convergence_flag`: the controller reports convergence while the target equals the planned position.
+///   * `invalid_targets`: the controller keeps streaming after receiving an undecodable datagram and a message without target.
+///
+/// # Warning
+/// Only run the checks on a robot that is allowed to move: the targets are derived from the planned position reported by the controller.
+pub fn run<T: EgmTransport>(peer: &mut ConnectedEgmPeer<T>, config: &ConformanceConfig) -> ConformanceReport {
+	let mut runner = Runner { peer, config, sequence: 0 };
+	let messages = runner.collect(config.messages);
+
+	let mut capabilities = ProtocolCapabilities::default();
+	for (message, _) in &messages {
+		capabilities.merge(&ProtocolCapabilities::from_message(message));
+	}
+
+	let mut checks = vec![
+		check_message_rate(&messages, config),
+		check_sequence_numbers(&messages),
+		check_field_availability(&messages, config),
+		check_convergence_flag(&messages),
+	];
+	checks.push(runner.check_invalid_targets());
+	ConformanceReport { checks, capabilities }
+}
+
+struct Runner<'a, T: EgmTransport> {
+	peer: &'a mut ConnectedEgmPeer<T>,
+	config: &'a ConformanceConfig,
+	sequence: u32,
+}
+
+impl<T: EgmTransport> Runner<'_, T> {
+	/// Receive up to `count` messages, answering each with a holding target.
+	///
+	/// Stops early if no message was received for the receive timeout.
+	fn collect(&mut self, count: usize) -> Vec<(EgmRobot, Instant)> {
+		let mut messages = Vec::with_capacity(count);
+		let mut last_message = Instant::now();
+		while messages.len() < count && last_message.elapsed() < self.config.receive_timeout {
+			match self.peer.recv() {
+				Ok(message) => {
+					last_message = Instant::now();
+					self.hold(&message);
+					messages.push((message, last_message));
+				},
+				Err(ReceiveError::Io(_)) => std::thread::sleep(Duration::from_millis(1)),
+				Err(e) => log::warn!("conformance run ignores invalid message: {}", e),
+			}
+		}
+		messages
+	}
+
+	/// Send a target that echoes the planned position of a message.
+	fn hold(&mut self, message: &EgmRobot) {
+		let time = message.planned_time().unwrap_or_default();
+		let target = match self.config.mode {
+			KeepaliveMode::Joint => message.planned_joints().map(|x| EgmSensor::joint_target(self.sequence, x.clone(), time)),
+			KeepaliveMode::Pose => message.planned_pose().map(|x| EgmSensor::pose_target(self.sequence, x.clone(), time)),
+		};
+		if let Some(target) = target {
+			self.sequence = self.sequence.wrapping_add(1);
+			if let Err(e) = self.peer.send(&target) {
+				log::warn!("conformance run failed to send holding target: {}", e);
+			}
+		}
+	}
+
+	fn check_invalid_targets(&mut self) -> CheckResult {
+		let name = "invalid_targets";
+		if let Err(e) = self.peer.socket_mut().send(&[0xff; 16]) {
+			log::warn!("conformance run failed to send undecodable datagram: {}", e);
+		}
+		let empty = EgmSensor {
+			header: Some(EgmHeader::command(self.sequence, 0)),
+			..Default::default()
+		};
+		self.sequence = self.sequence.wrapping_add(1);
+		if let Err(e) = self.peer.send(&empty) {
+			return result(name, CheckOutcome::Skipped, format!("failed to send message without target: {}", e));
+		}
+
+		let expected = self.config.messages_after_invalid;
+		let received = self.collect(expected).len();
+		if received < expected {
+			result(name, CheckOutcome::Fail, format!("controller stopped sending after {} of {} messages", received, expected))
+		} else {
+			result(name, CheckOutcome::Pass, format!("controller kept sending {} messages", received))
+		}
+	}
+}
+
+fn result(name: &'static str, outcome: CheckOutcome, details: String) -> CheckResult {
+	CheckResult { name, outcome, details }
+}
+
+fn check_message_rate(messages: &[(EgmRobot, Instant)], config: &ConformanceConfig) -> CheckResult {
+	let name = "message_rate";
+	let times: Option<Vec<Duration>> = messages.iter().map(|(x, _)| x.feedback_time().map(|x| x.elapsed_since_epoch())).collect();
+	let times = match times {
+		Some(times) if times.len() >= 2 => times,
+		_ => return result(name, CheckOutcome::Skipped, "not enough messages with feedback time".into()),
+	};
+	let intervals: Vec<f64> = times.windows(2).map(|x| x[1].as_secs_f64() - x[0].as_secs_f64()).collect();
+	let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+	let expected = config.expected_sample_time.map(|x| x.as_secs_f64()).unwrap_or(mean);
+	let worst = intervals.iter().map(|x| (x - expected).abs()).fold(0.0, f64::max);
+	let details = format!(
+		"mean interval {:.3} ms, worst deviation {:.3} ms from {:.3} ms",
+		mean * 1e3,
+		worst * 1e3,
+		expected * 1e3
+	);
+	if expected > 0.0 && worst <= expected * config.rate_tolerance {
+		result(name, CheckOutcome::Pass, details)
+	} else {
+		result(name, CheckOutcome::Fail, details)
+	}
+}
+
+fn check_sequence_numbers(messages: &[(EgmRobot, Instant)]) -> CheckResult {
+	let name = "sequence_numbers";
+	let numbers: Option<Vec<u32>> = messages.iter().map(|(x, _)| x.sequence_number()).collect();
+	let numbers = match numbers {
+		Some(numbers) if numbers.len() >= 2 => numbers,
+		_ => return result(name, CheckOutcome::Skipped, "not enough messages with sequence number".into()),
+	};
+	let gaps = numbers.windows(2).filter(|x| x[1] != x[0].wrapping_add(1)).count();
+	if gaps == 0 {
+		result(name, CheckOutcome::Pass, format!("{} consecutive sequence numbers", numbers.len()))
+	} else {
+		result(name, CheckOutcome::Fail, format!("{} gaps in {} sequence numbers", gaps, numbers.len()))
+	}
+}
+
+/// A field name with a function that checks if the field is present.
+type FieldCheck = (&'static str, fn(&EgmRobot) -> bool);
+
+fn check_field_availability(messages: &[(EgmRobot, Instant)], config: &ConformanceConfig) -> CheckResult {
+	let name = "field_availability";
+	if messages.is_empty() {
+		return result(name, CheckOutcome::Fail, "no messages received".into());
+	}
+	let required: [FieldCheck; 5] = [
+		("header", |x| x.header.is_some()),
+		("feed_back.joints", |x| x.feedback_joints().is_some()),
+		("feed_back.time", |x| x.feedback_time().is_some()),
+		("planned.time", |x| x.planned_time().is_some()),
+		("planned target", |x| x.planned_joints().is_some() || x.planned_pose().is_some()),
+	];
+	let mut missing: Vec<&str> = required
+		.iter()
+		.filter(|(_, present)| !messages.iter().all(|(x, _)| present(x)))
+		.map(|(field, _)| *field)
+		.collect();
+	let planned = match config.mode {
+		KeepaliveMode::Joint => messages.iter().all(|(x, _)| x.planned_joints().is_some()),
+		KeepaliveMode::Pose => messages.iter().all(|(x, _)| x.planned_pose().is_some()),
+	};
+	if !planned {
+		missing.push(match config.mode {
+			KeepaliveMode::Joint => "planned.joints",
+			KeepaliveMode::Pose => "planned.cartesian",
+		});
+	}
+	if missing.is_empty() {
+		result(name, CheckOutcome::Pass, "all required fields present in every message".into())
+	} else {
+		result(name, CheckOutcome::Fail, format!("missing in some messages: {}", missing.join(", ")))
+	}
+}
+
+fn check_convergence_flag(messages: &[(EgmRobot, Instant)]) -> CheckResult {
+	let name = "convergence_flag";
+	let flags: Vec<bool> = messages.iter().filter_map(|(x, _)| x.mci_convergence_met).collect();
+	if flags.is_empty() {
+		return result(name, CheckOutcome::Skipped, "controller does not send mci_convergence_met".into());
+	}
+	match flags.iter().position(|x| *x) {
+		Some(index) => result(name, CheckOutcome::Pass, format!("convergence reported after {} messages", index + 1)),
+		None => result(name, CheckOutcome::Fail, "convergence never reported while holding the planned position".into()),
+	}
+}
+
+impl std::fmt::Display for CheckOutcome {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Pass => write!(f, "PASS"),
+			Self::Fail => write!(f, "FAIL"),
+			Self::Skipped => write!(f, "SKIP"),
+		}
+	}
+}
+
+impl std::fmt::Display for ConformanceReport {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		for check in &self.checks {
+			writeln!(f, "{} {:<20} {}", check.outcome, check.name, check.details)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_conformance() {
+	use crate::msg::{EgmClock, EgmFeedBack, EgmJoints, EgmPlanned};
+	use crate::testing::MockRobot;
+	use assert2::assert;
+
+	let message = |seqno: u32| EgmRobot {
+		header: Some(EgmHeader::data(seqno, seqno * 4)),
+		feed_back: Some(EgmFeedBack {
+			joints: Some(EgmJoints::from_degrees(vec![0.0; 6])),
+			time: Some(EgmClock::new(0, u64::from(seqno) * 4000)),
+			..Default::default()
+		}),
+		planned: Some(EgmPlanned::joints(vec![0.0; 6], EgmClock::new(0, u64::from(seqno) * 4000))),
+		mci_convergence_met: Some(seqno > 5),
+		..Default::default()
+	};
+
+	// The robot answers every target with the next message, but stops after 40 messages.
+	let robot = MockRobot::with_responses(vec![message(0)]);
+	let mut seqno = 0;
+	robot.set_responder(move |_| {
+		seqno += 1;
+		if seqno < 40 {
+			Some(message(seqno))
+		} else {
+			None
+		}
+	});
+
+	let config = ConformanceConfig {
+		messages: 20,
+		messages_after_invalid: 30,
+		receive_timeout: Duration::from_millis(50),
+		..Default::default()
+	};
+	let mut peer = ConnectedEgmPeer::from_transport(robot);
+	let report = run(&mut peer, &config);
+	assert!(report.check("message_rate").unwrap().outcome == CheckOutcome::Pass);
+	assert!(report.check("sequence_numbers").unwrap().outcome == CheckOutcome::Pass);
+	assert!(report.check("field_availability").unwrap().outcome == CheckOutcome::Pass);
+	assert!(report.check("convergence_flag").unwrap().details == "convergence reported after 7 messages");
+	assert!(report.check("invalid_targets").unwrap().outcome == CheckOutcome::Fail);
+	assert!(!report.passed());
+	assert!(report.capabilities.planned_joints);
+	assert!(report.to_string().lines().count() == 5);
+}
@@ -0,0 +1,536 @@
+use std::time::Duration;
+
+use crate::msg::{EgmCartesian, EgmJoints, EgmPose, EgmQuaternion, EgmSpeedRef};
+use crate::wrench::{ForceFrame, Wrench};
+
+/// Virtual mass, damping and stiffness of a single axis of an [`Admittance`].
+///
+/// For translational axes, the units are kg, N·s/m and N/m.
+/// For rotational axes, the units are kg·m², N·m·s/rad and N·m/rad.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MassSpringDamper {
+	/// The virtual mass or inertia. Must be positive.
+	pub mass: f64,
+
+	/// The virtual damping.
+	pub damping: f64,
+
+	/// The virtual stiffness that pulls the offset back to zero.
+	///
+	/// With zero stiffness the robot stays where the external force pushed it.
+	pub stiffness: f64,
+}
+
+impl MassSpringDamper {
+	/// Create a new parameterization from a mass, damping and stiffness.
+	pub fn new(mass: f64, damping: f64, stiffness: f64) -> Self {
+		Self { mass, damping, stiffness }
+	}
+
+	/// Create a critically damped parameterization from a mass and stiffness.
+	pub fn critically_damped(mass: f64, stiffness: f64) -> Self {
+		Self::new(mass, 2.0 * (mass * stiffness).sqrt(), stiffness)
+	}
+}
+
+/// A pose offset computed by an [`Admittance`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PoseOffset {
+	/// The translation along the X, Y and Z axis in millimeters.
+	pub translation: [f64; 3],
+
+	/// The rotation as rotation vector in degrees: the direction is the axis and the length is the angle.
+	pub rotation: [f64; 3],
+
+	/// The frame of the wrench that caused the offset, which is also the frame the offset is expressed in.
+	pub frame: ForceFrame,
+}
+
+impl PoseOffset {
+	/// Create an offset without translation or rotation.
+	pub fn zero(frame: ForceFrame) -> Self {
+		Self {
+			translation: [0.0; 3],
+			rotation: [0.0; 3],
+			frame,
+		}
+	}
+
+	/// Get the rotation of the offset as unit quaternion.
+	pub fn rotation_quaternion(&self) -> EgmQuaternion {
+		let [x, y, z] = self.rotation.map(f64::to_radians);
+		let angle = (x * x + y * y + z * z).sqrt();
+		if angle < 1e-12 {
+			return EgmQuaternion::IDENTITY;
+		}
+		let (sin, cos) = (angle / 2.0).sin_cos();
+		EgmQuaternion::from_wxyz(cos, x / angle * sin, y / angle * sin, z / angle * sin)
+	}
+
+	/// Apply the offset to a pose.
+	///
+	/// If the offset is expressed in the tool or sensor frame, it is applied in the tool frame of the pose.
+	/// Otherwise, the translation is applied in the base frame and the rotation is applied around the base axes through the tool center point.
+	///
+	/// Missing fields of the pose are treated as zero position and identity orientation.
+	pub fn apply_to(&self, pose: &EgmPose) -> EgmPose {
+		let position = pose.pos.as_ref().map(EgmCartesian::as_mm).unwrap_or_default();
+		let orientation = match (&pose.orient, &pose.euler) {
+			(_, Some(euler)) => euler.to_quaternion().as_wxyz(),
+			(Some(orient), None) => orient.as_wxyz(),
+			(None, None) => EgmQuaternion::IDENTITY.as_wxyz(),
+		};
+		let rotation = self.rotation_quaternion().as_wxyz();
+		let (translation, orientation) = match self.frame {
+			ForceFrame::Tool | ForceFrame::Sensor => (rotate(orientation, self.translation), multiply(orientation, rotation)),
+			_ => (self.translation, multiply(rotation, orientation)),
+		};
+		let [w, x, y, z] = orientation;
+		EgmPose::new(
+			[position[0] + translation[0], position[1] + translation[1], position[2] + translation[2]],
+			EgmQuaternion::from_wxyz(w, x, y, z),
+		)
+	}
+}
+
+/// Admittance controller for compliant motion.
+///
+/// The controller simulates a virtual mass-spring-damper system on each axis, driven by the measured force and torque:
+/// `M·a + D·v + K·x = F`.
+/// The resulting displacement `x` is an offset from a nominal pose,
+/// which makes the robot yield to external forces, for example to find the hole in a peg-in-hole insertion task.
+///
+/// Call [`Admittance::update`] with every received wrench, and apply the returned offset to the nominal pose
+/// with [`PoseOffset::apply_to`] before sending it as target.
+///
+/// The measured force usually contains an offset and noise when nothing touches the tool.
+/// Use [`Admittance::with_deadband`] to ignore small forces, and [`Admittance::with_max_offset`] to limit how far the robot may be pushed away.
+#[derive(Clone, Debug)]
+pub struct Admittance {
+	axes: [MassSpringDamper; 6],
+	force_deadband: f64,
+	torque_deadband: f64,
+	max_translation: f64,
+	max_rotation: f64,
+	position: [f64; 6],
+	velocity: [f64; 6],
+	frame: ForceFrame,
+}
+
+impl Admittance {
+	/// Create an admittance controller with the same parameterization for all translational and all rotational axes.
+	pub fn new(translation: MassSpringDamper, rotation: MassSpringDamper) -> Self {
+		Self::per_axis([translation; 3], [rotation; 3])
+	}
+
+	/// Create an admittance controller with a different parameterization for each axis.
+	pub fn per_axis(translation: [MassSpringDamper; 3], rotation: [MassSpringDamper; 3]) -> Self {
+		let [tx, ty, tz] = translation;
+		let [rx, ry, rz] = rotation;
+		Self {
+			axes: [tx, ty, tz, rx, ry, rz],
+			force_deadband: 0.0,
+			torque_deadband: 0.0,
+			max_translation: f64::INFINITY,
+			max_rotation: f64::INFINITY,
+			position: [0.0; 6],
+			velocity: [0.0; 6],
+			frame: ForceFrame::Unknown,
+		}
+	}
+
+	/// Ignore forces in newton and torques in newton meter with a smaller magnitude than the deadband.
+	///
+	/// The deadband is subtracted from larger values, so the controller does not jump when a force leaves the deadband.
+	pub fn with_deadband(mut self, force: f64, torque: f64) -> Self {
+		self.force_deadband = force;
+		self.torque_deadband = torque;
+		self
+	}
+
+	/// Limit the offset on each axis to a translation in millimeters and a rotation in degrees.
+	pub fn with_max_offset(mut self, translation: f64, rotation: f64) -> Self {
+		self.max_translation = translation;
+		self.max_rotation = rotation;
+		self
+	}
+
+	/// Get the parameterization of each axis: X, Y and Z translation followed by X, Y and Z rotation.
+	pub fn axes(&self) -> &[MassSpringDamper; 6] {
+		&self.axes
+	}
+
+	/// Advance the simulation by a time step with a measured wrench and get the new offset.
+	///
+	/// The offset is expressed in the frame of the wrench.
+	/// Wrenches in different frames should not be mixed: the state is not transformed when the frame changes.
+	pub fn update(&mut self, wrench: &Wrench, dt: Duration) -> PoseOffset {
+		let dt = dt.as_secs_f64();
+		let external = wrench.to_values();
+		for (i, (axis, external)) in self.axes.iter().zip(external).enumerate() {
+			let (deadband, limit) = if i < 3 {
+				(self.force_deadband, self.max_translation / 1000.0)
+			} else {
+				(self.torque_deadband, self.max_rotation.to_radians())
+			};
+			let force = apply_deadband(external, deadband);
+			let position = &mut self.position[i];
+			let velocity = &mut self.velocity[i];
+
+			// Semi-implicit Euler integration, which stays stable for stiff springs at the EGM sample rate.
+			let acceleration = (force - axis.damping * *velocity - axis.stiffness * *position) / axis.mass;
+			*velocity += acceleration * dt;
+			*position += *velocity * dt;
+
+			if position.abs() > limit {
+				*position = position.clamp(-limit, limit);
+				*velocity = 0.0;
+			}
+		}
+		self.frame = wrench.frame;
+		self.offset()
+	}
+
+	/// Get the current offset.
+	pub fn offset(&self) -> PoseOffset {
+		let [x, y, z, rx, ry, rz] = self.position;
+		PoseOffset {
+			translation: [x * 1000.0, y * 1000.0, z * 1000.0],
+			rotation: [rx.to_degrees(), ry.to_degrees(), rz.to_degrees()],
+			frame: self.frame,
+		}
+	}
+
+	/// Reset the offset and velocity to zero.
+	pub fn reset(&mut self) {
+		self.position = [0.0; 6];
+		self.velocity = [0.0; 6];
+	}
+}
+
+/// Proportional, integral and derivative gains of a [`Pid`] controller.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PidGains {
+	/// The proportional gain.
+	pub kp: f64,
+
+	/// The integral gain, per second.
+	pub ki: f64,
+
+	/// The derivative gain, in seconds.
+	pub kd: f64,
+}
+
+impl PidGains {
+	/// Create new gains.
+	pub fn new(kp: f64, ki: f64, kd: f64) -> Self {
+		Self { kp, ki, kd }
+	}
+
+	/// Create gains for a proportional controller.
+	pub fn proportional(kp: f64) -> Self {
+		Self::new(kp, 0.0, 0.0)
+	}
+}
+
+/// PID controller for a single axis, with output clamping and anti-windup.
+///
+/// The derivative is computed from the error, so a step in the target causes a spike in the output.
+/// The output is clamped to the output limit.
+/// To prevent windup, the integral does not grow while the output is saturated in the direction of the error,
+/// and it is clamped so that the integral term alone never exceeds the output limit.
+#[derive(Clone, Debug)]
+pub struct Pid {
+	gains: PidGains,
+	output_limit: f64,
+	integral: f64,
+	previous_error: Option<f64>,
+}
+
+impl Pid {
+	/// Create a new controller with the given gains and no output limit.
+	pub fn new(gains: PidGains) -> Self {
+		Self {
+			gains,
+			output_limit: f64::INFINITY,
+			integral: 0.0,
+			previous_error: None,
+		}
+	}
+
+	/// Clamp the output to the range `[-limit, limit]`.
+	pub fn with_output_limit(mut self, limit: f64) -> Self {
+		self.output_limit = limit;
+		self
+	}
+
+	/// Get the gains of the controller.
+	pub fn gains(&self) -> &PidGains {
+		&self.gains
+	}
+
+	/// Get the output limit of the controller.
+	pub fn output_limit(&self) -> f64 {
+		self.output_limit
+	}
+
+	/// Compute the output for the error between target and feedback, and advance the controller by a time step.
+	///
+	/// The derivative term is zero on the first update after creation or reset.
+	pub fn update(&mut self, error: f64, dt: Duration) -> f64 {
+		let dt = dt.as_secs_f64();
+		let derivative = match self.previous_error {
+			Some(previous) if dt > 0.0 => (error - previous) / dt,
+			_ => 0.0,
+		};
+		self.previous_error = Some(error);
+
+		let gains = self.gains;
+		let unclamped = |integral: f64| gains.kp * error + gains.ki * integral + gains.kd * derivative;
+		let mut integral = self.integral + error * dt;
+		if gains.ki != 0.0 {
+			let limit = (self.output_limit / gains.ki).abs();
+			integral = integral.clamp(-limit, limit);
+		}
+		let output = unclamped(integral);
+		let saturated = output.abs() > self.output_limit && output.signum() == error.signum();
+		if !saturated {
+			self.integral = integral;
+		}
+		unclamped(self.integral).clamp(-self.output_limit, self.output_limit)
+	}
+
+	/// Reset the integral and derivative state.
+	pub fn reset(&mut self) {
+		self.integral = 0.0;
+		self.previous_error = None;
+	}
+}
+
+/// Error that may occur when computing the output of a [`JointPid`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PidError {
+	/// The target or feedback does not have the same number of joints as the controller.
+	WrongNumberOfJoints { expected: usize, actual: usize },
+}
+
+/// PID controllers for each joint of a robot.
+///
+/// The error is computed in degrees and the output is a joint speed in degrees/s.
+/// Use [`JointPid::speed_ref`] to send the output as speed reference,
+/// or [`JointPid::next_target`] to integrate the output into a target for the next sample.
+#[derive(Clone, Debug)]
+pub struct JointPid {
+	axes: Vec<Pid>,
+}
+
+impl JointPid {
+	/// Create controllers for a number of joints, all with the same gains and output limit in degrees/s.
+	pub fn new(joints: usize, gains: PidGains, output_limit: f64) -> Self {
+		Self::per_axis(vec![Pid::new(gains).with_output_limit(output_limit); joints])
+	}
+
+	/// Create controllers with a different configuration for each joint.
+	pub fn per_axis(axes: Vec<Pid>) -> Self {
+		Self { axes }
+	}
+
+	/// Get the controllers of all joints.
+	pub fn axes(&self) -> &[Pid] {
+		&self.axes
+	}
+
+	/// Compute the joint speeds in degrees/s that move the feedback toward the target.
+	pub fn update(&mut self, target: &EgmJoints, feedback: &EgmJoints, dt: Duration) -> Result<Vec<f64>, PidError> {
+		for actual in [target.joints.len(), feedback.joints.len()] {
+			if actual != self.axes.len() {
+				return Err(PidError::WrongNumberOfJoints { expected: self.axes.len(), actual });
+			}
+		}
+		Ok(self
+			.axes
+			.iter_mut()
+			.zip(target.joints.iter().zip(&feedback.joints))
+			.map(|(pid, (target, feedback))| pid.update(target - feedback, dt))
+			.collect())
+	}
+
+	/// Compute the joint speeds and wrap them in a speed reference.
+	pub fn speed_ref(&mut self, target: &EgmJoints, feedback: &EgmJoints, dt: Duration) -> Result<EgmSpeedRef, PidError> {
+		Ok(EgmSpeedRef::joints(self.update(target, feedback, dt)?))
+	}
+
+	/// Compute the joint speeds and integrate them over the time step into an incremental target.
+	pub fn next_target(&mut self, target: &EgmJoints, feedback: &EgmJoints, dt: Duration) -> Result<EgmJoints, PidError> {
+		let speed = self.update(target, feedback, dt)?;
+		let step = dt.as_secs_f64();
+		Ok(feedback.joints.iter().zip(speed).map(|(position, speed)| position + speed * step).collect::<Vec<_>>().into())
+	}
+
+	/// Reset the state of all controllers.
+	pub fn reset(&mut self) {
+		self.axes.iter_mut().for_each(Pid::reset)
+	}
+}
+
+/// PID controllers for the X, Y and Z position of the tool.
+///
+/// The error is computed in millimeters and the output is a linear speed in mm/s.
+/// Use [`CartesianPid::speed_ref`] to send the output as speed reference,
+/// or [`CartesianPid::next_target`] to integrate the output into a target for the next sample.
+#[derive(Clone, Debug)]
+pub struct CartesianPid {
+	axes: [Pid; 3],
+}
+
+impl CartesianPid {
+	/// Create controllers for the three axes, all with the same gains and output limit in mm/s.
+	pub fn new(gains: PidGains, output_limit: f64) -> Self {
+		let pid = Pid::new(gains).with_output_limit(output_limit);
+		Self::per_axis([pid.clone(), pid.clone(), pid])
+	}
+
+	/// Create controllers with a different configuration for each axis.
+	pub fn per_axis(axes: [Pid; 3]) -> Self {
+		Self { axes }
+	}
+
+	/// Get the controllers of the X, Y and Z axis.
+	pub fn axes(&self) -> &[Pid; 3] {
+		&self.axes
+	}
+
+	/// Compute the linear speed in mm/s that moves the feedback toward the target.
+	pub fn update(&mut self, target: &EgmCartesian, feedback: &EgmCartesian, dt: Duration) -> [f64; 3] {
+		let target = target.as_mm();
+		let feedback = feedback.as_mm();
+		let mut output = [0.0; 3];
+		for (i, (output, pid)) in output.iter_mut().zip(&mut self.axes).enumerate() {
+			*output = pid.update(target[i] - feedback[i], dt);
+		}
+		output
+	}
+
+	/// Compute the linear speed and wrap it in a speed reference.
+	pub fn speed_ref(&mut self, target: &EgmCartesian, feedback: &EgmCartesian, dt: Duration) -> EgmSpeedRef {
+		EgmSpeedRef::cartesian(self.update(target, feedback, dt))
+	}
+
+	/// Compute the linear speed and integrate it over the time step into an incremental target.
+	pub fn next_target(&mut self, target: &EgmCartesian, feedback: &EgmCartesian, dt: Duration) -> EgmCartesian {
+		let [vx, vy, vz] = self.update(target, feedback, dt);
+		let [x, y, z] = feedback.as_mm();
+		let step = dt.as_secs_f64();
+		EgmCartesian::from_mm(x + vx * step, y + vy * step, z + vz * step)
+	}
+
+	/// Reset the state of all controllers.
+	pub fn reset(&mut self) {
+		self.axes.iter_mut().for_each(Pid::reset)
+	}
+}
+
+impl std::fmt::Display for PidError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::WrongNumberOfJoints { expected, actual } => write!(f, "wrong number of joints, expected {}, got {}", expected, actual),
+		}
+	}
+}
+
+impl std::error::Error for PidError {}
+
+fn apply_deadband(value: f64, deadband: f64) -> f64 {
+	if value.abs() <= deadband {
+		0.0
+	} else {
+		value - deadband.copysign(value)
+	}
+}
+
+/// Multiply two quaternions in [w, x, y, z] order.
+fn multiply(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+	let [aw, ax, ay, az] = a;
+	let [bw, bx, by, bz] = b;
+	[
+		aw * bw - ax * bx - ay * by - az * bz,
+		aw * bx + ax * bw + ay * bz - az * by,
+		aw * by - ax * bz + ay * bw + az * bx,
+		aw * bz + ax * by - ay * bx + az * bw,
+	]
+}
+
+/// Rotate a vector by a unit quaternion in [w, x, y, z] order.
+fn rotate(rotation: [f64; 4], vector: [f64; 3]) -> [f64; 3] {
+	let [w, x, y, z] = rotation;
+	let conjugate = [w, -x, -y, -z];
+	let [_, x, y, z] = multiply(multiply(rotation, [0.0, vector[0], vector[1], vector[2]]), conjugate);
+	[x, y, z]
+}
+
+#[cfg(test)]
+#[test]
+fn test_admittance() {
+	use assert2::assert;
+
+	let spring = MassSpringDamper::critically_damped(2.0, 1000.0);
+	let mut admittance = Admittance::new(spring, MassSpringDamper::new(0.1, 1.0, 10.0)).with_deadband(1.0, 0.1);
+	let push = Wrench::new([11.0, 0.5, 0.0], [0.0; 3], ForceFrame::Tool);
+	for _ in 0..2000 {
+		admittance.update(&push, Duration::from_millis(4));
+	}
+
+	// The spring settles at x = F / K, after subtracting the deadband.
+	let offset = admittance.offset();
+	assert!((offset.translation[0] - 10.0).abs() < 1e-6);
+	assert!(offset.translation[1] == 0.0);
+	assert!(offset.rotation == [0.0; 3]);
+
+	let mut limited = Admittance::new(spring, spring).with_max_offset(2.0, 1.0);
+	let offset = limited.update(&Wrench::new([1e6, 0.0, 0.0], [0.0, 0.0, -1e6], ForceFrame::Base), Duration::from_millis(4));
+	assert!(offset.translation[0] == 2.0);
+	assert!(offset.rotation[2] == -1.0);
+	limited.reset();
+	assert!(limited.offset() == PoseOffset::zero(ForceFrame::Base));
+
+	let pose = EgmPose::new([100.0, 0.0, 0.0], EgmQuaternion::from_wxyz(0.0, 0.0, 0.0, 1.0));
+	let offset = PoseOffset {
+		translation: [10.0, 0.0, 0.0],
+		rotation: [0.0, 0.0, 90.0],
+		frame: ForceFrame::Tool,
+	};
+	let moved = offset.apply_to(&pose);
+	let [x, y, z] = moved.pos.unwrap().as_mm();
+	assert!((x - 90.0).abs() < 1e-9 && y.abs() < 1e-9 && z == 0.0);
+	assert!(moved.orient.unwrap().angle_to(&EgmQuaternion::from_wxyz(-0.5f64.sqrt(), 0.0, 0.0, 0.5f64.sqrt())) < 1e-6);
+}
+
+#[cfg(test)]
+#[test]
+fn test_pid() {
+	use assert2::{assert, let_assert};
+
+	let dt = Duration::from_millis(4);
+	let mut pid = Pid::new(PidGains::new(2.0, 10.0, 0.0)).with_output_limit(5.0);
+	assert!(pid.update(1.0, dt) == 2.0 + 10.0 * 0.004);
+
+	// A large error saturates the output without winding up the integral.
+	for _ in 0..1000 {
+		assert!(pid.update(100.0, dt) == 5.0);
+	}
+	assert!(pid.update(0.0, dt) < 0.5);
+
+	let mut joints = JointPid::new(2, PidGains::proportional(10.0), 20.0);
+	let target = EgmJoints::from_degrees(vec![10.0, -1.0]);
+	let feedback = EgmJoints::from_degrees(vec![0.0, 0.0]);
+	let_assert!(Ok(speed) = joints.speed_ref(&target, &feedback, dt));
+	assert!(speed == EgmSpeedRef::joints(vec![20.0, -10.0]));
+	let_assert!(Ok(next) = joints.next_target(&target, &feedback, dt));
+	assert!(next == EgmJoints::from_degrees(vec![0.08, -0.04]));
+	let too_many = EgmJoints::from_degrees(vec![0.0; 3]);
+	assert!(let Err(PidError::WrongNumberOfJoints { expected: 2, actual: 3 }) = joints.update(&too_many, &feedback, dt));
+
+	let mut cartesian = CartesianPid::new(PidGains::proportional(1.0), 100.0);
+	let speed = cartesian.update(&EgmCartesian::from_mm(10.0, 0.0, -500.0), &EgmCartesian::from_mm(0.0, 0.0, 0.0), dt);
+	assert!(speed == [10.0, 0.0, -100.0]);
+}
@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use crate::msg::{EgmPose, EgmQuaternion, EgmRobot, EgmSensor};
+
+/// Convergence check of the robot feedback against the commanded target, with user-defined tolerances.
+///
+/// The robot controller reports convergence with [`EgmRobot::mci_convergence_met`],
+/// but it uses the tolerances of the EGM configuration on the controller, which are often not easy to change.
+/// This checker uses tolerances per joint and per cartesian axis,
+/// and only reports convergence after the feedback stayed within the tolerances for the settle time.
+///
+/// The settle time is measured with the feedback time of the robot controller.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConvergenceChecker {
+	joint_tolerances: Vec<f64>,
+	position_tolerance: [f64; 3],
+	orientation_tolerance: f64,
+	settle_time: Duration,
+	within_since: Option<Duration>,
+}
+
+impl ConvergenceChecker {
+	/// Create a new checker with the same tolerance for all joints in degrees,
+	/// a position tolerance in millimeters for all axes and an orientation tolerance in degrees.
+	///
+	/// The settle time is zero: convergence is reported for the first feedback within the tolerances.
+	pub fn new(joint_tolerance: f64, position_tolerance: f64, orientation_tolerance: f64) -> Self {
+		Self {
+			joint_tolerances: vec![joint_tolerance],
+			position_tolerance: [position_tolerance; 3],
+			orientation_tolerance,
+			settle_time: Duration::ZERO,
+			within_since: None,
+		}
+	}
+
+	/// Use a separate tolerance in degrees for each joint.
+	///
+	/// If the target has more joints than tolerances, the last tolerance is used for the remaining joints.
+	pub fn with_joint_tolerances(mut self, tolerances: impl Into<Vec<f64>>) -> Self {
+		self.joint_tolerances = tolerances.into();
+		self
+	}
+
+	/// Use a separate position tolerance in millimeters for the X, Y and Z axis.
+	pub fn with_position_tolerance(mut self, tolerance: [f64; 3]) -> Self {
+		self.position_tolerance = tolerance;
+		self
+	}
+
+	/// Require the feedback to stay within the tolerances for a duration before reporting convergence.
+	pub fn with_settle_time(mut self, settle_time: Duration) -> Self {
+		self.settle_time = settle_time;
+		self
+	}
+
+	/// Get the tolerance in degrees for a joint.
+	pub fn joint_tolerance(&self, joint: usize) -> f64 {
+		match self.joint_tolerances.get(joint).or_else(|| self.joint_tolerances.last()) {
+			Some(tolerance) => *tolerance,
+			None => 0.0,
+		}
+	}
+
+	/// Check if joint positions in degrees are within the tolerances of a target.
+	pub fn joints_within(&self, feedback: &[f64], target: &[f64]) -> bool {
+		feedback.len() == target.len() && feedback.iter().zip(target).enumerate().all(|(i, (a, b))| (a - b).abs() <= self.joint_tolerance(i))
+	}
+
+	/// Check if a pose is within the tolerances of a target.
+	///
+	/// Missing fields are treated as zero position and identity orientation.
+	pub fn pose_within(&self, feedback: &EgmPose, target: &EgmPose) -> bool {
+		let a = feedback.pos.unwrap_or_default().as_mm();
+		let b = target.pos.unwrap_or_default().as_mm();
+		let position = a.iter().zip(&b).zip(&self.position_tolerance).all(|((a, b), tolerance)| (a - b).abs() <= *tolerance);
+		let a = feedback.orient.unwrap_or(EgmQuaternion::IDENTITY);
+		let b = target.orient.unwrap_or(EgmQuaternion::IDENTITY);
+		position && a.angle_to(&b) <= self.orientation_tolerance
+	}
+
+	/// Check if the feedback of a message is within the tolerances of a commanded target.
+	///
+	/// Planned joints and the planned pose of the target are checked if present.
+	/// Returns false if the target has neither, or if the feedback is missing a field of the target.
+	pub fn within(&self, state: &EgmRobot, target: &EgmSensor) -> bool {
+		let planned = match &target.planned {
+			Some(planned) => planned,
+			None => return false,
+		};
+		if planned.joints.is_none() && planned.cartesian.is_none() {
+			return false;
+		}
+		if let Some(target) = &planned.joints {
+			match state.feedback_joints() {
+				Some(feedback) if self.joints_within(feedback, &target.joints) => (),
+				_ => return false,
+			}
+		}
+		if let Some(target) = &planned.cartesian {
+			match state.feedback_pose() {
+				Some(feedback) if self.pose_within(feedback, target) => (),
+				_ => return false,
+			}
+		}
+		true
+	}
+
+	/// Update the checker with a message from the robot controller and check if the robot settled at a commanded target.
+	///
+	/// Returns true once the feedback has been within the tolerances for the settle time.
+	/// Feedback outside of the tolerances restarts the settle time.
+	pub fn update(&mut self, state: &EgmRobot, target: &EgmSensor) -> bool {
+		if !self.within(state, target) {
+			self.within_since = None;
+			return false;
+		}
+		let now = state.feedback_time().map(|x| x.elapsed_since_epoch()).unwrap_or_default();
+		let since = *self.within_since.get_or_insert(now);
+		now.checked_sub(since).unwrap_or_default() >= self.settle_time
+	}
+
+	/// Restart the settle time, for example when a new target is commanded.
+	pub fn reset(&mut self) {
+		self.within_since = None;
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_convergence_checker() {
+	use crate::msg::{EgmClock, EgmFeedBack, EgmJoints};
+	use assert2::assert;
+
+	let mut checker = ConvergenceChecker::new(0.1, 0.5, 0.5)
+		.with_joint_tolerances([0.01, 0.05])
+		.with_settle_time(Duration::from_millis(8));
+	assert!(checker.joint_tolerance(0) == 0.01);
+	assert!(checker.joint_tolerance(5) == 0.05);
+	assert!(checker.joints_within(&[1.005, 2.04, 3.0], &[1.0, 2.0, 3.04]));
+	assert!(!checker.joints_within(&[1.02, 2.0, 3.0], &[1.0, 2.0, 3.0]));
+	assert!(!checker.joints_within(&[1.0], &[1.0, 2.0]));
+
+	let pose_checker = ConvergenceChecker::new(0.1, 0.5, 0.5).with_position_tolerance([0.1, 1.0, 1.0]);
+	let target = EgmPose::new([100.0, 0.0, 0.0], EgmQuaternion::IDENTITY);
+	assert!(pose_checker.pose_within(&EgmPose::new([100.05, 0.9, 0.0], EgmQuaternion::IDENTITY), &target));
+	assert!(!pose_checker.pose_within(&EgmPose::new([100.2, 0.0, 0.0], EgmQuaternion::IDENTITY), &target));
+
+	let state = |joints: [f64; 2], msec: u64| EgmRobot {
+		feed_back: Some(EgmFeedBack {
+			joints: Some(EgmJoints::from_degrees(joints.to_vec())),
+			time: Some(EgmClock::new(0, msec * 1000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let target = EgmSensor::joint_target(0, vec![1.0, 2.0], EgmClock::new(0, 0));
+	assert!(!checker.update(&state([1.0, 2.0], 0), &target));
+	assert!(!checker.update(&state([1.0, 2.0], 4), &target));
+	assert!(!checker.update(&state([1.5, 2.0], 8), &target));
+	assert!(!checker.update(&state([1.0, 2.0], 12), &target));
+	assert!(checker.update(&state([1.0, 2.0], 20), &target));
+	assert!(!checker.within(&state([1.0, 2.0], 24), &EgmSensor::default()));
+}
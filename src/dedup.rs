@@ -0,0 +1,162 @@
+use std::sync::{Arc, Mutex};
+
+use crate::middleware::Middleware;
+use crate::msg::EgmRobot;
+use crate::MessageRejectedError;
+
+/// The number of recent sequence numbers remembered by a [`DuplicateFilter`].
+const WINDOW: u32 = 64;
+
+#[derive(Debug, Default)]
+struct State {
+	/// The newest sequence number, and a bit mask of the sequence numbers seen before it.
+	///
+	/// Bit `n` is set if sequence number `newest - n` was seen.
+	window: Option<(u32, u64)>,
+	duplicates: u64,
+}
+
+/// Middleware that rejects duplicated messages from the robot controller, based on the sequence number in the header.
+///
+/// Some industrial network equipment duplicates UDP datagrams.
+/// A duplicated feedback message looks like a cycle in which the robot did not move, which corrupts velocity estimates.
+///
+/// The filter remembers the last 64 sequence numbers, so duplicates are also detected if they arrive out of order.
+/// A message with a sequence number that was already seen is rejected, so the peer returns a `Rejected` error instead of the message.
+/// Messages without a sequence number are not checked.
+/// A sequence number far behind the newest one is treated as a controller restart, which resets the filter.
+///
+/// The filter can be cloned to keep a handle after adding it to the middleware stack of a peer.
+/// All clones share the same state.
+#[derive(Clone, Debug, Default)]
+pub struct DuplicateFilter {
+	state: Arc<Mutex<State>>,
+}
+
+impl DuplicateFilter {
+	/// Create a filter that has not seen any messages.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Get the number of duplicates rejected so far.
+	pub fn duplicate_count(&self) -> u64 {
+		self.lock().duplicates
+	}
+
+	/// Forget the seen sequence numbers.
+	pub fn reset(&self) {
+		self.lock().window = None;
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+		self.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	/// Record a sequence number, returning false if it was already seen.
+	fn record(&self, seqno: u32) -> bool {
+		let mut state = self.lock();
+		let (newest, seen) = match state.window {
+			None => {
+				state.window = Some((seqno, 1));
+				return true;
+			},
+			Some(window) => window,
+		};
+
+		let ahead = seqno.wrapping_sub(newest);
+		let behind = newest.wrapping_sub(seqno);
+		if ahead != 0 && ahead < 1 << 31 {
+			let seen = if ahead < WINDOW { seen << ahead } else { 0 };
+			state.window = Some((seqno, seen | 1));
+			true
+		} else if behind < WINDOW {
+			if seen & (1 << behind) != 0 {
+				state.duplicates += 1;
+				return false;
+			}
+			state.window = Some((newest, seen | 1 << behind));
+			true
+		} else {
+			state.window = Some((seqno, 1));
+			true
+		}
+	}
+}
+
+impl Middleware for DuplicateFilter {
+	fn on_recv(&self, msg: &mut EgmRobot) -> Result<(), MessageRejectedError> {
+		match msg.sequence_number() {
+			Some(seqno) if !self.record(seqno) => Err(MessageRejectedError::new(format!("duplicate message with sequence number {}", seqno))),
+			_ => Ok(()),
+		}
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_duplicate_filter() {
+	use assert2::assert;
+
+	let filter = DuplicateFilter::new();
+	assert!(filter.record(100));
+	assert!(!filter.record(100));
+	assert!(filter.record(102));
+
+	// Late messages within the window are accepted once.
+	assert!(filter.record(101));
+	assert!(!filter.record(101));
+	assert!(filter.record(102 - WINDOW + 1));
+	assert!(!filter.record(102 - WINDOW + 1));
+	assert!(filter.duplicate_count() == 3);
+
+	// A sequence number further back than the window is a controller restart.
+	assert!(filter.record(102 - WINDOW));
+	assert!(!filter.record(102 - WINDOW));
+	assert!(filter.record(102));
+
+	filter.reset();
+	assert!(filter.record(102));
+	assert!(filter.duplicate_count() == 4);
+}
+
+#[cfg(test)]
+#[test]
+fn test_duplicate_filter_wrap_around() {
+	use assert2::assert;
+
+	let filter = DuplicateFilter::new();
+	assert!(filter.record(u32::MAX - 1));
+	assert!(filter.record(u32::MAX));
+	assert!(filter.record(1));
+
+	// Sequence numbers from before the wrap are still remembered.
+	assert!(!filter.record(u32::MAX));
+	assert!(!filter.record(u32::MAX - 1));
+	assert!(filter.record(0));
+	assert!(!filter.record(0));
+	assert!(filter.record(2));
+	assert!(filter.duplicate_count() == 3);
+}
+
+#[cfg(test)]
+#[test]
+fn test_duplicate_filter_middleware() {
+	use crate::msg::EgmHeader;
+	use assert2::assert;
+
+	let filter = DuplicateFilter::new();
+	let handle = filter.clone();
+	let mut msg = EgmRobot {
+		header: Some(EgmHeader::data(7, 0)),
+		..Default::default()
+	};
+	assert!(let Ok(()) = filter.on_recv(&mut msg));
+	assert!(let Err(_) = filter.on_recv(&mut msg));
+	assert!(handle.duplicate_count() == 1);
+
+	// Messages without a sequence number are not checked.
+	assert!(let Ok(()) = filter.on_recv(&mut EgmRobot::default()));
+	assert!(let Ok(()) = filter.on_recv(&mut EgmRobot::default()));
+	assert!(handle.duplicate_count() == 1);
+}
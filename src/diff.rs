@@ -0,0 +1,189 @@
+use crate::msg::egm_mci_state::MciStateType;
+use crate::msg::egm_motor_state::MotorStateType;
+use crate::msg::egm_rapid_ctrl_exec_state::RapidCtrlExecStateType;
+use crate::msg::EgmRobot;
+use crate::math;
+
+/// Tolerances below which changes are not reported by [`StateDiff`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiffTolerance {
+	/// The minimum change of a joint in degrees for rotating axes or millimeters for linear axes.
+	pub joint: f64,
+
+	/// The minimum change of the position of the feedback pose in millimeters.
+	pub position: f64,
+
+	/// The minimum change of the orientation of the feedback pose in degrees.
+	pub orientation: f64,
+}
+
+impl Default for DiffTolerance {
+	fn default() -> Self {
+		Self {
+			joint: 0.01,
+			position: 0.01,
+			orientation: 0.01,
+		}
+	}
+}
+
+/// A change of a value between two messages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Change<T> {
+	/// The value in the old message.
+	pub from: T,
+
+	/// The value in the new message.
+	pub to: T,
+}
+
+/// A change of a single joint between two messages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JointChange {
+	/// The index of the joint.
+	pub joint: usize,
+
+	/// The value in the old message.
+	pub from: f64,
+
+	/// The value in the new message.
+	pub to: f64,
+}
+
+/// Structured report of the meaningful changes between two messages from the robot controller.
+///
+/// Only the feedback values and the state fields are compared.
+/// Fields that are absent in a message are reported as `None` in a state change.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StateDiff {
+	/// The feedback joints that moved more than the tolerance.
+	pub joints: Vec<JointChange>,
+
+	/// The feedback external joints that moved more than the tolerance.
+	pub external_joints: Vec<JointChange>,
+
+	/// The distance in millimeters the feedback position moved, if more than the tolerance.
+	pub position: Option<f64>,
+
+	/// The angle in degrees the feedback orientation rotated, if more than the tolerance.
+	pub orientation: Option<f64>,
+
+	/// The transition of the motor state.
+	pub motor_state: Option<Change<Option<MotorStateType>>>,
+
+	/// The transition of the MCI state.
+	pub mci_state: Option<Change<Option<MciStateType>>>,
+
+	/// The transition of the RAPID execution state.
+	pub rapid_exec_state: Option<Change<Option<RapidCtrlExecStateType>>>,
+
+	/// The flip of the MCI convergence flag.
+	pub convergence: Option<Change<Option<bool>>>,
+}
+
+impl StateDiff {
+	/// Compare two messages from the robot controller.
+	pub fn between(old: &EgmRobot, new: &EgmRobot, tolerance: &DiffTolerance) -> Self {
+		let (old_pose, new_pose) = (old.feedback_pose(), new.feedback_pose());
+		let old_position = old_pose.and_then(|x| x.pos.as_ref());
+		let new_position = new_pose.and_then(|x| x.pos.as_ref());
+		let old_orientation = old_pose.and_then(|x| x.orient.as_ref());
+		let new_orientation = new_pose.and_then(|x| x.orient.as_ref());
+
+		let position = match (old_position, new_position) {
+			(Some(a), Some(b)) => Some(math::position_distance(*a, *b)),
+			_ => None,
+		};
+		let orientation = match (old_orientation, new_orientation) {
+			(Some(a), Some(b)) => Some(math::orientation_angle(*a, *b)),
+			_ => None,
+		};
+
+		Self {
+			joints: joint_changes(old.feedback_joints(), new.feedback_joints(), tolerance.joint),
+			external_joints: joint_changes(old.feedback_extenal_joints(), new.feedback_extenal_joints(), tolerance.joint),
+			position: position.filter(|x| *x > tolerance.position),
+			orientation: orientation.filter(|x| *x > tolerance.orientation),
+			motor_state: change(motor_state(old), motor_state(new)),
+			mci_state: change(mci_state(old), mci_state(new)),
+			rapid_exec_state: change(rapid_exec_state(old), rapid_exec_state(new)),
+			convergence: change(old.mci_convergence_met, new.mci_convergence_met),
+		}
+	}
+
+	/// Check if no meaningful changes were found.
+	pub fn is_empty(&self) -> bool {
+		self == &Self::default()
+	}
+
+	/// Check if any of the state fields or the convergence flag changed.
+	pub fn has_state_change(&self) -> bool {
+		self.motor_state.is_some() || self.mci_state.is_some() || self.rapid_exec_state.is_some() || self.convergence.is_some()
+	}
+}
+
+fn joint_changes(old: Option<&Vec<f64>>, new: Option<&Vec<f64>>, tolerance: f64) -> Vec<JointChange> {
+	let (old, new) = match (old, new) {
+		(Some(old), Some(new)) => (old, new),
+		_ => return Vec::new(),
+	};
+	old.iter()
+		.zip(new)
+		.enumerate()
+		.filter(|(_, (from, to))| (*to - *from).abs() > tolerance)
+		.map(|(joint, (&from, &to))| JointChange { joint, from, to })
+		.collect()
+}
+
+fn change<T: PartialEq>(from: T, to: T) -> Option<Change<T>> {
+	if from == to {
+		None
+	} else {
+		Some(Change { from, to })
+	}
+}
+
+fn motor_state(msg: &EgmRobot) -> Option<MotorStateType> {
+	Some(msg.motor_state.as_ref()?.state())
+}
+
+fn mci_state(msg: &EgmRobot) -> Option<MciStateType> {
+	Some(msg.mci_state.as_ref()?.state())
+}
+
+fn rapid_exec_state(msg: &EgmRobot) -> Option<RapidCtrlExecStateType> {
+	Some(msg.rapid_exec_state.as_ref()?.state())
+}
+
+#[cfg(test)]
+#[test]
+fn test_state_diff() {
+	use crate::msg::{EgmFeedBack, EgmJoints, EgmMotorState};
+	use assert2::assert;
+
+	let message = |joints: Vec<f64>, motors: MotorStateType, converged: bool| {
+		let mut msg = EgmRobot {
+			feed_back: Some(EgmFeedBack {
+				joints: Some(EgmJoints::from_degrees(joints)),
+				..Default::default()
+			}),
+			motor_state: Some(EgmMotorState::default()),
+			mci_convergence_met: Some(converged),
+			..Default::default()
+		};
+		msg.motor_state.as_mut().unwrap().set_state(motors);
+		msg
+	};
+
+	let a = message(vec![0.0, 10.0, 20.0], MotorStateType::MotorsOff, false);
+	let b = message(vec![0.001, 12.0, 20.0], MotorStateType::MotorsOn, true);
+	let diff = StateDiff::between(&a, &b, &DiffTolerance::default());
+	assert!(diff.joints == [JointChange { joint: 1, from: 10.0, to: 12.0 }]);
+	assert!(diff.position == None);
+	assert!(diff.motor_state == Some(Change { from: Some(MotorStateType::MotorsOff), to: Some(MotorStateType::MotorsOn) }));
+	assert!(diff.mci_state == None);
+	assert!(diff.convergence == Some(Change { from: Some(false), to: Some(true) }));
+	assert!(diff.has_state_change());
+
+	assert!(StateDiff::between(&a, &a, &DiffTolerance::default()).is_empty());
+}
@@ -0,0 +1,122 @@
+use std::net::SocketAddr;
+
+use crate::msg::EgmRobot;
+use crate::rate::EgmRate;
+use crate::ReceiveError;
+
+/// The result of discovering a robot controller.
+#[derive(Debug)]
+pub struct Discovered<P> {
+	/// The peer, connected to the robot controller.
+	pub peer: P,
+
+	/// The address of the robot controller.
+	pub controller: SocketAddr,
+
+	/// The last message received from the robot controller during discovery.
+	pub state: EgmRobot,
+
+	/// The sample rate of the EGM session, estimated from the header timestamps of the received messages.
+	///
+	/// This is `None` if the timestamps are missing or do not correspond to a valid sample rate.
+	pub rate: Option<EgmRate>,
+}
+
+/// Bind a blocking peer and wait for the robot controller to send the first messages.
+///
+/// See [`discover_with`] for more details.
+pub fn discover(addrs: impl std::net::ToSocketAddrs) -> Result<Discovered<crate::sync_peer::ConnectedEgmPeer>, ReceiveError> {
+	discover_with(crate::sync_peer::EgmPeer::bind(addrs)?)
+}
+
+/// Wait for the robot controller to send the first messages on an unconnected blocking peer.
+///
+/// The socket is connected to the source address of the first message,
+/// and the returned peer can use [`crate::sync_peer::ConnectedEgmPeer::recv`] and [`crate::sync_peer::ConnectedEgmPeer::send`].
+/// One more message is received from the robot controller to estimate the sample rate.
+///
+/// Set a read timeout on the socket of the peer to avoid waiting forever.
+pub fn discover_with(peer: crate::sync_peer::EgmPeer) -> Result<Discovered<crate::sync_peer::ConnectedEgmPeer>, ReceiveError> {
+	let (mut peer, first) = peer.connect_to_first_sender()?;
+	let controller = peer.remote_address()?;
+	let state = peer.recv()?;
+	let rate = estimate_rate(&first, &state);
+	Ok(Discovered {
+		peer,
+		controller,
+		state,
+		rate,
+	})
+}
+
+/// Bind an asynchronous peer and wait for the robot controller to send the first messages.
+///
+/// See [`discover_with_async`] for more details.
+#[cfg(feature = "tokio")]
+pub async fn discover_async(addrs: impl tokio::net::ToSocketAddrs) -> Result<Discovered<crate::tokio_peer::ConnectedEgmPeer>, ReceiveError> {
+	discover_with_async(crate::tokio_peer::EgmPeer::bind(addrs).await?).await
+}
+
+/// Wait for the robot controller to send the first messages on an unconnected asynchronous peer.
+///
+/// The socket is connected to the source address of the first message,
+/// and the returned peer can use [`crate::tokio_peer::ConnectedEgmPeer::recv`] and [`crate::tokio_peer::ConnectedEgmPeer::send`].
+/// One more message is received from the robot controller to estimate the sample rate.
+///
+/// Use [`tokio::time::timeout`] to avoid waiting forever.
+#[cfg(feature = "tokio")]
+pub async fn discover_with_async(peer: crate::tokio_peer::EgmPeer) -> Result<Discovered<crate::tokio_peer::ConnectedEgmPeer>, ReceiveError> {
+	let (peer, first) = peer.connect_to_first_sender().await?;
+	let controller = peer.remote_address()?;
+	let state = peer.recv().await?;
+	let rate = estimate_rate(&first, &state);
+	Ok(Discovered {
+		peer,
+		controller,
+		state,
+		rate,
+	})
+}
+
+/// Estimate the sample rate from the header timestamps of two consecutive messages.
+///
+/// The difference is rounded to the nearest multiple of 4 milliseconds to filter out jitter.
+/// No rate is estimated if the timestamp went backwards, for example because the messages were reordered or the controller restarted.
+fn estimate_rate(first: &EgmRobot, second: &EgmRobot) -> Option<EgmRate> {
+	let elapsed = second.timestamp_ms()?.checked_sub(first.timestamp_ms()?)?;
+	EgmRate::from_sample_time_ms(elapsed.saturating_add(2) / 4 * 4).ok()
+}
+
+#[cfg(test)]
+#[test]
+fn test_discover() {
+	use crate::msg::EgmHeader;
+	use assert2::assert;
+	use prost::Message;
+
+	let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+	let peer = crate::sync_peer::EgmPeer::bind("127.0.0.1:0").unwrap();
+	let address = peer.socket().local_addr().unwrap();
+
+	for (seqno, tm) in [(1, 1000), (2, 1009)] {
+		let state = EgmRobot {
+			header: Some(EgmHeader::data(seqno, tm)),
+			..Default::default()
+		};
+		socket.send_to(&state.encode_to_vec(), address).unwrap();
+	}
+
+	let discovered = discover_with(peer).unwrap();
+	assert!(discovered.controller == socket.local_addr().unwrap());
+	assert!(discovered.peer.socket().peer_addr().unwrap() == socket.local_addr().unwrap());
+	assert!(discovered.state.sequence_number() == Some(2));
+	assert!(discovered.rate == Some(EgmRate::MS_8));
+
+	let state = |tm| EgmRobot {
+		header: Some(EgmHeader::data(0, tm)),
+		..Default::default()
+	};
+	assert!(estimate_rate(&state(1009), &state(1000)) == None);
+	assert!(let Some(_) = estimate_rate(&state(0), &state(u32::MAX)));
+	assert!(estimate_rate(&state(1000), &state(1004)) == Some(EgmRate::MS_4));
+}
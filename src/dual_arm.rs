@@ -0,0 +1,133 @@
+use std::net::SocketAddr;
+
+use crate::msg::EgmRobot;
+use crate::msg::EgmSensor;
+use crate::tokio_peer::EgmPeer;
+use crate::InvalidMessageError;
+use crate::ReceiveError;
+use crate::SendError;
+
+/// The state of both arms, received in the same cycle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DualArmState {
+	/// The message from the left arm.
+	pub left: EgmRobot,
+
+	/// The message from the right arm.
+	pub right: EgmRobot,
+}
+
+/// Asynchronous peer for coordinated control of a dual-arm robot, such as the ABB YuMi.
+///
+/// Each arm is a separate mechanical unit with its own EGM channel on a separate UDP port.
+/// This peer manages both channels, receives the state of both arms in the same cycle
+/// and sends coordinated targets to both arms together.
+#[derive(Debug)]
+pub struct DualArmPeer {
+	left: EgmPeer,
+	right: EgmPeer,
+	left_address: Option<SocketAddr>,
+	right_address: Option<SocketAddr>,
+}
+
+impl DualArmPeer {
+	/// Create a dual-arm peer from two existing peers.
+	///
+	/// The peers do not need to be connected:
+	/// the remote addresses are remembered from the messages received by [`DualArmPeer::recv`].
+	pub fn new(left: EgmPeer, right: EgmPeer) -> Self {
+		Self {
+			left,
+			right,
+			left_address: None,
+			right_address: None,
+		}
+	}
+
+	/// Create a dual-arm peer on two newly bound UDP sockets.
+	pub async fn bind(left: impl tokio::net::ToSocketAddrs, right: impl tokio::net::ToSocketAddrs) -> std::io::Result<Self> {
+		Ok(Self::new(EgmPeer::bind(left).await?, EgmPeer::bind(right).await?))
+	}
+
+	/// Get a shared reference to the peer for the left arm.
+	pub fn left(&self) -> &EgmPeer {
+		&self.left
+	}
+
+	/// Get a shared reference to the peer for the right arm.
+	pub fn right(&self) -> &EgmPeer {
+		&self.right
+	}
+
+	/// Consume self and get the peers for the left and right arm.
+	pub fn into_peers(self) -> (EgmPeer, EgmPeer) {
+		(self.left, self.right)
+	}
+
+	/// Purge all messages from the read queues of both arms.
+	///
+	/// Use this to re-synchronize the arms after the peer has been left unpolled for a while.
+	pub fn purge_read_queues(&self) -> std::io::Result<()> {
+		self.left.purge_read_queue()?;
+		self.right.purge_read_queue()
+	}
+
+	/// Receive one message from each arm.
+	///
+	/// This waits until a message has been received from both arms.
+	/// The addresses of the robot controller are remembered for [`DualArmPeer::send`].
+	pub async fn recv(&mut self) -> Result<DualArmState, ReceiveError> {
+		let ((left, left_address), (right, right_address)) = tokio::try_join!(self.left.recv_from(), self.right.recv_from())?;
+		self.left_address = Some(left_address);
+		self.right_address = Some(right_address);
+		Ok(DualArmState { left, right })
+	}
+
+	/// Send coordinated targets to both arms.
+	///
+	/// Both messages are validated before anything is sent,
+	/// so an invalid target for one arm never results in only the other arm moving.
+	///
+	/// The messages are sent to the addresses of the last messages received with [`DualArmPeer::recv`].
+	/// If no messages have been received yet, an error is returned.
+	pub async fn send(&mut self, left: &EgmSensor, right: &EgmSensor) -> Result<(), SendError> {
+		InvalidMessageError::check_sensor_msg(left)?;
+		InvalidMessageError::check_sensor_msg(right)?;
+		let left_address = self.left_address.ok_or_else(no_remote_address)?;
+		let right_address = self.right_address.ok_or_else(no_remote_address)?;
+		tokio::try_join!(self.left.send_to(left, &left_address), self.right.send_to(right, &right_address))?;
+		Ok(())
+	}
+}
+
+fn no_remote_address() -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::NotConnected, "no message received from the robot controller yet")
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_dual_arm_round_trip() {
+	use crate::msg::EgmClock;
+	use assert2::assert;
+	use prost::Message;
+
+	let mut peer = DualArmPeer::bind("127.0.0.1:0", "127.0.0.1:0").await.unwrap();
+	let left_robot = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+	let right_robot = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+	let left_target = EgmSensor::joint_target(1, [1.0; 6], EgmClock::new(0, 0));
+	let right_target = EgmSensor::joint_target(1, [2.0; 6], EgmClock::new(0, 0));
+	assert!(let Err(SendError::Io(_)) = peer.send(&left_target, &right_target).await);
+
+	let state = EgmRobot::default().encode_to_vec();
+	left_robot.send_to(&state, peer.left().socket().local_addr().unwrap()).await.unwrap();
+	right_robot.send_to(&state, peer.right().socket().local_addr().unwrap()).await.unwrap();
+	peer.recv().await.unwrap();
+
+	peer.send(&left_target, &right_target).await.unwrap();
+	let mut buffer = [0; 1024];
+	let len = left_robot.recv(&mut buffer).await.unwrap();
+	assert!(EgmSensor::decode(&buffer[..len]).unwrap() == left_target);
+	let len = right_robot.recv(&mut buffer).await.unwrap();
+	assert!(EgmSensor::decode(&buffer[..len]).unwrap() == right_target);
+}
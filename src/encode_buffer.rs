@@ -0,0 +1,72 @@
+/// The minimum capacity of an [`EncodeBuffer`] once it allocates.
+///
+/// A typical [`crate::msg::EgmSensor`] message is well below this size,
+/// so most applications will never need to grow the buffer after the first message.
+const MIN_CAPACITY: usize = 256;
+
+/// Reusable buffer for encoding protocol buffers messages.
+///
+/// The buffer keeps its allocation between messages, so encoding a message in a control loop does not allocate.
+///
+/// When a message does not fit in the current capacity, the capacity grows to the next power of two
+/// that can hold the message, with a minimum of 256 bytes.
+/// The buffer never shrinks by itself.
+#[derive(Debug, Default)]
+pub struct EncodeBuffer {
+	data: Vec<u8>,
+}
+
+impl EncodeBuffer {
+	/// Create a new empty buffer.
+	///
+	/// This does not allocate until the first message is encoded.
+	pub fn new() -> Self {
+		Self { data: Vec::new() }
+	}
+
+	/// Encode a message into the buffer, replacing the previous contents.
+	///
+	/// Returns the encoded message as a byte slice.
+	pub fn encode(&mut self, msg: &impl prost::Message) -> Result<&[u8], prost::EncodeError> {
+		let encoded_len = msg.encoded_len();
+		self.data.clear();
+		if self.data.capacity() < encoded_len {
+			let capacity = encoded_len.next_power_of_two().max(MIN_CAPACITY);
+			self.data.reserve_exact(capacity);
+		}
+		msg.encode(&mut self.data)?;
+		Ok(&self.data)
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_encode() {
+	use assert2::assert;
+	use prost::Message;
+
+	let mut buffer = EncodeBuffer::new();
+	assert!(buffer.encode(&true).unwrap().len() == true.encoded_len());
+	assert!(buffer.encode(&10).unwrap().len() == 10.encoded_len());
+	assert!(buffer.encode(&String::from("aap noot mies")).unwrap().len() == String::from("aap noot mies").encoded_len());
+}
+
+#[cfg(test)]
+#[test]
+fn test_capacity_growth() {
+	use assert2::assert;
+
+	let mut buffer = EncodeBuffer::new();
+	assert!(buffer.data.capacity() == 0);
+
+	buffer.encode(&true).unwrap();
+	assert!(buffer.data.capacity() == MIN_CAPACITY);
+
+	// 300 bytes of string data plus tag and length prefix.
+	buffer.encode(&"x".repeat(300)).unwrap();
+	assert!(buffer.data.capacity() == 512);
+
+	// Smaller messages do not shrink the buffer.
+	buffer.encode(&true).unwrap();
+	assert!(buffer.data.capacity() == 512);
+}
@@ -1,4 +1,8 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
 /// Check if a whole buffer was successfully transferred.
+#[cfg(feature = "std")]
 pub fn check_transfer(transferred: usize, total: usize) -> Result<(), IncompleteTransmissionError> {
 	if transferred == total {
 		Ok(())
@@ -17,36 +21,60 @@ fn test_check_transfer() {
 }
 
 /// Error that may occur when receiving a message.
+#[cfg(feature = "std")]
 #[derive(Debug)]
 pub enum ReceiveError {
 	Io(std::io::Error),
-	Decode(prost::DecodeError),
+	Decode(crate::codec::DecodeError),
+	Rejected(MessageRejectedError),
 }
 
 /// Error that may occur when sending a message.
+#[cfg(feature = "std")]
 #[derive(Debug)]
 pub enum SendError {
 	InvalidMessage(InvalidMessageError),
 	Io(std::io::Error),
 	Encode(prost::EncodeError),
 	IncompleteTransmission(IncompleteTransmissionError),
+	Rejected(MessageRejectedError),
+	RetriesExhausted(RetriesExhaustedError),
 }
 
 /// Error indicating that a message is invalid.
 #[derive(Debug)]
 pub enum InvalidMessageError {
 	/// The message being sent contains one or more NaN values.
-	MessageHasNan,
+	MessageHasNan {
+		/// The paths of the fields containing NaN values, such as `planned.cartesian.pos.x`.
+		fields: Vec<String>,
+	},
+
+	/// The message contains a pose with a quaternion and Euler angles that describe different orientations.
+	///
+	/// The robot controller gives priority to the Euler angles in that case.
+	ConflictingOrientation {
+		/// The angle in degrees between the two orientations.
+		angle: f64,
+	},
+
+	/// The path correction along an axis exceeds the configured maximum.
+	PathCorrectionTooLarge {
+		/// The path of the offending field, such as `path_corr.pos.x`.
+		field: &'static str,
+
+		/// The correction in millimeters.
+		value: f64,
+
+		/// The maximum absolute correction in millimeters.
+		limit: f64,
+	},
 }
 
 impl InvalidMessageError {
 	/// Check if an [`msg::EgmSensor`] is invalid.
 	pub fn check_sensor_msg(message: &crate::msg::EgmSensor) -> Result<(), Self> {
-		if message.has_nan() {
-			Err(Self::MessageHasNan)
-		} else {
-			Ok(())
-		}
+		message.validate()
 	}
 }
 
@@ -60,73 +88,149 @@ pub struct IncompleteTransmissionError {
 	pub total: usize,
 }
 
+/// Error indicating that sending a message kept failing with transient errors until the retry policy gave up.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct RetriesExhaustedError {
+	/// The total number of attempts, including the first one.
+	pub attempts: u32,
+
+	/// The error of the last attempt.
+	pub error: std::io::Error,
+}
+
+/// Error indicating that a message was rejected by a middleware layer.
+#[derive(Clone, Debug)]
+pub struct MessageRejectedError {
+	/// The reason given by the middleware for rejecting the message.
+	pub reason: String,
+}
+
+impl MessageRejectedError {
+	/// Create a new error with the reason for rejecting the message.
+	pub fn new(reason: impl Into<String>) -> Self {
+		Self { reason: reason.into() }
+	}
+}
+
+/// Error indicating that a joint list does not have the expected number of joints.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct WrongNumberOfJointsError {
+	/// The expected number of joints.
+	pub expected: usize,
+
+	/// The actual number of joints.
+	pub actual: usize,
+}
+
+#[cfg(feature = "std")]
 impl From<std::io::Error> for ReceiveError {
 	fn from(other: std::io::Error) -> Self {
 		Self::Io(other)
 	}
 }
 
+#[cfg(feature = "std")]
 impl From<prost::DecodeError> for ReceiveError {
 	fn from(other: prost::DecodeError) -> Self {
+		Self::Decode(other.into())
+	}
+}
+
+#[cfg(feature = "std")]
+impl From<crate::codec::DecodeError> for ReceiveError {
+	fn from(other: crate::codec::DecodeError) -> Self {
 		Self::Decode(other)
 	}
 }
 
+#[cfg(feature = "std")]
+impl From<MessageRejectedError> for ReceiveError {
+	fn from(other: MessageRejectedError) -> Self {
+		Self::Rejected(other)
+	}
+}
+
+#[cfg(feature = "std")]
 impl From<InvalidMessageError> for SendError {
 	fn from(other: InvalidMessageError) -> Self {
 		Self::InvalidMessage(other)
 	}
 }
 
+#[cfg(feature = "std")]
 impl From<std::io::Error> for SendError {
 	fn from(other: std::io::Error) -> Self {
 		Self::Io(other)
 	}
 }
 
+#[cfg(feature = "std")]
 impl From<prost::EncodeError> for SendError {
 	fn from(other: prost::EncodeError) -> Self {
 		Self::Encode(other)
 	}
 }
 
+#[cfg(feature = "std")]
 impl From<IncompleteTransmissionError> for SendError {
 	fn from(other: IncompleteTransmissionError) -> Self {
 		Self::IncompleteTransmission(other)
 	}
 }
 
-impl std::fmt::Display for ReceiveError {
-	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+#[cfg(feature = "std")]
+impl From<MessageRejectedError> for SendError {
+	fn from(other: MessageRejectedError) -> Self {
+		Self::Rejected(other)
+	}
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for ReceiveError {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
 		match self {
 			Self::Io(e) => e.fmt(f),
 			Self::Decode(e) => e.fmt(f),
+			Self::Rejected(e) => e.fmt(f),
 		}
 	}
 }
 
-impl std::fmt::Display for SendError {
-	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+#[cfg(feature = "std")]
+impl core::fmt::Display for SendError {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
 		match self {
 			Self::InvalidMessage(e) => e.fmt(f),
 			Self::Io(e) => e.fmt(f),
 			Self::Encode(e) => e.fmt(f),
 			Self::IncompleteTransmission(e) => e.fmt(f),
+			Self::Rejected(e) => e.fmt(f),
+			Self::RetriesExhausted(e) => e.fmt(f),
 		}
 	}
 }
 
-impl std::fmt::Display for InvalidMessageError {
-	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+#[cfg(feature = "std")]
+impl core::fmt::Display for RetriesExhaustedError {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		write!(f, "failed to send message after {} attempts: {}", self.attempts, self.error)
+	}
+}
+
+impl core::fmt::Display for InvalidMessageError {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
 		match self {
-			Self::MessageHasNan => write!(f, "invalid message: message contains one or more NaN values"),
+			Self::MessageHasNan { fields } => write!(f, "invalid message: message contains NaN values in: {}", fields.join(", ")),
+			Self::ConflictingOrientation { angle } => write!(f, "invalid message: quaternion and Euler angles of pose differ by {} degrees", angle),
+			Self::PathCorrectionTooLarge { field, value, limit } => write!(f, "invalid message: path correction {} of {} mm exceeds the limit of {} mm", field, value, limit),
 		}
 	}
 }
 
-impl std::fmt::Display for IncompleteTransmissionError {
+impl core::fmt::Display for IncompleteTransmissionError {
 	#[rustfmt::skip]
-	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
 		write!(f, "incomplete transmission: transferred only {} of {} bytes",
 			self.transferred,
 			self.total
@@ -134,7 +238,29 @@ impl std::fmt::Display for IncompleteTransmissionError {
 	}
 }
 
+impl core::fmt::Display for WrongNumberOfJointsError {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		write!(f, "wrong number of joints, expected {}, got {}", self.expected, self.actual)
+	}
+}
+
+impl core::fmt::Display for MessageRejectedError {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		write!(f, "message rejected by middleware: {}", self.reason)
+	}
+}
+
+#[cfg(feature = "std")]
 impl std::error::Error for ReceiveError {}
+#[cfg(feature = "std")]
 impl std::error::Error for SendError {}
+#[cfg(feature = "std")]
+impl std::error::Error for RetriesExhaustedError {}
+#[cfg(feature = "std")]
 impl std::error::Error for InvalidMessageError {}
+#[cfg(feature = "std")]
 impl std::error::Error for IncompleteTransmissionError {}
+#[cfg(feature = "std")]
+impl std::error::Error for MessageRejectedError {}
+#[cfg(feature = "std")]
+impl std::error::Error for WrongNumberOfJointsError {}
@@ -0,0 +1,211 @@
+use std::time::Duration;
+
+use crate::msg::EgmRobot;
+use crate::session::{SessionEvent, SessionMonitor};
+
+/// The gains of an alpha-beta filter.
+///
+/// The alpha gain determines how much of the position error is corrected in each update,
+/// the beta gain determines how much of the position error is attributed to a wrong velocity.
+/// Both gains must be in the range `[0, 1]`, with higher gains tracking faster but smoothing less.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AlphaBeta {
+	/// The gain for the position correction.
+	pub alpha: f64,
+
+	/// The gain for the velocity correction.
+	pub beta: f64,
+}
+
+impl AlphaBeta {
+	/// Create filter gains from alpha and beta directly.
+	pub fn new(alpha: f64, beta: f64) -> Self {
+		Self { alpha, beta }
+	}
+
+	/// Compute the gains of the steady-state Kalman filter for a constant velocity model.
+	///
+	/// The `process_noise` is the standard deviation of the acceleration in degrees/s²,
+	/// the `measurement_noise` is the standard deviation of the position feedback in degrees,
+	/// and the `period` is the expected time between feedback messages.
+	pub fn from_noise(process_noise: f64, measurement_noise: f64, period: Duration) -> Self {
+		let dt = period.as_secs_f64();
+		let lambda = process_noise * dt * dt / measurement_noise;
+		let r = (4.0 + lambda - crate::float::sqrt(8.0 * lambda + lambda * lambda)) / 4.0;
+		let alpha = 1.0 - r * r;
+		let beta = 2.0 * (2.0 - alpha) - 4.0 * crate::float::sqrt(1.0 - alpha);
+		Self { alpha, beta }
+	}
+}
+
+impl Default for AlphaBeta {
+	fn default() -> Self {
+		Self { alpha: 0.5, beta: 0.1 }
+	}
+}
+
+/// The estimated state of a single joint.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct JointEstimate {
+	/// The estimated position in degrees.
+	pub position: f64,
+
+	/// The estimated velocity in degrees/s.
+	pub velocity: f64,
+}
+
+/// Estimate smoothed joint positions and velocities from position feedback.
+///
+/// EGM does not report joint velocities, and differentiating raw feedback samples amplifies the noise on the positions.
+/// This estimator runs an alpha-beta filter for every joint,
+/// which is equivalent to a steady-state Kalman filter for a constant velocity model when the gains are chosen with [`AlphaBeta::from_noise`].
+///
+/// The time step of each update is taken from the controller timestamps of the feedback,
+/// so lost or delayed messages are handled correctly.
+/// When a controller restart is detected or the number of joints changes, the estimator is reset automatically.
+#[derive(Clone, Debug)]
+pub struct JointEstimator {
+	gains: AlphaBeta,
+	time: Option<Duration>,
+	joints: Vec<JointEstimate>,
+	session: SessionMonitor,
+}
+
+impl JointEstimator {
+	/// Create a new estimator with the given filter gains.
+	pub fn new(gains: AlphaBeta) -> Self {
+		Self {
+			gains,
+			time: None,
+			joints: Vec::new(),
+			session: SessionMonitor::default(),
+		}
+	}
+
+	/// Get the filter gains.
+	pub fn gains(&self) -> AlphaBeta {
+		self.gains
+	}
+
+	/// Update the estimator with a new message from the robot.
+	///
+	/// Messages without a feedback time or joint feedback are ignored, as are messages that are not newer than the latest feedback.
+	/// Returns true if the message was used.
+	pub fn update(&mut self, state: &EgmRobot) -> bool {
+		let events = self.session.observe(state);
+		if events.iter().any(|event| matches!(event, SessionEvent::ControllerRestarted { .. })) {
+			self.time = None;
+			self.joints.clear();
+		}
+
+		match (state.feedback_time(), state.feedback_joints()) {
+			(Some(time), Some(joints)) => self.update_at(time.elapsed_since_epoch(), joints),
+			_ => false,
+		}
+	}
+
+	/// Update the estimator with joint positions in degrees, measured at a time of the controller clock.
+	///
+	/// Returns true if the measurement was used.
+	pub fn update_at(&mut self, time: Duration, positions: &[f64]) -> bool {
+		let dt = match self.time {
+			Some(previous) if time <= previous => return false,
+			Some(previous) if self.joints.len() == positions.len() => (time - previous).as_secs_f64(),
+			_ => {
+				self.time = Some(time);
+				self.joints = positions.iter().map(|&position| JointEstimate { position, velocity: 0.0 }).collect();
+				return true;
+			},
+		};
+
+		let AlphaBeta { alpha, beta } = self.gains;
+		for (joint, &measured) in self.joints.iter_mut().zip(positions) {
+			let predicted = joint.position + joint.velocity * dt;
+			let residual = measured - predicted;
+			joint.position = predicted + alpha * residual;
+			joint.velocity += beta * residual / dt;
+		}
+		self.time = Some(time);
+		true
+	}
+
+	/// Forget all feedback, for example after a controller restart.
+	pub fn reset(&mut self) {
+		self.time = None;
+		self.joints.clear();
+		self.session.reset();
+	}
+
+	/// Get the controller time of the latest estimate.
+	pub fn time(&self) -> Option<Duration> {
+		self.time
+	}
+
+	/// Get the estimated state of all joints.
+	///
+	/// Returns an empty slice if no feedback has been received yet.
+	pub fn joints(&self) -> &[JointEstimate] {
+		&self.joints
+	}
+
+	/// Get the estimated joint positions in degrees.
+	pub fn positions(&self) -> Vec<f64> {
+		self.joints.iter().map(|joint| joint.position).collect()
+	}
+
+	/// Get the estimated joint velocities in degrees/s.
+	pub fn velocities(&self) -> Vec<f64> {
+		self.joints.iter().map(|joint| joint.velocity).collect()
+	}
+
+	/// Predict the joint positions in degrees, `ahead` of the latest estimate.
+	pub fn predict(&self, ahead: Duration) -> Vec<f64> {
+		let ahead = ahead.as_secs_f64();
+		self.joints.iter().map(|joint| joint.position + joint.velocity * ahead).collect()
+	}
+}
+
+impl Default for JointEstimator {
+	fn default() -> Self {
+		Self::new(AlphaBeta::default())
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_joint_estimator() {
+	use crate::msg;
+	use assert2::assert;
+
+	let gains = AlphaBeta::from_noise(100.0, 0.01, Duration::from_millis(4));
+	assert!(gains.alpha > 0.0 && gains.alpha < 1.0);
+	assert!(gains.beta > 0.0 && gains.beta < gains.alpha);
+
+	// A joint moving at a constant 250 degrees/s with alternating measurement noise.
+	let mut estimator = JointEstimator::new(gains);
+	for i in 0..500u64 {
+		let noise = if i % 2 == 0 { 0.01 } else { -0.01 };
+		assert!(estimator.update_at(Duration::from_millis(4 * i), &[i as f64 + noise, 5.0]));
+	}
+	let velocities = estimator.velocities();
+	assert!((velocities[0] - 250.0).abs() < 1.0);
+	assert!(velocities[1].abs() < 1e-9);
+	assert!((estimator.positions()[0] - 499.0).abs() < 0.02);
+	assert!((estimator.predict(Duration::from_millis(4))[0] - 500.0).abs() < 0.05);
+
+	// Stale measurements are ignored.
+	assert!(!estimator.update_at(Duration::from_millis(4), &[0.0, 0.0]));
+
+	// A controller restart resets the estimator.
+	let state = |sec: u64, joint: f64| EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(vec![joint, 5.0])),
+			time: Some(msg::EgmClock::new(sec, 0)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	assert!(estimator.update(&state(10, 10.0)));
+	assert!(estimator.update(&state(0, 20.0)));
+	assert!(estimator.joints()[0] == JointEstimate { position: 20.0, velocity: 0.0 });
+}
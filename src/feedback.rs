@@ -0,0 +1,435 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::msg::{EgmClock, EgmPose, EgmQuaternion, EgmRobot};
+use crate::session::{SessionEvent, SessionMonitor};
+
+/// A snapshot of the feedback of the robot at one point in time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeedbackSample {
+	/// The feedback time according to the clock of the robot controller.
+	pub time: Duration,
+
+	/// The joint positions in degrees.
+	pub joints: Vec<f64>,
+
+	/// The external joint positions in degrees.
+	pub external_joints: Vec<f64>,
+
+	/// The pose of the tool, if the robot controller sent it.
+	pub pose: Option<EgmPose>,
+}
+
+impl FeedbackSample {
+	/// Create a sample from a message from the robot controller.
+	///
+	/// Returns `None` if the message has no feedback time.
+	pub fn from_message(msg: &EgmRobot) -> Option<Self> {
+		Some(Self {
+			time: msg.feedback_time()?.elapsed_since_epoch(),
+			joints: msg.feedback_joints().cloned().unwrap_or_default(),
+			external_joints: msg.feedback_extenal_joints().cloned().unwrap_or_default(),
+			pose: msg.feedback_pose().cloned(),
+		})
+	}
+}
+
+#[derive(Debug)]
+struct Inner {
+	samples: VecDeque<FeedbackSample>,
+	capacity: usize,
+	session: SessionMonitor,
+}
+
+/// Ring buffer holding the last feedback samples of the robot.
+///
+/// The buffer can be cloned cheaply to share it between the task receiving messages and other threads.
+/// All clones refer to the same samples.
+/// The samples are protected by a mutex that is only held for short copies.
+///
+/// When a controller restart is detected, the buffer is cleared, since the old samples use a different clock.
+#[derive(Clone, Debug)]
+pub struct FeedbackBuffer {
+	inner: Arc<Mutex<Inner>>,
+}
+
+impl FeedbackBuffer {
+	/// Create a new buffer that holds up to `capacity` samples.
+	///
+	/// A capacity of 0 is treated as 1.
+	pub fn new(capacity: usize) -> Self {
+		let capacity = capacity.max(1);
+		Self {
+			inner: Arc::new(Mutex::new(Inner {
+				samples: VecDeque::with_capacity(capacity),
+				capacity,
+				session: SessionMonitor::default(),
+			})),
+		}
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Inner> {
+		// The buffer is always left in a consistent state, so a poisoned lock can safely be used.
+		self.inner.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	/// Add the feedback of a message from the robot controller.
+	///
+	/// Messages without a feedback time are ignored, as are messages that are not newer than the latest sample.
+	/// Returns true if the message was added.
+	pub fn push(&self, msg: &EgmRobot) -> bool {
+		let mut inner = self.lock();
+		let events = inner.session.observe(msg);
+		if events.iter().any(|event| matches!(event, SessionEvent::ControllerRestarted { .. })) {
+			inner.samples.clear();
+		}
+
+		let sample = match FeedbackSample::from_message(msg) {
+			Some(sample) => sample,
+			None => return false,
+		};
+		if let Some(latest) = inner.samples.back() {
+			if sample.time <= latest.time {
+				return false;
+			}
+		}
+		if inner.samples.len() == inner.capacity {
+			inner.samples.pop_front();
+		}
+		inner.samples.push_back(sample);
+		true
+	}
+
+	/// Get the maximum number of samples in the buffer.
+	pub fn capacity(&self) -> usize {
+		self.lock().capacity
+	}
+
+	/// Get the number of samples in the buffer.
+	pub fn len(&self) -> usize {
+		self.lock().samples.len()
+	}
+
+	/// Check if the buffer is empty.
+	pub fn is_empty(&self) -> bool {
+		self.lock().samples.is_empty()
+	}
+
+	/// Remove all samples from the buffer.
+	pub fn clear(&self) {
+		let mut inner = self.lock();
+		inner.samples.clear();
+		inner.session.reset();
+	}
+
+	/// Get the latest sample.
+	pub fn latest(&self) -> Option<FeedbackSample> {
+		self.lock().samples.back().cloned()
+	}
+
+	/// Get a copy of all samples in the buffer, from oldest to newest.
+	pub fn snapshot(&self) -> Vec<FeedbackSample> {
+		self.lock().samples.iter().cloned().collect()
+	}
+
+	/// Get the joint positions of all samples in the buffer as an array with one row per sample and one column per joint, from oldest to newest.
+	///
+	/// Fails if the samples do not all have the same number of joints.
+	#[cfg(feature = "ndarray")]
+	pub fn joint_array(&self) -> Result<ndarray::Array2<f64>, crate::WrongNumberOfJointsError> {
+		let inner = self.lock();
+		crate::ndarray::stack_rows(inner.samples.iter().map(|sample| sample.joints.as_slice()))
+	}
+
+	/// Get the joint positions at a time, by linear interpolation between the surrounding samples.
+	///
+	/// Returns `None` if the time is outside the range of the buffer,
+	/// or if the surrounding samples have a different number of joints.
+	pub fn joints_at(&self, time: Duration) -> Option<Vec<f64>> {
+		let inner = self.lock();
+		let (before, after, fraction) = surrounding(&inner.samples, time)?;
+		if before.joints.len() != after.joints.len() {
+			return None;
+		}
+		Some(lerp_values(&before.joints, &after.joints, fraction))
+	}
+
+	/// Get the feedback at a time, by interpolation between the surrounding samples.
+	///
+	/// Joint positions and the tool position are interpolated linearly,
+	/// the tool orientation is interpolated with spherical linear interpolation.
+	/// This can be used to find the state of the robot at the moment an external sensor captured data.
+	///
+	/// Returns `None` if the time is outside the range of the buffer,
+	/// or if the surrounding samples do not contain the same fields.
+	pub fn feedback_at(&self, time: EgmClock) -> Option<FeedbackSample> {
+		let time = time.elapsed_since_epoch();
+		let inner = self.lock();
+		let (before, after, fraction) = surrounding(&inner.samples, time)?;
+		if before.joints.len() != after.joints.len() || before.external_joints.len() != after.external_joints.len() {
+			return None;
+		}
+		let pose = match (&before.pose, &after.pose) {
+			(Some(a), Some(b)) => Some(lerp_pose(a, b, fraction)),
+			(None, None) => None,
+			_ => return None,
+		};
+		Some(FeedbackSample {
+			time,
+			joints: lerp_values(&before.joints, &after.joints, fraction),
+			external_joints: lerp_values(&before.external_joints, &after.external_joints, fraction),
+			pose,
+		})
+	}
+
+	/// Estimate the velocity of the tool from the pose feedback of the last `window` samples.
+	///
+	/// The velocity is computed between each pair of consecutive samples in the window.
+	/// Pairs with a linear or angular speed more than three median absolute deviations away from the median are rejected as outliers,
+	/// so a single bad sample or a hiccup of the controller clock does not produce a velocity spike.
+	/// The remaining velocities are averaged.
+	///
+	/// Returns `None` if the window contains less than two samples with a pose.
+	pub fn tcp_velocity(&self, window: usize) -> Option<TcpVelocity> {
+		let inner = self.lock();
+		let skip = inner.samples.len().saturating_sub(window);
+		let samples: Vec<_> = inner.samples.iter().skip(skip).filter_map(|sample| Some((sample.time, sample.pose.as_ref()?))).collect();
+		let velocities: Vec<TcpVelocity> = samples.windows(2).filter_map(|pair| velocity_between(pair[0].1, pair[0].0, pair[1].1, pair[1].0)).collect();
+		if velocities.is_empty() {
+			return None;
+		}
+
+		let linear_inlier = inliers(velocities.iter().map(TcpVelocity::linear_speed).collect());
+		let angular_inlier = inliers(velocities.iter().map(TcpVelocity::angular_speed).collect());
+		let kept: Vec<_> = velocities
+			.iter()
+			.zip(linear_inlier.iter().zip(&angular_inlier))
+			.filter(|(_, (&linear, &angular))| linear && angular)
+			.map(|(velocity, _)| velocity)
+			.collect();
+		if kept.is_empty() {
+			return velocities.last().copied();
+		}
+
+		let count = kept.len() as f64;
+		let mut mean = TcpVelocity {
+			linear: [0.0; 3],
+			angular: [0.0; 3],
+		};
+		for velocity in kept {
+			for i in 0..3 {
+				mean.linear[i] += velocity.linear[i] / count;
+				mean.angular[i] += velocity.angular[i] / count;
+			}
+		}
+		Some(mean)
+	}
+}
+
+/// The estimated velocity of the tool center point.
+///
+/// Both vectors are expressed in the base frame of the robot.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TcpVelocity {
+	/// The linear velocity in mm/s.
+	pub linear: [f64; 3],
+
+	/// The angular velocity in degrees/s, as a rotation vector.
+	pub angular: [f64; 3],
+}
+
+impl TcpVelocity {
+	/// Compute the average velocity between two poses and their feedback times.
+	///
+	/// Returns `None` if the poses have no position or orientation, or if the second pose is not newer than the first.
+	pub fn between(a: &EgmPose, a_time: EgmClock, b: &EgmPose, b_time: EgmClock) -> Option<Self> {
+		velocity_between(a, a_time.elapsed_since_epoch(), b, b_time.elapsed_since_epoch())
+	}
+
+	/// Get the linear speed in mm/s.
+	pub fn linear_speed(&self) -> f64 {
+		norm(&self.linear)
+	}
+
+	/// Get the angular speed in degrees/s.
+	pub fn angular_speed(&self) -> f64 {
+		norm(&self.angular)
+	}
+}
+
+fn velocity_between(a: &EgmPose, a_time: Duration, b: &EgmPose, b_time: Duration) -> Option<TcpVelocity> {
+	let dt = b_time.checked_sub(a_time).filter(|dt| !dt.is_zero())?.as_secs_f64();
+	let a_pos = a.pos?.as_mm();
+	let b_pos = b.pos?.as_mm();
+	let [w1, x1, y1, z1] = a.orient?.as_wxyz();
+	let [w2, x2, y2, z2] = b.orient?.as_wxyz();
+
+	// The rotation from a to b in the base frame is b * conjugate(a).
+	let mut w = w2 * w1 + x2 * x1 + y2 * y1 + z2 * z1;
+	let mut axis = [
+		-w2 * x1 + x2 * w1 - y2 * z1 + z2 * y1,
+		-w2 * y1 + x2 * z1 + y2 * w1 - z2 * x1,
+		-w2 * z1 - x2 * y1 + y2 * x1 + z2 * w1,
+	];
+	if w < 0.0 {
+		w = -w;
+		axis.iter_mut().for_each(|x| *x = -*x);
+	}
+	let sin_half = norm(&axis);
+	let angle = 2.0 * sin_half.atan2(w);
+	let scale = if sin_half > 0.0 { angle.to_degrees() / sin_half / dt } else { 0.0 };
+
+	Some(TcpVelocity {
+		linear: [(b_pos[0] - a_pos[0]) / dt, (b_pos[1] - a_pos[1]) / dt, (b_pos[2] - a_pos[2]) / dt],
+		angular: [axis[0] * scale, axis[1] * scale, axis[2] * scale],
+	})
+}
+
+fn norm(vector: &[f64; 3]) -> f64 {
+	(vector[0] * vector[0] + vector[1] * vector[1] + vector[2] * vector[2]).sqrt()
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+	values.sort_by(|a, b| a.total_cmp(b));
+	let middle = values.len() / 2;
+	if values.len().is_multiple_of(2) {
+		(values[middle - 1] + values[middle]) / 2.0
+	} else {
+		values[middle]
+	}
+}
+
+/// Check which values are within three median absolute deviations of the median.
+fn inliers(values: Vec<f64>) -> Vec<bool> {
+	let center = median(values.clone());
+	let deviation = median(values.iter().map(|x| (x - center).abs()).collect());
+	values.iter().map(|x| (x - center).abs() <= 3.0 * deviation).collect()
+}
+
+pub(crate) fn lerp_values(a: &[f64], b: &[f64], fraction: f64) -> Vec<f64> {
+	a.iter().zip(b).map(|(a, b)| a + (b - a) * fraction).collect()
+}
+
+pub(crate) fn lerp_pose(a: &EgmPose, b: &EgmPose, fraction: f64) -> EgmPose {
+	let position = lerp_values(&a.pos.unwrap_or_default().as_mm(), &b.pos.unwrap_or_default().as_mm(), fraction);
+	let a = a.orient.unwrap_or(EgmQuaternion::IDENTITY);
+	let b = b.orient.unwrap_or(EgmQuaternion::IDENTITY);
+	EgmPose::new([position[0], position[1], position[2]], a.slerp(&b, fraction))
+}
+
+/// Find the samples surrounding a time and the interpolation fraction between them.
+fn surrounding(samples: &VecDeque<FeedbackSample>, time: Duration) -> Option<(&FeedbackSample, &FeedbackSample, f64)> {
+	let index = samples.iter().position(|sample| sample.time >= time)?;
+	let after = &samples[index];
+	if after.time == time {
+		return Some((after, after, 0.0));
+	}
+	let before = samples.get(index.checked_sub(1)?)?;
+	let fraction = (time - before.time).as_secs_f64() / (after.time - before.time).as_secs_f64();
+	Some((before, after, fraction))
+}
+
+#[cfg(test)]
+#[test]
+fn test_feedback_buffer() {
+	use crate::msg;
+	use assert2::assert;
+
+	let state = |msec: u64, joint: f64| EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(vec![joint, 0.0])),
+			time: Some(msg::EgmClock::new(1, msec * 1000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let ms = |msec: u64| Duration::from_secs(1) + Duration::from_millis(msec);
+
+	let buffer = FeedbackBuffer::new(3);
+	let shared = buffer.clone();
+	assert!(buffer.push(&state(0, 0.0)));
+	assert!(buffer.push(&state(4, 4.0)));
+	assert!(!buffer.push(&state(4, 5.0)));
+	assert!(buffer.push(&state(8, 6.0)));
+	assert!(buffer.push(&state(12, 10.0)));
+	assert!(shared.len() == 3);
+	assert!(shared.latest().unwrap().joints == [10.0, 0.0]);
+
+	// The oldest sample was dropped.
+	assert!(shared.joints_at(ms(2)).is_none());
+	assert!(shared.joints_at(ms(4)) == Some(vec![4.0, 0.0]));
+	assert!(shared.joints_at(ms(6)) == Some(vec![5.0, 0.0]));
+	assert!(shared.joints_at(ms(11)) == Some(vec![9.0, 0.0]));
+	assert!(shared.joints_at(ms(13)).is_none());
+}
+
+#[cfg(test)]
+#[test]
+fn test_feedback_at() {
+	use crate::msg;
+	use assert2::assert;
+
+	let state = |msec: u64, x: f64, orient: EgmQuaternion| EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(vec![x])),
+			cartesian: Some(EgmPose::new([x, 0.0, 0.0], orient)),
+			time: Some(EgmClock::new(0, msec * 1000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+
+	let rotated = msg::EgmEuler::from_xyz_degrees(0.0, 0.0, 90.0).to_quaternion();
+	let buffer = FeedbackBuffer::new(8);
+	buffer.push(&state(0, 0.0, EgmQuaternion::IDENTITY));
+	buffer.push(&state(4, 8.0, rotated));
+
+	let sample = buffer.feedback_at(EgmClock::new(0, 1000)).unwrap();
+	assert!(sample.time == Duration::from_millis(1));
+	assert!(sample.joints == [2.0]);
+	let pose = sample.pose.unwrap();
+	assert!(pose.pos.unwrap().as_mm() == [2.0, 0.0, 0.0]);
+	let expected = msg::EgmEuler::from_xyz_degrees(0.0, 0.0, 22.5).to_quaternion();
+	assert!(pose.orient.unwrap().angle_to(&expected) < 1e-6);
+
+	assert!(buffer.feedback_at(EgmClock::new(0, 5000)).is_none());
+}
+
+#[cfg(test)]
+#[test]
+fn test_tcp_velocity() {
+	use crate::msg;
+	use assert2::assert;
+
+	let state = |i: u64, x: f64| EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			cartesian: Some(EgmPose::new([x, 0.0, 0.0], msg::EgmEuler::from_xyz_degrees(0.0, 0.0, i as f64).to_quaternion())),
+			time: Some(EgmClock::new(0, i * 4_000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+
+	let buffer = FeedbackBuffer::new(16);
+	assert!(buffer.tcp_velocity(8).is_none());
+	for i in 0..10 {
+		// Sample 7 has a glitch in the position.
+		let glitch = if i == 7 { 5.0 } else { 0.0 };
+		buffer.push(&state(i, i as f64 + glitch));
+	}
+
+	let velocity = buffer.tcp_velocity(8).unwrap();
+	assert!((velocity.linear[0] - 250.0).abs() < 1e-6);
+	assert!(velocity.linear[1] == 0.0);
+	assert!(velocity.angular[0].abs() < 1e-6);
+	assert!((velocity.angular[2] - 250.0).abs() < 1e-6);
+
+	let a = EgmPose::new([0.0, 0.0, 0.0], EgmQuaternion::IDENTITY);
+	let b = EgmPose::new([0.0, 2.0, 0.0], msg::EgmEuler::from_xyz_degrees(-90.0, 0.0, 0.0).to_quaternion());
+	let velocity = TcpVelocity::between(&a, EgmClock::new(1, 0), &b, EgmClock::new(2, 0)).unwrap();
+	assert!(velocity.linear == [0.0, 2.0, 0.0]);
+	assert!((velocity.angular[0] + 90.0).abs() < 1e-6);
+	assert!((velocity.angular_speed() - 90.0).abs() < 1e-6);
+	assert!(TcpVelocity::between(&a, EgmClock::new(2, 0), &b, EgmClock::new(2, 0)).is_none());
+}
@@ -0,0 +1,182 @@
+use crate::msg::EgmRobot;
+
+/// A field of the messages from the robot controller that can be mapped to an external variable.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FeedbackField {
+	/// The sequence number of the message.
+	SequenceNumber,
+
+	/// The feedback time in seconds since the epoch of the controller clock.
+	FeedbackTime,
+
+	/// The feedback position of a joint in degrees, by index.
+	Joint(usize),
+
+	/// The feedback position of an external joint in degrees, by index.
+	ExternalJoint(usize),
+
+	/// The feedback position of the tool along the X axis in millimeters.
+	PositionX,
+
+	/// The feedback position of the tool along the Y axis in millimeters.
+	PositionY,
+
+	/// The feedback position of the tool along the Z axis in millimeters.
+	PositionZ,
+
+	/// A component of the feedback orientation quaternion of the tool, in w, x, y, z order.
+	Orientation(usize),
+
+	/// A component of the measured force, by index.
+	Force(usize),
+
+	/// True if the motors are on.
+	MotorsEnabled,
+
+	/// True if the RAPID program is running.
+	RapidRunning,
+
+	/// True if the controller reports an MCI error.
+	MciError,
+}
+
+/// The value of a feedback field.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FieldValue {
+	/// A floating point value.
+	Double(f64),
+
+	/// An unsigned integer value.
+	UInt32(u32),
+
+	/// A boolean value.
+	Boolean(bool),
+}
+
+impl FeedbackField {
+	/// Get the value of the field from a message, or `None` if the message does not contain the field.
+	pub fn value(&self, state: &EgmRobot) -> Option<FieldValue> {
+		let position = || state.feedback_pose()?.pos;
+		match *self {
+			Self::SequenceNumber => state.sequence_number().map(FieldValue::UInt32),
+			Self::FeedbackTime => state.feedback_time().map(|time| FieldValue::Double(time.elapsed_since_epoch().as_secs_f64())),
+			Self::Joint(index) => state.feedback_joints()?.get(index).copied().map(FieldValue::Double),
+			Self::ExternalJoint(index) => state.feedback_extenal_joints()?.get(index).copied().map(FieldValue::Double),
+			Self::PositionX => position().map(|pos| FieldValue::Double(pos.x)),
+			Self::PositionY => position().map(|pos| FieldValue::Double(pos.y)),
+			Self::PositionZ => position().map(|pos| FieldValue::Double(pos.z)),
+			Self::Orientation(index) => state.feedback_pose()?.orient?.as_wxyz().get(index).copied().map(FieldValue::Double),
+			Self::Force(index) => state.measured_force()?.get(index).copied().map(FieldValue::Double),
+			Self::MotorsEnabled => state.motors_enabled().map(FieldValue::Boolean),
+			Self::RapidRunning => state.rapid_running().map(FieldValue::Boolean),
+			Self::MciError => state.mci_error().map(FieldValue::Boolean),
+		}
+	}
+}
+
+impl std::fmt::Display for FeedbackField {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::SequenceNumber => write!(f, "SequenceNumber"),
+			Self::FeedbackTime => write!(f, "FeedbackTime"),
+			Self::Joint(index) => write!(f, "Joint{}", index),
+			Self::ExternalJoint(index) => write!(f, "ExternalJoint{}", index),
+			Self::PositionX => write!(f, "PositionX"),
+			Self::PositionY => write!(f, "PositionY"),
+			Self::PositionZ => write!(f, "PositionZ"),
+			Self::Orientation(index) => write!(f, "Orientation{}", index),
+			Self::Force(index) => write!(f, "Force{}", index),
+			Self::MotorsEnabled => write!(f, "MotorsEnabled"),
+			Self::RapidRunning => write!(f, "RapidRunning"),
+			Self::MciError => write!(f, "MciError"),
+		}
+	}
+}
+
+/// Maps selected feedback fields to the variables of an external system, such as the nodes of an OPC UA server.
+///
+/// The mapping is independent of the server implementation:
+/// the node identifier `N` can be any type, such as the node ID type of an OPC UA server crate or a plain string.
+/// Call [`FieldMapping::values`] with every message and write the values to the variables,
+/// so plant SCADA systems can observe the EGM session without custom code per field.
+///
+/// The value types are chosen to match the OPC UA built-in types `Double`, `UInt32` and `Boolean`.
+/// With the `opcua` feature, a mapping to OPC UA node IDs can create and write the variables of an `async-opcua` server directly.
+#[derive(Clone, Debug)]
+pub struct FieldMapping<N> {
+	fields: Vec<(FeedbackField, N)>,
+}
+
+impl<N> FieldMapping<N> {
+	/// Create an empty mapping.
+	pub fn new() -> Self {
+		Self { fields: Vec::new() }
+	}
+
+	/// Add a field mapped to a node.
+	pub fn with(mut self, field: FeedbackField, node: N) -> Self {
+		self.fields.push((field, node));
+		self
+	}
+
+	/// Add a field mapped to a node.
+	pub fn insert(&mut self, field: FeedbackField, node: N) {
+		self.fields.push((field, node));
+	}
+
+	/// Get the mapped fields and their nodes.
+	pub fn fields(&self) -> &[(FeedbackField, N)] {
+		&self.fields
+	}
+
+	/// Get the values of all mapped fields present in a message, with their nodes.
+	///
+	/// Fields that are absent from the message are skipped, so their variables keep the last known value.
+	pub fn values(&self, state: &EgmRobot) -> Vec<(&N, FieldValue)> {
+		self.fields.iter().filter_map(|(field, node)| Some((node, field.value(state)?))).collect()
+	}
+}
+
+impl<N> Default for FieldMapping<N> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_field_mapping() {
+	use crate::msg;
+	use assert2::assert;
+
+	let mapping = FieldMapping::new()
+		.with(FeedbackField::SequenceNumber, "ns=2;s=Robot.Seqno")
+		.with(FeedbackField::Joint(1), "ns=2;s=Robot.J2")
+		.with(FeedbackField::PositionZ, "ns=2;s=Robot.Z")
+		.with(FeedbackField::Orientation(0), "ns=2;s=Robot.Qw")
+		.with(FeedbackField::Force(0), "ns=2;s=Robot.Fx")
+		.with(FeedbackField::MotorsEnabled, "ns=2;s=Robot.MotorsOn");
+
+	let state = EgmRobot {
+		header: Some(msg::EgmHeader::data(12, 0)),
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(vec![1.0, 2.0])),
+			cartesian: Some(msg::EgmPose::new([1.0, 2.0, 3.0], msg::EgmQuaternion::IDENTITY)),
+			..Default::default()
+		}),
+		motor_state: Some(msg::EgmMotorState {
+			state: msg::egm_motor_state::MotorStateType::MotorsOn as i32,
+		}),
+		..Default::default()
+	};
+	assert!(
+		mapping.values(&state)
+			== [
+				(&"ns=2;s=Robot.Seqno", FieldValue::UInt32(12)),
+				(&"ns=2;s=Robot.J2", FieldValue::Double(2.0)),
+				(&"ns=2;s=Robot.Z", FieldValue::Double(3.0)),
+				(&"ns=2;s=Robot.Qw", FieldValue::Double(1.0)),
+				(&"ns=2;s=Robot.MotorsOn", FieldValue::Boolean(true)),
+			]
+	);
+}
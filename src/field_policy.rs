@@ -0,0 +1,185 @@
+use std::borrow::Cow;
+
+use crate::msg::{EgmPose, EgmRobot};
+
+/// An optional part of the messages from the robot controller.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OptionalField {
+	/// The measured force, only sent when a force sensor is configured.
+	MeasuredForce,
+
+	/// The external joints, only sent when the mechanical unit has external axes.
+	ExternalJoints,
+
+	/// The planned position, which some controller versions and configurations omit.
+	Planned,
+}
+
+/// What to do when the controller omits an optional field.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AbsencePolicy {
+	/// Fail with a [`MissingFieldError`].
+	Error,
+
+	/// Substitute a default value.
+	///
+	/// The measured force defaults to six zeros, the external joints to an empty list,
+	/// and the planned position to the feedback position.
+	Substitute,
+
+	/// Return `None`, so features depending on the field are skipped.
+	Skip,
+}
+
+/// Error indicating that the controller omitted a field that is required by the [`FieldPolicy`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MissingFieldError {
+	/// The missing field.
+	pub field: OptionalField,
+}
+
+/// Policy for optional fields that the controller may omit, depending on its configuration.
+///
+/// Not every setup has a force sensor, external axes or planned data.
+/// Instead of handling `Option` values throughout the application,
+/// decide once per field whether its absence is an error, should be substituted with a default, or skips the feature using it.
+/// Then read the fields through the accessors of the policy.
+///
+/// By default, all fields use [`AbsencePolicy::Skip`], which matches the accessors of [`EgmRobot`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FieldPolicy {
+	measured_force: AbsencePolicy,
+	external_joints: AbsencePolicy,
+	planned: AbsencePolicy,
+}
+
+impl FieldPolicy {
+	/// Create a policy that applies the same policy to all fields.
+	pub fn all(policy: AbsencePolicy) -> Self {
+		Self {
+			measured_force: policy,
+			external_joints: policy,
+			planned: policy,
+		}
+	}
+
+	/// Set the policy for the measured force.
+	pub fn with_measured_force(mut self, policy: AbsencePolicy) -> Self {
+		self.measured_force = policy;
+		self
+	}
+
+	/// Set the policy for the external joints.
+	pub fn with_external_joints(mut self, policy: AbsencePolicy) -> Self {
+		self.external_joints = policy;
+		self
+	}
+
+	/// Set the policy for the planned position.
+	pub fn with_planned(mut self, policy: AbsencePolicy) -> Self {
+		self.planned = policy;
+		self
+	}
+
+	/// Get the policy for a field.
+	pub fn policy(&self, field: OptionalField) -> AbsencePolicy {
+		match field {
+			OptionalField::MeasuredForce => self.measured_force,
+			OptionalField::ExternalJoints => self.external_joints,
+			OptionalField::Planned => self.planned,
+		}
+	}
+
+	/// Get the measured force and torque.
+	pub fn measured_force<'a>(&self, state: &'a EgmRobot) -> Result<Option<Cow<'a, [f64]>>, MissingFieldError> {
+		let value = state.measured_force().map(|x| Cow::Borrowed(x.as_slice()));
+		self.apply(OptionalField::MeasuredForce, value, || Some(Cow::Owned(vec![0.0; 6])))
+	}
+
+	/// Get the feedback positions of the external joints in degrees.
+	pub fn external_joints<'a>(&self, state: &'a EgmRobot) -> Result<Option<Cow<'a, [f64]>>, MissingFieldError> {
+		let value = state.feedback_extenal_joints().map(|x| Cow::Borrowed(x.as_slice()));
+		self.apply(OptionalField::ExternalJoints, value, || Some(Cow::Owned(Vec::new())))
+	}
+
+	/// Get the planned joint positions in degrees.
+	///
+	/// The substitute is the feedback joint positions, if present.
+	pub fn planned_joints<'a>(&self, state: &'a EgmRobot) -> Result<Option<&'a [f64]>, MissingFieldError> {
+		let value = state.planned_joints().map(|x| x.as_slice());
+		self.apply(OptionalField::Planned, value, || state.feedback_joints().map(|x| x.as_slice()))
+	}
+
+	/// Get the planned pose.
+	///
+	/// The substitute is the feedback pose, if present.
+	pub fn planned_pose<'a>(&self, state: &'a EgmRobot) -> Result<Option<&'a EgmPose>, MissingFieldError> {
+		self.apply(OptionalField::Planned, state.planned_pose(), || state.feedback_pose())
+	}
+
+	fn apply<T>(&self, field: OptionalField, value: Option<T>, substitute: impl FnOnce() -> Option<T>) -> Result<Option<T>, MissingFieldError> {
+		if value.is_some() {
+			return Ok(value);
+		}
+		match self.policy(field) {
+			AbsencePolicy::Error => Err(MissingFieldError { field }),
+			AbsencePolicy::Substitute => Ok(substitute()),
+			AbsencePolicy::Skip => Ok(None),
+		}
+	}
+}
+
+impl Default for FieldPolicy {
+	fn default() -> Self {
+		Self::all(AbsencePolicy::Skip)
+	}
+}
+
+impl std::fmt::Display for OptionalField {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::MeasuredForce => write!(f, "measured force"),
+			Self::ExternalJoints => write!(f, "external joints"),
+			Self::Planned => write!(f, "planned position"),
+		}
+	}
+}
+
+impl std::fmt::Display for MissingFieldError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "the robot controller did not send the {}", self.field)
+	}
+}
+
+impl std::error::Error for MissingFieldError {}
+
+#[cfg(test)]
+#[test]
+fn test_field_policy() {
+	use crate::msg;
+	use assert2::{assert, let_assert};
+
+	let state = EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(vec![1.0, 2.0])),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+
+	let policy = FieldPolicy::default();
+	assert!(let Ok(None) = policy.measured_force(&state));
+	assert!(let Ok(None) = policy.planned_joints(&state));
+
+	let policy = FieldPolicy::all(AbsencePolicy::Substitute).with_external_joints(AbsencePolicy::Error);
+	let_assert!(Ok(Some(force)) = policy.measured_force(&state));
+	assert!(*force == [0.0; 6]);
+	assert!(policy.planned_joints(&state) == Ok(Some(&[1.0, 2.0][..])));
+	assert!(policy.planned_pose(&state) == Ok(None));
+	assert!(policy.external_joints(&state) == Err(MissingFieldError { field: OptionalField::ExternalJoints }));
+
+	// Present fields are returned regardless of the policy.
+	let mut state = state;
+	state.planned = Some(msg::EgmPlanned::joints(vec![3.0, 4.0], msg::EgmClock::new(0, 0)));
+	assert!(policy.planned_joints(&state) == Ok(Some(&[3.0, 4.0][..])));
+}
@@ -0,0 +1,75 @@
+//! Floating point functions that work with and without the standard library.
+//!
+//! Without the standard library, the functions from `libm` are used.
+
+#[cfg(feature = "std")]
+mod imp {
+	pub fn abs(x: f64) -> f64 {
+		x.abs()
+	}
+
+	pub fn sqrt(x: f64) -> f64 {
+		x.sqrt()
+	}
+
+	pub fn round(x: f64) -> f64 {
+		x.round()
+	}
+
+	pub fn floor(x: f64) -> f64 {
+		x.floor()
+	}
+
+	pub fn ceil(x: f64) -> f64 {
+		x.ceil()
+	}
+
+	pub fn sin(x: f64) -> f64 {
+		x.sin()
+	}
+
+	pub fn acos(x: f64) -> f64 {
+		x.acos()
+	}
+
+	pub fn sin_cos(x: f64) -> (f64, f64) {
+		x.sin_cos()
+	}
+}
+
+#[cfg(not(feature = "std"))]
+mod imp {
+	pub fn abs(x: f64) -> f64 {
+		libm::fabs(x)
+	}
+
+	pub fn sqrt(x: f64) -> f64 {
+		libm::sqrt(x)
+	}
+
+	pub fn round(x: f64) -> f64 {
+		libm::round(x)
+	}
+
+	pub fn floor(x: f64) -> f64 {
+		libm::floor(x)
+	}
+
+	pub fn ceil(x: f64) -> f64 {
+		libm::ceil(x)
+	}
+
+	pub fn sin(x: f64) -> f64 {
+		libm::sin(x)
+	}
+
+	pub fn acos(x: f64) -> f64 {
+		libm::acos(x)
+	}
+
+	pub fn sin_cos(x: f64) -> (f64, f64) {
+		libm::sincos(x)
+	}
+}
+
+pub use imp::*;
@@ -0,0 +1,616 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::msg::EgmRobot;
+
+/// The WebSocket subprotocol of the Foxglove WebSocket protocol.
+pub const SUBPROTOCOL: &str = "foxglove.websocket.v1";
+
+/// The topic of the complete messages from the robot controller.
+pub const ROBOT_TOPIC: &str = "/egm/robot";
+
+/// The topic of the feedback pose of the robot, as `foxglove.PoseInFrame`.
+pub const POSE_TOPIC: &str = "/egm/pose";
+
+/// The topic of the feedback joints of the robot in degrees.
+pub const JOINTS_TOPIC: &str = "/egm/joints";
+
+/// The topic of the measured force and torque.
+pub const FORCE_TOPIC: &str = "/egm/force";
+
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+const MESSAGE_DATA: u8 = 0x01;
+const MAX_CLIENT_FRAME_LEN: u64 = 1 << 20;
+
+/// The maximum number of frames queued for a client before it is disconnected.
+const CLIENT_QUEUE_LEN: usize = 64;
+
+/// The maximum time to write a frame to a client.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The maximum time and size of the HTTP request of the WebSocket handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_HANDSHAKE_LEN: u64 = 16 * 1024;
+
+/// The channels advertised to clients: id, topic, schema name and JSON schema.
+static CHANNELS: [(u32, &str, &str, &str); 4] = [
+	(1, ROBOT_TOPIC, "abb.egm.EgmRobot", r#"{"type":"object"}"#),
+	(
+		2,
+		POSE_TOPIC,
+		"foxglove.PoseInFrame",
+		r#"{"type":"object","properties":{"timestamp":{"type":"object","properties":{"sec":{"type":"integer"},"nsec":{"type":"integer"}}},"frame_id":{"type":"string"},"pose":{"type":"object","properties":{"position":{"type":"object","properties":{"x":{"type":"number"},"y":{"type":"number"},"z":{"type":"number"}}},"orientation":{"type":"object","properties":{"x":{"type":"number"},"y":{"type":"number"},"z":{"type":"number"},"w":{"type":"number"}}}}}}}"#,
+	),
+	(3, JOINTS_TOPIC, "abb.egm.Joints", r#"{"type":"object","properties":{"positions":{"type":"array","items":{"type":"number"}}}}"#),
+	(
+		4,
+		FORCE_TOPIC,
+		"abb.egm.Wrench",
+		r#"{"type":"object","properties":{"force":{"type":"array","items":{"type":"number"}},"torque":{"type":"array","items":{"type":"number"}}}}"#,
+	),
+];
+
+/// Server that republishes live robot feedback over the Foxglove WebSocket protocol.
+///
+/// Connect to the server from Foxglove with the "Foxglove WebSocket" data source to visualize the robot state during commissioning.
+/// Call [`FoxgloveServer::publish`] with every received message.
+/// Messages are only serialized for channels that a client subscribed to, so an idle server costs very little.
+///
+/// Messages are JSON encoded.
+/// Positions on [`POSE_TOPIC`] are converted to meters, as expected by the 3D panel of Foxglove, in the frame `base`.
+///
+/// The server uses a thread to accept connections, and a thread to read from and a thread to write to each client.
+/// Publishing never blocks on a client: frames are queued for the writing thread,
+/// and a client that falls too far behind is disconnected.
+/// All threads are stopped when the server is dropped.
+pub struct FoxgloveServer {
+	shared: Arc<Shared>,
+	local_addr: SocketAddr,
+}
+
+struct Shared {
+	name: String,
+	clients: Mutex<Vec<Arc<Client>>>,
+	stop: AtomicBool,
+}
+
+struct Client {
+	stream: TcpStream,
+	queue: SyncSender<(u8, Vec<u8>)>,
+	subscriptions: Mutex<Vec<(u32, u32)>>,
+}
+
+impl FoxgloveServer {
+	/// Start a server on the given address, such as `0.0.0.0:8765`.
+	///
+	/// The name is shown by Foxglove in the connection status.
+	pub fn bind(address: impl ToSocketAddrs, name: impl Into<String>) -> std::io::Result<Self> {
+		let listener = TcpListener::bind(address)?;
+		listener.set_nonblocking(true)?;
+		let local_addr = listener.local_addr()?;
+		let shared = Arc::new(Shared {
+			name: name.into(),
+			clients: Mutex::new(Vec::new()),
+			stop: AtomicBool::new(false),
+		});
+
+		let accept_shared = shared.clone();
+		std::thread::Builder::new()
+			.name("egm-foxglove".into())
+			.spawn(move || accept(listener, accept_shared))?;
+		Ok(Self { shared, local_addr })
+	}
+
+	/// Get the address the server is listening on.
+	pub fn local_addr(&self) -> SocketAddr {
+		self.local_addr
+	}
+
+	/// Get the number of connected clients.
+	pub fn client_count(&self) -> usize {
+		self.shared.clients.lock().unwrap().len()
+	}
+
+	/// Publish a message from the robot controller to all subscribed clients.
+	///
+	/// The messages are queued for each client, so this never waits for a client.
+	/// Clients that can not keep up or can not be written to are disconnected.
+	pub fn publish(&self, state: &EgmRobot) {
+		let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+		let clients = self.shared.clients.lock().unwrap().clone();
+		let mut payloads: [Option<Option<Vec<u8>>>; 4] = Default::default();
+		for client in clients {
+			let subscriptions = client.subscriptions.lock().unwrap().clone();
+			for (subscription, channel) in subscriptions {
+				let payload = payloads[channel as usize - 1].get_or_insert_with(|| serialize(channel, state, timestamp));
+				if let Some(payload) = payload {
+					let mut frame = Vec::with_capacity(13 + payload.len());
+					frame.push(MESSAGE_DATA);
+					frame.extend_from_slice(&subscription.to_le_bytes());
+					frame.extend_from_slice(&(timestamp.as_nanos() as u64).to_le_bytes());
+					frame.extend_from_slice(payload);
+					if !client.send(OPCODE_BINARY, frame) {
+						break;
+					}
+				}
+			}
+		}
+	}
+}
+
+impl Drop for FoxgloveServer {
+	fn drop(&mut self) {
+		self.shared.stop.store(true, Ordering::Relaxed);
+		for client in self.shared.clients.lock().unwrap().drain(..) {
+			client.shutdown();
+		}
+	}
+}
+
+impl Client {
+	/// Queue a frame for the writing thread, or disconnect the client if the queue is full.
+	///
+	/// Returns false if the client is disconnected.
+	fn send(&self, opcode: u8, payload: Vec<u8>) -> bool {
+		match self.queue.try_send((opcode, payload)) {
+			Ok(()) => true,
+			Err(TrySendError::Full(_)) => {
+				log::warn!("disconnecting Foxglove client that can not keep up");
+				self.shutdown();
+				false
+			},
+			Err(TrySendError::Disconnected(_)) => {
+				self.shutdown();
+				false
+			},
+		}
+	}
+
+	fn shutdown(&self) {
+		let _ = self.stream.shutdown(Shutdown::Both);
+	}
+}
+
+/// Write the queued frames to a client until the client is disconnected.
+fn write_frames(mut stream: TcpStream, queue: Receiver<(u8, Vec<u8>)>) {
+	for (opcode, payload) in queue {
+		if let Err(e) = write_frame(&mut stream, opcode, &payload) {
+			log::warn!("failed to write to Foxglove client: {}", e);
+			let _ = stream.shutdown(Shutdown::Both);
+			return;
+		}
+	}
+}
+
+fn accept(listener: TcpListener, shared: Arc<Shared>) {
+	while !shared.stop.load(Ordering::Relaxed) {
+		match listener.accept() {
+			Ok((stream, _)) => {
+				let shared = shared.clone();
+				let _ = std::thread::Builder::new()
+					.name("egm-foxglove-client".into())
+					.spawn(move || serve(stream, shared));
+			},
+			Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(50)),
+			Err(e) => {
+				log::warn!("failed to accept Foxglove client: {}", e);
+				std::thread::sleep(Duration::from_millis(50));
+			},
+		}
+	}
+}
+
+fn serve(stream: TcpStream, shared: Arc<Shared>) {
+	let (client, mut reader) = match handshake(stream, &shared) {
+		Ok((client, reader)) => (Arc::new(client), reader),
+		Err(e) => {
+			log::warn!("Foxglove WebSocket handshake failed: {}", e);
+			return;
+		},
+	};
+	shared.clients.lock().unwrap().push(client.clone());
+
+	// The server may have been dropped during the handshake.
+	if shared.stop.load(Ordering::Relaxed) {
+		client.shutdown();
+	}
+	while !shared.stop.load(Ordering::Relaxed) {
+		let (opcode, payload) = match read_frame(&mut reader) {
+			Ok(x) => x,
+			Err(_) => break,
+		};
+		match opcode {
+			OPCODE_TEXT => handle_request(&client, &payload),
+			OPCODE_PING => {
+				client.send(OPCODE_PONG, payload);
+			},
+			OPCODE_CLOSE => {
+				client.send(OPCODE_CLOSE, Vec::new());
+				break;
+			},
+			_ => (),
+		}
+	}
+
+	// Dropping the last reference to the client stops the writing thread after the queued frames are written,
+	// which closes the connection.
+	shared.clients.lock().unwrap().retain(|x| !Arc::ptr_eq(x, &client));
+}
+
+/// Perform the WebSocket handshake and advertise the channels.
+///
+/// The handshake must complete within [`HANDSHAKE_TIMEOUT`], and the request may not be longer than [`MAX_HANDSHAKE_LEN`].
+/// Returns the client and the reader for its frames, which may already hold buffered data.
+fn handshake(stream: TcpStream, shared: &Shared) -> std::io::Result<(Client, BufReader<TcpStream>)> {
+	stream.set_nonblocking(false)?;
+	stream.set_nodelay(true)?;
+	stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+	let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+	let mut reader = BufReader::new(stream.try_clone()?);
+	let mut key = None;
+	let mut line = String::new();
+	let mut request_len = 0;
+	loop {
+		let remaining = deadline.saturating_duration_since(Instant::now());
+		if remaining.is_zero() {
+			return Err(std::io::ErrorKind::TimedOut.into());
+		}
+		stream.set_read_timeout(Some(remaining))?;
+		line.clear();
+		let len = (&mut reader).take(MAX_HANDSHAKE_LEN - request_len).read_line(&mut line)?;
+		request_len += len as u64;
+		if request_len >= MAX_HANDSHAKE_LEN {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "WebSocket handshake too large"));
+		}
+		if len == 0 {
+			return Err(std::io::ErrorKind::UnexpectedEof.into());
+		}
+		let line = line.trim_end();
+		if line.is_empty() {
+			break;
+		}
+		if let Some((name, value)) = line.split_once(':') {
+			if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+				key = Some(value.trim().to_string());
+			}
+		}
+	}
+	stream.set_read_timeout(None)?;
+	let key = key.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "missing Sec-WebSocket-Key header"))?;
+	let accept = base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()));
+
+	let mut stream = stream;
+	write!(
+		stream,
+		"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\nSec-WebSocket-Protocol: {}\r\n\r\n",
+		accept, SUBPROTOCOL
+	)?;
+
+	let info = json!({
+		"op": "serverInfo",
+		"name": shared.name,
+		"capabilities": [],
+		"supportedEncodings": ["json"],
+	});
+	write_frame(&mut stream, OPCODE_TEXT, info.to_string().as_bytes())?;
+
+	let channels: Vec<Value> = CHANNELS
+		.iter()
+		.map(|(id, topic, schema_name, schema)| {
+			json!({
+				"id": id,
+				"topic": topic,
+				"encoding": "json",
+				"schemaName": schema_name,
+				"schema": schema,
+			})
+		})
+		.collect();
+	let advertise = json!({ "op": "advertise", "channels": channels });
+	write_frame(&mut stream, OPCODE_TEXT, advertise.to_string().as_bytes())?;
+
+	let (queue, frames) = std::sync::mpsc::sync_channel(CLIENT_QUEUE_LEN);
+	let writer = stream.try_clone()?;
+	std::thread::Builder::new()
+		.name("egm-foxglove-writer".into())
+		.spawn(move || write_frames(writer, frames))?;
+	let client = Client {
+		stream,
+		queue,
+		subscriptions: Mutex::new(Vec::new()),
+	};
+	Ok((client, reader))
+}
+
+fn handle_request(client: &Client, payload: &[u8]) {
+	let request: Value = match serde_json::from_slice(payload) {
+		Ok(x) => x,
+		Err(e) => {
+			log::warn!("invalid request from Foxglove client: {}", e);
+			return;
+		},
+	};
+	let mut subscriptions = client.subscriptions.lock().unwrap();
+	match request["op"].as_str() {
+		Some("subscribe") => {
+			for subscription in request["subscriptions"].as_array().into_iter().flatten() {
+				let id = subscription["id"].as_u64().map(|x| x as u32);
+				let channel = subscription["channelId"].as_u64().map(|x| x as u32);
+				if let (Some(id), Some(channel)) = (id, channel) {
+					if CHANNELS.iter().any(|x| x.0 == channel) {
+						subscriptions.push((id, channel));
+					}
+				}
+			}
+		},
+		Some("unsubscribe") => {
+			let ids: Vec<u64> = request["subscriptionIds"].as_array().into_iter().flatten().filter_map(Value::as_u64).collect();
+			subscriptions.retain(|(id, _)| !ids.contains(&u64::from(*id)));
+		},
+		_ => (),
+	}
+}
+
+/// Serialize the message for a channel, or `None` if the message does not contain the data of the channel.
+fn serialize(channel: u32, state: &EgmRobot, timestamp: Duration) -> Option<Vec<u8>> {
+	let value = match channel {
+		1 => return serde_json::to_vec(state).ok(),
+		2 => {
+			let pose = state.feedback_pose()?;
+			let [x, y, z] = pose.pos.as_ref()?.as_mm();
+			let [qw, qx, qy, qz] = match (&pose.orient, &pose.euler) {
+				(Some(orient), _) => orient.as_wxyz(),
+				(None, Some(euler)) => euler.to_quaternion().as_wxyz(),
+				(None, None) => return None,
+			};
+			json!({
+				"timestamp": { "sec": timestamp.as_secs(), "nsec": timestamp.subsec_nanos() },
+				"frame_id": "base",
+				"pose": {
+					"position": { "x": x / 1000.0, "y": y / 1000.0, "z": z / 1000.0 },
+					"orientation": { "x": qx, "y": qy, "z": qz, "w": qw },
+				},
+			})
+		},
+		3 => json!({ "positions": state.feedback_joints()? }),
+		4 => {
+			let force = state.measured_force()?;
+			json!({ "force": force.get(..3)?, "torque": force.get(3..6)? })
+		},
+		_ => return None,
+	};
+	Some(value.to_string().into_bytes())
+}
+
+/// Write an unmasked WebSocket frame.
+fn write_frame(stream: &mut impl Write, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+	let mut header = vec![0x80 | opcode];
+	match payload.len() {
+		len @ 0..=125 => header.push(len as u8),
+		len @ 126..=0xffff => {
+			header.push(126);
+			header.extend_from_slice(&(len as u16).to_be_bytes());
+		},
+		len => {
+			header.push(127);
+			header.extend_from_slice(&(len as u64).to_be_bytes());
+		},
+	}
+	stream.write_all(&header)?;
+	stream.write_all(payload)
+}
+
+/// Read a WebSocket frame and unmask the payload.
+fn read_frame(stream: &mut impl Read) -> std::io::Result<(u8, Vec<u8>)> {
+	let mut header = [0; 2];
+	stream.read_exact(&mut header)?;
+	let len = match header[1] & 0x7f {
+		126 => {
+			let mut len = [0; 2];
+			stream.read_exact(&mut len)?;
+			u64::from(u16::from_be_bytes(len))
+		},
+		127 => {
+			let mut len = [0; 8];
+			stream.read_exact(&mut len)?;
+			u64::from_be_bytes(len)
+		},
+		len => u64::from(len),
+	};
+	if len > MAX_CLIENT_FRAME_LEN {
+		return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "WebSocket frame too large"));
+	}
+	let mut mask = [0; 4];
+	if header[1] & 0x80 != 0 {
+		stream.read_exact(&mut mask)?;
+	}
+	let mut payload = vec![0; len as usize];
+	stream.read_exact(&mut payload)?;
+	for (i, byte) in payload.iter_mut().enumerate() {
+		*byte ^= mask[i % 4];
+	}
+	Ok((header[0] & 0x0f, payload))
+}
+
+/// Compute the SHA-1 hash of some data, as needed for the WebSocket handshake.
+fn sha1(data: &[u8]) -> [u8; 20] {
+	let mut state: [u32; 5] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476, 0xc3d2_e1f0];
+	let mut message = data.to_vec();
+	message.push(0x80);
+	while message.len() % 64 != 56 {
+		message.push(0);
+	}
+	message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+	for block in message.chunks(64) {
+		let mut w = [0u32; 80];
+		for (i, word) in block.chunks(4).enumerate() {
+			w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+		}
+		for i in 16..80 {
+			w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+		}
+		let [mut a, mut b, mut c, mut d, mut e] = state;
+		for (i, w) in w.iter().enumerate() {
+			let (f, k) = match i {
+				0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+				20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+				40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+				_ => (b ^ c ^ d, 0xca62_c1d6),
+			};
+			let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*w);
+			e = d;
+			d = c;
+			c = b.rotate_left(30);
+			b = a;
+			a = temp;
+		}
+		for (state, value) in state.iter_mut().zip([a, b, c, d, e]) {
+			*state = state.wrapping_add(value);
+		}
+	}
+
+	let mut hash = [0; 20];
+	for (output, word) in hash.chunks_mut(4).zip(&state) {
+		output.copy_from_slice(&word.to_be_bytes());
+	}
+	hash
+}
+
+/// Encode data as standard base64 with padding.
+fn base64(data: &[u8]) -> String {
+	const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut output = String::with_capacity(data.len() / 3 * 4 + 4);
+	for chunk in data.chunks(3) {
+		let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+		let value = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+		for i in 0..4 {
+			if i <= chunk.len() {
+				output.push(ALPHABET[(value >> (18 - 6 * i) & 0x3f) as usize] as char);
+			} else {
+				output.push('=');
+			}
+		}
+	}
+	output
+}
+
+#[cfg(test)]
+#[test]
+fn test_foxglove_server() {
+	use crate::msg::{EgmFeedBack, EgmJoints, EgmPose};
+	use assert2::{assert, let_assert};
+
+	let_assert!(Ok(server) = FoxgloveServer::bind("127.0.0.1:0", "test"));
+	let mut client = TcpStream::connect(server.local_addr()).unwrap();
+	client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+	write!(
+		client,
+		"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Protocol: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+		SUBPROTOCOL
+	)
+	.unwrap();
+
+	let mut reader = BufReader::new(client.try_clone().unwrap());
+	let mut response = String::new();
+	while !response.ends_with("\r\n\r\n") {
+		reader.read_line(&mut response).unwrap();
+	}
+	assert!(response.starts_with("HTTP/1.1 101"));
+	assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+	let_assert!(Ok((OPCODE_TEXT, info)) = read_frame(&mut reader));
+	let info: Value = serde_json::from_slice(&info).unwrap();
+	assert!(info["op"] == "serverInfo");
+	assert!(info["name"] == "test");
+	let_assert!(Ok((OPCODE_TEXT, advertise)) = read_frame(&mut reader));
+	let advertise: Value = serde_json::from_slice(&advertise).unwrap();
+	assert!(advertise["channels"][2]["topic"] == JOINTS_TOPIC);
+
+	// Client frames must be masked: a zero mask leaves the payload unchanged.
+	let subscribe = br#"{"op":"subscribe","subscriptions":[{"id":7,"channelId":3}]}"#;
+	let mut frame = vec![0x80 | OPCODE_TEXT, 0x80 | subscribe.len() as u8, 0, 0, 0, 0];
+	frame.extend_from_slice(subscribe);
+	client.write_all(&frame).unwrap();
+
+	let state = EgmRobot {
+		feed_back: Some(EgmFeedBack {
+			joints: Some(EgmJoints::from_degrees(vec![1.0, 2.0])),
+			cartesian: Some(EgmPose::new([0.0; 3], crate::msg::EgmQuaternion::IDENTITY)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let start = std::time::Instant::now();
+	while server.client_count() == 0 || server.shared.clients.lock().unwrap()[0].subscriptions.lock().unwrap().is_empty() {
+		assert!(start.elapsed() < Duration::from_secs(5));
+		std::thread::sleep(Duration::from_millis(5));
+	}
+	server.publish(&state);
+
+	let_assert!(Ok((OPCODE_BINARY, message)) = read_frame(&mut reader));
+	assert!(message[0] == MESSAGE_DATA);
+	assert!(message[1..5] == 7u32.to_le_bytes());
+	let joints: Value = serde_json::from_slice(&message[13..]).unwrap();
+	assert!(joints == json!({ "positions": [1.0, 2.0] }));
+
+	assert!(base64(b"ab") == "YWI=");
+	drop(server);
+	assert!(let Err(_) = read_frame(&mut reader));
+}
+
+#[cfg(test)]
+#[test]
+fn test_slow_clients() {
+	use crate::msg::{EgmFeedBack, EgmJoints};
+	use assert2::{assert, let_assert};
+
+	let_assert!(Ok(server) = FoxgloveServer::bind("127.0.0.1:0", "test"));
+
+	// An oversized handshake is rejected.
+	let mut client = TcpStream::connect(server.local_addr()).unwrap();
+	client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+	let header = format!("GET / HTTP/1.1\r\nX-Padding: {}\r\n", "a".repeat(MAX_HANDSHAKE_LEN as usize));
+	let _ = client.write_all(header.as_bytes());
+	let mut response = Vec::new();
+	let _ = client.read_to_end(&mut response);
+	assert!(response.is_empty());
+
+	// A client that stops reading is disconnected without blocking the publisher.
+	let mut client = TcpStream::connect(server.local_addr()).unwrap();
+	client.write_all(b"GET / HTTP/1.1\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n").unwrap();
+	let subscribe = br#"{"op":"subscribe","subscriptions":[{"id":1,"channelId":1}]}"#;
+	let mut frame = vec![0x80 | OPCODE_TEXT, 0x80 | subscribe.len() as u8, 0, 0, 0, 0];
+	frame.extend_from_slice(subscribe);
+	client.write_all(&frame).unwrap();
+
+	let state = EgmRobot {
+		feed_back: Some(EgmFeedBack {
+			joints: Some(EgmJoints::from_degrees(vec![1.0; 1000])),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let start = Instant::now();
+	while server.client_count() == 0 || server.shared.clients.lock().unwrap()[0].subscriptions.lock().unwrap().is_empty() {
+		assert!(start.elapsed() < Duration::from_secs(5));
+		std::thread::sleep(Duration::from_millis(5));
+	}
+	while server.client_count() > 0 {
+		assert!(start.elapsed() < Duration::from_secs(10));
+		let publish_start = Instant::now();
+		server.publish(&state);
+		assert!(publish_start.elapsed() < Duration::from_millis(500));
+	}
+}
@@ -0,0 +1,217 @@
+use crate::msg::{EgmPose, EgmQuaternion};
+
+/// A rigid transformation: a rotation followed by a translation in millimeters.
+///
+/// A frame describes the pose of a child coordinate system in a parent coordinate system.
+/// Transforming a point by the frame converts it from child coordinates to parent coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frame {
+	/// The position of the origin of the child frame in the parent frame, in millimeters.
+	pub position: [f64; 3],
+
+	/// The orientation of the child frame in the parent frame, as unit quaternion.
+	pub orientation: EgmQuaternion,
+}
+
+impl Frame {
+	/// The identity transformation.
+	pub const IDENTITY: Self = Self {
+		position: [0.0; 3],
+		orientation: EgmQuaternion::IDENTITY,
+	};
+
+	/// Create a new frame from a position in millimeters and an orientation.
+	pub fn new(position: [f64; 3], orientation: EgmQuaternion) -> Self {
+		Self { position, orientation }
+	}
+
+	/// Create a frame from a pose.
+	///
+	/// If the pose has no quaternion, the Euler angles are used.
+	/// Missing fields are treated as zero position and identity orientation.
+	pub fn from_pose(pose: &EgmPose) -> Self {
+		let orientation = pose
+			.orient
+			.or_else(|| pose.euler.map(|x| x.to_quaternion()))
+			.unwrap_or(EgmQuaternion::IDENTITY);
+		Self::new(pose.pos.unwrap_or_default().as_mm(), orientation)
+	}
+
+	/// Convert the frame to a pose with a quaternion.
+	pub fn to_pose(&self) -> EgmPose {
+		EgmPose::new(self.position, self.orientation)
+	}
+
+	/// Get the inverse transformation.
+	pub fn inverse(&self) -> Self {
+		let orientation = conjugate(&self.orientation);
+		let [x, y, z] = rotate(&orientation, self.position);
+		Self::new([-x, -y, -z], orientation)
+	}
+
+	/// Chain two transformations: first `other`, then `self`.
+	///
+	/// If `other` is the pose of frame C in frame B and `self` is the pose of frame B in frame A,
+	/// the result is the pose of frame C in frame A.
+	pub fn compose(&self, other: &Self) -> Self {
+		Self::new(self.transform_point(other.position), multiply(&self.orientation, &other.orientation))
+	}
+
+	/// Transform a point from child coordinates to parent coordinates.
+	pub fn transform_point(&self, point: [f64; 3]) -> [f64; 3] {
+		let [x, y, z] = rotate(&self.orientation, point);
+		[x + self.position[0], y + self.position[1], z + self.position[2]]
+	}
+
+	/// Transform a vector from child coordinates to parent coordinates, ignoring the translation.
+	pub fn transform_vector(&self, vector: [f64; 3]) -> [f64; 3] {
+		rotate(&self.orientation, vector)
+	}
+
+	/// Transform a pose from child coordinates to parent coordinates.
+	pub fn transform_pose(&self, pose: &EgmPose) -> EgmPose {
+		self.compose(&Frame::from_pose(pose)).to_pose()
+	}
+}
+
+impl Default for Frame {
+	fn default() -> Self {
+		Self::IDENTITY
+	}
+}
+
+/// How a robot is mounted.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Mounting {
+	/// Mounted on the floor, with the base frame aligned to the world frame.
+	#[default]
+	Floor,
+
+	/// Mounted on a wall, with the base frame rotated 90 degrees around the world Y axis.
+	///
+	/// The Z axis of the base frame points along the world X axis.
+	Wall,
+
+	/// Mounted upside down on the ceiling, with the base frame rotated 180 degrees around the world X axis.
+	Ceiling,
+
+	/// Mounted at a custom orientation of the base frame in the world frame.
+	Custom(EgmQuaternion),
+}
+
+impl Mounting {
+	/// Get the orientation of the base frame in the world frame.
+	pub fn orientation(&self) -> EgmQuaternion {
+		let half = std::f64::consts::FRAC_1_SQRT_2;
+		match self {
+			Self::Floor => EgmQuaternion::IDENTITY,
+			Self::Wall => EgmQuaternion::from_wxyz(half, 0.0, half, 0.0),
+			Self::Ceiling => EgmQuaternion::from_wxyz(0.0, 1.0, 0.0, 0.0),
+			Self::Custom(orientation) => *orientation,
+		}
+	}
+}
+
+/// The mounting of a robot in the world.
+///
+/// EGM poses are expressed in the base frame of the robot (or in a work object, depending on the RAPID program).
+/// For inverted or wall mounted robots, the base frame differs from the world frame that applications usually work in.
+/// This configuration converts between both frames, so the mounting is handled by configuration instead of hand-derived transforms.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MountConfig {
+	/// How the robot is mounted.
+	pub mounting: Mounting,
+
+	/// The position of the origin of the base frame in the world frame, in millimeters.
+	pub base_offset: [f64; 3],
+}
+
+impl MountConfig {
+	/// Create a new mount configuration.
+	pub fn new(mounting: Mounting, base_offset: [f64; 3]) -> Self {
+		Self { mounting, base_offset }
+	}
+
+	/// Get the pose of the base frame in the world frame.
+	pub fn base_frame(&self) -> Frame {
+		Frame::new(self.base_offset, self.mounting.orientation())
+	}
+
+	/// Convert a pose from the world frame to the base frame.
+	pub fn world_to_base(&self, pose: &EgmPose) -> EgmPose {
+		self.base_frame().inverse().transform_pose(pose)
+	}
+
+	/// Convert a pose from the base frame to the world frame.
+	pub fn base_to_world(&self, pose: &EgmPose) -> EgmPose {
+		self.base_frame().transform_pose(pose)
+	}
+
+	/// Convert a pose from a user frame to the base frame.
+	///
+	/// The user frame is given as pose in the world frame.
+	pub fn user_to_base(&self, user_frame: &Frame, pose: &EgmPose) -> EgmPose {
+		self.base_frame().inverse().compose(user_frame).transform_pose(pose)
+	}
+
+	/// Get the direction of gravity in the base frame, as unit vector.
+	///
+	/// This can be used for gravity compensation of force measurements.
+	pub fn gravity(&self) -> [f64; 3] {
+		self.base_frame().inverse().transform_vector([0.0, 0.0, -1.0])
+	}
+}
+
+fn conjugate(q: &EgmQuaternion) -> EgmQuaternion {
+	EgmQuaternion::from_wxyz(q.u0, -q.u1, -q.u2, -q.u3)
+}
+
+fn multiply(a: &EgmQuaternion, b: &EgmQuaternion) -> EgmQuaternion {
+	let [w1, x1, y1, z1] = a.as_wxyz();
+	let [w2, x2, y2, z2] = b.as_wxyz();
+	EgmQuaternion::from_wxyz(
+		w1 * w2 - x1 * x2 - y1 * y2 - z1 * z2,
+		w1 * x2 + x1 * w2 + y1 * z2 - z1 * y2,
+		w1 * y2 - x1 * z2 + y1 * w2 + z1 * x2,
+		w1 * z2 + x1 * y2 - y1 * x2 + z1 * w2,
+	)
+}
+
+fn rotate(q: &EgmQuaternion, [x, y, z]: [f64; 3]) -> [f64; 3] {
+	let rotated = multiply(&multiply(q, &EgmQuaternion::from_wxyz(0.0, x, y, z)), &conjugate(q));
+	[rotated.u1, rotated.u2, rotated.u3]
+}
+
+#[cfg(test)]
+#[test]
+fn test_mount_config() {
+	use assert2::assert;
+
+	let close = |a: [f64; 3], b: [f64; 3]| a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-9);
+
+	let floor = MountConfig::default();
+	assert!(close(floor.gravity(), [0.0, 0.0, -1.0]));
+
+	let ceiling = MountConfig::new(Mounting::Ceiling, [0.0, 0.0, 2000.0]);
+	assert!(close(ceiling.gravity(), [0.0, 0.0, 1.0]));
+	let target = EgmPose::new([100.0, 200.0, 500.0], EgmQuaternion::IDENTITY);
+	let in_base = ceiling.world_to_base(&target);
+	assert!(close(in_base.pos.unwrap().as_mm(), [100.0, -200.0, 1500.0]));
+	assert!(in_base.orient.unwrap().angle_to(&Mounting::Ceiling.orientation()) < 1e-6);
+	let back = ceiling.base_to_world(&in_base);
+	assert!(close(back.pos.unwrap().as_mm(), [100.0, 200.0, 500.0]));
+	assert!(back.orient.unwrap().angle_to(&EgmQuaternion::IDENTITY) < 1e-6);
+
+	let wall = MountConfig::new(Mounting::Wall, [0.0; 3]);
+	assert!(close(wall.gravity(), [1.0, 0.0, 0.0]));
+	assert!(close(wall.base_frame().transform_vector([0.0, 0.0, 1.0]), [1.0, 0.0, 0.0]));
+
+	let user_frame = Frame::new([0.0, 0.0, 100.0], EgmQuaternion::IDENTITY);
+	let in_base = ceiling.user_to_base(&user_frame, &EgmPose::new([0.0; 3], EgmQuaternion::IDENTITY));
+	assert!(close(in_base.pos.unwrap().as_mm(), [0.0, 0.0, 1900.0]));
+
+	let frame = Frame::new([1.0, 2.0, 3.0], crate::msg::EgmEuler::from_xyz_degrees(10.0, 20.0, 30.0).to_quaternion());
+	let identity = frame.compose(&frame.inverse());
+	assert!(close(identity.position, [0.0; 3]));
+	assert!(identity.orientation.angle_to(&EgmQuaternion::IDENTITY) < 1e-6);
+}
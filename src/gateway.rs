@@ -0,0 +1,360 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::watch;
+
+use crate::msg::{EgmClock, EgmPose, EgmRobot, EgmSensor};
+
+/// The largest request accepted from a client.
+const MAX_REQUEST_LEN: usize = 1 << 16;
+
+/// The maximum time to send a message to a WebSocket client.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A motion target received from a gateway client.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GatewayTarget {
+	/// A joint target in degrees.
+	Joints(Vec<f64>),
+
+	/// A pose target.
+	Pose(EgmPose),
+}
+
+impl GatewayTarget {
+	/// Create a sensor message for the target.
+	pub fn to_sensor(&self, sequence_number: u32, time: impl Into<EgmClock>) -> EgmSensor {
+		match self {
+			Self::Joints(joints) => EgmSensor::joint_target(sequence_number, joints.as_slice(), time),
+			Self::Pose(pose) => EgmSensor::pose_target(sequence_number, pose.clone(), time),
+		}
+	}
+
+	/// Parse a target request from a client.
+	fn parse(request: &str) -> Result<Self, String> {
+		let request: Value = serde_json::from_str(request).map_err(|e| e.to_string())?;
+		let target = match request["type"].as_str() {
+			Some("joints") => Self::Joints(serde_json::from_value(request["joints"].clone()).map_err(|e| format!("invalid joints: {}", e))?),
+			Some("pose") => Self::Pose(serde_json::from_value(request["pose"].clone()).map_err(|e| format!("invalid pose: {}", e))?),
+			Some(other) => return Err(format!("unknown request type: {}", other)),
+			None => return Err("missing request type".into()),
+		};
+		let has_nan = match &target {
+			Self::Joints(joints) => joints.iter().any(|x| x.is_nan()),
+			Self::Pose(pose) => pose.has_nan(),
+		};
+		if has_nan {
+			return Err("target contains NaN values".into());
+		}
+		Ok(target)
+	}
+}
+
+/// HTTP server that exposes robot feedback and accepts motion targets, for clients that are not written in Rust.
+///
+/// The server is built on [`axum`] and offers these endpoints:
+///   * `GET /feedback`: the JSON encoding of the last [`EgmRobot`] message passed to [`GatewayServer::publish`],
+///     or `204 No Content` if nothing was published yet.
+///   * `POST /target`: set a target, answered with `204 No Content`.
+///   * `GET /ws`: a WebSocket that sends every published message as `{"type": "feedback", "message": {...}}`
+///     and accepts targets as text messages.
+///
+/// Targets are sent as `{"type": "joints", "joints": [...]}` with joint positions in degrees,
+/// or as `{"type": "pose", "pose": {...}}` with the JSON encoding of an [`EgmPose`].
+/// Invalid targets are answered with `{"type": "error", "message": "..."}`,
+/// with status `400 Bad Request` for `POST /target`.
+///
+/// Feedback and targets have latest-value semantics.
+/// A WebSocket client that falls behind skips to the most recent message,
+/// so publishing never waits for a client.
+/// [`GatewayServer::take_target`] returns only the most recent target from any client.
+/// The application remains responsible for sending the targets to the robot controller,
+/// so all the usual validation and safety layers still apply.
+///
+/// The server runs as a task on the `tokio` runtime it was started on.
+/// The server and all WebSocket connections are stopped when the server is dropped.
+pub struct GatewayServer {
+	shared: Arc<Shared>,
+	local_addr: SocketAddr,
+}
+
+struct Shared {
+	/// The JSON encoding of the last published message.
+	feedback: watch::Sender<Option<Arc<str>>>,
+	target: Mutex<Option<GatewayTarget>>,
+	stop: watch::Sender<bool>,
+}
+
+impl GatewayServer {
+	/// Start a server on the given address, such as `0.0.0.0:6511`.
+	///
+	/// Must be called from within a `tokio` runtime.
+	pub async fn bind(address: impl ToSocketAddrs) -> std::io::Result<Self> {
+		let listener = TcpListener::bind(address).await?;
+		let local_addr = listener.local_addr()?;
+		let shared = Arc::new(Shared {
+			feedback: watch::Sender::new(None),
+			target: Mutex::new(None),
+			stop: watch::Sender::new(false),
+		});
+
+		let app = Router::new()
+			.route("/feedback", get(get_feedback))
+			.route("/target", post(post_target))
+			.route("/ws", get(upgrade))
+			.layer(DefaultBodyLimit::max(MAX_REQUEST_LEN))
+			.with_state(shared.clone());
+		let mut stop = shared.stop.subscribe();
+		let server = axum::serve(listener, app).with_graceful_shutdown(async move {
+			let _ = stop.wait_for(|&stop| stop).await;
+		});
+		tokio::spawn(async move {
+			if let Err(e) = server.await {
+				log::warn!("gateway server failed: {}", e);
+			}
+		});
+		Ok(Self { shared, local_addr })
+	}
+
+	/// Get the address the server is listening on.
+	pub fn local_addr(&self) -> SocketAddr {
+		self.local_addr
+	}
+
+	/// Get the number of connected WebSocket clients.
+	pub fn client_count(&self) -> usize {
+		self.shared.feedback.receiver_count()
+	}
+
+	/// Publish a message from the robot controller to all clients.
+	///
+	/// This never waits for a client.
+	pub fn publish(&self, state: &EgmRobot) {
+		match serde_json::to_string(state) {
+			Ok(message) => {
+				self.shared.feedback.send_replace(Some(message.into()));
+			},
+			Err(e) => log::warn!("failed to encode feedback for gateway clients: {}", e),
+		}
+	}
+
+	/// Take the most recent target received from a client, if any arrived since the last call.
+	pub fn take_target(&self) -> Option<GatewayTarget> {
+		self.shared.target.lock().unwrap().take()
+	}
+}
+
+impl Drop for GatewayServer {
+	fn drop(&mut self) {
+		self.shared.stop.send_replace(true);
+	}
+}
+
+impl Shared {
+	/// Parse and store a target from a client.
+	fn set_target(&self, request: &str) -> Result<(), String> {
+		let target = GatewayTarget::parse(request)?;
+		*self.target.lock().unwrap() = Some(target);
+		Ok(())
+	}
+}
+
+fn error_message(message: &str) -> Value {
+	json!({ "type": "error", "message": message })
+}
+
+async fn get_feedback(State(shared): State<Arc<Shared>>) -> Response {
+	let message = shared.feedback.borrow().clone();
+	match message {
+		Some(message) => ([(header::CONTENT_TYPE, "application/json")], message.to_string()).into_response(),
+		None => StatusCode::NO_CONTENT.into_response(),
+	}
+}
+
+async fn post_target(State(shared): State<Arc<Shared>>, request: String) -> Response {
+	match shared.set_target(&request) {
+		Ok(()) => StatusCode::NO_CONTENT.into_response(),
+		Err(e) => (StatusCode::BAD_REQUEST, Json(error_message(&e))).into_response(),
+	}
+}
+
+async fn upgrade(State(shared): State<Arc<Shared>>, upgrade: WebSocketUpgrade) -> Response {
+	upgrade
+		.max_message_size(MAX_REQUEST_LEN)
+		.on_upgrade(move |socket| serve(socket, shared))
+}
+
+/// Send a text message to a WebSocket client, giving up after [`WRITE_TIMEOUT`].
+async fn send(socket: &mut WebSocket, message: String) -> Result<(), ()> {
+	match tokio::time::timeout(WRITE_TIMEOUT, socket.send(Message::Text(message.into()))).await {
+		Ok(Ok(())) => Ok(()),
+		Ok(Err(e)) => {
+			log::warn!("failed to write to gateway client: {}", e);
+			Err(())
+		},
+		Err(_) => {
+			log::warn!("disconnecting gateway client that can not keep up");
+			Err(())
+		},
+	}
+}
+
+/// Exchange feedback and targets with a WebSocket client until it disconnects or the server is dropped.
+async fn serve(mut socket: WebSocket, shared: Arc<Shared>) {
+	let mut feedback = shared.feedback.subscribe();
+	let mut stop = shared.stop.subscribe();
+	loop {
+		tokio::select! {
+			_ = async { stop.wait_for(|&stop| stop).await.is_err() } => break,
+			changed = feedback.changed() => {
+				if changed.is_err() {
+					break;
+				}
+				let message = feedback.borrow_and_update().clone();
+				if let Some(message) = message {
+					let message = format!("{{\"type\":\"feedback\",\"message\":{}}}", message);
+					if send(&mut socket, message).await.is_err() {
+						break;
+					}
+				}
+			},
+			request = socket.recv() => match request {
+				Some(Ok(Message::Text(request))) => {
+					if let Err(e) = shared.set_target(request.as_str()) {
+						if send(&mut socket, error_message(&e).to_string()).await.is_err() {
+							break;
+						}
+					}
+				},
+				Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+				Some(Ok(_)) => (),
+			},
+		}
+	}
+	let _ = socket.send(Message::Close(None)).await;
+}
+
+/// Send a HTTP request and return the status code and body of the response.
+#[cfg(test)]
+async fn request(server: &GatewayServer, method: &str, path: &str, body: &str) -> (u16, String) {
+	use assert2::let_assert;
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+	use tokio::net::TcpStream;
+
+	let mut stream = TcpStream::connect(server.local_addr()).await.unwrap();
+	let request = format!(
+		"{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+		method,
+		path,
+		body.len(),
+		body
+	);
+	stream.write_all(request.as_bytes()).await.unwrap();
+	let mut response = String::new();
+	stream.read_to_string(&mut response).await.unwrap();
+	let_assert!(Some((head, body)) = response.split_once("\r\n\r\n"));
+	let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+	(status, body.to_string())
+}
+
+#[cfg(test)]
+fn feedback(joints: Vec<f64>) -> EgmRobot {
+	use crate::msg::{EgmFeedBack, EgmJoints};
+
+	EgmRobot {
+		feed_back: Some(EgmFeedBack {
+			joints: Some(EgmJoints::from_degrees(joints)),
+			..Default::default()
+		}),
+		..Default::default()
+	}
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_gateway_rest() {
+	use crate::msg::EgmJoints;
+	use assert2::{assert, let_assert};
+
+	let_assert!(Ok(server) = GatewayServer::bind("127.0.0.1:0").await);
+
+	assert!(request(&server, "GET", "/feedback", "").await.0 == 204);
+	let state = feedback(vec![1.0, 2.0]);
+	server.publish(&state);
+	let (status, body) = request(&server, "GET", "/feedback", "").await;
+	assert!(status == 200);
+	assert!(serde_json::from_str::<EgmRobot>(&body).unwrap() == state);
+
+	let (status, body) = request(&server, "POST", "/target", "{\"type\": \"spline\"}").await;
+	assert!(status == 400);
+	assert!(serde_json::from_str::<Value>(&body).unwrap() == error_message("unknown request type: spline"));
+	assert!(server.take_target().is_none());
+
+	let (status, _) = request(&server, "POST", "/target", "{\"type\": \"joints\", \"joints\": [3.0, 4.0]}").await;
+	assert!(status == 204);
+	let_assert!(Some(target) = server.take_target());
+	assert!(target == GatewayTarget::Joints(vec![3.0, 4.0]));
+	let sensor = target.to_sensor(3, EgmClock::new(0, 4_000));
+	assert!(sensor.planned.unwrap().joints == Some(EgmJoints::from_degrees(vec![3.0, 4.0])));
+	assert!(server.take_target().is_none());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_gateway_websocket() {
+	use assert2::{assert, let_assert};
+	use futures_util::{SinkExt, StreamExt};
+	use tokio_tungstenite::tungstenite;
+
+	let_assert!(Ok(server) = GatewayServer::bind("127.0.0.1:0").await);
+	let url = format!("ws://{}/ws", server.local_addr());
+	let_assert!(Ok((mut client, _)) = tokio_tungstenite::connect_async(url).await);
+
+	let start = std::time::Instant::now();
+	while server.client_count() == 0 {
+		assert!(start.elapsed() < Duration::from_secs(5));
+		tokio::time::sleep(Duration::from_millis(5)).await;
+	}
+	let state = feedback(vec![1.0, 2.0]);
+	server.publish(&state);
+	let_assert!(Some(Ok(tungstenite::Message::Text(feedback))) = client.next().await);
+	let feedback: Value = serde_json::from_str(&feedback).unwrap();
+	assert!(feedback["type"] == "feedback");
+	assert!(serde_json::from_value::<EgmRobot>(feedback["message"].clone()).unwrap() == state);
+
+	client.send(tungstenite::Message::text("{\"type\": \"spline\"}")).await.unwrap();
+	let_assert!(Some(Ok(tungstenite::Message::Text(error))) = client.next().await);
+	assert!(serde_json::from_str::<Value>(&error).unwrap() == error_message("unknown request type: spline"));
+
+	client.send(tungstenite::Message::text("{\"type\": \"joints\", \"joints\": [1.0, 2.0]}")).await.unwrap();
+	client.send(tungstenite::Message::text("{\"type\": \"joints\", \"joints\": [3.0, 4.0]}")).await.unwrap();
+	let start = std::time::Instant::now();
+	loop {
+		assert!(start.elapsed() < Duration::from_secs(5));
+		if server.take_target() == Some(GatewayTarget::Joints(vec![3.0, 4.0])) {
+			break;
+		}
+		tokio::time::sleep(Duration::from_millis(5)).await;
+	}
+	assert!(server.take_target().is_none());
+
+	drop(server);
+	let closed = tokio::time::timeout(Duration::from_secs(5), async {
+		loop {
+			match client.next().await {
+				Some(Ok(tungstenite::Message::Close(_))) | Some(Err(_)) | None => break,
+				Some(Ok(_)) => (),
+			}
+		}
+	});
+	assert!(let Ok(()) = closed.await);
+}
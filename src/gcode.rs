@@ -0,0 +1,390 @@
+use crate::msg::{EgmCartesianSpeed, EgmPose, EgmQuaternion};
+
+/// Options for interpreting G-code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GcodeOptions {
+	/// The speed in mm/s used for rapid moves (`G0`).
+	pub rapid_speed: f64,
+
+	/// The work offsets in millimeters for `G54` to `G59`, in the coordinate frame of the robot.
+	pub work_offsets: [[f64; 3]; 6],
+
+	/// The position in millimeters at the start of the program, in the coordinate frame of the robot.
+	pub start: [f64; 3],
+}
+
+impl Default for GcodeOptions {
+	fn default() -> Self {
+		Self {
+			rapid_speed: 250.0,
+			work_offsets: [[0.0; 3]; 6],
+			start: [0.0; 3],
+		}
+	}
+}
+
+/// Error that may occur while interpreting G-code.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GcodeError {
+	/// A word of the program could not be parsed.
+	InvalidWord {
+		/// The line number, starting at 1.
+		line: usize,
+
+		/// The word that could not be parsed.
+		word: String,
+	},
+
+	/// The program uses a code that is not supported.
+	UnsupportedCode {
+		/// The line number, starting at 1.
+		line: usize,
+
+		/// The unsupported code, such as `G2`.
+		code: String,
+	},
+
+	/// A feed move was programmed before a feed rate was set.
+	MissingFeedRate {
+		/// The line number, starting at 1.
+		line: usize,
+	},
+}
+
+/// A segment of a trajectory interpreted from G-code.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GcodeSegment {
+	/// The start position in millimeters.
+	pub start: [f64; 3],
+
+	/// The end position in millimeters.
+	pub end: [f64; 3],
+
+	/// The duration of the segment in seconds.
+	pub duration: f64,
+
+	/// True for rapid moves (`G0`).
+	pub rapid: bool,
+}
+
+impl GcodeSegment {
+	/// Get the velocity in mm/s of the segment.
+	///
+	/// The velocity is zero for dwell segments.
+	pub fn velocity(&self) -> [f64; 3] {
+		if self.duration == 0.0 {
+			return [0.0; 3];
+		}
+		let [x, y, z] = sub(self.end, self.start);
+		[x / self.duration, y / self.duration, z / self.duration]
+	}
+
+	/// Get the position at a time in seconds since the start of the segment.
+	pub fn position_at(&self, time: f64) -> [f64; 3] {
+		let fraction = if self.duration == 0.0 { 1.0 } else { (time / self.duration).clamp(0.0, 1.0) };
+		let [x, y, z] = sub(self.end, self.start);
+		[self.start[0] + x * fraction, self.start[1] + y * fraction, self.start[2] + z * fraction]
+	}
+}
+
+/// A time-parameterized cartesian trajectory interpreted from a subset of G-code.
+///
+/// The supported subset is aimed at dispensing and milling front-ends:
+///   * `G0` and `G1`: rapid and feed moves with `X`, `Y`, `Z` and feed rate `F` in units per minute.
+///   * `G4`: dwell for `P` seconds.
+///   * `G20` and `G21`: inch and millimeter units.
+///   * `G54` to `G59`: select a work offset.
+///   * `G90` and `G91`: absolute and relative positioning.
+///
+/// Line numbers (`N`) and comments are ignored, as are `M`, `S` and `T` words since they do not affect the motion.
+/// Any other code is rejected.
+///
+/// Moves have a constant speed without acceleration, so the feed rates should be chosen accordingly.
+/// Sample the trajectory at the feedback time of the robot controller to stream the targets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GcodeTrajectory {
+	segments: Vec<GcodeSegment>,
+	start_times: Vec<f64>,
+	start: [f64; 3],
+}
+
+impl GcodeTrajectory {
+	/// Interpret a G-code program.
+	pub fn parse(program: &str, options: &GcodeOptions) -> Result<Self, GcodeError> {
+		let mut interpreter = Interpreter {
+			options,
+			position: options.start,
+			motion: None,
+			feed: None,
+			scale: 1.0,
+			relative: false,
+			work_offset: [0.0; 3],
+			segments: Vec::new(),
+		};
+		for (index, line) in program.lines().enumerate() {
+			interpreter.line(index + 1, line)?;
+		}
+
+		let mut start_times = Vec::with_capacity(interpreter.segments.len());
+		let mut time = 0.0;
+		for segment in &interpreter.segments {
+			start_times.push(time);
+			time += segment.duration;
+		}
+		Ok(Self {
+			segments: interpreter.segments,
+			start_times,
+			start: options.start,
+		})
+	}
+
+	/// Get the segments of the trajectory.
+	pub fn segments(&self) -> &[GcodeSegment] {
+		&self.segments
+	}
+
+	/// Get the total duration of the trajectory in seconds.
+	pub fn duration(&self) -> f64 {
+		match (self.start_times.last(), self.segments.last()) {
+			(Some(start), Some(segment)) => start + segment.duration,
+			_ => 0.0,
+		}
+	}
+
+	/// Find the segment at a time in seconds, and the time since the start of the segment.
+	fn segment_at(&self, time: f64) -> Option<(&GcodeSegment, f64)> {
+		let index = self.start_times.partition_point(|start| *start <= time).checked_sub(1)?;
+		Some((&self.segments[index], time - self.start_times[index]))
+	}
+
+	/// Get the position in millimeters at a time in seconds since the start of the trajectory.
+	pub fn position_at(&self, time: f64) -> [f64; 3] {
+		match self.segment_at(time) {
+			Some((segment, time)) => segment.position_at(time),
+			None => self.start,
+		}
+	}
+
+	/// Get the velocity in mm/s at a time in seconds since the start of the trajectory.
+	pub fn velocity_at(&self, time: f64) -> [f64; 3] {
+		match self.segment_at(time) {
+			Some((segment, time)) if time < segment.duration => segment.velocity(),
+			_ => [0.0; 3],
+		}
+	}
+
+	/// Get the target pose at a time in seconds since the start of the trajectory, with a fixed tool orientation.
+	pub fn pose_at(&self, time: f64, orientation: EgmQuaternion) -> EgmPose {
+		EgmPose::new(self.position_at(time), orientation)
+	}
+
+	/// Get the speed reference at a time in seconds since the start of the trajectory.
+	pub fn speed_at(&self, time: f64) -> EgmCartesianSpeed {
+		self.velocity_at(time).into()
+	}
+}
+
+/// Modal state of the G-code interpreter.
+struct Interpreter<'a> {
+	options: &'a GcodeOptions,
+	position: [f64; 3],
+	motion: Option<u32>,
+	feed: Option<f64>,
+	scale: f64,
+	relative: bool,
+	work_offset: [f64; 3],
+	segments: Vec<GcodeSegment>,
+}
+
+impl Interpreter<'_> {
+	fn line(&mut self, line: usize, text: &str) -> Result<(), GcodeError> {
+		// A line starting with `%` marks the start or end of the program.
+		if text.trim_start().starts_with('%') {
+			return Ok(());
+		}
+
+		let mut axes = [None; 3];
+		let mut dwell = false;
+		let mut dwell_time = 0.0;
+		let mut motion = None;
+		for word in words(text) {
+			let invalid = || GcodeError::InvalidWord { line, word: word.clone() };
+			let mut chars = word.chars();
+			let letter = chars.next().filter(char::is_ascii_alphabetic).ok_or_else(invalid)?.to_ascii_uppercase();
+			let number = chars.as_str();
+			let value: f64 = number.parse().map_err(|_| invalid())?;
+			match letter {
+				'G' => match number.trim_start_matches('0') {
+					"" => motion = Some(0),
+					"1" => motion = Some(1),
+					"4" => dwell = true,
+					"20" => self.scale = 25.4,
+					"21" => self.scale = 1.0,
+					"90" => self.relative = false,
+					"91" => self.relative = true,
+					"54" | "55" | "56" | "57" | "58" | "59" => self.work_offset = self.options.work_offsets[value as usize - 54],
+					_ => return Err(GcodeError::UnsupportedCode { line, code: word.to_ascii_uppercase() }),
+				},
+				'X' => axes[0] = Some(value),
+				'Y' => axes[1] = Some(value),
+				'Z' => axes[2] = Some(value),
+				'F' => self.feed = Some(value * self.scale / 60.0),
+				'P' => dwell_time = value,
+				'N' | 'M' | 'S' | 'T' => (),
+				_ => return Err(GcodeError::UnsupportedCode { line, code: word.to_ascii_uppercase() }),
+			}
+		}
+
+		if dwell {
+			self.segments.push(GcodeSegment {
+				start: self.position,
+				end: self.position,
+				duration: dwell_time.max(0.0),
+				rapid: false,
+			});
+			return Ok(());
+		}
+
+		if motion.is_some() {
+			self.motion = motion;
+		}
+		if axes.iter().all(Option::is_none) {
+			return Ok(());
+		}
+		let rapid = match self.motion {
+			Some(0) => true,
+			Some(_) => false,
+			None => return Err(GcodeError::UnsupportedCode { line, code: "axis words without G0 or G1".into() }),
+		};
+
+		let mut end = self.position;
+		for (i, value) in axes.iter().enumerate() {
+			if let Some(value) = value {
+				end[i] = if self.relative {
+					self.position[i] + value * self.scale
+				} else {
+					self.work_offset[i] + value * self.scale
+				};
+			}
+		}
+		let speed = if rapid {
+			self.options.rapid_speed
+		} else {
+			self.feed.filter(|x| *x > 0.0).ok_or(GcodeError::MissingFeedRate { line })?
+		};
+		let [x, y, z] = sub(end, self.position);
+		let distance = (x * x + y * y + z * z).sqrt();
+		if distance > 0.0 {
+			self.segments.push(GcodeSegment {
+				start: self.position,
+				end,
+				duration: distance / speed,
+				rapid,
+			});
+		}
+		self.position = end;
+		Ok(())
+	}
+}
+
+/// Split a line of G-code in words, removing comments.
+fn words(line: &str) -> Vec<String> {
+	let line = line.split(';').next().unwrap_or_default();
+	let mut words = Vec::new();
+	let mut in_comment = false;
+	let mut word = String::new();
+	for c in line.chars() {
+		match c {
+			'(' => in_comment = true,
+			')' => in_comment = false,
+			_ if in_comment || c.is_whitespace() => (),
+			_ if c.is_ascii_alphabetic() => {
+				if !word.is_empty() {
+					words.push(std::mem::take(&mut word));
+				}
+				word.push(c);
+			},
+			_ => word.push(c),
+		}
+	}
+	if !word.is_empty() {
+		words.push(word);
+	}
+	words
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+	[a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+impl std::fmt::Display for GcodeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::InvalidWord { line, word } => write!(f, "line {}: invalid word: {}", line, word),
+			Self::UnsupportedCode { line, code } => write!(f, "line {}: unsupported code: {}", line, code),
+			Self::MissingFeedRate { line } => write!(f, "line {}: feed move without feed rate", line),
+		}
+	}
+}
+
+impl std::error::Error for GcodeError {}
+
+#[cfg(test)]
+#[test]
+fn test_gcode() {
+	use assert2::{assert, let_assert};
+
+	let mut options = GcodeOptions {
+		rapid_speed: 100.0,
+		..Default::default()
+	};
+	options.work_offsets[1] = [500.0, 0.0, 200.0];
+	let program = "
+		N10 G21 G90 (millimeters, absolute)
+		G55 G0 X0 Y0 Z10 ; rapid to the work offset
+		G1 Z0 F600
+		X30 Y40 M3
+		G4 P0.5
+		G91 G1 X-30
+	";
+	let_assert!(Ok(trajectory) = GcodeTrajectory::parse(program, &options));
+	let segments = trajectory.segments();
+	assert!(segments.len() == 5);
+	assert!(segments[0].rapid);
+	assert!(segments[0].end == [500.0, 0.0, 210.0]);
+	assert!(segments[1].end == [500.0, 0.0, 200.0]);
+	assert!(segments[1].duration == 1.0);
+	assert!(segments[2].duration == 5.0);
+	assert!(segments[3].duration == 0.5);
+	assert!(segments[4].end == [500.0, 40.0, 200.0]);
+
+	let close = |a: [f64; 3], b: [f64; 3]| a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-9);
+	let start = segments[0].duration + 1.0;
+	assert!(close(trajectory.position_at(start + 2.5), [515.0, 20.0, 200.0]));
+	assert!(close(trajectory.velocity_at(start + 2.5), [6.0, 8.0, 0.0]));
+	assert!(trajectory.velocity_at(start + 5.25) == [0.0; 3]);
+	assert!(trajectory.position_at(-1.0) == [0.0; 3]);
+	assert!(trajectory.position_at(trajectory.duration() + 1.0) == [500.0, 40.0, 200.0]);
+
+	assert!(let Err(GcodeError::MissingFeedRate { line: 1 }) = GcodeTrajectory::parse("G1 X10", &options));
+	assert!(let Err(GcodeError::UnsupportedCode { line: 2, .. }) = GcodeTrajectory::parse("G0 X1\nG2 X10 I5", &options));
+	assert!(let Err(GcodeError::InvalidWord { line: 1, .. }) = GcodeTrajectory::parse("G0 X1.2.3", &options));
+}
+
+#[cfg(test)]
+#[test]
+fn test_gcode_words() {
+	use assert2::{assert, let_assert};
+
+	let options = GcodeOptions::default();
+
+	// Program delimiters are skipped.
+	let_assert!(Ok(trajectory) = GcodeTrajectory::parse("%\nG0 X10\n%", &options));
+	assert!(trajectory.segments().len() == 1);
+
+	// Words that do not start with a letter are invalid, also when they start with a multi-byte character.
+	let_assert!(Err(GcodeError::InvalidWord { line: 1, word }) = GcodeTrajectory::parse("µ10", &options));
+	assert!(word == "µ10");
+	assert!(let Err(GcodeError::InvalidWord { line: 2, .. }) = GcodeTrajectory::parse("G0\nX1 €", &options));
+	assert!(let Err(GcodeError::InvalidWord { line: 1, .. }) = GcodeTrajectory::parse("G0 Xé", &options));
+}
@@ -1,4 +1,5 @@
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmHeader {
     /// sequence number (to be able to find lost messages)
     #[prost(uint32, optional, tag="1")]
@@ -12,6 +13,7 @@ pub struct EgmHeader {
 /// Nested message and enum types in `EgmHeader`.
 pub mod egm_header {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     #[repr(i32)]
     pub enum MessageType {
         MsgtypeUndefined = 0,
@@ -26,6 +28,8 @@ pub mod egm_header {
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy)]
 pub struct EgmCartesian {
     #[prost(double, required, tag="1")]
     pub x: f64,
@@ -38,6 +42,8 @@ pub struct EgmCartesian {
 // If both are sent, Euler angles have higher priority.
 
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy)]
 pub struct EgmQuaternion {
     #[prost(double, required, tag="1")]
     pub u0: f64,
@@ -49,6 +55,8 @@ pub struct EgmQuaternion {
     pub u3: f64,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy)]
 pub struct EgmEuler {
     #[prost(double, required, tag="1")]
     pub x: f64,
@@ -58,6 +66,8 @@ pub struct EgmEuler {
     pub z: f64,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy)]
 pub struct EgmClock {
     #[prost(uint64, required, tag="1")]
     pub sec: u64,
@@ -65,6 +75,7 @@ pub struct EgmClock {
     pub usec: u64,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmPose {
     #[prost(message, optional, tag="1")]
     pub pos: ::core::option::Option<EgmCartesian>,
@@ -74,22 +85,26 @@ pub struct EgmPose {
     pub euler: ::core::option::Option<EgmEuler>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmCartesianSpeed {
     #[prost(double, repeated, packed="false", tag="1")]
     pub value: ::prost::alloc::vec::Vec<f64>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmJoints {
     #[prost(double, repeated, packed="false", tag="1")]
     pub joints: ::prost::alloc::vec::Vec<f64>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmExternalJoints {
     #[prost(double, repeated, packed="false", tag="1")]
     pub joints: ::prost::alloc::vec::Vec<f64>,
 }
 /// Is used for position streaming (source: controller) and position guidance (source: sensor)
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmPlanned {
     #[prost(message, optional, tag="1")]
     pub joints: ::core::option::Option<EgmJoints>,
@@ -101,6 +116,7 @@ pub struct EgmPlanned {
     pub time: ::core::option::Option<EgmClock>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmSpeedRef {
     #[prost(message, optional, tag="1")]
     pub joints: ::core::option::Option<EgmJoints>,
@@ -110,6 +126,8 @@ pub struct EgmSpeedRef {
     pub external_joints: ::core::option::Option<EgmJoints>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Copy)]
 pub struct EgmPathCorr {
     /// Sensor measurement (x, y, z) relative the sensor tool coordinate system
     #[prost(message, required, tag="1")]
@@ -119,6 +137,7 @@ pub struct EgmPathCorr {
     pub age: u32,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmFeedBack {
     #[prost(message, optional, tag="1")]
     pub joints: ::core::option::Option<EgmJoints>,
@@ -130,6 +149,7 @@ pub struct EgmFeedBack {
     pub time: ::core::option::Option<EgmClock>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmMotorState {
     #[prost(enumeration="egm_motor_state::MotorStateType", required, tag="1")]
     pub state: i32,
@@ -137,6 +157,7 @@ pub struct EgmMotorState {
 /// Nested message and enum types in `EgmMotorState`.
 pub mod egm_motor_state {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     #[repr(i32)]
     pub enum MotorStateType {
         MotorsUndefined = 0,
@@ -145,6 +166,7 @@ pub mod egm_motor_state {
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmMciState {
     #[prost(enumeration="egm_mci_state::MciStateType", required, tag="1", default="MciUndefined")]
     pub state: i32,
@@ -152,6 +174,7 @@ pub struct EgmMciState {
 /// Nested message and enum types in `EgmMCIState`.
 pub mod egm_mci_state {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     #[repr(i32)]
     pub enum MciStateType {
         MciUndefined = 0,
@@ -161,6 +184,7 @@ pub mod egm_mci_state {
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmRapidCtrlExecState {
     #[prost(enumeration="egm_rapid_ctrl_exec_state::RapidCtrlExecStateType", required, tag="1", default="RapidUndefined")]
     pub state: i32,
@@ -168,6 +192,7 @@ pub struct EgmRapidCtrlExecState {
 /// Nested message and enum types in `EgmRapidCtrlExecState`.
 pub mod egm_rapid_ctrl_exec_state {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
     #[repr(i32)]
     pub enum RapidCtrlExecStateType {
         RapidUndefined = 0,
@@ -176,17 +201,20 @@ pub mod egm_rapid_ctrl_exec_state {
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmTestSignals {
     #[prost(double, repeated, packed="false", tag="1")]
     pub signals: ::prost::alloc::vec::Vec<f64>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmMeasuredForce {
     #[prost(double, repeated, packed="false", tag="1")]
     pub force: ::prost::alloc::vec::Vec<f64>,
 }
 /// Robot controller outbound message, sent from the controller to the sensor during position guidance and position streaming
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmRobot {
     #[prost(message, optional, tag="1")]
     pub header: ::core::option::Option<EgmHeader>,
@@ -211,6 +239,7 @@ pub struct EgmRobot {
 }
 /// Robot controller inbound message, sent from sensor to the controller during position guidance
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmSensor {
     #[prost(message, optional, tag="1")]
     pub header: ::core::option::Option<EgmHeader>,
@@ -221,6 +250,7 @@ pub struct EgmSensor {
 }
 /// Robot controller inbound message, sent from sensor during path correction
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EgmSensorPathCorr {
     #[prost(message, optional, tag="1")]
     pub header: ::core::option::Option<EgmHeader>,
@@ -1 +1,5 @@
+#[cfg(not(feature = "robotware-6-10"))]
 include!("abb.egm.rs");
+
+#[cfg(feature = "robotware-6-10")]
+include!("robotware_6_10/abb.egm.rs");
@@ -0,0 +1,30 @@
+use crate::msg;
+
+// DVec3
+
+impl From<msg::EgmCartesian> for glam::DVec3 {
+	fn from(other: msg::EgmCartesian) -> Self {
+		Self::new(other.x, other.y, other.z)
+	}
+}
+
+impl From<glam::DVec3> for msg::EgmCartesian {
+	fn from(other: glam::DVec3) -> Self {
+		Self::from_mm(other.x, other.y, other.z)
+	}
+}
+
+// DQuat
+
+impl From<msg::EgmQuaternion> for glam::DQuat {
+	fn from(other: msg::EgmQuaternion) -> Self {
+		let [x, y, z] = other.vector();
+		Self::from_xyzw(x, y, z, other.scalar())
+	}
+}
+
+impl From<glam::DQuat> for msg::EgmQuaternion {
+	fn from(other: glam::DQuat) -> Self {
+		Self::from_scalar_vector(other.w, [other.x, other.y, other.z])
+	}
+}
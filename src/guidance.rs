@@ -0,0 +1,313 @@
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+
+use crate::keepalive::KeepaliveMode;
+use crate::middleware::Middleware;
+use crate::msg::{EgmClock, EgmJoints, EgmPose, EgmRobot, EgmSensor, EgmSpeedRef};
+use crate::sync_peer::ConnectedEgmPeer;
+use crate::transport::EgmTransport;
+use crate::{MessageRejectedError, ReceiveError, SendError};
+
+/// Connected peer for a session started with `EGMActJoint`, which only sends joint targets.
+///
+/// The controller silently ignores pose targets in a joint guidance session, and joint targets in a pose guidance session.
+/// Wrapping the peer in [`JointGuidance`] or [`PoseGuidance`] turns that mistake into a compile error.
+///
+/// The wrapper keeps track of the sequence number of the sent messages.
+#[derive(Debug)]
+pub struct JointGuidance<T = UdpSocket> {
+	peer: ConnectedEgmPeer<T>,
+	sequence_number: u32,
+}
+
+/// Connected peer for a session started with `EGMActPose`, which only sends pose targets.
+///
+/// See [`JointGuidance`] for the motivation.
+///
+/// The wrapper keeps track of the sequence number of the sent messages.
+#[derive(Debug)]
+pub struct PoseGuidance<T = UdpSocket> {
+	peer: ConnectedEgmPeer<T>,
+	sequence_number: u32,
+}
+
+impl<T: EgmTransport> JointGuidance<T> {
+	/// Wrap a connected peer for a joint guidance session.
+	pub fn new(peer: ConnectedEgmPeer<T>) -> Self {
+		Self { peer, sequence_number: 0 }
+	}
+
+	/// Get a shared reference to the inner peer.
+	pub fn peer(&self) -> &ConnectedEgmPeer<T> {
+		&self.peer
+	}
+
+	/// Consume self and get the inner peer.
+	pub fn into_peer(self) -> ConnectedEgmPeer<T> {
+		self.peer
+	}
+
+	/// Get the sequence number of the next message.
+	pub fn sequence_number(&self) -> u32 {
+		self.sequence_number
+	}
+
+	/// Receive a message from the robot controller.
+	pub fn recv(&mut self) -> Result<EgmRobot, ReceiveError> {
+		self.peer.recv()
+	}
+
+	/// Send a joint target.
+	pub fn send_target(&mut self, joints: impl Into<EgmJoints>, time: impl Into<EgmClock>) -> Result<(), SendError> {
+		let msg = EgmSensor::joint_target(self.sequence_number, joints, time);
+		self.send(&msg)
+	}
+
+	/// Send a joint target with a joint space speed reference.
+	pub fn send_target_with_speed(&mut self, joints: impl Into<EgmJoints>, speed: impl Into<EgmJoints>, time: impl Into<EgmClock>) -> Result<(), SendError> {
+		let msg = EgmSensor::joint_target_with_speed(self.sequence_number, joints, speed, time);
+		self.send(&msg)
+	}
+
+	/// Send a joint target with a full speed reference.
+	pub fn send_target_with_speed_refs(&mut self, joints: impl Into<EgmJoints>, speed_ref: EgmSpeedRef, time: impl Into<EgmClock>) -> Result<(), SendError> {
+		let msg = EgmSensor::joint_target_with_speed_refs(self.sequence_number, joints, speed_ref, time);
+		self.send(&msg)
+	}
+
+	fn send(&mut self, msg: &EgmSensor) -> Result<(), SendError> {
+		self.peer.send(msg)?;
+		self.sequence_number = self.sequence_number.wrapping_add(1);
+		Ok(())
+	}
+}
+
+impl<T: EgmTransport> PoseGuidance<T> {
+	/// Wrap a connected peer for a pose guidance session.
+	pub fn new(peer: ConnectedEgmPeer<T>) -> Self {
+		Self { peer, sequence_number: 0 }
+	}
+
+	/// Get a shared reference to the inner peer.
+	pub fn peer(&self) -> &ConnectedEgmPeer<T> {
+		&self.peer
+	}
+
+	/// Consume self and get the inner peer.
+	pub fn into_peer(self) -> ConnectedEgmPeer<T> {
+		self.peer
+	}
+
+	/// Get the sequence number of the next message.
+	pub fn sequence_number(&self) -> u32 {
+		self.sequence_number
+	}
+
+	/// Receive a message from the robot controller.
+	pub fn recv(&mut self) -> Result<EgmRobot, ReceiveError> {
+		self.peer.recv()
+	}
+
+	/// Send a pose target.
+	pub fn send_target(&mut self, pose: impl Into<EgmPose>, time: impl Into<EgmClock>) -> Result<(), SendError> {
+		let msg = EgmSensor::pose_target(self.sequence_number, pose, time);
+		self.send(&msg)
+	}
+
+	/// Send a pose target with a full speed reference.
+	pub fn send_target_with_speed_refs(&mut self, pose: impl Into<EgmPose>, speed_ref: EgmSpeedRef, time: impl Into<EgmClock>) -> Result<(), SendError> {
+		let msg = EgmSensor::pose_target_with_speed_refs(self.sequence_number, pose, speed_ref, time);
+		self.send(&msg)
+	}
+
+	fn send(&mut self, msg: &EgmSensor) -> Result<(), SendError> {
+		self.peer.send(msg)?;
+		self.sequence_number = self.sequence_number.wrapping_add(1);
+		Ok(())
+	}
+}
+
+/// The guidance mode of an EGM session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuidanceMode {
+	/// Joint guidance, for sessions started with `EGMActJoint`.
+	Joint,
+
+	/// Pose guidance, for sessions started with `EGMActPose`.
+	Pose,
+}
+
+impl GuidanceMode {
+	/// Infer the guidance mode of the session from the planned data in a message from the robot controller.
+	///
+	/// Returns `None` if the planned data contains both or neither joints and a pose,
+	/// in which case the mode can not be inferred from the message.
+	pub fn detect(msg: &EgmRobot) -> Option<Self> {
+		match (msg.planned_joints().is_some(), msg.planned_pose().is_some()) {
+			(true, false) => Some(Self::Joint),
+			(false, true) => Some(Self::Pose),
+			_ => None,
+		}
+	}
+
+	/// Get the guidance mode matching the target in a message to the robot controller.
+	///
+	/// Returns `None` if the message contains both or neither a joint target and a pose target.
+	pub fn of_target(msg: &EgmSensor) -> Option<Self> {
+		let planned = msg.planned.as_ref();
+		match (planned.and_then(|x| x.joints.as_ref()).is_some(), planned.and_then(|x| x.cartesian.as_ref()).is_some()) {
+			(true, false) => Some(Self::Joint),
+			(false, true) => Some(Self::Pose),
+			_ => None,
+		}
+	}
+
+	fn to_u8(self) -> u8 {
+		match self {
+			Self::Joint => 1,
+			Self::Pose => 2,
+		}
+	}
+
+	fn from_u8(value: u8) -> Option<Self> {
+		match value {
+			1 => Some(Self::Joint),
+			2 => Some(Self::Pose),
+			_ => None,
+		}
+	}
+}
+
+impl From<GuidanceMode> for KeepaliveMode {
+	fn from(other: GuidanceMode) -> Self {
+		match other {
+			GuidanceMode::Joint => Self::Joint,
+			GuidanceMode::Pose => Self::Pose,
+		}
+	}
+}
+
+/// Middleware that infers the guidance mode of the session from the planned data of incoming messages.
+///
+/// Messages from which the mode can not be inferred leave the detected mode unchanged (see [`GuidanceMode::detect`]).
+/// In strict mode, outgoing messages with a target that does not match the detected mode are rejected.
+///
+/// The detector can be cloned to keep a handle after adding it to the middleware stack of a peer.
+/// All clones share the detected mode.
+#[derive(Clone, Debug, Default)]
+pub struct GuidanceDetector {
+	mode: Arc<AtomicU8>,
+	strict: bool,
+}
+
+impl GuidanceDetector {
+	/// Create a detector that does not reject any messages.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Create a detector that rejects outgoing targets that do not match the detected mode.
+	pub fn strict() -> Self {
+		Self {
+			strict: true,
+			..Self::default()
+		}
+	}
+
+	/// Check if the detector rejects outgoing targets that do not match the detected mode.
+	pub fn is_strict(&self) -> bool {
+		self.strict
+	}
+
+	/// Get the detected guidance mode, or `None` if it has not been detected yet.
+	pub fn mode(&self) -> Option<GuidanceMode> {
+		GuidanceMode::from_u8(self.mode.load(Ordering::Relaxed))
+	}
+
+	/// Forget the detected guidance mode, for example when a new session is started.
+	pub fn reset(&self) {
+		self.mode.store(0, Ordering::Relaxed);
+	}
+}
+
+impl Middleware for GuidanceDetector {
+	fn on_send(&self, msg: &mut EgmSensor) -> Result<(), MessageRejectedError> {
+		if !self.strict {
+			return Ok(());
+		}
+		match (self.mode(), GuidanceMode::of_target(msg)) {
+			(Some(detected), Some(target)) if detected != target => Err(MessageRejectedError::new(format!(
+				"{:?} target does not match the {:?} guidance mode of the session",
+				target, detected
+			))),
+			_ => Ok(()),
+		}
+	}
+
+	fn on_recv(&self, msg: &mut EgmRobot) -> Result<(), MessageRejectedError> {
+		if let Some(mode) = GuidanceMode::detect(msg) {
+			self.mode.store(mode.to_u8(), Ordering::Relaxed);
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_guidance() {
+	use crate::testing::MockRobot;
+	use assert2::assert;
+
+	let robot = MockRobot::new();
+	let mut joint = JointGuidance::new(ConnectedEgmPeer::from_transport(robot.clone()));
+	assert!(let Ok(()) = joint.send_target(vec![1.0; 6], EgmClock::new(0, 4_000)));
+	assert!(let Ok(()) = joint.send_target_with_speed(vec![1.0; 6], vec![0.5; 6], EgmClock::new(0, 8_000)));
+	assert!(joint.sequence_number() == 2);
+
+	let mut pose = PoseGuidance::new(joint.into_peer());
+	assert!(let Ok(()) = pose.send_target(EgmPose::new([1.0, 2.0, 3.0], crate::msg::EgmQuaternion::IDENTITY), EgmClock::new(0, 12_000)));
+
+	let received = robot.take_received();
+	assert!(received.len() == 3);
+	assert!(received[1].header.as_ref().unwrap().seqno == Some(1));
+	assert!(received[1].planned.as_ref().unwrap().cartesian.is_none());
+	assert!(received[2].header.as_ref().unwrap().seqno == Some(0));
+	assert!(received[2].planned.as_ref().unwrap().joints.is_none());
+	assert!(received[2].planned.as_ref().unwrap().cartesian.is_some());
+}
+
+#[cfg(test)]
+#[test]
+fn test_guidance_detector() {
+	use crate::msg::{EgmPlanned, EgmQuaternion};
+	use crate::testing::MockRobot;
+	use assert2::assert;
+
+	let message = |planned: EgmPlanned| EgmRobot {
+		planned: Some(planned),
+		..Default::default()
+	};
+	let pose = EgmPose::new([1.0, 2.0, 3.0], EgmQuaternion::IDENTITY);
+	let robot = MockRobot::with_responses(vec![
+		EgmRobot::default(),
+		message(EgmPlanned::pose(pose.clone(), EgmClock::new(0, 0))),
+		EgmRobot::default(),
+	]);
+	let detector = GuidanceDetector::strict();
+	let mut peer = ConnectedEgmPeer::from_transport(robot);
+	peer.middleware_mut().push(detector.clone());
+
+	assert!(let Ok(_) = peer.recv());
+	assert!(detector.mode() == None);
+	assert!(let Ok(()) = peer.send(&EgmSensor::joint_target(0, vec![0.0; 6], EgmClock::new(0, 0))));
+
+	assert!(let Ok(_) = peer.recv());
+	assert!(let Ok(_) = peer.recv());
+	assert!(detector.mode() == Some(GuidanceMode::Pose));
+	assert!(let Err(SendError::Rejected(_)) = peer.send(&EgmSensor::joint_target(1, vec![0.0; 6], EgmClock::new(0, 0))));
+	assert!(let Ok(()) = peer.send(&EgmSensor::pose_target(1, pose, EgmClock::new(0, 0))));
+
+	detector.reset();
+	assert!(detector.mode() == None);
+}
@@ -0,0 +1,240 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use prost::Message;
+
+use crate::msg::{EgmJoints, EgmPose, EgmRobot, EgmSensor};
+
+/// Environment variable that makes [`Capture::assert_snapshot`] overwrite existing snapshots.
+pub const UPDATE_SNAPSHOTS_ENV: &str = "ABBEGM_UPDATE_SNAPSHOTS";
+
+/// Read a trace of messages from the robot controller.
+///
+/// The trace must consist of length-delimited protobuf messages, as written by [`write_trace`].
+pub fn read_trace(data: &[u8]) -> Result<Vec<EgmRobot>, crate::codec::DecodeError> {
+	let mut buffer = data;
+	let mut trace = Vec::new();
+	while !buffer.is_empty() {
+		trace.push(EgmRobot::decode_length_delimited(&mut buffer)?);
+	}
+	Ok(trace)
+}
+
+/// Write a trace of messages from the robot controller as length-delimited protobuf messages.
+pub fn write_trace(trace: &[EgmRobot]) -> Vec<u8> {
+	let mut buffer = Vec::new();
+	for msg in trace {
+		// Encoding into a Vec can not fail.
+		msg.encode_length_delimited(&mut buffer).unwrap();
+	}
+	buffer
+}
+
+/// Read a trace from a file, see [`read_trace`].
+pub fn read_trace_file(path: impl AsRef<Path>) -> std::io::Result<Vec<EgmRobot>> {
+	let data = std::fs::read(path)?;
+	read_trace(&data).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Write a trace to a file, see [`write_trace`].
+pub fn write_trace_file(path: impl AsRef<Path>, trace: &[EgmRobot]) -> std::io::Result<()> {
+	std::fs::write(path, write_trace(trace))
+}
+
+/// Feed a recorded trace into a controller and capture the messages it produces.
+///
+/// The controller is called once for every message in the trace, in order.
+/// It can return a message to send to the robot controller, or `None` to send nothing for that cycle.
+///
+/// No sockets are involved, so the run is fully deterministic as long as the controller is.
+pub fn run<F>(trace: &[EgmRobot], mut controller: F) -> Capture
+where
+	F: FnMut(&EgmRobot) -> Option<EgmSensor>,
+{
+	let sent = trace
+		.iter()
+		.enumerate()
+		.filter_map(|(index, state)| Some(CapturedMessage { index, msg: controller(state)? }))
+		.collect();
+	Capture { sent }
+}
+
+/// A message produced by the controller in a harness run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapturedMessage {
+	/// The index of the message in the trace that the controller responded to.
+	pub index: usize,
+
+	/// The message produced by the controller.
+	pub msg: EgmSensor,
+}
+
+/// The messages produced by a controller in a harness run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Capture {
+	/// The messages produced by the controller, in order.
+	pub sent: Vec<CapturedMessage>,
+}
+
+impl Capture {
+	/// Get the number of captured messages.
+	pub fn len(&self) -> usize {
+		self.sent.len()
+	}
+
+	/// Check if the controller did not produce any message.
+	pub fn is_empty(&self) -> bool {
+		self.sent.is_empty()
+	}
+
+	/// Render the captured messages as text for snapshot comparison.
+	///
+	/// Each message is written on a single line.
+	/// Values are rounded to 6 decimals, so that tiny numerical differences do not change the snapshot.
+	pub fn to_snapshot(&self) -> String {
+		let mut output = String::new();
+		for captured in &self.sent {
+			let _ = write!(output, "{}:", captured.index);
+			write_sensor(&mut output, &captured.msg);
+			output.push('\n');
+		}
+		output
+	}
+
+	/// Compare the captured messages with a snapshot file.
+	///
+	/// If the file does not exist yet, or if the `ABBEGM_UPDATE_SNAPSHOTS` environment variable is set,
+	/// the snapshot is written to the file instead.
+	///
+	/// # Panics
+	/// This function panics if the snapshot differs from the file, or if the file can not be read or written.
+	#[track_caller]
+	pub fn assert_snapshot(&self, path: impl AsRef<Path>) {
+		let path = path.as_ref();
+		let snapshot = self.to_snapshot();
+		if std::env::var_os(UPDATE_SNAPSHOTS_ENV).is_some() || !path.exists() {
+			if let Err(e) = std::fs::write(path, &snapshot) {
+				panic!("failed to write snapshot {}: {}", path.display(), e);
+			}
+			return;
+		}
+
+		let expected = match std::fs::read_to_string(path) {
+			Ok(x) => x,
+			Err(e) => panic!("failed to read snapshot {}: {}", path.display(), e),
+		};
+		if let Some((line, (actual, expected))) = snapshot.lines().zip(expected.lines()).enumerate().find(|(_, (a, b))| a != b) {
+			panic!(
+				"snapshot {} differs on line {}:\n  actual:   {}\n  expected: {}",
+				path.display(),
+				line + 1,
+				actual,
+				expected
+			);
+		}
+		let actual_lines = snapshot.lines().count();
+		let expected_lines = expected.lines().count();
+		if actual_lines != expected_lines {
+			panic!("snapshot {} has {} lines, but {} were captured", path.display(), expected_lines, actual_lines);
+		}
+	}
+}
+
+fn write_values(output: &mut String, name: &str, values: &[f64]) {
+	let _ = write!(output, " {}=[", name);
+	for (i, value) in values.iter().enumerate() {
+		if i > 0 {
+			output.push_str(", ");
+		}
+		let _ = write!(output, "{:.6}", value);
+	}
+	output.push(']');
+}
+
+fn write_joints(output: &mut String, name: &str, joints: Option<&EgmJoints>) {
+	if let Some(joints) = joints {
+		write_values(output, name, &joints.joints);
+	}
+}
+
+fn write_pose(output: &mut String, name: &str, pose: Option<&EgmPose>) {
+	let pose = match pose {
+		Some(x) => x,
+		None => return,
+	};
+	if let Some(pos) = &pose.pos {
+		write_values(output, &format!("{}.pos", name), &[pos.x, pos.y, pos.z]);
+	}
+	if let Some(orient) = &pose.orient {
+		write_values(output, &format!("{}.orient", name), &[orient.u0, orient.u1, orient.u2, orient.u3]);
+	}
+	if let Some(euler) = &pose.euler {
+		write_values(output, &format!("{}.euler", name), &[euler.x, euler.y, euler.z]);
+	}
+}
+
+fn write_sensor(output: &mut String, msg: &EgmSensor) {
+	if let Some(seqno) = msg.header.as_ref().and_then(|x| x.seqno) {
+		let _ = write!(output, " seqno={}", seqno);
+	}
+	if let Some(planned) = &msg.planned {
+		write_joints(output, "planned.joints", planned.joints.as_ref());
+		write_pose(output, "planned.cartesian", planned.cartesian.as_ref());
+		write_joints(output, "planned.external_joints", planned.external_joints.as_ref());
+		if let Some(time) = &planned.time {
+			let _ = write!(output, " planned.time={}.{:06}", time.sec, time.usec);
+		}
+	}
+	if let Some(speed_ref) = &msg.speed_ref {
+		write_joints(output, "speed_ref.joints", speed_ref.joints.as_ref());
+		if let Some(cartesians) = &speed_ref.cartesians {
+			write_values(output, "speed_ref.cartesians", &cartesians.value);
+		}
+		write_joints(output, "speed_ref.external_joints", speed_ref.external_joints.as_ref());
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_harness() {
+	use crate::msg::{EgmClock, EgmFeedBack};
+	use assert2::assert;
+
+	let trace: Vec<_> = (0..4u32)
+		.map(|i| EgmRobot {
+			feed_back: Some(EgmFeedBack {
+				joints: Some(EgmJoints::from_degrees(vec![f64::from(i); 6])),
+				time: Some(EgmClock::new(0, u64::from(i) * 4000)),
+				..Default::default()
+			}),
+			..Default::default()
+		})
+		.collect();
+	assert!(read_trace(&write_trace(&trace)).unwrap() == trace);
+
+	// Send the feedback plus one degree on every other cycle.
+	let controller = |state: &EgmRobot| {
+		let joints = state.feedback_joints()?;
+		if joints[0] as u32 % 2 == 1 {
+			return None;
+		}
+		let target: Vec<f64> = joints.iter().map(|x| x + 1.0).collect();
+		Some(EgmSensor::joint_target(joints[0] as u32, target, EgmClock::new(0, 0)))
+	};
+	let capture = run(&trace, controller);
+	assert!(capture.len() == 2);
+	assert!(capture.sent[1].index == 2);
+	assert!(capture == run(&trace, controller));
+
+	let snapshot = capture.to_snapshot();
+	let lines: Vec<_> = snapshot.lines().collect();
+	assert!(lines.len() == 2);
+	assert!(lines[1].starts_with("2: seqno=2 planned.joints=[3.000000, 3.000000,"));
+	assert!(lines[1].ends_with("planned.time=0.000000"));
+
+	let path = std::env::temp_dir().join(format!("abbegm-harness-{}.snap", std::process::id()));
+	let _ = std::fs::remove_file(&path);
+	capture.assert_snapshot(&path);
+	capture.assert_snapshot(&path);
+	std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,372 @@
+use std::time::{Duration, Instant};
+
+use crate::msg::{EgmRobot, EgmSensor};
+use crate::proxy::{ProxyHook, Verdict};
+use crate::transport::EgmTransport;
+
+/// Parameters of a degraded network.
+///
+/// All probabilities are between 0 and 1, and are applied independently to each message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImpairmentConfig {
+	/// The probability that a message is lost.
+	pub loss: f64,
+
+	/// The probability that a message is delivered twice.
+	pub duplication: f64,
+
+	/// The probability that a message is held back by [`ImpairmentConfig::reorder_delay`], so that later messages overtake it.
+	pub reordering: f64,
+
+	/// The extra delay of reordered messages.
+	pub reorder_delay: Duration,
+
+	/// The fixed latency added to every message.
+	pub latency: Duration,
+
+	/// The maximum random latency added on top of the fixed latency, uniformly distributed.
+	pub jitter: Duration,
+}
+
+impl Default for ImpairmentConfig {
+	fn default() -> Self {
+		Self {
+			loss: 0.0,
+			duplication: 0.0,
+			reordering: 0.0,
+			reorder_delay: Duration::from_millis(10),
+			latency: Duration::ZERO,
+			jitter: Duration::ZERO,
+		}
+	}
+}
+
+/// Counters of the impairments applied by an [`Impairment`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImpairmentStats {
+	/// The number of messages seen.
+	pub messages: u64,
+
+	/// The number of lost messages.
+	pub lost: u64,
+
+	/// The number of duplicated messages.
+	pub duplicated: u64,
+
+	/// The number of reordered messages.
+	pub reordered: u64,
+}
+
+/// Injects packet loss, duplication, reordering and latency jitter into a stream of messages.
+///
+/// The impairment is driven by a seeded pseudo-random generator, so a failing test can be reproduced with the same seed.
+/// Use it with the proxy through [`ImpairmentHook`], or with a transport such as [`crate::testing::MockRobot`] through [`ImpairedTransport`].
+#[derive(Clone, Debug)]
+pub struct Impairment {
+	config: ImpairmentConfig,
+	rng: u64,
+	stats: ImpairmentStats,
+}
+
+impl Impairment {
+	/// Create an impairment with the given parameters and random seed.
+	pub fn new(config: ImpairmentConfig, seed: u64) -> Self {
+		Self {
+			config,
+			rng: seed,
+			stats: ImpairmentStats::default(),
+		}
+	}
+
+	/// Get the parameters of the impairment.
+	pub fn config(&self) -> &ImpairmentConfig {
+		&self.config
+	}
+
+	/// Change the parameters of the impairment, for example to simulate a network that degrades over time.
+	pub fn set_config(&mut self, config: ImpairmentConfig) {
+		self.config = config;
+	}
+
+	/// Get the counters of the impairments applied so far.
+	pub fn stats(&self) -> ImpairmentStats {
+		self.stats
+	}
+
+	/// Decide what happens to the next message.
+	///
+	/// The verdict is [`Verdict::Drop`] for lost messages, [`Verdict::Duplicate`] for duplicated messages
+	/// and [`Verdict::Delay`] otherwise, possibly with a delay of zero.
+	pub fn decide(&mut self) -> Verdict {
+		self.stats.messages += 1;
+		if self.chance(self.config.loss) {
+			self.stats.lost += 1;
+			return Verdict::Drop;
+		}
+		let first = self.delay();
+		if self.chance(self.config.duplication) {
+			self.stats.duplicated += 1;
+			let second = self.delay();
+			return Verdict::Duplicate(first, second);
+		}
+		Verdict::Delay(first)
+	}
+
+	/// Get the delay of a single delivery.
+	fn delay(&mut self) -> Duration {
+		let jitter = self.config.jitter.mul_f64(self.next_f64());
+		let mut delay = self.config.latency + jitter;
+		if self.chance(self.config.reordering) {
+			self.stats.reordered += 1;
+			delay += self.config.reorder_delay;
+		}
+		delay
+	}
+
+	fn chance(&mut self, probability: f64) -> bool {
+		probability > 0.0 && self.next_f64() < probability
+	}
+
+	/// Get a uniformly distributed number in the range [0, 1) from a splitmix64 generator.
+	fn next_f64(&mut self) -> f64 {
+		self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+		let mut z = self.rng;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		z ^= z >> 31;
+		(z >> 11) as f64 / (1u64 << 53) as f64
+	}
+}
+
+/// Proxy hook that impairs the messages in both directions.
+#[derive(Clone, Debug)]
+pub struct ImpairmentHook {
+	/// The impairment of messages from the robot controller.
+	pub from_robot: Impairment,
+
+	/// The impairment of messages to the robot controller.
+	pub to_robot: Impairment,
+}
+
+impl ImpairmentHook {
+	/// Create a hook with the same parameters in both directions.
+	///
+	/// The directions use different random streams derived from the seed.
+	pub fn new(config: ImpairmentConfig, seed: u64) -> Self {
+		Self {
+			from_robot: Impairment::new(config.clone(), seed),
+			to_robot: Impairment::new(config, !seed),
+		}
+	}
+}
+
+impl ProxyHook for ImpairmentHook {
+	fn on_robot_message(&mut self, _msg: &mut EgmRobot) -> Verdict {
+		self.from_robot.decide()
+	}
+
+	fn on_sensor_message(&mut self, _msg: &mut EgmSensor) -> Verdict {
+		self.to_robot.decide()
+	}
+}
+
+/// Transport wrapper that impairs the datagrams sent and received through another transport.
+///
+/// Delayed datagrams are queued in the wrapper.
+/// Received datagrams that are not due yet are reported as [`std::io::ErrorKind::WouldBlock`],
+/// and delayed sent datagrams are sent by a later call to any of the transport functions.
+/// This fits non-blocking transports such as [`crate::testing::MockRobot`], which are polled in a loop.
+pub struct ImpairedTransport<T: EgmTransport> {
+	inner: T,
+	incoming: Impairment,
+	outgoing: Impairment,
+	received: Vec<(Instant, Vec<u8>, Option<T::Address>)>,
+	sent: Vec<(Instant, Vec<u8>, Option<T::Address>)>,
+}
+
+impl<T: EgmTransport> ImpairedTransport<T>
+where
+	T::Address: Clone,
+{
+	/// Wrap a transport with impairments for incoming and outgoing datagrams.
+	pub fn new(inner: T, incoming: Impairment, outgoing: Impairment) -> Self {
+		Self {
+			inner,
+			incoming,
+			outgoing,
+			received: Vec::new(),
+			sent: Vec::new(),
+		}
+	}
+
+	/// Get the impairment of incoming datagrams.
+	pub fn incoming(&self) -> &Impairment {
+		&self.incoming
+	}
+
+	/// Get the impairment of outgoing datagrams.
+	pub fn outgoing(&self) -> &Impairment {
+		&self.outgoing
+	}
+
+	/// Get a reference to the wrapped transport.
+	pub fn inner(&self) -> &T {
+		&self.inner
+	}
+
+	/// Get a mutable reference to the wrapped transport.
+	pub fn inner_mut(&mut self) -> &mut T {
+		&mut self.inner
+	}
+
+	/// Consume the wrapper and get the wrapped transport, discarding queued datagrams.
+	pub fn into_inner(self) -> T {
+		self.inner
+	}
+
+	/// Get the number of delayed datagrams that have not been delivered yet, in both directions.
+	pub fn pending(&self) -> usize {
+		self.received.len() + self.sent.len()
+	}
+
+	fn receive(&mut self, buffer: &mut [u8], any_address: bool) -> std::io::Result<(usize, Option<T::Address>)> {
+		self.flush_sent()?;
+		if !self.has_due(false) {
+			let (len, address) = if any_address {
+				let (len, address) = self.inner.recv_from(buffer)?;
+				(len, Some(address))
+			} else {
+				(self.inner.recv(buffer)?, None)
+			};
+			let verdict = self.incoming.decide();
+			schedule(&mut self.received, verdict, &buffer[..len], address);
+		}
+		if !self.has_due(false) {
+			return Err(std::io::ErrorKind::WouldBlock.into());
+		}
+		let (_, datagram, address) = self.received.remove(0);
+		if datagram.len() > buffer.len() {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "receive buffer too small for datagram"));
+		}
+		buffer[..datagram.len()].copy_from_slice(&datagram);
+		Ok((datagram.len(), address))
+	}
+
+	fn transmit(&mut self, datagram: &[u8], target: Option<&T::Address>) -> std::io::Result<usize> {
+		let verdict = self.outgoing.decide();
+		schedule(&mut self.sent, verdict, datagram, target.cloned());
+		self.flush_sent()?;
+		Ok(datagram.len())
+	}
+
+	fn has_due(&mut self, sent: bool) -> bool {
+		let queue = if sent { &mut self.sent } else { &mut self.received };
+		queue.sort_by_key(|(due, _, _)| *due);
+		queue.first().map(|(due, _, _)| *due <= Instant::now()).unwrap_or(false)
+	}
+
+	fn flush_sent(&mut self) -> std::io::Result<()> {
+		while self.has_due(true) {
+			let (_, datagram, target) = self.sent.remove(0);
+			match target {
+				Some(target) => self.inner.send_to(&datagram, &target)?,
+				None => self.inner.send(&datagram)?,
+			};
+		}
+		Ok(())
+	}
+}
+
+/// Queue the deliveries of a datagram according to a verdict.
+fn schedule<A: Clone>(queue: &mut Vec<(Instant, Vec<u8>, Option<A>)>, verdict: Verdict, datagram: &[u8], address: Option<A>) {
+	let now = Instant::now();
+	match verdict {
+		Verdict::Forward => queue.push((now, datagram.to_vec(), address)),
+		Verdict::Drop => (),
+		Verdict::Delay(delay) => queue.push((now + delay, datagram.to_vec(), address)),
+		Verdict::Duplicate(first, second) => {
+			queue.push((now + first, datagram.to_vec(), address.clone()));
+			queue.push((now + second, datagram.to_vec(), address));
+		},
+	}
+}
+
+impl<T: EgmTransport> EgmTransport for ImpairedTransport<T>
+where
+	T::Address: Clone,
+{
+	type Address = T::Address;
+
+	fn recv(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+		Ok(self.receive(buffer, false)?.0)
+	}
+
+	fn recv_from(&mut self, buffer: &mut [u8]) -> std::io::Result<(usize, Self::Address)> {
+		match self.receive(buffer, true)? {
+			(len, Some(address)) => Ok((len, address)),
+			(_, None) => Err(std::io::Error::other("datagram was received without address")),
+		}
+	}
+
+	fn send(&mut self, datagram: &[u8]) -> std::io::Result<usize> {
+		self.transmit(datagram, None)
+	}
+
+	fn send_to(&mut self, datagram: &[u8], target: &Self::Address) -> std::io::Result<usize> {
+		self.transmit(datagram, Some(target))
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_impairment() {
+	use crate::msg::{EgmClock, EgmHeader};
+	use crate::sync_peer::ConnectedEgmPeer;
+	use crate::testing::MockRobot;
+	use assert2::{assert, let_assert};
+
+	let config = ImpairmentConfig {
+		loss: 0.25,
+		duplication: 0.25,
+		reordering: 0.1,
+		..Default::default()
+	};
+	let mut impairment = Impairment::new(config.clone(), 42);
+	let verdicts: Vec<_> = (0..1000).map(|_| impairment.decide()).collect();
+	let stats = impairment.stats();
+	assert!(stats.messages == 1000);
+	assert!(stats.lost > 200 && stats.lost < 300);
+	assert!(stats.duplicated > 150 && stats.duplicated < 225);
+	assert!(stats.reordered > 50 && stats.reordered < 150);
+	let mut same_seed = Impairment::new(config, 42);
+	assert!((0..1000).map(|_| same_seed.decide()).collect::<Vec<_>>() == verdicts);
+
+	let message = |seqno| EgmRobot {
+		header: Some(EgmHeader::data(seqno, 0)),
+		..Default::default()
+	};
+	let robot = MockRobot::with_responses((1..=3).map(message));
+	let duplicate = ImpairmentConfig {
+		duplication: 1.0,
+		..Default::default()
+	};
+	let delay = ImpairmentConfig {
+		latency: Duration::from_millis(20),
+		..Default::default()
+	};
+	let transport = ImpairedTransport::new(robot.clone(), Impairment::new(duplicate, 1), Impairment::new(delay, 2));
+	let mut peer = ConnectedEgmPeer::from_transport(transport);
+
+	let mut received = Vec::new();
+	while let Ok(msg) = peer.recv() {
+		received.push(msg.sequence_number().unwrap());
+	}
+	assert!(received == [1, 1, 2, 2, 3, 3]);
+
+	let_assert!(Ok(()) = peer.send(&EgmSensor::joint_target(1, vec![0.0; 6], EgmClock::new(0, 0))));
+	assert!(robot.received().is_empty());
+	std::thread::sleep(Duration::from_millis(25));
+	assert!(let Err(_) = peer.recv());
+	assert!(robot.received().len() == 1);
+	assert!(peer.socket().pending() == 0);
+}
@@ -0,0 +1,140 @@
+use std::convert::TryFrom;
+
+use crate::msg::{EgmClock, EgmRobot, EgmSensor};
+
+/// The pose that incremental offsets are applied to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OffsetReference {
+	/// Apply offsets to the latest feedback pose of the robot.
+	Feedback,
+
+	/// Apply offsets to the last commanded pose.
+	///
+	/// The first offset is applied to the feedback pose, since nothing has been commanded yet.
+	LastCommand,
+}
+
+/// Controller that turns small pose offsets into absolute pose targets.
+///
+/// Each offset is composed onto a reference pose, which is either the latest feedback pose or the last commanded pose.
+/// This is the natural interface for visual servoing, where a camera measures the required correction rather than an absolute target.
+///
+/// Offsets are expressed in the tool frame of the reference pose, with translations in millimeters.
+#[derive(Clone, Debug)]
+pub struct IncrementalController {
+	reference: OffsetReference,
+	feedback_pose: Option<nalgebra::Isometry3<f64>>,
+	feedback_time: Option<EgmClock>,
+	last_command: Option<nalgebra::Isometry3<f64>>,
+	sequence_number: u32,
+}
+
+impl IncrementalController {
+	/// Create a new controller that applies offsets to the given reference pose.
+	pub fn new(reference: OffsetReference) -> Self {
+		Self {
+			reference,
+			feedback_pose: None,
+			feedback_time: None,
+			last_command: None,
+			sequence_number: 0,
+		}
+	}
+
+	/// Update the controller with a new message from the robot.
+	pub fn update_feedback(&mut self, state: &EgmRobot) -> Result<(), IncrementalControllerError> {
+		let pose = state.feedback_pose().ok_or(IncrementalControllerError::MissingFeedbackPose)?;
+		let pose = nalgebra::Isometry3::try_from(pose).map_err(|_| IncrementalControllerError::MissingFeedbackPose)?;
+		let time = state.feedback_time().ok_or(IncrementalControllerError::MissingFeedbackTime)?;
+		self.feedback_pose = Some(pose);
+		self.feedback_time = Some(time);
+		Ok(())
+	}
+
+	/// Get the pose that the next offset will be applied to.
+	pub fn reference_pose(&self) -> Option<&nalgebra::Isometry3<f64>> {
+		match self.reference {
+			OffsetReference::Feedback => self.feedback_pose.as_ref(),
+			OffsetReference::LastCommand => self.last_command.as_ref().or(self.feedback_pose.as_ref()),
+		}
+	}
+
+	/// Get the last commanded pose.
+	pub fn last_command(&self) -> Option<&nalgebra::Isometry3<f64>> {
+		self.last_command.as_ref()
+	}
+
+	/// Compose an offset onto the reference pose and create a sensor message for the resulting pose.
+	///
+	/// The offset is expressed in the tool frame of the reference pose.
+	/// The message is timestamped with the time of the latest feedback.
+	pub fn offset_by(&mut self, delta: &nalgebra::Isometry3<f64>) -> Result<EgmSensor, IncrementalControllerError> {
+		let reference = self.reference_pose().ok_or(IncrementalControllerError::NoFeedback)?;
+		let time = self.feedback_time.ok_or(IncrementalControllerError::NoFeedback)?;
+		let target = reference * delta;
+		self.last_command = Some(target);
+
+		let message = EgmSensor::pose_target(self.sequence_number, target, time);
+		self.sequence_number = self.sequence_number.wrapping_add(1);
+		Ok(message)
+	}
+}
+
+/// Error that may occur when using an [`IncrementalController`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum IncrementalControllerError {
+	/// The robot message did not contain a complete feedback pose.
+	MissingFeedbackPose,
+
+	/// The robot message did not contain a feedback time.
+	MissingFeedbackTime,
+
+	/// No feedback has been received yet, so there is no reference pose.
+	NoFeedback,
+}
+
+impl std::fmt::Display for IncrementalControllerError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::MissingFeedbackPose => write!(f, "robot message does not contain a complete feedback pose"),
+			Self::MissingFeedbackTime => write!(f, "robot message does not contain a feedback time"),
+			Self::NoFeedback => write!(f, "no feedback received from the robot yet"),
+		}
+	}
+}
+
+impl std::error::Error for IncrementalControllerError {}
+
+#[cfg(test)]
+#[test]
+fn test_offset_by() {
+	use crate::msg;
+	use assert2::assert;
+
+	let state = EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			cartesian: Some(msg::EgmPose::new([100.0, 0.0, 0.0], msg::EgmQuaternion::IDENTITY)),
+			time: Some(EgmClock::new(1, 0)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let delta = nalgebra::Isometry3::translation(1.0, 0.0, 0.0);
+
+	let mut controller = IncrementalController::new(OffsetReference::LastCommand);
+	assert!(controller.offset_by(&delta) == Err(IncrementalControllerError::NoFeedback));
+
+	controller.update_feedback(&state).unwrap();
+	controller.offset_by(&delta).unwrap();
+	let message = controller.offset_by(&delta).unwrap();
+	let pos = message.planned.unwrap().cartesian.unwrap().pos.unwrap();
+	assert!(pos.as_mm() == [102.0, 0.0, 0.0]);
+	assert!(message.header.unwrap().seqno == Some(1));
+
+	let mut controller = IncrementalController::new(OffsetReference::Feedback);
+	controller.update_feedback(&state).unwrap();
+	controller.offset_by(&delta).unwrap();
+	let message = controller.offset_by(&delta).unwrap();
+	let pos = message.planned.unwrap().cartesian.unwrap().pos.unwrap();
+	assert!(pos.as_mm() == [101.0, 0.0, 0.0]);
+}
@@ -0,0 +1,270 @@
+use crate::middleware::Middleware;
+use crate::msg::{EgmJoints, EgmRobot, EgmSensor};
+use crate::MessageRejectedError;
+
+/// A coupling between two robot joints.
+///
+/// Robots with a parallel bar linkage, such as palletizers, often report the angle of axis 3 relative to the ground,
+/// while kinematic models usually expect it relative to axis 2, or the other way around.
+/// A coupling adds a multiple of the source joint to the coupled joint to convert between these conventions.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct JointCoupling {
+	/// The index of the robot joint that is coupled.
+	pub joint: usize,
+
+	/// The index of the robot joint it is coupled to.
+	pub source: usize,
+
+	/// The factor applied to the source joint: `user = robot + factor * source`, before reordering.
+	pub factor: f64,
+}
+
+/// Error indicating that a joint mapping is invalid.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InvalidJointMappingError {
+	/// The order is not a permutation of the joint indices.
+	InvalidOrder,
+
+	/// The number of signs or offsets does not match the number of joints.
+	LengthMismatch { expected: usize, actual: usize },
+
+	/// A coupling refers to a joint that does not exist, couples a joint to itself or uses a coupled joint as source.
+	InvalidCoupling(JointCoupling),
+}
+
+/// Conversion between the joint conventions of the robot controller and the application.
+///
+/// The mapping supports reordering joints, flipping their direction, adding offsets and coupling joints.
+/// Converting robot joints to user joints first applies the couplings, then reorders the joints, flips signs and adds offsets:
+/// `user[i] = sign[i] * coupled[order[i]] + offset[i]`.
+///
+/// The mapping implements [`Middleware`], so it can be added to a peer to convert all feedback and targets transparently.
+/// Incoming feedback and planned joints are converted to the user convention,
+/// outgoing planned joints and joint speed references are converted to the robot convention.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JointMapping {
+	order: Vec<usize>,
+	signs: Vec<f64>,
+	offsets: Vec<f64>,
+	couplings: Vec<JointCoupling>,
+}
+
+impl JointMapping {
+	/// Create a mapping that does not change the joints.
+	pub fn identity(joints: usize) -> Self {
+		Self {
+			order: (0..joints).collect(),
+			signs: vec![1.0; joints],
+			offsets: vec![0.0; joints],
+			couplings: Vec::new(),
+		}
+	}
+
+	/// Create a mapping from an order, the signs and the offsets in degrees of the user joints.
+	///
+	/// `order[i]` is the index of the robot joint that corresponds to user joint `i`.
+	pub fn new(order: Vec<usize>, signs: Vec<f64>, offsets: Vec<f64>) -> Result<Self, InvalidJointMappingError> {
+		let mut seen = vec![false; order.len()];
+		for &index in &order {
+			match seen.get_mut(index) {
+				Some(seen) if !*seen => *seen = true,
+				_ => return Err(InvalidJointMappingError::InvalidOrder),
+			}
+		}
+		for actual in [signs.len(), offsets.len()] {
+			if actual != order.len() {
+				return Err(InvalidJointMappingError::LengthMismatch { expected: order.len(), actual });
+			}
+		}
+		Ok(Self {
+			order,
+			signs,
+			offsets,
+			couplings: Vec::new(),
+		})
+	}
+
+	/// Add a coupling between two robot joints.
+	///
+	/// The source joint of a coupling can not itself be coupled to another joint.
+	pub fn with_coupling(mut self, coupling: JointCoupling) -> Result<Self, InvalidJointMappingError> {
+		let joints = self.order.len();
+		let invalid = coupling.joint >= joints
+			|| coupling.source >= joints
+			|| coupling.joint == coupling.source
+			|| self.couplings.iter().any(|x| x.joint == coupling.source || x.source == coupling.joint);
+		if invalid {
+			return Err(InvalidJointMappingError::InvalidCoupling(coupling));
+		}
+		self.couplings.push(coupling);
+		Ok(self)
+	}
+
+	/// Get the number of joints of the mapping.
+	pub fn len(&self) -> usize {
+		self.order.len()
+	}
+
+	/// Check if the mapping has no joints.
+	pub fn is_empty(&self) -> bool {
+		self.order.is_empty()
+	}
+
+	fn check_len(&self, joints: &[f64]) -> Result<(), MessageRejectedError> {
+		if joints.len() == self.len() {
+			Ok(())
+		} else {
+			Err(MessageRejectedError::new(format!("joint mapping expects {} joints, got {}", self.len(), joints.len())))
+		}
+	}
+
+	fn to_user_impl(&self, robot: &[f64], offsets: bool) -> Vec<f64> {
+		let mut coupled = robot.to_vec();
+		for coupling in &self.couplings {
+			coupled[coupling.joint] += coupling.factor * robot[coupling.source];
+		}
+		(0..self.len())
+			.map(|i| {
+				let offset = if offsets { self.offsets[i] } else { 0.0 };
+				self.signs[i] * coupled[self.order[i]] + offset
+			})
+			.collect()
+	}
+
+	fn to_robot_impl(&self, user: &[f64], offsets: bool) -> Vec<f64> {
+		let mut coupled = vec![0.0; self.len()];
+		for (i, value) in user.iter().enumerate() {
+			let offset = if offsets { self.offsets[i] } else { 0.0 };
+			coupled[self.order[i]] = (value - offset) * self.signs[i];
+		}
+		let mut robot = coupled.clone();
+		for coupling in &self.couplings {
+			robot[coupling.joint] -= coupling.factor * coupled[coupling.source];
+		}
+		robot
+	}
+
+	/// Convert joint positions in degrees from the robot convention to the user convention.
+	///
+	/// # Panics
+	/// This function panics if the number of joints does not match the mapping.
+	pub fn to_user(&self, robot: &[f64]) -> Vec<f64> {
+		assert_eq!(robot.len(), self.len(), "wrong number of joints for joint mapping");
+		self.to_user_impl(robot, true)
+	}
+
+	/// Convert joint positions in degrees from the user convention to the robot convention.
+	///
+	/// # Panics
+	/// This function panics if the number of joints does not match the mapping.
+	pub fn to_robot(&self, user: &[f64]) -> Vec<f64> {
+		assert_eq!(user.len(), self.len(), "wrong number of joints for joint mapping");
+		self.to_robot_impl(user, true)
+	}
+
+	/// Convert joint velocities from the robot convention to the user convention.
+	///
+	/// Velocities are converted like positions, but without offsets.
+	///
+	/// # Panics
+	/// This function panics if the number of joints does not match the mapping.
+	pub fn velocity_to_user(&self, robot: &[f64]) -> Vec<f64> {
+		assert_eq!(robot.len(), self.len(), "wrong number of joints for joint mapping");
+		self.to_user_impl(robot, false)
+	}
+
+	/// Convert joint velocities from the user convention to the robot convention.
+	///
+	/// Velocities are converted like positions, but without offsets.
+	///
+	/// # Panics
+	/// This function panics if the number of joints does not match the mapping.
+	pub fn velocity_to_robot(&self, user: &[f64]) -> Vec<f64> {
+		assert_eq!(user.len(), self.len(), "wrong number of joints for joint mapping");
+		self.to_robot_impl(user, false)
+	}
+
+	fn map_joints(&self, joints: Option<&mut EgmJoints>, map: impl Fn(&[f64]) -> Vec<f64>) -> Result<(), MessageRejectedError> {
+		if let Some(joints) = joints {
+			self.check_len(&joints.joints)?;
+			joints.joints = map(&joints.joints);
+		}
+		Ok(())
+	}
+}
+
+impl Middleware for JointMapping {
+	fn on_send(&self, msg: &mut EgmSensor) -> Result<(), MessageRejectedError> {
+		if let Some(planned) = &mut msg.planned {
+			self.map_joints(planned.joints.as_mut(), |x| self.to_robot_impl(x, true))?;
+		}
+		if let Some(speed_ref) = &mut msg.speed_ref {
+			self.map_joints(speed_ref.joints.as_mut(), |x| self.to_robot_impl(x, false))?;
+		}
+		Ok(())
+	}
+
+	fn on_recv(&self, msg: &mut EgmRobot) -> Result<(), MessageRejectedError> {
+		if let Some(feedback) = &mut msg.feed_back {
+			self.map_joints(feedback.joints.as_mut(), |x| self.to_user_impl(x, true))?;
+		}
+		if let Some(planned) = &mut msg.planned {
+			self.map_joints(planned.joints.as_mut(), |x| self.to_user_impl(x, true))?;
+		}
+		Ok(())
+	}
+}
+
+impl std::fmt::Display for InvalidJointMappingError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::InvalidOrder => write!(f, "joint order is not a permutation of the joint indices"),
+			Self::LengthMismatch { expected, actual } => write!(f, "expected {} values for joint mapping, got {}", expected, actual),
+			Self::InvalidCoupling(coupling) => write!(f, "invalid coupling of joint {} to joint {}", coupling.joint, coupling.source),
+		}
+	}
+}
+
+impl std::error::Error for InvalidJointMappingError {}
+
+#[cfg(test)]
+#[test]
+fn test_joint_mapping() {
+	use crate::middleware::MiddlewareStack;
+	use crate::msg::{EgmClock, EgmFeedBack};
+	use assert2::assert;
+
+	assert!(JointMapping::new(vec![0, 0], vec![1.0; 2], vec![0.0; 2]) == Err(InvalidJointMappingError::InvalidOrder));
+	assert!(let Err(InvalidJointMappingError::LengthMismatch { expected: 2, actual: 1 }) = JointMapping::new(vec![1, 0], vec![1.0], vec![0.0; 2]));
+
+	let mapping = JointMapping::new(vec![0, 2, 1], vec![1.0, -1.0, 1.0], vec![0.0, 0.0, 90.0])
+		.unwrap()
+		.with_coupling(JointCoupling { joint: 2, source: 1, factor: 1.0 })
+		.unwrap();
+	assert!(let Err(_) = mapping.clone().with_coupling(JointCoupling { joint: 1, source: 0, factor: 1.0 }));
+
+	let robot = [10.0, 20.0, 30.0];
+	let user = mapping.to_user(&robot);
+	assert!(user == [10.0, -50.0, 110.0]);
+	assert!(mapping.to_robot(&user) == robot);
+	assert!(mapping.velocity_to_user(&[1.0, 2.0, 3.0]) == [1.0, -5.0, 2.0]);
+	assert!(mapping.velocity_to_robot(&[1.0, -5.0, 2.0]) == [1.0, 2.0, 3.0]);
+
+	let mut stack = MiddlewareStack::new();
+	stack.push(mapping);
+	let target = EgmSensor::joint_target(1, user.to_vec(), EgmClock::new(0, 0));
+	let sent = stack.process_outgoing(&target).unwrap();
+	assert!(sent.planned.as_ref().unwrap().joints.as_ref().unwrap().joints == robot);
+
+	let state = EgmRobot {
+		feed_back: Some(EgmFeedBack {
+			joints: Some(EgmJoints::from_degrees(robot.to_vec())),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	assert!(stack.process_incoming(state).unwrap().feedback_joints().unwrap() == &user);
+
+	let wrong = EgmSensor::joint_target(1, [0.0; 6], EgmClock::new(0, 0));
+	assert!(let Err(_) = stack.process_outgoing(&wrong));
+}
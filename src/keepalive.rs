@@ -0,0 +1,109 @@
+use std::time::{Duration, Instant};
+
+use crate::msg::{EgmRobot, EgmSensor};
+
+/// The kind of targets sent by a [`Keepalive`].
+///
+/// This must match the RAPID instruction used to start the EGM session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeepaliveMode {
+	/// Echo the planned joints, for sessions started with `EGMActJoint`.
+	Joint,
+
+	/// Echo the planned pose, for sessions started with `EGMActPose`.
+	Pose,
+}
+
+/// Keepalive for position streaming sessions with idle periods.
+///
+/// The robot controller stops the EGM session with a communication timeout if it receives no messages for a while.
+/// When the application has no new targets, the keepalive produces benign messages that echo the planned position
+/// reported by the controller, so the robot holds its position and the session stays alive.
+///
+/// Call [`Keepalive::poll`] for every received message,
+/// and [`Keepalive::target_sent`] whenever the application sends its own target.
+/// The keepalive only produces a message if no target was sent for the configured interval.
+#[derive(Clone, Debug)]
+pub struct Keepalive {
+	mode: KeepaliveMode,
+	interval: Duration,
+	last_sent: Option<Instant>,
+}
+
+impl Keepalive {
+	/// Create a keepalive that sends a message if no target was sent for the given interval.
+	///
+	/// The interval should be well below the communication timeout configured on the controller.
+	pub fn new(mode: KeepaliveMode, interval: Duration) -> Self {
+		Self {
+			mode,
+			interval,
+			last_sent: None,
+		}
+	}
+
+	/// Get the kind of targets sent by the keepalive.
+	pub fn mode(&self) -> KeepaliveMode {
+		self.mode
+	}
+
+	/// Get the interval after which a keepalive message is sent.
+	pub fn interval(&self) -> Duration {
+		self.interval
+	}
+
+	/// Record that the application sent a target, which postpones the next keepalive message.
+	pub fn target_sent(&mut self, now: Instant) {
+		self.last_sent = Some(now);
+	}
+
+	/// Get a keepalive message if one is due.
+	///
+	/// The message echoes the planned position of the received message.
+	/// Returns `None` if a target was sent less than the interval ago,
+	/// or if the message has no planned position of the right kind.
+	/// A returned message counts as sent: send it to the controller right away.
+	pub fn poll(&mut self, state: &EgmRobot, sequence_number: u32, now: Instant) -> Option<EgmSensor> {
+		if let Some(last_sent) = self.last_sent {
+			if now.saturating_duration_since(last_sent) < self.interval {
+				return None;
+			}
+		}
+		let time = state.planned_time().unwrap_or_default();
+		let msg = match self.mode {
+			KeepaliveMode::Joint => EgmSensor::joint_target(sequence_number, state.planned_joints()?.clone(), time),
+			KeepaliveMode::Pose => EgmSensor::pose_target(sequence_number, state.planned_pose()?.clone(), time),
+		};
+		self.last_sent = Some(now);
+		Some(msg)
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_keepalive() {
+	use crate::msg::{EgmClock, EgmJoints, EgmPlanned};
+	use assert2::{assert, let_assert};
+
+	let state = EgmRobot {
+		planned: Some(EgmPlanned {
+			joints: Some(EgmJoints::from_degrees(vec![1.0, 2.0])),
+			time: Some(EgmClock::new(1, 0)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+
+	let start = Instant::now();
+	let mut keepalive = Keepalive::new(KeepaliveMode::Joint, Duration::from_millis(100));
+	keepalive.target_sent(start);
+	assert!(keepalive.poll(&state, 1, start + Duration::from_millis(50)) == None);
+
+	let_assert!(Some(msg) = keepalive.poll(&state, 2, start + Duration::from_millis(100)));
+	assert!(msg == EgmSensor::joint_target(2, vec![1.0, 2.0], EgmClock::new(1, 0)));
+	assert!(keepalive.poll(&state, 3, start + Duration::from_millis(150)) == None);
+	assert!(let Some(_) = keepalive.poll(&state, 3, start + Duration::from_millis(200)));
+
+	let mut keepalive = Keepalive::new(KeepaliveMode::Pose, Duration::from_millis(100));
+	assert!(keepalive.poll(&state, 1, start) == None);
+}
@@ -26,7 +26,7 @@
 //!
 //! # Features
 //! Some optional features are available.
-//! Note that all features are enabled by default.
+//! By default, the `std` and `tokio` features are enabled.
 //! To avoid unnecessary dependencies you can disable the default features and select only the ones you need:
 //!
 //! ```toml
@@ -35,35 +35,330 @@
 //! ```
 //!
 //! The available features are:
-//!   * `tokio`: enable the asynchronous peer.
-//!   * `nalgebra`: implement conversions between `nalgebra` types and EGM messages.
-
-use std::time::Duration;
-
+//!   * `std`: enable everything that needs the standard library, including the peers.
+//!     Without it, only the message types and their constructors are available, using only `core` and `alloc`.
+//!   * `tokio`: enable the asynchronous peer. Implies `std`.
+//!   * `nalgebra`: implement conversions between `nalgebra` types and EGM messages. Implies `std`.
+//!   * `glam`: implement conversions between the `glam` types `DVec3` and `DQuat` and EGM messages. Implies `std`.
+//!   * `mint`: implement conversions between the `mint` types `Vector3`, `Point3` and `Quaternion` and EGM messages, for any math library with `mint` support.
+//!   * `ndarray`: convert joint lists and feedback histories to and from `ndarray` arrays with one row per sample and one column per joint. Implies `std`.
+//!   * `python`: Python bindings for the peer, the message builders and the recorder using `pyo3`. Build the extension module with `maturin`. Implies `std`.
+//!   * `robotware-6-10`: use the message definitions of RobotWare 6.10 and later.
+//!     These add fields for RAPID data, collision detection and force control.
+//!     Note that this changes the encoding of `EgmMeasuredForce`, so only enable it for newer controllers.
+//!   * `serde`: implement `serde::Serialize` and `serde::Deserialize` for all EGM messages.
+//!   * `cli`: build the `egm-dump` and `egm-replay` command line tools to capture, decode and replay EGM traffic. Implies `std`.
+//!   * `program`: load simple motion programs from TOML or JSON files and run them with a streamer. Implies `std` and `serde`.
+//!   * `gcode`: interpret a subset of G-code into cartesian trajectories. Implies `std`.
+//!   * `config`: load the configuration of a complete EGM setup from TOML or JSON files and construct a streamer from it. Implies `std` and `serde`.
+//!   * `pcap`: read EGM messages from pcap and pcapng captures, such as those made with Wireshark. Implies `std`.
+//!   * `mcap`: export recordings to MCAP files with JSON encoded messages, for analysis with Foxglove. Implies `std` and `serde`.
+//!   * `foxglove`: republish live robot feedback over the Foxglove WebSocket protocol for real-time visualization. Implies `std` and `serde`.
+//!   * `gateway`: expose robot feedback and accept targets over HTTP and WebSocket with `axum`, for clients in other languages. Implies `tokio` and `serde`.
+//!   * `mqtt`: publish decimated feedback telemetry to an MQTT broker. Implies `std` and `serde`.
+//!   * `opcua`: create and write OPC UA server variables for the fields of a `FieldMapping` with `async-opcua`. Implies `std`.
+//!   * `approx`: implement the `approx::AbsDiffEq` trait for positions, orientations, poses and joints.
+//!   * `realtime`: helpers to run the control loop thread with real-time priority, pinned to a CPU. Only implemented for Linux. Implies `std`.
+//!   * `timestamping`: receive timestamps from the kernel or network card with `SO_TIMESTAMPNS` and `SO_TIMESTAMPING`. Only implemented for Linux. Implies `std`.
+//!   * `mmsg`: receive and send batches of messages with the `recvmmsg` and `sendmmsg` system calls on Linux. Other platforms use one system call per message. Implies `std`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+mod batch;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+mod encode_buffer;
 mod error;
+mod float;
 pub use error::IncompleteTransmissionError;
 pub use error::InvalidMessageError;
+pub use error::MessageRejectedError;
+#[cfg(feature = "std")]
 pub use error::ReceiveError;
+#[cfg(feature = "std")]
+pub use error::RetriesExhaustedError;
+#[cfg(feature = "std")]
 pub use error::SendError;
+pub use error::WrongNumberOfJointsError;
+pub use validate::ValidationOptions;
 
 mod generated;
+mod validate;
 
 /// Generated protobuf messages used by EGM.
 pub mod msg {
 	pub use super::generated::*;
+	pub use crate::codec::{decode_robot, decode_sensor};
 }
 
+/// Encoding and decoding of EGM messages without a transport.
+pub mod codec;
+
+/// Transport-independent EGM channels.
+pub mod transport;
+
+/// Typed force and torque measurements.
+pub mod wrench;
+
+/// Normalization and unwrapping of revolute joint angles.
+pub mod angles;
+
+/// Distance metrics between positions, orientations and joint vectors.
+pub mod math;
+
 /// Synchronous (blocking) EGM peer.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
 pub mod sync_peer;
 
 /// Asynchronous EGM peer using `tokio`.
 #[cfg(feature = "tokio")]
 pub mod tokio_peer;
 
+/// Retry policies for sends that fail with transient errors.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod retry;
+
+/// Discovery of the robot controller from the first received messages.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod discovery;
+
+/// Helpers for dual-stack and multi-homed binding.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod net;
+
+/// Hooks to inspect, modify or reject messages sent and received by the peers.
+#[cfg(feature = "std")]
+pub mod middleware;
+
+/// Coordinate frames and robot mounting configuration.
+#[cfg(feature = "std")]
+pub mod frames;
+
+/// Conversion between the joint conventions of the robot controller and the application.
+#[cfg(feature = "std")]
+pub mod joint_mapping;
+
+/// Detection of the optional message fields sent by a robot controller.
+#[cfg(feature = "std")]
+pub mod capabilities;
+
+/// Convergence checks with user-defined tolerances and settle time.
+#[cfg(feature = "std")]
+pub mod convergence;
+
+/// Coordinated control of dual-arm robots using `tokio`.
+#[cfg(feature = "tokio")]
+pub mod dual_arm;
+
+/// Barrier for aligned control cycles of multiple units using `tokio`.
+#[cfg(feature = "tokio")]
+pub mod sync_group;
+
+/// Shared history of robot feedback.
+#[cfg(feature = "std")]
+pub mod feedback;
+
+/// Policies for optional fields that the robot controller may omit.
+#[cfg(feature = "std")]
+pub mod field_policy;
+
+/// Detection of reordered and delayed messages from the robot controller.
+#[cfg(feature = "std")]
+pub mod stale;
+
+/// Suppression of duplicated messages from the robot controller.
+#[cfg(feature = "std")]
+pub mod dedup;
+
+/// Change detection between messages from the robot controller.
+#[cfg(feature = "std")]
+pub mod diff;
+
+/// Background receivers that keep the latest robot state available.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod subscriber;
+
+/// Latency compensation by extrapolating robot feedback.
+#[cfg(feature = "std")]
+pub mod predictor;
+
+/// Real and mock clocks for timing logic.
+#[cfg(feature = "std")]
+pub mod clock;
+
+/// Resampling of feedback to a fixed rate.
+#[cfg(feature = "std")]
+pub mod resample;
+
+/// Smoothed joint position and velocity estimates from feedback.
+#[cfg(feature = "std")]
+pub mod estimator;
+
+/// EGM sample rates and loop timers.
+#[cfg(feature = "std")]
+pub mod rate;
+
+/// Real-time scheduling and CPU pinning for the control loop thread.
+#[cfg(feature = "realtime")]
+pub mod realtime;
+
+/// Kernel and NIC receive timestamps for UDP sockets.
+#[cfg(all(feature = "timestamping", not(target_arch = "wasm32")))]
+pub mod timestamping;
+
+/// Sources of targets for control loops.
+#[cfg(feature = "std")]
+pub mod servo;
+
+/// Queue of targets released at their planned time of the controller clock.
+#[cfg(feature = "std")]
+pub mod target_queue;
+
+/// Bounded channels between planners and control loops.
+#[cfg(feature = "tokio")]
+pub mod channel;
+
+/// Admittance and PID controllers for compliant motion and closed-loop control.
+#[cfg(feature = "std")]
+pub mod control;
+
+/// Detection of session events such as controller restarts.
+#[cfg(feature = "std")]
+pub mod session;
+
+/// Keepalive messages for position streaming sessions with idle periods.
+#[cfg(feature = "std")]
+pub mod keepalive;
+
+/// Session types and detection for the joint or pose guidance mode of the controller.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod guidance;
+
+/// High-level motion primitives that stream interpolated targets.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod streamer;
+
+/// Spline trajectories with curvature-limited speed.
+#[cfg(feature = "std")]
+pub mod trajectory;
+
+/// Motion programs loaded from TOML or JSON files.
+#[cfg(all(feature = "program", not(target_arch = "wasm32")))]
+pub mod program;
+
+/// Configuration of a complete EGM setup, loaded from TOML or JSON files.
+#[cfg(all(feature = "config", not(target_arch = "wasm32")))]
+pub mod config;
+
+/// Cartesian trajectories interpreted from a subset of G-code.
+#[cfg(feature = "gcode")]
+pub mod gcode;
+
+/// Path correction sessions with measurement aging and rate limiting.
+#[cfg(feature = "std")]
+pub mod path_correction;
+
+/// Weave patterns for path correction.
+#[cfg(feature = "std")]
+pub mod weave;
+
+/// Generation of RAPID modules for the robot controller.
+#[cfg(feature = "std")]
+pub mod rapid;
+
+/// Binary recordings of EGM messages with an index for random access.
+#[cfg(feature = "std")]
+pub mod recording;
+
+/// Periodic checkpoints of the last commanded target.
+#[cfg(feature = "std")]
+pub mod checkpoint;
+
+/// Message size, field population and bandwidth statistics.
+#[cfg(feature = "std")]
+pub mod bandwidth;
+
+/// Offline inspection of EGM messages in pcap and pcapng captures.
+#[cfg(feature = "pcap")]
+pub mod pcap;
+
+/// Export of recordings to MCAP files for Foxglove.
+#[cfg(feature = "mcap")]
+pub mod mcap;
+
+/// Live visualization of robot feedback over the Foxglove WebSocket protocol.
+#[cfg(all(feature = "foxglove", not(target_arch = "wasm32")))]
+pub mod foxglove;
+
+/// HTTP and WebSocket gateway exposing robot feedback and accepting targets.
+#[cfg(all(feature = "gateway", not(target_arch = "wasm32")))]
+pub mod gateway;
+
+/// Publishing of decimated feedback telemetry to an MQTT broker.
+#[cfg(all(feature = "mqtt", not(target_arch = "wasm32")))]
+pub mod mqtt;
+
+/// Mapping of feedback fields to the variables of external systems, such as OPC UA nodes.
+#[cfg(feature = "std")]
+pub mod field_mapping;
+
+/// Replay of recorded traces through a controller for regression tests.
+#[cfg(feature = "std")]
+pub mod harness;
+
+/// Man-in-the-middle relay between a robot controller and an EGM application, with inspection and mutation hooks.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod proxy;
+
+/// Simulation of packet loss, reordering, duplication and latency jitter for tests.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod impairment;
+
+/// Qualification of robot controllers with a battery of checks against a live EGM session.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod conformance;
+/// Scriptable mock robot controller for unit tests.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod testing;
+
 /// Conversions to/from nalgebra types.
 #[cfg(feature = "nalgebra")]
 mod nalgebra;
 
+/// Conversions to/from glam types.
+#[cfg(feature = "glam")]
+mod glam;
+
+/// Conversions to/from mint types.
+#[cfg(feature = "mint")]
+mod mint;
+
+/// Conversions to/from ndarray types.
+#[cfg(feature = "ndarray")]
+mod ndarray;
+
+/// Writing of mapped feedback fields to the variables of an OPC UA server.
+#[cfg(feature = "opcua")]
+mod opcua;
+
+/// Python bindings for the message and peer layer using pyo3.
+#[cfg(feature = "python")]
+mod python;
+
+/// Implementations of the `approx` traits.
+#[cfg(feature = "approx")]
+mod approx;
+
+/// Incremental pose commands relative to the robot feedback.
+#[cfg(feature = "nalgebra")]
+pub mod incremental;
+
 impl msg::EgmHeader {
 	pub fn new(seqno: u32, timestamp_ms: u32, kind: msg::egm_header::MessageType) -> Self {
 		Self {
@@ -95,6 +390,9 @@ impl msg::EgmHeader {
 }
 
 impl msg::EgmCartesian {
+	/// The origin: a cartesian position with all components set to zero.
+	pub const ZERO: Self = Self { x: 0.0, y: 0.0, z: 0.0 };
+
 	/// Create a cartesian position from x, y and z components in millimeters.
 	pub fn from_mm(x: f64, y: f64, z: f64) -> Self {
 		Self { x, y, z }
@@ -109,6 +407,11 @@ impl msg::EgmCartesian {
 	pub fn has_nan(&self) -> bool {
 		self.x.is_nan() || self.y.is_nan() || self.z.is_nan()
 	}
+
+	/// Check if the distance to another position is at most `tolerance` millimeters.
+	pub fn approx_eq(&self, other: &Self, tolerance: f64) -> bool {
+		math::position_distance(*self, *other) <= tolerance
+	}
 }
 
 impl From<[f64; 3]> for msg::EgmCartesian {
@@ -135,8 +438,46 @@ impl From<(f64, f64, f64)> for msg::EgmCartesian {
 	}
 }
 
+impl From<msg::EgmCartesian> for [f64; 3] {
+	/// Get the x, y and z components of a cartesian position in millimeters.
+	///
+	/// Together with [`msg::EgmQuaternion::as_xyzw`] and [`msg::EgmQuaternion::from_xyzw`],
+	/// this allows conversion to and from the plain array representations of `mint` and other math libraries.
+	fn from(other: msg::EgmCartesian) -> Self {
+		other.as_mm()
+	}
+}
+
+impl From<&msg::EgmCartesian> for [f64; 3] {
+	/// Get the x, y and z components of a cartesian position in millimeters.
+	fn from(other: &msg::EgmCartesian) -> Self {
+		other.as_mm()
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_cartesian_array() {
+	use assert2::assert;
+
+	let position = msg::EgmCartesian::from([1.0, 2.0, 3.0]);
+	assert!(<[f64; 3]>::from(position) == [1.0, 2.0, 3.0]);
+	assert!(<[f64; 3]>::from(&position) == [1.0, 2.0, 3.0]);
+}
+
 impl msg::EgmQuaternion {
+	/// The identity rotation.
+	pub const IDENTITY: Self = Self {
+		u0: 1.0,
+		u1: 0.0,
+		u2: 0.0,
+		u3: 0.0,
+	};
+
 	/// Create a new quaternion from w, x, y and z components.
+	///
+	/// The components are used as given: the quaternion is not normalized, and the robot controller expects a unit quaternion.
+	/// With the `nalgebra` feature, `EgmPose::from_mm_and_unit_quat()` takes a `UnitQuaternion` instead.
 	pub fn from_wxyz(w: f64, x: f64, y: f64, z: f64) -> Self {
 		Self {
 			u0: w,
@@ -146,18 +487,100 @@ impl msg::EgmQuaternion {
 		}
 	}
 
+	/// Create a new quaternion from x, y, z and w components.
+	///
+	/// This is the component order used by many graphics and game math libraries.
+	pub fn from_xyzw(x: f64, y: f64, z: f64, w: f64) -> Self {
+		Self::from_wxyz(w, x, y, z)
+	}
+
+	/// Create a new quaternion from the scalar part and the vector part.
+	///
+	/// Unlike [`Self::from_wxyz`] and [`Self::from_xyzw`], the scalar and vector parts can not be swapped by accident.
+	/// Conversions from math libraries should use this constructor.
+	pub fn from_scalar_vector(w: f64, [x, y, z]: [f64; 3]) -> Self {
+		Self::from_wxyz(w, x, y, z)
+	}
+
+	/// Get the scalar part of the quaternion.
+	pub fn scalar(&self) -> f64 {
+		self.u0
+	}
+
+	/// Get the vector part of the quaternion.
+	pub fn vector(&self) -> [f64; 3] {
+		[self.u1, self.u2, self.u3]
+	}
+
 	/// Get the quaternion as [w, x, y, z] array.
 	pub fn as_wxyz(&self) -> [f64; 4] {
 		[self.u0, self.u1, self.u2, self.u3]
 	}
 
+	/// Get the quaternion as [x, y, z, w] array.
+	pub fn as_xyzw(&self) -> [f64; 4] {
+		[self.u1, self.u2, self.u3, self.u0]
+	}
+
+	/// Get the angle in degrees of the rotation between two quaternions.
+	///
+	/// The quaternions do not need to be normalized.
+	/// Returns NaN if either quaternion has zero length.
+	pub fn angle_to(&self, other: &Self) -> f64 {
+		let [w1, x1, y1, z1] = self.as_wxyz();
+		let [w2, x2, y2, z2] = other.as_wxyz();
+		let norm = float::sqrt(w1 * w1 + x1 * x1 + y1 * y1 + z1 * z1) * float::sqrt(w2 * w2 + x2 * x2 + y2 * y2 + z2 * z2);
+		let dot = float::abs(w1 * w2 + x1 * x2 + y1 * y2 + z1 * z2) / norm;
+		2.0 * float::acos(dot.min(1.0)).to_degrees()
+	}
+
+	/// Spherically interpolate between two unit quaternions.
+	///
+	/// A `fraction` of 0 gives `self` and a fraction of 1 gives `other`.
+	/// The interpolation always follows the shortest path.
+	pub fn slerp(&self, other: &Self, fraction: f64) -> Self {
+		let a = self.as_wxyz();
+		let mut b = other.as_wxyz();
+		let mut dot: f64 = a.iter().zip(&b).map(|(a, b)| a * b).sum();
+		if dot < 0.0 {
+			b = [-b[0], -b[1], -b[2], -b[3]];
+			dot = -dot;
+		}
+
+		// Fall back to linear interpolation for nearly identical rotations.
+		let (weight_a, weight_b) = if dot > 0.9995 {
+			(1.0 - fraction, fraction)
+		} else {
+			let theta = float::acos(dot);
+			let sin_theta = float::sin(theta);
+			(float::sin((1.0 - fraction) * theta) / sin_theta, float::sin(fraction * theta) / sin_theta)
+		};
+
+		let mut result = a;
+		for (result, b) in result.iter_mut().zip(&b) {
+			*result = weight_a * *result + weight_b * b;
+		}
+		let norm = float::sqrt(result.iter().map(|x| x * x).sum::<f64>());
+		Self::from_wxyz(result[0] / norm, result[1] / norm, result[2] / norm, result[3] / norm)
+	}
+
 	/// Check if any of the values are NaN.
 	pub fn has_nan(&self) -> bool {
 		self.u0.is_nan() || self.u1.is_nan() || self.u2.is_nan() || self.u3.is_nan()
 	}
+
+	/// Check if the angle to another orientation is at most `tolerance` degrees.
+	///
+	/// A quaternion and its negation describe the same orientation, so they compare equal.
+	pub fn approx_eq(&self, other: &Self, tolerance: f64) -> bool {
+		self.angle_to(other) <= tolerance
+	}
 }
 
 impl msg::EgmEuler {
+	/// The identity rotation: all angles set to zero.
+	pub const ZERO: Self = Self { x: 0.0, y: 0.0, z: 0.0 };
+
 	/// Create a new rotation from X, Y and Z rotations specified in degrees.
 	pub fn from_xyz_degrees(x: f64, y: f64, z: f64) -> Self {
 		Self { x, y, z }
@@ -168,12 +591,99 @@ impl msg::EgmEuler {
 		[self.x, self.y, self.z]
 	}
 
+	/// Convert the rotation to a quaternion.
+	///
+	/// The angles are interpreted the same way as the RAPID `OrientZYX` function:
+	/// a rotation around the Z axis, followed by a rotation around the new Y axis and finally around the new X axis.
+	pub fn to_quaternion(&self) -> msg::EgmQuaternion {
+		let (sx, cx) = float::sin_cos(self.x.to_radians() / 2.0);
+		let (sy, cy) = float::sin_cos(self.y.to_radians() / 2.0);
+		let (sz, cz) = float::sin_cos(self.z.to_radians() / 2.0);
+		msg::EgmQuaternion::from_wxyz(
+			cx * cy * cz + sx * sy * sz,
+			sx * cy * cz - cx * sy * sz,
+			cx * sy * cz + sx * cy * sz,
+			cx * cy * sz - sx * sy * cz,
+		)
+	}
+
 	/// Check if any of the values are NaN.
 	pub fn has_nan(&self) -> bool {
 		self.x.is_nan() || self.y.is_nan() || self.z.is_nan()
 	}
 }
 
+#[cfg(test)]
+#[test]
+fn test_euler_to_quaternion() {
+	use assert2::assert;
+	use msg::{EgmEuler, EgmQuaternion};
+
+	let half = std::f64::consts::FRAC_1_SQRT_2;
+	assert!(EgmEuler::ZERO.to_quaternion().angle_to(&EgmQuaternion::IDENTITY) < 1e-9);
+	assert!(EgmEuler::from_xyz_degrees(0.0, 0.0, 90.0).to_quaternion().angle_to(&EgmQuaternion::from_wxyz(half, 0.0, 0.0, half)) < 1e-6);
+	assert!(EgmEuler::from_xyz_degrees(90.0, 0.0, 0.0).to_quaternion().angle_to(&EgmQuaternion::from_wxyz(half, half, 0.0, 0.0)) < 1e-6);
+	assert!((EgmQuaternion::IDENTITY.angle_to(&EgmQuaternion::from_wxyz(half, 0.0, half, 0.0)) - 90.0).abs() < 1e-6);
+}
+
+#[cfg(test)]
+#[test]
+fn test_quaternion_component_order() {
+	use assert2::assert;
+	use msg::EgmQuaternion;
+
+	let quaternion = EgmQuaternion::from_wxyz(1.0, 2.0, 3.0, 4.0);
+	assert!(EgmQuaternion::from_xyzw(2.0, 3.0, 4.0, 1.0) == quaternion);
+	assert!(EgmQuaternion::from_scalar_vector(1.0, [2.0, 3.0, 4.0]) == quaternion);
+	assert!(quaternion.scalar() == 1.0);
+	assert!(quaternion.vector() == [2.0, 3.0, 4.0]);
+	assert!(quaternion.as_wxyz() == [1.0, 2.0, 3.0, 4.0]);
+	assert!(quaternion.as_xyzw() == [2.0, 3.0, 4.0, 1.0]);
+	let [x, y, z, w] = quaternion.as_xyzw();
+	assert!(EgmQuaternion::from_xyzw(x, y, z, w) == quaternion);
+}
+
+#[cfg(all(test, feature = "glam"))]
+#[test]
+fn test_glam_quaternion_component_order() {
+	use assert2::assert;
+	use msg::EgmQuaternion;
+
+	let quaternion = ::glam::DQuat::from(EgmQuaternion::from_wxyz(0.1, 0.2, 0.3, 0.4));
+	assert!(quaternion.w == 0.1);
+	assert!(quaternion.x == 0.2);
+	assert!(quaternion.y == 0.3);
+	assert!(quaternion.z == 0.4);
+
+	let quaternion = EgmQuaternion::from(::glam::DQuat::from_xyzw(0.2, 0.3, 0.4, 0.1));
+	assert!(quaternion.u0 == 0.1);
+	assert!(quaternion.u1 == 0.2);
+	assert!(quaternion.u2 == 0.3);
+	assert!(quaternion.u3 == 0.4);
+	assert!(EgmQuaternion::from(::glam::DQuat::from(quaternion)) == quaternion);
+
+	let position = ::glam::DVec3::from(msg::EgmCartesian::from_mm(1.0, 2.0, 3.0));
+	assert!(position == ::glam::DVec3::new(1.0, 2.0, 3.0));
+	assert!(msg::EgmCartesian::from(position).as_mm() == [1.0, 2.0, 3.0]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_slerp() {
+	use assert2::assert;
+	use msg::{EgmEuler, EgmQuaternion};
+
+	let a = EgmQuaternion::IDENTITY;
+	let b = EgmEuler::from_xyz_degrees(0.0, 0.0, 90.0).to_quaternion();
+	assert!(a.slerp(&b, 0.0).angle_to(&a) < 1e-6);
+	assert!(a.slerp(&b, 1.0).angle_to(&b) < 1e-6);
+	assert!(a.slerp(&b, 0.5).angle_to(&EgmEuler::from_xyz_degrees(0.0, 0.0, 45.0).to_quaternion()) < 1e-6);
+
+	// The shortest path is used, even if the quaternions are in opposite hemispheres.
+	let negated = EgmQuaternion::from_wxyz(-b.u0, -b.u1, -b.u2, -b.u3);
+	assert!(a.slerp(&negated, 0.5).angle_to(&EgmEuler::from_xyz_degrees(0.0, 0.0, 45.0).to_quaternion()) < 1e-6);
+}
+
 impl msg::EgmClock {
 	/// Create a new time point from seconds and microseconds.
 	pub fn new(sec: u64, usec: u64) -> Self {
@@ -197,6 +707,7 @@ impl msg::EgmClock {
 
 #[cfg(test)]
 #[test]
+#[allow(clippy::zero_prefixed_literal)]
 fn test_clock_to_duration() {
 	use assert2::assert;
 	use msg::EgmClock;
@@ -221,9 +732,7 @@ fn test_clock_to_timestampc() {
 	assert!(EgmClock::new(4, 2_345_000).as_timestamp_ms() == 6_345);
 }
 
-impl Copy for msg::EgmClock {}
-
-impl std::ops::Add<Duration> for msg::EgmClock {
+impl core::ops::Add<Duration> for msg::EgmClock {
 	type Output = Self;
 
 	#[allow(clippy::suspicious_arithmetic_impl)]
@@ -236,7 +745,7 @@ impl std::ops::Add<Duration> for msg::EgmClock {
 	}
 }
 
-impl std::ops::Add<msg::EgmClock> for Duration {
+impl core::ops::Add<msg::EgmClock> for Duration {
 	type Output = msg::EgmClock;
 
 	fn add(self, right: msg::EgmClock) -> Self::Output {
@@ -244,7 +753,7 @@ impl std::ops::Add<msg::EgmClock> for Duration {
 	}
 }
 
-impl std::ops::Add<&Duration> for &msg::EgmClock {
+impl core::ops::Add<&Duration> for &msg::EgmClock {
 	type Output = msg::EgmClock;
 
 	fn add(self, right: &Duration) -> Self::Output {
@@ -252,7 +761,7 @@ impl std::ops::Add<&Duration> for &msg::EgmClock {
 	}
 }
 
-impl std::ops::Add<&msg::EgmClock> for &Duration {
+impl core::ops::Add<&msg::EgmClock> for &Duration {
 	type Output = msg::EgmClock;
 
 	fn add(self, right: &msg::EgmClock) -> Self::Output {
@@ -260,13 +769,13 @@ impl std::ops::Add<&msg::EgmClock> for &Duration {
 	}
 }
 
-impl std::ops::AddAssign<&Duration> for msg::EgmClock {
+impl core::ops::AddAssign<&Duration> for msg::EgmClock {
 	fn add_assign(&mut self, right: &Duration) {
 		*self = &*self + right
 	}
 }
 
-impl std::ops::AddAssign<Duration> for msg::EgmClock {
+impl core::ops::AddAssign<Duration> for msg::EgmClock {
 	fn add_assign(&mut self, right: Duration) {
 		*self += &right
 	}
@@ -274,6 +783,7 @@ impl std::ops::AddAssign<Duration> for msg::EgmClock {
 
 #[cfg(test)]
 #[test]
+#[allow(clippy::op_ref)]
 fn test_add_duration() {
 	use assert2::assert;
 	use msg::EgmClock;
@@ -305,6 +815,17 @@ impl msg::EgmPose {
 		}
 	}
 
+	/// Create a new 6-DOF pose from a position and Euler angles in degrees.
+	///
+	/// Note that the robot controller gives priority to the Euler angles if a pose contains both Euler angles and a quaternion.
+	pub fn with_euler(position: impl Into<msg::EgmCartesian>, orientation: impl Into<msg::EgmEuler>) -> Self {
+		Self {
+			pos: Some(position.into()),
+			orient: None,
+			euler: Some(orientation.into()),
+		}
+	}
+
 	/// Check if any of the values are NaN.
 	pub fn has_nan(&self) -> bool {
 		let has_nan = false;
@@ -313,6 +834,28 @@ impl msg::EgmPose {
 		let has_nan = has_nan || self.euler.as_ref().map(|x| x.has_nan()).unwrap_or(false);
 		has_nan
 	}
+
+	/// Get the orientation of the pose as quaternion, converting the Euler angles if there is no quaternion.
+	fn orientation(&self) -> Option<msg::EgmQuaternion> {
+		self.orient.or_else(|| self.euler.as_ref().map(|x| x.to_quaternion()))
+	}
+
+	/// Check if the position and orientation are within a tolerance of another pose.
+	///
+	/// The position tolerance is in millimeters, and the orientation tolerance in degrees.
+	/// Orientations given as Euler angles are converted to quaternions first.
+	/// Missing fields only compare equal to missing fields.
+	pub fn approx_eq(&self, other: &Self, position_tolerance: f64, orientation_tolerance: f64) -> bool {
+		let position = match (&self.pos, &other.pos) {
+			(Some(a), Some(b)) => a.approx_eq(b, position_tolerance),
+			(a, b) => a.is_none() && b.is_none(),
+		};
+		let orientation = match (self.orientation(), other.orientation()) {
+			(Some(a), Some(b)) => a.approx_eq(&b, orientation_tolerance),
+			(a, b) => a.is_none() && b.is_none(),
+		};
+		position && orientation
+	}
 }
 
 impl msg::EgmCartesianSpeed {
@@ -358,6 +901,36 @@ impl msg::EgmJoints {
 	pub fn has_nan(&self) -> bool {
 		self.joints.iter().any(|x| x.is_nan())
 	}
+
+	/// Check if all joints are within `tolerance` of another joint list with the same number of joints.
+	pub fn approx_eq(&self, other: &Self, tolerance: f64) -> bool {
+		math::max_joint_difference(&self.joints, &other.joints).map(|x| x <= tolerance) == Some(true)
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_approx_eq() {
+	use assert2::assert;
+	use msg::{EgmEuler, EgmJoints, EgmPose, EgmQuaternion};
+
+	let q = EgmEuler::from_xyz_degrees(0.0, 0.0, 90.0).to_quaternion();
+	let negated = EgmQuaternion::from_wxyz(-q.u0, -q.u1, -q.u2, -q.u3);
+	assert!(q.approx_eq(&negated, 1e-6));
+	assert!(!q.approx_eq(&EgmQuaternion::IDENTITY, 89.0));
+
+	let a = EgmPose::new([1.0, 2.0, 3.0], q);
+	let b = EgmPose {
+		pos: Some([1.0, 2.0, 3.1].into()),
+		orient: None,
+		euler: Some(EgmEuler::from_xyz_degrees(0.0, 0.0, 90.0)),
+	};
+	assert!(a.approx_eq(&b, 0.2, 1e-6));
+	assert!(!a.approx_eq(&b, 0.05, 1e-6));
+	assert!(!a.approx_eq(&EgmPose::default(), 1.0, 1.0));
+
+	assert!(EgmJoints::from(vec![1.0, 2.0]).approx_eq(&vec![1.05, 2.0].into(), 0.1));
+	assert!(!EgmJoints::from(vec![1.0, 2.0]).approx_eq(&vec![1.0].into(), 0.1));
 }
 
 impl From<Vec<f64>> for msg::EgmJoints {
@@ -388,6 +961,44 @@ impl From<&[f64; 6]> for msg::EgmJoints {
 	}
 }
 
+impl core::convert::TryFrom<&msg::EgmJoints> for [f64; 6] {
+	type Error = WrongNumberOfJointsError;
+
+	/// Get the joint values in degrees as an array, if the list contains exactly 6 joints.
+	fn try_from(other: &msg::EgmJoints) -> Result<Self, Self::Error> {
+		let mut joints = [0.0; 6];
+		copy_joints(&mut joints, &other.joints)?;
+		Ok(joints)
+	}
+}
+
+impl core::convert::TryFrom<&msg::EgmJoints> for [f64; 7] {
+	type Error = WrongNumberOfJointsError;
+
+	/// Get the joint values in degrees as an array, if the list contains exactly 7 joints.
+	///
+	/// Note that the controller reports the seventh axis of 7-axis robots in the external joints,
+	/// so the joint list of those robots has only 6 values.
+	/// Use [`msg::EgmRobot::feedback_joints_7axis`] to combine them.
+	fn try_from(other: &msg::EgmJoints) -> Result<Self, Self::Error> {
+		let mut joints = [0.0; 7];
+		copy_joints(&mut joints, &other.joints)?;
+		Ok(joints)
+	}
+}
+
+/// Copy joint values into a fixed-size array, if the number of joints matches.
+fn copy_joints(target: &mut [f64], source: &[f64]) -> Result<(), WrongNumberOfJointsError> {
+	if source.len() != target.len() {
+		return Err(WrongNumberOfJointsError {
+			expected: target.len(),
+			actual: source.len(),
+		});
+	}
+	target.copy_from_slice(source);
+	Ok(())
+}
+
 impl msg::EgmExternalJoints {
 	/// Create a new external joint list from a vector of joint values in degrees.
 	pub fn from_degrees(joints: impl Into<Vec<f64>>) -> Self {
@@ -435,6 +1046,30 @@ impl msg::EgmPlanned {
 		}
 	}
 
+	/// Set the joint target in degrees.
+	///
+	/// If the message already contains joint values, the existing allocation is reused.
+	pub fn set_joints(&mut self, joints: &[f64]) {
+		assign_values(&mut self.joints.get_or_insert_with(Default::default).joints, joints);
+	}
+
+	/// Set the 6-DOF pose target.
+	pub fn set_pose(&mut self, pose: impl Into<msg::EgmPose>) {
+		self.cartesian = Some(pose.into());
+	}
+
+	/// Set the external joint target in degrees for rotating axes or millimeters for linear axes.
+	///
+	/// If the message already contains external joint values, the existing allocation is reused.
+	pub fn set_external_joints(&mut self, joints: &[f64]) {
+		assign_values(&mut self.external_joints.get_or_insert_with(Default::default).joints, joints);
+	}
+
+	/// Set the time of the target.
+	pub fn set_time(&mut self, time: impl Into<msg::EgmClock>) {
+		self.time = Some(time.into());
+	}
+
 	/// Check if any of the values are NaN.
 	pub fn has_nan(&self) -> bool {
 		let has_nan = false;
@@ -445,7 +1080,25 @@ impl msg::EgmPlanned {
 	}
 }
 
+/// Replace the contents of a vector with new values, reusing the existing allocation.
+fn assign_values(target: &mut Vec<f64>, values: &[f64]) {
+	target.clear();
+	target.extend_from_slice(values);
+}
+
 impl msg::EgmSpeedRef {
+	/// Create a speed reference with any combination of joint, cartesian and external joint speeds.
+	///
+	/// Joint speeds are in degrees/s, cartesian speeds in mm/s.
+	/// External joint speeds are in degrees/s for rotating axes or mm/s for linear axes.
+	pub fn new(joints: Option<msg::EgmJoints>, cartesian: Option<msg::EgmCartesianSpeed>, external_joints: Option<msg::EgmJoints>) -> Self {
+		Self {
+			joints,
+			cartesians: cartesian,
+			external_joints,
+		}
+	}
+
 	pub fn joints(joints: impl Into<msg::EgmJoints>) -> Self {
 		Self {
 			joints: Some(joints.into()),
@@ -497,6 +1150,8 @@ impl msg::EgmSensor {
 			header: Some(msg::EgmHeader::correction(sequence_number, time.as_timestamp_ms())),
 			planned: Some(msg::EgmPlanned::joints(joints, time)),
 			speed_ref: None,
+			#[cfg(feature = "robotware-6-10")]
+			rapi_dto_robot: None,
 		}
 	}
 
@@ -509,9 +1164,25 @@ impl msg::EgmSensor {
 			header: Some(msg::EgmHeader::correction(sequence_number, time.as_timestamp_ms())),
 			planned: Some(msg::EgmPlanned::joints(joints, time)),
 			speed_ref: Some(msg::EgmSpeedRef::joints(speed)),
+			#[cfg(feature = "robotware-6-10")]
+			rapi_dto_robot: None,
 		}
 	}
 
+	/// Create a sensor message containing a joint space target for a 7-axis robot, such as a single arm of a YuMi.
+	///
+	/// The joints are given in axis order: `joints[0..6]` are axis 1 to 6 and `joints[6]` is axis 7.
+	/// The controller expects the seventh axis as the first external joint,
+	/// so axis 1 to 6 are sent as joint target and axis 7 as external joint target.
+	/// Note that for a YuMi, axis 7 is physically located between axis 2 and 3.
+	///
+	/// The header timestamp is created from the `time` parameter.
+	pub fn joint_target_7axis(sequence_number: u32, joints: [f64; 7], time: impl Into<msg::EgmClock>) -> Self {
+		let mut msg = Self::joint_target(sequence_number, &joints[..6], time);
+		msg.set_external_joints(&joints[6..]);
+		msg
+	}
+
 	/// Create a sensor message containing a 6-DOF pose target.
 	///
 	/// The header timestamp is created from the `time` parameter.
@@ -521,6 +1192,8 @@ impl msg::EgmSensor {
 			header: Some(msg::EgmHeader::correction(sequence_number, time.as_timestamp_ms())),
 			planned: Some(msg::EgmPlanned::pose(pose, time)),
 			speed_ref: None,
+			#[cfg(feature = "robotware-6-10")]
+			rapi_dto_robot: None,
 		}
 	}
 
@@ -533,18 +1206,203 @@ impl msg::EgmSensor {
 			header: Some(msg::EgmHeader::correction(sequence_number, time.as_timestamp_ms())),
 			planned: Some(msg::EgmPlanned::pose(pose, time)),
 			speed_ref: Some(msg::EgmSpeedRef::cartesian(speed)),
+			#[cfg(feature = "robotware-6-10")]
+			rapi_dto_robot: None,
+		}
+	}
+
+	/// Create a sensor message containing a joint space target and a full speed reference.
+	///
+	/// The speed reference may contain any combination of joint, cartesian and external joint speeds.
+	///
+	/// The header timestamp is created from the `time` parameter.
+	pub fn joint_target_with_speed_refs(sequence_number: u32, joints: impl Into<msg::EgmJoints>, speed_ref: msg::EgmSpeedRef, time: impl Into<msg::EgmClock>) -> Self {
+		let time = time.into();
+		Self {
+			header: Some(msg::EgmHeader::correction(sequence_number, time.as_timestamp_ms())),
+			planned: Some(msg::EgmPlanned::joints(joints, time)),
+			speed_ref: Some(speed_ref),
+			#[cfg(feature = "robotware-6-10")]
+			rapi_dto_robot: None,
+		}
+	}
+
+	/// Create a sensor message containing a 6-DOF pose target and a full speed reference.
+	///
+	/// The speed reference may contain any combination of joint, cartesian and external joint speeds.
+	///
+	/// The header timestamp is created from the `time` parameter.
+	pub fn pose_target_with_speed_refs(sequence_number: u32, pose: impl Into<msg::EgmPose>, speed_ref: msg::EgmSpeedRef, time: impl Into<msg::EgmClock>) -> Self {
+		let time = time.into();
+		Self {
+			header: Some(msg::EgmHeader::correction(sequence_number, time.as_timestamp_ms())),
+			planned: Some(msg::EgmPlanned::pose(pose, time)),
+			speed_ref: Some(speed_ref),
+			#[cfg(feature = "robotware-6-10")]
+			rapi_dto_robot: None,
 		}
 	}
 
+	/// Set the sequence number in the header.
+	pub fn set_sequence_number(&mut self, sequence_number: u32) {
+		let header = self.header.get_or_insert_with(|| msg::EgmHeader::correction(0, 0));
+		header.seqno = Some(sequence_number);
+	}
+
+	/// Set the joint target in degrees.
+	///
+	/// If the message already contains joint values, the existing allocation is reused.
+	pub fn set_joints(&mut self, joints: &[f64]) {
+		self.planned.get_or_insert_with(Default::default).set_joints(joints);
+	}
+
+	/// Set the 6-DOF pose target.
+	pub fn set_pose(&mut self, pose: impl Into<msg::EgmPose>) {
+		self.planned.get_or_insert_with(Default::default).set_pose(pose);
+	}
+
+	/// Set the external joint target in degrees for rotating axes or millimeters for linear axes.
+	///
+	/// If the message already contains external joint values, the existing allocation is reused.
+	pub fn set_external_joints(&mut self, joints: &[f64]) {
+		self.planned.get_or_insert_with(Default::default).set_external_joints(joints);
+	}
+
+	/// Set or clear the speed reference.
+	pub fn set_speed_ref(&mut self, speed_ref: Option<msg::EgmSpeedRef>) {
+		self.speed_ref = speed_ref;
+	}
+
+	/// Set the time of the target.
+	///
+	/// The header timestamp is updated from the `time` parameter too.
+	pub fn set_time(&mut self, time: impl Into<msg::EgmClock>) {
+		let time = time.into();
+		let header = self.header.get_or_insert_with(|| msg::EgmHeader::correction(0, 0));
+		header.tm = Some(time.as_timestamp_ms());
+		self.planned.get_or_insert_with(Default::default).set_time(time);
+	}
+
 	/// Check if any of the values are NaN.
 	pub fn has_nan(&self) -> bool {
 		let has_nan = false;
 		let has_nan = has_nan || self.planned.as_ref().map(|x| x.has_nan()).unwrap_or(false);
 		let has_nan = has_nan || self.speed_ref.as_ref().map(|x| x.has_nan()).unwrap_or(false);
+		#[cfg(feature = "robotware-6-10")]
+		let has_nan = has_nan || self.rapi_dto_robot.as_ref().map(|x| x.has_nan()).unwrap_or(false);
 		has_nan
 	}
 }
 
+#[cfg(feature = "robotware-6-10")]
+impl msg::EgmSensor {
+	/// Set the data to send from the sensor to RAPID.
+	pub fn set_rapid_to_robot(&mut self, values: impl Into<Vec<f64>>) {
+		self.rapi_dto_robot = Some(msg::EgmRapiDdata::from_values(values));
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_sensor_setters() {
+	use assert2::assert;
+	use msg::{EgmClock, EgmSensor};
+
+	let mut sensor = EgmSensor::joint_target(1, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0], EgmClock::new(1, 0));
+	let joints_ptr = sensor.planned.as_ref().unwrap().joints.as_ref().unwrap().joints.as_ptr();
+
+	sensor.set_sequence_number(2);
+	sensor.set_joints(&[6.0, 5.0, 4.0, 3.0, 2.0, 1.0]);
+	sensor.set_time(EgmClock::new(2, 500_000));
+
+	assert!(sensor.header.as_ref().unwrap().seqno == Some(2));
+	assert!(sensor.header.as_ref().unwrap().tm == Some(2_500));
+	assert!(sensor.planned.as_ref().unwrap().time == Some(EgmClock::new(2, 500_000)));
+	let joints = &sensor.planned.as_ref().unwrap().joints.as_ref().unwrap().joints;
+	assert!(joints == &[6.0, 5.0, 4.0, 3.0, 2.0, 1.0]);
+	assert!(joints.as_ptr() == joints_ptr);
+
+	let mut sensor = EgmSensor::default();
+	sensor.set_external_joints(&[1.0]);
+	assert!(sensor.header.is_none());
+	assert!(sensor.planned.as_ref().unwrap().external_joints.as_ref().unwrap().joints == [1.0]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_speed_refs() {
+	use assert2::{assert, let_assert};
+	use msg::{EgmClock, EgmJoints, EgmPose, EgmQuaternion, EgmSensor, EgmSpeedRef};
+	use prost::Message;
+
+	let speed_ref = EgmSpeedRef::new(
+		Some(EgmJoints::from_degrees(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0])),
+		Some([10.0, 20.0, 30.0].into()),
+		Some(EgmJoints::from_degrees(vec![7.0])),
+	);
+	assert!(speed_ref.joints.as_ref().unwrap().joints == [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+	assert!(speed_ref.cartesians.as_ref().unwrap().value == [10.0, 20.0, 30.0]);
+	assert!(speed_ref.external_joints.as_ref().unwrap().joints == [7.0]);
+
+	let sensor = EgmSensor::joint_target_with_speed_refs(1, [0.0; 6], speed_ref.clone(), EgmClock::new(1, 0));
+	let_assert!(Ok(decoded) = EgmSensor::decode(sensor.encode_to_vec().as_slice()));
+	assert!(decoded.speed_ref.as_ref() == Some(&speed_ref));
+	assert!(decoded.planned.unwrap().joints.unwrap().joints == [0.0; 6]);
+
+	let pose = EgmPose::new([1.0, 2.0, 3.0], EgmQuaternion::IDENTITY);
+	let speed_ref = EgmSpeedRef::new(None, Some([5.0, 0.0, 0.0].into()), None);
+	let sensor = EgmSensor::pose_target_with_speed_refs(2, pose.clone(), speed_ref.clone(), EgmClock::new(1, 0));
+	let_assert!(Ok(decoded) = EgmSensor::decode(sensor.encode_to_vec().as_slice()));
+	assert!(decoded.speed_ref.as_ref() == Some(&speed_ref));
+	assert!(decoded.speed_ref.unwrap().joints.is_none());
+	assert!(decoded.planned.unwrap().cartesian == Some(pose));
+}
+
+#[cfg(test)]
+#[test]
+fn test_path_correction_age() {
+	use assert2::assert;
+	use msg::{EgmClock, EgmSensorPathCorr};
+
+	let msg = EgmSensorPathCorr::from_clocks(3, [1.0, 2.0, 3.0], EgmClock::new(1, 980_000), EgmClock::new(2, 12_000));
+	assert!(msg.header.as_ref().unwrap().seqno == Some(3));
+	assert!(msg.header.as_ref().unwrap().tm == Some(2_012));
+	assert!(msg.path_corr.as_ref().unwrap().age == 32);
+
+	let msg = EgmSensorPathCorr::from_clocks(3, [1.0, 2.0, 3.0], EgmClock::new(3, 0), EgmClock::new(2, 0));
+	assert!(msg.path_corr.as_ref().unwrap().age == 0);
+
+	let start = std::time::Instant::now();
+	let ms = Duration::from_millis;
+	let msg = EgmSensorPathCorr::from_instants(4, [1.0, 2.0, 3.0], start, start + ms(100), start + ms(140));
+	assert!(msg.header.as_ref().unwrap().tm == Some(140));
+	assert!(msg.path_corr.as_ref().unwrap().age == 40);
+}
+
+#[cfg(test)]
+#[test]
+fn test_fixed_size_joints() {
+	use assert2::assert;
+	use core::convert::TryFrom;
+	use msg::{EgmClock, EgmJoints, EgmRobot, EgmSensor};
+
+	let joints = EgmJoints::from_degrees(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+	assert!(<[f64; 6]>::try_from(&joints) == Ok([1.0, 2.0, 3.0, 4.0, 5.0, 6.0]));
+	assert!(<[f64; 7]>::try_from(&joints) == Err(WrongNumberOfJointsError { expected: 7, actual: 6 }));
+
+	let sensor = EgmSensor::joint_target_7axis(1, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0], EgmClock::new(0, 0));
+	let planned = sensor.planned.unwrap();
+	assert!(planned.joints.as_ref().unwrap().joints == [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+	assert!(planned.external_joints.as_ref().unwrap().joints == [7.0]);
+
+	let robot = EgmRobot {
+		planned: Some(planned),
+		..Default::default()
+	};
+	assert!(robot.planned_joints_7axis() == Some([1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]));
+	assert!(robot.feedback_joints_7axis() == None);
+}
+
 impl msg::EgmSensorPathCorr {
 	/// Create a sensor message containing a path correction.
 	pub fn new(sequence_number: u32, timestamp_ms: u32, correction: impl Into<msg::EgmCartesian>, age_ms: u32) -> Self {
@@ -554,12 +1412,46 @@ impl msg::EgmSensorPathCorr {
 		}
 	}
 
+	/// Create a path correction from the time the measurement was captured and the current time.
+	///
+	/// Both times must be on the same clock.
+	/// The header timestamp is created from `now`, and the age is the time between `captured_at` and `now`.
+	/// Measurements from the future get an age of zero.
+	pub fn from_clocks(sequence_number: u32, correction: impl Into<msg::EgmCartesian>, captured_at: impl Into<msg::EgmClock>, now: impl Into<msg::EgmClock>) -> Self {
+		let now = now.into();
+		let age = now.elapsed_since_epoch().saturating_sub(captured_at.into().elapsed_since_epoch());
+		Self::new(sequence_number, now.as_timestamp_ms(), correction, duration_to_ms(age))
+	}
+
+	/// Create a path correction from the instant the measurement was captured and the current instant.
+	///
+	/// The header timestamp is the time between `start` and `now`, where `start` is typically the start of the session.
+	/// The age is the time between `captured_at` and `now`.
+	/// Measurements from the future get an age of zero.
+	#[cfg(feature = "std")]
+	pub fn from_instants(
+		sequence_number: u32,
+		correction: impl Into<msg::EgmCartesian>,
+		start: std::time::Instant,
+		captured_at: std::time::Instant,
+		now: std::time::Instant,
+	) -> Self {
+		let timestamp = now.saturating_duration_since(start);
+		let age = now.saturating_duration_since(captured_at);
+		Self::new(sequence_number, duration_to_ms(timestamp), correction, duration_to_ms(age))
+	}
+
 	/// Check if any of the values are NaN.
 	pub fn has_nan(&self) -> bool {
 		self.path_corr.as_ref().map(|x| x.has_nan()).unwrap_or(false)
 	}
 }
 
+/// Convert a duration to whole milliseconds, saturating at `u32::MAX`.
+fn duration_to_ms(duration: Duration) -> u32 {
+	core::convert::TryFrom::try_from(duration.as_millis()).unwrap_or(u32::MAX)
+}
+
 impl msg::EgmFeedBack {
 	/// Check if any of the values are NaN.
 	pub fn has_nan(&self) -> bool {
@@ -571,7 +1463,29 @@ impl msg::EgmFeedBack {
 	}
 }
 
+#[cfg(feature = "robotware-6-10")]
+impl msg::EgmRapiDdata {
+	/// Create a new RAPID data message from a vector of values.
+	pub fn from_values(values: impl Into<Vec<f64>>) -> Self {
+		Self { dnum: values.into() }
+	}
+
+	/// Check if any of the values are NaN.
+	pub fn has_nan(&self) -> bool {
+		self.dnum.iter().any(|x| x.is_nan())
+	}
+}
+
 impl msg::EgmMeasuredForce {
+	/// Create a new measured force message from a vector of force values.
+	pub fn from_values(force: impl Into<Vec<f64>>) -> Self {
+		Self {
+			#[cfg(feature = "robotware-6-10")]
+			fc_active: None,
+			force: force.into(),
+		}
+	}
+
 	/// Check if any of the values are NaN.
 	pub fn has_nan(&self) -> bool {
 		self.force.iter().any(|x| x.is_nan())
@@ -599,6 +1513,14 @@ impl msg::EgmRobot {
 		Some(&self.feed_back.as_ref()?.external_joints.as_ref()?.joints)
 	}
 
+	/// Get the feedback joints of a 7-axis robot, such as a single arm of a YuMi, in axis order.
+	///
+	/// The controller reports axis 1 to 6 as joints and axis 7 as the first external joint.
+	/// Returns `None` if there are not exactly 6 joints, or if there is no external joint.
+	pub fn feedback_joints_7axis(&self) -> Option<[f64; 7]> {
+		join_7axis(self.feedback_joints()?, self.feedback_extenal_joints()?)
+	}
+
 	pub fn feedback_time(&self) -> Option<msg::EgmClock> {
 		self.feed_back.as_ref()?.time
 	}
@@ -615,6 +1537,13 @@ impl msg::EgmRobot {
 		Some(&self.planned.as_ref()?.external_joints.as_ref()?.joints)
 	}
 
+	/// Get the planned joints of a 7-axis robot, such as a single arm of a YuMi, in axis order.
+	///
+	/// See [`Self::feedback_joints_7axis`] for the index mapping.
+	pub fn planned_joints_7axis(&self) -> Option<[f64; 7]> {
+		join_7axis(self.planned_joints()?, self.planned_extenal_joints()?)
+	}
+
 	pub fn planned_time(&self) -> Option<msg::EgmClock> {
 		self.planned.as_ref()?.time
 	}
@@ -637,6 +1566,16 @@ impl msg::EgmRobot {
 		}
 	}
 
+	/// Check if the controller reports an MCI error, or `None` if the MCI state is absent or undefined.
+	pub fn mci_error(&self) -> Option<bool> {
+		use msg::egm_mci_state::MciStateType;
+		match self.mci_state.as_ref()?.state() {
+			MciStateType::MciUndefined => None,
+			MciStateType::MciError => Some(true),
+			MciStateType::MciStopped | MciStateType::MciRunning => Some(false),
+		}
+	}
+
 	pub fn test_signals(&self) -> Option<&Vec<f64>> {
 		Some(&self.test_signals.as_ref()?.signals)
 	}
@@ -652,25 +1591,34 @@ impl msg::EgmRobot {
 		let has_nan = has_nan || self.planned.as_ref().map(|x| x.has_nan()).unwrap_or(false);
 		let has_nan = has_nan || self.measured_force.as_ref().map(|x| x.has_nan()).unwrap_or(false);
 		let has_nan = has_nan || self.utilization_rate.as_ref().map(|x| x.is_nan()).unwrap_or(false);
+		#[cfg(feature = "robotware-6-10")]
+		let has_nan = has_nan || self.rapi_dfrom_robot.as_ref().map(|x| x.has_nan()).unwrap_or(false);
 		has_nan
 	}
 }
 
-/// Encode a protocol buffers message to a byte vector.
-fn encode_to_vec(msg: &impl prost::Message) -> Result<Vec<u8>, prost::EncodeError> {
-	let encoded_len = msg.encoded_len();
-	let mut buffer = Vec::<u8>::with_capacity(encoded_len);
-	msg.encode(&mut buffer)?;
-	Ok(buffer)
+/// Combine 6 joints and the first external joint into the axis values of a 7-axis robot.
+fn join_7axis(joints: &[f64], external_joints: &[f64]) -> Option<[f64; 7]> {
+	match (joints, external_joints.first()) {
+		(&[a1, a2, a3, a4, a5, a6], Some(&a7)) => Some([a1, a2, a3, a4, a5, a6, a7]),
+		_ => None,
+	}
 }
 
-#[cfg(test)]
-#[test]
-fn test_encode_to_vec() {
-	use assert2::assert;
-	use prost::Message;
+#[cfg(feature = "robotware-6-10")]
+impl msg::EgmRobot {
+	/// Check if the controller reports that force control is active.
+	pub fn force_control_active(&self) -> Option<bool> {
+		self.measured_force.as_ref()?.fc_active
+	}
+
+	/// Check if the collision detection of the controller was triggered.
+	pub fn collision_triggered(&self) -> Option<bool> {
+		self.collision_info.as_ref()?.collsion_triggered
+	}
 
-	assert!(encode_to_vec(&true).unwrap().len() == true.encoded_len());
-	assert!(encode_to_vec(&10).unwrap().len() == 10.encoded_len());
-	assert!(encode_to_vec(&String::from("aap noot mies")).unwrap().len() == String::from("aap noot mies").encoded_len());
+	/// Get the data sent from RAPID to the sensor.
+	pub fn rapid_from_robot(&self) -> Option<&Vec<f64>> {
+		Some(&self.rapi_dfrom_robot.as_ref()?.dnum)
+	}
 }
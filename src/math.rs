@@ -0,0 +1,67 @@
+//! Distance metrics between positions, orientations and joint vectors.
+//!
+//! The functions accept anything that converts into the EGM message types,
+//! so they also work with `nalgebra` vectors and rotations when the `nalgebra` feature is enabled.
+
+use crate::float;
+use crate::msg::{EgmCartesian, EgmQuaternion};
+
+/// Get the Euclidean distance between two positions, in millimeters.
+pub fn position_distance(a: impl Into<EgmCartesian>, b: impl Into<EgmCartesian>) -> f64 {
+	let (a, b) = (a.into(), b.into());
+	let (dx, dy, dz) = (a.x - b.x, a.y - b.y, a.z - b.z);
+	float::sqrt(dx * dx + dy * dy + dz * dz)
+}
+
+/// Get the angle of the rotation between two orientations, in degrees.
+///
+/// The result is in the range [0, 180].
+/// A quaternion and its negation describe the same orientation, so the angle between them is zero.
+pub fn orientation_angle(a: impl Into<EgmQuaternion>, b: impl Into<EgmQuaternion>) -> f64 {
+	a.into().angle_to(&b.into())
+}
+
+/// Get the Euclidean distance between two joint vectors.
+///
+/// The unit is degrees for rotating axes and millimeters for linear axes, so only mix the two deliberately.
+/// Returns `None` if the vectors have a different number of joints.
+pub fn joint_space_distance(a: &[f64], b: &[f64]) -> Option<f64> {
+	if a.len() != b.len() {
+		return None;
+	}
+	Some(float::sqrt(a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()))
+}
+
+/// Get the largest difference of a single joint between two joint vectors.
+///
+/// Returns `None` if the vectors have a different number of joints.
+pub fn max_joint_difference(a: &[f64], b: &[f64]) -> Option<f64> {
+	if a.len() != b.len() {
+		return None;
+	}
+	Some(a.iter().zip(b).map(|(a, b)| float::abs(a - b)).fold(0.0, f64::max))
+}
+
+#[cfg(test)]
+#[test]
+fn test_distances() {
+	use assert2::assert;
+	use crate::msg::EgmEuler;
+
+	assert!(position_distance([1.0, 2.0, 3.0], [4.0, 6.0, 3.0]) == 5.0);
+	let q = EgmEuler::from_xyz_degrees(0.0, 0.0, 30.0).to_quaternion();
+	assert!((orientation_angle(EgmQuaternion::IDENTITY, q) - 30.0).abs() < 1e-9);
+	assert!(orientation_angle(q, EgmQuaternion::from_wxyz(-q.u0, -q.u1, -q.u2, -q.u3)) < 1e-6);
+
+	assert!(joint_space_distance(&[0.0, 3.0, 1.0], &[4.0, 0.0, 1.0]) == Some(5.0));
+	assert!(joint_space_distance(&[0.0], &[0.0, 1.0]) == None);
+	assert!(max_joint_difference(&[0.0, 3.0, 1.0], &[-4.0, 0.0, 1.0]) == Some(4.0));
+
+	#[cfg(feature = "nalgebra")]
+	{
+		let a = nalgebra::Vector3::new(1.0, 2.0, 3.0);
+		assert!(position_distance(a, nalgebra::Vector3::new(1.0, 2.0, 5.0)) == 2.0);
+		let r = nalgebra::UnitQuaternion::from_euler_angles(0.0, 0.0, 0.5);
+		assert!((orientation_angle(r, nalgebra::UnitQuaternion::identity()) - 0.5f64.to_degrees()).abs() < 1e-9);
+	}
+}
@@ -0,0 +1,249 @@
+use std::io::{Read, Seek, Write};
+use std::time::Duration;
+
+use crate::msg::{EgmRobot, EgmSensor};
+use crate::recording::{Direction, Record, RecordingError, RecordingReader};
+
+/// The magic bytes at the start and end of an MCAP file.
+pub const MAGIC: [u8; 8] = *b"\x89MCAP0\r\n";
+
+/// The topic of the messages from the robot controller.
+pub const ROBOT_TOPIC: &str = "/egm/robot";
+
+/// The topic of the messages to the robot controller.
+pub const SENSOR_TOPIC: &str = "/egm/sensor";
+
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_DATA_END: u8 = 0x0f;
+
+const ROBOT_CHANNEL: u16 = 1;
+const SENSOR_CHANNEL: u16 = 2;
+
+/// Error that may occur when exporting to MCAP.
+#[derive(Debug)]
+pub enum McapError {
+	/// An I/O error occurred.
+	Io(std::io::Error),
+
+	/// A message could not be serialized as JSON.
+	Json(serde_json::Error),
+
+	/// The recording could not be read.
+	Recording(RecordingError),
+}
+
+/// Writer for MCAP files with EGM messages.
+///
+/// MCAP is the log format used by Foxglove, and the default storage format of rosbag2.
+/// The messages are not ROS messages, so the files can be inspected with Foxglove but not replayed with `ros2 bag play`.
+/// Messages from the robot controller are written to the [`ROBOT_TOPIC`] channel,
+/// and messages to the robot controller to the [`SENSOR_TOPIC`] channel.
+/// Messages are JSON encoded, with the same field names as the protobuf definitions,
+/// so they can be plotted and inspected without a protobuf schema.
+///
+/// The file is written without chunks or summary section, which keeps the writer simple and streaming.
+/// Readers find the messages by scanning the data section.
+pub struct McapWriter<W: Write> {
+	writer: W,
+	start_time: Duration,
+	sequence: [u32; 2],
+	buffer: Vec<u8>,
+}
+
+impl<W: Write> McapWriter<W> {
+	/// Create a writer and write the header, schemas and channels.
+	///
+	/// The start time is added to the time of each message to get the log time.
+	/// Use the time since the UNIX epoch of the start of the recording to get absolute timestamps in the viewer.
+	pub fn new(mut writer: W, start_time: Duration) -> Result<Self, McapError> {
+		writer.write_all(&MAGIC)?;
+
+		let mut buffer = Vec::new();
+		write_record(&mut writer, &mut buffer, OP_HEADER, |x| {
+			write_string(x, "");
+			write_string(x, concat!("abbegm ", env!("CARGO_PKG_VERSION")));
+		})?;
+
+		for (id, name) in [(ROBOT_CHANNEL, "abb.egm.EgmRobot"), (SENSOR_CHANNEL, "abb.egm.EgmSensor")] {
+			let schema = format!(r#"{{"title":"{}","type":"object"}}"#, name);
+			write_record(&mut writer, &mut buffer, OP_SCHEMA, |x| {
+				x.extend_from_slice(&id.to_le_bytes());
+				write_string(x, name);
+				write_string(x, "jsonschema");
+				write_string(x, &schema);
+			})?;
+		}
+
+		for (id, topic) in [(ROBOT_CHANNEL, ROBOT_TOPIC), (SENSOR_CHANNEL, SENSOR_TOPIC)] {
+			write_record(&mut writer, &mut buffer, OP_CHANNEL, |x| {
+				x.extend_from_slice(&id.to_le_bytes());
+				x.extend_from_slice(&id.to_le_bytes());
+				write_string(x, topic);
+				write_string(x, "json");
+				x.extend_from_slice(&0u32.to_le_bytes());
+			})?;
+		}
+
+		Ok(Self {
+			writer,
+			start_time,
+			sequence: [0; 2],
+			buffer,
+		})
+	}
+
+	/// Write a message from the robot controller.
+	pub fn write_robot(&mut self, time: Duration, msg: &EgmRobot) -> Result<(), McapError> {
+		let data = serde_json::to_vec(msg).map_err(McapError::Json)?;
+		self.write_message(ROBOT_CHANNEL, time, &data)
+	}
+
+	/// Write a message to the robot controller.
+	pub fn write_sensor(&mut self, time: Duration, msg: &EgmSensor) -> Result<(), McapError> {
+		let data = serde_json::to_vec(msg).map_err(McapError::Json)?;
+		self.write_message(SENSOR_CHANNEL, time, &data)
+	}
+
+	/// Write a recorded message.
+	///
+	/// Records that fail to decode are skipped.
+	/// Returns true if the record was written.
+	pub fn write_record(&mut self, record: &Record) -> Result<bool, McapError> {
+		match record.direction {
+			Direction::FromRobot => match record.decode_robot() {
+				Ok(msg) => self.write_robot(record.time, &msg)?,
+				Err(_) => return Ok(false),
+			},
+			Direction::ToRobot => match record.decode_sensor() {
+				Ok(msg) => self.write_sensor(record.time, &msg)?,
+				Err(_) => return Ok(false),
+			},
+		}
+		Ok(true)
+	}
+
+	/// Write the end of the data section and the footer, and return the inner writer.
+	pub fn finish(mut self) -> Result<W, McapError> {
+		// A CRC of zero means the CRC was not computed.
+		write_record(&mut self.writer, &mut self.buffer, OP_DATA_END, |x| x.extend_from_slice(&0u32.to_le_bytes()))?;
+		write_record(&mut self.writer, &mut self.buffer, OP_FOOTER, |x| x.extend_from_slice(&[0; 20]))?;
+		self.writer.write_all(&MAGIC)?;
+		self.writer.flush()?;
+		Ok(self.writer)
+	}
+
+	fn write_message(&mut self, channel: u16, time: Duration, data: &[u8]) -> Result<(), McapError> {
+		let sequence = &mut self.sequence[usize::from(channel - 1)];
+		let time = (self.start_time + time).as_nanos() as u64;
+		write_record(&mut self.writer, &mut self.buffer, OP_MESSAGE, |x| {
+			x.extend_from_slice(&channel.to_le_bytes());
+			x.extend_from_slice(&sequence.to_le_bytes());
+			x.extend_from_slice(&time.to_le_bytes());
+			x.extend_from_slice(&time.to_le_bytes());
+			x.extend_from_slice(data);
+		})?;
+		*sequence = sequence.wrapping_add(1);
+		Ok(())
+	}
+}
+
+/// Export all messages in a recording to an MCAP file.
+///
+/// See [`McapWriter::new`] for the meaning of the start time.
+pub fn export_recording<R: Read + Seek, W: Write>(reader: &mut RecordingReader<R>, writer: W, start_time: Duration) -> Result<W, McapError> {
+	let mut mcap = McapWriter::new(writer, start_time)?;
+	for index in 0..reader.len() {
+		if let Some(record) = reader.read(index)? {
+			mcap.write_record(&record)?;
+		}
+	}
+	mcap.finish()
+}
+
+/// Write a record with an opcode and length prefix, using a buffer to compute the length.
+fn write_record(writer: &mut impl Write, buffer: &mut Vec<u8>, opcode: u8, content: impl FnOnce(&mut Vec<u8>)) -> std::io::Result<()> {
+	buffer.clear();
+	content(buffer);
+	writer.write_all(&[opcode])?;
+	writer.write_all(&(buffer.len() as u64).to_le_bytes())?;
+	writer.write_all(buffer)
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+	buffer.extend_from_slice(&(value.len() as u32).to_le_bytes());
+	buffer.extend_from_slice(value.as_bytes());
+}
+
+impl From<std::io::Error> for McapError {
+	fn from(other: std::io::Error) -> Self {
+		Self::Io(other)
+	}
+}
+
+impl From<RecordingError> for McapError {
+	fn from(other: RecordingError) -> Self {
+		Self::Recording(other)
+	}
+}
+
+impl std::fmt::Display for McapError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "{}", e),
+			Self::Json(e) => write!(f, "failed to serialize message: {}", e),
+			Self::Recording(e) => write!(f, "failed to read recording: {}", e),
+		}
+	}
+}
+
+impl std::error::Error for McapError {}
+
+#[cfg(test)]
+#[test]
+fn test_mcap_export() {
+	use crate::msg::{EgmClock, EgmHeader};
+	use crate::recording::Recorder;
+	use assert2::{assert, let_assert};
+	use std::convert::TryInto;
+
+	let robot = EgmRobot {
+		header: Some(EgmHeader::data(3, 12)),
+		..Default::default()
+	};
+	let sensor = EgmSensor::joint_target(3, vec![1.0; 6], EgmClock::new(0, 12_000));
+	let mut recorder = Recorder::new(std::io::Cursor::new(Vec::new())).unwrap();
+	recorder.record_robot(Duration::from_millis(12), &robot).unwrap();
+	recorder.record_sensor(Duration::from_millis(13), &sensor).unwrap();
+	recorder.record(Direction::FromRobot, Duration::from_millis(14), &[0xff]).unwrap();
+	let mut reader = RecordingReader::new(recorder.finish().unwrap()).unwrap();
+	let_assert!(Ok(data) = export_recording(&mut reader, Vec::new(), Duration::from_secs(1)));
+
+	assert!(data[..8] == MAGIC);
+	assert!(data[data.len() - 8..] == MAGIC);
+
+	// Walk the records and collect the messages.
+	let mut records = &data[8..data.len() - 8];
+	let mut opcodes = Vec::new();
+	let mut messages = Vec::new();
+	while !records.is_empty() {
+		let len = u64::from_le_bytes(records[1..9].try_into().unwrap()) as usize;
+		let content = &records[9..9 + len];
+		if records[0] == OP_MESSAGE {
+			let channel = u16::from_le_bytes([content[0], content[1]]);
+			let time = u64::from_le_bytes(content[6..14].try_into().unwrap());
+			messages.push((channel, time, &content[22..]));
+		}
+		opcodes.push(records[0]);
+		records = &records[9 + len..];
+	}
+	assert!(opcodes == [OP_HEADER, OP_SCHEMA, OP_SCHEMA, OP_CHANNEL, OP_CHANNEL, OP_MESSAGE, OP_MESSAGE, OP_DATA_END, OP_FOOTER]);
+	assert!(messages[0].0 == ROBOT_CHANNEL);
+	assert!(messages[0].1 == 1_012_000_000);
+	assert!(serde_json::from_slice::<EgmRobot>(messages[0].2).unwrap() == robot);
+	assert!(messages[1].0 == SENSOR_CHANNEL);
+	assert!(serde_json::from_slice::<EgmSensor>(messages[1].2).unwrap() == sensor);
+}
@@ -0,0 +1,178 @@
+use std::borrow::Cow;
+
+use crate::msg::{EgmRobot, EgmSensor};
+use crate::MessageRejectedError;
+
+/// A hook that is invoked for every message sent or received by a peer.
+///
+/// Middleware can inspect messages (for example for logging), modify them or reject them.
+/// A rejected outgoing message is not sent, and a rejected incoming message is not returned to the caller.
+/// In both cases the peer returns a `Rejected` error.
+///
+/// The hooks take `&self` so that the asynchronous peer can receive and send messages concurrently.
+/// Use interior mutability if a middleware needs to keep state.
+pub trait Middleware: Send + Sync {
+	/// Called for every message before it is sent to the robot controller.
+	///
+	/// The message is validated after all middleware ran, so a middleware can not cause invalid messages to be sent.
+	fn on_send(&self, msg: &mut EgmSensor) -> Result<(), MessageRejectedError> {
+		let _ = msg;
+		Ok(())
+	}
+
+	/// Called for every message received from the robot controller.
+	fn on_recv(&self, msg: &mut EgmRobot) -> Result<(), MessageRejectedError> {
+		let _ = msg;
+		Ok(())
+	}
+}
+
+/// A stack of middleware layers.
+///
+/// Outgoing messages pass through the layers in the order they were added,
+/// incoming messages pass through the layers in the reverse order.
+/// That way, the first layer added is always the closest to the application.
+#[derive(Default)]
+pub struct MiddlewareStack {
+	layers: Vec<Box<dyn Middleware>>,
+}
+
+impl MiddlewareStack {
+	/// Create an empty stack.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Add a layer to the stack.
+	pub fn push(&mut self, layer: impl Middleware + 'static) {
+		self.layers.push(Box::new(layer));
+	}
+
+	/// Remove all layers from the stack.
+	pub fn clear(&mut self) {
+		self.layers.clear();
+	}
+
+	/// Get the number of layers in the stack.
+	pub fn len(&self) -> usize {
+		self.layers.len()
+	}
+
+	/// Check if the stack has no layers.
+	pub fn is_empty(&self) -> bool {
+		self.layers.is_empty()
+	}
+
+	/// Pass an outgoing message through all layers.
+	///
+	/// The message is only cloned if there is at least one layer.
+	pub fn process_outgoing<'a>(&self, msg: &'a EgmSensor) -> Result<Cow<'a, EgmSensor>, MessageRejectedError> {
+		if self.layers.is_empty() {
+			return Ok(Cow::Borrowed(msg));
+		}
+		let mut msg = msg.clone();
+		for layer in &self.layers {
+			layer.on_send(&mut msg)?;
+		}
+		Ok(Cow::Owned(msg))
+	}
+
+	/// Pass an incoming message through all layers.
+	pub fn process_incoming(&self, mut msg: EgmRobot) -> Result<EgmRobot, MessageRejectedError> {
+		for layer in self.layers.iter().rev() {
+			layer.on_recv(&mut msg)?;
+		}
+		Ok(msg)
+	}
+}
+
+impl std::fmt::Debug for MiddlewareStack {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("MiddlewareStack").field("layers", &self.layers.len()).finish()
+	}
+}
+
+/// Middleware that calls a function for every outgoing message.
+///
+/// Created with [`on_send`].
+pub struct OnSend<F> {
+	function: F,
+}
+
+/// Middleware that calls a function for every incoming message.
+///
+/// Created with [`on_recv`].
+pub struct OnRecv<F> {
+	function: F,
+}
+
+/// Create a middleware from a function that is called for every outgoing message.
+pub fn on_send<F>(function: F) -> OnSend<F>
+where
+	F: Fn(&mut EgmSensor) -> Result<(), MessageRejectedError> + Send + Sync,
+{
+	OnSend { function }
+}
+
+/// Create a middleware from a function that is called for every incoming message.
+pub fn on_recv<F>(function: F) -> OnRecv<F>
+where
+	F: Fn(&mut EgmRobot) -> Result<(), MessageRejectedError> + Send + Sync,
+{
+	OnRecv { function }
+}
+
+impl<F> Middleware for OnSend<F>
+where
+	F: Fn(&mut EgmSensor) -> Result<(), MessageRejectedError> + Send + Sync,
+{
+	fn on_send(&self, msg: &mut EgmSensor) -> Result<(), MessageRejectedError> {
+		(self.function)(msg)
+	}
+}
+
+impl<F> Middleware for OnRecv<F>
+where
+	F: Fn(&mut EgmRobot) -> Result<(), MessageRejectedError> + Send + Sync,
+{
+	fn on_recv(&self, msg: &mut EgmRobot) -> Result<(), MessageRejectedError> {
+		(self.function)(msg)
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_middleware_stack() {
+	use crate::msg::EgmClock;
+	use assert2::assert;
+	use std::sync::atomic::{AtomicU32, Ordering};
+	use std::sync::Arc;
+
+	let received = Arc::new(AtomicU32::new(0));
+	let mut stack = MiddlewareStack::new();
+	stack.push(on_send(|msg: &mut EgmSensor| {
+		msg.set_sequence_number(msg.header.as_ref().and_then(|x| x.seqno).unwrap_or(0) + 10);
+		Ok(())
+	}));
+	stack.push(on_send(|msg: &mut EgmSensor| match msg.header.as_ref().and_then(|x| x.seqno) {
+		Some(seqno) if seqno > 15 => Err(MessageRejectedError::new("sequence number too high")),
+		_ => Ok(()),
+	}));
+	stack.push(on_recv({
+		let received = received.clone();
+		move |_: &mut EgmRobot| {
+			received.fetch_add(1, Ordering::Relaxed);
+			Ok(())
+		}
+	}));
+
+	let msg = EgmSensor::joint_target(1, [0.0; 6], EgmClock::new(0, 0));
+	let processed = stack.process_outgoing(&msg).unwrap();
+	assert!(processed.header.as_ref().unwrap().seqno == Some(11));
+
+	let msg = EgmSensor::joint_target(6, [0.0; 6], EgmClock::new(0, 0));
+	assert!(let Err(MessageRejectedError { .. }) = stack.process_outgoing(&msg));
+
+	stack.process_incoming(EgmRobot::default()).unwrap();
+	assert!(received.load(Ordering::Relaxed) == 1);
+}
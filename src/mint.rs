@@ -0,0 +1,74 @@
+use crate::msg;
+
+// Vector3
+
+impl From<msg::EgmCartesian> for mint::Vector3<f64> {
+	fn from(other: msg::EgmCartesian) -> Self {
+		Self {
+			x: other.x,
+			y: other.y,
+			z: other.z,
+		}
+	}
+}
+
+impl From<mint::Vector3<f64>> for msg::EgmCartesian {
+	fn from(other: mint::Vector3<f64>) -> Self {
+		Self::from_mm(other.x, other.y, other.z)
+	}
+}
+
+// Point3
+
+impl From<msg::EgmCartesian> for mint::Point3<f64> {
+	fn from(other: msg::EgmCartesian) -> Self {
+		Self {
+			x: other.x,
+			y: other.y,
+			z: other.z,
+		}
+	}
+}
+
+impl From<mint::Point3<f64>> for msg::EgmCartesian {
+	fn from(other: mint::Point3<f64>) -> Self {
+		Self::from_mm(other.x, other.y, other.z)
+	}
+}
+
+// Quaternion
+
+impl From<msg::EgmQuaternion> for mint::Quaternion<f64> {
+	fn from(other: msg::EgmQuaternion) -> Self {
+		Self {
+			s: other.scalar(),
+			v: other.vector().into(),
+		}
+	}
+}
+
+impl From<mint::Quaternion<f64>> for msg::EgmQuaternion {
+	fn from(other: mint::Quaternion<f64>) -> Self {
+		Self::from_scalar_vector(other.s, other.v.into())
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_mint_round_trip() {
+	use assert2::assert;
+
+	let position = msg::EgmCartesian::from_mm(1.0, 2.0, 3.0);
+	let vector = mint::Vector3::from(position);
+	assert!((vector.x, vector.y, vector.z) == (1.0, 2.0, 3.0));
+	assert!(msg::EgmCartesian::from(vector) == position);
+	let point = mint::Point3::from(position);
+	assert!((point.x, point.y, point.z) == (1.0, 2.0, 3.0));
+	assert!(msg::EgmCartesian::from(point) == position);
+
+	let quaternion = msg::EgmQuaternion::from_wxyz(0.1, 0.2, 0.3, 0.4);
+	let converted = mint::Quaternion::from(quaternion);
+	assert!(converted.s == 0.1);
+	assert!((converted.v.x, converted.v.y, converted.v.z) == (0.2, 0.3, 0.4));
+	assert!(msg::EgmQuaternion::from(converted) == quaternion);
+}
@@ -0,0 +1,287 @@
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::msg::EgmRobot;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const DISCONNECT: u8 = 0xE0;
+
+/// The quality of service level of published telemetry.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MqttQos {
+	/// QoS 0: messages are sent once, without acknowledgement.
+	AtMostOnce,
+
+	/// QoS 1: messages are acknowledged by the broker.
+	///
+	/// Messages are not retransmitted by the publisher,
+	/// but the number of messages without acknowledgement is available with [`MqttPublisher::unacknowledged`].
+	AtLeastOnce,
+}
+
+/// Publishes decimated robot feedback to an MQTT broker, for dashboards that consume IIoT telemetry.
+///
+/// This is a minimal MQTT 3.1.1 client that only publishes.
+/// Every message passed to [`MqttPublisher::publish`] is published on three topics below the topic prefix,
+/// unless it arrives within the minimum interval after the last published message:
+///   * `<prefix>/joints`: the feedback joints in degrees, as `{"joints": [...]}`.
+///   * `<prefix>/pose`: the feedback pose, as the JSON encoding of an [`EgmPose`](crate::msg::EgmPose).
+///   * `<prefix>/state`: the motor, RAPID and MCI state, as `{"motors_enabled": true, "rapid_running": true, "mci_error": false}`.
+///
+/// Publishing writes to a TCP stream and may block if the broker is slow,
+/// so call it from a telemetry thread rather than from the control loop.
+pub struct MqttPublisher {
+	stream: TcpStream,
+	topic_prefix: String,
+	qos: MqttQos,
+	retain: bool,
+	interval: Duration,
+	last_published: Option<Instant>,
+	next_packet_id: u16,
+	pending: Arc<Mutex<BTreeSet<u16>>>,
+}
+
+impl MqttPublisher {
+	/// Connect to an MQTT broker, such as `localhost:1883`, with a client identifier.
+	///
+	/// By default, messages are published below the topic prefix `egm` with QoS 0, without the retain flag, at most 10 times per second.
+	pub fn connect(address: impl ToSocketAddrs, client_id: &str) -> std::io::Result<Self> {
+		let mut stream = TcpStream::connect(address)?;
+		stream.set_nodelay(true)?;
+
+		let mut connect = Vec::new();
+		write_string(&mut connect, "MQTT");
+		// Protocol level 4, clean session, keep alive disabled.
+		connect.extend_from_slice(&[4, 0x02, 0, 0]);
+		write_string(&mut connect, client_id);
+		write_packet(&mut stream, CONNECT, &connect)?;
+
+		stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+		let (header, body) = read_packet(&mut stream)?;
+		if header & 0xF0 != CONNACK || body.len() != 2 {
+			return Err(invalid_data("expected CONNACK from MQTT broker"));
+		}
+		if body[1] != 0 {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::ConnectionRefused,
+				format!("MQTT broker refused the connection with return code {}", body[1]),
+			));
+		}
+		stream.set_read_timeout(None)?;
+
+		let pending = Arc::new(Mutex::new(BTreeSet::new()));
+		let reader = stream.try_clone()?;
+		let reader_pending = pending.clone();
+		std::thread::Builder::new()
+			.name("egm-mqtt".into())
+			.spawn(move || read_acknowledgements(reader, reader_pending))?;
+
+		Ok(Self {
+			stream,
+			topic_prefix: "egm".into(),
+			qos: MqttQos::AtMostOnce,
+			retain: false,
+			interval: Duration::from_millis(100),
+			last_published: None,
+			next_packet_id: 1,
+			pending,
+		})
+	}
+
+	/// Set the prefix of the published topics.
+	pub fn with_topic_prefix(mut self, topic_prefix: impl Into<String>) -> Self {
+		self.topic_prefix = topic_prefix.into();
+		self
+	}
+
+	/// Set the quality of service level of the published messages.
+	pub fn with_qos(mut self, qos: MqttQos) -> Self {
+		self.qos = qos;
+		self
+	}
+
+	/// Set if the broker should retain the last message of each topic for new subscribers.
+	pub fn with_retain(mut self, retain: bool) -> Self {
+		self.retain = retain;
+		self
+	}
+
+	/// Set the minimum interval between published messages.
+	pub fn with_interval(mut self, interval: Duration) -> Self {
+		self.interval = interval;
+		self
+	}
+
+	/// Get the number of QoS 1 messages that were not acknowledged by the broker yet.
+	pub fn unacknowledged(&self) -> usize {
+		self.pending.lock().unwrap().len()
+	}
+
+	/// Publish the feedback of a message from the robot controller, unless it arrives within the minimum interval.
+	///
+	/// Returns true if the message was published.
+	pub fn publish(&mut self, state: &EgmRobot) -> std::io::Result<bool> {
+		let now = Instant::now();
+		if let Some(last_published) = self.last_published {
+			if now < last_published + self.interval {
+				return Ok(false);
+			}
+		}
+		self.last_published = Some(now);
+
+		if let Some(joints) = state.feedback_joints() {
+			self.publish_json("joints", &json!({ "joints": joints }))?;
+		}
+		if let Some(pose) = state.feedback_pose() {
+			self.publish_json("pose", &json!(pose))?;
+		}
+		let status = json!({
+			"motors_enabled": state.motors_enabled(),
+			"rapid_running": state.rapid_running(),
+			"mci_error": state.mci_error(),
+		});
+		self.publish_json("state", &status)?;
+		Ok(true)
+	}
+
+	fn publish_json(&mut self, topic: &str, value: &serde_json::Value) -> std::io::Result<()> {
+		let mut body = Vec::new();
+		write_string(&mut body, &format!("{}/{}", self.topic_prefix, topic));
+		let mut header = PUBLISH | u8::from(self.retain);
+		if self.qos == MqttQos::AtLeastOnce {
+			let packet_id = self.next_packet_id;
+			self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+			self.pending.lock().unwrap().insert(packet_id);
+			body.extend_from_slice(&packet_id.to_be_bytes());
+			header |= 0x02;
+		}
+		body.extend_from_slice(value.to_string().as_bytes());
+		write_packet(&mut self.stream, header, &body)
+	}
+}
+
+impl Drop for MqttPublisher {
+	fn drop(&mut self) {
+		let _ = write_packet(&mut self.stream, DISCONNECT, &[]);
+		let _ = self.stream.shutdown(Shutdown::Both);
+	}
+}
+
+fn read_acknowledgements(mut stream: TcpStream, pending: Arc<Mutex<BTreeSet<u16>>>) {
+	while let Ok((header, body)) = read_packet(&mut stream) {
+		if header & 0xF0 == PUBACK && body.len() == 2 {
+			pending.lock().unwrap().remove(&u16::from_be_bytes([body[0], body[1]]));
+		}
+	}
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Append a length-prefixed UTF-8 string.
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+	buffer.extend_from_slice(&(value.len() as u16).to_be_bytes());
+	buffer.extend_from_slice(value.as_bytes());
+}
+
+/// Write a packet with a fixed header and a variable length encoded remaining length.
+fn write_packet(stream: &mut impl Write, header: u8, body: &[u8]) -> std::io::Result<()> {
+	let mut packet = Vec::with_capacity(body.len() + 5);
+	packet.push(header);
+	let mut remaining = body.len();
+	loop {
+		let byte = (remaining % 128) as u8;
+		remaining /= 128;
+		packet.push(if remaining > 0 { byte | 0x80 } else { byte });
+		if remaining == 0 {
+			break;
+		}
+	}
+	packet.extend_from_slice(body);
+	stream.write_all(&packet)
+}
+
+/// Read a packet, returning the first byte of the fixed header and the rest of the packet.
+fn read_packet(stream: &mut impl Read) -> std::io::Result<(u8, Vec<u8>)> {
+	let mut byte = [0];
+	stream.read_exact(&mut byte)?;
+	let header = byte[0];
+	let mut length = 0usize;
+	for shift in 0..4 {
+		stream.read_exact(&mut byte)?;
+		length |= usize::from(byte[0] & 0x7F) << (7 * shift);
+		if byte[0] & 0x80 == 0 {
+			let mut body = vec![0; length];
+			stream.read_exact(&mut body)?;
+			return Ok((header, body));
+		}
+	}
+	Err(invalid_data("invalid remaining length in MQTT packet"))
+}
+
+#[cfg(test)]
+#[test]
+fn test_mqtt_publisher() {
+	use crate::msg::{EgmFeedBack, EgmJoints};
+	use assert2::{assert, let_assert};
+
+	let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+	let address = listener.local_addr().unwrap();
+	let broker = std::thread::spawn(move || {
+		let (mut stream, _) = listener.accept().unwrap();
+		let (header, body) = read_packet(&mut stream).unwrap();
+		assert!(header == CONNECT);
+		assert!(body[..7] == [0, 4, b'M', b'Q', b'T', b'T', 4]);
+		assert!(body[10..] == [0, 4, b't', b'e', b's', b't']);
+		write_packet(&mut stream, CONNACK, &[0, 0]).unwrap();
+
+		let mut published = Vec::new();
+		loop {
+			let (header, body) = read_packet(&mut stream).unwrap();
+			if header == DISCONNECT {
+				return published;
+			}
+			assert!(header == PUBLISH | 0x02);
+			let topic_len = usize::from(u16::from_be_bytes([body[0], body[1]]));
+			let packet_id = [body[2 + topic_len], body[3 + topic_len]];
+			write_packet(&mut stream, PUBACK, &packet_id).unwrap();
+			let topic = String::from_utf8(body[2..2 + topic_len].to_vec()).unwrap();
+			let payload: serde_json::Value = serde_json::from_slice(&body[4 + topic_len..]).unwrap();
+			published.push((topic, payload));
+		}
+	});
+
+	let_assert!(Ok(publisher) = MqttPublisher::connect(address, "test"));
+	let mut publisher = publisher.with_topic_prefix("cell/robot").with_qos(MqttQos::AtLeastOnce).with_interval(Duration::from_secs(60));
+	let state = EgmRobot {
+		feed_back: Some(EgmFeedBack {
+			joints: Some(EgmJoints::from_degrees(vec![1.0, 2.0])),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	assert!(let Ok(true) = publisher.publish(&state));
+	assert!(let Ok(false) = publisher.publish(&state));
+
+	let start = Instant::now();
+	while publisher.unacknowledged() > 0 {
+		assert!(start.elapsed() < Duration::from_secs(5));
+		std::thread::sleep(Duration::from_millis(5));
+	}
+	drop(publisher);
+
+	let published = broker.join().unwrap();
+	assert!(published.len() == 2);
+	assert!(published[0] == ("cell/robot/joints".to_string(), json!({ "joints": [1.0, 2.0] })));
+	assert!(published[1].0 == "cell/robot/state");
+	assert!(published[1].1["motors_enabled"].is_null());
+}
@@ -70,13 +70,13 @@ impl_through_ref!(TryFrom<msg::EgmCartesianSpeed> for nalgebra::Vector3<f64>);
 
 impl From<&msg::EgmQuaternion> for nalgebra::Quaternion<f64> {
 	fn from(other: &msg::EgmQuaternion) -> Self {
-		Self::new(other.u0, other.u1, other.u2, other.u3)
+		Self::from_parts(other.scalar(), other.vector().into())
 	}
 }
 
 impl From<&nalgebra::Quaternion<f64>> for msg::EgmQuaternion {
 	fn from(other: &nalgebra::Quaternion<f64>) -> Self {
-		Self::from_wxyz(other.scalar(), other.imag().x, other.imag().y, other.imag().z)
+		Self::from_scalar_vector(other.scalar(), other.imag().into())
 	}
 }
 
@@ -139,6 +139,60 @@ impl From<&nalgebra::Isometry3<f64>> for msg::EgmPose {
 impl_through_ref!(From<nalgebra::Isometry3<f64>> for msg::EgmPose);
 impl_through_ref!(TryFrom<msg::EgmPose> for nalgebra::Isometry3<f64>);
 
+impl msg::EgmPose {
+	/// Create a new 6-DOF pose from a position in millimeters and a unit quaternion.
+	///
+	/// Prefer this over [`msg::EgmQuaternion::from_wxyz`] when the orientation comes from `nalgebra`,
+	/// since the unit quaternion is guaranteed to be normalized and its components can not be passed in the wrong order.
+	///
+	/// In debug builds, this panics if the quaternion has drifted away from unit length.
+	pub fn from_mm_and_unit_quat(position: impl Into<msg::EgmCartesian>, orientation: &nalgebra::UnitQuaternion<f64>) -> Self {
+		debug_assert!(
+			(orientation.as_ref().norm() - 1.0).abs() < 1e-6,
+			"orientation is not a unit quaternion, its norm is {}",
+			orientation.as_ref().norm()
+		);
+		Self::new(position, orientation)
+	}
+}
+
+impl msg::EgmSensor {
+	/// Create a sensor message containing a 6-DOF pose target from a position in millimeters and a unit quaternion.
+	///
+	/// See [`msg::EgmPose::from_mm_and_unit_quat`] and [`msg::EgmSensor::pose_target`].
+	pub fn pose_target_unit_quat(
+		sequence_number: u32,
+		position: impl Into<msg::EgmCartesian>,
+		orientation: &nalgebra::UnitQuaternion<f64>,
+		time: impl Into<msg::EgmClock>,
+	) -> Self {
+		Self::pose_target(sequence_number, msg::EgmPose::from_mm_and_unit_quat(position, orientation), time)
+	}
+}
+
+// Wrench
+
+impl crate::wrench::Wrench {
+	/// Get the force as vector in newton.
+	pub fn force_vector(&self) -> nalgebra::Vector3<f64> {
+		self.force.into()
+	}
+
+	/// Get the torque as vector in newton meter.
+	pub fn torque_vector(&self) -> nalgebra::Vector3<f64> {
+		self.torque.into()
+	}
+}
+
+impl From<&crate::wrench::Wrench> for nalgebra::Vector6<f64> {
+	/// Convert a wrench into a vector with the force followed by the torque.
+	fn from(other: &crate::wrench::Wrench) -> Self {
+		other.to_values().into()
+	}
+}
+
+impl_through_ref!(From<crate::wrench::Wrench> for nalgebra::Vector6<f64>);
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum TryFromEgmCartesianSpeedError {
 	WrongNumberOfValues(usize),
@@ -169,3 +223,25 @@ impl std::fmt::Display for TryFromEgmPoseError {
 
 impl std::error::Error for TryFromEgmCartesianSpeedError {}
 impl std::error::Error for TryFromEgmPoseError {}
+
+#[cfg(test)]
+#[test]
+fn test_from_mm_and_unit_quat() {
+	use assert2::assert;
+
+	let orientation = nalgebra::UnitQuaternion::from_euler_angles(0.0, 0.0, core::f64::consts::FRAC_PI_2);
+	let pose = msg::EgmPose::from_mm_and_unit_quat([1.0, 2.0, 3.0], &orientation);
+	assert!(pose.pos.unwrap().as_mm() == [1.0, 2.0, 3.0]);
+	assert!(pose.orient.unwrap().approx_eq(&msg::EgmEuler::from_xyz_degrees(0.0, 0.0, 90.0).to_quaternion(), 1e-9));
+
+	// Converting back and forth must preserve the component order.
+	let quaternion = msg::EgmQuaternion::from_wxyz(0.1, 0.2, 0.3, 0.4);
+	let converted = nalgebra::Quaternion::from(quaternion);
+	assert!(converted.w == 0.1);
+	assert!(converted.imag() == nalgebra::Vector3::new(0.2, 0.3, 0.4));
+	assert!(msg::EgmQuaternion::from(converted) == quaternion);
+	assert!(nalgebra::UnitQuaternion::from(msg::EgmQuaternion::from(orientation)) == orientation);
+
+	let sensor = msg::EgmSensor::pose_target_unit_quat(7, [1.0, 2.0, 3.0], &orientation, msg::EgmClock::new(0, 4_000));
+	assert!(sensor.planned.unwrap().cartesian == Some(pose));
+}
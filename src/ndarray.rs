@@ -0,0 +1,99 @@
+use ndarray::{Array1, Array2, ArrayView1, ArrayView2};
+use std::convert::TryFrom;
+
+use crate::error::WrongNumberOfJointsError;
+use crate::msg;
+
+// Array1
+
+impl From<&msg::EgmJoints> for Array1<f64> {
+	fn from(other: &msg::EgmJoints) -> Self {
+		Self::from(other.joints.clone())
+	}
+}
+
+impl From<msg::EgmJoints> for Array1<f64> {
+	fn from(other: msg::EgmJoints) -> Self {
+		Self::from(other.joints)
+	}
+}
+
+impl From<ArrayView1<'_, f64>> for msg::EgmJoints {
+	fn from(other: ArrayView1<'_, f64>) -> Self {
+		Self::from_degrees(other.to_vec())
+	}
+}
+
+impl From<Array1<f64>> for msg::EgmJoints {
+	fn from(other: Array1<f64>) -> Self {
+		Self::from_degrees(other.to_vec())
+	}
+}
+
+// Array2
+
+impl msg::EgmJoints {
+	/// Stack joint lists into an array with one row per sample and one column per joint.
+	///
+	/// Fails if the joint lists do not all have the same number of joints.
+	/// The `From` and `TryFrom` traits can not be implemented between `Vec<EgmJoints>` and `Array2<f64>`,
+	/// because neither type is defined in this crate.
+	pub fn to_array2(joints: &[Self]) -> Result<Array2<f64>, WrongNumberOfJointsError> {
+		stack_rows(joints.iter().map(|joints| joints.joints.as_slice()))
+	}
+
+	/// Split an array with one row per sample and one column per joint into joint lists.
+	pub fn from_array2(array: ArrayView2<'_, f64>) -> Vec<Self> {
+		array.outer_iter().map(Self::from).collect()
+	}
+}
+
+impl TryFrom<&crate::feedback::FeedbackBuffer> for Array2<f64> {
+	type Error = WrongNumberOfJointsError;
+
+	/// Get the joint positions of all samples in the buffer, see [`crate::feedback::FeedbackBuffer::joint_array`].
+	fn try_from(other: &crate::feedback::FeedbackBuffer) -> Result<Self, Self::Error> {
+		other.joint_array()
+	}
+}
+
+/// Stack rows of joint positions into an array.
+pub(crate) fn stack_rows<'a>(rows: impl IntoIterator<Item = &'a [f64]>) -> Result<Array2<f64>, WrongNumberOfJointsError> {
+	let mut rows = rows.into_iter().peekable();
+	let columns = rows.peek().map(|row| row.len()).unwrap_or(0);
+	let mut data = Vec::new();
+	for row in rows {
+		if row.len() != columns {
+			return Err(WrongNumberOfJointsError {
+				expected: columns,
+				actual: row.len(),
+			});
+		}
+		data.extend_from_slice(row);
+	}
+	let shape = (data.len().checked_div(columns).unwrap_or(0), columns);
+	Ok(Array2::from_shape_vec(shape, data).expect("length of data must match the shape"))
+}
+
+#[cfg(test)]
+#[test]
+fn test_ndarray_round_trip() {
+	use assert2::{assert, let_assert};
+	use msg::EgmJoints;
+
+	let joints = vec![EgmJoints::from_degrees(vec![1.0, 2.0]), EgmJoints::from_degrees(vec![3.0, 4.0]), EgmJoints::from_degrees(vec![5.0, 6.0])];
+	let_assert!(Ok(array) = EgmJoints::to_array2(&joints));
+	assert!(array == ndarray::array![[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]]);
+	assert!(EgmJoints::from_array2(array.view()) == joints);
+
+	assert!(Array1::from(&joints[1]) == ndarray::array![3.0, 4.0]);
+	assert!(EgmJoints::from(Array1::from(joints[1].clone())) == joints[1]);
+	assert!(EgmJoints::from(array.row(2)) == joints[2]);
+
+	let mismatched = [EgmJoints::from_degrees(vec![1.0, 2.0]), EgmJoints::from_degrees(vec![3.0])];
+	assert!(EgmJoints::to_array2(&mismatched) == Err(WrongNumberOfJointsError { expected: 2, actual: 1 }));
+	let_assert!(Ok(empty) = EgmJoints::to_array2(&[]));
+	assert!(empty.dim() == (0, 0));
+	let_assert!(Ok(empty) = Array2::try_from(&crate::feedback::FeedbackBuffer::new(4)));
+	assert!(empty.dim() == (0, 0));
+}
@@ -0,0 +1,154 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+/// Bind a UDP socket on all IPv6 and IPv4 interfaces.
+///
+/// The socket accepts IPv6 and IPv4 traffic regardless of the platform default:
+/// Linux creates dual-stack sockets by default, but Windows does not.
+/// IPv4 senders are reported as IPv4-mapped IPv6 addresses: use [`normalize_address`] to compare them with IPv4 addresses.
+pub fn bind_dual_stack(port: u16) -> std::io::Result<UdpSocket> {
+	let socket = socket2::Socket::new(socket2::Domain::IPV6, socket2::Type::DGRAM, Some(socket2::Protocol::UDP))?;
+	socket.set_only_v6(false)?;
+	socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())?;
+	Ok(socket.into())
+}
+
+/// Get the local IP address of the interface that the operating system uses to reach a remote address.
+///
+/// No datagrams are sent: the address is determined from the routing table.
+pub fn local_address_toward(remote: SocketAddr) -> std::io::Result<IpAddr> {
+	let unspecified = match remote {
+		SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+		SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+	};
+	let socket = UdpSocket::bind((unspecified, 0))?;
+	socket.connect(remote)?;
+	Ok(socket.local_addr()?.ip())
+}
+
+/// Bind a UDP socket on the interface that the operating system uses to reach the robot controller.
+///
+/// On PCs with multiple network interfaces, binding to all interfaces means the robot controller
+/// may be configured with the address of the wrong interface.
+/// Binding to the interface toward the controller makes the mistake visible at bind time instead.
+pub fn bind_toward(controller: SocketAddr, port: u16) -> std::io::Result<UdpSocket> {
+	UdpSocket::bind((local_address_toward(controller)?, port))
+}
+
+/// Get the local endpoint that the robot controller must send its messages to.
+///
+/// If the socket is bound to all interfaces, the address of the interface toward the controller is used.
+/// The result can be used in the configuration of the controller,
+/// for example with [`crate::rapid::RapidSetup::transmission_protocol_cfg`].
+pub fn effective_local_endpoint(socket: &UdpSocket, controller: SocketAddr) -> std::io::Result<SocketAddr> {
+	let local = socket.local_addr()?;
+	if !local.ip().is_unspecified() {
+		return Ok(local);
+	}
+	Ok(SocketAddr::new(local_address_toward(normalize_address(controller))?, local.port()))
+}
+
+/// Stop ICMP port unreachable messages from resetting a UDP socket on Windows.
+///
+/// When the robot controller reboots, datagrams sent to it trigger ICMP port unreachable messages.
+/// By default, Windows reports these as `WSAECONNRESET` on the next receive call, for connected and unconnected sockets,
+/// and may drop the datagrams that arrived in the meantime.
+/// This sets the `SIO_UDP_CONNRESET` option of the socket to disable that behavior.
+///
+/// On other platforms this does nothing.
+/// Note that Linux reports ICMP errors once as [`std::io::ErrorKind::ConnectionRefused`] on connected sockets,
+/// after which the socket keeps working.
+#[cfg(windows)]
+pub fn disable_connection_reset(socket: &impl std::os::windows::io::AsRawSocket) -> std::io::Result<()> {
+	use std::ffi::c_void;
+
+	const SIO_UDP_CONNRESET: u32 = 0x9800_000C;
+
+	#[link(name = "ws2_32")]
+	extern "system" {
+		fn WSAIoctl(
+			socket: usize,
+			control_code: u32,
+			in_buffer: *const c_void,
+			in_length: u32,
+			out_buffer: *mut c_void,
+			out_length: u32,
+			bytes_returned: *mut u32,
+			overlapped: *mut c_void,
+			completion_routine: *const c_void,
+		) -> i32;
+	}
+
+	let enable: u32 = 0;
+	let mut bytes_returned = 0;
+	let result = unsafe {
+		WSAIoctl(
+			socket.as_raw_socket() as usize,
+			SIO_UDP_CONNRESET,
+			&enable as *const u32 as *const c_void,
+			std::mem::size_of::<u32>() as u32,
+			std::ptr::null_mut(),
+			0,
+			&mut bytes_returned,
+			std::ptr::null_mut(),
+			std::ptr::null(),
+		)
+	};
+	if result == 0 {
+		Ok(())
+	} else {
+		Err(std::io::Error::last_os_error())
+	}
+}
+
+/// Stop ICMP port unreachable messages from resetting a UDP socket on Windows.
+///
+/// When the robot controller reboots, datagrams sent to it trigger ICMP port unreachable messages.
+/// By default, Windows reports these as `WSAECONNRESET` on the next receive call, for connected and unconnected sockets,
+/// and may drop the datagrams that arrived in the meantime.
+/// This sets the `SIO_UDP_CONNRESET` option of the socket to disable that behavior.
+///
+/// On other platforms this does nothing.
+/// Note that Linux reports ICMP errors once as [`std::io::ErrorKind::ConnectionRefused`] on connected sockets,
+/// after which the socket keeps working.
+#[cfg(not(windows))]
+pub fn disable_connection_reset<S>(socket: &S) -> std::io::Result<()> {
+	let _ = socket;
+	Ok(())
+}
+
+/// Convert an IPv4-mapped IPv6 address into a plain IPv4 address.
+///
+/// Other addresses are returned unchanged.
+pub fn normalize_address(address: SocketAddr) -> SocketAddr {
+	match address {
+		SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+			Some(ip) => SocketAddr::new(IpAddr::V4(ip), v6.port()),
+			None => address,
+		},
+		SocketAddr::V4(_) => address,
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_net() {
+	use assert2::{assert, let_assert};
+
+	let_assert!(Ok(socket) = bind_dual_stack(0));
+	let port = socket.local_addr().unwrap().port();
+	let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+	sender.send_to(b"egm", ("127.0.0.1", port)).unwrap();
+	let mut buffer = [0; 16];
+	let_assert!(Ok((3, from)) = socket.recv_from(&mut buffer));
+	assert!(normalize_address(from) == sender.local_addr().unwrap());
+
+	let controller = sender.local_addr().unwrap();
+	let_assert!(Ok(ip) = local_address_toward(controller));
+	assert!(ip == IpAddr::V4(Ipv4Addr::LOCALHOST));
+	let_assert!(Ok(socket) = bind_toward(controller, 0));
+	assert!(socket.local_addr().unwrap().ip() == ip);
+
+	let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+	let_assert!(Ok(endpoint) = effective_local_endpoint(&socket, controller));
+	assert!(endpoint == SocketAddr::new(ip, socket.local_addr().unwrap().port()));
+}
@@ -0,0 +1,114 @@
+use opcua::server::address_space::{AddressSpace, VariableBuilder};
+use opcua::server::node_manager::memory::{InMemoryNodeManager, InMemoryNodeManagerImpl};
+use opcua::server::SubscriptionCache;
+use opcua::types::{DataTypeId, DataValue, NodeId, StatusCode, Variant};
+
+use crate::field_mapping::{FeedbackField, FieldMapping, FieldValue};
+use crate::msg::EgmRobot;
+
+impl From<FieldValue> for Variant {
+	fn from(other: FieldValue) -> Self {
+		match other {
+			FieldValue::Double(x) => Variant::Double(x),
+			FieldValue::UInt32(x) => Variant::UInt32(x),
+			FieldValue::Boolean(x) => Variant::Boolean(x),
+		}
+	}
+}
+
+/// Get the OPC UA data type and initial value of the variable for a field.
+fn data_type(field: FeedbackField) -> (DataTypeId, Variant) {
+	match field {
+		FeedbackField::SequenceNumber => (DataTypeId::UInt32, Variant::UInt32(0)),
+		FeedbackField::MotorsEnabled | FeedbackField::RapidRunning | FeedbackField::MciError => (DataTypeId::Boolean, Variant::Boolean(false)),
+		_ => (DataTypeId::Double, Variant::Double(0.0)),
+	}
+}
+
+impl FieldMapping<NodeId> {
+	/// Add a read-only variable for each mapped field to the address space of an OPC UA server, organized by the `parent` node.
+	///
+	/// The browse name of each variable is the name of the field, such as `Joint0`.
+	/// The namespace of the nodes must already be registered in the address space.
+	///
+	/// Returns false if any of the nodes already existed.
+	pub fn add_variables(&self, address_space: &mut AddressSpace, parent: &NodeId) -> bool {
+		let mut added = true;
+		for (field, node) in self.fields() {
+			let name = field.to_string();
+			let (data_type, value) = data_type(*field);
+			added &= VariableBuilder::new(node, name.as_str(), name.as_str())
+				.data_type(data_type)
+				.value(value)
+				.organized_by(parent.clone())
+				.insert(address_space);
+		}
+		added
+	}
+
+	/// Write the mapped fields present in a message to the variables of an OPC UA server, and notify subscribed clients of the changes.
+	///
+	/// Fields that are absent from the message are skipped, so their variables keep the last known value.
+	pub fn write<T: InMemoryNodeManagerImpl>(
+		&self,
+		state: &EgmRobot,
+		node_manager: &InMemoryNodeManager<T>,
+		subscriptions: &SubscriptionCache,
+	) -> Result<(), StatusCode> {
+		let values = self.values(state);
+		node_manager.set_values(
+			subscriptions,
+			values.into_iter().map(|(node, value)| (node, None, DataValue::new_now(Variant::from(value)))),
+		)
+	}
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_opcua_write() {
+	use crate::msg;
+	use assert2::{assert, let_assert};
+	use opcua::server::address_space::NodeType;
+	use opcua::server::diagnostics::NamespaceMetadata;
+	use opcua::server::node_manager::memory::{simple_node_manager, SimpleNodeManager};
+	use opcua::server::ServerBuilder;
+	use opcua::types::{DataEncoding, NumericRange, ObjectId, TimestampsToReturn};
+
+	let_assert!(Ok((_server, handle)) = ServerBuilder::new_anonymous("abbegm")
+		.with_node_manager(simple_node_manager(
+			NamespaceMetadata {
+				namespace_uri: "urn:abbegm".into(),
+				..Default::default()
+			},
+			"abbegm",
+		))
+		.build());
+	let_assert!(Some(node_manager) = handle.node_managers().get_of_type::<SimpleNodeManager>());
+	let_assert!(Some(ns) = handle.get_namespace_index("urn:abbegm"));
+
+	let mapping = FieldMapping::new()
+		.with(FeedbackField::SequenceNumber, NodeId::new(ns, "Robot.Seqno"))
+		.with(FeedbackField::Joint(1), NodeId::new(ns, "Robot.J2"))
+		.with(FeedbackField::MotorsEnabled, NodeId::new(ns, "Robot.MotorsOn"));
+	assert!(mapping.add_variables(&mut node_manager.address_space().write(), &ObjectId::ObjectsFolder.into()));
+	assert!(!mapping.add_variables(&mut node_manager.address_space().write(), &ObjectId::ObjectsFolder.into()));
+
+	let state = EgmRobot {
+		header: Some(msg::EgmHeader::data(12, 0)),
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(vec![1.0, 2.0])),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	assert!(let Ok(()) = mapping.write(&state, &node_manager, handle.subscriptions()));
+
+	let address_space = node_manager.address_space().read();
+	let value = |name: &str| {
+		let_assert!(Some(NodeType::Variable(variable)) = address_space.find(&NodeId::new(ns, name)));
+		variable.value(TimestampsToReturn::Neither, &NumericRange::None, &DataEncoding::Binary, 0.0).value
+	};
+	assert!(value("Robot.Seqno") == Some(Variant::UInt32(12)));
+	assert!(value("Robot.J2") == Some(Variant::Double(2.0)));
+	assert!(value("Robot.MotorsOn") == Some(Variant::Boolean(false)));
+}
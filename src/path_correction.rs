@@ -0,0 +1,204 @@
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::msg::{EgmCartesian, EgmSensorPathCorr};
+
+/// Options for a [`PathCorrectionSession`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathCorrectionOptions {
+	/// The sample time of the path correction, as configured with `EGMActMove`.
+	///
+	/// The robot controller only applies corrections on this grid,
+	/// so the session sends at most one correction per period.
+	pub period: Duration,
+
+	/// The maximum length of a correction in millimeters.
+	///
+	/// Longer corrections are scaled down to this length.
+	pub max_correction: f64,
+
+	/// The maximum age of a measurement.
+	///
+	/// Older measurements are dropped instead of sent.
+	pub max_age: Duration,
+}
+
+impl Default for PathCorrectionOptions {
+	fn default() -> Self {
+		Self {
+			period: Duration::from_millis(48),
+			max_correction: 10.0,
+			max_age: Duration::from_millis(500),
+		}
+	}
+}
+
+/// Session for sending path corrections to a robot executing `EGMMoveL` or `EGMMoveC`.
+///
+/// The session takes care of the path correction protocol details:
+/// it numbers the messages, computes the age of measurements from their capture time,
+/// sends at most one correction per sample period and limits the length of corrections.
+///
+/// Submit measurements with [`PathCorrectionSession::submit_measurement`] as they become available,
+/// and call [`PathCorrectionSession::poll`] periodically to send measurements that arrived between sample periods.
+/// Send the returned messages with [`crate::sync_peer::ConnectedEgmPeer::send_path_correction`] or the asynchronous equivalent.
+///
+/// The session uses the system clock by default.
+/// Use [`PathCorrectionSession::with_clock`] with a [`MockClock`](crate::clock::MockClock) to test timing logic without sleeping.
+#[derive(Clone, Debug)]
+pub struct PathCorrectionSession<C = SystemClock> {
+	options: PathCorrectionOptions,
+	clock: C,
+	start: Instant,
+	sequence_number: u32,
+	last_sent: Option<Instant>,
+	pending: Option<(EgmCartesian, Instant)>,
+}
+
+impl PathCorrectionSession {
+	/// Create a new session.
+	pub fn new(options: PathCorrectionOptions) -> Self {
+		Self::with_clock(options, SystemClock)
+	}
+}
+
+impl<C: Clock> PathCorrectionSession<C> {
+	/// Create a new session with a custom clock.
+	pub fn with_clock(options: PathCorrectionOptions, clock: C) -> Self {
+		Self {
+			options,
+			start: clock.now(),
+			clock,
+			sequence_number: 0,
+			last_sent: None,
+			pending: None,
+		}
+	}
+
+	/// Get the options of the session.
+	pub fn options(&self) -> &PathCorrectionOptions {
+		&self.options
+	}
+
+	/// Get the number of corrections sent so far.
+	pub fn sent(&self) -> u32 {
+		self.sequence_number
+	}
+
+	/// Submit a measured correction in the sensor tool frame, in millimeters.
+	///
+	/// `captured_at` is the time at which the sensor captured the measurement.
+	///
+	/// Returns a message to send if a correction is due now.
+	/// Otherwise, the measurement replaces any pending measurement and is sent by a later call to [`Self::poll`].
+	pub fn submit_measurement(&mut self, correction: impl Into<EgmCartesian>, captured_at: Instant) -> Option<EgmSensorPathCorr> {
+		let now = self.clock.now();
+		self.submit_measurement_at(correction, captured_at, now)
+	}
+
+	/// Submit a measured correction, using `now` as the current time.
+	///
+	/// See [`Self::submit_measurement`].
+	pub fn submit_measurement_at(&mut self, correction: impl Into<EgmCartesian>, captured_at: Instant, now: Instant) -> Option<EgmSensorPathCorr> {
+		self.pending = Some((correction.into(), captured_at));
+		self.poll_at(now)
+	}
+
+	/// Get the pending measurement as message if a correction is due now.
+	pub fn poll(&mut self) -> Option<EgmSensorPathCorr> {
+		let now = self.clock.now();
+		self.poll_at(now)
+	}
+
+	/// Get the pending measurement as message if a correction is due, using `now` as the current time.
+	pub fn poll_at(&mut self, now: Instant) -> Option<EgmSensorPathCorr> {
+		if let Some(last_sent) = self.last_sent {
+			if now < last_sent + self.options.period {
+				return None;
+			}
+		}
+
+		let (correction, captured_at) = self.pending.take()?;
+		if now.saturating_duration_since(captured_at) > self.options.max_age {
+			return None;
+		}
+
+		self.last_sent = Some(now);
+		self.sequence_number = self.sequence_number.wrapping_add(1);
+		let correction = clamp_length(correction, self.options.max_correction);
+		Some(EgmSensorPathCorr::from_instants(self.sequence_number, correction, self.start, captured_at, now))
+	}
+}
+
+impl Default for PathCorrectionSession {
+	fn default() -> Self {
+		Self::new(PathCorrectionOptions::default())
+	}
+}
+
+/// Scale a vector down to a maximum length.
+fn clamp_length(vector: EgmCartesian, max_length: f64) -> EgmCartesian {
+	let [x, y, z] = vector.as_mm();
+	let length = (x * x + y * y + z * z).sqrt();
+	if length <= max_length {
+		return vector;
+	}
+	let scale = max_length / length;
+	EgmCartesian::from_mm(x * scale, y * scale, z * scale)
+}
+
+#[cfg(test)]
+#[test]
+fn test_path_correction_session() {
+	use assert2::{assert, let_assert};
+
+	let ms = Duration::from_millis;
+	let mut session = PathCorrectionSession::default();
+	let start = session.start;
+
+	let_assert!(Some(msg) = session.submit_measurement_at([1.0, 0.0, 0.0], start, start + ms(20)));
+	assert!(msg.header.as_ref().unwrap().seqno == Some(1));
+	assert!(msg.header.as_ref().unwrap().tm == Some(20));
+	assert!(msg.path_corr.unwrap().age == 20);
+
+	// Measurements within the sample period are held back, and only the latest is sent.
+	assert!(session.submit_measurement_at([2.0, 0.0, 0.0], start + ms(30), start + ms(40)).is_none());
+	assert!(session.submit_measurement_at([3.0, 0.0, 0.0], start + ms(50), start + ms(60)).is_none());
+	let_assert!(Some(msg) = session.poll_at(start + ms(68)));
+	assert!(msg.header.as_ref().unwrap().seqno == Some(2));
+	assert!(msg.path_corr.unwrap().pos.as_mm() == [3.0, 0.0, 0.0]);
+	assert!(msg.path_corr.unwrap().age == 18);
+	assert!(session.poll_at(start + ms(200)).is_none());
+
+	// Long corrections are clamped.
+	let_assert!(Some(msg) = session.submit_measurement_at([0.0, 30.0, 40.0], start + ms(200), start + ms(200)));
+	assert!(msg.path_corr.unwrap().pos.as_mm() == [0.0, 6.0, 8.0]);
+
+	// Old measurements are dropped.
+	assert!(session.submit_measurement_at([1.0, 0.0, 0.0], start, start + ms(1000)).is_none());
+	assert!(session.sent() == 3);
+}
+
+#[cfg(test)]
+#[test]
+fn test_path_correction_clock() {
+	use crate::clock::MockClock;
+	use assert2::{assert, let_assert};
+
+	let ms = Duration::from_millis;
+	let clock = MockClock::new();
+	let start = clock.now();
+	let mut session = PathCorrectionSession::with_clock(PathCorrectionOptions::default(), clock.clone());
+
+	clock.advance(ms(20));
+	let_assert!(Some(msg) = session.submit_measurement([1.0, 0.0, 0.0], start));
+	assert!(msg.header.as_ref().unwrap().tm == Some(20));
+
+	clock.advance(ms(20));
+	assert!(session.submit_measurement([2.0, 0.0, 0.0], start + ms(30)).is_none());
+	assert!(session.poll().is_none());
+	clock.advance(ms(28));
+	let_assert!(Some(msg) = session.poll());
+	assert!(msg.header.as_ref().unwrap().tm == Some(68));
+	assert!(msg.path_corr.unwrap().age == 38);
+}
@@ -0,0 +1,660 @@
+use std::convert::TryInto;
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::codec::DecodeError;
+use crate::msg::{EgmRobot, EgmSensor};
+use crate::recording::Direction;
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_OPTION_TSRESOL: u16 = 9;
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LOOP: u32 = 108;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+const IP_PROTOCOL_UDP: u8 = 17;
+
+/// The maximum size of a record or block, to avoid huge allocations for corrupt files.
+const MAX_BLOCK_LEN: usize = 16 << 20;
+
+/// A UDP datagram extracted from a packet capture.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UdpDatagram {
+	/// The capture time of the packet, relative to the UNIX epoch.
+	///
+	/// Packets from pcapng simple packet blocks have no timestamp, and get a time of zero.
+	pub time: Duration,
+
+	/// The address and port of the sender.
+	pub source: SocketAddr,
+
+	/// The address and port of the receiver.
+	pub destination: SocketAddr,
+
+	/// The payload of the datagram.
+	pub payload: Vec<u8>,
+}
+
+/// A decoded EGM message.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum EgmMessage {
+	/// A message sent by the robot controller.
+	Robot(EgmRobot),
+
+	/// A message sent to the robot controller.
+	Sensor(EgmSensor),
+}
+
+/// An EGM message extracted from a packet capture.
+#[derive(Debug)]
+pub struct CapturedMessage {
+	/// The datagram that contained the message.
+	pub datagram: UdpDatagram,
+
+	/// The direction of the message, determined from the EGM port.
+	pub direction: Direction,
+
+	/// The decoded message, or the error that occurred while decoding it.
+	pub message: Result<EgmMessage, DecodeError>,
+}
+
+/// Error that may occur when reading a packet capture.
+#[derive(Debug)]
+pub enum PcapError {
+	/// An I/O error occurred.
+	Io(std::io::Error),
+
+	/// The data does not start with the magic bytes of a pcap or pcapng file.
+	InvalidMagic,
+
+	/// The capture uses a link-layer type that is not supported.
+	UnsupportedLinkType(u32),
+
+	/// The capture is corrupt.
+	Corrupt(&'static str),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Endian {
+	Little,
+	Big,
+}
+
+impl Endian {
+	fn u16(self, data: &[u8]) -> u16 {
+		let bytes = [data[0], data[1]];
+		match self {
+			Self::Little => u16::from_le_bytes(bytes),
+			Self::Big => u16::from_be_bytes(bytes),
+		}
+	}
+
+	fn u32(self, data: &[u8]) -> u32 {
+		let bytes = [data[0], data[1], data[2], data[3]];
+		match self {
+			Self::Little => u32::from_le_bytes(bytes),
+			Self::Big => u32::from_be_bytes(bytes),
+		}
+	}
+}
+
+/// The resolution of the timestamps of a capture interface.
+#[derive(Copy, Clone, Debug)]
+enum Resolution {
+	/// Timestamps are in units of 10^-x seconds.
+	Decimal(u8),
+
+	/// Timestamps are in units of 2^-x seconds.
+	Binary(u8),
+}
+
+impl Resolution {
+	fn to_duration(self, units: u64) -> Duration {
+		let (per_second, nanos_per_unit) = match self {
+			Self::Decimal(exponent) => (10u128.pow(u32::from(exponent.min(38))), 1_000_000_000.0 / 10f64.powi(i32::from(exponent))),
+			Self::Binary(exponent) => (1u128 << exponent.min(127), 1_000_000_000.0 / 2f64.powi(i32::from(exponent))),
+		};
+		let units = u128::from(units);
+		let seconds = (units / per_second) as u64;
+		let nanos = ((units % per_second) as f64 * nanos_per_unit) as u32;
+		Duration::new(seconds, nanos.min(999_999_999))
+	}
+}
+
+#[derive(Clone, Debug)]
+struct Interface {
+	link_type: u32,
+	resolution: Resolution,
+}
+
+#[derive(Clone, Debug)]
+enum Format {
+	Pcap { endian: Endian, resolution: Resolution, link_type: u32 },
+	Pcapng { endian: Endian, interfaces: Vec<Interface> },
+}
+
+/// Reader for UDP datagrams in pcap and pcapng files, such as captures made with Wireshark or tcpdump.
+///
+/// The reader supports Ethernet (with VLAN tags), raw IP, loopback and Linux cooked captures, over IPv4 and IPv6.
+/// Packets that are not UDP, fragmented or truncated by the snapshot length are skipped.
+///
+/// The reader is an iterator over all UDP datagrams.
+/// Use [`PcapReader::egm_messages`] to get the decoded EGM messages on a port instead.
+pub struct PcapReader<R> {
+	reader: R,
+	format: Format,
+	failed: bool,
+}
+
+impl PcapReader<std::io::BufReader<std::fs::File>> {
+	/// Open a packet capture file.
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, PcapError> {
+		Self::new(std::io::BufReader::new(std::fs::File::open(path)?))
+	}
+}
+
+impl<R: Read> PcapReader<R> {
+	/// Create a reader for a pcap or pcapng stream.
+	///
+	/// The format is detected from the magic bytes at the start of the stream.
+	pub fn new(mut reader: R) -> Result<Self, PcapError> {
+		let mut magic = [0; 4];
+		reader.read_exact(&mut magic)?;
+		let format = if u32::from_le_bytes(magic) == PCAPNG_SECTION_HEADER {
+			let endian = read_section_header(&mut reader)?;
+			Format::Pcapng { endian, interfaces: Vec::new() }
+		} else {
+			let (endian, resolution) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+				(PCAP_MAGIC_MICROS, _) => (Endian::Little, Resolution::Decimal(6)),
+				(PCAP_MAGIC_NANOS, _) => (Endian::Little, Resolution::Decimal(9)),
+				(_, PCAP_MAGIC_MICROS) => (Endian::Big, Resolution::Decimal(6)),
+				(_, PCAP_MAGIC_NANOS) => (Endian::Big, Resolution::Decimal(9)),
+				_ => return Err(PcapError::InvalidMagic),
+			};
+			let mut header = [0; 20];
+			reader.read_exact(&mut header)?;
+			let link_type = endian.u32(&header[16..]) & 0x0fff_ffff;
+			check_link_type(link_type)?;
+			Format::Pcap { endian, resolution, link_type }
+		};
+		Ok(Self {
+			reader,
+			format,
+			failed: false,
+		})
+	}
+
+	/// Get an iterator over the EGM messages sent to or from a port.
+	///
+	/// The port is the UDP port of the external PC, as configured in the transmission protocol of the robot controller.
+	/// Datagrams sent to the port are decoded as [`EgmRobot`], datagrams sent from the port as [`EgmSensor`].
+	/// Other datagrams are skipped.
+	pub fn egm_messages(self, port: u16) -> EgmMessages<R> {
+		EgmMessages { reader: self, port }
+	}
+
+	/// Read the next UDP datagram, skipping packets that do not contain one.
+	///
+	/// Returns `Ok(None)` at the end of the capture.
+	pub fn read_datagram(&mut self) -> Result<Option<UdpDatagram>, PcapError> {
+		loop {
+			let packet = match &mut self.format {
+				Format::Pcap { endian, resolution, link_type } => read_pcap_record(&mut self.reader, *endian, *resolution, *link_type)?,
+				Format::Pcapng { endian, interfaces } => read_pcapng_block(&mut self.reader, endian, interfaces)?,
+			};
+			match packet {
+				Packet::End => return Ok(None),
+				Packet::Skipped => continue,
+				Packet::Data { time, link_type, data } => {
+					if let Some(datagram) = parse_link_layer(link_type, &data).and_then(|x| x.into_datagram(time)) {
+						return Ok(Some(datagram));
+					}
+				},
+			}
+		}
+	}
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+	type Item = Result<UdpDatagram, PcapError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.failed {
+			return None;
+		}
+		let result = self.read_datagram();
+		self.failed = result.is_err();
+		result.transpose()
+	}
+}
+
+/// Iterator over the EGM messages in a packet capture.
+///
+/// Created by [`PcapReader::egm_messages`].
+pub struct EgmMessages<R> {
+	reader: PcapReader<R>,
+	port: u16,
+}
+
+impl<R: Read> Iterator for EgmMessages<R> {
+	type Item = Result<CapturedMessage, PcapError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			let datagram = match self.reader.next()? {
+				Ok(x) => x,
+				Err(e) => return Some(Err(e)),
+			};
+			let (direction, message) = if datagram.destination.port() == self.port {
+				(Direction::FromRobot, crate::codec::decode_robot(&datagram.payload).map(EgmMessage::Robot))
+			} else if datagram.source.port() == self.port {
+				(Direction::ToRobot, crate::codec::decode_sensor(&datagram.payload).map(EgmMessage::Sensor))
+			} else {
+				continue;
+			};
+			return Some(Ok(CapturedMessage { datagram, direction, message }));
+		}
+	}
+}
+
+enum Packet {
+	End,
+	Skipped,
+	Data { time: Duration, link_type: u32, data: Vec<u8> },
+}
+
+struct UdpPacket<'a> {
+	source: IpAddr,
+	destination: IpAddr,
+	payload: &'a [u8],
+}
+
+impl UdpPacket<'_> {
+	fn into_datagram(self, time: Duration) -> Option<UdpDatagram> {
+		let payload = self.payload;
+		if payload.len() < 8 {
+			return None;
+		}
+		let source_port = u16::from_be_bytes([payload[0], payload[1]]);
+		let destination_port = u16::from_be_bytes([payload[2], payload[3]]);
+		let len = usize::from(u16::from_be_bytes([payload[4], payload[5]]));
+		// The length is zero for IPv6 jumbograms, so fall back to the captured length.
+		let len = if len == 0 { payload.len() } else { len };
+		Some(UdpDatagram {
+			time,
+			source: SocketAddr::new(self.source, source_port),
+			destination: SocketAddr::new(self.destination, destination_port),
+			payload: payload.get(8..len)?.to_vec(),
+		})
+	}
+}
+
+fn check_link_type(link_type: u32) -> Result<(), PcapError> {
+	match link_type {
+		LINKTYPE_NULL | LINKTYPE_ETHERNET | LINKTYPE_RAW | LINKTYPE_LOOP | LINKTYPE_LINUX_SLL | LINKTYPE_IPV4 | LINKTYPE_IPV6 | LINKTYPE_LINUX_SLL2 => Ok(()),
+		_ => Err(PcapError::UnsupportedLinkType(link_type)),
+	}
+}
+
+/// Read exactly enough bytes to fill the buffer, or nothing at all at the end of the stream.
+///
+/// Returns false if the stream ended before the first byte.
+fn read_or_end(reader: &mut impl Read, buffer: &mut [u8]) -> Result<bool, PcapError> {
+	let mut read = 0;
+	while read < buffer.len() {
+		match reader.read(&mut buffer[read..]) {
+			Ok(0) if read == 0 => return Ok(false),
+			Ok(0) => return Err(PcapError::Corrupt("truncated record")),
+			Ok(n) => read += n,
+			Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+			Err(e) => return Err(e.into()),
+		}
+	}
+	Ok(true)
+}
+
+fn read_vec(reader: &mut impl Read, len: usize) -> Result<Vec<u8>, PcapError> {
+	if len > MAX_BLOCK_LEN {
+		return Err(PcapError::Corrupt("record too large"));
+	}
+	let mut data = vec![0; len];
+	reader.read_exact(&mut data).map_err(|e| match e.kind() {
+		std::io::ErrorKind::UnexpectedEof => PcapError::Corrupt("truncated record"),
+		_ => e.into(),
+	})?;
+	Ok(data)
+}
+
+fn read_pcap_record(reader: &mut impl Read, endian: Endian, resolution: Resolution, link_type: u32) -> Result<Packet, PcapError> {
+	let mut header = [0; 16];
+	if !read_or_end(reader, &mut header)? {
+		return Ok(Packet::End);
+	}
+	let seconds = endian.u32(&header[0..]);
+	let fraction = endian.u32(&header[4..]);
+	let captured_len = endian.u32(&header[8..]) as usize;
+	let data = read_vec(reader, captured_len)?;
+	let time = Duration::from_secs(seconds.into()) + resolution.to_duration(fraction.into());
+	Ok(Packet::Data { time, link_type, data })
+}
+
+/// Read the rest of a pcapng section header block, after the block type.
+fn read_section_header(reader: &mut impl Read) -> Result<Endian, PcapError> {
+	let mut header = [0; 8];
+	reader.read_exact(&mut header)?;
+	let endian = match (u32::from_le_bytes([header[4], header[5], header[6], header[7]]), u32::from_be_bytes([header[4], header[5], header[6], header[7]])) {
+		(PCAPNG_BYTE_ORDER_MAGIC, _) => Endian::Little,
+		(_, PCAPNG_BYTE_ORDER_MAGIC) => Endian::Big,
+		_ => return Err(PcapError::InvalidMagic),
+	};
+	let len = endian.u32(&header) as usize;
+	if len < 12 + 16 {
+		return Err(PcapError::Corrupt("section header block too short"));
+	}
+	read_vec(reader, len - 12)?;
+	Ok(endian)
+}
+
+fn read_pcapng_block(reader: &mut impl Read, endian: &mut Endian, interfaces: &mut Vec<Interface>) -> Result<Packet, PcapError> {
+	let mut block_type = [0; 4];
+	if !read_or_end(reader, &mut block_type)? {
+		return Ok(Packet::End);
+	}
+	if u32::from_le_bytes(block_type) == PCAPNG_SECTION_HEADER {
+		*endian = read_section_header(reader)?;
+		interfaces.clear();
+		return Ok(Packet::Skipped);
+	}
+
+	let block_type = endian.u32(&block_type);
+	let mut len = [0; 4];
+	reader.read_exact(&mut len)?;
+	let len = endian.u32(&len) as usize;
+	if len < 12 || len & 3 != 0 {
+		return Err(PcapError::Corrupt("invalid block length"));
+	}
+	let block = read_vec(reader, len - 8)?;
+	let body = &block[..block.len() - 4];
+
+	match block_type {
+		PCAPNG_INTERFACE_DESCRIPTION => {
+			if body.len() < 8 {
+				return Err(PcapError::Corrupt("interface description block too short"));
+			}
+			let link_type = u32::from(endian.u16(body));
+			let resolution = parse_options(*endian, &body[8..], PCAPNG_OPTION_TSRESOL)
+				.and_then(|x| x.first().copied())
+				.map(|x| if x & 0x80 == 0 { Resolution::Decimal(x) } else { Resolution::Binary(x & 0x7f) })
+				.unwrap_or(Resolution::Decimal(6));
+			interfaces.push(Interface { link_type, resolution });
+			Ok(Packet::Skipped)
+		},
+		PCAPNG_ENHANCED_PACKET => {
+			if body.len() < 20 {
+				return Err(PcapError::Corrupt("enhanced packet block too short"));
+			}
+			let interface = interfaces
+				.get(endian.u32(body) as usize)
+				.ok_or(PcapError::Corrupt("packet references unknown interface"))?;
+			check_link_type(interface.link_type)?;
+			let timestamp = u64::from(endian.u32(&body[4..])) << 32 | u64::from(endian.u32(&body[8..]));
+			let captured_len = endian.u32(&body[12..]) as usize;
+			let data = body.get(20..20 + captured_len).ok_or(PcapError::Corrupt("enhanced packet block too short"))?;
+			Ok(Packet::Data {
+				time: interface.resolution.to_duration(timestamp),
+				link_type: interface.link_type,
+				data: data.to_vec(),
+			})
+		},
+		PCAPNG_SIMPLE_PACKET => {
+			let interface = interfaces.first().ok_or(PcapError::Corrupt("packet references unknown interface"))?;
+			check_link_type(interface.link_type)?;
+			if body.len() < 4 {
+				return Err(PcapError::Corrupt("simple packet block too short"));
+			}
+			let original_len = endian.u32(body) as usize;
+			let data = &body[4..];
+			Ok(Packet::Data {
+				time: Duration::ZERO,
+				link_type: interface.link_type,
+				data: data[..original_len.min(data.len())].to_vec(),
+			})
+		},
+		_ => Ok(Packet::Skipped),
+	}
+}
+
+/// Find the value of an option in the options of a pcapng block.
+fn parse_options(endian: Endian, mut options: &[u8], code: u16) -> Option<&[u8]> {
+	while options.len() >= 4 {
+		let option_code = endian.u16(options);
+		let len = usize::from(endian.u16(&options[2..]));
+		let value = options.get(4..4 + len)?;
+		if option_code == 0 {
+			return None;
+		} else if option_code == code {
+			return Some(value);
+		}
+		options = options.get(4 + ((len + 3) & !3)..)?;
+	}
+	None
+}
+
+fn parse_link_layer(link_type: u32, data: &[u8]) -> Option<UdpPacket<'_>> {
+	match link_type {
+		LINKTYPE_ETHERNET => {
+			let mut ethertype = u16::from_be_bytes([*data.get(12)?, *data.get(13)?]);
+			let mut offset = 14;
+			while ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+				ethertype = u16::from_be_bytes([*data.get(offset + 2)?, *data.get(offset + 3)?]);
+				offset += 4;
+			}
+			parse_ethertype(ethertype, data.get(offset..)?)
+		},
+		LINKTYPE_NULL | LINKTYPE_LOOP => {
+			// The address family is in the byte order of the capturing host for LINKTYPE_NULL.
+			let family = data.get(..4)?;
+			let family = u32::from_le_bytes([family[0], family[1], family[2], family[3]]).min(u32::from_be_bytes([family[0], family[1], family[2], family[3]]));
+			match family {
+				2 => parse_ipv4(data.get(4..)?),
+				24 | 28 | 30 => parse_ipv6(data.get(4..)?),
+				_ => None,
+			}
+		},
+		LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => match data.first()? >> 4 {
+			4 => parse_ipv4(data),
+			6 => parse_ipv6(data),
+			_ => None,
+		},
+		LINKTYPE_LINUX_SLL => parse_ethertype(u16::from_be_bytes([*data.get(14)?, *data.get(15)?]), data.get(16..)?),
+		LINKTYPE_LINUX_SLL2 => parse_ethertype(u16::from_be_bytes([*data.first()?, *data.get(1)?]), data.get(20..)?),
+		_ => None,
+	}
+}
+
+fn parse_ethertype(ethertype: u16, data: &[u8]) -> Option<UdpPacket<'_>> {
+	match ethertype {
+		ETHERTYPE_IPV4 => parse_ipv4(data),
+		ETHERTYPE_IPV6 => parse_ipv6(data),
+		_ => None,
+	}
+}
+
+fn parse_ipv4(data: &[u8]) -> Option<UdpPacket<'_>> {
+	let header = data.get(..20)?;
+	let header_len = usize::from(header[0] & 0x0f) * 4;
+	let total_len = usize::from(u16::from_be_bytes([header[2], header[3]]));
+	let fragment = u16::from_be_bytes([header[6], header[7]]);
+	if fragment & 0x3fff != 0 || header[9] != IP_PROTOCOL_UDP || header_len < 20 {
+		return None;
+	}
+	let source = Ipv4Addr::new(header[12], header[13], header[14], header[15]);
+	let destination = Ipv4Addr::new(header[16], header[17], header[18], header[19]);
+	Some(UdpPacket {
+		source: source.into(),
+		destination: destination.into(),
+		payload: data.get(header_len..total_len)?,
+	})
+}
+
+fn parse_ipv6(data: &[u8]) -> Option<UdpPacket<'_>> {
+	let payload_len = usize::from(u16::from_be_bytes([*data.get(4)?, *data.get(5)?]));
+	let mut next_header = *data.get(6)?;
+	let source: [u8; 16] = data.get(8..24)?.try_into().ok()?;
+	let destination: [u8; 16] = data.get(24..40)?.try_into().ok()?;
+	let mut payload = data.get(40..40 + payload_len)?;
+	// Skip hop-by-hop, routing and destination options headers.
+	while matches!(next_header, 0 | 43 | 60) {
+		let len = (usize::from(*payload.get(1)?) + 1) * 8;
+		next_header = payload[0];
+		payload = payload.get(len..)?;
+	}
+	if next_header != IP_PROTOCOL_UDP {
+		return None;
+	}
+	Some(UdpPacket {
+		source: Ipv6Addr::from(source).into(),
+		destination: Ipv6Addr::from(destination).into(),
+		payload,
+	})
+}
+
+impl From<std::io::Error> for PcapError {
+	fn from(other: std::io::Error) -> Self {
+		Self::Io(other)
+	}
+}
+
+impl std::fmt::Display for PcapError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "{}", e),
+			Self::InvalidMagic => write!(f, "not a pcap or pcapng file: invalid magic bytes"),
+			Self::UnsupportedLinkType(x) => write!(f, "unsupported link-layer type: {}", x),
+			Self::Corrupt(reason) => write!(f, "corrupt capture: {}", reason),
+		}
+	}
+}
+
+impl std::error::Error for PcapError {}
+
+#[cfg(test)]
+#[test]
+fn test_pcap() {
+	use crate::msg::EgmClock;
+	use assert2::{assert, let_assert};
+	use prost::Message;
+
+	fn ipv4_udp(source: [u8; 4], source_port: u16, destination: [u8; 4], destination_port: u16, payload: &[u8]) -> Vec<u8> {
+		let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, IP_PROTOCOL_UDP, 0, 0];
+		packet[2..4].copy_from_slice(&(28 + payload.len() as u16).to_be_bytes());
+		packet.extend_from_slice(&source);
+		packet.extend_from_slice(&destination);
+		packet.extend_from_slice(&source_port.to_be_bytes());
+		packet.extend_from_slice(&destination_port.to_be_bytes());
+		packet.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+		packet.extend_from_slice(&[0, 0]);
+		packet.extend_from_slice(payload);
+		packet
+	}
+
+	fn ethernet(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+		let mut frame = vec![0; 12];
+		frame.extend_from_slice(&ethertype.to_be_bytes());
+		frame.extend_from_slice(payload);
+		frame
+	}
+
+	let robot = EgmRobot {
+		header: Some(crate::msg::EgmHeader::data(7, 1000)),
+		..Default::default()
+	};
+	let sensor = EgmSensor::joint_target(7, vec![1.0; 6], EgmClock::new(1, 0));
+	let controller = [192, 168, 125, 1];
+	let pc = [192, 168, 125, 100];
+	let packets = [
+		ethernet(ETHERTYPE_IPV4, &ipv4_udp(controller, 50000, pc, 6510, &robot.encode_to_vec())),
+		ethernet(0x0806, &[0; 28]),
+		ethernet(ETHERTYPE_IPV4, &ipv4_udp(pc, 5353, [224, 0, 0, 251], 5353, b"mdns")),
+		ethernet(ETHERTYPE_IPV4, &ipv4_udp(pc, 6510, controller, 50000, &sensor.encode_to_vec())),
+	];
+
+	// A classic pcap file in big-endian byte order with nanosecond timestamps.
+	let mut pcap = Vec::new();
+	pcap.extend_from_slice(&PCAP_MAGIC_NANOS.to_be_bytes());
+	pcap.extend_from_slice(&[0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 0, 0, 0, 1]);
+	for (i, packet) in packets.iter().enumerate() {
+		pcap.extend_from_slice(&100u32.to_be_bytes());
+		pcap.extend_from_slice(&(i as u32 * 4_000_000).to_be_bytes());
+		pcap.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+		pcap.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+		pcap.extend_from_slice(packet);
+	}
+
+	let_assert!(Ok(reader) = PcapReader::new(&pcap[..]));
+	let_assert!(Ok(datagrams) = reader.collect::<Result<Vec<_>, _>>());
+	assert!(datagrams.len() == 3);
+	assert!(datagrams[1].payload == b"mdns");
+	assert!(datagrams[1].destination == "224.0.0.251:5353".parse().unwrap());
+
+	let messages: Vec<_> = PcapReader::new(&pcap[..]).unwrap().egm_messages(6510).collect();
+	assert!(messages.len() == 2);
+	let_assert!(Ok(first) = &messages[0]);
+	assert!(first.direction == Direction::FromRobot);
+	assert!(first.datagram.time == Duration::from_secs(100));
+	assert!(first.datagram.source == "192.168.125.1:50000".parse().unwrap());
+	let_assert!(Ok(EgmMessage::Robot(decoded)) = &first.message);
+	assert!(decoded == &robot);
+	let_assert!(Ok(second) = &messages[1]);
+	assert!(second.direction == Direction::ToRobot);
+	assert!(second.datagram.time == Duration::from_millis(100_012));
+	let_assert!(Ok(EgmMessage::Sensor(decoded)) = &second.message);
+	assert!(decoded == &sensor);
+
+	// A pcapng file in little-endian byte order with the default microsecond timestamps.
+	let block = |block_type: u32, body: &[u8]| {
+		let len = (12 + ((body.len() + 3) & !3)) as u32;
+		let mut block = block_type.to_le_bytes().to_vec();
+		block.extend_from_slice(&len.to_le_bytes());
+		block.extend_from_slice(body);
+		block.resize(len as usize - 4, 0);
+		block.extend_from_slice(&len.to_le_bytes());
+		block
+	};
+	let mut pcapng = block(PCAPNG_SECTION_HEADER, &[0x4d, 0x3c, 0x2b, 0x1a, 1, 0, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255]);
+	pcapng.extend(block(PCAPNG_INTERFACE_DESCRIPTION, &[1, 0, 0, 0, 0, 0, 0, 0]));
+	let mut body = vec![0; 8];
+	body.extend_from_slice(&2_500_000u32.to_le_bytes());
+	body.extend_from_slice(&(packets[0].len() as u32).to_le_bytes());
+	body.extend_from_slice(&(packets[0].len() as u32).to_le_bytes());
+	body.extend_from_slice(&packets[0]);
+	pcapng.extend(block(PCAPNG_ENHANCED_PACKET, &body));
+
+	let_assert!(Ok(reader) = PcapReader::new(&pcapng[..]));
+	let messages: Vec<_> = reader.egm_messages(6510).collect();
+	assert!(messages.len() == 1);
+	let_assert!(Ok(message) = &messages[0]);
+	assert!(message.datagram.time == Duration::from_millis(2500));
+	assert!(let Ok(EgmMessage::Robot(_)) = &message.message);
+
+	assert!(let Err(PcapError::InvalidMagic) = PcapReader::new(&b"not a capture"[..]));
+}
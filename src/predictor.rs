@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use crate::msg::{EgmPose, EgmRobot};
+use crate::session::{SessionEvent, SessionMonitor};
+
+/// A feedback sample with its controller timestamp.
+#[derive(Clone, Debug)]
+struct Sample {
+	time: Duration,
+	joints: Vec<f64>,
+	pose: Option<EgmPose>,
+}
+
+/// Predict where the robot is right now from delayed feedback.
+///
+/// Feedback from the robot controller is always somewhat old by the time it is processed.
+/// This predictor extrapolates the feedback forward in time to compensate for that latency.
+///
+/// EGM does not report velocities, so they are estimated from the last two feedback messages and their controller timestamps.
+/// Only the joint positions and the cartesian position are extrapolated, the orientation is taken from the latest feedback.
+///
+/// When a controller restart is detected, the predictor is reset automatically.
+#[derive(Clone, Debug, Default)]
+pub struct FeedbackPredictor {
+	previous: Option<Sample>,
+	latest: Option<Sample>,
+	session: SessionMonitor,
+}
+
+impl FeedbackPredictor {
+	/// Create a new predictor without any feedback.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Update the predictor with a new message from the robot.
+	///
+	/// Messages without a feedback time are ignored, as are messages that are not newer than the latest feedback.
+	/// Returns true if the message was used.
+	pub fn update(&mut self, state: &EgmRobot) -> bool {
+		let events = self.session.observe(state);
+		if events.iter().any(|event| matches!(event, SessionEvent::ControllerRestarted { .. })) {
+			self.previous = None;
+			self.latest = None;
+		}
+
+		let time = match state.feedback_time() {
+			Some(time) => time.elapsed_since_epoch(),
+			None => return false,
+		};
+		if let Some(latest) = &self.latest {
+			if time <= latest.time {
+				return false;
+			}
+		}
+		let sample = Sample {
+			time,
+			joints: state.feedback_joints().cloned().unwrap_or_default(),
+			pose: state.feedback_pose().cloned(),
+		};
+		self.previous = self.latest.replace(sample);
+		true
+	}
+
+	/// Forget all feedback, for example after a controller restart.
+	pub fn reset(&mut self) {
+		self.previous = None;
+		self.latest = None;
+		self.session.reset();
+	}
+
+	/// Get the time between the last two feedback samples.
+	fn time_step(&self) -> Option<(&Sample, &Sample, f64)> {
+		let previous = self.previous.as_ref()?;
+		let latest = self.latest.as_ref()?;
+		Some((previous, latest, (latest.time - previous.time).as_secs_f64()))
+	}
+
+	/// Get the estimated joint velocities in degrees/s.
+	pub fn joint_velocities(&self) -> Option<Vec<f64>> {
+		let (previous, latest, dt) = self.time_step()?;
+		if previous.joints.len() != latest.joints.len() || latest.joints.is_empty() {
+			return None;
+		}
+		Some(latest.joints.iter().zip(&previous.joints).map(|(a, b)| (a - b) / dt).collect())
+	}
+
+	/// Get the estimated linear velocity of the TCP in mm/s.
+	pub fn linear_velocity(&self) -> Option<[f64; 3]> {
+		let (previous, latest, dt) = self.time_step()?;
+		let a = latest.pose.as_ref()?.pos.as_ref()?;
+		let b = previous.pose.as_ref()?.pos.as_ref()?;
+		Some([(a.x - b.x) / dt, (a.y - b.y) / dt, (a.z - b.z) / dt])
+	}
+
+	/// Predict the joint positions in degrees, `ahead` of the latest feedback.
+	///
+	/// With only one feedback sample, the latest joint positions are returned without extrapolation.
+	pub fn predict_joints(&self, ahead: Duration) -> Option<Vec<f64>> {
+		let latest = self.latest.as_ref()?;
+		if latest.joints.is_empty() {
+			return None;
+		}
+		match self.joint_velocities() {
+			Some(velocities) => {
+				let ahead = ahead.as_secs_f64();
+				Some(latest.joints.iter().zip(velocities).map(|(x, v)| x + v * ahead).collect())
+			},
+			None => Some(latest.joints.clone()),
+		}
+	}
+
+	/// Predict the pose, `ahead` of the latest feedback.
+	///
+	/// Only the position is extrapolated.
+	/// With only one feedback sample, the latest pose is returned without extrapolation.
+	pub fn predict_pose(&self, ahead: Duration) -> Option<EgmPose> {
+		let mut pose = self.latest.as_ref()?.pose.clone()?;
+		if let (Some(pos), Some(velocity)) = (pose.pos.as_mut(), self.linear_velocity()) {
+			let ahead = ahead.as_secs_f64();
+			pos.x += velocity[0] * ahead;
+			pos.y += velocity[1] * ahead;
+			pos.z += velocity[2] * ahead;
+		}
+		Some(pose)
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_predict() {
+	use crate::msg;
+	use assert2::assert;
+
+	let state = |usec: u64, joint: f64, x: f64| EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(vec![joint, 0.0])),
+			cartesian: Some(EgmPose::new([x, 0.0, 0.0], msg::EgmQuaternion::IDENTITY)),
+			time: Some(msg::EgmClock::new(1, usec)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+
+	let mut predictor = FeedbackPredictor::new();
+	assert!(predictor.predict_joints(Duration::from_millis(4)).is_none());
+
+	assert!(predictor.update(&state(0, 10.0, 100.0)));
+	assert!(predictor.predict_joints(Duration::from_millis(4)) == Some(vec![10.0, 0.0]));
+
+	// Stale messages are ignored.
+	assert!(!predictor.update(&state(0, 11.0, 100.0)));
+
+	assert!(predictor.update(&state(4_000, 11.0, 104.0)));
+	assert!(predictor.joint_velocities() == Some(vec![250.0, 0.0]));
+	assert!(predictor.predict_joints(Duration::from_millis(8)) == Some(vec![13.0, 0.0]));
+	let pose = predictor.predict_pose(Duration::from_millis(8)).unwrap();
+	assert!(pose.pos.unwrap().as_mm() == [112.0, 0.0, 0.0]);
+	assert!(pose.orient == Some(msg::EgmQuaternion::IDENTITY));
+
+	// A large jump back in time is a controller restart, which resets the predictor.
+	let mut restarted = state(0, 20.0, 100.0);
+	restarted.feed_back.as_mut().unwrap().time = Some(msg::EgmClock::new(0, 0));
+	assert!(predictor.update(&restarted));
+	assert!(predictor.joint_velocities().is_none());
+	assert!(predictor.predict_joints(Duration::from_millis(8)) == Some(vec![20.0, 0.0]));
+}
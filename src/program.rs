@@ -0,0 +1,313 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::msg::{EgmEuler, EgmPose, EgmQuaternion, EgmRobot};
+use crate::streamer::MotionError;
+
+/// A simple motion program: a list of waypoints and dwell times.
+///
+/// Programs can be loaded from TOML or JSON, so simple motions can be run without recompiling.
+/// In TOML, a program looks like this:
+///
+/// ```toml
+/// [[steps]]
+/// type = "joint"
+/// joints = [0.0, 0.0, 0.0, 0.0, 30.0, 0.0]
+/// speed = 20.0
+///
+/// [[steps]]
+/// type = "dwell"
+/// time = 0.5
+///
+/// [[steps]]
+/// type = "linear"
+/// position = [600.0, 0.0, 800.0]
+/// euler = [0.0, 90.0, 0.0]
+/// speed = 100.0
+/// ```
+///
+/// Note that the robot controller only accepts joint targets or pose targets in one EGM session,
+/// depending on the RAPID instruction used to start it.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct MotionProgram {
+	/// The steps of the program, executed in order.
+	pub steps: Vec<Step>,
+}
+
+/// A step in a [`MotionProgram`].
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Step {
+	/// Move the joints to a target in degrees, with a maximum joint speed in degrees/s.
+	Joint {
+		/// The target joint positions in degrees.
+		joints: Vec<f64>,
+
+		/// The maximum joint speed in degrees/s.
+		speed: f64,
+	},
+
+	/// Move the tool in a straight line to a pose, with a maximum linear speed in mm/s.
+	///
+	/// The orientation is given either as unit quaternion or as Euler angles, but not both.
+	Linear {
+		/// The target position in millimeters.
+		position: [f64; 3],
+
+		/// The target orientation as unit quaternion in `[w, x, y, z]` order.
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		orientation: Option<[f64; 4]>,
+
+		/// The target orientation as XYZ Euler angles in degrees.
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		euler: Option<[f64; 3]>,
+
+		/// The maximum linear speed in mm/s.
+		speed: f64,
+	},
+
+	/// Hold the current position.
+	Dwell {
+		/// The time to hold the position in seconds.
+		time: f64,
+	},
+}
+
+/// Error that may occur while loading a motion program.
+#[derive(Debug)]
+pub enum ProgramError {
+	/// Reading the program file failed.
+	Io(std::io::Error),
+
+	/// The program is not valid TOML for a motion program.
+	Toml(toml::de::Error),
+
+	/// The program is not valid JSON for a motion program.
+	Json(serde_json::Error),
+
+	/// The file extension does not indicate a supported format.
+	UnknownFormat(PathBuf),
+
+	/// A step of the program is invalid.
+	InvalidStep {
+		/// The index of the step in the program.
+		index: usize,
+
+		/// The reason the step is invalid.
+		reason: &'static str,
+	},
+
+	/// Executing a step of the program failed.
+	Motion(MotionError),
+}
+
+impl Step {
+	/// Check that the step can be executed.
+	fn validate(&self) -> Result<(), &'static str> {
+		match self {
+			Self::Joint { speed, .. } | Self::Linear { speed, .. } if !(*speed > 0.0 && speed.is_finite()) => Err("speed must be a positive number"),
+			Self::Linear {
+				orientation: Some(_),
+				euler: Some(_),
+				..
+			} => Err("orientation and euler can not both be set"),
+			Self::Linear {
+				orientation: None,
+				euler: None,
+				..
+			} => Err("orientation or euler must be set"),
+			Self::Dwell { time } if !(*time >= 0.0 && time.is_finite()) => Err("dwell time must be a non-negative number"),
+			_ => Ok(()),
+		}
+	}
+
+	/// Get the target pose of a linear step.
+	fn pose(position: [f64; 3], orientation: Option<[f64; 4]>, euler: Option<[f64; 3]>) -> EgmPose {
+		match (orientation, euler) {
+			(Some([w, x, y, z]), _) => EgmPose::new(position, EgmQuaternion::from_wxyz(w, x, y, z)),
+			(None, Some([x, y, z])) => EgmPose::new(position, EgmEuler::from_xyz_degrees(x, y, z).to_quaternion()),
+			(None, None) => EgmPose::new(position, EgmQuaternion::IDENTITY),
+		}
+	}
+}
+
+impl MotionProgram {
+	/// Parse a program from TOML.
+	pub fn from_toml_str(data: &str) -> Result<Self, ProgramError> {
+		let program: Self = toml::from_str(data).map_err(ProgramError::Toml)?;
+		program.validate()?;
+		Ok(program)
+	}
+
+	/// Parse a program from JSON.
+	pub fn from_json_str(data: &str) -> Result<Self, ProgramError> {
+		let program: Self = serde_json::from_str(data).map_err(ProgramError::Json)?;
+		program.validate()?;
+		Ok(program)
+	}
+
+	/// Load a program from a file.
+	///
+	/// The format is determined by the file extension: `.toml` or `.json`.
+	pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ProgramError> {
+		let path = path.as_ref();
+		let parse = match path.extension().and_then(|x| x.to_str()) {
+			Some("toml") => Self::from_toml_str,
+			Some("json") => Self::from_json_str,
+			_ => return Err(ProgramError::UnknownFormat(path.to_owned())),
+		};
+		parse(&std::fs::read_to_string(path).map_err(ProgramError::Io)?)
+	}
+
+	/// Check that all steps of the program can be executed.
+	pub fn validate(&self) -> Result<(), ProgramError> {
+		for (index, step) in self.steps.iter().enumerate() {
+			step.validate().map_err(|reason| ProgramError::InvalidStep { index, reason })?;
+		}
+		Ok(())
+	}
+
+	/// Execute the program with a streamer.
+	///
+	/// The program is validated before the first step is executed.
+	/// Dwell steps stream the same kind of targets as the previous motion step, or joint targets if there is none.
+	/// Returns the last message received from the robot controller, or `None` if the program is empty.
+	pub fn run(&self, streamer: &mut crate::streamer::Streamer) -> Result<Option<EgmRobot>, ProgramError> {
+		self.validate()?;
+		let mut linear = false;
+		let mut last = None;
+		for step in &self.steps {
+			let state = match step {
+				Step::Joint { joints, speed } => {
+					linear = false;
+					streamer.move_joint(joints.clone(), *speed)?
+				},
+				Step::Linear {
+					position,
+					orientation,
+					euler,
+					speed,
+				} => {
+					linear = true;
+					streamer.move_linear(Step::pose(*position, *orientation, *euler), *speed)?
+				},
+				Step::Dwell { time } if linear => streamer.dwell_linear(Duration::from_secs_f64(*time))?,
+				Step::Dwell { time } => streamer.dwell_joint(Duration::from_secs_f64(*time))?,
+			};
+			last = Some(state);
+		}
+		Ok(last)
+	}
+
+	/// Execute the program with an asynchronous streamer.
+	///
+	/// See [`Self::run`].
+	#[cfg(feature = "tokio")]
+	pub async fn run_async(&self, streamer: &mut crate::streamer::AsyncStreamer) -> Result<Option<EgmRobot>, ProgramError> {
+		self.validate()?;
+		let mut linear = false;
+		let mut last = None;
+		for step in &self.steps {
+			let state = match step {
+				Step::Joint { joints, speed } => {
+					linear = false;
+					streamer.move_joint(joints.clone(), *speed).await?
+				},
+				Step::Linear {
+					position,
+					orientation,
+					euler,
+					speed,
+				} => {
+					linear = true;
+					streamer.move_linear(Step::pose(*position, *orientation, *euler), *speed).await?
+				},
+				Step::Dwell { time } if linear => streamer.dwell_linear(Duration::from_secs_f64(*time)).await?,
+				Step::Dwell { time } => streamer.dwell_joint(Duration::from_secs_f64(*time)).await?,
+			};
+			last = Some(state);
+		}
+		Ok(last)
+	}
+}
+
+impl std::fmt::Display for ProgramError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "failed to read motion program: {}", e),
+			Self::Toml(e) => write!(f, "failed to parse motion program: {}", e),
+			Self::Json(e) => write!(f, "failed to parse motion program: {}", e),
+			Self::UnknownFormat(path) => write!(f, "unknown motion program format, expected .toml or .json: {}", path.display()),
+			Self::InvalidStep { index, reason } => write!(f, "invalid step {} in motion program: {}", index, reason),
+			Self::Motion(e) => write!(f, "failed to execute motion program: {}", e),
+		}
+	}
+}
+
+impl std::error::Error for ProgramError {}
+
+impl From<MotionError> for ProgramError {
+	fn from(other: MotionError) -> Self {
+		Self::Motion(other)
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_motion_program() {
+	use assert2::{assert, let_assert};
+
+	let_assert!(
+		Ok(program) = MotionProgram::from_toml_str(
+			r#"
+			[[steps]]
+			type = "joint"
+			joints = [0.0, 0.0, 0.0, 0.0, 30.0, 0.0]
+			speed = 20.0
+
+			[[steps]]
+			type = "dwell"
+			time = 0.5
+
+			[[steps]]
+			type = "linear"
+			position = [600.0, 0.0, 800.0]
+			euler = [0.0, 90.0, 0.0]
+			speed = 100.0
+			"#
+		)
+	);
+	assert!(program.steps.len() == 3);
+	assert!(program.steps[1] == Step::Dwell { time: 0.5 });
+
+	let json = serde_json::to_string(&program).unwrap();
+	let_assert!(Ok(parsed) = MotionProgram::from_json_str(&json));
+	assert!(parsed == program);
+
+	let_assert!(
+		Err(ProgramError::InvalidStep { index: 1, .. }) = MotionProgram::from_json_str(
+			r#"{"steps": [{"type": "dwell", "time": 1.0}, {"type": "linear", "position": [0, 0, 0], "speed": 10}]}"#
+		)
+	);
+	let_assert!(Err(ProgramError::Json(_)) = MotionProgram::from_json_str(r#"{"steps": [{"type": "circle"}]}"#));
+	let_assert!(Err(ProgramError::UnknownFormat(_)) = MotionProgram::from_file("program.yaml"));
+}
+
+#[cfg(test)]
+#[test]
+fn test_run_validates() {
+	use assert2::let_assert;
+
+	// Programs built from the public fields are validated before anything is sent.
+	let program = MotionProgram {
+		steps: vec![Step::Dwell { time: -1.0 }],
+	};
+	let peer = crate::sync_peer::EgmPeer::bind("127.0.0.1:0").unwrap();
+	let mut streamer = crate::streamer::Streamer::new(peer, Default::default());
+	let_assert!(Err(ProgramError::InvalidStep { index: 0, .. }) = program.run(&mut streamer));
+
+	let program = MotionProgram {
+		steps: vec![Step::Dwell { time: f64::NAN }],
+	};
+	let_assert!(Err(ProgramError::InvalidStep { index: 0, .. }) = program.run(&mut streamer));
+}
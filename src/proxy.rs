@@ -0,0 +1,387 @@
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use prost::Message;
+
+use crate::msg::{EgmRobot, EgmSensor};
+use crate::recording::Direction;
+
+/// The longest time a relay thread blocks before checking if it should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// What the proxy should do with a message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+	/// Forward the message right away.
+	Forward,
+
+	/// Drop the message.
+	Drop,
+
+	/// Forward the message after a delay.
+	///
+	/// Messages with different delays may overtake each other.
+	Delay(Duration),
+
+	/// Forward the message twice, each copy after its own delay.
+	Duplicate(Duration, Duration),
+}
+
+/// Hooks to inspect, delay, drop or mutate the messages relayed by an [`EgmProxy`].
+///
+/// The default implementations forward all messages unchanged.
+/// If a hook modifies a message, the modified message is encoded and forwarded.
+/// Unmodified messages are forwarded byte for byte, including fields unknown to this crate.
+pub trait ProxyHook: Send + 'static {
+	/// Called for each message from the robot controller to the application.
+	fn on_robot_message(&mut self, msg: &mut EgmRobot) -> Verdict {
+		let _ = msg;
+		Verdict::Forward
+	}
+
+	/// Called for each message from the application to the robot controller.
+	fn on_sensor_message(&mut self, msg: &mut EgmSensor) -> Verdict {
+		let _ = msg;
+		Verdict::Forward
+	}
+}
+
+impl ProxyHook for () {}
+
+/// Counters of the messages relayed in one direction by an [`EgmProxy`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProxyStats {
+	/// The number of forwarded messages, including delayed messages that have been sent.
+	///
+	/// Messages that fail to send are logged, but still counted.
+	pub forwarded: u64,
+
+	/// The number of messages dropped by the hook.
+	pub dropped: u64,
+
+	/// The number of messages modified by the hook.
+	pub modified: u64,
+
+	/// The number of datagrams that could not be decoded, which are forwarded unchanged.
+	pub undecodable: u64,
+}
+
+/// Man-in-the-middle relay between a robot controller and an existing EGM application.
+///
+/// The robot controller is configured to send its messages to the proxy instead of the application.
+/// The proxy forwards them to the application, and forwards the replies of the application back to the controller.
+/// A [`ProxyHook`] can log, delay, drop or mutate the messages in both directions,
+/// which makes the proxy a tool to test the robustness of third-party EGM clients.
+///
+/// The proxy uses two sockets: one facing the robot controller and one facing the application,
+/// so the application sees a normal controller on a single address.
+pub struct EgmProxy<H> {
+	robot_socket: UdpSocket,
+	application_socket: UdpSocket,
+	application: SocketAddr,
+	hook: H,
+}
+
+impl<H: ProxyHook> EgmProxy<H> {
+	/// Create a proxy that receives messages from the robot controller on a local address and relays them to an application.
+	///
+	/// The socket facing the application is bound to an ephemeral port on all interfaces.
+	pub fn bind(robot_side: impl ToSocketAddrs, application: SocketAddr, hook: H) -> std::io::Result<Self> {
+		let robot_socket = UdpSocket::bind(robot_side)?;
+		let unspecified: SocketAddr = match application {
+			SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+			SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+		};
+		let application_socket = UdpSocket::bind(unspecified)?;
+		Ok(Self {
+			robot_socket,
+			application_socket,
+			application,
+			hook,
+		})
+	}
+
+	/// Get the local address that the robot controller must send its messages to.
+	pub fn robot_side_address(&self) -> std::io::Result<SocketAddr> {
+		self.robot_socket.local_addr()
+	}
+
+	/// Get the local address that the application receives messages from.
+	pub fn application_side_address(&self) -> std::io::Result<SocketAddr> {
+		self.application_socket.local_addr()
+	}
+
+	/// Start relaying messages on two background threads.
+	pub fn spawn(self) -> std::io::Result<ProxyHandle<H>> {
+		self.robot_socket.set_read_timeout(Some(POLL_INTERVAL))?;
+		self.application_socket.set_read_timeout(Some(POLL_INTERVAL))?;
+		let shared = Arc::new(Shared {
+			hook: Mutex::new(self.hook),
+			controller: Mutex::new(None),
+			stats: Default::default(),
+			stop: AtomicBool::new(false),
+		});
+
+		let robot_socket = Arc::new(self.robot_socket);
+		let application_socket = Arc::new(self.application_socket);
+		let application = self.application;
+
+		let from_robot = {
+			let shared = shared.clone();
+			let input = robot_socket.clone();
+			let output = application_socket.clone();
+			std::thread::Builder::new().name("egm-proxy-from-robot".into()).spawn(move || {
+				relay(&shared, Direction::FromRobot, &input, &output, |shared, from| {
+					*shared.controller.lock().unwrap() = Some(from);
+					Some(application)
+				})
+			})?
+		};
+		let to_robot = {
+			let shared = shared.clone();
+			std::thread::Builder::new().name("egm-proxy-to-robot".into()).spawn(move || {
+				relay(&shared, Direction::ToRobot, &application_socket, &robot_socket, |shared, _| {
+					*shared.controller.lock().unwrap()
+				})
+			})?
+		};
+
+		Ok(ProxyHandle {
+			shared,
+			threads: vec![from_robot, to_robot],
+		})
+	}
+}
+
+/// Handle to a running [`EgmProxy`].
+///
+/// Dropping the handle signals the relay threads to stop without waiting for them.
+pub struct ProxyHandle<H> {
+	shared: Arc<Shared<H>>,
+	threads: Vec<std::thread::JoinHandle<()>>,
+}
+
+struct Shared<H> {
+	hook: Mutex<H>,
+	controller: Mutex<Option<SocketAddr>>,
+	stats: [Counters; 2],
+	stop: AtomicBool,
+}
+
+#[derive(Default)]
+struct Counters {
+	forwarded: AtomicU64,
+	dropped: AtomicU64,
+	modified: AtomicU64,
+	undecodable: AtomicU64,
+}
+
+impl<H> ProxyHandle<H> {
+	/// Lock the hook, for example to change its behavior while the proxy is running.
+	///
+	/// Relaying blocks while the lock is held.
+	pub fn hook(&self) -> MutexGuard<'_, H> {
+		self.shared.hook.lock().unwrap()
+	}
+
+	/// Get the address of the robot controller, once it sent a message.
+	pub fn controller_address(&self) -> Option<SocketAddr> {
+		*self.shared.controller.lock().unwrap()
+	}
+
+	/// Get the counters of the messages relayed in one direction.
+	pub fn stats(&self, direction: Direction) -> ProxyStats {
+		let counters = &self.shared.stats[direction_index(direction)];
+		ProxyStats {
+			forwarded: counters.forwarded.load(Ordering::Relaxed),
+			dropped: counters.dropped.load(Ordering::Relaxed),
+			modified: counters.modified.load(Ordering::Relaxed),
+			undecodable: counters.undecodable.load(Ordering::Relaxed),
+		}
+	}
+
+	/// Stop the relay threads and get the hook back.
+	///
+	/// Delayed messages that have not been sent yet are discarded.
+	pub fn stop(mut self) -> H {
+		self.shared.stop.store(true, Ordering::Relaxed);
+		for thread in self.threads.drain(..) {
+			if let Err(e) = thread.join() {
+				std::panic::resume_unwind(e);
+			}
+		}
+		let shared = self.shared.clone();
+		drop(self);
+		match Arc::try_unwrap(shared) {
+			Ok(shared) => shared.hook.into_inner().unwrap(),
+			Err(_) => unreachable!("all relay threads have been joined"),
+		}
+	}
+}
+
+impl<H> Drop for ProxyHandle<H> {
+	fn drop(&mut self) {
+		self.shared.stop.store(true, Ordering::Relaxed);
+	}
+}
+
+fn direction_index(direction: Direction) -> usize {
+	match direction {
+		Direction::FromRobot => 0,
+		Direction::ToRobot => 1,
+	}
+}
+
+/// Relay datagrams from one socket to another until the proxy is stopped.
+///
+/// The destination function records the sender and returns where to send the datagram, if known yet.
+fn relay<H: ProxyHook>(
+	shared: &Shared<H>,
+	direction: Direction,
+	input: &UdpSocket,
+	output: &UdpSocket,
+	destination: impl Fn(&Shared<H>, SocketAddr) -> Option<SocketAddr>,
+) {
+	let counters = &shared.stats[direction_index(direction)];
+	let mut buffer = vec![0; 64 * 1024];
+	let mut delayed: Vec<(Instant, SocketAddr, Vec<u8>)> = Vec::new();
+
+	while !shared.stop.load(Ordering::Relaxed) {
+		let now = Instant::now();
+		let timeout = delayed.iter().map(|(due, _, _)| due.saturating_duration_since(now)).min().unwrap_or(POLL_INTERVAL);
+		let _ = input.set_read_timeout(Some(timeout.clamp(Duration::from_millis(1), POLL_INTERVAL)));
+
+		match input.recv_from(&mut buffer) {
+			Ok((len, from)) => {
+				if let Some(to) = destination(shared, from) {
+					let data = &buffer[..len];
+					let (verdict, data) = inspect(shared, direction, counters, data);
+					match verdict {
+						Verdict::Forward => send(output, counters, &data, to),
+						Verdict::Drop => {
+							counters.dropped.fetch_add(1, Ordering::Relaxed);
+						},
+						Verdict::Delay(delay) => delayed.push((Instant::now() + delay, to, data)),
+						Verdict::Duplicate(first, second) => {
+							let now = Instant::now();
+							delayed.push((now + first, to, data.clone()));
+							delayed.push((now + second, to, data));
+						},
+					}
+				}
+			},
+			Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => (),
+			Err(e) => log::warn!("EGM proxy failed to receive: {}", e),
+		}
+
+		let now = Instant::now();
+		delayed.sort_by_key(|(due, _, _)| *due);
+		let due = delayed.iter().take_while(|(due, _, _)| *due <= now).count();
+		for (_, to, data) in delayed.drain(..due) {
+			send(output, counters, &data, to);
+		}
+	}
+}
+
+/// Decode a datagram and pass it through the hook.
+///
+/// Returns the verdict and the datagram to forward.
+fn inspect<H: ProxyHook>(shared: &Shared<H>, direction: Direction, counters: &Counters, data: &[u8]) -> (Verdict, Vec<u8>) {
+	let mut hook = shared.hook.lock().unwrap();
+	let result = match direction {
+		Direction::FromRobot => crate::codec::decode_robot(data).map(|original| {
+			let mut msg = original.clone();
+			let verdict = hook.on_robot_message(&mut msg);
+			(verdict, (msg != original).then(|| msg.encode_to_vec()))
+		}),
+		Direction::ToRobot => crate::codec::decode_sensor(data).map(|original| {
+			let mut msg = original.clone();
+			let verdict = hook.on_sensor_message(&mut msg);
+			(verdict, (msg != original).then(|| msg.encode_to_vec()))
+		}),
+	};
+	match result {
+		Ok((verdict, Some(modified))) => {
+			counters.modified.fetch_add(1, Ordering::Relaxed);
+			(verdict, modified)
+		},
+		Ok((verdict, None)) => (verdict, data.to_vec()),
+		Err(e) => {
+			log::warn!("EGM proxy forwards undecodable datagram: {}", e);
+			counters.undecodable.fetch_add(1, Ordering::Relaxed);
+			(Verdict::Forward, data.to_vec())
+		},
+	}
+}
+
+fn send(socket: &UdpSocket, counters: &Counters, data: &[u8], to: SocketAddr) {
+	counters.forwarded.fetch_add(1, Ordering::Relaxed);
+	if let Err(e) = socket.send_to(data, to) {
+		log::warn!("EGM proxy failed to send to {}: {}", to, e);
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_proxy() {
+	use crate::msg::{EgmClock, EgmHeader};
+	use assert2::{assert, let_assert};
+
+	struct Hook {
+		seen: Vec<u32>,
+	}
+
+	impl ProxyHook for Hook {
+		fn on_robot_message(&mut self, msg: &mut EgmRobot) -> Verdict {
+			let seqno = msg.sequence_number().unwrap_or(0);
+			self.seen.push(seqno);
+			match seqno {
+				1 => Verdict::Drop,
+				2 => {
+					msg.header.as_mut().unwrap().tm = Some(999);
+					Verdict::Forward
+				},
+				_ => Verdict::Delay(Duration::from_millis(20)),
+			}
+		}
+	}
+
+	let controller = UdpSocket::bind("127.0.0.1:0").unwrap();
+	let application = UdpSocket::bind("127.0.0.1:0").unwrap();
+	controller.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+	application.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+	let_assert!(Ok(proxy) = EgmProxy::bind("127.0.0.1:0", application.local_addr().unwrap(), Hook { seen: Vec::new() }));
+	let proxy_address = proxy.robot_side_address().unwrap();
+	let_assert!(Ok(proxy) = proxy.spawn());
+
+	let mut buffer = [0; 1024];
+	for seqno in 1..=3 {
+		let msg = EgmRobot {
+			header: Some(EgmHeader::data(seqno, 4 * seqno)),
+			..Default::default()
+		};
+		controller.send_to(&msg.encode_to_vec(), proxy_address).unwrap();
+	}
+
+	let_assert!(Ok((len, proxy_application_side)) = application.recv_from(&mut buffer));
+	let_assert!(Ok(msg) = crate::codec::decode_robot(&buffer[..len]));
+	assert!(msg.sequence_number() == Some(2));
+	assert!(msg.timestamp_ms() == Some(999));
+	let_assert!(Ok((len, _)) = application.recv_from(&mut buffer));
+	assert!(crate::codec::decode_robot(&buffer[..len]).unwrap().sequence_number() == Some(3));
+
+	let reply = EgmSensor::joint_target(1, vec![0.0; 6], EgmClock::new(0, 0));
+	application.send_to(&reply.encode_to_vec(), proxy_application_side).unwrap();
+	let_assert!(Ok((len, from)) = controller.recv_from(&mut buffer));
+	assert!(from == proxy_address);
+	assert!(crate::codec::decode_sensor(&buffer[..len]).unwrap() == reply);
+
+	assert!(proxy.controller_address() == Some(controller.local_addr().unwrap()));
+	let stats = proxy.stats(Direction::FromRobot);
+	assert!(stats == ProxyStats { forwarded: 2, dropped: 1, modified: 1, undecodable: 0 });
+	assert!(proxy.stats(Direction::ToRobot).forwarded == 1);
+	assert!(proxy.stop().seen == [1, 2, 3]);
+}
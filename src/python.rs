@@ -0,0 +1,379 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::msg;
+
+pyo3::create_exception!(abbegm, EgmError, PyException, "Error raised when a message can not be decoded, validated, sent or recorded.");
+
+fn egm_error(error: impl std::fmt::Display) -> PyErr {
+	EgmError::new_err(error.to_string())
+}
+
+fn receive_error(error: crate::ReceiveError) -> PyErr {
+	match error {
+		crate::ReceiveError::Io(e) => e.into(),
+		e => egm_error(e),
+	}
+}
+
+fn send_error(error: crate::SendError) -> PyErr {
+	match error {
+		crate::SendError::Io(e) => e.into(),
+		e => egm_error(e),
+	}
+}
+
+fn recording_error(error: crate::recording::RecordingError) -> PyErr {
+	match error {
+		crate::recording::RecordingError::Io(e) => e.into(),
+		e => egm_error(e),
+	}
+}
+
+fn duration(seconds: f64) -> PyResult<Duration> {
+	Duration::try_from_secs_f64(seconds).map_err(|_| PyValueError::new_err(format!("invalid time: {}, expected a non-negative number of seconds", seconds)))
+}
+
+fn clock(seconds: f64) -> PyResult<msg::EgmClock> {
+	let time = duration(seconds)?;
+	Ok(msg::EgmClock::new(time.as_secs(), u64::from(time.subsec_micros())))
+}
+
+fn pose_tuple(pose: &msg::EgmPose) -> Option<([f64; 3], [f64; 4])> {
+	Some((pose.pos?.as_mm(), pose.orient?.as_wxyz()))
+}
+
+/// A message from the robot controller.
+#[pyclass(name = "EgmRobot", module = "abbegm", frozen)]
+struct PyEgmRobot {
+	inner: msg::EgmRobot,
+}
+
+#[pymethods]
+impl PyEgmRobot {
+	/// Decode a message from the bytes of a datagram.
+	#[staticmethod]
+	fn decode(data: &[u8]) -> PyResult<Self> {
+		let inner = crate::codec::decode_robot(data).map_err(egm_error)?;
+		Ok(Self { inner })
+	}
+
+	/// Encode the message into bytes.
+	fn encode<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+		use prost::Message;
+		PyBytes::new(py, &self.inner.encode_to_vec())
+	}
+
+	/// The sequence number of the message, if any.
+	#[getter]
+	fn sequence_number(&self) -> Option<u32> {
+		self.inner.sequence_number()
+	}
+
+	/// The feedback time in seconds according to the clock of the robot controller, if any.
+	#[getter]
+	fn feedback_time(&self) -> Option<f64> {
+		Some(self.inner.feedback_time()?.elapsed_since_epoch().as_secs_f64())
+	}
+
+	/// The joint positions in degrees, if any.
+	#[getter]
+	fn feedback_joints(&self) -> Option<Vec<f64>> {
+		self.inner.feedback_joints().cloned()
+	}
+
+	/// The pose of the tool as position in mm and orientation as (w, x, y, z) quaternion, if any.
+	#[getter]
+	fn feedback_pose(&self) -> Option<([f64; 3], [f64; 4])> {
+		pose_tuple(self.inner.feedback_pose()?)
+	}
+
+	/// The planned joint positions in degrees, if any.
+	#[getter]
+	fn planned_joints(&self) -> Option<Vec<f64>> {
+		self.inner.planned_joints().cloned()
+	}
+
+	/// The planned pose of the tool as position in mm and orientation as (w, x, y, z) quaternion, if any.
+	#[getter]
+	fn planned_pose(&self) -> Option<([f64; 3], [f64; 4])> {
+		pose_tuple(self.inner.planned_pose()?)
+	}
+
+	fn __repr__(&self) -> String {
+		format!("{:?}", self.inner)
+	}
+}
+
+/// A message to the robot controller.
+#[pyclass(name = "EgmSensor", module = "abbegm", frozen)]
+struct PyEgmSensor {
+	inner: msg::EgmSensor,
+}
+
+#[pymethods]
+impl PyEgmSensor {
+	/// Create a joint target with joint positions in degrees and a time in seconds.
+	#[staticmethod]
+	fn joint_target(sequence_number: u32, joints: Vec<f64>, time: f64) -> PyResult<Self> {
+		let inner = msg::EgmSensor::joint_target(sequence_number, joints, clock(time)?);
+		Ok(Self { inner })
+	}
+
+	/// Create a pose target with a position in mm, a (w, x, y, z) quaternion orientation and a time in seconds.
+	#[staticmethod]
+	fn pose_target(sequence_number: u32, position: [f64; 3], orientation: [f64; 4], time: f64) -> PyResult<Self> {
+		let [w, x, y, z] = orientation;
+		let pose = msg::EgmPose::new(position, msg::EgmQuaternion::from_wxyz(w, x, y, z));
+		let inner = msg::EgmSensor::pose_target(sequence_number, pose, clock(time)?);
+		Ok(Self { inner })
+	}
+
+	/// Decode a message from the bytes of a datagram.
+	#[staticmethod]
+	fn decode(data: &[u8]) -> PyResult<Self> {
+		let inner = crate::codec::decode_sensor(data).map_err(egm_error)?;
+		Ok(Self { inner })
+	}
+
+	/// Validate the message and encode it into bytes.
+	fn encode<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+		let mut buffer = Vec::new();
+		crate::codec::encode_sensor(&self.inner, &mut buffer).map_err(egm_error)?;
+		Ok(PyBytes::new(py, &buffer))
+	}
+
+	/// The sequence number of the message, if any.
+	#[getter]
+	fn sequence_number(&self) -> Option<u32> {
+		self.inner.header.as_ref()?.seqno
+	}
+
+	/// The target joint positions in degrees, if any.
+	#[getter]
+	fn joints(&self) -> Option<Vec<f64>> {
+		Some(self.inner.planned.as_ref()?.joints.as_ref()?.joints.clone())
+	}
+
+	/// The target pose as position in mm and orientation as (w, x, y, z) quaternion, if any.
+	#[getter]
+	fn pose(&self) -> Option<([f64; 3], [f64; 4])> {
+		pose_tuple(self.inner.planned.as_ref()?.cartesian.as_ref()?)
+	}
+
+	fn __repr__(&self) -> String {
+		format!("{:?}", self.inner)
+	}
+}
+
+/// Blocking EGM peer on a UDP socket.
+///
+/// Received messages are decoded and checked against the decode limits, and targets are validated before they are sent,
+/// exactly like with the Rust peer.
+/// The GIL is released while waiting for a message.
+#[pyclass(name = "EgmPeer", module = "abbegm")]
+struct PyEgmPeer {
+	inner: Mutex<crate::sync_peer::EgmPeer>,
+}
+
+impl PyEgmPeer {
+	fn lock(&self) -> std::sync::MutexGuard<'_, crate::sync_peer::EgmPeer> {
+		self.inner.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+#[pymethods]
+impl PyEgmPeer {
+	/// Create a peer on a newly bound UDP socket, for example on "0.0.0.0:6510".
+	#[new]
+	fn new(address: &str) -> PyResult<Self> {
+		let peer = crate::sync_peer::EgmPeer::bind(address)?;
+		Ok(Self { inner: Mutex::new(peer) })
+	}
+
+	/// The local address of the socket.
+	#[getter]
+	fn local_address(&self) -> PyResult<String> {
+		Ok(self.lock().socket().local_addr()?.to_string())
+	}
+
+	/// Set the time in seconds after which receiving fails with `TimeoutError`, or `None` to wait forever.
+	fn set_read_timeout(&self, timeout: Option<f64>) -> PyResult<()> {
+		let timeout = timeout.map(duration).transpose()?;
+		self.lock().socket().set_read_timeout(timeout)?;
+		Ok(())
+	}
+
+	/// Receive a message, and return it with the address of the sender.
+	fn recv_from(&self, py: Python<'_>) -> PyResult<(PyEgmRobot, String)> {
+		let (inner, address) = py.detach(|| self.lock().recv_from()).map_err(receive_error)?;
+		Ok((PyEgmRobot { inner }, address.to_string()))
+	}
+
+	/// Validate a message and send it to an address.
+	fn send_to(&self, msg: &PyEgmSensor, address: &str) -> PyResult<()> {
+		let address: SocketAddr = address.parse().map_err(|e| PyValueError::new_err(format!("invalid address: {}: {}", address, e)))?;
+		self.lock().send_to(&msg.inner, &address).map_err(send_error)
+	}
+
+	/// Drop all messages that are waiting in the receive queue of the socket.
+	fn purge_recv_queue(&self) -> PyResult<()> {
+		Ok(self.lock().purge_recv_queue()?)
+	}
+
+	/// The sample time of the controller in seconds, measured from the arrival times of received messages, if known.
+	#[getter]
+	fn measured_sample_time(&self) -> Option<f64> {
+		self.lock().measured_sample_time().map(|x| x.as_secs_f64())
+	}
+}
+
+/// Writer for recordings of EGM messages, in the format of the `recording` module.
+///
+/// Call `finish()` to write the index of the recording.
+#[pyclass(name = "Recorder", module = "abbegm")]
+struct PyRecorder {
+	inner: Mutex<Option<crate::recording::Recorder<BufWriter<File>>>>,
+}
+
+impl PyRecorder {
+	fn with_recorder<R>(&self, f: impl FnOnce(&mut crate::recording::Recorder<BufWriter<File>>) -> Result<R, crate::recording::RecordingError>) -> PyResult<R> {
+		let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+		let recorder = inner.as_mut().ok_or_else(|| egm_error("the recording is already finished"))?;
+		f(recorder).map_err(recording_error)
+	}
+}
+
+#[pymethods]
+impl PyRecorder {
+	/// Create a new recording file.
+	#[new]
+	fn new(path: std::path::PathBuf) -> PyResult<Self> {
+		let recorder = crate::recording::Recorder::new(BufWriter::new(File::create(path)?)).map_err(recording_error)?;
+		Ok(Self {
+			inner: Mutex::new(Some(recorder)),
+		})
+	}
+
+	/// Record a message from the robot controller at a time in seconds.
+	fn record_robot(&self, time: f64, msg: &PyEgmRobot) -> PyResult<()> {
+		let time = duration(time)?;
+		self.with_recorder(|recorder| recorder.record_robot(time, &msg.inner))
+	}
+
+	/// Record a message to the robot controller at a time in seconds.
+	fn record_sensor(&self, time: f64, msg: &PyEgmSensor) -> PyResult<()> {
+		let time = duration(time)?;
+		self.with_recorder(|recorder| recorder.record_sensor(time, &msg.inner))
+	}
+
+	/// Write the index and close the recording.
+	fn finish(&self) -> PyResult<()> {
+		let recorder = self.inner.lock().unwrap_or_else(|e| e.into_inner()).take();
+		let recorder = recorder.ok_or_else(|| egm_error("the recording is already finished"))?;
+		let mut writer = recorder.finish().map_err(recording_error)?;
+		std::io::Write::flush(&mut writer)?;
+		Ok(())
+	}
+
+	fn __len__(&self) -> PyResult<usize> {
+		self.with_recorder(|recorder| Ok(recorder.len()))
+	}
+}
+
+/// Python bindings of the message and peer layer of `abbegm`.
+#[pymodule]
+fn abbegm(module: &Bound<'_, PyModule>) -> PyResult<()> {
+	module.add_class::<PyEgmRobot>()?;
+	module.add_class::<PyEgmSensor>()?;
+	module.add_class::<PyEgmPeer>()?;
+	module.add_class::<PyRecorder>()?;
+	module.add("EgmError", module.py().get_type::<EgmError>())?;
+	Ok(())
+}
+
+#[cfg(test)]
+#[test]
+fn test_python_bindings() {
+	use prost::Message;
+	use pyo3::types::{PyDict, PyModule};
+
+	let robot = msg::EgmRobot {
+		header: Some(msg::EgmHeader::data(3, 12)),
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0])),
+			time: Some(msg::EgmClock::new(1, 500_000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let path = std::env::temp_dir().join(format!("abbegm-python-{}.egmrec", std::process::id()));
+
+	Python::initialize();
+	Python::attach(|py| -> PyResult<()> {
+		let module = PyModule::new(py, "abbegm")?;
+		abbegm(&module)?;
+		let locals = PyDict::new(py);
+		locals.set_item("abbegm", module)?;
+		locals.set_item("robot_bytes", PyBytes::new(py, &robot.encode_to_vec()))?;
+		locals.set_item("path", &path)?;
+		py.run(
+			pyo3::ffi::c_str!(
+				r#"
+import socket
+
+# Messages round-trip through their encoding, in the same component order.
+target = abbegm.EgmSensor.pose_target(7, [1.0, 2.0, 3.0], [0.1, 0.2, 0.3, 0.4], 1.5)
+decoded = abbegm.EgmSensor.decode(target.encode())
+assert decoded.sequence_number == 7
+assert decoded.pose == ([1.0, 2.0, 3.0], [0.1, 0.2, 0.3, 0.4])
+
+# Invalid targets are rejected by the validation of the crate.
+try:
+	abbegm.EgmSensor.joint_target(1, [float("nan")] * 6, 0.0).encode()
+	assert False
+except abbegm.EgmError:
+	pass
+
+# Exchange messages with a simulated robot.
+peer = abbegm.EgmPeer("127.0.0.1:0")
+peer.set_read_timeout(1.0)
+robot = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+robot.bind(("127.0.0.1", 0))
+robot.sendto(robot_bytes, ("127.0.0.1", int(peer.local_address.split(":")[1])))
+state, address = peer.recv_from()
+assert address == "127.0.0.1:%d" % robot.getsockname()[1]
+assert state.sequence_number == 3
+assert state.feedback_joints == [1.0, 2.0, 3.0, 4.0, 5.0, 6.0]
+assert state.feedback_time == 1.5
+assert state.feedback_pose is None
+
+target = abbegm.EgmSensor.joint_target(1, state.feedback_joints, state.feedback_time)
+peer.send_to(target, address)
+assert abbegm.EgmSensor.decode(robot.recv(1024)).joints == state.feedback_joints
+
+recorder = abbegm.Recorder(path)
+recorder.record_robot(0.0, state)
+recorder.record_sensor(0.004, target)
+assert len(recorder) == 2
+recorder.finish()
+"#
+			),
+			None,
+			Some(&locals),
+		)
+	})
+	.unwrap();
+
+	let mut reader = crate::recording::RecordingReader::new(File::open(&path).unwrap()).unwrap();
+	std::fs::remove_file(&path).unwrap();
+	assert2::assert!(reader.len() == 2);
+	assert2::assert!(reader.read(0).unwrap().unwrap().decode_robot().unwrap() == robot);
+}
@@ -0,0 +1,338 @@
+//! Generation of RAPID modules for the robot controller.
+//!
+//! The generated modules follow the examples in the ABB application manual for Externally Guided Motion.
+//! They are meant as a starting point: review the generated code and adjust it to your cell before running it.
+
+use std::fmt::Write;
+
+use crate::rate::EgmRate;
+
+/// A configuration type that can be turned into a RAPID module.
+pub trait RapidTemplate {
+	/// Generate the source code of the RAPID module.
+	fn to_rapid(&self) -> String;
+
+	/// Write the RAPID module to a file, usually with the `.mod` extension.
+	fn write_module(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+		std::fs::write(path, self.to_rapid())
+	}
+}
+
+/// Settings shared by the RAPID templates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RapidSetup {
+	/// The name of the generated RAPID module.
+	pub module_name: String,
+
+	/// The mechanical unit to control.
+	pub mech_unit: String,
+
+	/// The name of the EGM configuration in the controller configuration (`External Motion Interface Data`).
+	pub ext_config: String,
+
+	/// The name of the UDP unicast device of the sensor (`Transmission Protocol`).
+	pub uc_device: String,
+}
+
+impl Default for RapidSetup {
+	fn default() -> Self {
+		Self {
+			module_name: String::from("EGM_Main"),
+			mech_unit: String::from("ROB_1"),
+			ext_config: String::from("default"),
+			uc_device: String::from("UCdevice"),
+		}
+	}
+}
+
+impl RapidSetup {
+	/// Generate the transmission protocol entry of the UDP unicast device for the `SIO.cfg` controller configuration.
+	///
+	/// The endpoint is the local address the robot controller sends its messages to,
+	/// for example from [`crate::net::effective_local_endpoint`].
+	pub fn transmission_protocol_cfg(&self, endpoint: std::net::SocketAddr) -> String {
+		let mut out = String::new();
+		writeln!(out, "COM_TRP:").unwrap();
+		writeln!(out).unwrap();
+		// The misspelling of `RemoteAdress` is how the controller names the attribute.
+		writeln!(
+			out,
+			"      -Name \"{}\" -Type \"UDPUC\" -RemoteAdress \"{}\" -RemotePortNumber {}",
+			self.uc_device,
+			endpoint.ip(),
+			endpoint.port()
+		)
+		.unwrap();
+		out
+	}
+}
+
+/// Settings for the convergence and filtering of position guidance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GuidanceSettings {
+	/// The sample rate of the EGM session.
+	pub sample_rate: EgmRate,
+
+	/// The cut-off frequency of the low-pass filter in Hz, or zero to disable the filter.
+	pub lp_filter: f64,
+
+	/// The maximum speed deviation from the programmed speed, in degrees/s for joint guidance and mm/s for pose guidance.
+	pub max_speed_deviation: f64,
+
+	/// The convergence criterium: the maximum deviation per axis, in degrees for joint guidance and mm or degrees for pose guidance.
+	pub convergence: f64,
+
+	/// The maximum time in seconds that the EGM session may run.
+	pub cond_time: f64,
+
+	/// The time in seconds to ramp in the corrections at the start of the session.
+	pub ramp_in_time: f64,
+
+	/// The position correction gain, between 0 and 1.
+	pub pos_corr_gain: f64,
+}
+
+impl Default for GuidanceSettings {
+	fn default() -> Self {
+		Self {
+			sample_rate: EgmRate::MS_4,
+			lp_filter: 20.0,
+			max_speed_deviation: 20.0,
+			convergence: 0.1,
+			cond_time: 20.0,
+			ramp_in_time: 0.05,
+			pos_corr_gain: 1.0,
+		}
+	}
+}
+
+/// Configuration for a RAPID module that performs joint space position guidance with `EGMActJoint`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct JointGuidanceConfig {
+	/// General setup of the module.
+	pub setup: RapidSetup,
+
+	/// Convergence and filtering settings.
+	pub settings: GuidanceSettings,
+}
+
+/// Configuration for a RAPID module that performs cartesian position guidance with `EGMActPose`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoseGuidanceConfig {
+	/// General setup of the module.
+	pub setup: RapidSetup,
+
+	/// Convergence and filtering settings.
+	pub settings: GuidanceSettings,
+
+	/// The name of the RAPID tool data to use.
+	pub tool: String,
+
+	/// The name of the RAPID work object to use.
+	pub work_object: String,
+
+	/// The convergence criterium for rotations in degrees.
+	pub rotation_convergence: f64,
+}
+
+impl Default for PoseGuidanceConfig {
+	fn default() -> Self {
+		Self {
+			setup: RapidSetup::default(),
+			settings: GuidanceSettings {
+				max_speed_deviation: 1000.0,
+				..GuidanceSettings::default()
+			},
+			tool: String::from("tool0"),
+			work_object: String::from("wobj0"),
+			rotation_convergence: 0.1,
+		}
+	}
+}
+
+/// Configuration for a RAPID module that applies path corrections with `EGMMoveL`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathCorrectionConfig {
+	/// General setup of the module.
+	pub setup: RapidSetup,
+
+	/// The sample rate of the path correction in milliseconds.
+	pub sample_rate_ms: u32,
+
+	/// The name of the RAPID tool data to use.
+	pub tool: String,
+
+	/// The names of the RAPID robtargets that define the corrected path.
+	///
+	/// The robot moves to the first target with a normal `MoveL` and follows the rest with `EGMMoveL`.
+	pub targets: Vec<String>,
+
+	/// The name of the RAPID speed data to use for the corrected path.
+	pub speed: String,
+}
+
+impl Default for PathCorrectionConfig {
+	fn default() -> Self {
+		Self {
+			setup: RapidSetup::default(),
+			sample_rate_ms: 48,
+			tool: String::from("tool0"),
+			targets: vec![String::from("p10"), String::from("p20"), String::from("p30")],
+			speed: String::from("v10"),
+		}
+	}
+}
+
+/// Format a floating point number for RAPID.
+fn num(value: f64) -> String {
+	let formatted = format!("{}", value);
+	if formatted.contains('.') || formatted.contains('e') {
+		formatted
+	} else {
+		formatted + ".0"
+	}
+}
+
+impl RapidTemplate for JointGuidanceConfig {
+	fn to_rapid(&self) -> String {
+		let setup = &self.setup;
+		let settings = &self.settings;
+		let mut out = String::new();
+		module_header(&mut out, setup);
+		writeln!(out, "\tCONST egm_minmax egm_minmax_joint:=[{},{}];", num(-settings.convergence), num(settings.convergence)).unwrap();
+		writeln!(out).unwrap();
+		writeln!(out, "\tPROC main()").unwrap();
+		setup_uc(&mut out, setup, "Joint");
+		writeln!(
+			out,
+			"\t\tEGMActJoint egmID \\J1:=egm_minmax_joint \\J2:=egm_minmax_joint \\J3:=egm_minmax_joint \\J4:=egm_minmax_joint \\J5:=egm_minmax_joint \\J6:=egm_minmax_joint \\LpFilter:={} \\SampleRate:={} \\MaxSpeedDeviation:={};",
+			num(settings.lp_filter),
+			settings.sample_rate.sample_time_ms(),
+			num(settings.max_speed_deviation),
+		).unwrap();
+		writeln!(
+			out,
+			"\t\tEGMRunJoint egmID, EGM_STOP_HOLD \\J1 \\J2 \\J3 \\J4 \\J5 \\J6 \\CondTime:={} \\RampInTime:={} \\PosCorrGain:={};",
+			num(settings.cond_time),
+			num(settings.ramp_in_time),
+			num(settings.pos_corr_gain),
+		).unwrap();
+		module_footer(&mut out);
+		out
+	}
+}
+
+impl RapidTemplate for PoseGuidanceConfig {
+	fn to_rapid(&self) -> String {
+		let setup = &self.setup;
+		let settings = &self.settings;
+		let mut out = String::new();
+		module_header(&mut out, setup);
+		writeln!(out, "\tCONST egm_minmax egm_minmax_lin:=[{},{}];", num(-settings.convergence), num(settings.convergence)).unwrap();
+		writeln!(out, "\tCONST egm_minmax egm_minmax_rot:=[{},{}];", num(-self.rotation_convergence), num(self.rotation_convergence)).unwrap();
+		writeln!(out, "\tCONST pose corr_frame_offs:=[[0,0,0],[1,0,0,0]];").unwrap();
+		writeln!(out, "\tCONST pose sensor_frame_offs:=[[0,0,0],[1,0,0,0]];").unwrap();
+		writeln!(out).unwrap();
+		writeln!(out, "\tPROC main()").unwrap();
+		setup_uc(&mut out, setup, "Pose");
+		writeln!(
+			out,
+			"\t\tEGMActPose egmID \\Tool:={} \\WObj:={}, corr_frame_offs, EGM_FRAME_BASE, sensor_frame_offs, EGM_FRAME_BASE \\x:=egm_minmax_lin \\y:=egm_minmax_lin \\z:=egm_minmax_lin \\rx:=egm_minmax_rot \\ry:=egm_minmax_rot \\rz:=egm_minmax_rot \\LpFilter:={} \\SampleRate:={} \\MaxSpeedDeviation:={};",
+			self.tool,
+			self.work_object,
+			num(settings.lp_filter),
+			settings.sample_rate.sample_time_ms(),
+			num(settings.max_speed_deviation),
+		).unwrap();
+		writeln!(
+			out,
+			"\t\tEGMRunPose egmID, EGM_STOP_HOLD \\x \\y \\z \\Rx \\Ry \\Rz \\CondTime:={} \\RampInTime:={} \\PosCorrGain:={};",
+			num(settings.cond_time),
+			num(settings.ramp_in_time),
+			num(settings.pos_corr_gain),
+		).unwrap();
+		module_footer(&mut out);
+		out
+	}
+}
+
+impl RapidTemplate for PathCorrectionConfig {
+	fn to_rapid(&self) -> String {
+		let setup = &self.setup;
+		let mut out = String::new();
+		module_header(&mut out, setup);
+		writeln!(out, "\tCONST pose sensor_frame:=[[0,0,0],[1,0,0,0]];").unwrap();
+		writeln!(out).unwrap();
+		writeln!(out, "\tPROC main()").unwrap();
+		writeln!(out, "\t\tEGMGetId egmID;").unwrap();
+		writeln!(out, "\t\tEGMSetupUC {}, egmID, \"{}\", \"{}\" \\PathCorr \\APTR;", setup.mech_unit, setup.ext_config, setup.uc_device).unwrap();
+		writeln!(out, "\t\tEGMActMove egmID, sensor_frame \\SampleRate:={};", self.sample_rate_ms).unwrap();
+		if let Some((first, rest)) = self.targets.split_first() {
+			writeln!(out, "\t\tMoveL {}, v100, fine, {};", first, self.tool).unwrap();
+			for (i, target) in rest.iter().enumerate() {
+				let zone = if i + 1 == rest.len() { "fine" } else { "z5" };
+				writeln!(out, "\t\tEGMMoveL egmID, {}, {}, {}, {};", target, self.speed, zone, self.tool).unwrap();
+			}
+		}
+		writeln!(out, "\t\tEGMReset egmID;").unwrap();
+		writeln!(out, "\tENDPROC").unwrap();
+		writeln!(out, "ENDMODULE").unwrap();
+		out
+	}
+}
+
+fn module_header(out: &mut String, setup: &RapidSetup) {
+	writeln!(out, "MODULE {}", setup.module_name).unwrap();
+	writeln!(out, "\t! Generated by the abbegm crate.").unwrap();
+	writeln!(out, "\tVAR egmident egmID;").unwrap();
+	writeln!(out, "\tVAR egmstate egmState;").unwrap();
+}
+
+fn setup_uc(out: &mut String, setup: &RapidSetup, mode: &str) {
+	writeln!(out, "\t\tEGMReset egmID;").unwrap();
+	writeln!(out, "\t\tEGMGetId egmID;").unwrap();
+	writeln!(out, "\t\tegmState := EGMGetState(egmID);").unwrap();
+	writeln!(out, "\t\tIF egmState <= EGM_STATE_CONNECTED THEN").unwrap();
+	writeln!(out, "\t\t\tEGMSetupUC {}, egmID, \"{}\", \"{}\" \\{};", setup.mech_unit, setup.ext_config, setup.uc_device, mode).unwrap();
+	writeln!(out, "\t\tENDIF").unwrap();
+}
+
+fn module_footer(out: &mut String) {
+	writeln!(out, "\t\tegmState := EGMGetState(egmID);").unwrap();
+	writeln!(out, "\t\tIF egmState = EGM_STATE_CONNECTED THEN").unwrap();
+	writeln!(out, "\t\t\tEGMReset egmID;").unwrap();
+	writeln!(out, "\t\tENDIF").unwrap();
+	writeln!(out, "\tENDPROC").unwrap();
+	writeln!(out, "ENDMODULE").unwrap();
+}
+
+#[cfg(test)]
+#[test]
+fn test_joint_guidance() {
+	use assert2::assert;
+
+	let rapid = JointGuidanceConfig::default().to_rapid();
+	assert!(rapid.starts_with("MODULE EGM_Main\n"));
+	assert!(rapid.ends_with("ENDMODULE\n"));
+	assert!(rapid.contains("\t\t\tEGMSetupUC ROB_1, egmID, \"default\", \"UCdevice\" \\Joint;\n"));
+	assert!(rapid.contains("CONST egm_minmax egm_minmax_joint:=[-0.1,0.1];"));
+	assert!(rapid.contains("\\LpFilter:=20.0 \\SampleRate:=4 \\MaxSpeedDeviation:=20.0;"));
+}
+
+#[cfg(test)]
+#[test]
+fn test_transmission_protocol_cfg() {
+	use assert2::assert;
+
+	let cfg = RapidSetup::default().transmission_protocol_cfg("192.168.125.100:6510".parse().unwrap());
+	assert!(cfg.ends_with("-Name \"UCdevice\" -Type \"UDPUC\" -RemoteAdress \"192.168.125.100\" -RemotePortNumber 6510\n"));
+}
+
+#[cfg(test)]
+#[test]
+fn test_path_correction() {
+	use assert2::assert;
+
+	let rapid = PathCorrectionConfig::default().to_rapid();
+	assert!(rapid.contains("\t\tMoveL p10, v100, fine, tool0;\n\t\tEGMMoveL egmID, p20, v10, z5, tool0;\n\t\tEGMMoveL egmID, p30, v10, fine, tool0;\n"));
+}
@@ -0,0 +1,771 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+
+/// The sample rate of an EGM session.
+///
+/// The sample time is configured on the robot controller with the `\SampleRate` argument of `EGMActJoint` or `EGMActPose`.
+/// It must be a multiple of 4 milliseconds.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct EgmRate {
+	sample_time_ms: u32,
+}
+
+impl EgmRate {
+	/// The fastest sample rate supported by the controller: 4 milliseconds, or 250 Hz.
+	pub const MS_4: Self = Self { sample_time_ms: 4 };
+
+	/// A sample time of 8 milliseconds, or 125 Hz.
+	pub const MS_8: Self = Self { sample_time_ms: 8 };
+
+	/// A sample time of 12 milliseconds, or about 83 Hz.
+	pub const MS_12: Self = Self { sample_time_ms: 12 };
+
+	/// A sample time of 16 milliseconds, or 62.5 Hz.
+	pub const MS_16: Self = Self { sample_time_ms: 16 };
+
+	/// Create a sample rate from the sample time in milliseconds.
+	///
+	/// The sample time must be a non-zero multiple of 4 milliseconds.
+	pub fn from_sample_time_ms(sample_time_ms: u32) -> Result<Self, InvalidSampleTimeError> {
+		if sample_time_ms == 0 || !sample_time_ms.is_multiple_of(4) {
+			Err(InvalidSampleTimeError { sample_time_ms })
+		} else {
+			Ok(Self { sample_time_ms })
+		}
+	}
+
+	/// Get the sample time in milliseconds.
+	pub fn sample_time_ms(&self) -> u32 {
+		self.sample_time_ms
+	}
+
+	/// Get the sample time as [`Duration`].
+	pub fn period(&self) -> Duration {
+		Duration::from_millis(self.sample_time_ms.into())
+	}
+
+	/// Get the sample rate in Hz.
+	pub fn frequency(&self) -> f64 {
+		1000.0 / f64::from(self.sample_time_ms)
+	}
+}
+
+impl Default for EgmRate {
+	fn default() -> Self {
+		Self::MS_4
+	}
+}
+
+/// Error indicating that a sample time is not a non-zero multiple of 4 milliseconds.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct InvalidSampleTimeError {
+	/// The invalid sample time in milliseconds.
+	pub sample_time_ms: u32,
+}
+
+impl std::fmt::Display for InvalidSampleTimeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "invalid EGM sample time: {} ms, expected a non-zero multiple of 4 ms", self.sample_time_ms)
+	}
+}
+
+impl std::error::Error for InvalidSampleTimeError {}
+
+/// Schedule of ticks at a fixed period, shared by the blocking and asynchronous timers.
+///
+/// Deadlines are computed from the previous deadline rather than from the time of waking up,
+/// so scheduling delays do not accumulate into drift.
+/// A zero period is treated as one nanosecond.
+#[derive(Clone, Debug)]
+struct Schedule {
+	period: Duration,
+	next: Instant,
+}
+
+impl Schedule {
+	fn new(period: Duration, start: Instant) -> Self {
+		let period = period.max(Duration::from_nanos(1));
+		Self { period, next: start + period }
+	}
+
+	/// Get the next deadline and advance the schedule.
+	///
+	/// If the deadline has already passed by one or more periods, the missed ticks are skipped.
+	/// Returns the deadline and the number of skipped ticks.
+	fn advance(&mut self, now: Instant) -> (Instant, u32) {
+		let late = now.saturating_duration_since(self.next);
+		let period = self.period.as_nanos();
+		let missed = late.as_nanos() / period;
+		let deadline = self.next + late - Duration::from_nanos((late.as_nanos() % period) as u64);
+		self.next = deadline + self.period;
+		(deadline, missed.min(u128::from(u32::MAX)) as u32)
+	}
+
+	/// Change the period, keeping the previous deadline.
+	fn set_period(&mut self, period: Duration) {
+		let period = period.max(Duration::from_nanos(1));
+		if let Some(previous) = self.next.checked_sub(self.period) {
+			self.next = previous + period;
+		}
+		self.period = period;
+	}
+
+	/// Shift the phase of the schedule towards the arrival time of a controller message.
+	///
+	/// The `gain` determines which fraction of the phase error is corrected, between 0 and 1.
+	fn align(&mut self, arrival: Instant, gain: f64) {
+		let period = self.period.as_nanos() as i128;
+		let offset = if arrival >= self.next {
+			(arrival - self.next).as_nanos() as i128
+		} else {
+			-((self.next - arrival).as_nanos() as i128)
+		};
+		// Phase error in the range (-period / 2, period / 2].
+		let error = offset.rem_euclid(period);
+		let error = if error > period / 2 { error - period } else { error };
+		let correction = (error as f64 * gain.clamp(0.0, 1.0)).round() as i128;
+		let magnitude = Duration::from_nanos(correction.unsigned_abs() as u64);
+		if correction >= 0 {
+			self.next += magnitude;
+		} else {
+			self.next -= magnitude;
+		}
+	}
+}
+
+/// Blocking timer for control loops running at the EGM sample rate.
+///
+/// The timer corrects for scheduling delays, so the loop does not slowly drift.
+/// Call [`RateTimer::align`] with the arrival time of controller messages to keep the loop phase-aligned with the controller.
+///
+/// The timer uses the system clock by default.
+/// Use [`RateTimer::with_clock`] with a [`MockClock`](crate::clock::MockClock) to test timing logic without sleeping.
+#[derive(Clone, Debug)]
+pub struct RateTimer<C = SystemClock> {
+	schedule: Schedule,
+	clock: C,
+}
+
+impl RateTimer {
+	/// Create a new timer with the first tick one period from now.
+	pub fn new(rate: EgmRate) -> Self {
+		Self::with_period(rate.period())
+	}
+
+	/// Create a new timer with an arbitrary period.
+	///
+	/// A zero period is treated as one nanosecond.
+	pub fn with_period(period: Duration) -> Self {
+		Self::with_clock(period, SystemClock)
+	}
+
+	/// Create a timer at the sample time measured by an estimator, with the first tick one period after the last recorded arrival.
+	///
+	/// Returns `None` if the sample time has not been measured yet.
+	pub fn from_estimator(estimator: &SampleTimeEstimator) -> Option<Self> {
+		Self::from_estimator_with_clock(estimator, SystemClock)
+	}
+}
+
+impl<C: Clock> RateTimer<C> {
+	/// Create a new timer with an arbitrary period and a custom clock, with the first tick one period from now.
+	///
+	/// A zero period is treated as one nanosecond.
+	pub fn with_clock(period: Duration, clock: C) -> Self {
+		Self {
+			schedule: Schedule::new(period, clock.now()),
+			clock,
+		}
+	}
+
+	/// Create a timer with a custom clock at the sample time measured by an estimator, with the first tick one period after the last recorded arrival.
+	///
+	/// The arrival times must have been measured with the same clock.
+	/// Returns `None` if the sample time has not been measured yet.
+	/// The peers use this for [`EgmPeer::rate_timer`](crate::sync_peer::EgmPeer::rate_timer).
+	pub fn from_estimator_with_clock(estimator: &SampleTimeEstimator, clock: C) -> Option<Self> {
+		Some(Self {
+			schedule: Schedule::new(estimator.sample_time()?, estimator.last_arrival()?),
+			clock,
+		})
+	}
+
+	/// Sleep until the next tick.
+	///
+	/// Returns the number of ticks that were skipped because the loop was running late.
+	pub fn wait(&mut self) -> u32 {
+		let now = self.clock.now();
+		let (deadline, missed) = self.schedule.advance(now);
+		if deadline > now {
+			self.clock.sleep(deadline - now);
+		}
+		missed
+	}
+
+	/// Get the period of the timer.
+	pub fn period(&self) -> Duration {
+		self.schedule.period
+	}
+
+	/// Change the period of the timer, for example to follow the measured sample time of the controller.
+	///
+	/// The next tick is one new period after the previous tick.
+	/// A zero period is treated as one nanosecond.
+	pub fn set_period(&mut self, period: Duration) {
+		self.schedule.set_period(period)
+	}
+
+	/// Shift the phase of the timer towards the arrival time of a controller message.
+	///
+	/// The `gain` determines which fraction of the phase error is corrected, between 0 and 1.
+	/// A small gain such as 0.1 filters out network jitter.
+	pub fn align(&mut self, arrival: Instant, gain: f64) {
+		self.schedule.align(arrival, gain)
+	}
+}
+
+/// Asynchronous timer for control loops running at the EGM sample rate, using `tokio`.
+///
+/// The timer corrects for scheduling delays, so the loop does not slowly drift.
+/// Call [`AsyncRateTimer::align`] with the arrival time of controller messages to keep the loop phase-aligned with the controller.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Debug)]
+pub struct AsyncRateTimer {
+	schedule: Schedule,
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRateTimer {
+	/// Create a new timer with the first tick one period from now.
+	pub fn new(rate: EgmRate) -> Self {
+		Self::with_period(rate.period())
+	}
+
+	/// Create a new timer with an arbitrary period.
+	///
+	/// A zero period is treated as one nanosecond.
+	pub fn with_period(period: Duration) -> Self {
+		Self {
+			schedule: Schedule::new(period, Instant::now()),
+		}
+	}
+
+	/// Create a timer at the sample time measured by an estimator, with the first tick one period after the last recorded arrival.
+	///
+	/// Returns `None` if the sample time has not been measured yet.
+	pub fn from_estimator(estimator: &SampleTimeEstimator) -> Option<Self> {
+		Some(Self {
+			schedule: Schedule::new(estimator.sample_time()?, estimator.last_arrival()?),
+		})
+	}
+
+	/// Sleep until the next tick.
+	///
+	/// Returns the number of ticks that were skipped because the loop was running late.
+	pub async fn wait(&mut self) -> u32 {
+		let (deadline, missed) = self.schedule.advance(Instant::now());
+		tokio::time::sleep_until(deadline.into()).await;
+		missed
+	}
+
+	/// Get the period of the timer.
+	pub fn period(&self) -> Duration {
+		self.schedule.period
+	}
+
+	/// Change the period of the timer, for example to follow the measured sample time of the controller.
+	///
+	/// The next tick is one new period after the previous tick.
+	/// A zero period is treated as one nanosecond.
+	pub fn set_period(&mut self, period: Duration) {
+		self.schedule.set_period(period)
+	}
+
+	/// Shift the phase of the timer towards the arrival time of a controller message.
+	///
+	/// The `gain` determines which fraction of the phase error is corrected, between 0 and 1.
+	/// A small gain such as 0.1 filters out network jitter.
+	pub fn align(&mut self, arrival: Instant, gain: f64) {
+		self.schedule.align(arrival, gain)
+	}
+}
+
+/// Measures the actual sample time of the controller from the arrival times of its messages.
+///
+/// The sample time is the median of the last inter-arrival times, which is robust against network jitter and lost messages.
+/// It is only available after a warm-up window of intervals was recorded.
+///
+/// The peers measure the sample time automatically, see for example [`EgmPeer::measured_sample_time`](crate::sync_peer::EgmPeer::measured_sample_time).
+/// The peers create a [`RateTimer`] and a [`LoopTimer`] from the measurement,
+/// the streamers use it for the budget of their loop timer,
+/// and the speed limits of the `config` feature use it to limit the step between targets.
+#[derive(Clone, Debug)]
+pub struct SampleTimeEstimator {
+	window: usize,
+	intervals: VecDeque<Duration>,
+	last: Option<Instant>,
+}
+
+impl SampleTimeEstimator {
+	/// Create an estimator that uses the median of the last `window` intervals.
+	///
+	/// A window of 0 is treated as 1.
+	pub fn new(window: usize) -> Self {
+		let window = window.max(1);
+		Self {
+			window,
+			intervals: VecDeque::with_capacity(window),
+			last: None,
+		}
+	}
+
+	/// Get the number of intervals used for the median.
+	pub fn window(&self) -> usize {
+		self.window
+	}
+
+	/// Record the arrival of a message.
+	pub fn record(&mut self, arrival: Instant) {
+		if let Some(last) = self.last.replace(arrival) {
+			if self.intervals.len() == self.window {
+				self.intervals.pop_front();
+			}
+			self.intervals.push_back(arrival.saturating_duration_since(last));
+		}
+	}
+
+	/// Get the measured sample time, or `None` if fewer intervals than the window were recorded.
+	pub fn sample_time(&self) -> Option<Duration> {
+		if self.intervals.len() < self.window {
+			return None;
+		}
+		let mut intervals: Vec<_> = self.intervals.iter().copied().collect();
+		intervals.sort();
+		Some(intervals[intervals.len() / 2])
+	}
+
+	/// Get the time of the last recorded arrival, if any.
+	pub fn last_arrival(&self) -> Option<Instant> {
+		self.last
+	}
+
+	/// Get the measured sample time rounded to the nearest valid sample rate of the controller.
+	pub fn rate(&self) -> Option<EgmRate> {
+		let sample_time_ms = (self.sample_time()?.as_micros() + 2_000) / 4_000 * 4;
+		EgmRate::from_sample_time_ms(sample_time_ms.max(4) as u32).ok()
+	}
+
+	/// Forget all recorded arrivals, for example after a controller restart.
+	pub fn reset(&mut self) {
+		self.intervals.clear();
+		self.last = None;
+	}
+}
+
+impl Default for SampleTimeEstimator {
+	/// Create an estimator with a window of 50 intervals.
+	fn default() -> Self {
+		Self::new(50)
+	}
+}
+
+/// Histogram of latencies with buckets of equal width.
+///
+/// Latencies beyond the last bucket are counted in the last bucket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LatencyHistogram {
+	bucket_width: Duration,
+	buckets: Vec<u64>,
+}
+
+impl LatencyHistogram {
+	/// Create an empty histogram with a number of buckets of the given width.
+	///
+	/// The histogram has at least one bucket.
+	pub fn new(bucket_width: Duration, bucket_count: usize) -> Self {
+		Self {
+			bucket_width,
+			buckets: vec![0; bucket_count.max(1)],
+		}
+	}
+
+	/// Get the width of each bucket.
+	pub fn bucket_width(&self) -> Duration {
+		self.bucket_width
+	}
+
+	/// Get the counts of all buckets.
+	///
+	/// Bucket `i` counts the latencies in the range `[i * width, (i + 1) * width)`.
+	pub fn buckets(&self) -> &[u64] {
+		&self.buckets
+	}
+
+	/// Get the total number of recorded latencies.
+	pub fn count(&self) -> u64 {
+		self.buckets.iter().sum()
+	}
+
+	/// Add a latency to the histogram.
+	pub fn record(&mut self, latency: Duration) {
+		let width = self.bucket_width.as_nanos().max(1);
+		let index = (latency.as_nanos() / width).min(self.buckets.len() as u128 - 1) as usize;
+		self.buckets[index] += 1;
+	}
+
+	/// Get the upper bound of the bucket containing the given quantile, between 0 and 1.
+	///
+	/// Returns `None` if the histogram is empty.
+	pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+		let count = self.count();
+		if count == 0 {
+			return None;
+		}
+		let target = ((quantile.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+		let mut seen = 0;
+		for (i, bucket) in self.buckets.iter().enumerate() {
+			seen += bucket;
+			if seen >= target {
+				return Some(self.bucket_width * (i as u32 + 1));
+			}
+		}
+		Some(self.bucket_width * self.buckets.len() as u32)
+	}
+
+	/// Clear all buckets.
+	pub fn reset(&mut self) {
+		self.buckets.iter_mut().for_each(|x| *x = 0);
+	}
+}
+
+/// Statistics of the cycles measured by a [`LoopTimer`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoopTimerStats {
+	/// The number of measured cycles.
+	pub cycles: u64,
+
+	/// The number of cycles that exceeded the budget.
+	pub overruns: u64,
+
+	/// The latency of the last measured cycle.
+	pub last: Duration,
+
+	/// The smallest measured latency.
+	pub min: Duration,
+
+	/// The largest measured latency.
+	pub max: Duration,
+
+	/// The sum of all measured latencies, to compute the mean.
+	pub total: Duration,
+}
+
+impl LoopTimerStats {
+	/// Get the mean latency, or `None` if no cycles were measured.
+	pub fn mean(&self) -> Option<Duration> {
+		if self.cycles == 0 {
+			None
+		} else {
+			Some(self.total / self.cycles as u32)
+		}
+	}
+}
+
+/// Measures the time between receiving feedback and sending the corresponding target within a control loop.
+///
+/// Call [`LoopTimer::received`] when a message from the controller arrives and [`LoopTimer::sent`] after sending the target.
+/// The latencies are collected in a histogram, and a warning is logged when a cycle exceeds the budget.
+/// The controller expects a new target within one sample period, so the default budget is 4 milliseconds.
+#[derive(Clone, Debug)]
+pub struct LoopTimer<C = SystemClock> {
+	budget: Duration,
+	warn: bool,
+	received: Option<Instant>,
+	histogram: LatencyHistogram,
+	stats: LoopTimerStats,
+	clock: C,
+}
+
+impl LoopTimer {
+	/// Create a timer with a budget, and a histogram with buckets of 100 microseconds up to twice the budget.
+	pub fn new(budget: Duration) -> Self {
+		let bucket_width = Duration::from_micros(100);
+		let bucket_count = (2 * budget.as_micros() / 100) as usize;
+		Self::with_histogram(budget, LatencyHistogram::new(bucket_width, bucket_count))
+	}
+
+	/// Create a timer with a budget and a custom histogram.
+	pub fn with_histogram(budget: Duration, histogram: LatencyHistogram) -> Self {
+		Self {
+			budget,
+			warn: true,
+			received: None,
+			histogram,
+			stats: LoopTimerStats::default(),
+			clock: SystemClock,
+		}
+	}
+}
+
+impl<C: Clock> LoopTimer<C> {
+	/// Use a custom clock for [`LoopTimer::received`] and [`LoopTimer::sent`], such as a [`MockClock`](crate::clock::MockClock) in tests.
+	pub fn with_clock<D: Clock>(self, clock: D) -> LoopTimer<D> {
+		LoopTimer {
+			budget: self.budget,
+			warn: self.warn,
+			received: self.received,
+			histogram: self.histogram,
+			stats: self.stats,
+			clock,
+		}
+	}
+
+	/// Enable or disable logging a warning for each cycle that exceeds the budget.
+	pub fn with_warnings(mut self, warn: bool) -> Self {
+		self.warn = warn;
+		self
+	}
+
+	/// Get the budget of a single cycle.
+	pub fn budget(&self) -> Duration {
+		self.budget
+	}
+
+	/// Change the budget of a single cycle, for example to follow the measured sample time of the controller.
+	///
+	/// The histogram keeps its buckets.
+	pub fn set_budget(&mut self, budget: Duration) {
+		self.budget = budget;
+	}
+
+	/// Mark the arrival of feedback from the controller now.
+	pub fn received(&mut self) {
+		self.received_at(self.clock.now())
+	}
+
+	/// Mark the arrival of feedback from the controller at a specific time, such as the receive timestamp of the datagram.
+	pub fn received_at(&mut self, time: Instant) {
+		self.received = Some(time);
+	}
+
+	/// Mark that the target for the last received feedback was sent now.
+	///
+	/// Returns the latency of the cycle, or `None` if no feedback was received since the last target.
+	pub fn sent(&mut self) -> Option<Duration> {
+		self.sent_at(self.clock.now())
+	}
+
+	/// Mark that the target for the last received feedback was sent at a specific time.
+	///
+	/// Returns the latency of the cycle, or `None` if no feedback was received since the last target.
+	pub fn sent_at(&mut self, time: Instant) -> Option<Duration> {
+		let latency = time.saturating_duration_since(self.received.take()?);
+		self.histogram.record(latency);
+		let stats = &mut self.stats;
+		stats.min = if stats.cycles == 0 { latency } else { stats.min.min(latency) };
+		stats.max = stats.max.max(latency);
+		stats.cycles += 1;
+		stats.last = latency;
+		stats.total += latency;
+		if latency > self.budget {
+			stats.overruns += 1;
+			if self.warn {
+				log::warn!("control loop took {:?} between feedback and target, exceeding the budget of {:?}", latency, self.budget);
+			}
+		}
+		Some(latency)
+	}
+
+	/// Get the statistics of the measured cycles.
+	pub fn stats(&self) -> LoopTimerStats {
+		self.stats
+	}
+
+	/// Get the histogram of the measured latencies.
+	pub fn histogram(&self) -> &LatencyHistogram {
+		&self.histogram
+	}
+
+	/// Clear the statistics and the histogram.
+	pub fn reset(&mut self) {
+		self.received = None;
+		self.histogram.reset();
+		self.stats = LoopTimerStats::default();
+	}
+}
+
+impl Default for LoopTimer {
+	fn default() -> Self {
+		Self::new(EgmRate::MS_4.period())
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_rate() {
+	use assert2::assert;
+
+	assert!(EgmRate::from_sample_time_ms(8) == Ok(EgmRate::MS_8));
+	assert!(EgmRate::from_sample_time_ms(0).is_err());
+	assert!(EgmRate::from_sample_time_ms(6).is_err());
+	assert!(EgmRate::MS_4.period() == Duration::from_millis(4));
+	assert!(EgmRate::MS_4.frequency() == 250.0);
+}
+
+#[cfg(test)]
+#[test]
+fn test_schedule() {
+	use assert2::assert;
+
+	let ms = Duration::from_millis;
+	let start = Instant::now();
+	let mut schedule = Schedule::new(ms(4), start);
+
+	// Waking up late does not delay later deadlines.
+	assert!(schedule.advance(start + ms(1)) == (start + ms(4), 0));
+	assert!(schedule.advance(start + ms(5)) == (start + ms(8), 0));
+
+	// Missed ticks are skipped.
+	assert!(schedule.advance(start + ms(17)) == (start + ms(16), 1));
+	assert!(schedule.advance(start + ms(17)) == (start + ms(20), 0));
+
+	// Align the schedule with messages arriving 1 ms after our ticks.
+	schedule.align(start + ms(25), 0.5);
+	assert!(schedule.advance(start + ms(21)) == (start + ms(24) + Duration::from_micros(500), 0));
+
+	// Align the schedule with messages arriving 1 ms before our ticks.
+	schedule.align(start + ms(27) + Duration::from_micros(500), 1.0);
+	assert!(schedule.advance(start + ms(25)) == (start + ms(27) + Duration::from_micros(500), 0));
+}
+
+#[cfg(test)]
+#[test]
+fn test_schedule_missed_and_zero_period() {
+	use assert2::assert;
+
+	let ms = Duration::from_millis;
+	let start = Instant::now();
+
+	// A long stall skips all missed ticks at once.
+	let mut schedule = Schedule::new(ms(4), start);
+	assert!(schedule.advance(start + Duration::from_secs(3600) + ms(5)) == (start + Duration::from_secs(3600) + ms(4), 900_000));
+	assert!(schedule.advance(start + Duration::from_secs(3600) + ms(5)) == (start + Duration::from_secs(3600) + ms(8), 0));
+
+	// A zero period is clamped to one nanosecond.
+	let ns = Duration::from_nanos;
+	let mut schedule = Schedule::new(Duration::ZERO, start);
+	assert!(schedule.advance(start + ns(10)) == (start + ns(10), 9));
+	schedule.set_period(Duration::ZERO);
+	assert!(schedule.advance(start + ns(10)) == (start + ns(11), 0));
+}
+
+#[cfg(test)]
+#[test]
+fn test_sample_time_estimator() {
+	use assert2::assert;
+
+	let start = Instant::now();
+	let mut estimator = SampleTimeEstimator::new(5);
+	let arrivals = [0, 8, 16, 31, 32, 40];
+	for (i, &arrival) in arrivals.iter().enumerate() {
+		assert!(estimator.sample_time().is_none(), "available after {} arrivals", i);
+		estimator.record(start + Duration::from_millis(arrival));
+	}
+	assert!(estimator.sample_time() == Some(Duration::from_millis(8)));
+	assert!(estimator.rate() == Some(EgmRate::MS_8));
+
+	estimator.reset();
+	assert!(estimator.sample_time().is_none());
+}
+
+#[cfg(test)]
+#[test]
+fn test_timers_from_estimator() {
+	use assert2::{assert, let_assert};
+
+	let ms = Duration::from_millis;
+	let start = Instant::now();
+	let mut estimator = SampleTimeEstimator::new(2);
+	assert!(RateTimer::from_estimator(&estimator).is_none());
+	for arrival in [0, 8, 16] {
+		estimator.record(start + ms(arrival));
+	}
+	assert!(estimator.last_arrival() == Some(start + ms(16)));
+
+	// The first tick is one measured period after the last arrival.
+	let_assert!(Some(mut timer) = RateTimer::from_estimator(&estimator));
+	assert!(timer.period() == ms(8));
+	assert!(timer.schedule.advance(start + ms(20)) == (start + ms(24), 0));
+
+	// Changing the period keeps the previous tick.
+	timer.set_period(ms(4));
+	assert!(timer.schedule.advance(start + ms(25)) == (start + ms(28), 0));
+
+	let mut loop_timer = LoopTimer::default();
+	loop_timer.set_budget(ms(8));
+	assert!(loop_timer.budget() == ms(8));
+}
+
+#[cfg(test)]
+#[test]
+fn test_rate_timer_from_estimator_with_clock() {
+	use crate::clock::MockClock;
+	use assert2::{assert, let_assert};
+
+	let ms = Duration::from_millis;
+	let clock = MockClock::new();
+	let start = clock.now();
+	let mut estimator = SampleTimeEstimator::new(2);
+	assert!(RateTimer::from_estimator_with_clock(&estimator, clock.clone()).is_none());
+	for _ in 0..3 {
+		estimator.record(clock.now());
+		clock.advance(ms(8));
+	}
+
+	// The last arrival was at 16 ms, so the first tick is at 24 ms.
+	let_assert!(Some(mut timer) = RateTimer::from_estimator_with_clock(&estimator, clock.clone()));
+	assert!(timer.period() == ms(8));
+	assert!(timer.wait() == 0);
+	assert!(clock.now() == start + ms(24));
+
+	// Running late skips the tick at 32 ms and returns immediately for the tick at 40 ms.
+	clock.advance(ms(20));
+	assert!(timer.wait() == 1);
+	assert!(clock.now() == start + ms(44));
+	assert!(timer.wait() == 0);
+	assert!(clock.now() == start + ms(48));
+}
+
+#[cfg(test)]
+#[test]
+fn test_loop_timer() {
+	use assert2::assert;
+
+	let start = Instant::now();
+	let mut timer = LoopTimer::default().with_warnings(false);
+	assert!(timer.sent_at(start) == None);
+
+	for (i, latency_us) in [500, 1500, 1700, 5000].iter().enumerate() {
+		let received = start + Duration::from_millis(4 * i as u64);
+		timer.received_at(received);
+		assert!(timer.sent_at(received + Duration::from_micros(*latency_us)) == Some(Duration::from_micros(*latency_us)));
+	}
+
+	let stats = timer.stats();
+	assert!(stats.cycles == 4);
+	assert!(stats.overruns == 1);
+	assert!(stats.min == Duration::from_micros(500));
+	assert!(stats.max == Duration::from_millis(5));
+	assert!(stats.mean() == Some(Duration::from_micros(2175)));
+
+	let histogram = timer.histogram();
+	assert!(histogram.count() == 4);
+	assert!(histogram.buckets()[5] == 1);
+	assert!(histogram.quantile(0.5) == Some(Duration::from_micros(1600)));
+	assert!(histogram.quantile(1.0) == Some(Duration::from_micros(5100)));
+
+	timer.reset();
+	assert!(timer.stats().mean() == None);
+}
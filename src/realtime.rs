@@ -0,0 +1,101 @@
+//! Helpers to reduce the scheduling jitter of the thread running a control loop.
+//!
+//! Call these functions from the thread that receives the feedback and sends the targets,
+//! for example at the start of the thread that runs a [`crate::streamer::Streamer`] or a [`crate::sync_peer::ConnectedEgmPeer`] loop:
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! abbegm::realtime::pin_to_cpu(3)?;
+//! abbegm::realtime::set_fifo_priority(80)?;
+//! abbegm::realtime::lock_memory()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Raising the scheduling priority requires the `CAP_SYS_NICE` capability or a suitable `RLIMIT_RTPRIO`.
+//! A real-time thread that never blocks can starve the rest of the system, so only use this with a blocking receive or on a dedicated CPU.
+//!
+//! The helpers are only implemented for Linux, and return an error of kind [`std::io::ErrorKind::Unsupported`] on other platforms.
+
+use std::io;
+
+/// Run the calling thread with the `SCHED_FIFO` real-time scheduling policy and the given priority.
+///
+/// The priority must be between 1 and 99 on Linux.
+pub fn set_fifo_priority(priority: i32) -> io::Result<()> {
+	#[cfg(target_os = "linux")]
+	{
+		let param = libc::sched_param { sched_priority: priority };
+		check_pthread(unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) })
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	{
+		let _ = priority;
+		Err(unsupported())
+	}
+}
+
+/// Restrict the calling thread to a single CPU.
+///
+/// Combine this with the `isolcpus` kernel parameter to give the control loop a dedicated core.
+pub fn pin_to_cpu(cpu: usize) -> io::Result<()> {
+	#[cfg(target_os = "linux")]
+	{
+		if cpu >= libc::CPU_SETSIZE as usize {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("CPU index {} is too large", cpu)));
+		}
+		unsafe {
+			let mut set: libc::cpu_set_t = std::mem::zeroed();
+			libc::CPU_SET(cpu, &mut set);
+			check_pthread(libc::pthread_setaffinity_np(libc::pthread_self(), std::mem::size_of::<libc::cpu_set_t>(), &set))
+		}
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	{
+		let _ = cpu;
+		Err(unsupported())
+	}
+}
+
+/// Lock all current and future memory pages of the process in RAM, so the control loop is never delayed by page faults.
+pub fn lock_memory() -> io::Result<()> {
+	#[cfg(target_os = "linux")]
+	{
+		if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } == 0 {
+			Ok(())
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	{
+		Err(unsupported())
+	}
+}
+
+#[cfg(target_os = "linux")]
+fn check_pthread(result: libc::c_int) -> io::Result<()> {
+	if result == 0 {
+		Ok(())
+	} else {
+		Err(io::Error::from_raw_os_error(result))
+	}
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported() -> io::Error {
+	io::Error::new(io::ErrorKind::Unsupported, "real-time scheduling is only supported on Linux")
+}
+
+#[cfg(all(test, target_os = "linux"))]
+#[test]
+fn test_pin_to_cpu() {
+	use assert2::assert;
+
+	let result = std::thread::spawn(|| pin_to_cpu(0)).join().unwrap();
+	assert!(let Ok(()) = result);
+	assert!(let Err(_) = pin_to_cpu(usize::MAX));
+}
@@ -0,0 +1,412 @@
+use std::convert::TryFrom;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::Duration;
+
+use prost::Message;
+
+use crate::codec::DecodeError;
+use crate::msg::{EgmRobot, EgmSensor};
+
+/// The magic bytes at the start of a recording.
+pub const MAGIC: [u8; 8] = *b"ABBEGMRC";
+
+/// The magic bytes at the end of a recording with an index.
+pub const INDEX_MAGIC: [u8; 8] = *b"ABBEGMIX";
+
+/// The version of the recording format written by [`Recorder`].
+pub const VERSION: u16 = 1;
+
+const HEADER_LEN: u64 = 12;
+const RECORD_HEADER_LEN: u64 = 13;
+const INDEX_ENTRY_LEN: u64 = 16;
+const FOOTER_LEN: u64 = 24;
+
+/// The direction of a recorded message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+	/// A message sent by the robot controller.
+	FromRobot,
+
+	/// A message sent to the robot controller.
+	ToRobot,
+}
+
+impl Direction {
+	fn to_byte(self) -> u8 {
+		match self {
+			Self::FromRobot => 0,
+			Self::ToRobot => 1,
+		}
+	}
+
+	fn from_byte(byte: u8) -> Option<Self> {
+		match byte {
+			0 => Some(Self::FromRobot),
+			1 => Some(Self::ToRobot),
+			_ => None,
+		}
+	}
+}
+
+/// A recorded message.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+	/// The direction of the message.
+	pub direction: Direction,
+
+	/// The time at which the message was recorded, relative to a reference chosen by the recorder.
+	pub time: Duration,
+
+	/// The encoded message.
+	pub payload: Vec<u8>,
+}
+
+impl Record {
+	/// Decode the payload as message from the robot controller.
+	pub fn decode_robot(&self) -> Result<EgmRobot, DecodeError> {
+		crate::codec::decode_robot(&self.payload)
+	}
+
+	/// Decode the payload as message to the robot controller.
+	pub fn decode_sensor(&self) -> Result<EgmSensor, DecodeError> {
+		crate::codec::decode_sensor(&self.payload)
+	}
+}
+
+/// Error that may occur when reading or writing a recording.
+#[derive(Debug)]
+pub enum RecordingError {
+	/// An I/O error occurred.
+	Io(std::io::Error),
+
+	/// The data does not start with the magic bytes of a recording.
+	InvalidMagic,
+
+	/// The recording was written with an unsupported version of the format.
+	UnsupportedVersion(u16),
+
+	/// The recording is corrupt.
+	Corrupt(&'static str),
+
+	/// A record was written with a timestamp before the previous record.
+	TimeWentBackwards { previous: Duration, time: Duration },
+}
+
+/// Writer for recordings of EGM messages.
+///
+/// A recording consists of a header, a sequence of records and an index.
+/// Each record holds the direction, timestamp and length of a message, followed by the encoded message.
+/// All integers are stored in little-endian byte order, so recordings can be read on any platform.
+///
+/// The index is written by [`Recorder::finish`].
+/// If a recording is not finished, for example because the application crashed,
+/// [`RecordingReader`] rebuilds the index by scanning the records.
+pub struct Recorder<W: Write> {
+	writer: W,
+	position: u64,
+	index: Vec<(u64, Duration)>,
+}
+
+impl<W: Write> Recorder<W> {
+	/// Create a new recorder and write the header of the recording.
+	pub fn new(mut writer: W) -> Result<Self, RecordingError> {
+		writer.write_all(&MAGIC)?;
+		writer.write_all(&VERSION.to_le_bytes())?;
+		writer.write_all(&0u16.to_le_bytes())?;
+		Ok(Self {
+			writer,
+			position: HEADER_LEN,
+			index: Vec::new(),
+		})
+	}
+
+	/// Get the number of records written so far.
+	pub fn len(&self) -> usize {
+		self.index.len()
+	}
+
+	/// Check if no records have been written yet.
+	pub fn is_empty(&self) -> bool {
+		self.index.is_empty()
+	}
+
+	/// Write an encoded message to the recording.
+	///
+	/// The timestamps of the records must not decrease.
+	pub fn record(&mut self, direction: Direction, time: Duration, payload: &[u8]) -> Result<(), RecordingError> {
+		if let Some(&(_, previous)) = self.index.last() {
+			if time < previous {
+				return Err(RecordingError::TimeWentBackwards { previous, time });
+			}
+		}
+		let len = u32::try_from(payload.len()).map_err(|_| RecordingError::Corrupt("message too large"))?;
+		self.writer.write_all(&[direction.to_byte()])?;
+		self.writer.write_all(&duration_to_nanos(time).to_le_bytes())?;
+		self.writer.write_all(&len.to_le_bytes())?;
+		self.writer.write_all(payload)?;
+		self.index.push((self.position, time));
+		self.position += RECORD_HEADER_LEN + u64::from(len);
+		Ok(())
+	}
+
+	/// Write a message from the robot controller to the recording.
+	pub fn record_robot(&mut self, time: Duration, msg: &EgmRobot) -> Result<(), RecordingError> {
+		self.record(Direction::FromRobot, time, &msg.encode_to_vec())
+	}
+
+	/// Write a message to the robot controller to the recording.
+	pub fn record_sensor(&mut self, time: Duration, msg: &EgmSensor) -> Result<(), RecordingError> {
+		self.record(Direction::ToRobot, time, &msg.encode_to_vec())
+	}
+
+	/// Write the index and flush the recording.
+	///
+	/// Returns the inner writer.
+	pub fn finish(mut self) -> Result<W, RecordingError> {
+		let index_position = self.position;
+		for &(position, time) in &self.index {
+			self.writer.write_all(&position.to_le_bytes())?;
+			self.writer.write_all(&duration_to_nanos(time).to_le_bytes())?;
+		}
+		self.writer.write_all(&index_position.to_le_bytes())?;
+		self.writer.write_all(&(self.index.len() as u64).to_le_bytes())?;
+		self.writer.write_all(&INDEX_MAGIC)?;
+		self.writer.flush()?;
+		Ok(self.writer)
+	}
+}
+
+impl<W: Write> std::fmt::Debug for Recorder<W> {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("Recorder").field("records", &self.index.len()).finish()
+	}
+}
+
+/// Reader for recordings written by [`Recorder`], with random access by index and by time.
+#[derive(Debug)]
+pub struct RecordingReader<R> {
+	reader: R,
+	index: Vec<(u64, Duration)>,
+}
+
+impl<R: Read + Seek> RecordingReader<R> {
+	/// Open a recording and load its index.
+	///
+	/// If the recording has no index, the index is rebuilt by scanning the records.
+	/// An incomplete last record is ignored in that case.
+	pub fn new(mut reader: R) -> Result<Self, RecordingError> {
+		reader.seek(SeekFrom::Start(0))?;
+		let mut header = [0; HEADER_LEN as usize];
+		read_exact_or(&mut reader, &mut header, RecordingError::InvalidMagic)?;
+		if header[..8] != MAGIC {
+			return Err(RecordingError::InvalidMagic);
+		}
+		let version = u16::from_le_bytes([header[8], header[9]]);
+		if version != VERSION {
+			return Err(RecordingError::UnsupportedVersion(version));
+		}
+
+		let index = match read_index(&mut reader)? {
+			Some(index) => index,
+			None => scan_index(&mut reader)?,
+		};
+		Ok(Self { reader, index })
+	}
+
+	/// Get the number of records in the recording.
+	pub fn len(&self) -> usize {
+		self.index.len()
+	}
+
+	/// Check if the recording has no records.
+	pub fn is_empty(&self) -> bool {
+		self.index.is_empty()
+	}
+
+	/// Get the timestamp of a record without reading it.
+	pub fn time(&self, index: usize) -> Option<Duration> {
+		self.index.get(index).map(|&(_, time)| time)
+	}
+
+	/// Get the index of the first record at or after a time.
+	///
+	/// Returns [`RecordingReader::len`] if all records are before the time.
+	pub fn seek_time(&self, time: Duration) -> usize {
+		self.index.partition_point(|&(_, record_time)| record_time < time)
+	}
+
+	/// Read a record by index.
+	pub fn read(&mut self, index: usize) -> Result<Option<Record>, RecordingError> {
+		let position = match self.index.get(index) {
+			Some(&(position, _)) => position,
+			None => return Ok(None),
+		};
+		self.reader.seek(SeekFrom::Start(position))?;
+		match read_record(&mut self.reader)? {
+			Some(record) => Ok(Some(record)),
+			None => Err(RecordingError::Corrupt("indexed record is truncated")),
+		}
+	}
+
+	/// Read all records from an index up to the end of the recording.
+	pub fn read_from(&mut self, index: usize) -> Result<Vec<Record>, RecordingError> {
+		let mut records = Vec::with_capacity(self.len().saturating_sub(index));
+		for i in index..self.len() {
+			records.extend(self.read(i)?);
+		}
+		Ok(records)
+	}
+
+	/// Consume self and get the inner reader.
+	pub fn into_inner(self) -> R {
+		self.reader
+	}
+}
+
+fn duration_to_nanos(time: Duration) -> u64 {
+	time.as_secs().saturating_mul(1_000_000_000).saturating_add(u64::from(time.subsec_nanos()))
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+	let mut buffer = [0; 8];
+	buffer.copy_from_slice(&bytes[..8]);
+	u64::from_le_bytes(buffer)
+}
+
+/// Read exactly enough bytes to fill a buffer, returning a custom error on a premature end of file.
+fn read_exact_or(reader: &mut impl Read, buffer: &mut [u8], error: RecordingError) -> Result<(), RecordingError> {
+	match reader.read_exact(buffer) {
+		Ok(()) => Ok(()),
+		Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Err(error),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Read a record at the current position, or `None` if the data ends before the record is complete.
+fn read_record(reader: &mut impl Read) -> Result<Option<Record>, RecordingError> {
+	let mut header = [0; RECORD_HEADER_LEN as usize];
+	match reader.read_exact(&mut header) {
+		Ok(()) => (),
+		Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+		Err(e) => return Err(e.into()),
+	}
+	let direction = Direction::from_byte(header[0]).ok_or(RecordingError::Corrupt("invalid record direction"))?;
+	let time = Duration::from_nanos(read_u64(&header[1..9]));
+	let len = u32::from_le_bytes([header[9], header[10], header[11], header[12]]);
+	let mut payload = Vec::new();
+	reader.take(u64::from(len)).read_to_end(&mut payload)?;
+	if payload.len() != len as usize {
+		return Ok(None);
+	}
+	Ok(Some(Record { direction, time, payload }))
+}
+
+/// Read the index at the end of a recording, if it has one.
+fn read_index(reader: &mut (impl Read + Seek)) -> Result<Option<Vec<(u64, Duration)>>, RecordingError> {
+	let end = reader.seek(SeekFrom::End(0))?;
+	if end < HEADER_LEN + FOOTER_LEN {
+		return Ok(None);
+	}
+	reader.seek(SeekFrom::Start(end - FOOTER_LEN))?;
+	let mut footer = [0; FOOTER_LEN as usize];
+	reader.read_exact(&mut footer)?;
+	if footer[16..] != INDEX_MAGIC {
+		return Ok(None);
+	}
+	let index_position = read_u64(&footer[0..8]);
+	let count = read_u64(&footer[8..16]);
+	let index_len = count.checked_mul(INDEX_ENTRY_LEN).ok_or(RecordingError::Corrupt("invalid index length"))?;
+	if index_position < HEADER_LEN || index_position.checked_add(index_len) != Some(end - FOOTER_LEN) {
+		return Err(RecordingError::Corrupt("invalid index position"));
+	}
+
+	reader.seek(SeekFrom::Start(index_position))?;
+	let mut data = vec![0; index_len as usize];
+	reader.read_exact(&mut data)?;
+	let index = data
+		.chunks_exact(INDEX_ENTRY_LEN as usize)
+		.map(|entry| (read_u64(&entry[0..8]), Duration::from_nanos(read_u64(&entry[8..16]))))
+		.collect::<Vec<_>>();
+	if index.iter().any(|&(position, _)| position < HEADER_LEN || position >= index_position) {
+		return Err(RecordingError::Corrupt("index entry out of range"));
+	}
+	Ok(Some(index))
+}
+
+/// Rebuild the index of a recording by scanning all records.
+fn scan_index(reader: &mut (impl Read + Seek)) -> Result<Vec<(u64, Duration)>, RecordingError> {
+	let mut position = reader.seek(SeekFrom::Start(HEADER_LEN))?;
+	let mut index = Vec::new();
+	while let Some(record) = read_record(reader)? {
+		index.push((position, record.time));
+		position += RECORD_HEADER_LEN + record.payload.len() as u64;
+	}
+	Ok(index)
+}
+
+impl From<std::io::Error> for RecordingError {
+	fn from(other: std::io::Error) -> Self {
+		Self::Io(other)
+	}
+}
+
+impl std::fmt::Display for RecordingError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Io(e) => e.fmt(f),
+			Self::InvalidMagic => write!(f, "data is not an EGM recording"),
+			Self::UnsupportedVersion(version) => write!(f, "unsupported recording format version: {}", version),
+			Self::Corrupt(reason) => write!(f, "corrupt recording: {}", reason),
+			Self::TimeWentBackwards { previous, time } => {
+				write!(f, "record time {:?} is before the previous record time {:?}", time, previous)
+			}
+		}
+	}
+}
+
+impl std::error::Error for RecordingError {}
+
+#[cfg(test)]
+#[test]
+fn test_recording() {
+	use crate::msg::{EgmClock, EgmHeader};
+	use assert2::{assert, let_assert};
+	use std::io::Cursor;
+
+	let ms = Duration::from_millis;
+	let robot = |seqno| EgmRobot {
+		header: Some(EgmHeader::data(seqno, seqno * 4)),
+		..Default::default()
+	};
+
+	let mut recorder = Recorder::new(Vec::new()).unwrap();
+	for i in 0..10 {
+		recorder.record_robot(ms(u64::from(i) * 4), &robot(i)).unwrap();
+		recorder.record_sensor(ms(u64::from(i) * 4 + 1), &EgmSensor::joint_target(i, [0.0; 6], EgmClock::new(0, 0))).unwrap();
+	}
+	assert!(let Err(RecordingError::TimeWentBackwards { .. }) = recorder.record_robot(ms(0), &robot(0)));
+	let data = recorder.finish().unwrap();
+
+	let mut reader = RecordingReader::new(Cursor::new(&data)).unwrap();
+	assert!(reader.len() == 20);
+	assert!(reader.seek_time(ms(9)) == 5);
+	assert!(reader.seek_time(ms(100)) == 20);
+	let_assert!(Ok(Some(record)) = reader.read(4));
+	assert!(record.direction == Direction::FromRobot);
+	assert!(record.time == ms(8));
+	assert!(record.decode_robot().unwrap() == robot(2));
+	let_assert!(Ok(Some(record)) = reader.read(5));
+	assert!(record.direction == Direction::ToRobot);
+	assert!(record.decode_sensor().unwrap().header.and_then(|x| x.seqno) == Some(2));
+	assert!(let Ok(None) = reader.read(20));
+	assert!(reader.read_from(18).unwrap().len() == 2);
+
+	// Without the index and with a truncated last record, the index is rebuilt from the complete records.
+	let index_position = read_u64(&data[data.len() - 24..]) as usize;
+	let truncated = &data[..index_position - 3];
+	let reader = RecordingReader::new(Cursor::new(truncated)).unwrap();
+	assert!(reader.len() == 19);
+	assert!(reader.time(18) == Some(ms(36)));
+
+	assert!(let Err(RecordingError::InvalidMagic) = RecordingReader::new(Cursor::new(b"not a recording")));
+}
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use crate::feedback::{lerp_pose, lerp_values, FeedbackSample};
+use crate::msg::EgmRobot;
+
+/// How the resampler computes the value at a sample time between two feedback messages.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResamplePolicy {
+	/// Interpolate linearly between the feedback before and after the sample time.
+	///
+	/// Orientations are interpolated with spherical linear interpolation.
+	Linear,
+
+	/// Hold the value of the last feedback before the sample time.
+	Hold,
+}
+
+/// Converts feedback with irregular timing into a stream of samples at a fixed rate.
+///
+/// Fixed-rate estimators such as Kalman filters assume a constant time step,
+/// while feedback messages can be delayed, lost or arrive at a different rate than the estimator runs at.
+/// The resampler produces samples at exact multiples of the period on the controller clock,
+/// starting at the time of the first feedback.
+///
+/// A sample is only produced once feedback at or after its time has been received,
+/// so the output lags behind the feedback by less than one period.
+/// Messages without a feedback time, or with a time that is not newer than the previous message, are ignored.
+#[derive(Clone, Debug)]
+pub struct Resampler {
+	period: Duration,
+	policy: ResamplePolicy,
+	previous: Option<FeedbackSample>,
+	next_time: Option<Duration>,
+}
+
+impl Resampler {
+	/// Create a resampler with a fixed period and an interpolation policy.
+	///
+	/// # Panics
+	/// Panics if the period is zero.
+	pub fn new(period: Duration, policy: ResamplePolicy) -> Self {
+		assert!(period > Duration::ZERO, "the period of a resampler must not be zero");
+		Self {
+			period,
+			policy,
+			previous: None,
+			next_time: None,
+		}
+	}
+
+	/// Get the period of the output samples.
+	pub fn period(&self) -> Duration {
+		self.period
+	}
+
+	/// Add a message from the robot controller, and get the samples that became available.
+	pub fn push(&mut self, state: &EgmRobot) -> Vec<FeedbackSample> {
+		match FeedbackSample::from_message(state) {
+			Some(sample) => self.push_sample(sample),
+			None => Vec::new(),
+		}
+	}
+
+	/// Add a feedback sample, and get the samples that became available.
+	pub fn push_sample(&mut self, sample: FeedbackSample) -> Vec<FeedbackSample> {
+		let previous = match self.previous.take() {
+			Some(previous) if sample.time > previous.time => previous,
+			Some(previous) => {
+				self.previous = Some(previous);
+				return Vec::new();
+			},
+			None => {
+				self.next_time = Some(sample.time + self.period);
+				self.previous = Some(sample.clone());
+				return vec![sample];
+			},
+		};
+
+		let mut output = Vec::new();
+		let mut next_time = self.next_time.unwrap_or(sample.time);
+		while next_time <= sample.time {
+			output.push(self.sample_at(&previous, &sample, next_time));
+			next_time += self.period;
+		}
+		self.next_time = Some(next_time);
+		self.previous = Some(sample);
+		output
+	}
+
+	/// Forget all feedback, for example after a controller restart.
+	pub fn reset(&mut self) {
+		self.previous = None;
+		self.next_time = None;
+	}
+
+	fn sample_at(&self, before: &FeedbackSample, after: &FeedbackSample, time: Duration) -> FeedbackSample {
+		let fraction = match self.policy {
+			ResamplePolicy::Hold if time < after.time => 0.0,
+			ResamplePolicy::Hold => 1.0,
+			ResamplePolicy::Linear => (time - before.time).as_secs_f64() / (after.time - before.time).as_secs_f64(),
+		};
+		FeedbackSample {
+			time,
+			joints: lerp_or_hold(&before.joints, &after.joints, fraction),
+			external_joints: lerp_or_hold(&before.external_joints, &after.external_joints, fraction),
+			pose: match (&before.pose, &after.pose) {
+				(Some(a), Some(b)) => Some(lerp_pose(a, b, fraction)),
+				(a, b) => if fraction < 1.0 { a.clone() } else { b.clone() },
+			},
+		}
+	}
+}
+
+/// Interpolate between two lists of values, or hold the nearest one if they have a different length.
+fn lerp_or_hold(a: &[f64], b: &[f64], fraction: f64) -> Vec<f64> {
+	if a.len() == b.len() {
+		lerp_values(a, b, fraction)
+	} else if fraction < 1.0 {
+		a.to_vec()
+	} else {
+		b.to_vec()
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_resampler() {
+	use assert2::assert;
+
+	let ms = Duration::from_millis;
+	let sample = |time: u64, joint: f64| FeedbackSample {
+		time: ms(time),
+		joints: vec![joint],
+		external_joints: Vec::new(),
+		pose: Some(crate::msg::EgmPose::new([joint, 0.0, 0.0], crate::msg::EgmQuaternion::IDENTITY)),
+	};
+	let joints = |samples: Vec<FeedbackSample>| samples.into_iter().map(|x| (x.time, x.joints[0])).collect::<Vec<_>>();
+
+	let mut resampler = Resampler::new(ms(10), ResamplePolicy::Linear);
+	assert!(joints(resampler.push_sample(sample(100, 0.0))) == [(ms(100), 0.0)]);
+	assert!(joints(resampler.push_sample(sample(104, 4.0))) == []);
+	assert!(joints(resampler.push_sample(sample(112, 12.0))) == [(ms(110), 10.0)]);
+	assert!(joints(resampler.push_sample(sample(112, 0.0))) == []);
+	// A gap of several periods is filled in.
+	let output = resampler.push_sample(sample(142, 42.0));
+	assert!(output[2].pose.as_ref().unwrap().pos.unwrap().x == 40.0);
+	assert!(joints(output) == [(ms(120), 20.0), (ms(130), 30.0), (ms(140), 40.0)]);
+
+	let mut resampler = Resampler::new(ms(10), ResamplePolicy::Hold);
+	resampler.push_sample(sample(100, 0.0));
+	assert!(joints(resampler.push_sample(sample(108, 8.0))) == []);
+	assert!(joints(resampler.push_sample(sample(125, 25.0))) == [(ms(110), 8.0), (ms(120), 8.0)]);
+	assert!(joints(resampler.push_sample(sample(130, 30.0))) == [(ms(130), 30.0)]);
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use crate::error::RetriesExhaustedError;
+use crate::SendError;
+
+/// Policy for retrying sends that fail with a transient I/O error.
+///
+/// Transient errors are [`std::io::ErrorKind::WouldBlock`], [`std::io::ErrorKind::Interrupted`]
+/// and a full send buffer of the operating system (`ENOBUFS`).
+/// Other errors are returned immediately.
+///
+/// The default policy does not retry at all.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+	/// The maximum number of retries after the first attempt.
+	pub max_retries: u32,
+
+	/// The delay before the first retry.
+	pub backoff: Duration,
+
+	/// The maximum delay between retries. The delay doubles after each retry up to this limit.
+	pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self::none()
+	}
+}
+
+impl RetryPolicy {
+	/// A policy that never retries.
+	pub fn none() -> Self {
+		Self {
+			max_retries: 0,
+			backoff: Duration::ZERO,
+			max_backoff: Duration::ZERO,
+		}
+	}
+
+	/// A policy that retries up to `max_retries` times, starting with a delay of `backoff`.
+	///
+	/// The delay doubles after each retry, up to a maximum of one EGM sample period of 4 milliseconds.
+	pub fn new(max_retries: u32, backoff: Duration) -> Self {
+		Self {
+			max_retries,
+			backoff,
+			max_backoff: Duration::from_millis(4).max(backoff),
+		}
+	}
+
+	/// Set the maximum delay between retries.
+	pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+		self.max_backoff = max_backoff;
+		self
+	}
+
+	/// Get the delay before a retry, where the first retry has number 0.
+	pub fn delay(&self, retry: u32) -> Duration {
+		let factor = 1u32.checked_shl(retry.min(31)).unwrap_or(u32::MAX);
+		self.backoff.checked_mul(factor).unwrap_or(self.max_backoff).min(self.max_backoff)
+	}
+
+	/// Run a blocking send operation, retrying transient errors.
+	pub(crate) fn send(&self, mut send: impl FnMut() -> std::io::Result<usize>) -> Result<usize, SendError> {
+		let mut retry = 0;
+		loop {
+			match send() {
+				Ok(bytes_sent) => return Ok(bytes_sent),
+				Err(e) => self.check(e, retry)?,
+			}
+			std::thread::sleep(self.delay(retry));
+			retry += 1;
+		}
+	}
+
+	/// Run an asynchronous send operation, retrying transient errors.
+	#[cfg(feature = "tokio")]
+	pub(crate) async fn send_async<F, Fut>(&self, mut send: F) -> Result<usize, SendError>
+	where
+		F: FnMut() -> Fut,
+		Fut: std::future::Future<Output = std::io::Result<usize>>,
+	{
+		let mut retry = 0;
+		loop {
+			match send().await {
+				Ok(bytes_sent) => return Ok(bytes_sent),
+				Err(e) => self.check(e, retry)?,
+			}
+			tokio::time::sleep(self.delay(retry)).await;
+			retry += 1;
+		}
+	}
+
+	/// Check if an error should be retried, or turn it into the error to return.
+	fn check(&self, error: std::io::Error, retry: u32) -> Result<(), SendError> {
+		if !is_transient(&error) || self.max_retries == 0 {
+			Err(SendError::Io(error))
+		} else if retry >= self.max_retries {
+			Err(SendError::RetriesExhausted(RetriesExhaustedError { attempts: retry + 1, error }))
+		} else {
+			log::debug!("retrying send after transient error: {}", error);
+			Ok(())
+		}
+	}
+}
+
+/// Check if an I/O error is transient, so the operation can be retried.
+pub fn is_transient(error: &std::io::Error) -> bool {
+	#[cfg(target_os = "linux")]
+	const ENOBUFS: i32 = 105;
+	#[cfg(windows)]
+	const ENOBUFS: i32 = 10055;
+	#[cfg(not(any(target_os = "linux", windows)))]
+	const ENOBUFS: i32 = 55;
+
+	match error.kind() {
+		std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted => true,
+		_ => error.raw_os_error() == Some(ENOBUFS),
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_retry_policy() {
+	use assert2::{assert, let_assert};
+	use std::io::ErrorKind;
+
+	let policy = RetryPolicy::new(3, Duration::from_millis(1));
+	assert!(policy.delay(0) == Duration::from_millis(1));
+	assert!(policy.delay(2) == Duration::from_millis(4));
+	assert!(policy.delay(10) == Duration::from_millis(4));
+
+	let mut failures = 2;
+	let mut attempt = || {
+		if failures > 0 {
+			failures -= 1;
+			Err(ErrorKind::WouldBlock.into())
+		} else {
+			Ok(10)
+		}
+	};
+	assert!(let Ok(10) = policy.send(&mut attempt));
+
+	let_assert!(Err(SendError::RetriesExhausted(e)) = policy.send(|| Err(ErrorKind::Interrupted.into())));
+	assert!(e.attempts == 4);
+	assert!(e.error.kind() == ErrorKind::Interrupted);
+
+	assert!(let Err(SendError::Io(_)) = policy.send(|| Err(ErrorKind::ConnectionRefused.into())));
+	assert!(let Err(SendError::Io(_)) = RetryPolicy::none().send(|| Err(ErrorKind::WouldBlock.into())));
+}
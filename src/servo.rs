@@ -0,0 +1,86 @@
+/// A source of targets for a control loop, such as a stream of camera measurements.
+///
+/// The control loop polls the source once per cycle.
+/// Sources that buffer targets should return only the most recent one,
+/// so that a slow control loop never acts on stale measurements.
+pub trait TargetSource {
+	/// The type of target produced by the source.
+	type Target;
+
+	/// Get the most recent target, if a new one is available.
+	///
+	/// This function must not block.
+	fn poll_target(&mut self) -> Option<Self::Target>;
+}
+
+impl<T> TargetSource for std::sync::mpsc::Receiver<T> {
+	type Target = T;
+
+	fn poll_target(&mut self) -> Option<T> {
+		self.try_iter().last()
+	}
+}
+
+#[cfg(feature = "tokio")]
+impl<T> TargetSource for tokio::sync::mpsc::Receiver<T> {
+	type Target = T;
+
+	fn poll_target(&mut self) -> Option<T> {
+		let mut latest = None;
+		while let Ok(target) = self.try_recv() {
+			latest = Some(target);
+		}
+		latest
+	}
+}
+
+#[cfg(feature = "tokio")]
+impl<T: Clone> TargetSource for tokio::sync::watch::Receiver<T> {
+	type Target = T;
+
+	fn poll_target(&mut self) -> Option<T> {
+		if self.has_changed().unwrap_or(false) {
+			Some(self.borrow_and_update().clone())
+		} else {
+			None
+		}
+	}
+}
+
+/// A [`TargetSource`] that calls a function to poll for new targets.
+#[derive(Clone, Debug)]
+pub struct PollFn<F> {
+	poll: F,
+}
+
+/// Create a [`TargetSource`] from a function.
+pub fn poll_fn<F, T>(poll: F) -> PollFn<F>
+where
+	F: FnMut() -> Option<T>,
+{
+	PollFn { poll }
+}
+
+impl<F, T> TargetSource for PollFn<F>
+where
+	F: FnMut() -> Option<T>,
+{
+	type Target = T;
+
+	fn poll_target(&mut self) -> Option<T> {
+		(self.poll)()
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_channel_returns_latest() {
+	use assert2::assert;
+
+	let (sender, mut receiver) = std::sync::mpsc::channel();
+	assert!(receiver.poll_target() == None);
+	sender.send(1).unwrap();
+	sender.send(2).unwrap();
+	assert!(receiver.poll_target() == Some(2));
+	assert!(receiver.poll_target() == None);
+}
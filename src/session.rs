@@ -0,0 +1,677 @@
+#[cfg(feature = "tokio")]
+use std::collections::VecDeque;
+use std::time::Duration;
+#[cfg(feature = "tokio")]
+use tokio::time::Instant;
+
+use crate::middleware::Middleware;
+use crate::msg::{EgmRobot, EgmSensor};
+use crate::MessageRejectedError;
+#[cfg(feature = "tokio")]
+use crate::transport::AsyncEgmTransport;
+#[cfg(feature = "tokio")]
+use crate::ReceiveError;
+
+/// An event detected in the stream of messages from the robot controller.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionEvent {
+	/// The robot controller was restarted, or a new EGM session was started.
+	///
+	/// State derived from earlier messages, such as velocity estimates, is no longer valid.
+	ControllerRestarted {
+		/// The reason the restart was detected.
+		reason: RestartReason,
+	},
+
+	/// One or more messages were lost: the sequence number skipped ahead.
+	SequenceGap {
+		/// The sequence number that was expected.
+		expected: u32,
+
+		/// The sequence number that was received.
+		received: u32,
+	},
+
+	/// The controller reported that the robot converged to the target.
+	ConvergenceMet,
+
+	/// The motors were switched on or off.
+	MotorStateChanged {
+		/// True if the motors are now on.
+		enabled: bool,
+	},
+
+	/// The RAPID program was started or stopped.
+	RapidStateChanged {
+		/// True if the RAPID program is now running.
+		running: bool,
+	},
+
+	/// The controller entered the MCI error state.
+	///
+	/// The controller does not follow targets in this state.
+	MciError,
+
+	/// A message was received from the robot controller.
+	///
+	/// Only produced by [`SessionEvents`], after the events detected in the message.
+	Feedback(Box<EgmRobot>),
+
+	/// No message was received for the configured timeout.
+	///
+	/// Only produced by [`SessionEvents`].
+	Timeout {
+		/// The time since the last message, or since the stream was created if no message was received yet.
+		elapsed: Duration,
+	},
+}
+
+/// The reason a controller restart was detected.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RestartReason {
+	/// The sequence number jumped back further than could be explained by reordered messages.
+	SequenceNumberReset {
+		/// The sequence number of the previous message.
+		previous: u32,
+
+		/// The sequence number of the new message.
+		current: u32,
+	},
+
+	/// The feedback time jumped forward or backward by more than the configured limit.
+	TimestampJump {
+		/// The feedback time of the previous message.
+		previous: Duration,
+
+		/// The feedback time of the new message.
+		current: Duration,
+	},
+}
+
+/// Configuration for a [`SessionMonitor`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionConfig {
+	/// The largest jump in the feedback time that is not considered a restart.
+	pub max_time_jump: Duration,
+
+	/// The number of sequence numbers a message may be behind without being considered a restart.
+	///
+	/// UDP does not guarantee ordering, so a slightly older message is not necessarily a restart.
+	pub reorder_window: u32,
+}
+
+impl Default for SessionConfig {
+	fn default() -> Self {
+		Self {
+			max_time_jump: Duration::from_secs(1),
+			reorder_window: 16,
+		}
+	}
+}
+
+/// Monitor the messages from a robot controller to detect session events.
+///
+/// A controller restart is detected from a sequence number that is reset or a large jump in the feedback time.
+/// Motor and RAPID state changes are reported between two messages that both contain the state.
+/// Convergence is reported when the convergence flag becomes true, and an MCI error when the MCI state becomes `MciError`.
+#[derive(Clone, Debug, Default)]
+pub struct SessionMonitor {
+	config: SessionConfig,
+	sequence_number: Option<u32>,
+	time: Option<Duration>,
+	motors_enabled: Option<bool>,
+	rapid_running: Option<bool>,
+	mci_error: bool,
+	converged: bool,
+}
+
+impl SessionMonitor {
+	/// Create a new monitor.
+	pub fn new(config: SessionConfig) -> Self {
+		Self {
+			config,
+			..Self::default()
+		}
+	}
+
+	/// Get the configuration of the monitor.
+	pub fn config(&self) -> &SessionConfig {
+		&self.config
+	}
+
+	/// Forget all observed messages.
+	pub fn reset(&mut self) {
+		self.sequence_number = None;
+		self.time = None;
+		self.motors_enabled = None;
+		self.rapid_running = None;
+		self.mci_error = false;
+		self.converged = false;
+	}
+
+	/// Observe a message from the robot controller and return the detected events.
+	pub fn observe(&mut self, msg: &EgmRobot) -> Vec<SessionEvent> {
+		let mut events = Vec::new();
+		if let Some(reason) = self.detect_restart(msg) {
+			events.push(SessionEvent::ControllerRestarted { reason });
+			self.reset();
+		}
+
+		let sequence_number = msg.sequence_number();
+		let time = msg.feedback_time().map(|x| x.elapsed_since_epoch());
+		let newer = match (sequence_number, self.sequence_number) {
+			(Some(current), Some(previous)) => is_after(current, previous),
+			_ => true,
+		};
+		if !newer {
+			return events;
+		}
+		if let (Some(received), Some(previous)) = (sequence_number, self.sequence_number) {
+			let expected = previous.wrapping_add(1);
+			if received != expected {
+				events.push(SessionEvent::SequenceGap { expected, received });
+			}
+		}
+		self.sequence_number = sequence_number.or(self.sequence_number);
+		self.time = time.or(self.time);
+
+		if let Some(enabled) = msg.motors_enabled() {
+			if self.motors_enabled.replace(enabled) == Some(!enabled) {
+				events.push(SessionEvent::MotorStateChanged { enabled });
+			}
+		}
+		if let Some(running) = msg.rapid_running() {
+			if self.rapid_running.replace(running) == Some(!running) {
+				events.push(SessionEvent::RapidStateChanged { running });
+			}
+		}
+		if let Some(error) = msg.mci_error() {
+			if error && !self.mci_error {
+				events.push(SessionEvent::MciError);
+			}
+			self.mci_error = error;
+		}
+		if let Some(converged) = msg.mci_convergence_met {
+			if converged && !self.converged {
+				events.push(SessionEvent::ConvergenceMet);
+			}
+			self.converged = converged;
+		}
+		events
+	}
+
+	fn detect_restart(&self, msg: &EgmRobot) -> Option<RestartReason> {
+		if let (Some(current), Some(previous)) = (msg.sequence_number(), self.sequence_number) {
+			if !is_after(current, previous) && previous.wrapping_sub(current) > self.config.reorder_window {
+				return Some(RestartReason::SequenceNumberReset { previous, current });
+			}
+		}
+
+		if let (Some(current), Some(previous)) = (msg.feedback_time(), self.time) {
+			let current = current.elapsed_since_epoch();
+			let jump = current.max(previous) - current.min(previous);
+			if jump > self.config.max_time_jump {
+				return Some(RestartReason::TimestampJump { previous, current });
+			}
+		}
+
+		None
+	}
+}
+
+type StateCallback = Box<dyn Fn(bool) + Send + Sync>;
+
+/// Middleware that calls functions when the motor state or RAPID execution state changes.
+///
+/// Changes are detected between consecutive messages with a [`SessionMonitor`],
+/// so the callbacks are not called for the first message or for reordered messages.
+/// The callbacks are called from the task or thread that receives the messages, so they should return quickly.
+///
+/// Add it to the middleware stack of a peer, for example to hold a streamer when the operator stops the program:
+///
+/// ```
+/// # use abbegm::session::StateCallbacks;
+/// # use abbegm::streamer::HoldSignal;
+/// let hold = HoldSignal::new();
+/// let callbacks = StateCallbacks::new().with_hold_signal(hold.clone());
+/// ```
+#[derive(Default)]
+pub struct StateCallbacks {
+	monitor: std::sync::Mutex<SessionMonitor>,
+	on_motor_state: Vec<StateCallback>,
+	on_rapid_state: Vec<StateCallback>,
+}
+
+impl StateCallbacks {
+	/// Create a middleware without callbacks.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Add a function that is called with the new state when the motors are switched on or off.
+	pub fn with_motor_callback(mut self, callback: impl Fn(bool) + Send + Sync + 'static) -> Self {
+		self.on_motor_state.push(Box::new(callback));
+		self
+	}
+
+	/// Add a function that is called with the new state when the RAPID program is started or stopped.
+	pub fn with_rapid_callback(mut self, callback: impl Fn(bool) + Send + Sync + 'static) -> Self {
+		self.on_rapid_state.push(Box::new(callback));
+		self
+	}
+
+	/// Hold a streamer when the motors are switched off or the RAPID program is stopped.
+	///
+	/// The motion is not resumed automatically: call [`crate::streamer::HoldSignal::resume`] when the application is ready.
+	#[cfg(not(target_arch = "wasm32"))]
+	pub fn with_hold_signal(self, signal: crate::streamer::HoldSignal) -> Self {
+		let motors = signal.clone();
+		self.with_motor_callback(move |enabled| {
+			if !enabled {
+				motors.hold();
+			}
+		})
+		.with_rapid_callback(move |running| {
+			if !running {
+				signal.hold();
+			}
+		})
+	}
+}
+
+impl Middleware for StateCallbacks {
+	fn on_recv(&self, msg: &mut EgmRobot) -> Result<(), MessageRejectedError> {
+		let events = self.monitor.lock().unwrap_or_else(|e| e.into_inner()).observe(msg);
+		for event in events {
+			match event {
+				SessionEvent::MotorStateChanged { enabled } => self.on_motor_state.iter().for_each(|f| f(enabled)),
+				SessionEvent::RapidStateChanged { running } => self.on_rapid_state.iter().for_each(|f| f(running)),
+				_ => (),
+			}
+		}
+		Ok(())
+	}
+}
+
+impl std::fmt::Debug for StateCallbacks {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("StateCallbacks")
+			.field("on_motor_state", &self.on_motor_state.len())
+			.field("on_rapid_state", &self.on_rapid_state.len())
+			.finish()
+	}
+}
+
+/// Middleware that stops forwarding targets while the controller reports an MCI error.
+///
+/// When the MCI state of a received message is `MciError`, outgoing messages are rejected
+/// until a message reports that the error is gone, so the application gets a `Rejected` error instead of
+/// streaming targets that the controller ignores.
+/// Path correction messages are not passed through the middleware stack, so they are not affected.
+///
+/// Recovery functions are called once every time the controller enters the error state,
+/// for example to reset the error through the robot web services of the controller.
+///
+/// The guard can be cloned to keep a handle after adding it to the middleware stack of a peer.
+/// All clones share the error state.
+#[derive(Clone)]
+pub struct MciErrorGuard {
+	error: std::sync::Arc<std::sync::atomic::AtomicBool>,
+	reject_targets: bool,
+	recovery: Vec<std::sync::Arc<dyn Fn() + Send + Sync>>,
+}
+
+impl MciErrorGuard {
+	/// Create a guard that rejects targets while the controller reports an MCI error.
+	pub fn new() -> Self {
+		Self {
+			error: Default::default(),
+			reject_targets: true,
+			recovery: Vec::new(),
+		}
+	}
+
+	/// Set whether targets are rejected while the controller reports an MCI error.
+	///
+	/// Disable this to only track the error state and run the recovery functions.
+	pub fn with_reject_targets(mut self, reject_targets: bool) -> Self {
+		self.reject_targets = reject_targets;
+		self
+	}
+
+	/// Add a function that is called when the controller enters the MCI error state.
+	pub fn with_recovery(mut self, recovery: impl Fn() + Send + Sync + 'static) -> Self {
+		self.recovery.push(std::sync::Arc::new(recovery));
+		self
+	}
+
+	/// Check if the last message with an MCI state reported an error.
+	pub fn is_error(&self) -> bool {
+		self.error.load(std::sync::atomic::Ordering::Relaxed)
+	}
+}
+
+impl Default for MciErrorGuard {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Middleware for MciErrorGuard {
+	fn on_send(&self, _msg: &mut EgmSensor) -> Result<(), MessageRejectedError> {
+		if self.reject_targets && self.is_error() {
+			Err(MessageRejectedError::new("robot controller reports an MCI error"))
+		} else {
+			Ok(())
+		}
+	}
+
+	fn on_recv(&self, msg: &mut EgmRobot) -> Result<(), MessageRejectedError> {
+		if let Some(error) = msg.mci_error() {
+			let previous = self.error.swap(error, std::sync::atomic::Ordering::Relaxed);
+			if error && !previous {
+				log::error!("robot controller reports an MCI error");
+				self.recovery.iter().for_each(|f| f());
+			}
+		}
+		Ok(())
+	}
+}
+
+impl std::fmt::Debug for MciErrorGuard {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.debug_struct("MciErrorGuard")
+			.field("error", &self.is_error())
+			.field("reject_targets", &self.reject_targets)
+			.field("recovery", &self.recovery.len())
+			.finish()
+	}
+}
+
+/// Stream of session events from a connected asynchronous peer.
+///
+/// Every received message produces the events detected by a [`SessionMonitor`], followed by a [`SessionEvent::Feedback`] event.
+/// If no message is received for the timeout, a [`SessionEvent::Timeout`] event is produced, after which the stream keeps waiting.
+/// The timeout is measured with the `tokio` clock, so tests can use `tokio::time::pause()` to trigger it without waiting.
+/// This allows an application to drive all of its logic from a single loop:
+///
+/// ```no_run
+/// # async fn example(peer: abbegm::tokio_peer::ConnectedEgmPeer) {
+/// use abbegm::session::{SessionEvent, SessionEvents};
+/// use std::time::Duration;
+///
+/// let mut events = SessionEvents::new(peer, Duration::from_millis(100));
+/// while let Some(event) = events.next().await {
+///     match event {
+///         Ok(SessionEvent::Feedback(msg)) => { /* send the next target with events.peer_mut() */ },
+///         Ok(SessionEvent::RapidStateChanged { running: false }) => break,
+///         Ok(_) => (),
+///         Err(e) => eprintln!("{}", e),
+///     }
+/// }
+/// # }
+/// ```
+///
+/// [`SessionEvents::next`] is cancel safe, so it can be used in `tokio::select!`.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct SessionEvents<T = tokio::net::UdpSocket> {
+	peer: crate::tokio_peer::ConnectedEgmPeer<T>,
+	monitor: SessionMonitor,
+	timeout: Duration,
+	last_message: Instant,
+	pending: VecDeque<SessionEvent>,
+	finished: bool,
+}
+
+#[cfg(feature = "tokio")]
+impl<T: AsyncEgmTransport> SessionEvents<T> {
+	/// Create a stream of session events with the default session configuration.
+	pub fn new(peer: crate::tokio_peer::ConnectedEgmPeer<T>, timeout: Duration) -> Self {
+		Self::with_config(peer, timeout, SessionConfig::default())
+	}
+
+	/// Create a stream of session events with a custom session configuration.
+	pub fn with_config(peer: crate::tokio_peer::ConnectedEgmPeer<T>, timeout: Duration, config: SessionConfig) -> Self {
+		Self {
+			peer,
+			monitor: SessionMonitor::new(config),
+			timeout,
+			last_message: Instant::now(),
+			pending: VecDeque::new(),
+			finished: false,
+		}
+	}
+
+	/// Get a shared reference to the peer.
+	pub fn peer(&self) -> &crate::tokio_peer::ConnectedEgmPeer<T> {
+		&self.peer
+	}
+
+	/// Get an exclusive reference to the peer, for example to send targets.
+	pub fn peer_mut(&mut self) -> &mut crate::tokio_peer::ConnectedEgmPeer<T> {
+		&mut self.peer
+	}
+
+	/// Consume self and get the peer.
+	pub fn into_peer(self) -> crate::tokio_peer::ConnectedEgmPeer<T> {
+		self.peer
+	}
+
+	/// Wait for the next event.
+	///
+	/// Messages that fail to decode or are rejected by the middleware are returned as error, after which the stream continues.
+	/// An I/O error is returned once, after which the stream ends and `None` is returned.
+	pub async fn next(&mut self) -> Option<Result<SessionEvent, ReceiveError>> {
+		if let Some(event) = self.pending.pop_front() {
+			return Some(Ok(event));
+		}
+		if self.finished {
+			return None;
+		}
+
+		// The inner future is polled before the deadline, so queued messages are received even if the deadline passed.
+		let deadline = self.last_message + self.timeout;
+		let msg = match tokio::time::timeout_at(deadline, self.peer.recv()).await {
+			Ok(Ok(msg)) => msg,
+			Ok(Err(ReceiveError::Io(e))) => {
+				self.finished = true;
+				return Some(Err(ReceiveError::Io(e)));
+			},
+			Ok(Err(e)) => return Some(Err(e)),
+			Err(_) => {
+				let elapsed = self.last_message.elapsed();
+				self.last_message = Instant::now();
+				return Some(Ok(SessionEvent::Timeout { elapsed }));
+			},
+		};
+
+		self.last_message = Instant::now();
+		self.pending.extend(self.monitor.observe(&msg));
+		self.pending.push_back(SessionEvent::Feedback(Box::new(msg)));
+		self.pending.pop_front().map(Ok)
+	}
+}
+
+/// Check if a sequence number comes after another, taking wrap-around into account.
+fn is_after(current: u32, previous: u32) -> bool {
+	current != previous && current.wrapping_sub(previous) < 1 << 31
+}
+
+#[cfg(test)]
+#[test]
+fn test_restart_detection() {
+	use crate::msg::{EgmClock, EgmFeedBack, EgmHeader};
+	use assert2::assert;
+
+	let msg = |seqno: u32, sec: u64| EgmRobot {
+		header: Some(EgmHeader::data(seqno, 0)),
+		feed_back: Some(EgmFeedBack {
+			time: Some(EgmClock::new(sec, 0)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+
+	let mut monitor = SessionMonitor::default();
+	assert!(monitor.observe(&msg(100, 10)).is_empty());
+	assert!(monitor.observe(&msg(101, 10)).is_empty());
+
+	// Slightly reordered messages are not a restart.
+	assert!(monitor.observe(&msg(95, 10)).is_empty());
+
+	// Sequence numbers wrap around.
+	let mut wrapping = SessionMonitor::default();
+	assert!(wrapping.observe(&msg(u32::MAX, 10)).is_empty());
+	assert!(wrapping.observe(&msg(0, 10)).is_empty());
+
+	let events = monitor.observe(&msg(0, 10));
+	assert!(events == [SessionEvent::ControllerRestarted {
+		reason: RestartReason::SequenceNumberReset { previous: 101, current: 0 }
+	}]);
+	assert!(monitor.observe(&msg(1, 10)).is_empty());
+
+	let events = monitor.observe(&msg(2, 5));
+	assert!(events == [SessionEvent::ControllerRestarted {
+		reason: RestartReason::TimestampJump {
+			previous: Duration::from_secs(10),
+			current: Duration::from_secs(5),
+		}
+	}]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_state_events() {
+	use crate::msg::{EgmHeader, EgmMotorState, EgmRapidCtrlExecState};
+	use crate::msg::egm_motor_state::MotorStateType;
+	use crate::msg::egm_rapid_ctrl_exec_state::RapidCtrlExecStateType;
+	use assert2::assert;
+
+	let msg = |seqno: u32, motors: MotorStateType, rapid: RapidCtrlExecStateType, converged: bool| {
+		let mut msg = EgmRobot {
+			header: Some(EgmHeader::data(seqno, 0)),
+			motor_state: Some(EgmMotorState::default()),
+			rapid_exec_state: Some(EgmRapidCtrlExecState::default()),
+			mci_convergence_met: Some(converged),
+			..Default::default()
+		};
+		msg.motor_state.as_mut().unwrap().set_state(motors);
+		msg.rapid_exec_state.as_mut().unwrap().set_state(rapid);
+		msg
+	};
+
+	let mut monitor = SessionMonitor::default();
+	assert!(monitor.observe(&msg(1, MotorStateType::MotorsOff, RapidCtrlExecStateType::RapidStopped, false)).is_empty());
+	assert!(monitor.observe(&msg(2, MotorStateType::MotorsOn, RapidCtrlExecStateType::RapidStopped, false)) == [
+		SessionEvent::MotorStateChanged { enabled: true }
+	]);
+	assert!(monitor.observe(&msg(5, MotorStateType::MotorsOn, RapidCtrlExecStateType::RapidRunning, true)) == [
+		SessionEvent::SequenceGap { expected: 3, received: 5 },
+		SessionEvent::RapidStateChanged { running: true },
+		SessionEvent::ConvergenceMet,
+	]);
+	assert!(monitor.observe(&msg(6, MotorStateType::MotorsOn, RapidCtrlExecStateType::RapidRunning, true)).is_empty());
+	// Reordered messages do not produce events.
+	assert!(monitor.observe(&msg(4, MotorStateType::MotorsOff, RapidCtrlExecStateType::RapidStopped, false)).is_empty());
+
+	let hold = crate::streamer::HoldSignal::new();
+	let rapid = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+	let callbacks = StateCallbacks::new().with_hold_signal(hold.clone()).with_rapid_callback({
+		let rapid = rapid.clone();
+		move |running| rapid.lock().unwrap().push(running)
+	});
+	let mut messages = [
+		msg(1, MotorStateType::MotorsOn, RapidCtrlExecStateType::RapidStopped, false),
+		msg(2, MotorStateType::MotorsOn, RapidCtrlExecStateType::RapidRunning, false),
+		msg(3, MotorStateType::MotorsOn, RapidCtrlExecStateType::RapidRunning, false),
+	];
+	for msg in &mut messages {
+		callbacks.on_recv(msg).unwrap();
+	}
+	assert!(!hold.is_held());
+	callbacks.on_recv(&mut msg(4, MotorStateType::MotorsOff, RapidCtrlExecStateType::RapidRunning, false)).unwrap();
+	assert!(hold.is_held());
+	hold.resume();
+	callbacks.on_recv(&mut msg(5, MotorStateType::MotorsOff, RapidCtrlExecStateType::RapidStopped, false)).unwrap();
+	assert!(hold.is_held());
+	assert!(*rapid.lock().unwrap() == [true, false]);
+}
+
+#[cfg(all(test, feature = "tokio"))]
+#[tokio::test]
+async fn test_session_events() {
+	use crate::msg::EgmHeader;
+	use assert2::{assert, let_assert};
+	use prost::Message;
+
+	let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+	let robot = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+	socket.connect(robot.local_addr().unwrap()).await.unwrap();
+	let peer = crate::tokio_peer::ConnectedEgmPeer::new(socket).unwrap();
+	let address = peer.socket().local_addr().unwrap();
+	let mut events = SessionEvents::new(peer, Duration::from_millis(20));
+
+	let_assert!(Some(Ok(SessionEvent::Timeout { elapsed })) = events.next().await);
+	assert!(elapsed >= Duration::from_millis(20));
+
+	let message = |seqno| EgmRobot {
+		header: Some(EgmHeader::data(seqno, 0)),
+		..Default::default()
+	};
+	robot.send_to(&message(1).encode_to_vec(), address).await.unwrap();
+	robot.send_to(&[0xFF, 0xFF], address).await.unwrap();
+	robot.send_to(&message(3).encode_to_vec(), address).await.unwrap();
+
+	let_assert!(Some(Ok(SessionEvent::Feedback(msg))) = events.next().await);
+	assert!(*msg == message(1));
+	assert!(let Some(Err(ReceiveError::Decode(_))) = events.next().await);
+	let_assert!(Some(Ok(SessionEvent::SequenceGap { expected: 2, received: 3 })) = events.next().await);
+	let_assert!(Some(Ok(SessionEvent::Feedback(msg))) = events.next().await);
+	assert!(*msg == message(3));
+	assert!(let Some(Ok(SessionEvent::Timeout { .. })) = events.next().await);
+}
+
+#[cfg(test)]
+#[test]
+fn test_mci_error_guard() {
+	use crate::msg::egm_mci_state::MciStateType;
+	use crate::msg::{EgmClock, EgmMciState};
+	use crate::testing::MockRobot;
+	use assert2::assert;
+	use std::sync::atomic::{AtomicU32, Ordering};
+	use std::sync::Arc;
+
+	let msg = |state: MciStateType| {
+		let mut msg = EgmRobot {
+			mci_state: Some(EgmMciState::default()),
+			..Default::default()
+		};
+		msg.mci_state.as_mut().unwrap().set_state(state);
+		msg
+	};
+	let robot = MockRobot::with_responses(vec![msg(MciStateType::MciRunning), msg(MciStateType::MciError), msg(MciStateType::MciRunning)]);
+	let recoveries = Arc::new(AtomicU32::new(0));
+	let guard = MciErrorGuard::new().with_recovery({
+		let recoveries = recoveries.clone();
+		move || {
+			recoveries.fetch_add(1, Ordering::Relaxed);
+		}
+	});
+	let mut peer = crate::sync_peer::ConnectedEgmPeer::from_transport(robot);
+	peer.middleware_mut().push(guard.clone());
+	let target = crate::msg::EgmSensor::joint_target(0, vec![0.0; 6], EgmClock::new(0, 0));
+
+	let mut monitor = SessionMonitor::default();
+	assert!(monitor.observe(&peer.recv().unwrap()).is_empty());
+	assert!(let Ok(()) = peer.send(&target));
+
+	assert!(monitor.observe(&peer.recv().unwrap()) == [SessionEvent::MciError]);
+	assert!(guard.is_error());
+	assert!(recoveries.load(Ordering::Relaxed) == 1);
+	assert!(let Err(crate::SendError::Rejected(_)) = peer.send(&target));
+
+	assert!(monitor.observe(&peer.recv().unwrap()).is_empty());
+	assert!(!guard.is_error());
+	assert!(let Ok(()) = peer.send(&target));
+}
@@ -0,0 +1,239 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+use crate::middleware::Middleware;
+use crate::msg::EgmRobot;
+use crate::MessageRejectedError;
+
+/// A header timestamp this far behind the newest one is treated as a controller restart instead of a stale message.
+const RESTART_THRESHOLD_MS: u32 = 10_000;
+
+/// What to do with stale messages.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StaleAction {
+	/// Reject stale messages, so the peer returns a `Rejected` error instead of the message.
+	Reject,
+
+	/// Log a warning and count stale messages, but pass them to the application.
+	Flag,
+}
+
+#[derive(Debug, Default)]
+struct State {
+	newest: Option<u32>,
+	min_offset_ms: Option<i64>,
+	stale: u64,
+}
+
+/// Middleware that detects reordered and delayed messages from the robot controller using the header timestamp.
+///
+/// UDP datagrams can be reordered by the network, or sit in a socket buffer while the application is busy.
+/// Processing such a message as fresh state makes a control loop react to the past.
+///
+/// A message is stale if its header timestamp is older than the newest timestamp seen so far.
+/// With [`StaleFilter::with_max_age`], a message is also stale if it arrives more than the maximum age later than expected.
+/// The expected arrival time is derived from the lowest observed difference between the local clock and the header timestamps,
+/// so the age is only the delay on top of the normal latency.
+///
+/// Messages without a header timestamp are not checked.
+/// A timestamp far behind the newest one is treated as a controller restart, which resets the filter.
+///
+/// The filter can be cloned to keep a handle after adding it to the middleware stack of a peer.
+/// All clones share the same state.
+#[derive(Clone, Debug)]
+pub struct StaleFilter<C = SystemClock> {
+	action: StaleAction,
+	max_age: Option<Duration>,
+	clock: C,
+	epoch: Instant,
+	state: Arc<Mutex<State>>,
+}
+
+impl StaleFilter {
+	/// Create a filter that rejects messages older than the newest message.
+	pub fn new() -> Self {
+		Self::with_clock(SystemClock)
+	}
+}
+
+impl<C: Clock> StaleFilter<C> {
+	/// Create a filter with a custom clock to measure the arrival time of messages, such as a [`MockClock`](crate::clock::MockClock) in tests.
+	pub fn with_clock(clock: C) -> Self {
+		Self {
+			action: StaleAction::Reject,
+			max_age: None,
+			epoch: clock.now(),
+			clock,
+			state: Default::default(),
+		}
+	}
+
+	/// Set what to do with stale messages.
+	pub fn with_action(mut self, action: StaleAction) -> Self {
+		self.action = action;
+		self
+	}
+
+	/// Also treat messages as stale if they arrive more than `max_age` later than expected.
+	pub fn with_max_age(mut self, max_age: Duration) -> Self {
+		self.max_age = Some(max_age);
+		self
+	}
+
+	/// Get the number of stale messages detected so far.
+	pub fn stale_count(&self) -> u64 {
+		self.lock().stale
+	}
+
+	/// Forget the newest timestamp and the expected latency, for example after a controller restart.
+	pub fn reset(&self) {
+		let mut state = self.lock();
+		state.newest = None;
+		state.min_offset_ms = None;
+	}
+
+	fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+		self.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	/// Check a header timestamp, returning the reason if it is stale.
+	fn check(&self, timestamp: u32) -> Option<String> {
+		let now_ms = self.clock.now().saturating_duration_since(self.epoch).as_millis() as i64;
+		let mut state = self.lock();
+
+		if let Some(newest) = state.newest {
+			let behind = newest.wrapping_sub(timestamp);
+			if behind > RESTART_THRESHOLD_MS && behind < 1 << 31 {
+				log::info!("header timestamp jumped back {} ms, assuming the robot controller restarted", behind);
+				state.newest = None;
+				state.min_offset_ms = None;
+			} else if behind != 0 && behind < 1 << 31 {
+				state.stale += 1;
+				return Some(format!("message with timestamp {} ms is older than the newest message at {} ms", timestamp, newest));
+			}
+		}
+		state.newest = Some(timestamp);
+
+		// The offset is not unwrapped, so the latency estimate restarts when the timestamp wraps.
+		let offset = now_ms - i64::from(timestamp);
+		let min_offset = match state.min_offset_ms {
+			Some(min_offset) if (offset - min_offset).abs() < i64::from(RESTART_THRESHOLD_MS) => min_offset.min(offset),
+			_ => offset,
+		};
+		state.min_offset_ms = Some(min_offset);
+
+		let max_age = self.max_age?;
+		let age = Duration::from_millis((offset - min_offset) as u64);
+		if age > max_age {
+			state.stale += 1;
+			return Some(format!("message with timestamp {} ms arrived {:?} later than expected", timestamp, age));
+		}
+		None
+	}
+}
+
+impl Default for StaleFilter {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<C: Clock + Send + Sync> Middleware for StaleFilter<C> {
+	fn on_recv(&self, msg: &mut EgmRobot) -> Result<(), MessageRejectedError> {
+		let reason = match msg.timestamp_ms().and_then(|timestamp| self.check(timestamp)) {
+			Some(reason) => reason,
+			None => return Ok(()),
+		};
+		match self.action {
+			StaleAction::Reject => Err(MessageRejectedError::new(reason)),
+			StaleAction::Flag => {
+				log::warn!("stale feedback: {}", reason);
+				Ok(())
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_stale_filter() {
+	use crate::clock::MockClock;
+	use assert2::assert;
+
+	let clock = MockClock::new();
+	let filter = StaleFilter::with_clock(clock.clone());
+	assert!(filter.check(1000) == None);
+	clock.advance(Duration::from_millis(4));
+	assert!(filter.check(1008) == None);
+	assert!(let Some(_) = filter.check(1004));
+	assert!(filter.check(1008) == None);
+	assert!(filter.stale_count() == 1);
+
+	// A large jump back is a controller restart.
+	assert!(filter.check(30_000) == None);
+	assert!(filter.check(30_000 - RESTART_THRESHOLD_MS - 1) == None);
+	assert!(let Some(_) = filter.check(30_000 - RESTART_THRESHOLD_MS - 2));
+	assert!(filter.stale_count() == 2);
+
+	filter.reset();
+	assert!(filter.check(0) == None);
+}
+
+#[cfg(test)]
+#[test]
+fn test_stale_filter_max_age() {
+	use crate::clock::MockClock;
+	use assert2::assert;
+
+	let clock = MockClock::new();
+	let filter = StaleFilter::with_clock(clock.clone()).with_max_age(Duration::from_millis(10));
+	assert!(filter.check(1000) == None);
+
+	// A message that arrives exactly the maximum age later than expected is accepted.
+	clock.advance(Duration::from_millis(14));
+	assert!(filter.check(1004) == None);
+
+	// One millisecond more is too old.
+	clock.advance(Duration::from_millis(2));
+	assert!(let Some(_) = filter.check(1005));
+	assert!(filter.stale_count() == 1);
+
+	// The expected latency follows the fastest message.
+	assert!(filter.check(1016) == None);
+	clock.advance(Duration::from_millis(10));
+	assert!(filter.check(1016) == None);
+	clock.advance(Duration::from_millis(1));
+	assert!(let Some(_) = filter.check(1016));
+}
+
+#[cfg(test)]
+#[test]
+fn test_stale_filter_middleware() {
+	use crate::clock::MockClock;
+	use crate::msg::EgmHeader;
+	use assert2::assert;
+
+	let mut new = EgmRobot {
+		header: Some(EgmHeader::data(0, 1000)),
+		..Default::default()
+	};
+	let mut old = EgmRobot {
+		header: Some(EgmHeader::data(0, 996)),
+		..Default::default()
+	};
+
+	let filter = StaleFilter::with_clock(MockClock::new());
+	let handle = filter.clone();
+	assert!(let Ok(()) = filter.on_recv(&mut new));
+	assert!(let Err(_) = filter.on_recv(&mut old));
+	assert!(handle.stale_count() == 1);
+
+	// Messages without a timestamp are not checked.
+	assert!(let Ok(()) = filter.on_recv(&mut EgmRobot::default()));
+
+	let filter = StaleFilter::with_clock(MockClock::new()).with_action(StaleAction::Flag);
+	assert!(let Ok(()) = filter.on_recv(&mut new));
+	assert!(let Ok(()) = filter.on_recv(&mut old));
+	assert!(filter.stale_count() == 1);
+}
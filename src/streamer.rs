@@ -0,0 +1,1733 @@
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::msg::{EgmPose, EgmQuaternion, EgmRobot, EgmSensor};
+use crate::rate::LoopTimer;
+use crate::session::{SessionEvent, SessionMonitor};
+use crate::transport::EgmTransport;
+use crate::{ReceiveError, SendError};
+
+/// Limits and tolerances used by the motion primitives of a streamer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamerConfig {
+	/// The linear acceleration in mm/s² used for linear motions.
+	pub linear_acceleration: f64,
+
+	/// The maximum angular velocity of the tool in degrees/s used for linear motions.
+	pub angular_velocity: f64,
+
+	/// The angular acceleration of the tool in degrees/s² used for linear motions.
+	pub angular_acceleration: f64,
+
+	/// The joint acceleration in degrees/s² used for joint motions.
+	pub joint_acceleration: f64,
+
+	/// The maximum distance in mm between the feedback and target position for a linear motion to be finished.
+	pub position_tolerance: f64,
+
+	/// The maximum angle in degrees between the feedback and target orientation for a linear motion to be finished.
+	pub orientation_tolerance: f64,
+
+	/// The maximum difference in degrees between the feedback and target of each joint for a joint motion to be finished.
+	pub joint_tolerance: f64,
+
+	/// The time the robot is given to converge to the final target after the motion profile has finished.
+	pub convergence_timeout: Duration,
+
+	/// The time used to ramp the motion down to stand still when it is held, and back up when it is resumed.
+	pub hold_ramp_time: Duration,
+
+	/// The time used to blend from the running motion to a new goal set with a [`Retarget`] handle.
+	///
+	/// A zero blend time switches to the new goal without blending,
+	/// which is only smooth if the robot is standing still.
+	pub blend_time: Duration,
+
+	/// Slow down the motion when the robot controller reports a high utilization rate.
+	///
+	/// Set to `None` to ignore the utilization rate.
+	pub utilization_throttle: Option<UtilizationThrottle>,
+}
+
+impl Default for StreamerConfig {
+	fn default() -> Self {
+		Self {
+			linear_acceleration: 500.0,
+			angular_velocity: 90.0,
+			angular_acceleration: 180.0,
+			joint_acceleration: 90.0,
+			position_tolerance: 0.5,
+			orientation_tolerance: 0.5,
+			joint_tolerance: 0.1,
+			convergence_timeout: Duration::from_secs(2),
+			hold_ramp_time: Duration::from_millis(500),
+			blend_time: Duration::from_millis(200),
+			utilization_throttle: Some(UtilizationThrottle::default()),
+		}
+	}
+}
+
+/// Thresholds to slow down a motion when the robot controller is heavily loaded.
+///
+/// When the utilization rate reported by the robot controller exceeds the high threshold,
+/// the progress along the profile and the speed references are scaled down,
+/// until the utilization rate drops below the low threshold again.
+/// This prevents convergence failures when the controller can not keep up with the targets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UtilizationThrottle {
+	/// The utilization rate in percent above which the motion is slowed down.
+	pub high: f64,
+
+	/// The utilization rate in percent below which the motion returns to full speed.
+	pub low: f64,
+
+	/// The speed scale in the range `[0, 1]` applied while the motion is slowed down.
+	pub scale: f64,
+}
+
+impl Default for UtilizationThrottle {
+	fn default() -> Self {
+		Self {
+			high: 90.0,
+			low: 75.0,
+			scale: 0.5,
+		}
+	}
+}
+
+impl UtilizationThrottle {
+	/// Update the throttle state from the utilization rate of a message and get the speed scale to apply.
+	///
+	/// Messages without utilization rate leave the state unchanged.
+	fn update(&self, throttled: &mut bool, state: &EgmRobot) -> f64 {
+		if let Some(utilization_rate) = state.utilization_rate {
+			if !*throttled && utilization_rate > self.high {
+				log::info!("robot controller utilization rate is {:.0}%: slowing down motion", utilization_rate);
+				*throttled = true;
+			} else if *throttled && utilization_rate < self.low {
+				log::info!("robot controller utilization rate is {:.0}%: resuming full speed", utilization_rate);
+				*throttled = false;
+			}
+		}
+		if *throttled {
+			self.scale.clamp(0.0, 1.0)
+		} else {
+			1.0
+		}
+	}
+}
+
+/// Get the speed scale for the utilization rate of a message.
+fn utilization_scale(config: &StreamerConfig, throttled: &mut bool, state: &EgmRobot) -> f64 {
+	match &config.utilization_throttle {
+		Some(throttle) => throttle.update(throttled, state),
+		None => 1.0,
+	}
+}
+
+/// Runtime-adjustable speed override of a streamer, like the speed override on the FlexPendant.
+///
+/// The override scales the progress along the motion profile and all speed references sent to the robot controller.
+/// It is clamped to the range `[0, 1]`: 0 pauses the motion and 1 follows the planned profile.
+///
+/// The handle is cheap to clone and can be used to change the override from another thread while a motion is running.
+#[derive(Clone, Debug)]
+pub struct SpeedOverride {
+	value: Arc<AtomicU64>,
+}
+
+impl SpeedOverride {
+	/// Create a new speed override.
+	pub fn new(value: f64) -> Self {
+		let result = Self {
+			value: Arc::new(AtomicU64::new(0)),
+		};
+		result.set(value);
+		result
+	}
+
+	/// Get the current speed override.
+	pub fn get(&self) -> f64 {
+		f64::from_bits(self.value.load(Ordering::Relaxed))
+	}
+
+	/// Set the speed override.
+	///
+	/// The value is clamped to the range `[0, 1]`, and NaN is treated as 0.
+	pub fn set(&self, value: f64) {
+		let value = if value.is_nan() { 0.0 } else { value.clamp(0.0, 1.0) };
+		self.value.store(value.to_bits(), Ordering::Relaxed);
+	}
+}
+
+impl Default for SpeedOverride {
+	fn default() -> Self {
+		Self::new(1.0)
+	}
+}
+
+/// Handle to hold and resume the motion of a streamer, for example from another thread.
+///
+/// While held, the motion is ramped down to stand still along the path and the last target is repeated,
+/// without aborting the motion or the EGM session.
+/// When resumed, the motion ramps up again and continues along the planned path.
+#[derive(Clone, Debug, Default)]
+pub struct HoldSignal {
+	held: Arc<AtomicBool>,
+}
+
+impl HoldSignal {
+	/// Create a new signal that is not held.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Hold the motion.
+	pub fn hold(&self) {
+		self.held.store(true, Ordering::Relaxed);
+	}
+
+	/// Resume the motion.
+	pub fn resume(&self) {
+		self.held.store(false, Ordering::Relaxed);
+	}
+
+	/// Check if the motion is held.
+	pub fn is_held(&self) -> bool {
+		self.held.load(Ordering::Relaxed)
+	}
+}
+
+/// A new goal for a running motion.
+#[derive(Clone, Debug)]
+enum Goal {
+	Joint { joints: Vec<f64>, speed: f64 },
+	Linear { pose: EgmPose, speed: f64 },
+}
+
+/// Handle to change the goal of a running motion, for example from another thread.
+///
+/// The streamer blends from the running motion to a motion towards the new goal over the blend time of the configuration,
+/// so the commanded position and velocity stay continuous.
+/// The new goal must be of the same kind as the running motion: joint and pose targets can not be mixed.
+/// A goal of the wrong kind is ignored.
+///
+/// Goals only affect the running motion: a goal that is set while no motion is running is discarded when the next motion starts.
+#[derive(Clone, Debug, Default)]
+pub struct Retarget {
+	goal: Arc<Mutex<Option<Goal>>>,
+}
+
+impl Retarget {
+	/// Create a new handle without a goal.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Change the goal of a running joint motion to a target in degrees, with a maximum joint speed in degrees/s.
+	pub fn move_joint(&self, joints: impl Into<Vec<f64>>, speed: f64) {
+		self.set(Goal::Joint { joints: joints.into(), speed })
+	}
+
+	/// Change the goal of a running linear motion to a pose, with a maximum linear speed in mm/s.
+	pub fn move_linear(&self, pose: impl Into<EgmPose>, speed: f64) {
+		self.set(Goal::Linear { pose: pose.into(), speed })
+	}
+
+	fn set(&self, goal: Goal) {
+		*self.goal.lock().unwrap_or_else(|e| e.into_inner()) = Some(goal);
+	}
+
+	fn take(&self) -> Option<Goal> {
+		self.goal.lock().unwrap_or_else(|e| e.into_inner()).take()
+	}
+}
+
+/// Latched emergency stop of a streamer.
+///
+/// When tripped, the streamer immediately stops following the motion and streams targets
+/// that hold the position of the robot at the moment the stop was tripped.
+/// The stop stays tripped until it is explicitly cleared, even if the safety signal that tripped it is reset.
+/// The running motion then fails with [`MotionError::EmergencyStop`].
+/// Starting a new motion while the stop is tripped fails immediately with the same error, without receiving or sending any message.
+///
+/// This is a software layer of defense only, and does not replace the safety systems of the robot controller.
+#[derive(Clone, Debug, Default)]
+pub struct EmergencyStop {
+	tripped: Arc<AtomicBool>,
+}
+
+impl EmergencyStop {
+	/// Create a new emergency stop that is not tripped.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Trip the emergency stop.
+	pub fn trip(&self) {
+		if !self.tripped.swap(true, Ordering::Relaxed) {
+			log::warn!("emergency stop tripped: holding robot position");
+		}
+	}
+
+	/// Clear the emergency stop.
+	pub fn clear(&self) {
+		if self.tripped.swap(false, Ordering::Relaxed) {
+			log::info!("emergency stop cleared");
+		}
+	}
+
+	/// Check if the emergency stop is tripped.
+	pub fn is_tripped(&self) -> bool {
+		self.tripped.load(Ordering::Relaxed)
+	}
+}
+
+/// External safety signal that trips the emergency stop of a streamer when it returns true.
+struct SafetySignal(Box<dyn FnMut() -> bool + Send>);
+
+impl std::fmt::Debug for SafetySignal {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "SafetySignal(..)")
+	}
+}
+
+/// Poll the safety signal and check if the emergency stop is tripped.
+fn check_emergency_stop(signal: &mut Option<SafetySignal>, emergency_stop: &EmergencyStop) -> bool {
+	if let Some(signal) = signal {
+		if (signal.0)() {
+			emergency_stop.trip();
+		}
+	}
+	emergency_stop.is_tripped()
+}
+
+/// Error that may occur while executing a motion.
+#[derive(Debug)]
+pub enum MotionError {
+	/// Receiving a message from the robot controller failed.
+	Receive(ReceiveError),
+
+	/// Sending a message to the robot controller failed.
+	Send(SendError),
+
+	/// The feedback from the robot controller is missing a field needed for the motion.
+	MissingFeedback {
+		/// The path of the missing field, such as `feedBack.cartesian`.
+		field: &'static str,
+	},
+
+	/// The target pose is missing a position or an orientation.
+	IncompleteTarget {
+		/// The name of the missing field, such as `pos`.
+		field: &'static str,
+	},
+
+	/// The number of target joints does not match the number of joints in the feedback.
+	JointCountMismatch {
+		/// The number of joints in the feedback.
+		expected: usize,
+
+		/// The number of joints in the target.
+		actual: usize,
+	},
+
+	/// The requested speed is not a positive number.
+	InvalidSpeed {
+		/// The requested speed.
+		speed: f64,
+	},
+
+	/// The robot did not converge to the target within the convergence timeout.
+	ConvergenceTimeout,
+
+	/// The robot controller was restarted during the motion.
+	ControllerRestarted,
+
+	/// The emergency stop was tripped before or during the motion.
+	EmergencyStop,
+}
+
+/// Trapezoidal velocity profile for a motion over a fixed distance.
+///
+/// The profile accelerates with a constant acceleration up to the maximum velocity,
+/// moves at a constant velocity and decelerates again to stand still at the end.
+/// If the distance is too short to reach the maximum velocity, the profile is triangular instead.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrapezoidalProfile {
+	distance: f64,
+	velocity: f64,
+	acceleration: f64,
+	acceleration_time: f64,
+	cruise_time: f64,
+}
+
+impl TrapezoidalProfile {
+	/// Create a new profile for a distance, maximum velocity and acceleration.
+	///
+	/// The velocity and acceleration must be positive.
+	pub fn new(distance: f64, max_velocity: f64, acceleration: f64) -> Self {
+		let distance = distance.abs();
+		if distance == 0.0 {
+			return Self {
+				distance,
+				velocity: 0.0,
+				acceleration,
+				acceleration_time: 0.0,
+				cruise_time: 0.0,
+			};
+		}
+
+		let acceleration_time = max_velocity / acceleration;
+		let acceleration_distance = 0.5 * acceleration * acceleration_time * acceleration_time;
+		if 2.0 * acceleration_distance >= distance {
+			let acceleration_time = (distance / acceleration).sqrt();
+			Self {
+				distance,
+				velocity: acceleration * acceleration_time,
+				acceleration,
+				acceleration_time,
+				cruise_time: 0.0,
+			}
+		} else {
+			Self {
+				distance,
+				velocity: max_velocity,
+				acceleration,
+				acceleration_time,
+				cruise_time: (distance - 2.0 * acceleration_distance) / max_velocity,
+			}
+		}
+	}
+
+	/// Get the total distance of the profile.
+	pub fn distance(&self) -> f64 {
+		self.distance
+	}
+
+	/// Get the peak velocity of the profile.
+	pub fn peak_velocity(&self) -> f64 {
+		self.velocity
+	}
+
+	/// Get the total duration of the profile in seconds.
+	pub fn duration(&self) -> f64 {
+		2.0 * self.acceleration_time + self.cruise_time
+	}
+
+	/// Get the distance travelled at a time in seconds since the start of the profile.
+	pub fn position(&self, time: f64) -> f64 {
+		let time = time.clamp(0.0, self.duration());
+		let cruise_end = self.acceleration_time + self.cruise_time;
+		if time < self.acceleration_time {
+			0.5 * self.acceleration * time * time
+		} else if time < cruise_end {
+			0.5 * self.acceleration * self.acceleration_time * self.acceleration_time + self.velocity * (time - self.acceleration_time)
+		} else {
+			let remaining = self.duration() - time;
+			self.distance - 0.5 * self.acceleration * remaining * remaining
+		}
+	}
+
+	/// Get the velocity at a time in seconds since the start of the profile.
+	pub fn velocity(&self, time: f64) -> f64 {
+		let cruise_end = self.acceleration_time + self.cruise_time;
+		if time <= 0.0 || time >= self.duration() {
+			0.0
+		} else if time < self.acceleration_time {
+			self.acceleration * time
+		} else if time < cruise_end {
+			self.velocity
+		} else {
+			self.acceleration * (self.duration() - time)
+		}
+	}
+
+	/// Get the fraction of the distance travelled at a time in seconds since the start of the profile.
+	///
+	/// The fraction is 1 for a profile with zero distance.
+	pub fn fraction(&self, time: f64) -> f64 {
+		if self.distance == 0.0 {
+			1.0
+		} else {
+			self.position(time) / self.distance
+		}
+	}
+}
+
+/// The path of a motion.
+#[derive(Clone, Debug)]
+enum Path {
+	Linear { start: EgmPose, end: EgmPose },
+	Joint { start: Vec<f64>, end: Vec<f64> },
+}
+
+/// A planned motion, started from the first feedback message.
+#[derive(Clone, Debug)]
+struct Motion {
+	path: Path,
+	profile: TrapezoidalProfile,
+	/// The feedback time of the previous step.
+	last_time: Duration,
+	/// The time in seconds along the profile, scaled by the speed override.
+	profile_time: f64,
+	/// The feedback time at which the end of the profile was reached.
+	end_time: Option<Duration>,
+	/// The scale applied to the speed while ramping down for a hold or up after resuming.
+	hold_scale: f64,
+	/// Extra time in seconds to hold the end of the path after the profile finished.
+	dwell: f64,
+	/// The distance along the profile before the start of the path, used to start the motion with an initial velocity.
+	offset: f64,
+	/// The blend from a previous motion, if the goal was changed during that motion.
+	blend: Option<Blend>,
+	end_sent: bool,
+}
+
+/// A blend from a previous motion to the current motion.
+#[derive(Clone, Debug)]
+struct Blend {
+	previous: Box<Motion>,
+	/// The time in seconds since the start of the blend, scaled by the speed override.
+	elapsed: f64,
+	duration: f64,
+}
+
+/// A commanded point on a path, with its velocity.
+#[derive(Clone, Debug, PartialEq)]
+enum PathPoint {
+	Joint {
+		position: Vec<f64>,
+		velocity: Vec<f64>,
+	},
+	Pose {
+		position: [f64; 3],
+		orientation: EgmQuaternion,
+		velocity: [f64; 3],
+	},
+}
+
+impl PathPoint {
+	/// Blend from this point to another point with a weight and the rate of change of the weight.
+	///
+	/// Points of a different kind can not be blended, so the other point is returned unmodified.
+	fn blend(self, to: Self, weight: f64, weight_rate: f64) -> Self {
+		let mix = |a: f64, b: f64| a + (b - a) * weight;
+		let mix_velocity = |a: f64, b: f64, va: f64, vb: f64| va + (vb - va) * weight + (b - a) * weight_rate;
+		match (self, to) {
+			(Self::Joint { position: pa, velocity: va }, Self::Joint { position: pb, velocity: vb }) if pa.len() == pb.len() => Self::Joint {
+				position: pa.iter().zip(&pb).map(|(a, b)| mix(*a, *b)).collect(),
+				velocity: (0..pa.len()).map(|i| mix_velocity(pa[i], pb[i], va[i], vb[i])).collect(),
+			},
+			(
+				Self::Pose {
+					position: pa,
+					orientation: oa,
+					velocity: va,
+				},
+				Self::Pose {
+					position: pb,
+					orientation: ob,
+					velocity: vb,
+				},
+			) => Self::Pose {
+				position: [0, 1, 2].map(|i| mix(pa[i], pb[i])),
+				orientation: oa.slerp(&ob, weight),
+				velocity: [0, 1, 2].map(|i| mix_velocity(pa[i], pb[i], va[i], vb[i])),
+			},
+			(_, to) => to,
+		}
+	}
+
+	/// Create a target message for the point.
+	fn into_target(self, sequence_number: u32, time: crate::msg::EgmClock) -> EgmSensor {
+		match self {
+			Self::Joint { position, velocity } => EgmSensor::joint_target_with_speed(sequence_number, position, velocity, time),
+			Self::Pose {
+				position,
+				orientation,
+				velocity,
+			} => EgmSensor::pose_target_with_speed(sequence_number, EgmPose::new(position, orientation), velocity, time),
+		}
+	}
+}
+
+fn check_speed(speed: f64) -> Result<(), MotionError> {
+	if speed > 0.0 && speed.is_finite() {
+		Ok(())
+	} else {
+		Err(MotionError::InvalidSpeed { speed })
+	}
+}
+
+/// Abort the motion if the robot controller was restarted.
+fn check_restart(session: &mut SessionMonitor, state: &EgmRobot) -> Result<(), MotionError> {
+	let events = session.observe(state);
+	if events.iter().any(|event| matches!(event, SessionEvent::ControllerRestarted { .. })) {
+		Err(MotionError::ControllerRestarted)
+	} else {
+		Ok(())
+	}
+}
+
+fn feedback_time(state: &EgmRobot) -> Result<Duration, MotionError> {
+	let time = state.feedback_time().ok_or(MotionError::MissingFeedback { field: "feedBack.time" })?;
+	Ok(time.elapsed_since_epoch())
+}
+
+/// Get a pose with both a position and a quaternion orientation, converting Euler angles if needed.
+///
+/// Returns the name of the missing field if the pose has no position or no orientation at all.
+fn complete_pose(pose: &EgmPose) -> Result<EgmPose, &'static str> {
+	let position = pose.pos.ok_or("pos")?;
+	let orientation = pose.orient.or_else(|| pose.euler.map(|x| x.to_quaternion())).ok_or("orient")?;
+	Ok(EgmPose::new(position, orientation))
+}
+
+/// Get the feedback pose with both a position and a quaternion orientation.
+fn feedback_pose(state: &EgmRobot) -> Result<EgmPose, MotionError> {
+	let pose = state.feedback_pose().ok_or(MotionError::MissingFeedback { field: "feedBack.cartesian" })?;
+	complete_pose(pose).map_err(|field| MotionError::MissingFeedback {
+		field: if field == "pos" { "feedBack.cartesian.pos" } else { "feedBack.cartesian.orient" },
+	})
+}
+
+fn distance(a: &EgmPose, b: &EgmPose) -> f64 {
+	crate::math::position_distance(a.pos.unwrap_or_default(), b.pos.unwrap_or_default())
+}
+
+fn angle(a: &EgmPose, b: &EgmPose) -> f64 {
+	crate::math::orientation_angle(a.orient.unwrap_or(EgmQuaternion::IDENTITY), b.orient.unwrap_or(EgmQuaternion::IDENTITY))
+}
+
+impl Motion {
+	fn new(path: Path, profile: TrapezoidalProfile, last_time: Duration) -> Self {
+		Self {
+			path,
+			profile,
+			last_time,
+			profile_time: 0.0,
+			end_time: None,
+			hold_scale: 1.0,
+			dwell: 0.0,
+			offset: 0.0,
+			blend: None,
+			end_sent: false,
+		}
+	}
+
+	fn linear(state: &EgmRobot, end: EgmPose, speed: f64, config: &StreamerConfig) -> Result<Self, MotionError> {
+		Self::linear_from(feedback_pose(state)?, end, speed, config, feedback_time(state)?)
+	}
+
+	fn linear_from(start: EgmPose, end: EgmPose, speed: f64, config: &StreamerConfig, time: Duration) -> Result<Self, MotionError> {
+		check_speed(speed)?;
+		let end = complete_pose(&end).map_err(|field| MotionError::IncompleteTarget { field })?;
+		let linear = TrapezoidalProfile::new(distance(&start, &end), speed, config.linear_acceleration);
+		let angular = TrapezoidalProfile::new(angle(&start, &end), config.angular_velocity, config.angular_acceleration);
+		let profile = if angular.duration() > linear.duration() { angular } else { linear };
+		Ok(Self::new(Path::Linear { start, end }, profile, time))
+	}
+
+	fn joint(state: &EgmRobot, end: Vec<f64>, speed: f64, config: &StreamerConfig) -> Result<Self, MotionError> {
+		let start = state.feedback_joints().cloned().ok_or(MotionError::MissingFeedback { field: "feedBack.joints" })?;
+		Self::joint_from(start, end, speed, config, feedback_time(state)?)
+	}
+
+	fn joint_from(start: Vec<f64>, end: Vec<f64>, speed: f64, config: &StreamerConfig, time: Duration) -> Result<Self, MotionError> {
+		check_speed(speed)?;
+		if start.len() != end.len() {
+			return Err(MotionError::JointCountMismatch {
+				expected: start.len(),
+				actual: end.len(),
+			});
+		}
+
+		// All joints follow the profile of the joint with the largest distance, so they start and stop together.
+		let largest = start.iter().zip(&end).map(|(a, b)| (b - a).abs()).fold(0.0, f64::max);
+		let profile = TrapezoidalProfile::new(largest, speed, config.joint_acceleration);
+		Ok(Self::new(Path::Joint { start, end }, profile, time))
+	}
+
+	/// Change the goal of the motion, blending from the current motion to a motion towards the new goal.
+	///
+	/// Goals of a different kind than the current motion are ignored.
+	fn retarget(self, goal: Goal, config: &StreamerConfig) -> Result<Self, MotionError> {
+		let current = self.point(1.0);
+		let mut next = match (goal, &current) {
+			(Goal::Joint { joints, speed }, PathPoint::Joint { position, .. }) => Self::joint_from(position.clone(), joints, speed, config, self.last_time)?,
+			(Goal::Linear { pose, speed }, PathPoint::Pose { position, orientation, .. }) => {
+				Self::linear_from(EgmPose::new(*position, *orientation), pose, speed, config, self.last_time)?
+			},
+			_ => {
+				log::warn!("ignoring new goal: joint and pose targets can not be mixed in one motion");
+				return Ok(self);
+			},
+		};
+		next.hold_scale = self.hold_scale;
+		next.match_velocity(&current);
+		if !config.blend_time.is_zero() {
+			next.blend = Some(Blend {
+				previous: Box::new(self),
+				elapsed: 0.0,
+				duration: config.blend_time.as_secs_f64(),
+			});
+		}
+		Ok(next)
+	}
+
+	/// Start the profile with the component of a velocity along the path, so the motion does not have to start from rest.
+	///
+	/// The profile is extended backwards to the point where it would have started from rest.
+	/// The velocity is limited so the motion can still stop at the end of the path.
+	fn match_velocity(&mut self, current: &PathPoint) {
+		let distance = self.profile.distance();
+		if distance == 0.0 {
+			return;
+		}
+		let rate = match (&self.path, current) {
+			(Path::Joint { start, end }, PathPoint::Joint { velocity, .. }) => {
+				let delta: Vec<f64> = start.iter().zip(end).map(|(a, b)| b - a).collect();
+				let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+				dot(velocity, &delta) / dot(&delta, &delta)
+			},
+			(Path::Linear { start, end }, PathPoint::Pose { velocity, .. }) => {
+				let a = start.pos.unwrap_or_default().as_mm();
+				let b = end.pos.unwrap_or_default().as_mm();
+				let delta = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+				let length = delta.iter().map(|x| x * x).sum::<f64>();
+				if length == 0.0 {
+					return;
+				}
+				delta.iter().zip(velocity).map(|(a, b)| a * b).sum::<f64>() / length
+			},
+			_ => return,
+		};
+
+		let acceleration = self.profile.acceleration;
+		let peak = self.profile.peak_velocity();
+		let velocity = (rate * distance).min(peak).min((2.0 * acceleration * distance).sqrt());
+		if velocity.is_nan() || velocity <= 0.0 {
+			return;
+		}
+		let time = velocity / acceleration;
+		let offset = 0.5 * acceleration * time * time;
+		self.profile = TrapezoidalProfile::new(distance + offset, peak, acceleration);
+		self.profile_time = time;
+		self.offset = offset;
+	}
+
+	/// Hold the feedback position for a duration, using joint or pose targets.
+	fn dwell(state: &EgmRobot, linear: bool, duration: Duration) -> Result<Self, MotionError> {
+		let path = if linear {
+			let pose = feedback_pose(state)?;
+			Path::Linear { start: pose.clone(), end: pose }
+		} else {
+			let joints = state.feedback_joints().cloned().ok_or(MotionError::MissingFeedback { field: "feedBack.joints" })?;
+			Path::Joint { start: joints.clone(), end: joints }
+		};
+		let mut motion = Self::new(path, TrapezoidalProfile::new(0.0, 1.0, 1.0), feedback_time(state)?);
+		motion.dwell = duration.as_secs_f64();
+		Ok(motion)
+	}
+
+	/// Create a target that holds the feedback position of a previous message.
+	fn hold_target(&self, hold: &EgmRobot, state: &EgmRobot, sequence_number: u32) -> Result<EgmSensor, MotionError> {
+		let time = state.feedback_time().ok_or(MotionError::MissingFeedback { field: "feedBack.time" })?;
+		match &self.path {
+			Path::Linear { .. } => {
+				Ok(EgmSensor::pose_target(sequence_number, feedback_pose(hold)?, time))
+			},
+			Path::Joint { .. } => {
+				let joints = hold.feedback_joints().cloned().ok_or(MotionError::MissingFeedback { field: "feedBack.joints" })?;
+				Ok(EgmSensor::joint_target(sequence_number, joints, time))
+			},
+		}
+	}
+
+	/// Check if the feedback has converged to the end of the path.
+	fn converged(&self, state: &EgmRobot, config: &StreamerConfig) -> bool {
+		match &self.path {
+			Path::Linear { end, .. } => match feedback_pose(state) {
+				Ok(pose) => distance(&pose, end) <= config.position_tolerance && angle(&pose, end) <= config.orientation_tolerance,
+				Err(_) => false,
+			},
+			Path::Joint { end, .. } => match state.feedback_joints() {
+				Some(joints) => joints.len() == end.len() && joints.iter().zip(end).all(|(a, b)| (a - b).abs() <= config.joint_tolerance),
+				None => false,
+			},
+		}
+	}
+
+	/// Determine the next target to send after receiving a message from the robot.
+	///
+	/// The progress along the profile and the speed references are scaled by the speed override,
+	/// and ramped down to zero while the motion is held.
+	/// Returns `None` when the robot has converged to the end of the path.
+	fn step(&mut self, state: &EgmRobot, sequence_number: u32, config: &StreamerConfig, speed_override: f64, held: bool) -> Result<Option<EgmSensor>, MotionError> {
+		let time = state.feedback_time().ok_or(MotionError::MissingFeedback { field: "feedBack.time" })?;
+		let now = time.elapsed_since_epoch();
+		let elapsed = now.checked_sub(self.last_time).unwrap_or_default();
+		self.last_time = now;
+
+		let ramp = if config.hold_ramp_time.is_zero() {
+			1.0
+		} else {
+			elapsed.as_secs_f64() / config.hold_ramp_time.as_secs_f64()
+		};
+		self.hold_scale = if held { self.hold_scale - ramp } else { self.hold_scale + ramp }.clamp(0.0, 1.0);
+		let speed_override = speed_override * self.hold_scale;
+		self.advance(elapsed.as_secs_f64(), speed_override);
+
+		if self.blend.is_none() && self.profile_time >= self.end() {
+			let end_time = *self.end_time.get_or_insert(now);
+			if self.end_sent && self.converged(state, config) {
+				return Ok(None);
+			} else if !held && now > end_time + config.convergence_timeout {
+				return Err(MotionError::ConvergenceTimeout);
+			}
+		}
+
+		self.end_sent |= self.end_time.is_some();
+		Ok(Some(self.point(speed_override).into_target(sequence_number, time)))
+	}
+
+	/// Get the time in seconds along the profile at which the motion ends.
+	fn end(&self) -> f64 {
+		self.profile.duration() + self.dwell
+	}
+
+	/// Advance the motion and the blend from the previous motion by an amount of time in seconds.
+	fn advance(&mut self, elapsed: f64, speed_override: f64) {
+		self.profile_time = (self.profile_time + elapsed * speed_override).min(self.end());
+		if let Some(blend) = &mut self.blend {
+			blend.previous.advance(elapsed, speed_override);
+			blend.elapsed += elapsed * speed_override;
+			if blend.elapsed >= blend.duration {
+				self.blend = None;
+			}
+		}
+	}
+
+	/// Get the commanded point at the current time along the profile.
+	fn point(&self, speed_override: f64) -> PathPoint {
+		// The fraction of the path and its rate of change, used to derive the speed references for all axes.
+		let length = self.profile.distance() - self.offset;
+		let (fraction, fraction_rate) = if length <= 0.0 {
+			(1.0, 0.0)
+		} else {
+			let position = self.profile.position(self.profile_time) - self.offset;
+			(position / length, self.profile.velocity(self.profile_time) * speed_override / length)
+		};
+		let point = match &self.path {
+			Path::Linear { start, end } => {
+				let a = start.pos.unwrap_or_default().as_mm();
+				let b = end.pos.unwrap_or_default().as_mm();
+				let qa = start.orient.unwrap_or(EgmQuaternion::IDENTITY);
+				let qb = end.orient.unwrap_or(EgmQuaternion::IDENTITY);
+				PathPoint::Pose {
+					position: [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * fraction),
+					orientation: qa.slerp(&qb, fraction),
+					velocity: [0, 1, 2].map(|i| (b[i] - a[i]) * fraction_rate),
+				}
+			},
+			Path::Joint { start, end } => PathPoint::Joint {
+				position: start.iter().zip(end).map(|(a, b)| a + (b - a) * fraction).collect(),
+				velocity: start.iter().zip(end).map(|(a, b)| (b - a) * fraction_rate).collect(),
+			},
+		};
+
+		match &self.blend {
+			None => point,
+			Some(blend) => {
+				// Smoothstep weight, so the velocity is continuous at the start and end of the blend.
+				let s = (blend.elapsed / blend.duration).clamp(0.0, 1.0);
+				let weight = s * s * (3.0 - 2.0 * s);
+				let weight_rate = 6.0 * s * (1.0 - s) / blend.duration * speed_override;
+				blend.previous.point(speed_override).blend(point, weight, weight_rate)
+			},
+		}
+	}
+}
+
+/// A motion that is being executed by a [`StreamerCore`].
+#[derive(Debug)]
+struct Run {
+	motion: Motion,
+	session: SessionMonitor,
+	/// The message received when the emergency stop was tripped, if it was.
+	frozen: Option<EgmRobot>,
+}
+
+/// The per-cycle state machine of a streamer, without any I/O.
+///
+/// The core plans a motion from the first received message and determines the target to send for every following message.
+/// [`Streamer`] and [`AsyncStreamer`] only receive the messages and send the targets.
+///
+/// The core also holds the configuration, speed override, hold signal and emergency stop of the streamer.
+#[derive(Debug)]
+pub struct StreamerCore {
+	config: StreamerConfig,
+	speed_override: SpeedOverride,
+	hold: HoldSignal,
+	emergency_stop: EmergencyStop,
+	safety_signal: Option<SafetySignal>,
+	throttled: bool,
+	retarget: Retarget,
+	sequence_number: u32,
+	loop_timer: LoopTimer,
+	run: Option<Run>,
+}
+
+impl StreamerCore {
+	/// Create a new core with a configuration.
+	pub fn new(config: StreamerConfig) -> Self {
+		Self {
+			config,
+			speed_override: SpeedOverride::default(),
+			hold: HoldSignal::new(),
+			emergency_stop: EmergencyStop::new(),
+			safety_signal: None,
+			throttled: false,
+			retarget: Retarget::new(),
+			sequence_number: 0,
+			loop_timer: LoopTimer::default().with_warnings(false),
+			run: None,
+		}
+	}
+
+	/// Get the configuration of the streamer.
+	pub fn config(&self) -> &StreamerConfig {
+		&self.config
+	}
+
+	/// Get an exclusive reference to the configuration of the streamer.
+	pub fn config_mut(&mut self) -> &mut StreamerConfig {
+		&mut self.config
+	}
+
+	/// Get the timer that measures the time between receiving feedback and sending the next target.
+	///
+	/// The budget of the timer follows the sample time measured by the peer of the streamer,
+	/// so [`LoopTimerStats::overruns`](crate::rate::LoopTimerStats::overruns) counts the targets that were sent too late.
+	pub fn loop_timer(&self) -> &LoopTimer {
+		&self.loop_timer
+	}
+
+	/// Get an exclusive reference to the loop timer, for example to reset it.
+	pub fn loop_timer_mut(&mut self) -> &mut LoopTimer {
+		&mut self.loop_timer
+	}
+
+	/// Get the current speed override.
+	pub fn speed_override(&self) -> f64 {
+		self.speed_override.get()
+	}
+
+	/// Set the speed override, clamped to the range `[0, 1]`.
+	///
+	/// The new value is applied from the next target sent to the robot controller.
+	pub fn set_speed_override(&self, value: f64) {
+		self.speed_override.set(value)
+	}
+
+	/// Get a handle to change the speed override while a motion is running.
+	pub fn speed_override_handle(&self) -> SpeedOverride {
+		self.speed_override.clone()
+	}
+
+	/// Hold the motion: ramp down to stand still and keep sending the last target.
+	///
+	/// The motion is not aborted and continues after [`Self::resume`].
+	pub fn hold(&self) {
+		self.hold.hold()
+	}
+
+	/// Resume a held motion.
+	pub fn resume(&self) {
+		self.hold.resume()
+	}
+
+	/// Check if the motion is held.
+	pub fn is_held(&self) -> bool {
+		self.hold.is_held()
+	}
+
+	/// Get a handle to hold and resume the motion while it is running.
+	pub fn hold_handle(&self) -> HoldSignal {
+		self.hold.clone()
+	}
+
+	/// Get a handle to change the goal of a running motion.
+	pub fn retarget_handle(&self) -> Retarget {
+		self.retarget.clone()
+	}
+
+	/// Check if the motion is slowed down because of a high utilization rate of the robot controller.
+	pub fn is_throttled(&self) -> bool {
+		self.throttled
+	}
+
+	/// Get a handle to the emergency stop of the streamer, to trip or clear it from another thread.
+	pub fn emergency_stop(&self) -> EmergencyStop {
+		self.emergency_stop.clone()
+	}
+
+	/// Set a safety signal that trips the emergency stop when it returns true.
+	///
+	/// The signal is polled every time a message is received from the robot controller.
+	/// The emergency stop stays tripped until it is cleared with [`EmergencyStop::clear`].
+	pub fn set_safety_signal(&mut self, signal: impl FnMut() -> bool + Send + 'static) {
+		self.safety_signal = Some(SafetySignal(Box::new(signal)));
+	}
+
+	/// Use a watch channel as safety signal: the emergency stop is tripped when the value is true.
+	///
+	/// See [`Self::set_safety_signal`].
+	#[cfg(feature = "tokio")]
+	pub fn set_safety_watch(&mut self, signal: tokio::sync::watch::Receiver<bool>) {
+		self.set_safety_signal(move || *signal.borrow())
+	}
+
+	/// Refuse to start a motion while the emergency stop is tripped.
+	fn check_emergency_stop(&mut self) -> Result<(), MotionError> {
+		if check_emergency_stop(&mut self.safety_signal, &self.emergency_stop) {
+			Err(MotionError::EmergencyStop)
+		} else {
+			Ok(())
+		}
+	}
+
+	/// Plan a new motion from the first message received from the robot controller.
+	///
+	/// Any goal set with a [`Retarget`] handle before the motion starts is discarded.
+	fn start(&mut self, state: &EgmRobot, plan: impl FnOnce(&EgmRobot, &StreamerConfig) -> Result<Motion, MotionError>) -> Result<(), MotionError> {
+		let motion = plan(state, &self.config)?;
+		self.retarget.take();
+		self.run = Some(Run {
+			motion,
+			session: SessionMonitor::default(),
+			frozen: None,
+		});
+		Ok(())
+	}
+
+	/// Determine the next target to send for a message received from the robot controller.
+	///
+	/// Returns `None` when the robot has converged to the end of the motion, or when no motion is running.
+	fn next_target(&mut self, state: &EgmRobot) -> Result<Option<EgmSensor>, MotionError> {
+		// The run is only put back while the motion continues, so errors and convergence end it.
+		let mut run = match self.run.take() {
+			Some(run) => run,
+			None => return Ok(None),
+		};
+		check_restart(&mut run.session, state)?;
+		let target = if check_emergency_stop(&mut self.safety_signal, &self.emergency_stop) {
+			let hold = run.frozen.get_or_insert_with(|| state.clone());
+			Some(run.motion.hold_target(hold, state, self.sequence_number)?)
+		} else if run.frozen.is_some() {
+			return Err(MotionError::EmergencyStop);
+		} else {
+			if let Some(goal) = self.retarget.take() {
+				run.motion = run.motion.retarget(goal, &self.config)?;
+			}
+			let speed_override = self.speed_override.get() * utilization_scale(&self.config, &mut self.throttled, state);
+			run.motion.step(state, self.sequence_number, &self.config, speed_override, self.hold.is_held())?
+		};
+		if target.is_some() {
+			self.run = Some(run);
+		}
+		Ok(target)
+	}
+
+	/// Record that a message was received, with the sample time measured by the peer.
+	fn received(&mut self, sample_time: Option<Duration>) {
+		self.loop_timer.received();
+		if let Some(sample_time) = sample_time {
+			self.loop_timer.set_budget(sample_time);
+		}
+	}
+
+	/// Record that the last target was sent.
+	fn sent(&mut self) {
+		self.loop_timer.sent();
+		self.sequence_number = self.sequence_number.wrapping_add(1);
+	}
+}
+
+/// Blocking streamer with MoveL/MoveJ-like motion primitives.
+///
+/// The streamer plans a trapezoidal velocity profile from the current feedback to the target
+/// and streams interpolated targets every time a message is received from the robot controller,
+/// until the robot has converged to the final target.
+///
+/// Elapsed time is measured with the clock of the robot controller, so the motion is not affected by network jitter.
+/// The controller does expect a new target within each sample period, so for deterministic motion
+/// run the streamer on a thread with real-time priority (see the `realtime` feature).
+///
+/// The speed override, hold signal, emergency stop and other settings are available through [`Self::core`].
+///
+/// By default, the streamer uses a peer with a UDP socket.
+/// Other transports can be used by implementing [`EgmTransport`].
+#[derive(Debug)]
+pub struct Streamer<T = UdpSocket> {
+	peer: crate::sync_peer::EgmPeer<T>,
+	core: StreamerCore,
+}
+
+impl<T: EgmTransport> Streamer<T> {
+	/// Create a new streamer for a peer.
+	///
+	/// The peer does not need to be connected: targets are sent to the address of the last received message.
+	pub fn new(peer: crate::sync_peer::EgmPeer<T>, config: StreamerConfig) -> Self {
+		Self {
+			peer,
+			core: StreamerCore::new(config),
+		}
+	}
+
+	/// Get a shared reference to the peer.
+	pub fn peer(&self) -> &crate::sync_peer::EgmPeer<T> {
+		&self.peer
+	}
+
+	/// Get an exclusive reference to the peer.
+	pub fn peer_mut(&mut self) -> &mut crate::sync_peer::EgmPeer<T> {
+		&mut self.peer
+	}
+
+	/// Consume self and get the peer.
+	pub fn into_peer(self) -> crate::sync_peer::EgmPeer<T> {
+		self.peer
+	}
+
+	/// Get a shared reference to the state machine of the streamer.
+	pub fn core(&self) -> &StreamerCore {
+		&self.core
+	}
+
+	/// Get an exclusive reference to the state machine of the streamer.
+	pub fn core_mut(&mut self) -> &mut StreamerCore {
+		&mut self.core
+	}
+
+	/// Move the tool in a straight line to a pose, with a maximum linear speed in mm/s.
+	///
+	/// The pose must have a position and an orientation, as a quaternion or as Euler angles.
+	///
+	/// Returns the last message received from the robot controller after the robot converged to the target.
+	pub fn move_linear(&mut self, pose: impl Into<EgmPose>, speed: f64) -> Result<EgmRobot, MotionError> {
+		let pose = pose.into();
+		self.run(|state, config| Motion::linear(state, pose, speed, config))
+	}
+
+	/// Move the joints to a target in degrees, with a maximum joint speed in degrees/s.
+	///
+	/// All joints start and stop at the same time.
+	/// Returns the last message received from the robot controller after the robot converged to the target.
+	pub fn move_joint(&mut self, joints: impl Into<Vec<f64>>, speed: f64) -> Result<EgmRobot, MotionError> {
+		let joints = joints.into();
+		self.run(|state, config| Motion::joint(state, joints, speed, config))
+	}
+
+	/// Hold the current joint positions for a duration, streaming joint targets.
+	///
+	/// Returns the last message received from the robot controller.
+	pub fn dwell_joint(&mut self, duration: Duration) -> Result<EgmRobot, MotionError> {
+		self.run(|state, _| Motion::dwell(state, false, duration))
+	}
+
+	/// Hold the current pose for a duration, streaming pose targets.
+	///
+	/// Returns the last message received from the robot controller.
+	pub fn dwell_linear(&mut self, duration: Duration) -> Result<EgmRobot, MotionError> {
+		self.run(|state, _| Motion::dwell(state, true, duration))
+	}
+
+	fn run(&mut self, plan: impl FnOnce(&EgmRobot, &StreamerConfig) -> Result<Motion, MotionError>) -> Result<EgmRobot, MotionError> {
+		self.core.check_emergency_stop()?;
+		let (mut state, mut address) = self.recv_from()?;
+		self.core.start(&state, plan)?;
+		while let Some(target) = self.core.next_target(&state)? {
+			self.peer.send_to(&target, &address)?;
+			self.core.sent();
+			let (new_state, new_address) = self.recv_from()?;
+			state = new_state;
+			address = new_address;
+		}
+		Ok(state)
+	}
+
+	fn recv_from(&mut self) -> Result<(EgmRobot, T::Address), MotionError> {
+		let received = self.peer.recv_from()?;
+		self.core.received(self.peer.measured_sample_time());
+		Ok(received)
+	}
+}
+
+/// Asynchronous streamer with MoveL/MoveJ-like motion primitives, using `tokio`.
+///
+/// The streamer plans a trapezoidal velocity profile from the current feedback to the target
+/// and streams interpolated targets every time a message is received from the robot controller,
+/// until the robot has converged to the final target.
+///
+/// Elapsed time is measured with the clock of the robot controller, so the motion is not affected by network jitter.
+///
+/// The speed override, hold signal, emergency stop and other settings are available through [`Self::core`].
+///
+/// By default, the streamer uses a peer with a `tokio` UDP socket.
+/// Other transports can be used by implementing [`crate::transport::AsyncEgmTransport`].
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct AsyncStreamer<T = tokio::net::UdpSocket> {
+	peer: crate::tokio_peer::EgmPeer<T>,
+	core: StreamerCore,
+}
+
+#[cfg(feature = "tokio")]
+impl<T: crate::transport::AsyncEgmTransport> AsyncStreamer<T> {
+	/// Create a new streamer for a peer.
+	///
+	/// The peer does not need to be connected: targets are sent to the address of the last received message.
+	pub fn new(peer: crate::tokio_peer::EgmPeer<T>, config: StreamerConfig) -> Self {
+		Self {
+			peer,
+			core: StreamerCore::new(config),
+		}
+	}
+
+	/// Get a shared reference to the peer.
+	pub fn peer(&self) -> &crate::tokio_peer::EgmPeer<T> {
+		&self.peer
+	}
+
+	/// Get an exclusive reference to the peer.
+	pub fn peer_mut(&mut self) -> &mut crate::tokio_peer::EgmPeer<T> {
+		&mut self.peer
+	}
+
+	/// Consume self and get the peer.
+	pub fn into_peer(self) -> crate::tokio_peer::EgmPeer<T> {
+		self.peer
+	}
+
+	/// Get a shared reference to the state machine of the streamer.
+	pub fn core(&self) -> &StreamerCore {
+		&self.core
+	}
+
+	/// Get an exclusive reference to the state machine of the streamer.
+	pub fn core_mut(&mut self) -> &mut StreamerCore {
+		&mut self.core
+	}
+
+	/// Move the tool in a straight line to a pose, with a maximum linear speed in mm/s.
+	///
+	/// The pose must have a position and an orientation, as a quaternion or as Euler angles.
+	///
+	/// Returns the last message received from the robot controller after the robot converged to the target.
+	pub async fn move_linear(&mut self, pose: impl Into<EgmPose>, speed: f64) -> Result<EgmRobot, MotionError> {
+		let pose = pose.into();
+		self.run(|state, config| Motion::linear(state, pose, speed, config)).await
+	}
+
+	/// Move the joints to a target in degrees, with a maximum joint speed in degrees/s.
+	///
+	/// All joints start and stop at the same time.
+	/// Returns the last message received from the robot controller after the robot converged to the target.
+	pub async fn move_joint(&mut self, joints: impl Into<Vec<f64>>, speed: f64) -> Result<EgmRobot, MotionError> {
+		let joints = joints.into();
+		self.run(|state, config| Motion::joint(state, joints, speed, config)).await
+	}
+
+	/// Hold the current joint positions for a duration, streaming joint targets.
+	///
+	/// Returns the last message received from the robot controller.
+	pub async fn dwell_joint(&mut self, duration: Duration) -> Result<EgmRobot, MotionError> {
+		self.run(|state, _| Motion::dwell(state, false, duration)).await
+	}
+
+	/// Hold the current pose for a duration, streaming pose targets.
+	///
+	/// Returns the last message received from the robot controller.
+	pub async fn dwell_linear(&mut self, duration: Duration) -> Result<EgmRobot, MotionError> {
+		self.run(|state, _| Motion::dwell(state, true, duration)).await
+	}
+
+	async fn run(&mut self, plan: impl FnOnce(&EgmRobot, &StreamerConfig) -> Result<Motion, MotionError>) -> Result<EgmRobot, MotionError> {
+		self.core.check_emergency_stop()?;
+		let (mut state, mut address) = self.recv_from().await?;
+		self.core.start(&state, plan)?;
+		while let Some(target) = self.core.next_target(&state)? {
+			self.peer.send_to(&target, &address).await?;
+			self.core.sent();
+			let (new_state, new_address) = self.recv_from().await?;
+			state = new_state;
+			address = new_address;
+		}
+		Ok(state)
+	}
+
+	async fn recv_from(&mut self) -> Result<(EgmRobot, T::Address), MotionError> {
+		let received = self.peer.recv_from().await?;
+		self.core.received(self.peer.measured_sample_time());
+		Ok(received)
+	}
+}
+
+impl From<ReceiveError> for MotionError {
+	fn from(other: ReceiveError) -> Self {
+		Self::Receive(other)
+	}
+}
+
+impl From<SendError> for MotionError {
+	fn from(other: SendError) -> Self {
+		Self::Send(other)
+	}
+}
+
+impl std::fmt::Display for MotionError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Receive(e) => write!(f, "failed to receive message from robot controller: {}", e),
+			Self::Send(e) => write!(f, "failed to send message to robot controller: {}", e),
+			Self::MissingFeedback { field } => write!(f, "robot feedback is missing field: {}", field),
+			Self::IncompleteTarget { field } => write!(f, "target pose is missing field: {}", field),
+			Self::JointCountMismatch { expected, actual } => {
+				write!(f, "target has {} joints, but the robot feedback has {} joints", actual, expected)
+			},
+			Self::InvalidSpeed { speed } => write!(f, "invalid speed: {}, expected a positive number", speed),
+			Self::ConvergenceTimeout => write!(f, "robot did not converge to the target in time"),
+			Self::ControllerRestarted => write!(f, "robot controller was restarted during the motion"),
+			Self::EmergencyStop => write!(f, "emergency stop was tripped"),
+		}
+	}
+}
+
+impl std::error::Error for MotionError {}
+
+#[cfg(test)]
+#[test]
+fn test_trapezoidal_profile() {
+	use assert2::assert;
+
+	// Accelerate for 1 second to 100 mm/s, cruise for 1 second and decelerate for 1 second.
+	let profile = TrapezoidalProfile::new(200.0, 100.0, 100.0);
+	assert!(profile.duration() == 3.0);
+	assert!(profile.position(-1.0) == 0.0);
+	assert!(profile.position(1.0) == 50.0);
+	assert!(profile.position(2.0) == 150.0);
+	assert!(profile.position(3.0) == 200.0);
+	assert!(profile.position(4.0) == 200.0);
+
+	// The distance is too short to reach the maximum velocity.
+	let profile = TrapezoidalProfile::new(-100.0, 1000.0, 100.0);
+	assert!(profile.duration() == 2.0);
+	assert!(profile.peak_velocity() == 100.0);
+	assert!(profile.fraction(1.0) == 0.5);
+
+	assert!(TrapezoidalProfile::new(0.0, 10.0, 10.0).fraction(0.0) == 1.0);
+}
+
+#[cfg(test)]
+#[test]
+fn test_move_joint() {
+	use crate::msg;
+	use assert2::assert;
+	use prost::Message;
+
+	let peer = crate::sync_peer::EgmPeer::bind("127.0.0.1:0").unwrap();
+	let address = peer.socket().local_addr().unwrap();
+	let mut streamer = Streamer::new(peer, StreamerConfig::default());
+
+	// Simulate a robot that perfectly tracks the targets with a 4 ms sample time.
+	let robot = std::thread::spawn(move || {
+		let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+		socket.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+		let mut joints = vec![0.0; 6];
+		let mut buffer = [0; 1024];
+		let mut idle = 0;
+		for cycle in 0.. {
+			let state = EgmRobot {
+				feed_back: Some(msg::EgmFeedBack {
+					joints: Some(msg::EgmJoints::from_degrees(joints.clone())),
+					time: Some(msg::EgmClock::new(0, cycle * 4_000)),
+					..Default::default()
+				}),
+				..Default::default()
+			};
+			socket.send_to(&state.encode_to_vec(), address).unwrap();
+			// Keep sending feedback when no target arrives, until the streamer is gone.
+			let len = match socket.recv(&mut buffer) {
+				Ok(len) => len,
+				Err(_) if idle < 5 => {
+					idle += 1;
+					continue;
+				},
+				Err(_) => return joints,
+			};
+			idle = 0;
+			let target = EgmSensor::decode(&buffer[..len]).unwrap();
+			joints = target.planned.unwrap().joints.unwrap().joints;
+		}
+		unreachable!()
+	});
+
+	assert!(let Err(MotionError::JointCountMismatch { expected: 6, actual: 2 }) = streamer.move_joint([1.0, 2.0], 10.0));
+	let state = streamer.move_joint([10.0, -10.0, 5.0, 0.0, 0.0, 0.0], 20.0).unwrap();
+	let joints = state.feedback_joints().unwrap();
+	assert!(joints.iter().zip(&[10.0, -10.0, 5.0, 0.0, 0.0, 0.0]).all(|(a, b)| (a - b).abs() <= 0.1));
+	drop(streamer);
+	assert!(robot.join().unwrap() == vec![10.0, -10.0, 5.0, 0.0, 0.0, 0.0]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_speed_override() {
+	use crate::msg;
+	use assert2::{assert, let_assert};
+
+	let speed_override = SpeedOverride::default();
+	assert!(speed_override.get() == 1.0);
+	speed_override.clone().set(2.0);
+	assert!(speed_override.get() == 1.0);
+	speed_override.set(f64::NAN);
+	assert!(speed_override.get() == 0.0);
+
+	let state = |msec: u64| EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(vec![0.0, 0.0])),
+			time: Some(msg::EgmClock::new(0, msec * 1000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+
+	// Accelerate for 1 second to 10 degrees/s, cruise for 9 seconds and decelerate for 1 second.
+	let config = StreamerConfig {
+		joint_acceleration: 10.0,
+		..Default::default()
+	};
+	let mut motion = Motion::joint(&state(0), vec![100.0, -50.0], 10.0, &config).unwrap();
+	let_assert!(Ok(Some(_)) = motion.step(&state(2000), 0, &config, 1.0, false));
+
+	// At half speed, the motion advances half as far and the speed references are halved.
+	let_assert!(Ok(Some(target)) = motion.step(&state(4000), 1, &config, 0.5, false));
+	assert!(target.planned.unwrap().joints.unwrap().joints == [25.0, -12.5]);
+	assert!(target.speed_ref.unwrap().joints.unwrap().joints == [5.0, -2.5]);
+
+	// A zero override holds the current target.
+	let_assert!(Ok(Some(target)) = motion.step(&state(6000), 2, &config, 0.0, false));
+	assert!(target.planned.unwrap().joints.unwrap().joints == [25.0, -12.5]);
+	assert!(target.speed_ref.unwrap().joints.unwrap().joints == [0.0, 0.0]);
+
+	// Holding ramps the speed down to zero, after which the target stays in place.
+	let mut motion = Motion::joint(&state(0), vec![100.0, -50.0], 10.0, &config).unwrap();
+	let_assert!(Ok(Some(_)) = motion.step(&state(2000), 0, &config, 1.0, false));
+	let_assert!(Ok(Some(target)) = motion.step(&state(2250), 1, &config, 1.0, true));
+	assert!(target.speed_ref.unwrap().joints.unwrap().joints == [5.0, -2.5]);
+	let_assert!(Ok(Some(held)) = motion.step(&state(2500), 2, &config, 1.0, true));
+	assert!(held.speed_ref.unwrap().joints.unwrap().joints == [0.0, 0.0]);
+	let_assert!(Ok(Some(target)) = motion.step(&state(5000), 3, &config, 1.0, true));
+	assert!(target.planned.unwrap().joints == held.planned.unwrap().joints);
+
+	// Resuming ramps the speed up again and continues along the path.
+	let_assert!(Ok(Some(target)) = motion.step(&state(5250), 4, &config, 1.0, false));
+	assert!(target.speed_ref.unwrap().joints.unwrap().joints == [5.0, -2.5]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_emergency_stop() {
+	use crate::msg;
+	use assert2::{assert, let_assert};
+
+	let tripped = Arc::new(AtomicBool::new(false));
+	let mut signal = Some(SafetySignal(Box::new({
+		let tripped = tripped.clone();
+		move || tripped.load(Ordering::Relaxed)
+	})));
+	let emergency_stop = EmergencyStop::new();
+	assert!(!check_emergency_stop(&mut signal, &emergency_stop));
+
+	// The stop is latched until it is cleared explicitly.
+	tripped.store(true, Ordering::Relaxed);
+	assert!(check_emergency_stop(&mut signal, &emergency_stop));
+	tripped.store(false, Ordering::Relaxed);
+	assert!(check_emergency_stop(&mut signal, &emergency_stop));
+	emergency_stop.clone().clear();
+	assert!(!check_emergency_stop(&mut signal, &emergency_stop));
+
+	let state = |joints: f64, msec: u64| EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(vec![joints; 2])),
+			time: Some(msg::EgmClock::new(0, msec * 1000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let motion = Motion::joint(&state(0.0, 0), vec![100.0, -50.0], 10.0, &StreamerConfig::default()).unwrap();
+	let_assert!(Ok(target) = motion.hold_target(&state(1.5, 100), &state(2.0, 200), 7));
+	assert!(target.planned.as_ref().unwrap().joints.as_ref().unwrap().joints == [1.5, 1.5]);
+	assert!(target.header.as_ref().unwrap().seqno == Some(7));
+}
+
+#[cfg(test)]
+#[test]
+fn test_utilization_throttle() {
+	use assert2::assert;
+
+	let throttle = UtilizationThrottle::default();
+	let state = |utilization_rate| EgmRobot {
+		utilization_rate,
+		..Default::default()
+	};
+	let mut throttled = false;
+	assert!(throttle.update(&mut throttled, &state(Some(80.0))) == 1.0);
+	assert!(throttle.update(&mut throttled, &state(Some(95.0))) == 0.5);
+	assert!(throttle.update(&mut throttled, &state(None)) == 0.5);
+	assert!(throttle.update(&mut throttled, &state(Some(80.0))) == 0.5);
+	assert!(throttle.update(&mut throttled, &state(Some(70.0))) == 1.0);
+	assert!(!throttled);
+}
+
+#[cfg(test)]
+#[test]
+fn test_retarget() {
+	use crate::msg;
+	use assert2::{assert, let_assert};
+
+	let state = |msec: u64| EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(vec![0.0])),
+			time: Some(msg::EgmClock::new(0, msec * 1000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let joints = |target: &EgmSensor| target.planned.as_ref().unwrap().joints.as_ref().unwrap().joints[0];
+	let speed = |target: &EgmSensor| target.speed_ref.as_ref().unwrap().joints.as_ref().unwrap().joints[0];
+
+	let config = StreamerConfig {
+		joint_acceleration: 10.0,
+		..Default::default()
+	};
+	let mut motion = Motion::joint(&state(0), vec![100.0], 10.0, &config).unwrap();
+	let_assert!(Ok(Some(before)) = motion.step(&state(2000), 0, &config, 1.0, false));
+	assert!(joints(&before) == 15.0);
+	assert!(speed(&before) == 10.0);
+
+	// Move to a closer goal: the new motion starts at the current velocity,
+	// and position and velocity stay continuous while blending.
+	let mut motion = motion.retarget(Goal::Joint { joints: vec![50.0], speed: 10.0 }, &config).unwrap();
+	let mut previous = before;
+	for msec in (2004..=2400).step_by(4) {
+		let_assert!(Ok(Some(target)) = motion.step(&state(msec), 0, &config, 1.0, false));
+		assert!((joints(&target) - joints(&previous)).abs() < 0.05);
+		assert!((speed(&target) - speed(&previous)).abs() < 0.1);
+		previous = target;
+	}
+	assert!(motion.blend.is_none());
+	assert!((speed(&previous) - 10.0).abs() < 1e-9);
+
+	// A pose goal can not be used for a joint motion.
+	let motion = motion.retarget(Goal::Linear { pose: EgmPose::default(), speed: 10.0 }, &config).unwrap();
+	assert!(motion.blend.is_none());
+}
+
+#[cfg(test)]
+#[test]
+fn test_incomplete_pose() {
+	use crate::msg::{self, EgmEuler};
+	use assert2::{assert, let_assert};
+
+	let state = EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			cartesian: Some(EgmPose::new([0.0, 0.0, 0.0], EgmQuaternion::IDENTITY)),
+			time: Some(msg::EgmClock::new(0, 0)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let config = StreamerConfig::default();
+
+	// Euler angles are converted instead of being replaced by the identity orientation.
+	let euler = EgmEuler::from_xyz_degrees(0.0, 0.0, 90.0);
+	let goal = EgmPose {
+		pos: Some([100.0, 0.0, 0.0].into()),
+		euler: Some(euler),
+		..Default::default()
+	};
+	let_assert!(Ok(motion) = Motion::linear(&state, goal, 10.0, &config));
+	let_assert!(Path::Linear { end, .. } = &motion.path);
+	assert!(end.orient.unwrap().angle_to(&euler.to_quaternion()) < 1e-9);
+	assert!(motion.profile.distance() == 100.0);
+
+	// A goal without a position or orientation is an error instead of the origin or the identity orientation.
+	let goal = EgmPose {
+		orient: Some(EgmQuaternion::IDENTITY),
+		..Default::default()
+	};
+	assert!(let Err(MotionError::IncompleteTarget { field: "pos" }) = Motion::linear(&state, goal, 10.0, &config));
+	let goal = EgmPose {
+		pos: Some([100.0, 0.0, 0.0].into()),
+		..Default::default()
+	};
+	assert!(let Err(MotionError::IncompleteTarget { field: "orient" }) = Motion::linear(&state, goal, 10.0, &config));
+}
+
+#[cfg(test)]
+#[test]
+fn test_streamer_core() {
+	use crate::msg;
+	use assert2::{assert, let_assert};
+
+	let state = |joints: Vec<f64>, msec: u64| EgmRobot {
+		feed_back: Some(msg::EgmFeedBack {
+			joints: Some(msg::EgmJoints::from_degrees(joints)),
+			time: Some(msg::EgmClock::new(0, msec * 1000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+
+	// Without a motion there is nothing to send.
+	let mut core = StreamerCore::new(StreamerConfig::default());
+	let_assert!(Ok(None) = core.next_target(&state(vec![0.0; 2], 0)));
+
+	// Feed back every target as if the robot tracks it perfectly, until the motion finished.
+	let_assert!(Ok(()) = core.start(&state(vec![0.0; 2], 0), |state, config| Motion::joint(state, vec![10.0, -5.0], 20.0, config)));
+	let mut feedback = state(vec![0.0; 2], 0);
+	let mut cycles = 0;
+	while let Some(target) = core.next_target(&feedback).unwrap() {
+		assert!(target.header.unwrap().seqno == Some(cycles));
+		core.sent();
+		cycles += 1;
+		feedback = state(target.planned.unwrap().joints.unwrap().joints, 4 * u64::from(cycles));
+	}
+	assert!(cycles > 100);
+	assert!(feedback.feedback_joints().unwrap() == &[10.0, -5.0]);
+	let_assert!(Ok(None) = core.next_target(&feedback));
+}
+
+/// A simulated robot that perfectly tracks the targets with a 4 ms sample time, as an in-memory transport.
+#[cfg(test)]
+#[derive(Debug)]
+struct MockRobot {
+	joints: Vec<f64>,
+	cycle: u64,
+	sent: Vec<EgmSensor>,
+	/// Trip the emergency stop before sending the message of the first cycle, and clear it before the second.
+	emergency_stop: Option<(EmergencyStop, u64, u64)>,
+}
+
+#[cfg(test)]
+impl EgmTransport for MockRobot {
+	type Address = ();
+
+	fn recv(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+		self.recv_from(buffer).map(|(len, ())| len)
+	}
+
+	fn recv_from(&mut self, buffer: &mut [u8]) -> std::io::Result<(usize, ())> {
+		use prost::Message;
+		if self.cycle >= 1000 {
+			return Err(std::io::ErrorKind::TimedOut.into());
+		}
+		if let Some((emergency_stop, trip, clear)) = &self.emergency_stop {
+			if self.cycle == *trip {
+				emergency_stop.trip();
+			} else if self.cycle == *clear {
+				emergency_stop.clear();
+			}
+		}
+		let state = EgmRobot {
+			feed_back: Some(crate::msg::EgmFeedBack {
+				joints: Some(crate::msg::EgmJoints::from_degrees(self.joints.clone())),
+				time: Some(crate::msg::EgmClock::new(0, self.cycle * 4_000)),
+				..Default::default()
+			}),
+			..Default::default()
+		};
+		self.cycle += 1;
+		let mut datagram = &mut buffer[..];
+		state.encode(&mut datagram)?;
+		Ok((state.encoded_len(), ()))
+	}
+
+	fn send(&mut self, datagram: &[u8]) -> std::io::Result<usize> {
+		self.send_to(datagram, &())
+	}
+
+	fn send_to(&mut self, datagram: &[u8], _target: &()) -> std::io::Result<usize> {
+		use prost::Message;
+		let target = EgmSensor::decode(datagram)?;
+		self.joints = target.planned.as_ref().unwrap().joints.as_ref().unwrap().joints.clone();
+		self.sent.push(target);
+		Ok(datagram.len())
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_emergency_stop_before_move() {
+	use assert2::{assert, let_assert};
+
+	let robot = MockRobot {
+		joints: vec![0.0; 6],
+		cycle: 0,
+		sent: Vec::new(),
+		emergency_stop: None,
+	};
+	let mut streamer = Streamer::new(crate::sync_peer::EgmPeer::new(robot), StreamerConfig::default());
+
+	// A tripped stop refuses the motion without exchanging any message.
+	streamer.core().emergency_stop().trip();
+	let_assert!(Err(MotionError::EmergencyStop) = streamer.move_joint([10.0; 6], 20.0));
+	let_assert!(Err(MotionError::EmergencyStop) = streamer.move_linear(EgmPose::new([0.0; 3], EgmQuaternion::IDENTITY), 20.0));
+	assert!(streamer.peer().socket().cycle == 0);
+	assert!(streamer.peer().socket().sent.is_empty());
+
+	// So does a safety signal that is active when the motion is started.
+	streamer.core().emergency_stop().clear();
+	streamer.core_mut().set_safety_signal(|| true);
+	let_assert!(Err(MotionError::EmergencyStop) = streamer.move_joint([10.0; 6], 20.0));
+	assert!(streamer.peer().socket().cycle == 0);
+
+	// After clearing the stop, the motion runs normally.
+	streamer.core_mut().set_safety_signal(|| false);
+	streamer.core().emergency_stop().clear();
+	let_assert!(Ok(state) = streamer.move_joint([10.0; 6], 20.0));
+	assert!(state.feedback_joints().unwrap() == &[10.0; 6]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_emergency_stop_during_move() {
+	use assert2::{assert, let_assert};
+
+	let robot = MockRobot {
+		joints: vec![0.0; 6],
+		cycle: 0,
+		sent: Vec::new(),
+		emergency_stop: None,
+	};
+	let mut streamer = Streamer::new(crate::sync_peer::EgmPeer::new(robot), StreamerConfig::default());
+	streamer.peer_mut().socket_mut().emergency_stop = Some((streamer.core().emergency_stop(), 50, 80));
+	let_assert!(Err(MotionError::EmergencyStop) = streamer.move_joint([10.0; 6], 20.0));
+
+	// The motion follows the profile until the stop is tripped,
+	// then holds the position of the robot until the stop is cleared and the motion fails.
+	let robot = streamer.peer().socket();
+	assert!(robot.cycle == 81);
+	assert!(robot.sent.len() == 80);
+	let joints = |target: &EgmSensor| target.planned.as_ref().unwrap().joints.as_ref().unwrap().joints.clone();
+	let frozen = joints(&robot.sent[49]);
+	assert!(frozen[0] > 0.0);
+	assert!(frozen[0] < 10.0);
+	assert!(robot.sent[50..].iter().all(|target| joints(target) == frozen));
+	assert!(robot.sent.last().unwrap().speed_ref.is_none());
+}
@@ -0,0 +1,564 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::diff::{DiffTolerance, StateDiff};
+use crate::msg::EgmRobot;
+use crate::ReceiveError;
+
+/// The latest message received from the robot controller.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RobotState {
+	/// The message from the robot controller.
+	pub message: EgmRobot,
+
+	/// The address the message was received from.
+	pub address: SocketAddr,
+
+	/// The local time when the message was received.
+	pub received_at: Instant,
+
+	/// The number of messages received so far, including this one.
+	pub count: u64,
+}
+
+impl RobotState {
+	/// Report the meaningful changes since an older state, using the default tolerances.
+	pub fn diff(&self, old: &RobotState) -> StateDiff {
+		self.diff_with(old, &DiffTolerance::default())
+	}
+
+	/// Report the meaningful changes since an older state, using custom tolerances.
+	pub fn diff_with(&self, old: &RobotState, tolerance: &DiffTolerance) -> StateDiff {
+		StateDiff::between(&old.message, &self.message, tolerance)
+	}
+}
+
+/// Which states are delivered to a decimated subscription.
+///
+/// Decimated subscriptions have latest-value semantics:
+/// when a state is delivered, it is always the latest state received from the robot controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decimation {
+	/// Deliver a state only if at least this many messages were received since the last delivered state.
+	EveryNth(u64),
+
+	/// Deliver a state only if it was received at least this long after the last delivered state.
+	Interval(Duration),
+}
+
+impl Decimation {
+	/// Deliver states at a target rate in Hz.
+	///
+	/// Returns `None` if the rate is not positive, or so low that the interval does not fit in a [`Duration`].
+	pub fn rate(hz: f64) -> Option<Self> {
+		if hz.is_nan() || hz <= 0.0 {
+			return None;
+		}
+		Duration::try_from_secs_f64(1.0 / hz).ok().map(Self::Interval)
+	}
+}
+
+/// Tracks the last delivered state of a decimated subscription.
+#[derive(Clone, Debug)]
+struct Decimator {
+	decimation: Decimation,
+	last: Option<(u64, Instant)>,
+}
+
+impl Decimator {
+	fn new(decimation: Decimation) -> Self {
+		Self { decimation, last: None }
+	}
+
+	fn accepts(&self, state: &RobotState) -> bool {
+		let (count, received_at) = match self.last {
+			None => return true,
+			Some(last) => last,
+		};
+		state.count > count
+			&& match self.decimation {
+				Decimation::EveryNth(n) => state.count >= count + n,
+				Decimation::Interval(interval) => state.received_at >= received_at + interval,
+			}
+	}
+
+	fn deliver(&mut self, state: &RobotState) {
+		self.last = Some((state.count, state.received_at));
+	}
+}
+
+/// Background task that continuously receives messages and keeps the latest state available.
+///
+/// Any number of tasks can sample the latest state with [`FeedbackSubscriber::latest`],
+/// or wait for new messages with a receiver from [`FeedbackSubscriber::subscribe`],
+/// without owning the peer.
+///
+/// Messages that fail to decode or are rejected by the middleware of the peer are logged and skipped.
+/// The task stops on I/O errors, or when [`FeedbackSubscriber::stop`] is called.
+/// Dropping the subscriber aborts the task.
+///
+/// The task owns the peer while it is running.
+/// Use [`FeedbackSubscriber::stop`] to get the peer back.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct FeedbackSubscriber {
+	state: Arc<tokio::sync::watch::Sender<Option<RobotState>>>,
+	shutdown: Option<tokio::sync::oneshot::Sender<()>>,
+	task: Option<tokio::task::JoinHandle<Result<crate::tokio_peer::EgmPeer, ReceiveError>>>,
+}
+
+#[cfg(feature = "tokio")]
+impl FeedbackSubscriber {
+	/// Spawn a task on the current tokio runtime that receives messages with the peer.
+	///
+	/// # Panics
+	/// This function panics if called outside of a tokio runtime.
+	pub fn spawn(peer: crate::tokio_peer::EgmPeer) -> Self {
+		let state = Arc::new(tokio::sync::watch::Sender::new(None));
+		let (shutdown, shutdown_rx) = tokio::sync::oneshot::channel();
+		let task = tokio::spawn(subscribe(peer, state.clone(), shutdown_rx));
+		Self {
+			state,
+			shutdown: Some(shutdown),
+			task: Some(task),
+		}
+	}
+
+	/// Get the latest state, or `None` if no message has been received yet.
+	pub fn latest(&self) -> Option<RobotState> {
+		self.state.borrow().clone()
+	}
+
+	/// Get a receiver that is notified of every new state.
+	///
+	/// The receiver only sees the latest state: intermediate messages are skipped if the receiver is not polled fast enough.
+	/// The receiver reports an error once the subscriber has been stopped or dropped.
+	pub fn subscribe(&self) -> tokio::sync::watch::Receiver<Option<RobotState>> {
+		self.state.subscribe()
+	}
+
+	/// Get a subscription that only delivers some of the states, for consumers that do not need every message.
+	pub fn subscribe_decimated(&self, decimation: Decimation) -> DecimatedSubscription {
+		DecimatedSubscription {
+			receiver: self.state.subscribe(),
+			decimator: Decimator::new(decimation),
+		}
+	}
+
+	/// Check if the background task has stopped, for example because of an I/O error.
+	pub fn is_finished(&self) -> bool {
+		match &self.task {
+			Some(task) => task.is_finished(),
+			None => true,
+		}
+	}
+
+	/// Stop the background task and get the peer back.
+	///
+	/// If the task already stopped because of an error, the error is returned instead.
+	pub async fn stop(mut self) -> Result<crate::tokio_peer::EgmPeer, ReceiveError> {
+		if let Some(shutdown) = self.shutdown.take() {
+			let _ = shutdown.send(());
+		}
+		let task = self.task.take().expect("task is only taken by stop");
+		match task.await {
+			Ok(result) => result,
+			Err(e) => std::panic::resume_unwind(e.into_panic()),
+		}
+	}
+}
+
+/// Subscription to a [`FeedbackSubscriber`] that only delivers some of the states.
+///
+/// Created with [`FeedbackSubscriber::subscribe_decimated`].
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct DecimatedSubscription {
+	receiver: tokio::sync::watch::Receiver<Option<RobotState>>,
+	decimator: Decimator,
+}
+
+#[cfg(feature = "tokio")]
+impl DecimatedSubscription {
+	/// Get the decimation of the subscription.
+	pub fn decimation(&self) -> Decimation {
+		self.decimator.decimation
+	}
+
+	/// Wait for the next state to deliver.
+	///
+	/// Returns `None` once the subscriber has been stopped or dropped.
+	pub async fn next(&mut self) -> Option<RobotState> {
+		let decimator = &self.decimator;
+		let state = self
+			.receiver
+			.wait_for(|x| matches!(x, Some(x) if decimator.accepts(x)))
+			.await
+			.ok()?
+			.clone()?;
+		self.decimator.deliver(&state);
+		Some(state)
+	}
+}
+
+#[cfg(feature = "tokio")]
+impl Drop for FeedbackSubscriber {
+	fn drop(&mut self) {
+		if let Some(task) = &self.task {
+			task.abort();
+		}
+	}
+}
+
+#[cfg(feature = "tokio")]
+async fn subscribe(
+	peer: crate::tokio_peer::EgmPeer,
+	state: Arc<tokio::sync::watch::Sender<Option<RobotState>>>,
+	mut shutdown: tokio::sync::oneshot::Receiver<()>,
+) -> Result<crate::tokio_peer::EgmPeer, ReceiveError> {
+	let mut count = 0;
+	loop {
+		let received = tokio::select! {
+			_ = &mut shutdown => return Ok(peer),
+			received = peer.recv_from() => received,
+		};
+		match received {
+			Ok((message, address)) => {
+				count += 1;
+				state.send_replace(Some(RobotState {
+					message,
+					address,
+					received_at: Instant::now(),
+					count,
+				}));
+			},
+			Err(ReceiveError::Io(e)) => return Err(ReceiveError::Io(e)),
+			Err(e) => log::warn!("ignoring message from robot controller: {}", e),
+		}
+	}
+}
+
+#[derive(Debug, Default)]
+struct Latest {
+	state: Option<RobotState>,
+	finished: bool,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+	latest: Mutex<Latest>,
+	fresh: Condvar,
+	stop: AtomicBool,
+}
+
+impl Shared {
+	fn lock(&self) -> MutexGuard<'_, Latest> {
+		// The state is always left in a consistent state, so a poisoned lock can safely be used.
+		self.latest.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+/// Background thread that continuously receives messages and keeps the latest state available.
+///
+/// This is the blocking equivalent of [`FeedbackSubscriber`], created with [`crate::sync_peer::EgmPeer::spawn_receiver`].
+/// Any number of threads can sample the latest state with [`FeedbackReceiver::latest`],
+/// or wait for a new message with [`FeedbackReceiver::wait_for_fresh`].
+///
+/// Messages that fail to decode or are rejected by the middleware of the peer are logged and skipped.
+/// Connection reset errors, which Windows reports after the robot controller restarts, are skipped too.
+/// The thread stops on other I/O errors, or when [`FeedbackReceiver::stop`] is called.
+///
+/// The thread owns the peer while it runs.
+/// Use [`FeedbackReceiver::sender`] to send targets to the robot controller over the same socket in the meantime.
+///
+/// The thread uses a short read timeout on the socket to check if it should stop.
+/// Dropping the handle signals the thread to stop without waiting for it.
+#[derive(Debug)]
+pub struct FeedbackReceiver {
+	shared: Arc<Shared>,
+	socket: std::net::UdpSocket,
+	thread: Option<std::thread::JoinHandle<Result<crate::sync_peer::EgmPeer, ReceiveError>>>,
+}
+
+impl FeedbackReceiver {
+	/// Spawn a thread that receives messages with the peer.
+	pub(crate) fn spawn(peer: crate::sync_peer::EgmPeer) -> std::io::Result<Self> {
+		let read_timeout = peer.socket().read_timeout()?;
+		peer.socket().set_read_timeout(Some(Duration::from_millis(50)))?;
+		let socket = peer.socket().try_clone()?;
+		let shared = Arc::new(Shared::default());
+		let thread = std::thread::Builder::new().name("egm-receiver".into()).spawn({
+			let shared = shared.clone();
+			move || {
+				let result = receive(peer, &shared);
+				shared.lock().finished = true;
+				shared.fresh.notify_all();
+				let peer = result?;
+				peer.socket().set_read_timeout(read_timeout)?;
+				Ok(peer)
+			}
+		})?;
+		Ok(Self {
+			shared,
+			socket,
+			thread: Some(thread),
+		})
+	}
+
+	/// Get a peer to send targets to the robot controller over the same socket as the background thread.
+	///
+	/// Send the targets to the address of the latest state with [`EgmPeer::send_to`](crate::sync_peer::EgmPeer::send_to).
+	/// The returned peer has its own middleware stack and options: the middleware of the receiving peer is not applied.
+	/// Do not receive with the returned peer, because it would take messages away from the background thread.
+	pub fn sender(&self) -> std::io::Result<crate::sync_peer::EgmPeer> {
+		Ok(crate::sync_peer::EgmPeer::new(self.socket.try_clone()?))
+	}
+
+	/// Get the latest state, or `None` if no message has been received yet.
+	pub fn latest(&self) -> Option<RobotState> {
+		self.shared.lock().state.clone()
+	}
+
+	/// Wait for a message that is received after this function is called.
+	///
+	/// Returns `None` if no message arrived before the timeout, or if the thread stopped.
+	pub fn wait_for_fresh(&self, timeout: Duration) -> Option<RobotState> {
+		let latest = self.shared.lock();
+		let count = latest.state.as_ref().map(|x| x.count);
+		let (latest, _) = self
+			.shared
+			.fresh
+			.wait_timeout_while(latest, timeout, |x| !x.finished && x.state.as_ref().map(|x| x.count) == count)
+			.unwrap_or_else(|e| e.into_inner());
+		match &latest.state {
+			Some(state) if Some(state.count) != count => Some(state.clone()),
+			_ => None,
+		}
+	}
+
+	/// Get a subscription that only delivers some of the states, for consumers that do not need every message.
+	pub fn decimated(&self, decimation: Decimation) -> DecimatedReceiver<'_> {
+		DecimatedReceiver {
+			receiver: self,
+			decimator: Decimator::new(decimation),
+		}
+	}
+
+	/// Check if the background thread has stopped, for example because of an I/O error.
+	pub fn is_finished(&self) -> bool {
+		self.shared.lock().finished
+	}
+
+	/// Stop the background thread and get the peer back.
+	///
+	/// The original read timeout of the socket is restored.
+	/// If the thread already stopped because of an error, the error is returned instead.
+	pub fn stop(mut self) -> Result<crate::sync_peer::EgmPeer, ReceiveError> {
+		self.shared.stop.store(true, Ordering::Relaxed);
+		let thread = self.thread.take().expect("thread is only taken by stop");
+		match thread.join() {
+			Ok(result) => result,
+			Err(e) => std::panic::resume_unwind(e),
+		}
+	}
+}
+
+/// Subscription to a [`FeedbackReceiver`] that only delivers some of the states.
+///
+/// Created with [`FeedbackReceiver::decimated`].
+#[derive(Debug)]
+pub struct DecimatedReceiver<'a> {
+	receiver: &'a FeedbackReceiver,
+	decimator: Decimator,
+}
+
+impl DecimatedReceiver<'_> {
+	/// Get the decimation of the subscription.
+	pub fn decimation(&self) -> Decimation {
+		self.decimator.decimation
+	}
+
+	/// Wait for the next state to deliver.
+	///
+	/// Returns `None` if no state was delivered before the timeout, or if the thread stopped.
+	pub fn next(&mut self, timeout: Duration) -> Option<RobotState> {
+		let decimator = &self.decimator;
+		let latest = self.receiver.shared.lock();
+		let (latest, _) = self
+			.receiver
+			.shared
+			.fresh
+			.wait_timeout_while(latest, timeout, |x| !x.finished && !matches!(&x.state, Some(x) if decimator.accepts(x)))
+			.unwrap_or_else(|e| e.into_inner());
+		let state = latest.state.clone().filter(|x| decimator.accepts(x))?;
+		drop(latest);
+		self.decimator.deliver(&state);
+		Some(state)
+	}
+}
+
+impl Drop for FeedbackReceiver {
+	fn drop(&mut self) {
+		self.shared.stop.store(true, Ordering::Relaxed);
+	}
+}
+
+fn receive(mut peer: crate::sync_peer::EgmPeer, shared: &Shared) -> Result<crate::sync_peer::EgmPeer, ReceiveError> {
+	let mut count = 0;
+	while !shared.stop.load(Ordering::Relaxed) {
+		match peer.recv_from() {
+			Ok((message, address)) => {
+				count += 1;
+				shared.lock().state = Some(RobotState {
+					message,
+					address,
+					received_at: Instant::now(),
+					count,
+				});
+				shared.fresh.notify_all();
+			},
+			Err(ReceiveError::Io(e)) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => (),
+			Err(ReceiveError::Io(e)) if e.kind() == std::io::ErrorKind::ConnectionReset => {
+				log::debug!("ignoring connection reset, the robot controller may have restarted");
+			},
+			Err(ReceiveError::Io(e)) => return Err(ReceiveError::Io(e)),
+			Err(e) => log::warn!("ignoring message from robot controller: {}", e),
+		}
+	}
+	Ok(peer)
+}
+
+#[cfg(all(test, feature = "tokio"))]
+#[tokio::test]
+async fn test_feedback_subscriber() {
+	use crate::msg::{EgmClock, EgmFeedBack};
+	use assert2::{assert, let_assert};
+	use prost::Message;
+
+	let peer = crate::tokio_peer::EgmPeer::bind("127.0.0.1:0").await.unwrap();
+	let address = peer.socket().local_addr().unwrap();
+	let robot = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+	let subscriber = FeedbackSubscriber::spawn(peer);
+	let mut receiver = subscriber.subscribe();
+	assert!(subscriber.latest() == None);
+
+	let message = EgmRobot {
+		feed_back: Some(EgmFeedBack {
+			time: Some(EgmClock::new(0, 4000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	robot.send_to(&message.encode_to_vec(), address).await.unwrap();
+	robot.send_to(&[0xFF, 0xFF], address).await.unwrap();
+	robot.send_to(&message.encode_to_vec(), address).await.unwrap();
+
+	let_assert!(Ok(Some(state)) = receiver.wait_for(|x| matches!(x, Some(x) if x.count == 2)).await.map(|x| x.clone()));
+	assert!(state.message == message);
+	assert!(state.address == robot.local_addr().unwrap());
+	assert!(subscriber.latest() == Some(state));
+	assert!(!subscriber.is_finished());
+
+	let_assert!(Ok(peer) = subscriber.stop().await);
+	assert!(peer.socket().local_addr().unwrap() == address);
+	assert!(let Err(_) = receiver.changed().await);
+}
+
+#[cfg(test)]
+#[test]
+fn test_feedback_receiver() {
+	use crate::msg::{EgmClock, EgmFeedBack, EgmSensor};
+	use assert2::{assert, let_assert};
+	use prost::Message;
+
+	let peer = crate::sync_peer::EgmPeer::bind("127.0.0.1:0").unwrap();
+	let address = peer.socket().local_addr().unwrap();
+	let robot = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+
+	let_assert!(Ok(receiver) = peer.spawn_receiver());
+	assert!(receiver.latest() == None);
+	assert!(receiver.wait_for_fresh(Duration::from_millis(10)) == None);
+
+	let message = EgmRobot {
+		feed_back: Some(EgmFeedBack {
+			time: Some(EgmClock::new(0, 4000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	std::thread::scope(|scope| {
+		scope.spawn(|| {
+			std::thread::sleep(Duration::from_millis(20));
+			robot.send_to(&message.encode_to_vec(), address).unwrap();
+		});
+		let_assert!(Some(state) = receiver.wait_for_fresh(Duration::from_secs(5)));
+		assert!(state.message == message);
+		assert!(state.count == 1);
+		assert!(receiver.latest() == Some(state.clone()));
+
+		// Targets can be sent while the background thread owns the peer.
+		let target = EgmSensor::joint_target(1, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0], EgmClock::new(0, 4000));
+		let_assert!(Ok(mut sender) = receiver.sender());
+		let_assert!(Ok(()) = sender.send_to(&target, &state.address));
+		let mut buffer = [0; 1024];
+		let_assert!(Ok((len, _)) = robot.recv_from(&mut buffer));
+		assert!(EgmSensor::decode(&buffer[..len]).unwrap() == target);
+	});
+	assert!(!receiver.is_finished());
+
+	let_assert!(Ok(peer) = receiver.stop());
+	assert!(peer.socket().read_timeout().unwrap() == None);
+}
+
+#[cfg(test)]
+#[test]
+fn test_decimated_receiver() {
+	use assert2::{assert, let_assert};
+	use prost::Message;
+
+	let peer = crate::sync_peer::EgmPeer::bind("127.0.0.1:0").unwrap();
+	let address = peer.socket().local_addr().unwrap();
+	let robot = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+	let_assert!(Ok(receiver) = peer.spawn_receiver());
+
+	let send = |count: u64| {
+		let target = receiver.latest().map(|x| x.count).unwrap_or(0) + count;
+		for _ in 0..count {
+			robot.send_to(&EgmRobot::default().encode_to_vec(), address).unwrap();
+		}
+		while receiver.latest().map(|x| x.count) != Some(target) {
+			std::thread::sleep(Duration::from_millis(1));
+		}
+	};
+
+	let mut decimated = receiver.decimated(Decimation::EveryNth(3));
+	assert!(decimated.next(Duration::from_millis(10)) == None);
+	send(10);
+	assert!(decimated.next(Duration::from_millis(10)).map(|x| x.count) == Some(10));
+	assert!(decimated.next(Duration::from_millis(10)) == None);
+	send(2);
+	assert!(decimated.next(Duration::from_millis(10)) == None);
+	send(1);
+	assert!(decimated.next(Duration::from_millis(10)).map(|x| x.count) == Some(13));
+
+	let mut decimated = receiver.decimated(Decimation::Interval(Duration::from_secs(60)));
+	assert!(decimated.next(Duration::from_millis(10)).map(|x| x.count) == Some(13));
+	send(5);
+	assert!(decimated.next(Duration::from_millis(10)) == None);
+}
+
+#[cfg(test)]
+#[test]
+fn test_decimation_rate() {
+	use assert2::assert;
+
+	assert!(Decimation::rate(50.0) == Some(Decimation::Interval(Duration::from_millis(20))));
+	assert!(Decimation::rate(f64::INFINITY) == Some(Decimation::Interval(Duration::ZERO)));
+	assert!(Decimation::rate(0.0) == None);
+	assert!(Decimation::rate(-10.0) == None);
+	assert!(Decimation::rate(f64::NAN) == None);
+	assert!(Decimation::rate(1e-30) == None);
+}
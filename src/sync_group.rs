@@ -0,0 +1,245 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::msg::EgmRobot;
+use crate::msg::EgmSensor;
+use crate::tokio_peer::EgmPeer;
+use crate::InvalidMessageError;
+use crate::ReceiveError;
+use crate::SendError;
+
+/// The state of all units of a [`SyncGroup`], received in the same control cycle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyncCycle {
+	/// The messages from the units, in the order of the peers in the group.
+	pub states: Vec<EgmRobot>,
+
+	/// The largest difference between the feedback times of the units.
+	///
+	/// This is `None` if a message has no feedback time.
+	pub skew: Option<Duration>,
+}
+
+/// A unit of a sync group.
+#[derive(Debug)]
+struct Unit {
+	peer: EgmPeer,
+	address: Option<SocketAddr>,
+}
+
+/// Group of asynchronous peers with aligned control cycles, for coordinated cells.
+///
+/// Each unit, such as a robot or an external axis controlled through a second EGM channel,
+/// has its own EGM channel on a separate UDP port, and sends messages in its own phase.
+/// The group acts as a barrier: [`SyncGroup::recv`] waits for a message from every unit
+/// and discards stale messages from units that lag behind,
+/// so targets for all units can be computed from the feedback of the same cycle.
+///
+/// This generalizes [`crate::dual_arm::DualArmPeer`] to any number of units.
+#[derive(Debug)]
+pub struct SyncGroup {
+	units: Vec<Unit>,
+	max_skew: Duration,
+}
+
+impl SyncGroup {
+	/// Create a sync group from existing peers.
+	///
+	/// The peers do not need to be connected:
+	/// the remote addresses are remembered from the messages received by [`SyncGroup::recv`].
+	///
+	/// The maximum skew between the feedback times of the units defaults to 2 milliseconds.
+	pub fn new(peers: impl IntoIterator<Item = EgmPeer>) -> Self {
+		Self {
+			units: peers.into_iter().map(|peer| Unit { peer, address: None }).collect(),
+			max_skew: Duration::from_millis(2),
+		}
+	}
+
+	/// Set the maximum difference between the feedback times of the units in one cycle.
+	///
+	/// This only works for units that share the clock of one robot controller.
+	pub fn with_max_skew(mut self, max_skew: Duration) -> Self {
+		self.max_skew = max_skew;
+		self
+	}
+
+	/// Get the number of units in the group.
+	pub fn len(&self) -> usize {
+		self.units.len()
+	}
+
+	/// Check if the group has no units.
+	pub fn is_empty(&self) -> bool {
+		self.units.is_empty()
+	}
+
+	/// Get a shared reference to the peer of a unit.
+	pub fn peer(&self, index: usize) -> Option<&EgmPeer> {
+		self.units.get(index).map(|x| &x.peer)
+	}
+
+	/// Consume self and get the peers of all units.
+	pub fn into_peers(self) -> Vec<EgmPeer> {
+		self.units.into_iter().map(|x| x.peer).collect()
+	}
+
+	/// Purge all messages from the read queues of all units.
+	pub fn purge_read_queues(&self) -> std::io::Result<()> {
+		for unit in &self.units {
+			unit.peer.purge_read_queue()?;
+		}
+		Ok(())
+	}
+
+	/// Receive one message from each unit, aligned to the same control cycle.
+	///
+	/// This waits until a message has been received from all units.
+	/// Then, units with a feedback time more than the maximum skew behind the newest message receive again,
+	/// until the messages are aligned.
+	/// To avoid waiting forever for units that do not share a clock, this is attempted a limited number of times:
+	/// check [`SyncCycle::skew`] if alignment is required.
+	///
+	/// The addresses of the units are remembered for [`SyncGroup::send`].
+	pub async fn recv(&mut self) -> Result<SyncCycle, ReceiveError> {
+		let mut states = Vec::with_capacity(self.units.len());
+		for unit in &mut self.units {
+			let (state, address) = unit.peer.recv_from().await?;
+			unit.address = Some(address);
+			states.push(state);
+		}
+
+		for _ in 0..4 * self.units.len() {
+			let times: Option<Vec<Duration>> = states.iter().map(|x| x.feedback_time().map(|x| x.elapsed_since_epoch())).collect();
+			let times = match times {
+				Some(times) => times,
+				None => break,
+			};
+			let newest = times.iter().copied().max().unwrap_or_default();
+			let lagging = match times.iter().position(|time| newest - *time > self.max_skew) {
+				Some(index) => index,
+				None => break,
+			};
+			let unit = &mut self.units[lagging];
+			let (state, address) = unit.peer.recv_from().await?;
+			unit.address = Some(address);
+			states[lagging] = state;
+		}
+
+		let skew = skew(&states);
+		Ok(SyncCycle { states, skew })
+	}
+
+	/// Send targets to all units.
+	///
+	/// The targets must be in the order of the peers in the group.
+	/// All messages are validated before anything is sent,
+	/// so an invalid target for one unit never results in only the other units moving.
+	///
+	/// The messages are sent to the addresses of the last messages received with [`SyncGroup::recv`].
+	/// If no messages have been received yet, or the number of targets does not match the number of units, an error is returned.
+	pub async fn send(&mut self, targets: &[EgmSensor]) -> Result<(), SendError> {
+		if targets.len() != self.units.len() {
+			let message = format!("expected {} targets for sync group, got {}", self.units.len(), targets.len());
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, message).into());
+		}
+		for target in targets {
+			InvalidMessageError::check_sensor_msg(target)?;
+		}
+		let addresses: Vec<SocketAddr> = self.units.iter().map(|x| x.address.ok_or_else(no_remote_address)).collect::<Result<_, _>>()?;
+		for ((unit, target), address) in self.units.iter_mut().zip(targets).zip(&addresses) {
+			unit.peer.send_to(target, address).await?;
+		}
+		Ok(())
+	}
+
+	/// Run one control cycle: receive the aligned state of all units, compute the targets and send them.
+	///
+	/// Returns the received state.
+	pub async fn step(&mut self, compute: impl FnOnce(&SyncCycle) -> Vec<EgmSensor>) -> Result<SyncCycle, SyncStepError> {
+		let cycle = self.recv().await.map_err(SyncStepError::Receive)?;
+		let targets = compute(&cycle);
+		self.send(&targets).await.map_err(SyncStepError::Send)?;
+		Ok(cycle)
+	}
+}
+
+/// Error that may occur in a control cycle of a [`SyncGroup`].
+#[derive(Debug)]
+pub enum SyncStepError {
+	/// Receiving the state of the units failed.
+	Receive(ReceiveError),
+
+	/// Sending the targets to the units failed.
+	Send(SendError),
+}
+
+fn skew(states: &[EgmRobot]) -> Option<Duration> {
+	let mut oldest: Option<Duration> = None;
+	let mut newest: Option<Duration> = None;
+	for state in states {
+		let time = state.feedback_time()?.elapsed_since_epoch();
+		oldest = Some(oldest.map_or(time, |x| x.min(time)));
+		newest = Some(newest.map_or(time, |x| x.max(time)));
+	}
+	Some(newest? - oldest?)
+}
+
+fn no_remote_address() -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::NotConnected, "no message received from the robot controller yet")
+}
+
+impl std::fmt::Display for SyncStepError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Receive(e) => write!(f, "failed to receive state of sync group: {}", e),
+			Self::Send(e) => write!(f, "failed to send targets to sync group: {}", e),
+		}
+	}
+}
+
+impl std::error::Error for SyncStepError {}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_sync_group() {
+	use crate::msg::{EgmClock, EgmFeedBack};
+	use assert2::{assert, let_assert};
+	use prost::Message;
+
+	let peers = vec![EgmPeer::bind("127.0.0.1:0").await.unwrap(), EgmPeer::bind("127.0.0.1:0").await.unwrap()];
+	let mut group = SyncGroup::new(peers);
+	let robot = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+	let axis = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+	let robot_address = group.peer(0).unwrap().socket().local_addr().unwrap();
+	let axis_address = group.peer(1).unwrap().socket().local_addr().unwrap();
+
+	let state = |msec: u64| {
+		EgmRobot {
+			feed_back: Some(EgmFeedBack {
+				time: Some(EgmClock::new(0, msec * 1000)),
+				..Default::default()
+			}),
+			..Default::default()
+		}
+		.encode_to_vec()
+	};
+
+	// The axis has a stale message queued from the previous cycle.
+	axis.send_to(&state(0), axis_address).await.unwrap();
+	axis.send_to(&state(4), axis_address).await.unwrap();
+	robot.send_to(&state(4), robot_address).await.unwrap();
+
+	let targets = [EgmSensor::joint_target(1, [1.0; 6], EgmClock::new(0, 0)), EgmSensor::joint_target(1, vec![2.0], EgmClock::new(0, 0))];
+	let_assert!(Ok(cycle) = group.step(|_| targets.to_vec()).await);
+	assert!(cycle.skew == Some(Duration::ZERO));
+	assert!(cycle.states[1].feedback_time().unwrap().elapsed_since_epoch() == Duration::from_millis(4));
+
+	let mut buffer = [0; 1024];
+	let len = robot.recv(&mut buffer).await.unwrap();
+	assert!(EgmSensor::decode(&buffer[..len]).unwrap() == targets[0]);
+	let len = axis.recv(&mut buffer).await.unwrap();
+	assert!(EgmSensor::decode(&buffer[..len]).unwrap() == targets[1]);
+
+	assert!(let Err(SendError::Io(_)) = group.send(&targets[..1]).await);
+}
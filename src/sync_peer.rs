@@ -1,30 +1,111 @@
-use std::net::SocketAddr;
-
-use prost::Message;
 use std::net::UdpSocket;
+use std::time::{Duration, Instant};
 
-use crate::InvalidMessageError;
+use crate::encode_buffer::EncodeBuffer;
+use crate::middleware::MiddlewareStack;
+use crate::transport::EgmTransport;
 use crate::ReceiveError;
 use crate::SendError;
+use crate::ValidationOptions;
+use crate::clock::{Clock, SystemClock};
+use crate::codec::DecodeLimits;
+use crate::rate::{LoopTimer, RateTimer, SampleTimeEstimator};
+use crate::retry::RetryPolicy;
 use crate::msg::EgmRobot;
 use crate::msg::EgmSensor;
+use crate::msg::EgmSensorPathCorr;
+use crate::subscriber::FeedbackReceiver;
 
 #[derive(Debug)]
-/// Blocking EGM peer for sending and receiving messages over UDP.
-pub struct EgmPeer {
-	socket: UdpSocket,
+/// Blocking EGM peer for sending and receiving messages.
+///
+/// By default, the peer uses a UDP socket.
+/// Other transports can be used by implementing [`EgmTransport`].
+///
+/// The arrival times of messages and the busy-polling timeout are measured with a [`Clock`].
+/// Use [`EgmPeer::with_clock`] with a [`MockClock`](crate::clock::MockClock) to test timing logic without sleeping.
+pub struct EgmPeer<T = UdpSocket, C = SystemClock> {
+	socket: T,
+	encode_buffer: EncodeBuffer,
+	middleware: MiddlewareStack,
+	validation: ValidationOptions,
+	decode_limits: DecodeLimits,
+	retry: RetryPolicy,
+	busy_poll: Option<BusyPoll>,
+	sample_time: SampleTimeEstimator,
+	clock: C,
 }
 
-impl EgmPeer {
-	/// Wrap an existing UDP socket in a peer.
+/// Parameters for busy-polling receive mode.
+///
+/// In busy-polling mode, the peer receives from a non-blocking socket in a loop instead of sleeping in the kernel.
+/// This avoids the wake-up latency of the receiving thread, at the cost of keeping a CPU core busy.
+/// Only use it with a thread that has a dedicated core.
+///
+/// The peer spins for the spin budget, and then yields to the scheduler between attempts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BusyPoll {
+	/// The time to spin without yielding to the scheduler, measured from the start of each receive call.
+	pub spin: Duration,
+
+	/// The time after which a receive call fails with [`std::io::ErrorKind::TimedOut`], if any.
 	///
-	/// If you want to use the [`EgmPeer::recv`] and [`EgmPeer::send`] functions,
-	/// you should use an already connected socket.
-	/// Otherwise, you can only use [`EgmPeer::recv_from`] and [`EgmPeer::send_to`].
-	pub fn new(socket: UdpSocket) -> Self {
-		Self { socket }
+	/// The read timeout of the socket has no effect in non-blocking mode,
+	/// so [`EgmPeer::set_busy_poll`] uses it when this is `None`.
+	pub timeout: Option<Duration>,
+}
+
+impl Default for BusyPoll {
+	fn default() -> Self {
+		Self {
+			spin: Duration::from_millis(4),
+			timeout: None,
+		}
+	}
+}
+
+impl BusyPoll {
+	/// Call `recv` until it returns something other than [`std::io::ErrorKind::WouldBlock`], measuring the spin time and timeout with a clock.
+	fn poll_with_clock<R>(&self, clock: &impl Clock, mut recv: impl FnMut() -> std::io::Result<R>) -> std::io::Result<R> {
+		let start = clock.now();
+		loop {
+			match recv() {
+				Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => (),
+				result => return result,
+			}
+			let elapsed = clock.now().saturating_duration_since(start);
+			if self.timeout.map(|timeout| elapsed >= timeout) == Some(true) {
+				return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out while busy-polling for a message"));
+			}
+			if elapsed < self.spin {
+				std::hint::spin_loop();
+			} else {
+				std::thread::yield_now();
+			}
+		}
 	}
+}
+
+/// The result of receiving a single message with [`EgmPeer::recv_batch_from`].
+pub type BatchMessage = Result<(EgmRobot, std::net::SocketAddr), ReceiveError>;
+
+/// Blocking EGM peer that is not connected to a remote address.
+///
+/// This is an alias for [`EgmPeer`], which can only send and receive with explicit addresses.
+pub type UnconnectedEgmPeer<T = UdpSocket, C = SystemClock> = EgmPeer<T, C>;
+
+/// Blocking EGM peer with a socket connected to the robot controller.
+///
+/// Unlike [`EgmPeer`], this peer can send and receive without specifying the remote address.
+/// Messages from other addresses are discarded by the socket.
+///
+/// Create it with [`EgmPeer::connect`], or with [`ConnectedEgmPeer::new`] from an already connected socket.
+#[derive(Debug)]
+pub struct ConnectedEgmPeer<T = UdpSocket, C = SystemClock> {
+	peer: EgmPeer<T, C>,
+}
 
+impl EgmPeer<UdpSocket> {
 	/// Create an EGM peer on a newly bound UDP socket.
 	///
 	/// The socket will not be connected to a remote peer,
@@ -33,43 +114,21 @@ impl EgmPeer {
 		Ok(Self::new(UdpSocket::bind(addrs)?))
 	}
 
-	/// Get a shared reference to the inner socket.
-	pub fn socket(&self) -> &UdpSocket {
-		&self.socket
-	}
-
-	/// Get an exclusive reference to the inner socket.
-	pub fn socket_mut(&mut self) -> &mut UdpSocket {
-		&mut self.socket
-	}
-
-	/// Consume self and get the inner socket.
-	pub fn into_socket(self) -> UdpSocket {
-		self.socket
-	}
-
-	/// Receive a message from the remote address to which the inner socket is connected.
+	/// Spawn a background thread that continuously receives messages with this peer.
 	///
-	/// To use this function, you must pass an already connected socket to [`EgmPeer::new`].
-	/// If the peer was created with an unconnected socket, this function will panic.
-	pub fn recv(&mut self) -> Result<EgmRobot, ReceiveError> {
-		let mut buffer = vec![0u8; 1024];
-		let bytes_received = self.socket.recv(&mut buffer)?;
-		Ok(EgmRobot::decode(&buffer[..bytes_received])?)
-	}
-
-	/// Receive a message from any remote address.
-	pub fn recv_from(&mut self) -> Result<(EgmRobot, SocketAddr), ReceiveError> {
-		let mut buffer = vec![0u8; 1024];
-		let (bytes_received, sender) = self.socket.recv_from(&mut buffer)?;
-		Ok((EgmRobot::decode(&buffer[..bytes_received])?, sender))
+	/// The returned handle keeps the latest state available for any number of threads.
+	/// See [`FeedbackReceiver`] for details.
+	pub fn spawn_receiver(self) -> std::io::Result<FeedbackReceiver> {
+		FeedbackReceiver::spawn(self)
 	}
+}
 
+impl<C: Clock> EgmPeer<UdpSocket, C> {
 	/// Purge all messages from the socket read queue.
 	///
 	/// Useful to ignore old messages when the socket has been left unpolled for a while.
 	///
-	/// This will leave the socket in blocking mode when the purging is done.
+	/// This will leave the socket in blocking mode when the purging is done, unless busy-polling is enabled.
 	pub fn purge_recv_queue(&mut self) -> std::io::Result<()> {
 		self.socket.set_nonblocking(true)?;
 
@@ -89,29 +148,738 @@ impl EgmPeer {
 
 		// Restore blocking mode, but make sure we return potential errors from the read loop
 		// before errors in restoring blocking mode.
-		let restore_blocking_result = self.socket.set_nonblocking(false);
+		let restore_blocking_result = self.socket.set_nonblocking(self.busy_poll.is_some());
 		read_loop_result?;
 		restore_blocking_result
 	}
 
-	/// Send a message to the remote address to which the inner socket is connected.
+	/// Enable or disable busy-polling receive mode.
+	///
+	/// This puts the socket in non-blocking mode when enabled, and back in blocking mode when disabled.
+	/// If the busy-polling parameters have no timeout, the current read timeout of the socket is used instead.
+	/// See [`BusyPoll`] for details.
+	pub fn set_busy_poll(&mut self, busy_poll: Option<BusyPoll>) -> std::io::Result<()> {
+		let busy_poll = match busy_poll {
+			Some(BusyPoll { spin, timeout: None }) => Some(BusyPoll {
+				spin,
+				timeout: self.socket.read_timeout()?,
+			}),
+			busy_poll => busy_poll,
+		};
+		self.socket.set_nonblocking(busy_poll.is_some())?;
+		self.busy_poll = busy_poll;
+		Ok(())
+	}
+
+	/// Receive up to `max` messages from any remote address with as few system calls as possible.
+	///
+	/// Waits for the first message, and then only returns messages that are already queued.
+	/// With the `mmsg` feature on Linux, all messages are received with a single `recvmmsg` system call.
+	///
+	/// Each message is decoded and passed through the middleware separately,
+	/// so a single invalid message does not affect the others.
+	pub fn recv_batch_from(&mut self, max: usize) -> std::io::Result<Vec<BatchMessage>> {
+		let mut buffers = vec![self.recv_buffer(); max];
+		let nonblocking = self.busy_poll.is_some();
+		let received = self.recv_datagram(|socket| crate::batch::recv_batch(socket, &mut buffers, nonblocking))?;
+		let messages = received
+			.into_iter()
+			.zip(&buffers)
+			.map(|((bytes_received, sender), buffer)| {
+				let msg = self.decode(&buffer[..bytes_received])?;
+				Ok((self.middleware.process_incoming(msg)?, sender))
+			})
+			.collect();
+		Ok(messages)
+	}
+
+	/// Send messages to the specified addresses with as few system calls as possible.
+	///
+	/// With the `mmsg` feature on Linux, all messages are sent with a single `sendmmsg` system call.
+	///
+	/// All messages are passed through the middleware and validated before anything is sent.
+	/// Returns the number of messages that were sent,
+	/// which can be less than the number of messages if sending failed after the first message.
+	pub fn send_batch_to(&mut self, messages: &[(EgmSensor, std::net::SocketAddr)]) -> Result<usize, SendError> {
+		let mut encoded = Vec::with_capacity(messages.len());
+		for (msg, target) in messages {
+			let msg = self.middleware.process_outgoing(msg)?;
+			msg.validate_with(&self.validation)?;
+			encoded.push((prost::Message::encode_to_vec(&*msg), *target));
+		}
+		let datagrams: Vec<_> = encoded.iter().map(|(buffer, target)| (buffer.as_slice(), *target)).collect();
+		let sent = crate::batch::send_batch(&self.socket, &datagrams)?;
+		for (bytes_sent, (buffer, _)) in sent.iter().zip(&encoded) {
+			crate::error::check_transfer(*bytes_sent, buffer.len())?;
+		}
+		Ok(sent.len())
+	}
+
+	/// Connect the socket to the robot controller.
+	///
+	/// The returned peer can use [`ConnectedEgmPeer::recv`] and [`ConnectedEgmPeer::send`].
+	pub fn connect(self, address: std::net::SocketAddr) -> std::io::Result<ConnectedEgmPeer<UdpSocket, C>> {
+		self.socket.connect(address)?;
+		Ok(ConnectedEgmPeer { peer: self })
+	}
+
+	/// Wait for the first message, and connect the socket to its sender.
+	///
+	/// Returns the connected peer and the first message.
+	/// Messages that fail to decode or are rejected by the middleware are skipped,
+	/// so a stray datagram does not connect the peer to the wrong address.
+	pub fn connect_to_first_sender(mut self) -> Result<(ConnectedEgmPeer<UdpSocket, C>, EgmRobot), ReceiveError> {
+		loop {
+			match self.recv_from() {
+				Ok((state, sender)) => return Ok((self.connect(sender)?, state)),
+				Err(ReceiveError::Io(e)) => return Err(ReceiveError::Io(e)),
+				Err(_) => continue,
+			}
+		}
+	}
+}
+
+impl<T: EgmTransport> EgmPeer<T> {
+	/// Wrap an existing UDP socket or other transport in a peer.
+	///
+	/// The peer can only use [`EgmPeer::recv_from`] and [`EgmPeer::send_to`].
+	/// Use [`ConnectedEgmPeer`] for a socket that is connected to the robot controller.
+	pub fn new(socket: T) -> Self {
+		Self {
+			socket,
+			encode_buffer: EncodeBuffer::new(),
+			middleware: MiddlewareStack::new(),
+			validation: ValidationOptions::default(),
+			decode_limits: DecodeLimits::default(),
+			retry: RetryPolicy::default(),
+			busy_poll: None,
+			sample_time: SampleTimeEstimator::default(),
+			clock: SystemClock,
+		}
+	}
+}
+
+impl<T: EgmTransport, C: Clock> EgmPeer<T, C> {
+	/// Use a custom clock to measure the arrival times of messages and the busy-polling timeout, such as a [`MockClock`](crate::clock::MockClock) in tests.
+	pub fn with_clock<D: Clock>(self, clock: D) -> EgmPeer<T, D> {
+		EgmPeer {
+			socket: self.socket,
+			encode_buffer: self.encode_buffer,
+			middleware: self.middleware,
+			validation: self.validation,
+			decode_limits: self.decode_limits,
+			retry: self.retry,
+			busy_poll: self.busy_poll,
+			sample_time: self.sample_time,
+			clock,
+		}
+	}
+
+	/// Get the parameters of busy-polling receive mode, or `None` if it is disabled.
+	pub fn busy_poll(&self) -> Option<&BusyPoll> {
+		self.busy_poll.as_ref()
+	}
+
+	/// Enable or disable busy-polling receive mode for a transport that is already non-blocking.
+	///
+	/// The transport must return [`std::io::ErrorKind::WouldBlock`] when no datagram is available.
+	/// For UDP sockets, use [`EgmPeer::set_busy_poll`] instead, which also changes the blocking mode of the socket.
+	pub fn set_busy_poll_nonblocking(&mut self, busy_poll: Option<BusyPoll>) {
+		self.busy_poll = busy_poll;
+	}
+
+	/// Receive a datagram, busy-polling if enabled.
+	fn recv_datagram<R>(&mut self, mut recv: impl FnMut(&mut T) -> std::io::Result<R>) -> std::io::Result<R> {
+		let socket = &mut self.socket;
+		match &self.busy_poll {
+			Some(busy_poll) => busy_poll.poll_with_clock(&self.clock, || recv(socket)),
+			None => recv(socket),
+		}
+	}
+
+	/// Receive a single datagram, and get its arrival time.
+	fn recv_message<R>(&mut self, recv: impl FnMut(&mut T) -> std::io::Result<R>) -> std::io::Result<(R, Instant)> {
+		let result = self.recv_datagram(recv)?;
+		Ok((result, self.clock.now()))
+	}
+
+	/// Decode a received datagram and pass it through the middleware.
+	///
+	/// The arrival is only recorded for the sample time measurement if the message is accepted,
+	/// so stray datagrams and rejected messages do not disturb the measurement.
+	fn accept_message(&mut self, data: &[u8], arrival: Instant) -> Result<EgmRobot, ReceiveError> {
+		let msg = self.decode(data)?;
+		let msg = self.middleware.process_incoming(msg)?;
+		self.sample_time.record(arrival);
+		Ok(msg)
+	}
+
+	/// Get the sample time of the controller, measured from the arrival times of received messages.
+	///
+	/// Returns `None` until enough messages were received, by default 51.
+	/// Messages received with [`EgmPeer::recv_batch_from`] are not measured.
+	/// See [`SampleTimeEstimator`] for details.
+	pub fn measured_sample_time(&self) -> Option<Duration> {
+		self.sample_time.sample_time()
+	}
+
+	/// Create a timer at the measured sample time, with the first tick one period after the last received message.
+	///
+	/// Returns `None` until the sample time has been measured.
+	pub fn rate_timer(&self) -> Option<RateTimer<C>>
+	where
+		C: Clone,
+	{
+		RateTimer::from_estimator_with_clock(&self.sample_time, self.clock.clone())
+	}
+
+	/// Create a loop timer with the measured sample time as budget, or the default budget if it has not been measured yet.
+	pub fn loop_timer(&self) -> LoopTimer {
+		self.measured_sample_time().map(LoopTimer::new).unwrap_or_default()
+	}
+
+	/// Set the number of inter-arrival times used to measure the sample time, and restart the measurement.
+	pub fn set_sample_time_window(&mut self, window: usize) {
+		self.sample_time = SampleTimeEstimator::new(window);
+	}
+
+	/// Get a shared reference to the inner socket.
+	pub fn socket(&self) -> &T {
+		&self.socket
+	}
+
+	/// Get an exclusive reference to the inner socket.
+	pub fn socket_mut(&mut self) -> &mut T {
+		&mut self.socket
+	}
+
+	/// Get a shared reference to the middleware stack of the peer.
+	pub fn middleware(&self) -> &MiddlewareStack {
+		&self.middleware
+	}
+
+	/// Get an exclusive reference to the middleware stack of the peer.
+	///
+	/// Use this to add hooks that are invoked for every sent and received message.
+	pub fn middleware_mut(&mut self) -> &mut MiddlewareStack {
+		&mut self.middleware
+	}
+
+	/// Get the options used to validate messages before they are sent.
+	pub fn validation_options(&self) -> &ValidationOptions {
+		&self.validation
+	}
+
+	/// Set the options used to validate messages before they are sent.
+	pub fn set_validation_options(&mut self, options: ValidationOptions) {
+		self.validation = options;
+	}
+
+	/// Get the limits used to decode received messages.
+	pub fn decode_limits(&self) -> &DecodeLimits {
+		&self.decode_limits
+	}
+
+	/// Set the limits used to decode received messages.
+	///
+	/// Datagrams longer than [`DecodeLimits::max_len`] are rejected with [`DecodeError::TooLong`](crate::codec::DecodeError::TooLong).
+	pub fn set_decode_limits(&mut self, limits: DecodeLimits) {
+		self.decode_limits = limits;
+	}
+
+	/// Allocate a receive buffer one byte longer than the maximum message length.
+	///
+	/// Longer datagrams are truncated by the operating system, so the extra byte is needed to detect them.
+	fn recv_buffer(&self) -> Vec<u8> {
+		vec![0u8; self.decode_limits.max_len.saturating_add(1)]
+	}
+
+	/// Decode a received datagram with the decode limits of the peer.
+	fn decode(&self, data: &[u8]) -> Result<EgmRobot, crate::codec::DecodeError> {
+		crate::codec::decode_robot_with(data, &self.decode_limits)
+	}
+
+	/// Get the policy for retrying sends that fail with a transient error.
+	pub fn retry_policy(&self) -> &RetryPolicy {
+		&self.retry
+	}
+
+	/// Set the policy for retrying sends that fail with a transient error.
+	///
+	/// By default, sends are not retried.
+	pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+		self.retry = policy;
+	}
+
+	/// Consume self and get the inner socket.
+	pub fn into_socket(self) -> T {
+		self.socket
+	}
+
+	/// Receive a message from any remote address.
+	pub fn recv_from(&mut self) -> Result<(EgmRobot, T::Address), ReceiveError> {
+		let mut buffer = self.recv_buffer();
+		let ((bytes_received, sender), arrival) = self.recv_message(|socket| socket.recv_from(&mut buffer))?;
+		Ok((self.accept_message(&buffer[..bytes_received], arrival)?, sender))
+	}
+
+	/// Send a message to the specified address.
+	pub fn send_to(&mut self, msg: &EgmSensor, target: &T::Address) -> Result<(), SendError> {
+		let msg = self.middleware.process_outgoing(msg)?;
+		msg.validate_with(&self.validation)?;
+		let buffer = self.encode_buffer.encode(&*msg)?;
+		let socket = &mut self.socket;
+		let bytes_sent = self.retry.send(|| socket.send_to(buffer, target))?;
+		crate::error::check_transfer(bytes_sent, buffer.len())?;
+		Ok(())
+	}
+
+	/// Send a path correction message to the specified address.
+	///
+	/// Path correction messages are not passed through the middleware stack.
+	pub fn send_path_correction_to(&mut self, msg: &EgmSensorPathCorr, target: &T::Address) -> Result<(), SendError> {
+		msg.validate_with(&self.validation)?;
+		let buffer = self.encode_buffer.encode(msg)?;
+		let socket = &mut self.socket;
+		let bytes_sent = self.retry.send(|| socket.send_to(buffer, target))?;
+		crate::error::check_transfer(bytes_sent, buffer.len())?;
+		Ok(())
+	}
+}
+
+impl ConnectedEgmPeer<UdpSocket> {
+	/// Wrap an already connected UDP socket in a peer.
+	///
+	/// Returns an error if the socket is not connected.
+	pub fn new(socket: UdpSocket) -> std::io::Result<Self> {
+		socket.peer_addr()?;
+		Ok(Self { peer: EgmPeer::new(socket) })
+	}
+}
+
+impl<C: Clock> ConnectedEgmPeer<UdpSocket, C> {
+	/// Get the address of the robot controller.
+	pub fn remote_address(&self) -> std::io::Result<std::net::SocketAddr> {
+		self.peer.socket.peer_addr()
+	}
+
+	/// Purge all messages from the socket read queue.
+	///
+	/// See [`EgmPeer::purge_recv_queue`].
+	pub fn purge_recv_queue(&mut self) -> std::io::Result<()> {
+		self.peer.purge_recv_queue()
+	}
+
+	/// Enable or disable busy-polling receive mode.
+	///
+	/// This puts the socket in non-blocking mode when enabled, and back in blocking mode when disabled.
+	/// If the busy-polling parameters have no timeout, the current read timeout of the socket is used instead.
+	/// See [`BusyPoll`] for details.
+	pub fn set_busy_poll(&mut self, busy_poll: Option<BusyPoll>) -> std::io::Result<()> {
+		self.peer.set_busy_poll(busy_poll)
+	}
+
+	/// Enable receive timestamps from the kernel or network card.
+	///
+	/// Use [`ConnectedEgmPeer::recv_with_timestamp`] to get the timestamps.
+	/// See [`TimestampSource`][crate::timestamping::TimestampSource] for the clock of the timestamps.
+	#[cfg(feature = "timestamping")]
+	pub fn enable_timestamps(&mut self, source: crate::timestamping::TimestampSource) -> std::io::Result<()> {
+		crate::timestamping::enable_timestamps(&self.peer.socket, source)
+	}
+
+	/// Receive a message from the robot controller, together with the receive timestamp of the datagram.
+	///
+	/// The timestamp is `None` if timestamps are not enabled, or if the kernel did not provide one.
+	#[cfg(feature = "timestamping")]
+	pub fn recv_with_timestamp(&mut self) -> Result<(EgmRobot, Option<std::time::SystemTime>), ReceiveError> {
+		let mut buffer = self.peer.recv_buffer();
+		let ((bytes_received, timestamp), arrival) = self.peer.recv_message(|socket| crate::timestamping::recv_with_timestamp(socket, &mut buffer))?;
+		Ok((self.peer.accept_message(&buffer[..bytes_received], arrival)?, timestamp))
+	}
+}
+
+impl<T: EgmTransport> ConnectedEgmPeer<T> {
+	/// Wrap a transport that is connected to a single remote address, such as a mock or a relay.
+	///
+	/// The transport must support [`EgmTransport::recv`] and [`EgmTransport::send`].
+	pub fn from_transport(transport: T) -> Self {
+		Self { peer: EgmPeer::new(transport) }
+	}
+}
+
+impl<T: EgmTransport, C: Clock> ConnectedEgmPeer<T, C> {
+	/// Use a custom clock to measure the arrival times of messages and the busy-polling timeout, such as a [`MockClock`](crate::clock::MockClock) in tests.
+	pub fn with_clock<D: Clock>(self, clock: D) -> ConnectedEgmPeer<T, D> {
+		ConnectedEgmPeer {
+			peer: self.peer.with_clock(clock),
+		}
+	}
+
+	/// Get a shared reference to the inner socket.
+	pub fn socket(&self) -> &T {
+		&self.peer.socket
+	}
+
+	/// Get an exclusive reference to the inner socket.
+	pub fn socket_mut(&mut self) -> &mut T {
+		&mut self.peer.socket
+	}
+
+	/// Get a shared reference to the middleware stack of the peer.
+	pub fn middleware(&self) -> &MiddlewareStack {
+		&self.peer.middleware
+	}
+
+	/// Get an exclusive reference to the middleware stack of the peer.
+	///
+	/// Use this to add hooks that are invoked for every sent and received message.
+	pub fn middleware_mut(&mut self) -> &mut MiddlewareStack {
+		&mut self.peer.middleware
+	}
+
+	/// Get the options used to validate messages before they are sent.
+	pub fn validation_options(&self) -> &ValidationOptions {
+		&self.peer.validation
+	}
+
+	/// Set the options used to validate messages before they are sent.
+	pub fn set_validation_options(&mut self, options: ValidationOptions) {
+		self.peer.validation = options;
+	}
+
+	/// Get the limits used to decode received messages.
+	pub fn decode_limits(&self) -> &DecodeLimits {
+		&self.peer.decode_limits
+	}
+
+	/// Set the limits used to decode received messages.
+	///
+	/// Datagrams longer than [`DecodeLimits::max_len`] are rejected with [`DecodeError::TooLong`](crate::codec::DecodeError::TooLong).
+	pub fn set_decode_limits(&mut self, limits: DecodeLimits) {
+		self.peer.decode_limits = limits;
+	}
+
+	/// Get the policy for retrying sends that fail with a transient error.
+	pub fn retry_policy(&self) -> &RetryPolicy {
+		&self.peer.retry
+	}
+
+	/// Set the policy for retrying sends that fail with a transient error.
+	///
+	/// By default, sends are not retried.
+	pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+		self.peer.retry = policy;
+	}
+
+	/// Get the parameters of busy-polling receive mode, or `None` if it is disabled.
+	pub fn busy_poll(&self) -> Option<&BusyPoll> {
+		self.peer.busy_poll()
+	}
+
+	/// Enable or disable busy-polling receive mode for a transport that is already non-blocking.
+	///
+	/// See [`EgmPeer::set_busy_poll_nonblocking`].
+	pub fn set_busy_poll_nonblocking(&mut self, busy_poll: Option<BusyPoll>) {
+		self.peer.set_busy_poll_nonblocking(busy_poll)
+	}
+
+	/// Get the sample time of the controller, measured from the arrival times of received messages.
 	///
-	/// To use this function, you must pass an already connected socket to [`EgmPeer::new`].
-	/// If the peer was created with an unconnected socket, this function will panic.
+	/// See [`EgmPeer::measured_sample_time`].
+	pub fn measured_sample_time(&self) -> Option<Duration> {
+		self.peer.measured_sample_time()
+	}
+
+	/// Create a timer at the measured sample time, with the first tick one period after the last received message.
+	///
+	/// See [`EgmPeer::rate_timer`].
+	pub fn rate_timer(&self) -> Option<RateTimer<C>>
+	where
+		C: Clone,
+	{
+		self.peer.rate_timer()
+	}
+
+	/// Create a loop timer with the measured sample time as budget, or the default budget if it has not been measured yet.
+	pub fn loop_timer(&self) -> LoopTimer {
+		self.peer.loop_timer()
+	}
+
+	/// Set the number of inter-arrival times used to measure the sample time, and restart the measurement.
+	pub fn set_sample_time_window(&mut self, window: usize) {
+		self.peer.set_sample_time_window(window)
+	}
+
+	/// Consume self and get the inner socket.
+	pub fn into_socket(self) -> T {
+		self.peer.socket
+	}
+
+	/// Consume self and get a peer that sends and receives with explicit addresses.
+	///
+	/// The socket stays connected.
+	pub fn into_unconnected(self) -> EgmPeer<T, C> {
+		self.peer
+	}
+
+	/// Receive a message from the robot controller.
+	pub fn recv(&mut self) -> Result<EgmRobot, ReceiveError> {
+		let mut buffer = self.peer.recv_buffer();
+		let (bytes_received, arrival) = self.peer.recv_message(|socket| socket.recv(&mut buffer))?;
+		self.peer.accept_message(&buffer[..bytes_received], arrival)
+	}
+
+	/// Send a message to the robot controller.
 	pub fn send(&mut self, msg: &EgmSensor) -> Result<(), SendError> {
-		InvalidMessageError::check_sensor_msg(msg)?;
-		let buffer = crate::encode_to_vec(msg)?;
-		let bytes_sent = self.socket.send(&buffer)?;
+		let peer = &mut self.peer;
+		let msg = peer.middleware.process_outgoing(msg)?;
+		msg.validate_with(&peer.validation)?;
+		let buffer = peer.encode_buffer.encode(&*msg)?;
+		let socket = &mut peer.socket;
+		let bytes_sent = peer.retry.send(|| socket.send(buffer))?;
 		crate::error::check_transfer(bytes_sent, buffer.len())?;
 		Ok(())
 	}
 
-	/// Send a message to the specified address.
-	pub fn send_to(&mut self, msg: &EgmSensor, target: &SocketAddr) -> Result<(), SendError> {
-		InvalidMessageError::check_sensor_msg(msg)?;
-		let buffer = crate::encode_to_vec(msg)?;
-		let bytes_sent = self.socket.send_to(&buffer, target)?;
+	/// Send a path correction message to the robot controller.
+	///
+	/// Path correction messages are not passed through the middleware stack.
+	pub fn send_path_correction(&mut self, msg: &EgmSensorPathCorr) -> Result<(), SendError> {
+		msg.validate_with(&self.peer.validation)?;
+		let buffer = self.peer.encode_buffer.encode(msg)?;
+		let socket = &mut self.peer.socket;
+		let bytes_sent = self.peer.retry.send(|| socket.send(buffer))?;
 		crate::error::check_transfer(bytes_sent, buffer.len())?;
 		Ok(())
 	}
 }
+
+#[cfg(test)]
+#[test]
+fn test_custom_transport() {
+	use crate::msg::EgmClock;
+	use assert2::assert;
+	use prost::Message;
+	use std::collections::VecDeque;
+
+	/// In-memory transport with numbered addresses.
+	#[derive(Default)]
+	struct Mock {
+		incoming: VecDeque<(Vec<u8>, u32)>,
+		outgoing: Vec<(Vec<u8>, u32)>,
+	}
+
+	impl EgmTransport for Mock {
+		type Address = u32;
+
+		fn recv(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+			self.recv_from(buffer).map(|(len, _)| len)
+		}
+
+		fn recv_from(&mut self, buffer: &mut [u8]) -> std::io::Result<(usize, u32)> {
+			let (datagram, sender) = self.incoming.pop_front().ok_or(std::io::ErrorKind::WouldBlock)?;
+			buffer[..datagram.len()].copy_from_slice(&datagram);
+			Ok((datagram.len(), sender))
+		}
+
+		fn send(&mut self, datagram: &[u8]) -> std::io::Result<usize> {
+			self.send_to(datagram, &0)
+		}
+
+		fn send_to(&mut self, datagram: &[u8], target: &u32) -> std::io::Result<usize> {
+			self.outgoing.push((datagram.to_vec(), *target));
+			Ok(datagram.len())
+		}
+	}
+
+	let mut peer = EgmPeer::new(Mock::default());
+	let state = EgmRobot::default();
+	peer.socket_mut().incoming.push_back((state.encode_to_vec(), 7));
+	assert!(peer.recv_from().unwrap() == (state, 7));
+
+	let target = EgmSensor::joint_target(1, [0.0; 6], EgmClock::new(0, 0));
+	peer.send_to(&target, &7).unwrap();
+	let (datagram, address) = &peer.socket().outgoing[0];
+	assert!(*address == 7);
+	assert!(EgmSensor::decode(datagram.as_slice()).unwrap() == target);
+}
+
+#[cfg(test)]
+#[test]
+fn test_connected_peer() {
+	use crate::msg::EgmClock;
+	use assert2::{assert, let_assert};
+	use prost::Message;
+
+	let robot = UdpSocket::bind("127.0.0.1:0").unwrap();
+	let peer = EgmPeer::bind("127.0.0.1:0").unwrap();
+	let address = peer.socket().local_addr().unwrap();
+	let_assert!(Err(_) = ConnectedEgmPeer::new(UdpSocket::bind("127.0.0.1:0").unwrap()));
+
+	let_assert!(Ok(mut peer) = peer.connect(robot.local_addr().unwrap()));
+	assert!(peer.remote_address().unwrap() == robot.local_addr().unwrap());
+	robot.send_to(&EgmRobot::default().encode_to_vec(), address).unwrap();
+	assert!(peer.recv().unwrap() == EgmRobot::default());
+
+	let target = EgmSensor::joint_target(1, [0.0; 6], EgmClock::new(0, 0));
+	peer.send(&target).unwrap();
+	let mut buffer = [0; 1024];
+	let len = robot.recv(&mut buffer).unwrap();
+	assert!(EgmSensor::decode(&buffer[..len]).unwrap() == target);
+
+	let peer = EgmPeer::bind("127.0.0.1:0").unwrap();
+	let address = peer.socket().local_addr().unwrap();
+	let stray = UdpSocket::bind("127.0.0.1:0").unwrap();
+	stray.send_to(&[0xFF, 0xFF], address).unwrap();
+	robot.send_to(&EgmRobot::default().encode_to_vec(), address).unwrap();
+	let_assert!(Ok((mut peer, state)) = peer.connect_to_first_sender());
+	assert!(state == EgmRobot::default());
+	assert!(peer.remote_address().unwrap() == robot.local_addr().unwrap());
+
+	// Purging drops the queued messages and leaves the socket in blocking mode.
+	robot.send_to(&EgmRobot::default().encode_to_vec(), address).unwrap();
+	robot.send_to(&EgmRobot::default().encode_to_vec(), address).unwrap();
+	std::thread::sleep(Duration::from_millis(10));
+	peer.purge_recv_queue().unwrap();
+	peer.socket().set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+	let_assert!(Err(ReceiveError::Io(e)) = peer.recv());
+	assert!(matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut));
+}
+
+#[cfg(test)]
+#[test]
+fn test_busy_poll() {
+	use assert2::{assert, let_assert};
+	use prost::Message;
+
+	let robot = UdpSocket::bind("127.0.0.1:0").unwrap();
+	let_assert!(Ok(mut peer) = EgmPeer::bind("127.0.0.1:0").unwrap().connect(robot.local_addr().unwrap()));
+	let address = peer.socket().local_addr().unwrap();
+	let busy_poll = BusyPoll {
+		spin: Duration::from_millis(1),
+		timeout: Some(Duration::from_millis(20)),
+	};
+	peer.set_busy_poll(Some(busy_poll)).unwrap();
+	assert!(peer.busy_poll() == Some(&busy_poll));
+
+	let_assert!(Err(ReceiveError::Io(e)) = peer.recv());
+	assert!(e.kind() == std::io::ErrorKind::TimedOut);
+
+	let sender = std::thread::spawn(move || {
+		std::thread::sleep(Duration::from_millis(5));
+		robot.send_to(&EgmRobot::default().encode_to_vec(), address).unwrap();
+	});
+	assert!(let Ok(_) = peer.recv());
+	sender.join().unwrap();
+
+	peer.set_busy_poll(None).unwrap();
+	assert!(peer.busy_poll() == None);
+
+	// Without an explicit timeout, the read timeout of the socket is used.
+	peer.socket().set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+	peer.set_busy_poll(Some(BusyPoll::default())).unwrap();
+	assert!(peer.busy_poll().unwrap().timeout == Some(Duration::from_millis(20)));
+	let_assert!(Err(ReceiveError::Io(e)) = peer.recv());
+	assert!(e.kind() == std::io::ErrorKind::TimedOut);
+}
+
+#[cfg(test)]
+#[test]
+fn test_busy_poll_clock() {
+	use crate::clock::MockClock;
+	use assert2::{assert, let_assert};
+
+	let clock = MockClock::new();
+	let busy_poll = BusyPoll {
+		spin: Duration::from_millis(1),
+		timeout: Some(Duration::from_millis(10)),
+	};
+
+	// Each attempt takes a millisecond of mock time, so the poll times out after ten attempts.
+	let mut attempts = 0;
+	let_assert!(Err(e) = busy_poll.poll_with_clock(&clock, || -> std::io::Result<()> {
+		attempts += 1;
+		clock.advance(Duration::from_millis(1));
+		Err(std::io::ErrorKind::WouldBlock.into())
+	}));
+	assert!(e.kind() == std::io::ErrorKind::TimedOut);
+	assert!(attempts == 10);
+
+	let mut attempts = 0;
+	let_assert!(Ok(3) = busy_poll.poll_with_clock(&clock, || {
+		attempts += 1;
+		if attempts < 3 {
+			Err(std::io::ErrorKind::WouldBlock.into())
+		} else {
+			Ok(attempts)
+		}
+	}));
+}
+
+#[cfg(test)]
+#[test]
+fn test_decode_limits() {
+	use crate::codec::DecodeError;
+	use assert2::{assert, let_assert};
+	use prost::Message;
+
+	let robot = UdpSocket::bind("127.0.0.1:0").unwrap();
+	let_assert!(Ok(mut peer) = EgmPeer::bind("127.0.0.1:0").unwrap().connect(robot.local_addr().unwrap()));
+	let address = peer.socket().local_addr().unwrap();
+
+	// Datagrams longer than the limit are rejected instead of being truncated and decoded.
+	robot.send_to(&[0; 2000], address).unwrap();
+	let_assert!(Err(ReceiveError::Decode(DecodeError::TooLong { len: 1025, max: 1024 })) = peer.recv());
+
+	let state = EgmRobot {
+		header: Some(crate::msg::EgmHeader::data(1, 0)),
+		..Default::default()
+	};
+	peer.set_decode_limits(DecodeLimits { max_len: 4, ..DecodeLimits::DEFAULT });
+	robot.send_to(&state.encode_to_vec(), address).unwrap();
+	let_assert!(Err(ReceiveError::Decode(DecodeError::TooLong { max: 4, .. })) = peer.recv());
+
+	peer.set_decode_limits(DecodeLimits::DEFAULT);
+	robot.send_to(&state.encode_to_vec(), address).unwrap();
+	assert!(peer.recv().unwrap() == state);
+}
+
+#[cfg(test)]
+#[test]
+fn test_measured_sample_time() {
+	use crate::clock::MockClock;
+	use assert2::{assert, let_assert};
+	use prost::Message;
+
+	let clock = MockClock::new();
+	let robot = UdpSocket::bind("127.0.0.1:0").unwrap();
+	let mut peer = EgmPeer::bind("127.0.0.1:0").unwrap().with_clock(clock.clone());
+	let address = peer.socket().local_addr().unwrap();
+	peer.set_sample_time_window(3);
+	assert!(peer.rate_timer().is_none());
+	assert!(peer.loop_timer().budget() == Duration::from_millis(4));
+
+	// Datagrams that fail to decode do not disturb the measurement.
+	let state = EgmRobot::default().encode_to_vec();
+	for _ in 0..3 {
+		robot.send_to(&state, address).unwrap();
+		let_assert!(Ok(_) = peer.recv_from());
+		assert!(peer.measured_sample_time() == None);
+		clock.advance(Duration::from_millis(2));
+		robot.send_to(&[0xFF, 0xFF], address).unwrap();
+		let_assert!(Err(ReceiveError::Decode(_)) = peer.recv_from());
+		clock.advance(Duration::from_millis(6));
+	}
+	robot.send_to(&state, address).unwrap();
+	let_assert!(Ok(_) = peer.recv_from());
+	let arrival = clock.now();
+	assert!(peer.measured_sample_time() == Some(Duration::from_millis(8)));
+	assert!(peer.loop_timer().budget() == Duration::from_millis(8));
+
+	// The rate timer uses the clock of the peer.
+	let_assert!(Some(mut timer) = peer.rate_timer());
+	assert!(timer.period() == Duration::from_millis(8));
+	assert!(timer.wait() == 0);
+	assert!(clock.now() == arrival + Duration::from_millis(8));
+}
@@ -0,0 +1,144 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::msg::{EgmClock, EgmRobot, EgmSensor};
+
+/// Queue of targets stamped with future times of the robot controller clock.
+///
+/// A planner can compute a trajectory ahead of execution and push the targets into the queue.
+/// The control loop polls the queue with every message from the controller,
+/// and the queue releases the target whose time has arrived according to the feedback time of the controller.
+/// Because the times are compared with the clock of the controller, network jitter does not affect the timing.
+///
+/// If several targets became due since the last poll, only the latest one is released,
+/// and the others are counted as skipped.
+#[derive(Clone, Debug, Default)]
+pub struct TargetQueue {
+	targets: VecDeque<(Duration, EgmSensor)>,
+	lead: Duration,
+	skipped: u64,
+}
+
+/// Error indicating that a target has no planned time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MissingTimeError;
+
+impl TargetQueue {
+	/// Create an empty queue.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Release targets this much before their time, to compensate for the latency of the network and the controller.
+	pub fn with_lead(mut self, lead: Duration) -> Self {
+		self.lead = lead;
+		self
+	}
+
+	/// Get the number of queued targets.
+	pub fn len(&self) -> usize {
+		self.targets.len()
+	}
+
+	/// Check if the queue is empty.
+	pub fn is_empty(&self) -> bool {
+		self.targets.is_empty()
+	}
+
+	/// Get the number of targets that were skipped because a later target was due at the same poll.
+	pub fn skipped(&self) -> u64 {
+		self.skipped
+	}
+
+	/// Get the time of the next queued target.
+	pub fn next_time(&self) -> Option<Duration> {
+		self.targets.front().map(|(time, _)| *time)
+	}
+
+	/// Add a target, using its planned time.
+	pub fn push(&mut self, target: EgmSensor) -> Result<(), MissingTimeError> {
+		let time = target.planned.as_ref().and_then(|x| x.time).ok_or(MissingTimeError)?;
+		self.push_at(time, target);
+		Ok(())
+	}
+
+	/// Add a target to be released at a specific time of the controller clock.
+	///
+	/// Targets may be pushed in any order.
+	/// A target with the same time as a queued target is released after it.
+	pub fn push_at(&mut self, time: EgmClock, target: EgmSensor) {
+		let time = time.elapsed_since_epoch();
+		let index = self.targets.partition_point(|(queued, _)| *queued <= time);
+		self.targets.insert(index, (time, target));
+	}
+
+	/// Get the target to send in response to a message from the controller.
+	///
+	/// Returns `None` if the message has no feedback time, or if no queued target is due.
+	pub fn poll(&mut self, state: &EgmRobot) -> Option<EgmSensor> {
+		self.poll_at(state.feedback_time()?)
+	}
+
+	/// Get the target that is due at a specific time of the controller clock.
+	///
+	/// Returns `None` if no queued target is due.
+	pub fn poll_at(&mut self, now: EgmClock) -> Option<EgmSensor> {
+		let now = now.elapsed_since_epoch() + self.lead;
+		let due = self.targets.partition_point(|(time, _)| *time <= now);
+		if due == 0 {
+			return None;
+		}
+		self.skipped += due as u64 - 1;
+		self.targets.drain(..due).next_back().map(|(_, target)| target)
+	}
+
+	/// Remove all queued targets.
+	pub fn clear(&mut self) {
+		self.targets.clear();
+	}
+}
+
+impl std::fmt::Display for MissingTimeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "target has no planned time")
+	}
+}
+
+impl std::error::Error for MissingTimeError {}
+
+#[cfg(test)]
+#[test]
+fn test_target_queue() {
+	use crate::msg::EgmFeedBack;
+	use assert2::assert;
+
+	let target = |ms: u64| EgmSensor::joint_target(ms as u32, [0.0; 6], EgmClock::new(0, ms * 1000));
+	let seqno = |target: Option<EgmSensor>| target.and_then(|x| x.header?.seqno);
+	let feedback = |ms: u64| EgmRobot {
+		feed_back: Some(EgmFeedBack {
+			time: Some(EgmClock::new(0, ms * 1000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+
+	let mut queue = TargetQueue::new();
+	for ms in [12, 4, 8, 16, 20] {
+		assert!(let Ok(()) = queue.push(target(ms)));
+	}
+	assert!(queue.push(EgmSensor::default()) == Err(MissingTimeError));
+	assert!(queue.len() == 5);
+	assert!(queue.next_time() == Some(Duration::from_millis(4)));
+
+	assert!(seqno(queue.poll(&feedback(0))) == None);
+	assert!(seqno(queue.poll(&feedback(4))) == Some(4));
+	assert!(seqno(queue.poll(&feedback(6))) == None);
+	assert!(seqno(queue.poll(&feedback(13))) == Some(12));
+	assert!(queue.skipped() == 1);
+	assert!(queue.poll(&EgmRobot::default()) == None);
+
+	let mut queue = queue.with_lead(Duration::from_millis(4));
+	assert!(seqno(queue.poll(&feedback(12))) == Some(16));
+	queue.clear();
+	assert!(queue.is_empty());
+}
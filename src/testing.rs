@@ -0,0 +1,219 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use prost::Message;
+
+use crate::msg::{EgmRobot, EgmSensor};
+use crate::transport::EgmTransport;
+
+type Responder = Box<dyn FnMut(&EgmSensor) -> Option<EgmRobot> + Send>;
+
+struct State {
+	responses: VecDeque<EgmRobot>,
+	received: Vec<EgmSensor>,
+	responder: Option<Responder>,
+}
+
+/// In-memory robot controller for unit tests.
+///
+/// The mock implements [`EgmTransport`] (and [`crate::transport::AsyncEgmTransport`] with the `tokio` feature),
+/// so it can be used in place of a UDP socket by the peers:
+/// messages received by the peer come from a scripted queue and messages sent by the peer are recorded.
+///
+/// The mock can be cloned to keep a handle for scripting and assertions after moving it into a peer.
+/// All clones share the same state.
+///
+/// Receiving from an empty queue fails immediately with [`std::io::ErrorKind::WouldBlock`], so tests never hang.
+#[derive(Clone)]
+pub struct MockRobot {
+	state: Arc<Mutex<State>>,
+}
+
+impl MockRobot {
+	/// The address the mock robot uses as source and expects as destination of messages.
+	pub const ADDRESS: SocketAddr = SocketAddr::V4(std::net::SocketAddrV4::new(std::net::Ipv4Addr::LOCALHOST, 6510));
+
+	/// Create a new mock robot without any scripted messages.
+	pub fn new() -> Self {
+		Self {
+			state: Arc::new(Mutex::new(State {
+				responses: VecDeque::new(),
+				received: Vec::new(),
+				responder: None,
+			})),
+		}
+	}
+
+	/// Create a new mock robot that sends the given messages in order.
+	pub fn with_responses(responses: impl IntoIterator<Item = EgmRobot>) -> Self {
+		let robot = Self::new();
+		robot.lock().responses.extend(responses);
+		robot
+	}
+
+	fn lock(&self) -> MutexGuard<'_, State> {
+		self.state.lock().unwrap_or_else(|e| e.into_inner())
+	}
+
+	/// Queue a message to be received by the peer.
+	pub fn push_response(&self, msg: EgmRobot) {
+		self.lock().responses.push_back(msg);
+	}
+
+	/// Set a function that is called for every message sent by the peer.
+	///
+	/// If the function returns a message, it is queued to be received by the peer.
+	/// This can be used to simulate a robot that reacts to the commands it receives.
+	pub fn set_responder(&self, responder: impl FnMut(&EgmSensor) -> Option<EgmRobot> + Send + 'static) {
+		self.lock().responder = Some(Box::new(responder));
+	}
+
+	/// Get the number of queued messages that have not been received by the peer yet.
+	pub fn pending_responses(&self) -> usize {
+		self.lock().responses.len()
+	}
+
+	/// Get a copy of all messages sent by the peer so far.
+	pub fn received(&self) -> Vec<EgmSensor> {
+		self.lock().received.clone()
+	}
+
+	/// Take all messages sent by the peer so far, clearing the record.
+	pub fn take_received(&self) -> Vec<EgmSensor> {
+		std::mem::take(&mut self.lock().received)
+	}
+
+	/// Get the last message sent by the peer.
+	pub fn last_received(&self) -> Option<EgmSensor> {
+		self.lock().received.last().cloned()
+	}
+
+	/// Assert that the peer sent exactly the given messages so far.
+	///
+	/// # Panics
+	/// This function panics if the sent messages differ from the expected messages.
+	#[track_caller]
+	pub fn assert_received(&self, expected: &[EgmSensor]) {
+		let received = self.lock().received.clone();
+		assert!(received == expected, "mock robot received unexpected messages:\n  received: {:?}\n  expected: {:?}", received, expected);
+	}
+
+	fn pop_datagram(&self, buffer: &mut [u8]) -> std::io::Result<usize> {
+		let msg = self.lock().responses.pop_front().ok_or(std::io::ErrorKind::WouldBlock)?;
+		let len = msg.encoded_len();
+		if len > buffer.len() {
+			return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "receive buffer too small for message"));
+		}
+		msg.encode(&mut &mut buffer[..]).map_err(std::io::Error::other)?;
+		Ok(len)
+	}
+
+	fn push_datagram(&self, datagram: &[u8], target: &SocketAddr) -> std::io::Result<usize> {
+		if *target != Self::ADDRESS {
+			return Err(std::io::Error::new(std::io::ErrorKind::AddrNotAvailable, "message sent to wrong address"));
+		}
+		let msg = EgmSensor::decode(datagram).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+		let mut state = self.lock();
+		let state = &mut *state;
+		if let Some(responder) = &mut state.responder {
+			if let Some(response) = responder(&msg) {
+				state.responses.push_back(response);
+			}
+		}
+		state.received.push(msg);
+		Ok(datagram.len())
+	}
+}
+
+impl Default for MockRobot {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl std::fmt::Debug for MockRobot {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let state = self.lock();
+		f.debug_struct("MockRobot")
+			.field("responses", &state.responses.len())
+			.field("received", &state.received.len())
+			.finish()
+	}
+}
+
+impl EgmTransport for MockRobot {
+	type Address = SocketAddr;
+
+	fn recv(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+		self.pop_datagram(buffer)
+	}
+
+	fn recv_from(&mut self, buffer: &mut [u8]) -> std::io::Result<(usize, Self::Address)> {
+		Ok((self.pop_datagram(buffer)?, Self::ADDRESS))
+	}
+
+	fn send(&mut self, datagram: &[u8]) -> std::io::Result<usize> {
+		self.push_datagram(datagram, &Self::ADDRESS)
+	}
+
+	fn send_to(&mut self, datagram: &[u8], target: &Self::Address) -> std::io::Result<usize> {
+		self.push_datagram(datagram, target)
+	}
+}
+
+#[cfg(feature = "tokio")]
+impl crate::transport::AsyncEgmTransport for MockRobot {
+	type Address = SocketAddr;
+
+	fn recv(&self, buffer: &mut [u8]) -> impl std::future::Future<Output = std::io::Result<usize>> + Send {
+		std::future::ready(self.pop_datagram(buffer))
+	}
+
+	fn recv_from(&self, buffer: &mut [u8]) -> impl std::future::Future<Output = std::io::Result<(usize, Self::Address)>> + Send {
+		std::future::ready(self.pop_datagram(buffer).map(|len| (len, Self::ADDRESS)))
+	}
+
+	fn send(&self, datagram: &[u8]) -> impl std::future::Future<Output = std::io::Result<usize>> + Send {
+		std::future::ready(self.push_datagram(datagram, &Self::ADDRESS))
+	}
+
+	fn send_to(&self, datagram: &[u8], target: &Self::Address) -> impl std::future::Future<Output = std::io::Result<usize>> + Send {
+		std::future::ready(self.push_datagram(datagram, target))
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_mock_robot() {
+	use crate::msg::{EgmClock, EgmHeader};
+	use crate::sync_peer::EgmPeer;
+	use assert2::assert;
+
+	let robot = MockRobot::with_responses(vec![EgmRobot {
+		header: Some(EgmHeader::data(1, 0)),
+		..Default::default()
+	}]);
+	robot.set_responder(|msg| {
+		Some(EgmRobot {
+			header: Some(EgmHeader::data(msg.header.as_ref()?.seqno? + 1, 0)),
+			..Default::default()
+		})
+	});
+
+	let mut peer = EgmPeer::new(robot.clone());
+	let (state, address) = peer.recv_from().unwrap();
+	assert!(state.sequence_number() == Some(1));
+	assert!(address == MockRobot::ADDRESS);
+	assert!(let Err(crate::ReceiveError::Io(_)) = peer.recv_from());
+
+	let target = EgmSensor::joint_target(1, [0.0; 6], EgmClock::new(0, 0));
+	peer.send_to(&target, &address).unwrap();
+	robot.assert_received(std::slice::from_ref(&target));
+	assert!(peer.recv_from().unwrap().0.sequence_number() == Some(2));
+
+	let mut peer = crate::sync_peer::ConnectedEgmPeer::from_transport(peer.into_socket());
+	peer.send(&target).unwrap();
+	assert!(peer.recv().unwrap().sequence_number() == Some(2));
+	assert!(robot.pending_responses() == 0);
+}
@@ -0,0 +1,157 @@
+//! Kernel and NIC receive timestamps for UDP sockets.
+//!
+//! Measuring the arrival time of a message with `Instant::now()` after the receive call returns
+//! includes the scheduling delay of the receiving thread.
+//! With socket timestamping, the kernel or the network card records the arrival time of each datagram instead.
+//!
+//! Software timestamps are taken from the system clock, so they can be compared with [`SystemTime::now()`].
+//! Hardware timestamps are taken from the clock of the network card,
+//! which is only comparable to the system clock if it is kept in sync, for example with `phc2sys`.
+//!
+//! Timestamps are only supported on Linux.
+//! On other platforms, the functions return an error of kind [`std::io::ErrorKind::Unsupported`].
+
+use std::io;
+use std::net::UdpSocket;
+use std::time::SystemTime;
+
+/// The source of receive timestamps.
+///
+/// All timestamps of a socket come from the same source, so they are never taken from different clocks.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimestampSource {
+	/// Timestamps taken from the system clock by the kernel when the datagram is received, using `SO_TIMESTAMPNS`.
+	#[default]
+	Software,
+
+	/// Raw timestamps taken from the clock of the network card, using `SO_TIMESTAMPING`.
+	///
+	/// Hardware timestamping must also be enabled on the network interface, for example with `hwstamp_ctl`.
+	/// The clock of the network card must be synchronized to the system clock, for example with `phc2sys`,
+	/// or the timestamps can not be compared with [`SystemTime::now()`].
+	/// Datagrams without a hardware timestamp have no timestamp.
+	RawHardware,
+}
+
+/// Enable receive timestamps on a socket.
+pub fn enable_timestamps(socket: &UdpSocket, source: TimestampSource) -> io::Result<()> {
+	#[cfg(target_os = "linux")]
+	{
+		use std::os::unix::io::AsRawFd;
+		let (option, value) = match source {
+			TimestampSource::Software => (libc::SO_TIMESTAMPNS, 1),
+			TimestampSource::RawHardware => (
+				libc::SO_TIMESTAMPING,
+				(libc::SOF_TIMESTAMPING_RX_HARDWARE | libc::SOF_TIMESTAMPING_RAW_HARDWARE) as libc::c_int,
+			),
+		};
+		let result = unsafe {
+			libc::setsockopt(
+				socket.as_raw_fd(),
+				libc::SOL_SOCKET,
+				option,
+				&value as *const libc::c_int as *const libc::c_void,
+				std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+			)
+		};
+		if result == 0 {
+			Ok(())
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	{
+		let _ = (socket, source);
+		Err(unsupported())
+	}
+}
+
+/// Receive a datagram from the connected remote address, together with its receive timestamp.
+///
+/// The timestamp is `None` if timestamps are not enabled on the socket, or if the kernel did not provide one.
+/// See [`TimestampSource`] for the clock of the timestamp.
+pub fn recv_with_timestamp(socket: &UdpSocket, buffer: &mut [u8]) -> io::Result<(usize, Option<SystemTime>)> {
+	#[cfg(target_os = "linux")]
+	{
+		use std::os::unix::io::AsRawFd;
+
+		// Room for the control message of SO_TIMESTAMPING, which holds three timestamps, with proper alignment.
+		let mut control = [0u64; 16];
+		let mut iov = libc::iovec {
+			iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+			iov_len: buffer.len(),
+		};
+		unsafe {
+			let mut msg: libc::msghdr = std::mem::zeroed();
+			msg.msg_iov = &mut iov;
+			msg.msg_iovlen = 1;
+			msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+			msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+			let bytes_received = libc::recvmsg(socket.as_raw_fd(), &mut msg, 0);
+			if bytes_received < 0 {
+				return Err(io::Error::last_os_error());
+			}
+
+			let mut timestamp = None;
+			let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+			while !cmsg.is_null() {
+				let header = &*cmsg;
+				let data = libc::CMSG_DATA(cmsg);
+				if header.cmsg_level == libc::SOL_SOCKET && header.cmsg_type == libc::SCM_TIMESTAMPNS {
+					timestamp = to_system_time(std::ptr::read_unaligned(data as *const libc::timespec));
+				} else if header.cmsg_level == libc::SOL_SOCKET && header.cmsg_type == libc::SCM_TIMESTAMPING {
+					// Only the raw hardware timestamp is requested, which is the third timestamp.
+					let stamps = std::ptr::read_unaligned(data as *const [libc::timespec; 3]);
+					timestamp = to_system_time(stamps[2]);
+				}
+				cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+			}
+			Ok((bytes_received as usize, timestamp))
+		}
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	{
+		let _ = (socket, buffer);
+		Err(unsupported())
+	}
+}
+
+/// Convert a timestamp from the kernel to [`SystemTime`], or `None` if it is zero.
+#[cfg(target_os = "linux")]
+fn to_system_time(timestamp: libc::timespec) -> Option<SystemTime> {
+	if timestamp.tv_sec == 0 && timestamp.tv_nsec == 0 {
+		None
+	} else {
+		let duration = std::time::Duration::new(timestamp.tv_sec as u64, timestamp.tv_nsec as u32);
+		Some(SystemTime::UNIX_EPOCH + duration)
+	}
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported() -> io::Error {
+	io::Error::new(io::ErrorKind::Unsupported, "socket timestamping is only supported on Linux")
+}
+
+#[cfg(all(test, target_os = "linux"))]
+#[test]
+fn test_software_timestamps() {
+	use assert2::{assert, let_assert};
+
+	let robot = UdpSocket::bind("127.0.0.1:0").unwrap();
+	let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+	socket.connect(robot.local_addr().unwrap()).unwrap();
+	enable_timestamps(&socket, TimestampSource::default()).unwrap();
+
+	let before = SystemTime::now();
+	robot.send_to(&[1, 2, 3], socket.local_addr().unwrap()).unwrap();
+	let mut buffer = [0; 16];
+	let_assert!(Ok((3, Some(timestamp))) = recv_with_timestamp(&socket, &mut buffer));
+	assert!(buffer[..3] == [1, 2, 3]);
+	let_assert!(Ok(age) = SystemTime::now().duration_since(timestamp));
+	assert!(age < std::time::Duration::from_secs(1));
+	assert!(timestamp >= before - std::time::Duration::from_millis(10));
+}
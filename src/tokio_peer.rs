@@ -1,30 +1,55 @@
-use std::net::SocketAddr;
-
-use prost::Message;
 use tokio::net::UdpSocket;
 
-use crate::InvalidMessageError;
+use crate::encode_buffer::EncodeBuffer;
+use crate::middleware::MiddlewareStack;
+use crate::transport::AsyncEgmTransport;
 use crate::ReceiveError;
 use crate::SendError;
+use crate::ValidationOptions;
+use crate::clock::{Clock, SystemClock};
+use crate::codec::DecodeLimits;
+use crate::rate::{AsyncRateTimer, LoopTimer, SampleTimeEstimator};
+use crate::retry::RetryPolicy;
 use crate::msg::EgmRobot;
 use crate::msg::EgmSensor;
+use crate::msg::EgmSensorPathCorr;
 
 #[derive(Debug)]
 /// Asynchronous EGM peer capable of sending and receiving messages.
-pub struct EgmPeer {
-	socket: UdpSocket,
+///
+/// By default, the peer uses a UDP socket.
+/// Other transports can be used by implementing [`AsyncEgmTransport`].
+///
+/// The arrival times of messages are measured with a [`Clock`].
+/// Use [`EgmPeer::with_clock`] with a [`MockClock`](crate::clock::MockClock) to test timing logic without sleeping.
+pub struct EgmPeer<T = UdpSocket, C = SystemClock> {
+	socket: T,
+	encode_buffer: EncodeBuffer,
+	middleware: MiddlewareStack,
+	validation: ValidationOptions,
+	decode_limits: DecodeLimits,
+	retry: RetryPolicy,
+	sample_time: std::sync::Mutex<SampleTimeEstimator>,
+	clock: C,
 }
 
-impl EgmPeer {
-	/// Wrap an existing UDP socket in a peer.
-	///
-	/// If you want to use the [`EgmPeer::recv`] and [`EgmPeer::send`] functions,
-	/// you should use an already connected socket.
-	/// Otherwise, you can only use [`EgmPeer::recv_from`] and [`EgmPeer::send_to`].
-	pub fn new(socket: UdpSocket) -> Self {
-		Self { socket }
-	}
+/// Asynchronous EGM peer that is not connected to a remote address.
+///
+/// This is an alias for [`EgmPeer`], which can only send and receive with explicit addresses.
+pub type UnconnectedEgmPeer<T = UdpSocket, C = SystemClock> = EgmPeer<T, C>;
+
+/// Asynchronous EGM peer with a socket connected to the robot controller.
+///
+/// Unlike [`EgmPeer`], this peer can send and receive without specifying the remote address.
+/// Messages from other addresses are discarded by the socket.
+///
+/// Create it with [`EgmPeer::connect`], or with [`ConnectedEgmPeer::new`] from an already connected socket.
+#[derive(Debug)]
+pub struct ConnectedEgmPeer<T = UdpSocket, C = SystemClock> {
+	peer: EgmPeer<T, C>,
+}
 
+impl EgmPeer<UdpSocket> {
 	/// Create an EGM peer on a newly bound UDP socket.
 	///
 	/// The socket will not be connected to a remote peer,
@@ -47,72 +72,520 @@ impl EgmPeer {
 		let socket = tokio::net::UdpSocket::from_std(socket)?;
 		Ok(Self::new(socket))
 	}
+}
+
+impl<C: Clock> EgmPeer<UdpSocket, C> {
+	/// Stop ICMP port unreachable messages from a rebooting controller from resetting the socket on Windows.
+	///
+	/// See [`crate::net::disable_connection_reset`].
+	pub fn disable_connection_reset(&self) -> std::io::Result<()> {
+		crate::net::disable_connection_reset(&self.socket)
+	}
+
+	/// Purge all messages from the socket read queue.
+	pub fn purge_read_queue(&self) -> std::io::Result<()> {
+		let mut buffer = vec![0; 1024];
+		loop {
+			match self.socket.try_recv_from(&mut buffer) {
+				Ok((0, _)) => return Ok(()),
+				Ok(_) => continue,
+				Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+					return Ok(());
+				},
+				Err(e) => return Err(e),
+			}
+		}
+	}
+
+	/// Connect the socket to the robot controller.
+	///
+	/// The returned peer can use [`ConnectedEgmPeer::recv`] and [`ConnectedEgmPeer::send`].
+	pub async fn connect(self, address: std::net::SocketAddr) -> std::io::Result<ConnectedEgmPeer<UdpSocket, C>> {
+		self.socket.connect(address).await?;
+		Ok(ConnectedEgmPeer { peer: self })
+	}
+
+	/// Wait for the first message, and connect the socket to its sender.
+	///
+	/// Returns the connected peer and the first message.
+	/// Messages that fail to decode or are rejected by the middleware are skipped,
+	/// so a stray datagram does not connect the peer to the wrong address.
+	pub async fn connect_to_first_sender(self) -> Result<(ConnectedEgmPeer<UdpSocket, C>, EgmRobot), ReceiveError> {
+		loop {
+			match self.recv_from().await {
+				Ok((state, sender)) => return Ok((self.connect(sender).await?, state)),
+				Err(ReceiveError::Io(e)) => return Err(ReceiveError::Io(e)),
+				Err(_) => continue,
+			}
+		}
+	}
+}
+
+impl<T: AsyncEgmTransport> EgmPeer<T> {
+	/// Wrap an existing UDP socket or other transport in a peer.
+	///
+	/// The peer can only use [`EgmPeer::recv_from`] and [`EgmPeer::send_to`].
+	/// Use [`ConnectedEgmPeer`] for a socket that is connected to the robot controller.
+	pub fn new(socket: T) -> Self {
+		Self {
+			socket,
+			encode_buffer: EncodeBuffer::new(),
+			middleware: MiddlewareStack::new(),
+			validation: ValidationOptions::default(),
+			decode_limits: DecodeLimits::default(),
+			retry: RetryPolicy::default(),
+			sample_time: Default::default(),
+			clock: SystemClock,
+		}
+	}
+}
+
+impl<T: AsyncEgmTransport, C: Clock> EgmPeer<T, C> {
+	/// Use a custom clock to measure the arrival times of messages, such as a [`MockClock`](crate::clock::MockClock) in tests.
+	pub fn with_clock<D: Clock>(self, clock: D) -> EgmPeer<T, D> {
+		EgmPeer {
+			socket: self.socket,
+			encode_buffer: self.encode_buffer,
+			middleware: self.middleware,
+			validation: self.validation,
+			decode_limits: self.decode_limits,
+			retry: self.retry,
+			sample_time: self.sample_time,
+			clock,
+		}
+	}
+
+	/// Get the sample time of the controller, measured from the arrival times of received messages.
+	///
+	/// Returns `None` until enough messages were received, by default 51.
+	/// See [`SampleTimeEstimator`] for details.
+	pub fn measured_sample_time(&self) -> Option<std::time::Duration> {
+		self.sample_time_estimator().sample_time()
+	}
+
+	/// Create a timer at the measured sample time, with the first tick one period after the last received message.
+	///
+	/// Returns `None` until the sample time has been measured.
+	pub fn rate_timer(&self) -> Option<AsyncRateTimer> {
+		AsyncRateTimer::from_estimator(&self.sample_time_estimator())
+	}
+
+	/// Create a loop timer with the measured sample time as budget, or the default budget if it has not been measured yet.
+	pub fn loop_timer(&self) -> LoopTimer {
+		self.measured_sample_time().map(LoopTimer::new).unwrap_or_default()
+	}
+
+	/// Set the number of inter-arrival times used to measure the sample time, and restart the measurement.
+	pub fn set_sample_time_window(&mut self, window: usize) {
+		*self.sample_time_estimator() = SampleTimeEstimator::new(window);
+	}
+
+	fn sample_time_estimator(&self) -> std::sync::MutexGuard<'_, SampleTimeEstimator> {
+		self.sample_time.lock().unwrap_or_else(|e| e.into_inner())
+	}
 
 	/// Get a shared reference to the inner socket.
-	pub fn socket(&self) -> &UdpSocket {
+	pub fn socket(&self) -> &T {
 		&self.socket
 	}
 
 	/// Get an exclusive reference to the inner socket.
-	pub fn socket_mut(&mut self) -> &mut UdpSocket {
+	pub fn socket_mut(&mut self) -> &mut T {
 		&mut self.socket
 	}
 
-	/// Consume self and get the inner socket.
-	pub fn into_socket(self) -> UdpSocket {
-		self.socket
+	/// Get a shared reference to the middleware stack of the peer.
+	pub fn middleware(&self) -> &MiddlewareStack {
+		&self.middleware
 	}
 
-	/// Receive a message from the remote address to which the inner socket is connected.
+	/// Get an exclusive reference to the middleware stack of the peer.
 	///
-	/// To use this function, you must pass an already connected socket to [`EgmPeer::new`].
-	/// If the peer was created with an unconnected socket, this function will panic.
-	pub async fn recv(&self) -> Result<EgmRobot, ReceiveError> {
-		let mut buffer = vec![0u8; 1024];
-		let bytes_received = self.socket.recv(&mut buffer).await?;
-		Ok(EgmRobot::decode(&buffer[..bytes_received])?)
+	/// Use this to add hooks that are invoked for every sent and received message.
+	pub fn middleware_mut(&mut self) -> &mut MiddlewareStack {
+		&mut self.middleware
+	}
+
+	/// Get the options used to validate messages before they are sent.
+	pub fn validation_options(&self) -> &ValidationOptions {
+		&self.validation
+	}
+
+	/// Set the options used to validate messages before they are sent.
+	pub fn set_validation_options(&mut self, options: ValidationOptions) {
+		self.validation = options;
+	}
+
+	/// Get the limits used to decode received messages.
+	pub fn decode_limits(&self) -> &DecodeLimits {
+		&self.decode_limits
+	}
+
+	/// Set the limits used to decode received messages.
+	///
+	/// Datagrams longer than [`DecodeLimits::max_len`] are rejected with [`DecodeError::TooLong`](crate::codec::DecodeError::TooLong).
+	pub fn set_decode_limits(&mut self, limits: DecodeLimits) {
+		self.decode_limits = limits;
+	}
+
+	/// Allocate a receive buffer one byte longer than the maximum message length.
+	///
+	/// Longer datagrams are truncated by the operating system, so the extra byte is needed to detect them.
+	fn recv_buffer(&self) -> Vec<u8> {
+		vec![0u8; self.decode_limits.max_len.saturating_add(1)]
+	}
+
+	/// Decode a received datagram with the decode limits of the peer.
+	fn decode(&self, data: &[u8]) -> Result<EgmRobot, crate::codec::DecodeError> {
+		crate::codec::decode_robot_with(data, &self.decode_limits)
+	}
+
+	/// Decode a received datagram and pass it through the middleware.
+	///
+	/// The arrival is only recorded for the sample time measurement if the message is accepted,
+	/// so stray datagrams and rejected messages do not disturb the measurement.
+	fn accept_message(&self, data: &[u8], arrival: std::time::Instant) -> Result<EgmRobot, ReceiveError> {
+		let msg = self.decode(data)?;
+		let msg = self.middleware.process_incoming(msg)?;
+		self.sample_time_estimator().record(arrival);
+		Ok(msg)
+	}
+
+	/// Get the policy for retrying sends that fail with a transient error.
+	pub fn retry_policy(&self) -> &RetryPolicy {
+		&self.retry
+	}
+
+	/// Set the policy for retrying sends that fail with a transient error.
+	///
+	/// By default, sends are not retried.
+	pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+		self.retry = policy;
+	}
+
+	/// Consume self and get the inner socket.
+	pub fn into_socket(self) -> T {
+		self.socket
 	}
 
 	/// Receive a message from any remote address.
-	pub async fn recv_from(&self) -> Result<(EgmRobot, SocketAddr), ReceiveError> {
-		let mut buffer = vec![0u8; 1024];
+	pub async fn recv_from(&self) -> Result<(EgmRobot, T::Address), ReceiveError> {
+		let mut buffer = self.recv_buffer();
 		let (bytes_received, sender) = self.socket.recv_from(&mut buffer).await?;
-		Ok((EgmRobot::decode(&buffer[..bytes_received])?, sender))
+		let arrival = self.clock.now();
+		Ok((self.accept_message(&buffer[..bytes_received], arrival)?, sender))
+	}
+
+	/// Send a message to the specified address.
+	pub async fn send_to(&mut self, msg: &EgmSensor, target: &T::Address) -> Result<(), SendError> {
+		let msg = self.middleware.process_outgoing(msg)?;
+		msg.validate_with(&self.validation)?;
+		let buffer = self.encode_buffer.encode(&*msg)?;
+		let socket = &self.socket;
+		let bytes_sent = self.retry.send_async(|| socket.send_to(buffer, target)).await?;
+		crate::error::check_transfer(bytes_sent, buffer.len())?;
+		Ok(())
+	}
+
+	/// Send a path correction message to the specified address.
+	///
+	/// Path correction messages are not passed through the middleware stack.
+	pub async fn send_path_correction_to(&mut self, msg: &EgmSensorPathCorr, target: &T::Address) -> Result<(), SendError> {
+		msg.validate_with(&self.validation)?;
+		let buffer = self.encode_buffer.encode(msg)?;
+		let socket = &self.socket;
+		let bytes_sent = self.retry.send_async(|| socket.send_to(buffer, target)).await?;
+		crate::error::check_transfer(bytes_sent, buffer.len())?;
+		Ok(())
+	}
+}
+
+impl ConnectedEgmPeer<UdpSocket> {
+	/// Wrap an already connected UDP socket in a peer.
+	///
+	/// Returns an error if the socket is not connected.
+	pub fn new(socket: UdpSocket) -> std::io::Result<Self> {
+		socket.peer_addr()?;
+		Ok(Self { peer: EgmPeer::new(socket) })
+	}
+}
+
+impl<C: Clock> ConnectedEgmPeer<UdpSocket, C> {
+	/// Get the address of the robot controller.
+	pub fn remote_address(&self) -> std::io::Result<std::net::SocketAddr> {
+		self.peer.socket.peer_addr()
+	}
+
+	/// Stop ICMP port unreachable messages from a rebooting controller from resetting the socket on Windows.
+	///
+	/// See [`crate::net::disable_connection_reset`].
+	pub fn disable_connection_reset(&self) -> std::io::Result<()> {
+		self.peer.disable_connection_reset()
 	}
 
 	/// Purge all messages from the socket read queue.
+	///
+	/// See [`EgmPeer::purge_read_queue`].
 	pub fn purge_read_queue(&self) -> std::io::Result<()> {
-		let mut buffer = vec![0; 1024];
-		loop {
-			match self.socket.try_recv_from(&mut buffer) {
-				Ok((0, _)) => return Ok(()),
-				Ok(_) => continue,
-				Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-					return Ok(());
-				},
-				Err(e) => return Err(e),
-			}
+		self.peer.purge_read_queue()
+	}
+}
+
+impl<T: AsyncEgmTransport> ConnectedEgmPeer<T> {
+	/// Wrap a transport that is connected to a single remote address, such as a mock or a relay.
+	///
+	/// The transport must support [`AsyncEgmTransport::recv`] and [`AsyncEgmTransport::send`].
+	pub fn from_transport(transport: T) -> Self {
+		Self { peer: EgmPeer::new(transport) }
+	}
+}
+
+impl<T: AsyncEgmTransport, C: Clock> ConnectedEgmPeer<T, C> {
+	/// Use a custom clock to measure the arrival times of messages, such as a [`MockClock`](crate::clock::MockClock) in tests.
+	pub fn with_clock<D: Clock>(self, clock: D) -> ConnectedEgmPeer<T, D> {
+		ConnectedEgmPeer {
+			peer: self.peer.with_clock(clock),
 		}
 	}
 
-	/// Send a message to the remote address to which the inner socket is connected.
+	/// Get a shared reference to the inner socket.
+	pub fn socket(&self) -> &T {
+		&self.peer.socket
+	}
+
+	/// Get an exclusive reference to the inner socket.
+	pub fn socket_mut(&mut self) -> &mut T {
+		&mut self.peer.socket
+	}
+
+	/// Get a shared reference to the middleware stack of the peer.
+	pub fn middleware(&self) -> &MiddlewareStack {
+		&self.peer.middleware
+	}
+
+	/// Get an exclusive reference to the middleware stack of the peer.
 	///
-	/// To use this function, you must pass an already connected socket to [`EgmPeer::new`].
-	/// If the peer was created with an unconnected socket, this function will panic.
+	/// Use this to add hooks that are invoked for every sent and received message.
+	pub fn middleware_mut(&mut self) -> &mut MiddlewareStack {
+		&mut self.peer.middleware
+	}
+
+	/// Get the options used to validate messages before they are sent.
+	pub fn validation_options(&self) -> &ValidationOptions {
+		&self.peer.validation
+	}
+
+	/// Set the options used to validate messages before they are sent.
+	pub fn set_validation_options(&mut self, options: ValidationOptions) {
+		self.peer.validation = options;
+	}
+
+	/// Get the limits used to decode received messages.
+	pub fn decode_limits(&self) -> &DecodeLimits {
+		&self.peer.decode_limits
+	}
+
+	/// Set the limits used to decode received messages.
+	///
+	/// Datagrams longer than [`DecodeLimits::max_len`] are rejected with [`DecodeError::TooLong`](crate::codec::DecodeError::TooLong).
+	pub fn set_decode_limits(&mut self, limits: DecodeLimits) {
+		self.peer.decode_limits = limits;
+	}
+
+	/// Get the policy for retrying sends that fail with a transient error.
+	pub fn retry_policy(&self) -> &RetryPolicy {
+		&self.peer.retry
+	}
+
+	/// Set the policy for retrying sends that fail with a transient error.
+	///
+	/// By default, sends are not retried.
+	pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+		self.peer.retry = policy;
+	}
+
+	/// Consume self and get the inner socket.
+	pub fn into_socket(self) -> T {
+		self.peer.socket
+	}
+
+	/// Get the sample time of the controller, measured from the arrival times of received messages.
+	///
+	/// See [`EgmPeer::measured_sample_time`].
+	pub fn measured_sample_time(&self) -> Option<std::time::Duration> {
+		self.peer.measured_sample_time()
+	}
+
+	/// Create a timer at the measured sample time, with the first tick one period after the last received message.
+	///
+	/// See [`EgmPeer::rate_timer`].
+	pub fn rate_timer(&self) -> Option<AsyncRateTimer> {
+		self.peer.rate_timer()
+	}
+
+	/// Create a loop timer with the measured sample time as budget, or the default budget if it has not been measured yet.
+	pub fn loop_timer(&self) -> LoopTimer {
+		self.peer.loop_timer()
+	}
+
+	/// Set the number of inter-arrival times used to measure the sample time, and restart the measurement.
+	pub fn set_sample_time_window(&mut self, window: usize) {
+		self.peer.set_sample_time_window(window)
+	}
+
+	/// Consume self and get a peer that sends and receives with explicit addresses.
+	///
+	/// The socket stays connected.
+	pub fn into_unconnected(self) -> EgmPeer<T, C> {
+		self.peer
+	}
+
+	/// Receive a message from the robot controller.
+	pub async fn recv(&self) -> Result<EgmRobot, ReceiveError> {
+		let mut buffer = self.peer.recv_buffer();
+		let bytes_received = self.peer.socket.recv(&mut buffer).await?;
+		let arrival = self.peer.clock.now();
+		self.peer.accept_message(&buffer[..bytes_received], arrival)
+	}
+
+	/// Send a message to the robot controller.
 	pub async fn send(&mut self, msg: &EgmSensor) -> Result<(), SendError> {
-		InvalidMessageError::check_sensor_msg(msg)?;
-		let buffer = crate::encode_to_vec(msg)?;
-		let bytes_sent = self.socket.send(&buffer).await?;
+		let peer = &mut self.peer;
+		let msg = peer.middleware.process_outgoing(msg)?;
+		msg.validate_with(&peer.validation)?;
+		let buffer = peer.encode_buffer.encode(&*msg)?;
+		let socket = &peer.socket;
+		let bytes_sent = peer.retry.send_async(|| socket.send(buffer)).await?;
 		crate::error::check_transfer(bytes_sent, buffer.len())?;
 		Ok(())
 	}
 
-	/// Send a message to the specified address.
-	pub async fn send_to(&mut self, msg: &EgmSensor, target: &SocketAddr) -> Result<(), SendError> {
-		InvalidMessageError::check_sensor_msg(msg)?;
-		let buffer = crate::encode_to_vec(msg)?;
-		let bytes_sent = self.socket.send_to(&buffer, target).await?;
+	/// Send a path correction message to the robot controller.
+	///
+	/// Path correction messages are not passed through the middleware stack.
+	pub async fn send_path_correction(&mut self, msg: &EgmSensorPathCorr) -> Result<(), SendError> {
+		msg.validate_with(&self.peer.validation)?;
+		let buffer = self.peer.encode_buffer.encode(msg)?;
+		let socket = &self.peer.socket;
+		let bytes_sent = self.peer.retry.send_async(|| socket.send(buffer)).await?;
 		crate::error::check_transfer(bytes_sent, buffer.len())?;
 		Ok(())
 	}
 }
+
+#[cfg(all(test, target_os = "linux"))]
+#[tokio::test]
+async fn test_controller_restart() {
+	use crate::msg::EgmClock;
+	use assert2::{assert, let_assert};
+	use prost::Message;
+	use std::time::Duration;
+
+	// Connect to an address where the controller is not listening, as if it is rebooting.
+	let address = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+	let_assert!(Ok(mut peer) = EgmPeer::bind("127.0.0.1:0").await.unwrap().connect(address).await);
+	peer.disable_connection_reset().unwrap();
+	let local = peer.socket().local_addr().unwrap();
+
+	let target = EgmSensor::joint_target(0, [0.0; 6], EgmClock::new(0, 0));
+	assert!(let Ok(()) = peer.send(&target).await);
+	let_assert!(Ok(Err(ReceiveError::Io(e))) = tokio::time::timeout(Duration::from_secs(1), peer.recv()).await);
+	assert!(e.kind() == std::io::ErrorKind::ConnectionRefused);
+
+	// The socket keeps working when the controller is back.
+	let robot = std::net::UdpSocket::bind(address).unwrap();
+	robot.send_to(&EgmRobot::default().encode_to_vec(), local).unwrap();
+	let_assert!(Ok(Ok(state)) = tokio::time::timeout(Duration::from_secs(1), peer.recv()).await);
+	assert!(state == EgmRobot::default());
+	assert!(let Ok(()) = peer.send(&target).await);
+	let mut buffer = [0; 1024];
+	let len = robot.recv(&mut buffer).unwrap();
+	assert!(EgmSensor::decode(&buffer[..len]).unwrap() == target);
+}
+
+#[cfg(all(test, windows))]
+#[tokio::test]
+async fn test_controller_restart() {
+	use crate::msg::EgmClock;
+	use assert2::{assert, let_assert};
+	use prost::Message;
+	use std::time::Duration;
+
+	// Connect to an address where the controller is not listening, as if it is rebooting.
+	let address = std::net::UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+	let_assert!(Ok(mut peer) = EgmPeer::bind("127.0.0.1:0").await.unwrap().connect(address).await);
+	peer.disable_connection_reset().unwrap();
+	let local = peer.socket().local_addr().unwrap();
+
+	// The port unreachable message does not reset the socket, so receiving simply waits.
+	let target = EgmSensor::joint_target(0, [0.0; 6], EgmClock::new(0, 0));
+	assert!(let Ok(()) = peer.send(&target).await);
+	assert!(let Err(_) = tokio::time::timeout(Duration::from_millis(200), peer.recv()).await);
+
+	// The socket keeps working when the controller is back.
+	let robot = std::net::UdpSocket::bind(address).unwrap();
+	robot.send_to(&EgmRobot::default().encode_to_vec(), local).unwrap();
+	let_assert!(Ok(Ok(state)) = tokio::time::timeout(Duration::from_secs(1), peer.recv()).await);
+	assert!(state == EgmRobot::default());
+	assert!(let Ok(()) = peer.send(&target).await);
+	let mut buffer = [0; 1024];
+	let len = robot.recv(&mut buffer).unwrap();
+	assert!(EgmSensor::decode(&buffer[..len]).unwrap() == target);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_purge_read_queue() {
+	use assert2::{assert, let_assert};
+	use prost::Message;
+	use std::time::Duration;
+
+	let robot = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+	let_assert!(Ok(peer) = EgmPeer::bind("127.0.0.1:0").await.unwrap().connect(robot.local_addr().unwrap()).await);
+	let local = peer.socket().local_addr().unwrap();
+	let state = EgmRobot {
+		header: Some(crate::msg::EgmHeader::data(1, 0)),
+		..Default::default()
+	};
+
+	// Queued messages are dropped, and later messages are received again.
+	robot.send_to(&state.encode_to_vec(), local).unwrap();
+	robot.send_to(&state.encode_to_vec(), local).unwrap();
+	tokio::time::sleep(Duration::from_millis(10)).await;
+	peer.purge_read_queue().unwrap();
+	assert!(let Err(_) = tokio::time::timeout(Duration::from_millis(10), peer.recv()).await);
+	robot.send_to(&state.encode_to_vec(), local).unwrap();
+	let_assert!(Ok(Ok(received)) = tokio::time::timeout(Duration::from_secs(1), peer.recv()).await);
+	assert!(received == state);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_measured_sample_time() {
+	use crate::clock::MockClock;
+	use assert2::{assert, let_assert};
+	use prost::Message;
+	use std::time::Duration;
+
+	let clock = MockClock::new();
+	let robot = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+	let peer = EgmPeer::bind("127.0.0.1:0").await.unwrap().with_clock(clock.clone());
+	let_assert!(Ok(mut peer) = peer.connect(robot.local_addr().unwrap()).await);
+	let local = peer.socket().local_addr().unwrap();
+	peer.set_sample_time_window(3);
+
+	// Datagrams that fail to decode do not disturb the measurement.
+	let state = EgmRobot::default().encode_to_vec();
+	for _ in 0..3 {
+		robot.send_to(&state, local).unwrap();
+		let_assert!(Ok(_) = peer.recv().await);
+		assert!(peer.measured_sample_time() == None);
+		clock.advance(Duration::from_millis(2));
+		robot.send_to(&[0xFF, 0xFF], local).unwrap();
+		let_assert!(Err(ReceiveError::Decode(_)) = peer.recv().await);
+		clock.advance(Duration::from_millis(6));
+	}
+	robot.send_to(&state, local).unwrap();
+	let_assert!(Ok(_) = peer.recv().await);
+	assert!(peer.measured_sample_time() == Some(Duration::from_millis(8)));
+}
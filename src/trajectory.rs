@@ -0,0 +1,391 @@
+use crate::msg::{EgmCartesianSpeed, EgmPose, EgmQuaternion};
+
+/// The kind of spline used to interpolate waypoints.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum SplineKind {
+	/// Natural cubic spline, passing through all waypoints.
+	Cubic,
+
+	/// Uniform cubic B-spline, using the waypoints as control points.
+	///
+	/// The curve starts and ends at the first and last waypoint, but only approaches the other waypoints.
+	/// It is smoother than the cubic spline and does not overshoot.
+	BSpline,
+}
+
+/// Error that may occur while creating a spline or spline trajectory.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SplineError {
+	/// A spline needs at least two waypoints.
+	TooFewWaypoints {
+		/// The number of waypoints given.
+		count: usize,
+	},
+
+	/// A limit of the trajectory is not a positive number.
+	InvalidLimit {
+		/// The name of the limit.
+		name: &'static str,
+
+		/// The value of the limit.
+		value: f64,
+	},
+}
+
+/// Cubic polynomial coefficients for each axis of a spline segment.
+type Segment = [[f64; 4]; 3];
+
+/// A cubic spline through three-dimensional waypoints in millimeters.
+///
+/// The spline is parameterized by `u` in the range `[0, segments]`, with one unit per segment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spline {
+	kind: SplineKind,
+	segments: Vec<Segment>,
+}
+
+impl Spline {
+	/// Create a spline through waypoints in millimeters.
+	pub fn new(kind: SplineKind, waypoints: &[[f64; 3]]) -> Result<Self, SplineError> {
+		if waypoints.len() < 2 {
+			return Err(SplineError::TooFewWaypoints { count: waypoints.len() });
+		}
+		let segments = match kind {
+			SplineKind::Cubic => cubic_segments(waypoints),
+			SplineKind::BSpline => bspline_segments(waypoints),
+		};
+		Ok(Self { kind, segments })
+	}
+
+	/// Get the kind of the spline.
+	pub fn kind(&self) -> SplineKind {
+		self.kind
+	}
+
+	/// Get the number of segments of the spline.
+	pub fn segments(&self) -> usize {
+		self.segments.len()
+	}
+
+	/// Find the segment and local parameter for a spline parameter.
+	fn locate(&self, u: f64) -> (&Segment, f64) {
+		let u = u.clamp(0.0, self.segments.len() as f64);
+		let index = (u as usize).min(self.segments.len() - 1);
+		(&self.segments[index], u - index as f64)
+	}
+
+	/// Get the position at a spline parameter.
+	pub fn position(&self, u: f64) -> [f64; 3] {
+		let (segment, s) = self.locate(u);
+		segment.map(|[a, b, c, d]| a + s * (b + s * (c + s * d)))
+	}
+
+	/// Get the first derivative with respect to the spline parameter.
+	pub fn derivative(&self, u: f64) -> [f64; 3] {
+		let (segment, s) = self.locate(u);
+		segment.map(|[_, b, c, d]| b + s * (2.0 * c + s * 3.0 * d))
+	}
+
+	/// Get the second derivative with respect to the spline parameter.
+	pub fn second_derivative(&self, u: f64) -> [f64; 3] {
+		let (segment, s) = self.locate(u);
+		segment.map(|[_, _, c, d]| 2.0 * c + 6.0 * d * s)
+	}
+
+	/// Get the curvature in 1/mm at a spline parameter.
+	pub fn curvature(&self, u: f64) -> f64 {
+		let d1 = self.derivative(u);
+		let speed = norm(d1);
+		if speed == 0.0 {
+			return 0.0;
+		}
+		norm(cross(d1, self.second_derivative(u))) / speed.powi(3)
+	}
+}
+
+/// Limits for the time parameterization of a spline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SplineLimits {
+	/// The maximum speed along the path in mm/s.
+	pub max_speed: f64,
+
+	/// The maximum acceleration along the path in mm/s².
+	pub max_acceleration: f64,
+
+	/// The maximum centripetal acceleration in mm/s², which limits the speed in curves.
+	pub max_centripetal_acceleration: f64,
+}
+
+impl Default for SplineLimits {
+	fn default() -> Self {
+		Self {
+			max_speed: 250.0,
+			max_acceleration: 500.0,
+			max_centripetal_acceleration: 500.0,
+		}
+	}
+}
+
+/// A sample of the time parameterization.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Sample {
+	time: f64,
+	u: f64,
+	speed: f64,
+}
+
+/// Time-parameterized trajectory along a spline, with the speed modulated by the curvature of the path.
+///
+/// The trajectory starts and ends at stand still.
+/// The speed is limited by the maximum speed, by the maximum centripetal acceleration in curves,
+/// and by the maximum acceleration along the path.
+/// This gives smoother motion than piecewise-linear interpolation, for example for scanning applications.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SplineTrajectory {
+	spline: Spline,
+	samples: Vec<Sample>,
+}
+
+impl SplineTrajectory {
+	/// The number of samples per spline segment used for the time parameterization.
+	const SAMPLES_PER_SEGMENT: usize = 64;
+
+	/// Create a trajectory along a spline.
+	pub fn new(spline: Spline, limits: &SplineLimits) -> Result<Self, SplineError> {
+		let limits_list = [
+			("max_speed", limits.max_speed),
+			("max_acceleration", limits.max_acceleration),
+			("max_centripetal_acceleration", limits.max_centripetal_acceleration),
+		];
+		for (name, value) in limits_list {
+			if !(value > 0.0 && value.is_finite()) {
+				return Err(SplineError::InvalidLimit { name, value });
+			}
+		}
+
+		let count = spline.segments() * Self::SAMPLES_PER_SEGMENT + 1;
+		let step = 1.0 / Self::SAMPLES_PER_SEGMENT as f64;
+		let params: Vec<f64> = (0..count).map(|i| i as f64 * step).collect();
+		let positions: Vec<[f64; 3]> = params.iter().map(|&u| spline.position(u)).collect();
+		let distances: Vec<f64> = positions.windows(2).map(|x| norm(sub(x[1], x[0]))).collect();
+
+		// Speed limit from the curvature, then forward and backward passes for the acceleration limit.
+		let mut speeds: Vec<f64> = params
+			.iter()
+			.map(|&u| {
+				let curvature = spline.curvature(u);
+				if curvature > 0.0 {
+					limits.max_speed.min((limits.max_centripetal_acceleration / curvature).sqrt())
+				} else {
+					limits.max_speed
+				}
+			})
+			.collect();
+		speeds[0] = 0.0;
+		speeds[count - 1] = 0.0;
+		for i in 1..count {
+			speeds[i] = speeds[i].min((speeds[i - 1].powi(2) + 2.0 * limits.max_acceleration * distances[i - 1]).sqrt());
+		}
+		for i in (0..count - 1).rev() {
+			speeds[i] = speeds[i].min((speeds[i + 1].powi(2) + 2.0 * limits.max_acceleration * distances[i]).sqrt());
+		}
+
+		let mut samples = Vec::with_capacity(count);
+		let mut time = 0.0;
+		for i in 0..count {
+			if i > 0 {
+				let average = (speeds[i - 1] + speeds[i]) / 2.0;
+				if average > 0.0 {
+					time += distances[i - 1] / average;
+				}
+			}
+			samples.push(Sample {
+				time,
+				u: params[i],
+				speed: speeds[i],
+			});
+		}
+		Ok(Self { spline, samples })
+	}
+
+	/// Get the spline of the trajectory.
+	pub fn spline(&self) -> &Spline {
+		&self.spline
+	}
+
+	/// Get the total duration of the trajectory in seconds.
+	pub fn duration(&self) -> f64 {
+		self.samples.last().map(|x| x.time).unwrap_or(0.0)
+	}
+
+	/// Get the spline parameter and speed at a time in seconds since the start of the trajectory.
+	fn sample_at(&self, time: f64) -> (f64, f64) {
+		let index = self.samples.partition_point(|x| x.time <= time);
+		if index == 0 {
+			return (0.0, 0.0);
+		} else if index == self.samples.len() {
+			let last = &self.samples[index - 1];
+			return (last.u, 0.0);
+		}
+		let a = &self.samples[index - 1];
+		let b = &self.samples[index];
+		let fraction = (time - a.time) / (b.time - a.time);
+		(a.u + (b.u - a.u) * fraction, a.speed + (b.speed - a.speed) * fraction)
+	}
+
+	/// Get the position in millimeters at a time in seconds since the start of the trajectory.
+	pub fn position_at(&self, time: f64) -> [f64; 3] {
+		self.spline.position(self.sample_at(time).0)
+	}
+
+	/// Get the velocity in mm/s at a time in seconds since the start of the trajectory.
+	pub fn velocity_at(&self, time: f64) -> [f64; 3] {
+		let (u, speed) = self.sample_at(time);
+		let tangent = self.spline.derivative(u);
+		let length = norm(tangent);
+		if length == 0.0 {
+			return [0.0; 3];
+		}
+		tangent.map(|x| x / length * speed)
+	}
+
+	/// Get the target pose at a time in seconds since the start of the trajectory, with a fixed tool orientation.
+	pub fn pose_at(&self, time: f64, orientation: EgmQuaternion) -> EgmPose {
+		EgmPose::new(self.position_at(time), orientation)
+	}
+
+	/// Get the speed reference at a time in seconds since the start of the trajectory.
+	pub fn speed_at(&self, time: f64) -> EgmCartesianSpeed {
+		self.velocity_at(time).into()
+	}
+}
+
+/// Compute the segments of a natural cubic spline with uniform knots.
+fn cubic_segments(points: &[[f64; 3]]) -> Vec<Segment> {
+	let n = points.len() - 1;
+	let mut segments = vec![[[0.0; 4]; 3]; n];
+	for axis in 0..3 {
+		let p: Vec<f64> = points.iter().map(|x| x[axis]).collect();
+
+		// Solve the tridiagonal system for the second derivatives with the Thomas algorithm.
+		let mut m = vec![0.0; n + 1];
+		if n > 1 {
+			let mut diagonal = vec![4.0; n - 1];
+			let mut rhs: Vec<f64> = (1..n).map(|i| 6.0 * (p[i + 1] - 2.0 * p[i] + p[i - 1])).collect();
+			for i in 1..n - 1 {
+				let factor = 1.0 / diagonal[i - 1];
+				diagonal[i] -= factor;
+				rhs[i] -= factor * rhs[i - 1];
+			}
+			m[n - 1] = rhs[n - 2] / diagonal[n - 2];
+			for i in (1..n - 1).rev() {
+				m[i] = (rhs[i - 1] - m[i + 1]) / diagonal[i - 1];
+			}
+		}
+
+		for i in 0..n {
+			segments[i][axis] = [
+				p[i],
+				p[i + 1] - p[i] - (2.0 * m[i] + m[i + 1]) / 6.0,
+				m[i] / 2.0,
+				(m[i + 1] - m[i]) / 6.0,
+			];
+		}
+	}
+	segments
+}
+
+/// Compute the segments of a uniform cubic B-spline, with repeated end points so the curve starts and ends at them.
+fn bspline_segments(points: &[[f64; 3]]) -> Vec<Segment> {
+	let first = points[0];
+	let last = points[points.len() - 1];
+	let mut control = vec![first, first];
+	control.extend_from_slice(points);
+	control.extend_from_slice(&[last, last]);
+	control
+		.windows(4)
+		.map(|p| {
+			let mut segment = [[0.0; 4]; 3];
+			for (axis, coefficients) in segment.iter_mut().enumerate() {
+				let [p0, p1, p2, p3] = [p[0][axis], p[1][axis], p[2][axis], p[3][axis]];
+				*coefficients = [
+					(p0 + 4.0 * p1 + p2) / 6.0,
+					(p2 - p0) / 2.0,
+					(p0 - 2.0 * p1 + p2) / 2.0,
+					(-p0 + 3.0 * p1 - 3.0 * p2 + p3) / 6.0,
+				];
+			}
+			segment
+		})
+		.collect()
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+	[a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+	[a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn norm(a: [f64; 3]) -> f64 {
+	(a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt()
+}
+
+impl std::fmt::Display for SplineError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::TooFewWaypoints { count } => write!(f, "a spline needs at least 2 waypoints, got {}", count),
+			Self::InvalidLimit { name, value } => write!(f, "invalid {}: {}, expected a positive number", name, value),
+		}
+	}
+}
+
+impl std::error::Error for SplineError {}
+
+#[cfg(test)]
+#[test]
+fn test_spline_trajectory() {
+	use assert2::{assert, let_assert};
+
+	let close = |a: [f64; 3], b: [f64; 3]| a.iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-6);
+	let waypoints = [[0.0, 0.0, 0.0], [100.0, 0.0, 0.0], [100.0, 100.0, 0.0], [200.0, 100.0, 50.0]];
+
+	let_assert!(Ok(cubic) = Spline::new(SplineKind::Cubic, &waypoints));
+	assert!(cubic.segments() == 3);
+	for (i, waypoint) in waypoints.iter().enumerate() {
+		assert!(close(cubic.position(i as f64), *waypoint));
+	}
+	// The second derivative is continuous at the waypoints and zero at the ends.
+	assert!(close(cubic.second_derivative(0.0), [0.0; 3]));
+	assert!(close(cubic.second_derivative(3.0), [0.0; 3]));
+	assert!(close(cubic.second_derivative(1.0 - 1e-9), cubic.second_derivative(1.0)));
+
+	let_assert!(Ok(bspline) = Spline::new(SplineKind::BSpline, &waypoints));
+	assert!(close(bspline.position(0.0), waypoints[0]));
+	assert!(close(bspline.position(bspline.segments() as f64), waypoints[3]));
+
+	let limits = SplineLimits::default();
+	let_assert!(Ok(trajectory) = SplineTrajectory::new(bspline, &limits));
+	assert!(trajectory.duration() > 0.0);
+	assert!(close(trajectory.position_at(-1.0), waypoints[0]));
+	assert!(close(trajectory.position_at(trajectory.duration() + 1.0), waypoints[3]));
+	assert!(close(trajectory.velocity_at(0.0), [0.0; 3]));
+	for i in 0..100 {
+		let speed = norm(trajectory.velocity_at(trajectory.duration() * i as f64 / 100.0));
+		assert!(speed <= limits.max_speed + 1e-9);
+	}
+
+	// A straight line is only limited by the speed and acceleration.
+	let_assert!(Ok(line) = Spline::new(SplineKind::Cubic, &[[0.0; 3], [1000.0, 0.0, 0.0]]));
+	let_assert!(Ok(trajectory) = SplineTrajectory::new(line, &limits));
+	assert!((trajectory.duration() - 4.5).abs() < 0.01);
+	assert!(close(trajectory.velocity_at(2.0), [250.0, 0.0, 0.0]));
+
+	assert!(Spline::new(SplineKind::Cubic, &waypoints[..1]) == Err(SplineError::TooFewWaypoints { count: 1 }));
+	let invalid = SplineLimits {
+		max_speed: 0.0,
+		..Default::default()
+	};
+	assert!(let Err(SplineError::InvalidLimit { name: "max_speed", .. }) = SplineTrajectory::new(cubic, &invalid));
+}
@@ -0,0 +1,278 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::codec::{self, EncodeError};
+use crate::msg::{EgmRobot, EgmSensor};
+use crate::InvalidMessageError;
+
+/// A transport for datagrams exchanged with a single robot controller.
+///
+/// Implement this trait to reuse the encoding, decoding and validation of this crate over other transports than UDP,
+/// such as a WebSocket or WebRTC relay in a browser, or a custom network stack on an embedded gateway.
+pub trait DatagramTransport {
+	/// The error type of the transport.
+	type Error;
+
+	/// Send a datagram to the robot controller.
+	fn send_datagram(&mut self, datagram: &[u8]) -> Result<(), Self::Error>;
+
+	/// Receive a datagram from the robot controller into a buffer.
+	///
+	/// Returns the length of the datagram, or `None` if no datagram is available.
+	/// Implementations may block until a datagram is available instead.
+	fn recv_datagram(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error>;
+}
+
+/// A connected UDP socket is a datagram transport.
+///
+/// If the socket is in non-blocking mode, [`DatagramTransport::recv_datagram`] returns `None` when no datagram is available.
+#[cfg(feature = "std")]
+impl DatagramTransport for std::net::UdpSocket {
+	type Error = std::io::Error;
+
+	fn send_datagram(&mut self, datagram: &[u8]) -> Result<(), Self::Error> {
+		let transferred = self.send(datagram)?;
+		crate::error::check_transfer(transferred, datagram.len()).map_err(std::io::Error::other)
+	}
+
+	fn recv_datagram(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+		match self.recv(buffer) {
+			Ok(len) => Ok(Some(len)),
+			Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+}
+
+/// A transport for the blocking peer, [`crate::sync_peer::EgmPeer`].
+///
+/// The transport is implemented for UDP sockets, which is what the robot controller uses.
+/// Implement it for other types to run the peer over mock transports in tests,
+/// UNIX sockets in simulators, or relays.
+///
+/// Unlike [`DatagramTransport`], this trait supports unconnected transports that exchange datagrams with multiple addresses.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub trait EgmTransport {
+	/// The address type of the transport.
+	type Address;
+
+	/// Receive a datagram from the connected remote address.
+	fn recv(&mut self, buffer: &mut [u8]) -> std::io::Result<usize>;
+
+	/// Receive a datagram from any remote address.
+	fn recv_from(&mut self, buffer: &mut [u8]) -> std::io::Result<(usize, Self::Address)>;
+
+	/// Send a datagram to the connected remote address.
+	fn send(&mut self, datagram: &[u8]) -> std::io::Result<usize>;
+
+	/// Send a datagram to the specified address.
+	fn send_to(&mut self, datagram: &[u8], target: &Self::Address) -> std::io::Result<usize>;
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+impl EgmTransport for std::net::UdpSocket {
+	type Address = std::net::SocketAddr;
+
+	fn recv(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+		std::net::UdpSocket::recv(self, buffer)
+	}
+
+	fn recv_from(&mut self, buffer: &mut [u8]) -> std::io::Result<(usize, Self::Address)> {
+		std::net::UdpSocket::recv_from(self, buffer)
+	}
+
+	fn send(&mut self, datagram: &[u8]) -> std::io::Result<usize> {
+		std::net::UdpSocket::send(self, datagram)
+	}
+
+	fn send_to(&mut self, datagram: &[u8], target: &Self::Address) -> std::io::Result<usize> {
+		std::net::UdpSocket::send_to(self, datagram, target)
+	}
+}
+
+/// A transport for the asynchronous peer, [`crate::tokio_peer::EgmPeer`].
+///
+/// The transport is implemented for `tokio` UDP sockets, which is what the robot controller uses.
+/// Implement it for other types to run the peer over mock transports in tests,
+/// UNIX sockets in simulators, or relays.
+///
+/// Receiving takes a shared reference, so that a peer can receive and send concurrently.
+#[cfg(feature = "tokio")]
+pub trait AsyncEgmTransport {
+	/// The address type of the transport.
+	type Address;
+
+	/// Receive a datagram from the connected remote address.
+	fn recv(&self, buffer: &mut [u8]) -> impl std::future::Future<Output = std::io::Result<usize>> + Send;
+
+	/// Receive a datagram from any remote address.
+	fn recv_from(&self, buffer: &mut [u8]) -> impl std::future::Future<Output = std::io::Result<(usize, Self::Address)>> + Send;
+
+	/// Send a datagram to the connected remote address.
+	fn send(&self, datagram: &[u8]) -> impl std::future::Future<Output = std::io::Result<usize>> + Send;
+
+	/// Send a datagram to the specified address.
+	fn send_to(&self, datagram: &[u8], target: &Self::Address) -> impl std::future::Future<Output = std::io::Result<usize>> + Send;
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncEgmTransport for tokio::net::UdpSocket {
+	type Address = std::net::SocketAddr;
+
+	fn recv(&self, buffer: &mut [u8]) -> impl std::future::Future<Output = std::io::Result<usize>> + Send {
+		tokio::net::UdpSocket::recv(self, buffer)
+	}
+
+	fn recv_from(&self, buffer: &mut [u8]) -> impl std::future::Future<Output = std::io::Result<(usize, Self::Address)>> + Send {
+		tokio::net::UdpSocket::recv_from(self, buffer)
+	}
+
+	fn send(&self, datagram: &[u8]) -> impl std::future::Future<Output = std::io::Result<usize>> + Send {
+		tokio::net::UdpSocket::send(self, datagram)
+	}
+
+	fn send_to(&self, datagram: &[u8], target: &Self::Address) -> impl std::future::Future<Output = std::io::Result<usize>> + Send {
+		tokio::net::UdpSocket::send_to(self, datagram, *target)
+	}
+}
+
+/// Error that may occur when using a [`Channel`].
+#[derive(Debug)]
+pub enum ChannelError<E> {
+	/// The transport reported an error.
+	Transport(E),
+
+	/// The message to send is invalid.
+	InvalidMessage(InvalidMessageError),
+
+	/// Encoding the message failed.
+	Encode(prost::EncodeError),
+
+	/// Decoding a received datagram failed.
+	Decode(codec::DecodeError),
+}
+
+/// Typed EGM channel on top of a datagram transport.
+///
+/// The channel encodes, validates and decodes messages, while the transport only moves bytes.
+#[derive(Debug)]
+pub struct Channel<T> {
+	transport: T,
+	encode_buffer: Vec<u8>,
+	recv_buffer: Vec<u8>,
+}
+
+impl<T: DatagramTransport> Channel<T> {
+	/// Create a new channel on a transport.
+	pub fn new(transport: T) -> Self {
+		Self {
+			transport,
+			encode_buffer: Vec::new(),
+			recv_buffer: vec![0; codec::DecodeLimits::DEFAULT.max_len + 1],
+		}
+	}
+
+	/// Get a shared reference to the transport.
+	pub fn transport(&self) -> &T {
+		&self.transport
+	}
+
+	/// Get an exclusive reference to the transport.
+	pub fn transport_mut(&mut self) -> &mut T {
+		&mut self.transport
+	}
+
+	/// Consume self and get the transport.
+	pub fn into_transport(self) -> T {
+		self.transport
+	}
+
+	/// Validate, encode and send a message to the robot controller.
+	pub fn send(&mut self, msg: &EgmSensor) -> Result<(), ChannelError<T::Error>> {
+		codec::encode_sensor(msg, &mut self.encode_buffer)?;
+		self.transport.send_datagram(&self.encode_buffer).map_err(ChannelError::Transport)
+	}
+
+	/// Receive and decode a message from the robot controller.
+	///
+	/// Returns `None` if the transport has no datagram available.
+	pub fn recv(&mut self) -> Result<Option<EgmRobot>, ChannelError<T::Error>> {
+		match self.transport.recv_datagram(&mut self.recv_buffer).map_err(ChannelError::Transport)? {
+			Some(len) => Ok(Some(codec::decode_robot(&self.recv_buffer[..len]).map_err(ChannelError::Decode)?)),
+			None => Ok(None),
+		}
+	}
+}
+
+impl<E> From<EncodeError> for ChannelError<E> {
+	fn from(other: EncodeError) -> Self {
+		match other {
+			EncodeError::InvalidMessage(e) => Self::InvalidMessage(e),
+			EncodeError::Encode(e) => Self::Encode(e),
+		}
+	}
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for ChannelError<E> {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		match self {
+			Self::Transport(e) => e.fmt(f),
+			Self::InvalidMessage(e) => e.fmt(f),
+			Self::Encode(e) => e.fmt(f),
+			Self::Decode(e) => e.fmt(f),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for ChannelError<E> {}
+
+#[cfg(test)]
+#[test]
+fn test_channel() {
+	use crate::msg::EgmClock;
+	use alloc::collections::VecDeque;
+	use assert2::assert;
+	use prost::Message;
+
+	/// A transport that keeps datagrams in memory.
+	#[derive(Default)]
+	struct Loopback {
+		sent: Vec<Vec<u8>>,
+		received: VecDeque<Vec<u8>>,
+	}
+
+	impl DatagramTransport for Loopback {
+		type Error = core::convert::Infallible;
+
+		fn send_datagram(&mut self, datagram: &[u8]) -> Result<(), Self::Error> {
+			self.sent.push(datagram.to_vec());
+			Ok(())
+		}
+
+		fn recv_datagram(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+			Ok(self.received.pop_front().map(|datagram| {
+				buffer[..datagram.len()].copy_from_slice(&datagram);
+				datagram.len()
+			}))
+		}
+	}
+
+	let mut channel = Channel::new(Loopback::default());
+	assert!(let Ok(None) = channel.recv());
+
+	let robot = EgmRobot {
+		header: Some(crate::msg::EgmHeader::data(3, 12)),
+		..Default::default()
+	};
+	channel.transport_mut().received.push_back(robot.encode_to_vec());
+	assert!(channel.recv().unwrap() == Some(robot));
+
+	let target = EgmSensor::joint_target(1, [0.0; 6], EgmClock::new(0, 0));
+	channel.send(&target).unwrap();
+	assert!(EgmSensor::decode(channel.transport().sent[0].as_slice()).unwrap() == target);
+
+	let invalid = EgmSensor::joint_target(1, [f64::NAN; 6], EgmClock::new(0, 0));
+	assert!(let Err(ChannelError::InvalidMessage(_)) = channel.send(&invalid));
+	assert!(channel.transport().sent.len() == 1);
+}
@@ -0,0 +1,360 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::msg;
+use crate::InvalidMessageError;
+
+/// Options for validating messages before they are sent.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationOptions {
+	/// The maximum angle in degrees between the quaternion and the Euler angles of a pose.
+	///
+	/// This is only checked if a pose contains both a quaternion and Euler angles.
+	/// The robot controller gives priority to the Euler angles in that case.
+	pub orientation_tolerance: f64,
+
+	/// The maximum absolute path correction in millimeters for the x, y and z axis.
+	///
+	/// Oversized corrections make the robot controller stop the motion with an error.
+	pub max_path_correction: [f64; 3],
+}
+
+impl Default for ValidationOptions {
+	fn default() -> Self {
+		Self {
+			orientation_tolerance: 0.1,
+			max_path_correction: [10.0; 3],
+		}
+	}
+}
+
+/// Collector for the paths of fields that contain NaN values.
+#[derive(Debug, Default)]
+pub struct NanFields {
+	path: Vec<&'static str>,
+	fields: Vec<String>,
+}
+
+impl NanFields {
+	/// Create a new empty collector.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Get the paths of all fields with NaN values found so far.
+	pub fn into_fields(self) -> Vec<String> {
+		self.fields
+	}
+
+	/// Get the full path of a field in the current message.
+	fn field_path(&self, name: &str) -> String {
+		let mut path = String::new();
+		for component in &self.path {
+			path.push_str(component);
+			path.push('.');
+		}
+		path.push_str(name);
+		path
+	}
+
+	/// Check a single value for NaN.
+	fn value(&mut self, name: &'static str, value: f64) {
+		if value.is_nan() {
+			self.fields.push(self.field_path(name));
+		}
+	}
+
+	/// Check a list of values for NaN.
+	fn values(&mut self, name: &'static str, values: &[f64]) {
+		for (i, value) in values.iter().enumerate() {
+			if value.is_nan() {
+				self.fields.push(format!("{}[{}]", self.field_path(name), i));
+			}
+		}
+	}
+
+	/// Check an optional nested message for NaN values.
+	fn message(&mut self, name: &'static str, message: Option<&impl FindNan>) {
+		if let Some(message) = message {
+			self.path.push(name);
+			message.find_nan(self);
+			self.path.pop();
+		}
+	}
+}
+
+/// Message types that can be searched for NaN values.
+pub trait FindNan {
+	/// Add the paths of all fields with NaN values to `fields`.
+	fn find_nan(&self, fields: &mut NanFields);
+}
+
+impl FindNan for msg::EgmCartesian {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.value("x", self.x);
+		fields.value("y", self.y);
+		fields.value("z", self.z);
+	}
+}
+
+impl FindNan for msg::EgmQuaternion {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.value("u0", self.u0);
+		fields.value("u1", self.u1);
+		fields.value("u2", self.u2);
+		fields.value("u3", self.u3);
+	}
+}
+
+impl FindNan for msg::EgmEuler {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.value("x", self.x);
+		fields.value("y", self.y);
+		fields.value("z", self.z);
+	}
+}
+
+impl FindNan for msg::EgmPose {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.message("pos", self.pos.as_ref());
+		fields.message("orient", self.orient.as_ref());
+		fields.message("euler", self.euler.as_ref());
+	}
+}
+
+impl FindNan for msg::EgmCartesianSpeed {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.values("value", &self.value);
+	}
+}
+
+impl FindNan for msg::EgmJoints {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.values("joints", &self.joints);
+	}
+}
+
+impl FindNan for msg::EgmPlanned {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.message("joints", self.joints.as_ref());
+		fields.message("cartesian", self.cartesian.as_ref());
+		fields.message("external_joints", self.external_joints.as_ref());
+	}
+}
+
+impl FindNan for msg::EgmSpeedRef {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.message("joints", self.joints.as_ref());
+		fields.message("cartesians", self.cartesians.as_ref());
+		fields.message("external_joints", self.external_joints.as_ref());
+	}
+}
+
+impl FindNan for msg::EgmPathCorr {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.message("pos", Some(&self.pos));
+	}
+}
+
+impl FindNan for msg::EgmFeedBack {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.message("joints", self.joints.as_ref());
+		fields.message("cartesian", self.cartesian.as_ref());
+		fields.message("external_joints", self.external_joints.as_ref());
+	}
+}
+
+impl FindNan for msg::EgmMeasuredForce {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.values("force", &self.force);
+	}
+}
+
+#[cfg(feature = "robotware-6-10")]
+impl FindNan for msg::EgmRapiDdata {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.values("dnum", &self.dnum);
+	}
+}
+
+impl FindNan for msg::EgmSensor {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.message("planned", self.planned.as_ref());
+		fields.message("speed_ref", self.speed_ref.as_ref());
+		#[cfg(feature = "robotware-6-10")]
+		fields.message("rapi_dto_robot", self.rapi_dto_robot.as_ref());
+	}
+}
+
+impl FindNan for msg::EgmSensorPathCorr {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.message("path_corr", self.path_corr.as_ref());
+	}
+}
+
+impl FindNan for msg::EgmRobot {
+	fn find_nan(&self, fields: &mut NanFields) {
+		fields.message("feed_back", self.feed_back.as_ref());
+		fields.message("planned", self.planned.as_ref());
+		fields.message("measured_force", self.measured_force.as_ref());
+		if let Some(utilization_rate) = self.utilization_rate {
+			fields.value("utilization_rate", utilization_rate);
+		}
+		#[cfg(feature = "robotware-6-10")]
+		fields.message("rapi_dfrom_robot", self.rapi_dfrom_robot.as_ref());
+	}
+}
+
+/// Get the paths of all fields in a message that contain NaN values.
+fn nan_fields(message: &impl FindNan) -> Vec<String> {
+	let mut fields = NanFields::new();
+	message.find_nan(&mut fields);
+	fields.into_fields()
+}
+
+/// Check that a message contains no NaN values.
+fn validate(message: &impl FindNan) -> Result<(), InvalidMessageError> {
+	let fields = nan_fields(message);
+	if fields.is_empty() {
+		Ok(())
+	} else {
+		Err(InvalidMessageError::MessageHasNan { fields })
+	}
+}
+
+/// Check that the quaternion and Euler angles of a pose describe the same orientation, if both are set.
+fn check_orientation(pose: &msg::EgmPose, tolerance: f64) -> Result<(), InvalidMessageError> {
+	if let (Some(orient), Some(euler)) = (&pose.orient, &pose.euler) {
+		let angle = orient.angle_to(&euler.to_quaternion());
+		if angle.is_nan() || angle > tolerance {
+			return Err(InvalidMessageError::ConflictingOrientation { angle });
+		}
+	}
+	Ok(())
+}
+
+impl msg::EgmSensor {
+	/// Validate the message with the default [`ValidationOptions`].
+	///
+	/// If the message contains NaN values, the returned error holds the paths of the offending fields,
+	/// such as `planned.cartesian.pos.x`.
+	pub fn validate(&self) -> Result<(), InvalidMessageError> {
+		self.validate_with(&ValidationOptions::default())
+	}
+
+	/// Validate the message with custom [`ValidationOptions`].
+	pub fn validate_with(&self, options: &ValidationOptions) -> Result<(), InvalidMessageError> {
+		validate(self)?;
+		if let Some(pose) = self.planned.as_ref().and_then(|x| x.cartesian.as_ref()) {
+			check_orientation(pose, options.orientation_tolerance)?;
+		}
+		Ok(())
+	}
+}
+
+impl msg::EgmSensorPathCorr {
+	/// Validate the message with the default [`ValidationOptions`].
+	///
+	/// If the message contains NaN values, the returned error holds the paths of the offending fields,
+	/// such as `path_corr.pos.x`.
+	pub fn validate(&self) -> Result<(), InvalidMessageError> {
+		self.validate_with(&ValidationOptions::default())
+	}
+
+	/// Validate the message with custom [`ValidationOptions`].
+	pub fn validate_with(&self, options: &ValidationOptions) -> Result<(), InvalidMessageError> {
+		validate(self)?;
+		if let Some(pos) = self.path_corr.as_ref().map(|x| x.pos) {
+			for ((field, value), limit) in ["path_corr.pos.x", "path_corr.pos.y", "path_corr.pos.z"].iter().zip(pos.as_mm()).zip(options.max_path_correction) {
+				if value.abs() > limit {
+					return Err(InvalidMessageError::PathCorrectionTooLarge { field, value, limit });
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Clamp the path correction to the given maximum absolute correction in millimeters for the x, y and z axis.
+	///
+	/// The sign of the limits is ignored, and a NaN limit allows no correction at all on that axis.
+	pub fn clamp(&mut self, max_path_correction: [f64; 3]) {
+		if let Some(path_corr) = &mut self.path_corr {
+			let mut pos = path_corr.pos.as_mm();
+			for (value, limit) in pos.iter_mut().zip(max_path_correction) {
+				let limit = if limit.is_nan() { 0.0 } else { limit.abs() };
+				*value = value.clamp(-limit, limit);
+			}
+			path_corr.pos = pos.into();
+		}
+	}
+}
+
+impl msg::EgmRobot {
+	/// Validate the message.
+	///
+	/// If the message contains NaN values, the returned error holds the paths of the offending fields,
+	/// such as `feed_back.joints.joints[2]`.
+	pub fn validate(&self) -> Result<(), InvalidMessageError> {
+		validate(self)
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_validate() {
+	use assert2::{assert, let_assert};
+	use msg::{EgmClock, EgmPose, EgmSensor};
+
+	let pose = EgmPose::new([1.0, f64::NAN, 3.0], msg::EgmQuaternion::IDENTITY);
+	let sensor = EgmSensor::pose_target_with_speed(0, pose, [0.0, 0.0, f64::NAN], EgmClock::new(0, 0));
+	let_assert!(Err(InvalidMessageError::MessageHasNan { fields }) = sensor.validate());
+	assert!(fields == ["planned.cartesian.pos.y", "speed_ref.cartesians.value[2]"]);
+
+	let sensor = EgmSensor::joint_target(0, [0.0; 6], EgmClock::new(0, 0));
+	assert!(let Ok(()) = sensor.validate());
+}
+
+#[cfg(test)]
+#[test]
+fn test_conflicting_orientation() {
+	use assert2::{assert, let_assert};
+	use msg::{EgmClock, EgmEuler, EgmPose, EgmQuaternion, EgmSensor};
+
+	let mut pose = EgmPose::with_euler([0.0; 3], EgmEuler::from_xyz_degrees(0.0, 0.0, 1.0));
+	pose.orient = Some(EgmQuaternion::IDENTITY);
+	let sensor = EgmSensor::pose_target(0, pose, EgmClock::new(0, 0));
+	let_assert!(Err(InvalidMessageError::ConflictingOrientation { angle }) = sensor.validate());
+	assert!((angle - 1.0).abs() < 1e-6);
+	assert!(let Ok(()) = sensor.validate_with(&ValidationOptions { orientation_tolerance: 2.0, ..Default::default() }));
+
+	let mut pose = EgmPose::with_euler([0.0; 3], EgmEuler::ZERO);
+	pose.orient = Some(EgmQuaternion::IDENTITY);
+	assert!(let Ok(()) = EgmSensor::pose_target(0, pose, EgmClock::new(0, 0)).validate());
+}
+
+#[cfg(test)]
+#[test]
+fn test_path_correction_limits() {
+	use assert2::{assert, let_assert};
+	use msg::EgmSensorPathCorr;
+
+	let mut msg = EgmSensorPathCorr::new(1, 0, [1.0, -12.0, 3.0], 0);
+	let_assert!(Err(InvalidMessageError::PathCorrectionTooLarge { field, value, limit }) = msg.validate());
+	assert!(field == "path_corr.pos.y");
+	assert!(value == -12.0);
+	assert!(limit == 10.0);
+
+	let options = ValidationOptions {
+		max_path_correction: [1.0, 20.0, 20.0],
+		..Default::default()
+	};
+	assert!(let Ok(()) = msg.validate_with(&options));
+
+	msg.clamp([0.5, 10.0, 10.0]);
+	assert!(msg.path_corr.as_ref().unwrap().pos.as_mm() == [0.5, -10.0, 3.0]);
+
+	// Negative and NaN limits do not panic.
+	msg.clamp([-0.25, f64::NAN, 20.0]);
+	assert!(msg.path_corr.unwrap().pos.as_mm() == [0.25, 0.0, 3.0]);
+}
@@ -0,0 +1,214 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::msg::{EgmCartesian, EgmRobot};
+
+/// The plane in which a weave pattern oscillates.
+///
+/// The first axis of the plane is the main weave direction, the second axis is used by two-dimensional shapes.
+/// The axes refer to the frame of the path correction, which is the sensor tool frame.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum WeavePlane {
+	/// Weave along X, with Y as second axis.
+	XY,
+
+	/// Weave along X, with Z as second axis.
+	XZ,
+
+	/// Weave along Y, with Z as second axis.
+	YZ,
+
+	/// Weave along Y, with X as second axis.
+	YX,
+
+	/// Weave along Z, with X as second axis.
+	ZX,
+
+	/// Weave along Z, with Y as second axis.
+	ZY,
+}
+
+impl WeavePlane {
+	fn to_cartesian(self, a: f64, b: f64) -> EgmCartesian {
+		match self {
+			Self::XY => EgmCartesian::from_mm(a, b, 0.0),
+			Self::XZ => EgmCartesian::from_mm(a, 0.0, b),
+			Self::YZ => EgmCartesian::from_mm(0.0, a, b),
+			Self::YX => EgmCartesian::from_mm(b, a, 0.0),
+			Self::ZX => EgmCartesian::from_mm(b, 0.0, a),
+			Self::ZY => EgmCartesian::from_mm(0.0, b, a),
+		}
+	}
+}
+
+/// The shape of a weave pattern.
+#[derive(Clone)]
+pub enum WeaveShape {
+	/// Sinusoidal oscillation along the main axis.
+	Sine,
+
+	/// Triangular oscillation along the main axis, with constant speed between the turning points.
+	Triangle,
+
+	/// Circular motion in the weave plane.
+	Circle,
+
+	/// Figure eight in the weave plane, oscillating along the second axis at twice the frequency.
+	FigureEight,
+
+	/// A custom pattern.
+	///
+	/// The function receives the phase in the range `[0, 1)`
+	/// and returns the offset along both axes of the plane, normally in the range `[-1, 1]`.
+	/// The offsets are multiplied by the amplitude.
+	Custom(Arc<dyn Fn(f64) -> [f64; 2] + Send + Sync>),
+}
+
+impl WeaveShape {
+	fn evaluate(&self, phase: f64) -> [f64; 2] {
+		let angle = 2.0 * PI * phase;
+		match self {
+			Self::Sine => [angle.sin(), 0.0],
+			Self::Triangle => {
+				// Start at zero going up, like the sine.
+				let value = if phase < 0.25 {
+					4.0 * phase
+				} else if phase < 0.75 {
+					2.0 - 4.0 * phase
+				} else {
+					4.0 * phase - 4.0
+				};
+				[value, 0.0]
+			}
+			Self::Circle => [angle.sin(), 1.0 - angle.cos()],
+			Self::FigureEight => [angle.sin(), (2.0 * angle).sin() / 2.0],
+			Self::Custom(function) => function(phase),
+		}
+	}
+}
+
+impl std::fmt::Debug for WeaveShape {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Self::Sine => write!(f, "Sine"),
+			Self::Triangle => write!(f, "Triangle"),
+			Self::Circle => write!(f, "Circle"),
+			Self::FigureEight => write!(f, "FigureEight"),
+			Self::Custom(_) => write!(f, "Custom(..)"),
+		}
+	}
+}
+
+/// Generator for weave patterns as path correction offsets, for example for welding.
+///
+/// The pattern is evaluated at the feedback time of the robot controller,
+/// so it stays synchronized with the motion of the robot even if messages are processed late.
+///
+/// Add the offsets to the measured corrections before submitting them to a [`crate::path_correction::PathCorrectionSession`],
+/// or submit them directly to weave along the programmed path.
+#[derive(Clone, Debug)]
+pub struct WeaveGenerator {
+	/// The shape of the pattern.
+	pub shape: WeaveShape,
+
+	/// The amplitude of the pattern in millimeters.
+	pub amplitude: f64,
+
+	/// The frequency of the pattern in Hz.
+	pub frequency: f64,
+
+	/// The plane in which the pattern oscillates.
+	pub plane: WeavePlane,
+
+	start: Option<Duration>,
+}
+
+impl WeaveGenerator {
+	/// Create a new weave generator.
+	pub fn new(shape: WeaveShape, amplitude: f64, frequency: f64, plane: WeavePlane) -> Self {
+		Self {
+			shape,
+			amplitude,
+			frequency,
+			plane,
+			start: None,
+		}
+	}
+
+	/// Get the offset at a time since the start of the pattern.
+	pub fn offset_at(&self, time: Duration) -> EgmCartesian {
+		let phase = (time.as_secs_f64() * self.frequency).rem_euclid(1.0);
+		let [a, b] = self.shape.evaluate(phase);
+		self.plane.to_cartesian(a * self.amplitude, b * self.amplitude)
+	}
+
+	/// Get the offset for the feedback time of a message from the robot controller.
+	///
+	/// The pattern starts at the feedback time of the first message passed to this function.
+	/// Returns `None` if the message has no feedback time.
+	pub fn offset_for(&mut self, state: &EgmRobot) -> Option<EgmCartesian> {
+		let time = state.feedback_time()?.elapsed_since_epoch();
+		let start = *self.start.get_or_insert(time);
+		Some(self.offset_at(time.checked_sub(start).unwrap_or_default()))
+	}
+
+	/// Add the offset for the feedback time of a message to a measured correction.
+	///
+	/// Returns the correction unmodified if the message has no feedback time.
+	pub fn superimpose(&mut self, correction: impl Into<EgmCartesian>, state: &EgmRobot) -> EgmCartesian {
+		let correction = correction.into();
+		match self.offset_for(state) {
+			Some(offset) => {
+				let [x, y, z] = correction.as_mm();
+				let [dx, dy, dz] = offset.as_mm();
+				EgmCartesian::from_mm(x + dx, y + dy, z + dz)
+			}
+			None => correction,
+		}
+	}
+
+	/// Restart the pattern at the next message.
+	pub fn reset(&mut self) {
+		self.start = None;
+	}
+}
+
+#[cfg(test)]
+#[test]
+fn test_weave() {
+	use crate::msg::{EgmClock, EgmFeedBack};
+	use assert2::assert;
+
+	let close = |a: EgmCartesian, b: [f64; 3]| a.as_mm().iter().zip(&b).all(|(a, b)| (a - b).abs() < 1e-9);
+	let ms = Duration::from_millis;
+
+	let sine = WeaveGenerator::new(WeaveShape::Sine, 2.0, 1.0, WeavePlane::YZ);
+	assert!(close(sine.offset_at(ms(0)), [0.0, 0.0, 0.0]));
+	assert!(close(sine.offset_at(ms(250)), [0.0, 2.0, 0.0]));
+	assert!(close(sine.offset_at(ms(1750)), [0.0, -2.0, 0.0]));
+
+	let triangle = WeaveGenerator::new(WeaveShape::Triangle, 1.0, 2.0, WeavePlane::XY);
+	assert!(close(triangle.offset_at(ms(0)), [0.0, 0.0, 0.0]));
+	assert!(close(triangle.offset_at(ms(25)), [0.2, 0.0, 0.0]));
+	assert!(close(triangle.offset_at(ms(125)), [1.0, 0.0, 0.0]));
+	assert!(close(triangle.offset_at(ms(375)), [-1.0, 0.0, 0.0]));
+
+	let circle = WeaveGenerator::new(WeaveShape::Circle, 1.0, 1.0, WeavePlane::XZ);
+	assert!(close(circle.offset_at(ms(500)), [0.0, 0.0, 2.0]));
+
+	let custom = WeaveGenerator::new(WeaveShape::Custom(Arc::new(|phase| [phase, -phase])), 10.0, 1.0, WeavePlane::ZY);
+	assert!(close(custom.offset_at(ms(300)), [0.0, -3.0, 3.0]));
+
+	let state = |msec: u64| EgmRobot {
+		feed_back: Some(EgmFeedBack {
+			time: Some(EgmClock::new(10, msec * 1000)),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let mut sine = WeaveGenerator::new(WeaveShape::Sine, 2.0, 1.0, WeavePlane::YZ);
+	assert!(close(sine.superimpose([1.0, 1.0, 1.0], &state(100)), [1.0, 1.0, 1.0]));
+	assert!(close(sine.superimpose([1.0, 1.0, 1.0], &state(350)), [1.0, 3.0, 1.0]));
+	assert!(sine.offset_for(&EgmRobot::default()).is_none());
+}
@@ -0,0 +1,138 @@
+use core::convert::TryFrom;
+
+use crate::msg::{EgmMeasuredForce, EgmRobot};
+
+/// The frame in which a wrench is expressed.
+///
+/// The messages from the robot controller do not say in which frame the measured force is expressed:
+/// that depends on the force sensor configuration of the controller.
+/// The frame is an annotation added by the application, to avoid mixing up wrenches in different frames.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum ForceFrame {
+	/// The frame is not known.
+	#[default]
+	Unknown,
+
+	/// The frame of the force sensor.
+	Sensor,
+
+	/// The tool frame.
+	Tool,
+
+	/// The base frame of the robot.
+	Base,
+
+	/// The work object frame.
+	WorkObject,
+}
+
+/// A force and torque, measured by the force sensor of the robot controller.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Wrench {
+	/// The force along the X, Y and Z axis in newton.
+	pub force: [f64; 3],
+
+	/// The torque around the X, Y and Z axis in newton meter.
+	pub torque: [f64; 3],
+
+	/// The frame the wrench is expressed in.
+	pub frame: ForceFrame,
+}
+
+/// Error that may occur when converting a measured force into a [`Wrench`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WrenchError {
+	/// The message does not contain a measured force.
+	Missing,
+
+	/// The measured force does not have 6 values.
+	WrongNumberOfValues(usize),
+}
+
+impl Wrench {
+	/// Create a wrench from a force and torque in a frame.
+	pub fn new(force: [f64; 3], torque: [f64; 3], frame: ForceFrame) -> Self {
+		Self { force, torque, frame }
+	}
+
+	/// Create a wrench from 6 values: the force followed by the torque.
+	pub fn from_values(values: &[f64], frame: ForceFrame) -> Result<Self, WrenchError> {
+		match values {
+			&[fx, fy, fz, tx, ty, tz] => Ok(Self::new([fx, fy, fz], [tx, ty, tz], frame)),
+			_ => Err(WrenchError::WrongNumberOfValues(values.len())),
+		}
+	}
+
+	/// Annotate the wrench with the frame it is expressed in.
+	pub fn in_frame(self, frame: ForceFrame) -> Self {
+		Self { frame, ..self }
+	}
+
+	/// Get the force followed by the torque as 6 values.
+	pub fn to_values(&self) -> [f64; 6] {
+		let [fx, fy, fz] = self.force;
+		let [tx, ty, tz] = self.torque;
+		[fx, fy, fz, tx, ty, tz]
+	}
+}
+
+impl TryFrom<&EgmMeasuredForce> for Wrench {
+	type Error = WrenchError;
+
+	/// Convert a measured force with 6 values into a wrench in an unknown frame.
+	fn try_from(other: &EgmMeasuredForce) -> Result<Self, Self::Error> {
+		Self::from_values(&other.force, ForceFrame::Unknown)
+	}
+}
+
+impl TryFrom<EgmMeasuredForce> for Wrench {
+	type Error = WrenchError;
+
+	fn try_from(other: EgmMeasuredForce) -> Result<Self, Self::Error> {
+		Self::try_from(&other)
+	}
+}
+
+impl From<Wrench> for EgmMeasuredForce {
+	fn from(other: Wrench) -> Self {
+		Self::from_values(other.to_values().to_vec())
+	}
+}
+
+impl EgmRobot {
+	/// Get the measured force as wrench, annotated with the frame it is expressed in.
+	pub fn measured_wrench(&self, frame: ForceFrame) -> Result<Wrench, WrenchError> {
+		let force = self.measured_force.as_ref().ok_or(WrenchError::Missing)?;
+		Ok(Wrench::try_from(force)?.in_frame(frame))
+	}
+}
+
+impl core::fmt::Display for WrenchError {
+	fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+		match self {
+			Self::Missing => write!(f, "missing field: measured_force"),
+			Self::WrongNumberOfValues(x) => write!(f, "wrong number of values, expected 6, got {}", x),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for WrenchError {}
+
+#[cfg(test)]
+#[test]
+fn test_wrench() {
+	use assert2::{assert, let_assert};
+
+	let mut state = EgmRobot::default();
+	assert!(state.measured_wrench(ForceFrame::Tool) == Err(WrenchError::Missing));
+
+	state.measured_force = Some(EgmMeasuredForce::from_values(vec![1.0, 2.0, 3.0]));
+	assert!(state.measured_wrench(ForceFrame::Tool) == Err(WrenchError::WrongNumberOfValues(3)));
+
+	let wrench = Wrench::new([1.0, 2.0, 3.0], [0.1, 0.2, 0.3], ForceFrame::Sensor);
+	state.measured_force = Some(wrench.into());
+	let_assert!(Ok(measured) = state.measured_wrench(ForceFrame::Tool));
+	assert!(measured == wrench.in_frame(ForceFrame::Tool));
+	assert!(measured.to_values() == [1.0, 2.0, 3.0, 0.1, 0.2, 0.3]);
+}